@@ -0,0 +1,16 @@
+// 开启 ffi 特性时使用 cbindgen 生成 C 语言头文件
+fn main() {
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", dir)).unwrap();
+        cbindgen::Builder::new()
+            .with_crate(&dir)
+            .with_config(config)
+            .generate()
+            .expect("failed to generate include/sqldb.h")
+            .write_to_file(format!("{}/include/sqldb.h", dir));
+    }
+}
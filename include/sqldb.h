@@ -0,0 +1,126 @@
+/* 由 cbindgen 生成，不要手动修改 */
+
+#ifndef SQLDB_H
+#define SQLDB_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define SQLDB_OK 0
+
+#define SQLDB_MISUSE 1
+
+#define SQLDB_INTERNAL 2
+
+#define SQLDB_PARSE 3
+
+#define SQLDB_CONFLICT 4
+
+#define SQLDB_NOT_FOUND 5
+
+#define SQLDB_DUPLICATE 6
+
+#define SQLDB_TYPE_MISMATCH 7
+
+#define SQLDB_CONSTRAINT 8
+
+#define SQLDB_INVALID_INPUT 9
+
+#define SQLDB_INVALID_STATE 10
+
+#define SQLDB_READ_ONLY 11
+
+#define SQLDB_OUT_OF_MEMORY 12
+
+typedef struct SqldbDatabase SqldbDatabase;
+
+typedef struct SqldbBuffer {
+  uint8_t *data;
+  size_t len;
+} SqldbBuffer;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * 打开磁盘上的数据库，path 是日志文件的路径，成功时 *out 指向新的连接
+ *
+ * # Safety
+ * path 是以 \0 结尾的字符串，out 是合法的指针
+ */
+int sqldb_open(const char *path, struct SqldbDatabase **out);
+
+/**
+ * 打开一个内存数据库，关闭之后数据丢失
+ *
+ * # Safety
+ * out 是合法的指针
+ */
+int sqldb_open_memory(struct SqldbDatabase **out);
+
+/**
+ * 关闭连接，未提交的事务被回滚，磁盘数据库在最后一个连接关闭时关闭存储引擎
+ *
+ * # Safety
+ * db 由 sqldb_open 或 sqldb_open_memory 返回，并且没有被关闭过，可以是空指针
+ */
+void sqldb_close(struct SqldbDatabase *db);
+
+/**
+ * 执行一条 SQL 语句，成功时结果写入 *result
+ *
+ * # Safety
+ * db 是打开的连接，sql 是以 \0 结尾的字符串，result 是合法的指针
+ */
+int sqldb_execute(struct SqldbDatabase *db, const char *sql, struct SqldbBuffer *result);
+
+/**
+ * 开启显式事务
+ *
+ * # Safety
+ * db 是打开的连接
+ */
+int sqldb_begin(struct SqldbDatabase *db);
+
+/**
+ * 提交事务
+ *
+ * # Safety
+ * db 是打开的连接
+ */
+int sqldb_commit(struct SqldbDatabase *db);
+
+/**
+ * 回滚事务
+ *
+ * # Safety
+ * db 是打开的连接
+ */
+int sqldb_rollback(struct SqldbDatabase *db);
+
+/**
+ * 最近一次失败的错误信息，没有错误时返回空指针
+ * 返回的字符串在下一次调用 db 上的函数之前有效
+ *
+ * # Safety
+ * db 是打开的连接
+ */
+const char *sqldb_errmsg(const struct SqldbDatabase *db);
+
+/**
+ * 释放 sqldb_execute 返回的结果
+ *
+ * # Safety
+ * buffer 由 sqldb_execute 写入，并且没有被释放过
+ */
+void sqldb_buffer_free(struct SqldbBuffer *buffer);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SQLDB_H */
@@ -0,0 +1,136 @@
+use std::{env, path::PathBuf, process::ExitCode, time::Duration};
+
+use sqldb_rs::error::{Error, Result};
+use sqldb_rs::sql::{
+    bench::{self, BenchConfig, Workload},
+    engine::{kv::KVEngine, Engine},
+};
+use sqldb_rs::storage::{
+    bptree::BPTreeEngine,
+    disk::{DiskOptions, Durability},
+    memory::MemoryEngine,
+};
+
+const DB_PATH: &str = "/tmp/sqldb-bench/sqldb-log";
+
+const USAGE: &str = "usage: bench [prepare|run|all] [options]
+  --path <file>       数据库日志文件，默认 /tmp/sqldb-bench/sqldb-log
+  --memory            使用内存存储引擎，只能和 all 一起使用
+  --bptree            使用 B+ 树存储引擎，只能和 all 一起使用
+  --sync <mode>       同步到磁盘的时机：always、commit 或者同步间隔的毫秒数，默认 commit
+  --tables <n>        表的个数，默认 1
+  --rows <n>          每张表的行数，默认 10000
+  --len <n>           c、pad 列的字符串长度，默认 60
+  --threads <n>       并发线程数，默认 4
+  --ops <n>           每个线程执行的操作数，默认 1000
+  --range <n>         范围查询返回的行数，默认 100
+  --workload <name>   point_select、range、update、join、mixed，默认 point_select
+  --seed <n>          随机数种子，默认 1";
+
+// 基准测试，prepare 生成数据，run 执行负载并输出吞吐和延迟，all 依次执行两者
+// cargo run --release --bin bench -- all --rows 100000 --threads 8 --workload mixed
+fn main() -> ExitCode {
+    match parse_args().and_then(|(mode, path, options, engine, config)| {
+        execute(&mode, path, options, engine, &config)
+    }) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args() -> Result<(String, PathBuf, DiskOptions, &'static str, BenchConfig)> {
+    let mut args = env::args().skip(1).peekable();
+    let mode = match args.next_if(|a| !a.starts_with("--")) {
+        Some(mode) => mode,
+        None => "all".to_string(),
+    };
+    let mut path = PathBuf::from(DB_PATH);
+    let mut options = DiskOptions::default();
+    let mut engine = "disk";
+    let mut config = BenchConfig::default();
+    while let Some(arg) = args.next() {
+        // 选择内存存储引擎的选项没有值
+        let memory_engine = match arg.as_str() {
+            "--memory" => Some("memory"),
+            "--bptree" => Some("bptree"),
+            _ => None,
+        };
+        if let Some(name) = memory_engine {
+            engine = name;
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| Error::InvalidInput(format!("missing value for {}", arg)))?;
+        match arg.as_str() {
+            "--path" => path = PathBuf::from(value),
+            "--sync" => options.durability = parse_durability(&value)?,
+            "--tables" => config.tables = value.parse()?,
+            "--rows" => config.rows = value.parse()?,
+            "--len" => config.value_len = value.parse()?,
+            "--threads" => config.threads = value.parse()?,
+            "--ops" => config.ops = value.parse()?,
+            "--range" => config.range = value.parse()?,
+            "--workload" => config.workload = Workload::parse(&value)?,
+            "--seed" => config.seed = value.parse()?,
+            _ => return Err(Error::InvalidInput(format!("unknown option {}", arg))),
+        }
+    }
+    Ok((mode, path, options, engine, config))
+}
+
+fn parse_durability(value: &str) -> Result<Durability> {
+    Ok(match value {
+        "always" => Durability::Always,
+        "commit" => Durability::Commit,
+        ms => Durability::Periodic(Duration::from_millis(ms.parse()?)),
+    })
+}
+
+fn execute(
+    mode: &str,
+    path: PathBuf,
+    options: DiskOptions,
+    engine: &str,
+    config: &BenchConfig,
+) -> Result<()> {
+    if engine != "disk" && mode != "all" {
+        return Err(Error::InvalidInput(format!(
+            "--{} only works with all",
+            engine
+        )));
+    }
+    match engine {
+        "memory" => return execute_engine(mode, &KVEngine::new(MemoryEngine::new()), config),
+        "bptree" => return execute_engine(mode, &KVEngine::new(BPTreeEngine::new()), config),
+        _ => {}
+    }
+    let engine = KVEngine::open(path, options)?;
+    let result = execute_engine(mode, &engine, config);
+    engine.close()?;
+    result
+}
+
+fn execute_engine<E: Engine + Send + 'static>(
+    mode: &str,
+    engine: &E,
+    config: &BenchConfig,
+) -> Result<()> {
+    if !matches!(mode, "prepare" | "run" | "all") {
+        return Err(Error::InvalidInput(format!("unknown mode {}", mode)));
+    }
+    if mode != "run" {
+        println!(
+            "preparing {} tables with {} rows each",
+            config.tables, config.rows
+        );
+        bench::prepare(engine, config)?;
+    }
+    if mode != "prepare" {
+        println!("{}", bench::run(engine, config)?);
+    }
+    Ok(())
+}
@@ -1,77 +1,60 @@
-use futures::{SinkExt, TryStreamExt};
 use rustyline::error::ReadlineError;
 use rustyline::DefaultEditor;
+use sqldb_rs::error::{Error, Result};
+use sqldb_rs::server::tcp::Client;
 use std::env;
-use std::{error::Error, net::SocketAddr};
-use tokio::net::TcpStream;
-use tokio_util::codec::{FramedRead, FramedWrite, LinesCodec};
 
-const RESPONSE_END: &str = "!!!end!!!";
-
-pub struct Client {
-    stream: TcpStream,
-    txn_version: Option<u64>,
+// 客户端可以发送的命令，SHOW TABLES 和 SHOW TABLE 不是 SQL 语句，单独发送请求
+enum SqlRequest {
+    Sql(String),
+    ListTables,
+    TableInfo(String),
 }
 
-impl Client {
-    pub async fn new(addr: SocketAddr) -> Result<Self, Box<dyn Error>> {
-        let stream = TcpStream::connect(addr).await?;
-        Ok(Self {
-            stream,
-            txn_version: None,
-        })
-    }
-
-    pub async fn execute_sql(&mut self, sql_cmd: &str) -> Result<(), Box<dyn Error>> {
-        let (r, w) = self.stream.split();
-        let mut sink = FramedWrite::new(w, LinesCodec::new());
-        let mut stream = FramedRead::new(r, LinesCodec::new());
-
-        // 发送命令并执行
-        sink.send(sql_cmd).await?;
-
-        // 拿到结果并打印
-        while let Some(res) = stream.try_next().await? {
-            if res == RESPONSE_END {
-                break;
-            }
-            // 解析事务命令
-            if res.starts_with("TRANSACTION") {
-                let args = res.split(" ").collect::<Vec<_>>();
-                if args[2] == "COMMIT" || args[2] == "ROLLBACK" {
-                    self.txn_version = None;
-                }
-                if args[2] == "BEGIN" {
-                    let version = args[1].parse::<u64>().unwrap();
-                    self.txn_version = Some(version);
-                }
+impl SqlRequest {
+    pub fn parse(cmd: &str) -> Self {
+        let upper_cmd = cmd.trim_end_matches(';').to_uppercase();
+        if upper_cmd == "SHOW TABLES" {
+            return SqlRequest::ListTables;
+        }
+        if upper_cmd.starts_with("SHOW TABLE") {
+            let args = upper_cmd.split_ascii_whitespace().collect::<Vec<_>>();
+            if args.len() == 3 {
+                return SqlRequest::TableInfo(args[2].to_lowercase());
             }
-            println!("{}", res);
         }
-        Ok(())
+        SqlRequest::Sql(cmd.into())
     }
 }
 
-impl Drop for Client {
-    fn drop(&mut self) {
-        if self.txn_version.is_some() {
-            futures::executor::block_on(self.execute_sql("ROLLBACK;")).expect("rollback failed");
-        }
+// 错误信息带上 SQLSTATE 错误码，可以据此区分错误类型
+fn format_error(err: &Error) -> String {
+    match err.sqlstate() {
+        Some(code) => format!("ERROR {}: {}", code, err),
+        None => format!("ERROR: {}", err),
+    }
+}
+
+async fn execute(client: &mut Client, cmd: &str) -> Result<String> {
+    match SqlRequest::parse(cmd) {
+        SqlRequest::Sql(sql) => client.execute(&sql).await.map(|rs| rs.to_string()),
+        SqlRequest::ListTables => client.list_tables().await,
+        SqlRequest::TableInfo(table_name) => client.get_table(&table_name).await,
     }
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
     let addr = env::args()
         .nth(1)
         .unwrap_or_else(|| "127.0.0.1:8080".to_string());
 
-    let addr = addr.parse::<SocketAddr>()?;
-    let mut client = Client::new(addr).await?;
+    // 断开连接时服务端会回滚没有提交的事务
+    let mut client = Client::connect(addr).await?;
 
     let mut editor = DefaultEditor::new()?;
     loop {
-        let prompt = match client.txn_version {
+        let prompt = match client.txn_version() {
             Some(version) => format!("sqldb#{}> ", version),
             None => "sqldb> ".into(),
         };
@@ -79,12 +62,15 @@ async fn main() -> Result<(), Box<dyn Error>> {
         match readline {
             Ok(sql_cmd) => {
                 let sql_cmd = sql_cmd.trim();
-                if sql_cmd.len() > 0 {
+                if !sql_cmd.is_empty() {
                     if sql_cmd == "quit" {
                         break;
                     }
                     editor.add_history_entry(sql_cmd)?;
-                    client.execute_sql(sql_cmd).await?;
+                    match execute(&mut client, sql_cmd).await {
+                        Ok(output) => println!("{}", output),
+                        Err(err) => println!("{}", format_error(&err)),
+                    }
                 }
             }
             Err(ReadlineError::Interrupted) => break,
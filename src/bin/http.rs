@@ -0,0 +1,27 @@
+use sqldb_rs::error::Result;
+use sqldb_rs::server::http;
+use sqldb_rs::sql::engine::kv::KVEngine;
+use sqldb_rs::storage::disk::DiskEngine;
+use tokio::net::TcpListener;
+
+use std::env;
+use std::path::PathBuf;
+
+const DB_PATH: &str = "/tmp/sqldb-test/sqldb-log";
+
+// HTTP 前端，例如:
+// curl -d 'select * from t;' http://127.0.0.1:8081/query
+#[tokio::main]
+async fn main() -> Result<()> {
+    let addr = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:8081".to_string());
+    // 第二个参数指定数据文件
+    let path = env::args().nth(2).unwrap_or_else(|| DB_PATH.to_string());
+
+    let listener = TcpListener::bind(&addr).await?;
+    println!("sqldb http server starts, listening on: {addr}");
+
+    let kvengine = KVEngine::new(DiskEngine::new(PathBuf::from(path))?);
+    http::serve(listener, kvengine).await
+}
@@ -0,0 +1,38 @@
+use std::{env, path::PathBuf, process::ExitCode};
+
+use sqldb_rs::sql::{
+    engine::{kv::KVEngine, Engine},
+    logictest,
+};
+use sqldb_rs::storage::memory::MemoryEngine;
+
+// 执行 sqllogictest 格式的测试脚本，每个脚本使用一个新的内存数据库
+// cargo run --bin logictest tests/slt/basic.slt tests/slt/join.slt
+fn main() -> ExitCode {
+    let paths = env::args().skip(1).map(PathBuf::from).collect::<Vec<_>>();
+    if paths.is_empty() {
+        eprintln!("usage: logictest <file.slt>...");
+        return ExitCode::FAILURE;
+    }
+
+    let mut failed = 0;
+    for path in paths.iter() {
+        let result = KVEngine::new(MemoryEngine::new())
+            .session()
+            .and_then(|mut s| logictest::run_file(&mut s, path));
+        match result {
+            Ok(count) => println!("ok   {} ({} records)", path.display(), count),
+            Err(err) => {
+                println!("FAIL {}\n{}", path.display(), err);
+                failed += 1;
+            }
+        }
+    }
+
+    println!("{} passed, {} failed", paths.len() - failed, failed);
+    if failed > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
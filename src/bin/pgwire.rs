@@ -0,0 +1,27 @@
+use sqldb_rs::error::Result;
+use sqldb_rs::server::pgwire;
+use sqldb_rs::sql::engine::kv::KVEngine;
+use sqldb_rs::storage::disk::DiskEngine;
+use tokio::net::TcpListener;
+
+use std::env;
+use std::path::PathBuf;
+
+const DB_PATH: &str = "/tmp/sqldb-test/sqldb-log";
+
+// Postgres 协议前端，例如:
+// psql -h 127.0.0.1 -p 5432 -c 'select * from t;'
+#[tokio::main]
+async fn main() -> Result<()> {
+    let addr = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:5432".to_string());
+    // 第二个参数指定数据文件
+    let path = env::args().nth(2).unwrap_or_else(|| DB_PATH.to_string());
+
+    let listener = TcpListener::bind(&addr).await?;
+    println!("sqldb pgwire server starts, listening on: {addr}");
+
+    let kvengine = KVEngine::new(DiskEngine::new(PathBuf::from(path))?);
+    pgwire::serve(listener, kvengine).await
+}
@@ -0,0 +1,133 @@
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use sqldb_rs::error::{Error, Result};
+use sqldb_rs::sql::engine::{kv::KVEngine, Engine, Session};
+use sqldb_rs::storage::disk::DiskEngine;
+use std::env;
+use std::io::Write;
+use std::path::PathBuf;
+
+const DB_PATH: &str = "/tmp/sqldb-cli/sqldb-log";
+const HISTORY_FILE: &str = ".sqldb_history";
+
+const HELP: &str = ".tables          列出所有的表
+.schema <table>  显示表结构
+.dump [file]     导出建表和插入数据的 SQL 语句，指定文件时写入文件
+.help            显示帮助
+.quit            退出";
+
+// 嵌入式的交互命令行，直接打开磁盘存储引擎，不需要启动 server
+// SQL 语句可以跨多行输入，以分号结尾时执行；以 . 开头的行是元命令
+// cargo run --bin sqldb-cli /tmp/sqldb-cli/sqldb-log
+fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let path = env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DB_PATH));
+    let engine = KVEngine::new(DiskEngine::new(path)?);
+    let mut session = engine.session()?;
+
+    // 历史记录保存在 HOME 目录下，没有 HOME 时不保存
+    let history = env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+    let mut editor = DefaultEditor::new()?;
+    if let Some(history) = &history {
+        let _ = editor.load_history(history);
+    }
+
+    let mut buffer = String::new();
+    loop {
+        let prompt = match (buffer.is_empty(), session.in_transaction()) {
+            (false, _) => "   ...> ",
+            (true, true) => "sqldb*> ",
+            (true, false) => "sqldb> ",
+        };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            // Ctrl-C 丢弃当前没有输入完的语句
+            Err(ReadlineError::Interrupted) => {
+                buffer.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(err) => {
+                println!("Error: {:?}", err);
+                break;
+            }
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if buffer.is_empty() && line.starts_with('.') {
+            editor.add_history_entry(line)?;
+            match meta_command(&session, line) {
+                Ok(Some(output)) => println!("{}", output),
+                Ok(None) => break,
+                Err(err) => println!("{}", format_error(&err)),
+            }
+            continue;
+        }
+
+        if !buffer.is_empty() {
+            buffer.push('\n');
+        }
+        buffer.push_str(line);
+        if !buffer.ends_with(';') {
+            continue;
+        }
+        let sql = std::mem::take(&mut buffer);
+        editor.add_history_entry(sql.as_str())?;
+        match session.execute(&sql) {
+            Ok(result) => println!("{}", result),
+            Err(err) => println!("{}", format_error(&err)),
+        }
+    }
+
+    if let Some(history) = &history {
+        let _ = editor.save_history(history);
+    }
+    // 退出时回滚没有提交的事务
+    if session.in_transaction() {
+        session.execute("rollback;")?;
+    }
+    drop(session);
+    engine.close()?;
+    Ok(())
+}
+
+// 执行元命令，返回 None 表示退出
+fn meta_command<E: Engine + 'static>(session: &Session<E>, line: &str) -> Result<Option<String>> {
+    let args = line.split_ascii_whitespace().collect::<Vec<_>>();
+    Ok(Some(match args.as_slice() {
+        [".tables"] => session.get_table_names()?,
+        [".schema", table_name] => session.get_table(table_name.trim_end_matches(';').into())?,
+        [".dump"] => {
+            let mut buf = Vec::new();
+            session.dump(&mut buf)?;
+            String::from_utf8_lossy(&buf).trim_end().into()
+        }
+        [".dump", file] => {
+            let mut writer = std::io::BufWriter::new(std::fs::File::create(file)?);
+            session.dump(&mut writer)?;
+            writer.flush()?;
+            format!("dumped to {}", file)
+        }
+        [".help"] => HELP.into(),
+        [".quit"] | [".exit"] => return Ok(None),
+        _ => {
+            return Err(Error::InvalidInput(format!(
+                "unknown command {}, enter .help for usage",
+                line
+            )))
+        }
+    }))
+}
+
+// 错误信息带上 SQLSTATE 错误码，可以据此区分错误类型
+fn format_error(err: &Error) -> String {
+    match err.sqlstate() {
+        Some(code) => format!("ERROR {}: {}", code, err),
+        None => format!("ERROR: {}", err),
+    }
+}
@@ -1,16 +1,60 @@
 use std::{array::TryFromSliceError, fmt::Display, string::FromUtf8Error, sync::PoisonError};
 
 use bincode::ErrorKind;
-use serde::{de, ser};
+use serde::{de, ser, Deserialize, Serialize};
 
 // 自定义 Result 类型
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Error {
+    // SQL 语法错误
     Parse(String),
+    // 内部错误，一般不应该出现
     Internal(String),
+    // 写入时发现数据已经被其他事务修改，事务不会自动回滚，回滚之后可以重试
     WriteConflict,
+    // 提交时发现写入的数据，或者 SERIALIZABLE 事务读取的数据已经被其他事务修改并提交，
+    // 事务已经回滚，可以重试
+    Serialization,
+    // 表、列等对象不存在
+    NotFound(String),
+    // 表已存在、主键重复
+    Duplicate(String),
+    // 类型不匹配，或者类型转换失败
+    TypeMismatch(String),
+    // 违反约束，例如非空约束
+    Constraint(String),
+    // 不合法的参数或者表定义
+    InvalidInput(String),
+    // 不合法的事务状态，例如重复开启事务
+    InvalidState(String),
+    // 写入只读的对象
+    ReadOnly(String),
+    // 查询使用的内存超过限制
+    OutOfMemory(String),
+    // 等待行锁时发现死锁，当前事务已经回滚，可以重试
+    Deadlock,
+}
+
+impl Error {
+    // 错误对应的 SQLSTATE 错误码，内部错误没有错误码
+    pub fn sqlstate(&self) -> Option<&'static str> {
+        Some(match self {
+            Error::Parse(_) => "42601",
+            Error::Internal(_) => return None,
+            Error::WriteConflict | Error::Serialization => "40001",
+            Error::NotFound(_) => "42704",
+            Error::Duplicate(_) => "23505",
+            Error::TypeMismatch(_) => "42804",
+            Error::Constraint(_) => "23000",
+            Error::InvalidInput(_) => "22023",
+            Error::InvalidState(_) => "25000",
+            Error::ReadOnly(_) => "25006",
+            Error::OutOfMemory(_) => "53200",
+            Error::Deadlock => "40P01",
+        })
+    }
 }
 
 impl From<std::num::ParseIntError> for Error {
@@ -55,6 +99,13 @@ impl From<FromUtf8Error> for Error {
     }
 }
 
+// csv 文件格式错误，或者读写文件失败
+impl From<csv::Error> for Error {
+    fn from(value: csv::Error) -> Self {
+        Error::InvalidInput(value.to_string())
+    }
+}
+
 impl std::error::Error for Error {}
 
 impl ser::Error for Error {
@@ -67,6 +118,10 @@ impl de::Error for Error {
     fn custom<T: Display>(msg: T) -> Self {
         Error::Internal(msg.to_string())
     }
+
+    fn invalid_type(unexp: de::Unexpected, exp: &dyn de::Expected) -> Self {
+        Error::TypeMismatch(format!("invalid type: {}, expected {}", unexp, exp))
+    }
 }
 
 impl Display for Error {
@@ -75,6 +130,19 @@ impl Display for Error {
             Error::Parse(err) => write!(f, "parse error {}", err),
             Error::Internal(err) => write!(f, "internal error {}", err),
             Error::WriteConflict => write!(f, "write conflict, try transaction"),
+            Error::Serialization => write!(
+                f,
+                "could not serialize access due to concurrent update, retry transaction"
+            ),
+            Error::NotFound(err) => write!(f, "not found error {}", err),
+            Error::Duplicate(err) => write!(f, "duplicate error {}", err),
+            Error::TypeMismatch(err) => write!(f, "type mismatch error {}", err),
+            Error::Constraint(err) => write!(f, "constraint error {}", err),
+            Error::InvalidInput(err) => write!(f, "invalid input error {}", err),
+            Error::InvalidState(err) => write!(f, "invalid state error {}", err),
+            Error::ReadOnly(err) => write!(f, "read only error {}", err),
+            Error::OutOfMemory(err) => write!(f, "out of memory error {}", err),
+            Error::Deadlock => write!(f, "deadlock detected, retry transaction"),
         }
     }
 }
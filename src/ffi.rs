@@ -0,0 +1,340 @@
+use std::{
+    ffi::{c_char, c_int, CStr, CString},
+    path::PathBuf,
+    ptr,
+};
+
+use crate::{
+    error::{Error, Result},
+    sql::{
+        engine::{kv::KVEngine, Engine, Session},
+        executor::ResultSet,
+        json::result_json,
+    },
+    storage::{disk::DiskEngine, memory::MemoryEngine},
+};
+
+// C 语言接口，头文件 include/sqldb.h 由 cbindgen 在开启 ffi 特性编译时生成
+// 所有函数返回错误码，SQLDB_OK 表示成功，出错时通过 sqldb_errmsg 获取错误信息
+// 执行结果序列化成 JSON 写入 SqldbBuffer，格式和 HTTP 接口相同，使用之后需要调用 sqldb_buffer_free 释放
+
+pub const SQLDB_OK: c_int = 0;
+// 参数不合法，例如空指针、SQL 不是 UTF-8 编码
+pub const SQLDB_MISUSE: c_int = 1;
+pub const SQLDB_INTERNAL: c_int = 2;
+pub const SQLDB_PARSE: c_int = 3;
+pub const SQLDB_CONFLICT: c_int = 4;
+pub const SQLDB_NOT_FOUND: c_int = 5;
+pub const SQLDB_DUPLICATE: c_int = 6;
+pub const SQLDB_TYPE_MISMATCH: c_int = 7;
+pub const SQLDB_CONSTRAINT: c_int = 8;
+pub const SQLDB_INVALID_INPUT: c_int = 9;
+pub const SQLDB_INVALID_STATE: c_int = 10;
+pub const SQLDB_READ_ONLY: c_int = 11;
+pub const SQLDB_OUT_OF_MEMORY: c_int = 12;
+
+// 数据库连接，包含一个 session，不能在多个线程中同时使用
+pub struct SqldbDatabase {
+    session: DatabaseSession,
+    // 最近一次错误的信息，成功执行之后清空
+    errmsg: Option<CString>,
+}
+
+enum DatabaseSession {
+    Disk(Session<KVEngine<DiskEngine>>),
+    Memory(Session<KVEngine<MemoryEngine>>),
+}
+
+// 执行结果，data 指向 len 个字节的 JSON 文本，不以 \0 结尾
+#[repr(C)]
+pub struct SqldbBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl SqldbDatabase {
+    fn execute(&mut self, sql: &str) -> Result<ResultSet> {
+        match &mut self.session {
+            DatabaseSession::Disk(s) => s.execute(sql),
+            DatabaseSession::Memory(s) => s.execute(sql),
+        }
+    }
+
+    // 记录错误信息，返回错误码
+    fn fail(&mut self, err: &Error) -> c_int {
+        self.errmsg = CString::new(err.to_string().replace('\0', "")).ok();
+        error_code(err)
+    }
+}
+
+fn error_code(err: &Error) -> c_int {
+    match err {
+        Error::Internal(_) => SQLDB_INTERNAL,
+        Error::Parse(_) => SQLDB_PARSE,
+        Error::WriteConflict | Error::Serialization | Error::Deadlock => SQLDB_CONFLICT,
+        Error::NotFound(_) => SQLDB_NOT_FOUND,
+        Error::Duplicate(_) => SQLDB_DUPLICATE,
+        Error::TypeMismatch(_) => SQLDB_TYPE_MISMATCH,
+        Error::Constraint(_) => SQLDB_CONSTRAINT,
+        Error::InvalidInput(_) => SQLDB_INVALID_INPUT,
+        Error::InvalidState(_) => SQLDB_INVALID_STATE,
+        Error::ReadOnly(_) => SQLDB_READ_ONLY,
+        Error::OutOfMemory(_) => SQLDB_OUT_OF_MEMORY,
+    }
+}
+
+fn open(session: Result<DatabaseSession>, out: *mut *mut SqldbDatabase) -> c_int {
+    match session {
+        Ok(session) => {
+            let db = Box::new(SqldbDatabase {
+                session,
+                errmsg: None,
+            });
+            unsafe { *out = Box::into_raw(db) };
+            SQLDB_OK
+        }
+        Err(err) => error_code(&err),
+    }
+}
+
+/// 打开磁盘上的数据库，path 是日志文件的路径，成功时 *out 指向新的连接
+///
+/// # Safety
+/// path 是以 \0 结尾的字符串，out 是合法的指针
+#[no_mangle]
+pub unsafe extern "C" fn sqldb_open(path: *const c_char, out: *mut *mut SqldbDatabase) -> c_int {
+    if path.is_null() || out.is_null() {
+        return SQLDB_MISUSE;
+    }
+    let path = match CStr::from_ptr(path).to_str() {
+        Ok(path) => PathBuf::from(path),
+        Err(_) => return SQLDB_MISUSE,
+    };
+    let session = DiskEngine::new(path)
+        .and_then(|engine| KVEngine::new(engine).session())
+        .map(DatabaseSession::Disk);
+    open(session, out)
+}
+
+/// 打开一个内存数据库，关闭之后数据丢失
+///
+/// # Safety
+/// out 是合法的指针
+#[no_mangle]
+pub unsafe extern "C" fn sqldb_open_memory(out: *mut *mut SqldbDatabase) -> c_int {
+    if out.is_null() {
+        return SQLDB_MISUSE;
+    }
+    let session = KVEngine::new(MemoryEngine::new())
+        .session()
+        .map(DatabaseSession::Memory);
+    open(session, out)
+}
+
+/// 关闭连接，未提交的事务被回滚，磁盘数据库在最后一个连接关闭时关闭存储引擎
+///
+/// # Safety
+/// db 由 sqldb_open 或 sqldb_open_memory 返回，并且没有被关闭过，可以是空指针
+#[no_mangle]
+pub unsafe extern "C" fn sqldb_close(db: *mut SqldbDatabase) {
+    if !db.is_null() {
+        let mut db = Box::from_raw(db);
+        // 没有显式事务时 rollback 报错，忽略即可
+        let _ = db.execute("ROLLBACK;");
+    }
+}
+
+/// 执行一条 SQL 语句，成功时结果写入 *result
+///
+/// # Safety
+/// db 是打开的连接，sql 是以 \0 结尾的字符串，result 是合法的指针
+#[no_mangle]
+pub unsafe extern "C" fn sqldb_execute(
+    db: *mut SqldbDatabase,
+    sql: *const c_char,
+    result: *mut SqldbBuffer,
+) -> c_int {
+    if db.is_null() || sql.is_null() || result.is_null() {
+        return SQLDB_MISUSE;
+    }
+    let db = &mut *db;
+    let sql = match CStr::from_ptr(sql).to_str() {
+        Ok(sql) => sql,
+        Err(_) => return SQLDB_MISUSE,
+    };
+    match db.execute(sql) {
+        Ok(rs) => {
+            db.errmsg = None;
+            let data = result_json(rs).to_string().into_bytes().into_boxed_slice();
+            let len = data.len();
+            *result = SqldbBuffer {
+                data: Box::into_raw(data) as *mut u8,
+                len,
+            };
+            SQLDB_OK
+        }
+        Err(err) => db.fail(&err),
+    }
+}
+
+/// 开启显式事务
+///
+/// # Safety
+/// db 是打开的连接
+#[no_mangle]
+pub unsafe extern "C" fn sqldb_begin(db: *mut SqldbDatabase) -> c_int {
+    execute_control(db, "BEGIN;")
+}
+
+/// 提交事务
+///
+/// # Safety
+/// db 是打开的连接
+#[no_mangle]
+pub unsafe extern "C" fn sqldb_commit(db: *mut SqldbDatabase) -> c_int {
+    execute_control(db, "COMMIT;")
+}
+
+/// 回滚事务
+///
+/// # Safety
+/// db 是打开的连接
+#[no_mangle]
+pub unsafe extern "C" fn sqldb_rollback(db: *mut SqldbDatabase) -> c_int {
+    execute_control(db, "ROLLBACK;")
+}
+
+// 执行事务控制语句，不需要返回结果
+unsafe fn execute_control(db: *mut SqldbDatabase, sql: &str) -> c_int {
+    if db.is_null() {
+        return SQLDB_MISUSE;
+    }
+    let db = &mut *db;
+    match db.execute(sql) {
+        Ok(_) => {
+            db.errmsg = None;
+            SQLDB_OK
+        }
+        Err(err) => db.fail(&err),
+    }
+}
+
+/// 最近一次失败的错误信息，没有错误时返回空指针
+/// 返回的字符串在下一次调用 db 上的函数之前有效
+///
+/// # Safety
+/// db 是打开的连接
+#[no_mangle]
+pub unsafe extern "C" fn sqldb_errmsg(db: *const SqldbDatabase) -> *const c_char {
+    match db.as_ref().and_then(|db| db.errmsg.as_ref()) {
+        Some(msg) => msg.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// 释放 sqldb_execute 返回的结果
+///
+/// # Safety
+/// buffer 由 sqldb_execute 写入，并且没有被释放过
+#[no_mangle]
+pub unsafe extern "C" fn sqldb_buffer_free(buffer: *mut SqldbBuffer) {
+    if let Some(buffer) = buffer.as_mut() {
+        if !buffer.data.is_null() {
+            drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+                buffer.data,
+                buffer.len,
+            )));
+        }
+        buffer.data = ptr::null_mut();
+        buffer.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        ffi::{CStr, CString},
+        ptr,
+    };
+
+    use super::*;
+
+    unsafe fn execute(db: *mut SqldbDatabase, sql: &str) -> (i32, String) {
+        let sql = CString::new(sql).unwrap();
+        let mut buffer = SqldbBuffer {
+            data: ptr::null_mut(),
+            len: 0,
+        };
+        let code = sqldb_execute(db, sql.as_ptr(), &mut buffer);
+        let json = if code == SQLDB_OK {
+            std::str::from_utf8(std::slice::from_raw_parts(buffer.data, buffer.len))
+                .unwrap()
+                .to_string()
+        } else {
+            CStr::from_ptr(sqldb_errmsg(db))
+                .to_str()
+                .unwrap()
+                .to_string()
+        };
+        sqldb_buffer_free(&mut buffer);
+        assert!(buffer.data.is_null());
+        (code, json)
+    }
+
+    #[test]
+    fn test_ffi() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let path = CString::new(p.to_str().unwrap()).unwrap();
+        unsafe {
+            let mut db = ptr::null_mut();
+            assert_eq!(sqldb_open(path.as_ptr(), &mut db), SQLDB_OK);
+            let (code, _) = execute(db, "create table t (a int primary key, b text);");
+            assert_eq!(code, SQLDB_OK);
+
+            // 回滚的事务不可见
+            assert_eq!(sqldb_begin(db), SQLDB_OK);
+            execute(db, "insert into t values (1, 'x');");
+            assert_eq!(sqldb_rollback(db), SQLDB_OK);
+            assert_eq!(sqldb_begin(db), SQLDB_OK);
+            execute(db, "insert into t values (2, 'y');");
+            assert_eq!(sqldb_commit(db), SQLDB_OK);
+            assert_eq!(sqldb_commit(db), SQLDB_INVALID_STATE);
+            assert!(!sqldb_errmsg(db).is_null());
+
+            let (code, json) = execute(db, "select * from t;");
+            assert_eq!(code, SQLDB_OK);
+            assert!(sqldb_errmsg(db).is_null());
+            assert_eq!(
+                json,
+                concat!(
+                    r#"{"columns":["a","b"],"rows":[[2,"y"]],"schema":["#,
+                    r#"{"name":"a","nullable":false,"type":"INTEGER"},"#,
+                    r#"{"name":"b","nullable":true,"type":"STRING"}],"type":"scan"}"#
+                )
+            );
+            let (code, msg) = execute(db, "select * from t2;");
+            assert_eq!(code, SQLDB_NOT_FOUND);
+            assert!(msg.contains("t2"), "{}", msg);
+            sqldb_close(db);
+
+            // 重新打开之后数据依然存在
+            assert_eq!(sqldb_open(path.as_ptr(), &mut db), SQLDB_OK);
+            let (_, json) = execute(db, "select * from t;");
+            assert!(json.contains("[[2,\"y\"]]"), "{}", json);
+            sqldb_close(db);
+
+            assert_eq!(sqldb_open_memory(&mut db), SQLDB_OK);
+            assert_eq!(
+                execute(db, "create table t (a int primary key);").0,
+                SQLDB_OK
+            );
+            assert_eq!(
+                sqldb_execute(db, ptr::null(), ptr::null_mut()),
+                SQLDB_MISUSE
+            );
+            sqldb_close(db);
+        }
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+}
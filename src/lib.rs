@@ -1,3 +1,9 @@
 pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "native")]
+pub mod server;
 pub mod sql;
 pub mod storage;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -0,0 +1,249 @@
+use serde_json::json;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{
+    error::{Error, Result},
+    sql::{
+        engine::{Engine, Session},
+        executor::ResultSet,
+        json::{error_json, json_value, result_json},
+    },
+};
+
+// HTTP 前端，POST /query 执行一条 SQL 语句，结果以 JSON 返回
+// 请求体是 SQL 文本，或者 {"sql": "...", "params": [...]} 形式的 JSON，params 依次绑定到 $1、$2 ...
+// 每个连接使用一个 session，同一个连接（keep-alive）上的多个请求可以在一个显式事务中执行
+pub async fn serve<E>(listener: TcpListener, engine: E) -> Result<()>
+where
+    E: Engine + Send + 'static,
+    E::Transaction: Send,
+{
+    loop {
+        let (socket, peer) = listener.accept().await?;
+        let mut session = engine.session()?;
+        session.set_user(peer.to_string());
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(&mut session, socket).await {
+                tracing::warn!(error = %e, %peer, "http connection error");
+            }
+        });
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    content_type: Option<String>,
+    body: Vec<u8>,
+    keep_alive: bool,
+}
+
+async fn handle_connection<E: Engine + 'static>(
+    session: &mut Session<E>,
+    socket: TcpStream,
+) -> Result<()> {
+    let mut reader = BufReader::new(socket);
+    while let Some(request) = read_request(&mut reader).await? {
+        let (status, body) = match (request.method.as_str(), request.path.as_str()) {
+            ("POST", "/query") => match execute(session, &request) {
+                Ok(result) => ("200 OK", result_json(result)),
+                Err(err) => ("400 Bad Request", error_json(&err)),
+            },
+            (_, "/query") => (
+                "405 Method Not Allowed",
+                json!({"error": "use POST /query"}),
+            ),
+            _ => ("404 Not Found", json!({"error": "not found"})),
+        };
+        let body = body.to_string();
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}\r\n{}",
+            status,
+            body.len(),
+            if request.keep_alive {
+                ""
+            } else {
+                "Connection: close\r\n"
+            },
+            body
+        );
+        reader.get_mut().write_all(response.as_bytes()).await?;
+        if !request.keep_alive {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// 读取一个请求，连接关闭时返回 None
+async fn read_request(reader: &mut BufReader<TcpStream>) -> Result<Option<Request>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).await? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let (method, path, version) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(path), Some(version)) => (method, path, version),
+        _ => {
+            return Err(Error::Parse(format!(
+                "invalid http request line {}",
+                line.trim()
+            )))
+        }
+    };
+    let mut request = Request {
+        method: method.to_string(),
+        // 忽略查询参数
+        path: path.split('?').next().unwrap_or_default().to_string(),
+        content_type: None,
+        body: Vec::new(),
+        // HTTP/1.1 默认保持连接
+        keep_alive: version == "HTTP/1.1",
+    };
+
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name.trim().to_lowercase(), value.trim()),
+            None => continue,
+        };
+        match name.as_str() {
+            "content-length" => content_length = value.parse()?,
+            "content-type" => request.content_type = Some(value.to_lowercase()),
+            "connection" => request.keep_alive = value.eq_ignore_ascii_case("keep-alive"),
+            _ => {}
+        }
+    }
+    request.body = vec![0; content_length];
+    reader.read_exact(&mut request.body).await?;
+    Ok(Some(request))
+}
+
+// 请求体是 JSON 时从中取出 sql 和 params，否则整个请求体就是 SQL
+fn execute<E: Engine + 'static>(session: &mut Session<E>, request: &Request) -> Result<ResultSet> {
+    let body = std::str::from_utf8(&request.body)
+        .map_err(|e| Error::InvalidInput(format!("request body is not utf-8: {}", e)))?;
+    let is_json = request
+        .content_type
+        .as_ref()
+        .is_some_and(|t| t.starts_with("application/json"));
+    if !is_json {
+        return session.execute(body);
+    }
+
+    let invalid = |msg: &str| Error::InvalidInput(msg.to_string());
+    let request: serde_json::Value =
+        serde_json::from_str(body).map_err(|e| Error::InvalidInput(e.to_string()))?;
+    let sql = request["sql"]
+        .as_str()
+        .ok_or_else(|| invalid("missing string field sql"))?;
+    let params = match &request["params"] {
+        serde_json::Value::Null => Vec::new(),
+        serde_json::Value::Array(params) => params.iter().map(json_value).collect::<Result<_>>()?,
+        _ => return Err(invalid("params must be an array")),
+    };
+    session.execute_params(sql, params)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use crate::{error::Result, sql::engine::kv::KVEngine, storage::memory::MemoryEngine};
+
+    use super::serve;
+
+    // 发送一个请求，返回状态码和 JSON 响应体
+    async fn post(
+        stream: &mut TcpStream,
+        path: &str,
+        content_type: &str,
+        body: &str,
+    ) -> Result<(u16, serde_json::Value)> {
+        let request = format!(
+            "POST {} HTTP/1.1\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n{}",
+            path,
+            content_type,
+            body.len(),
+            body
+        );
+        stream.write_all(request.as_bytes()).await?;
+
+        let mut buf = Vec::new();
+        loop {
+            let mut chunk = [0; 1024];
+            let n = stream.read(&mut chunk).await?;
+            buf.extend_from_slice(&chunk[..n]);
+            let text = String::from_utf8_lossy(&buf).to_string();
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let len = head
+                    .lines()
+                    .find_map(|l| l.strip_prefix("Content-Length: "))
+                    .unwrap()
+                    .parse::<usize>()?;
+                if body.len() >= len {
+                    let status = head[9..12].parse()?;
+                    return Ok((status, serde_json::from_str(body).unwrap()));
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_http_query() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(serve(listener, KVEngine::new(MemoryEngine::new())));
+
+        let mut stream = TcpStream::connect(addr).await?;
+        let text = "text/plain";
+        let (status, body) = post(
+            &mut stream,
+            "/query",
+            text,
+            "create table t (a int primary key, b text);",
+        )
+        .await?;
+        assert_eq!(status, 200);
+        assert_eq!(body["type"], "create_table");
+
+        // JSON 请求体绑定参数，参数中的引号不会破坏语句
+        let sql =
+            json!({"sql": "insert into t values ($1, $2), ($3, $2);", "params": [1, "it's", 2]});
+        let (status, body) =
+            post(&mut stream, "/query", "application/json", &sql.to_string()).await?;
+        assert_eq!((status, &body["count"]), (200, &json!(2)));
+
+        let (status, body) =
+            post(&mut stream, "/query", text, "select * from t order by a;").await?;
+        assert_eq!(status, 200);
+        assert_eq!(body["columns"], json!(["a", "b"]));
+        assert_eq!(body["rows"], json!([[1, "it's"], [2, "it's"]]));
+
+        // SQL 错误返回 400 和 SQLSTATE 错误码
+        let (status, body) = post(&mut stream, "/query", text, "select * from t2;").await?;
+        assert_eq!((status, &body["code"]), (400, &json!("42704")));
+        let sql = json!({"sql": "select * from t where a = $1;"});
+        let (status, _) = post(&mut stream, "/query", "application/json", &sql.to_string()).await?;
+        assert_eq!(status, 400);
+
+        let (status, _) = post(&mut stream, "/other", text, "select 1;").await?;
+        assert_eq!(status, 404);
+        Ok(())
+    }
+}
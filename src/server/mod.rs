@@ -0,0 +1,4 @@
+// 数据库的网络前端，每个连接对应一个 Session
+pub mod http;
+pub mod pgwire;
+pub mod tcp;
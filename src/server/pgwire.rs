@@ -0,0 +1,534 @@
+use bytes::{BufMut, BytesMut};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{
+    error::{Error, Result},
+    sql::{
+        engine::{Engine, Session},
+        executor::{ColumnDesc, ResultSet},
+        types::{DataType, Row, Value},
+    },
+};
+
+// Postgres 协议前端，psql 以及标准的 Postgres 驱动可以直接连接
+// 只实现了 simple query 协议：启动、Query、Terminate，不校验密码，不支持 SSL 和 extended query 协议
+// 一个 Query 消息中可以有多条以分号分隔的语句，依次执行，出错之后的语句不再执行
+// 查询结果以文本格式返回，列的类型取结果中的列类型，类型未知时根据第一个不是 NULL 的值推断，
+// 全是 NULL 的列是 text
+
+// 协议版本 3.0
+const PROTOCOL_VERSION: i32 = 196608;
+const SSL_REQUEST: i32 = 80877103;
+const GSSENC_REQUEST: i32 = 80877104;
+const CANCEL_REQUEST: i32 = 80877102;
+
+// 类型的 oid 以及长度，变长类型的长度为 -1
+const BOOL: (i32, i16) = (16, 1);
+const INT8: (i32, i16) = (20, 8);
+const FLOAT8: (i32, i16) = (701, 8);
+const TEXT: (i32, i16) = (25, -1);
+const DATE: (i32, i16) = (1082, 4);
+const TIMESTAMP: (i32, i16) = (1114, 8);
+const NUMERIC: (i32, i16) = (1700, -1);
+const BYTEA: (i32, i16) = (17, -1);
+
+pub async fn serve<E>(listener: TcpListener, engine: E) -> Result<()>
+where
+    E: Engine + Send + 'static,
+    E::Transaction: Send,
+{
+    loop {
+        let (socket, peer) = listener.accept().await?;
+        let mut session = engine.session()?;
+        session.set_user(peer.to_string());
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(&mut session, socket).await {
+                tracing::warn!(error = %e, %peer, "pgwire connection error");
+            }
+            if session.in_transaction() {
+                if let Err(e) = session.execute("rollback;") {
+                    tracing::warn!(error = %e, %peer, "rollback on disconnect failed");
+                }
+            }
+        });
+    }
+}
+
+async fn handle_connection<E: Engine + 'static>(
+    session: &mut Session<E>,
+    socket: TcpStream,
+) -> Result<()> {
+    let mut conn = Connection {
+        stream: BufReader::new(socket),
+        buf: BytesMut::new(),
+    };
+    if !conn.startup(session).await? {
+        return Ok(());
+    }
+    // extended query 协议的消息出错之后，忽略之后的消息直到 Sync
+    let mut skip = false;
+    while let Some((tag, body)) = conn.read_message().await? {
+        match tag {
+            b'Q' => {
+                let sql = String::from_utf8_lossy(body.strip_suffix(&[0]).unwrap_or(&body));
+                simple_query(session, &sql, &mut conn.buf);
+                conn.ready(session.in_transaction()).await?;
+            }
+            b'X' => break,
+            b'S' => {
+                skip = false;
+                conn.ready(session.in_transaction()).await?;
+            }
+            b'H' => {}
+            _ if skip => {}
+            b'P' | b'B' | b'D' | b'E' | b'C' => {
+                skip = true;
+                write_error(
+                    &mut conn.buf,
+                    &Error::InvalidInput("extended query protocol is not supported".into()),
+                );
+                conn.flush().await?;
+            }
+            tag => {
+                write_error(
+                    &mut conn.buf,
+                    &Error::InvalidInput(format!("unsupported message type {}", tag as char)),
+                );
+                conn.ready(session.in_transaction()).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+struct Connection {
+    stream: BufReader<TcpStream>,
+    // 待发送的消息
+    buf: BytesMut,
+}
+
+impl Connection {
+    // 处理启动消息，返回是否继续处理查询
+    // 客户端请求 SSL、GSSAPI 加密时回复 N，客户端会继续使用明文发送启动消息
+    async fn startup<E: Engine + 'static>(&mut self, session: &mut Session<E>) -> Result<bool> {
+        loop {
+            let len = match self.stream.read_i32().await {
+                Ok(len) => len,
+                Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(false),
+                Err(err) => return Err(err.into()),
+            };
+            let code = self.stream.read_i32().await?;
+            let mut body = vec![0; (len as usize).saturating_sub(8)];
+            self.stream.read_exact(&mut body).await?;
+            match code {
+                SSL_REQUEST | GSSENC_REQUEST => {
+                    self.stream.get_mut().write_all(b"N").await?;
+                }
+                CANCEL_REQUEST => return Ok(false),
+                PROTOCOL_VERSION => break,
+                code => {
+                    write_error(
+                        &mut self.buf,
+                        &Error::InvalidInput(format!("unsupported protocol version {}", code)),
+                    );
+                    self.flush().await?;
+                    return Ok(false);
+                }
+            }
+        }
+
+        // 不校验用户和密码，直接认证成功
+        message(&mut self.buf, b'R', |b| b.put_i32(0));
+        for (name, value) in [
+            ("server_version", "14.0"),
+            ("server_encoding", "UTF8"),
+            ("client_encoding", "UTF8"),
+            ("DateStyle", "ISO, YMD"),
+            ("integer_datetimes", "on"),
+            ("standard_conforming_strings", "on"),
+        ] {
+            message(&mut self.buf, b'S', |b| {
+                put_cstr(b, name);
+                put_cstr(b, value);
+            });
+        }
+        let id = session.id();
+        message(&mut self.buf, b'K', |b| {
+            b.put_i32(id as i32);
+            b.put_i32(0);
+        });
+        self.ready(session.in_transaction()).await?;
+        Ok(true)
+    }
+
+    // 读取一条消息的类型和内容，连接关闭时返回 None
+    async fn read_message(&mut self) -> Result<Option<(u8, Vec<u8>)>> {
+        let tag = match self.stream.read_u8().await {
+            Ok(tag) => tag,
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let len = self.stream.read_i32().await?;
+        let mut body = vec![0; (len as usize).saturating_sub(4)];
+        self.stream.read_exact(&mut body).await?;
+        Ok(Some((tag, body)))
+    }
+
+    // ReadyForQuery，带上当前的事务状态
+    async fn ready(&mut self, in_transaction: bool) -> Result<()> {
+        let status = if in_transaction { b'T' } else { b'I' };
+        message(&mut self.buf, b'Z', |b| b.put_u8(status));
+        self.flush().await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        let buf = self.buf.split();
+        self.stream.get_mut().write_all(&buf).await?;
+        Ok(())
+    }
+}
+
+fn simple_query<E: Engine + 'static>(session: &mut Session<E>, sql: &str, buf: &mut BytesMut) {
+    let statements = split_statements(sql);
+    if statements.is_empty() {
+        message(buf, b'I', |_| {});
+        return;
+    }
+    for stmt in statements {
+        match session.execute(&stmt) {
+            Ok(result) => write_result(buf, result),
+            Err(err) => {
+                write_error(buf, &err);
+                break;
+            }
+        }
+    }
+}
+
+// 按照引号之外的分号拆分语句，每条语句以分号结尾
+fn split_statements(sql: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in sql.char_indices() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, ';') => {
+                statements.push(&sql[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    statements.push(&sql[start..]);
+    statements
+        .into_iter()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| format!("{};", s))
+        .collect()
+}
+
+fn write_result(buf: &mut BytesMut, result: ResultSet) {
+    let tag = match result {
+        ResultSet::Scan { columns, rows } => {
+            let tag = format!("SELECT {}", rows.len());
+            write_rows(buf, &columns, &rows);
+            tag
+        }
+        // 执行计划每行作为一行结果返回
+        ResultSet::Explain { plan } => {
+            let rows = plan
+                .lines()
+                .map(|line| vec![Value::String(line.to_string())])
+                .collect::<Vec<_>>();
+            let column = ColumnDesc::new("QUERY PLAN", Some(DataType::String), false);
+            write_rows(buf, &[column], &rows);
+            "EXPLAIN".to_string()
+        }
+        ResultSet::Insert { count } => format!("INSERT 0 {}", count),
+        ResultSet::Update { count } => format!("UPDATE {}", count),
+        ResultSet::Delete { count } => format!("DELETE {}", count),
+        ResultSet::Copy { count } => format!("COPY {}", count),
+        ResultSet::CreateTable { .. } => "CREATE TABLE".into(),
+        ResultSet::DropTable { .. } => "DROP TABLE".into(),
+        ResultSet::TruncateTable { .. } => "TRUNCATE TABLE".into(),
+        ResultSet::AlterTable { .. } => "ALTER TABLE".into(),
+        ResultSet::Reindex { .. } => "REINDEX".into(),
+        ResultSet::CreateIndex { .. } => "CREATE INDEX".into(),
+        ResultSet::DropIndex { .. } => "DROP INDEX".into(),
+        ResultSet::Begin { .. } => "BEGIN".into(),
+        ResultSet::Commit { .. } => "COMMIT".into(),
+        ResultSet::Rollback { .. } | ResultSet::RollbackToSavepoint { .. } => "ROLLBACK".into(),
+        ResultSet::Savepoint { .. } => "SAVEPOINT".into(),
+        ResultSet::ReleaseSavepoint { .. } => "RELEASE".into(),
+        ResultSet::Set { .. } => "SET".into(),
+        ResultSet::Vacuum { .. } => "VACUUM".into(),
+        ResultSet::Backup { .. } => "BACKUP".into(),
+        ResultSet::Restore { .. } => "RESTORE".into(),
+        ResultSet::DeclareCursor { .. } => "DECLARE CURSOR".into(),
+        ResultSet::CloseCursor { .. } => "CLOSE CURSOR".into(),
+    };
+    message(buf, b'C', |b| put_cstr(b, &tag));
+}
+
+// RowDescription 以及每一行的 DataRow
+fn write_rows(buf: &mut BytesMut, columns: &[ColumnDesc], rows: &[Row]) {
+    message(buf, b'T', |b| {
+        b.put_i16(columns.len() as i16);
+        for (i, column) in columns.iter().enumerate() {
+            let (oid, len) = match &column.datatype {
+                Some(datatype) => datatype_oid(datatype),
+                None => column_type(rows, i),
+            };
+            put_cstr(b, &column.name);
+            b.put_i32(0);
+            b.put_i16(0);
+            b.put_i32(oid);
+            b.put_i16(len);
+            b.put_i32(-1);
+            b.put_i16(0);
+        }
+    });
+    for row in rows {
+        message(buf, b'D', |b| {
+            b.put_i16(row.len() as i16);
+            for value in row {
+                match text(value) {
+                    Some(s) => {
+                        b.put_i32(s.len() as i32);
+                        b.put_slice(s.as_bytes());
+                    }
+                    None => b.put_i32(-1),
+                }
+            }
+        });
+    }
+}
+
+// 区间和数组的文本格式和 Postgres 不同，作为 text 返回
+fn column_type(rows: &[Row], i: usize) -> (i32, i16) {
+    match rows.iter().map(|row| &row[i]).find(|v| **v != Value::Null) {
+        Some(Value::Boolean(_)) => BOOL,
+        Some(Value::Integer(_)) => INT8,
+        Some(Value::Float(_)) => FLOAT8,
+        Some(Value::Date(_)) => DATE,
+        Some(Value::Timestamp(_)) => TIMESTAMP,
+        Some(Value::Decimal(_)) => NUMERIC,
+        Some(Value::Blob(_)) => BYTEA,
+        _ => TEXT,
+    }
+}
+
+fn datatype_oid(datatype: &DataType) -> (i32, i16) {
+    match datatype {
+        DataType::Boolean => BOOL,
+        DataType::Integer => INT8,
+        DataType::Float => FLOAT8,
+        DataType::Date => DATE,
+        DataType::Timestamp => TIMESTAMP,
+        DataType::Decimal(..) => NUMERIC,
+        DataType::Blob => BYTEA,
+        DataType::String | DataType::Interval | DataType::Array(_) => TEXT,
+    }
+}
+
+// 值的文本格式，NULL 返回 None
+fn text(value: &Value) -> Option<String> {
+    Some(match value {
+        Value::Null => return None,
+        Value::Boolean(b) => if *b { "t" } else { "f" }.into(),
+        Value::Float(f) if f.is_nan() => "NaN".into(),
+        Value::Float(f) if f.is_infinite() => {
+            if *f > 0.0 { "Infinity" } else { "-Infinity" }.into()
+        }
+        v => v.to_string(),
+    })
+}
+
+// ErrorResponse，内部错误的错误码是 XX000
+fn write_error(buf: &mut BytesMut, err: &Error) {
+    message(buf, b'E', |b| {
+        for (field, value) in [
+            (b'S', "ERROR"),
+            (b'V', "ERROR"),
+            (b'C', err.sqlstate().unwrap_or("XX000")),
+            (b'M', &err.to_string()),
+        ] {
+            b.put_u8(field);
+            put_cstr(b, value);
+        }
+        b.put_u8(0);
+    });
+}
+
+// 写入一条消息：类型、包括长度本身在内的长度以及内容
+fn message(buf: &mut BytesMut, tag: u8, f: impl FnOnce(&mut BytesMut)) {
+    buf.put_u8(tag);
+    let start = buf.len();
+    buf.put_i32(0);
+    f(buf);
+    let len = (buf.len() - start) as i32;
+    buf[start..start + 4].copy_from_slice(&len.to_be_bytes());
+}
+
+fn put_cstr(buf: &mut BytesMut, s: &str) {
+    buf.put_slice(s.as_bytes());
+    buf.put_u8(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use crate::{error::Result, sql::engine::kv::KVEngine, storage::memory::MemoryEngine};
+
+    use super::{serve, split_statements, PROTOCOL_VERSION, SSL_REQUEST};
+
+    // 读取消息直到 ReadyForQuery，返回每条消息的类型和内容
+    async fn read_until_ready(stream: &mut TcpStream) -> Result<Vec<(char, Vec<u8>)>> {
+        let mut messages = Vec::new();
+        loop {
+            let tag = stream.read_u8().await?;
+            let len = stream.read_i32().await?;
+            let mut body = vec![0; len as usize - 4];
+            stream.read_exact(&mut body).await?;
+            messages.push((tag as char, body));
+            if tag == b'Z' {
+                return Ok(messages);
+            }
+        }
+    }
+
+    async fn query(stream: &mut TcpStream, sql: &str) -> Result<Vec<(char, Vec<u8>)>> {
+        let mut msg = vec![b'Q'];
+        msg.extend_from_slice(&(sql.len() as i32 + 5).to_be_bytes());
+        msg.extend_from_slice(sql.as_bytes());
+        msg.push(0);
+        stream.write_all(&msg).await?;
+        read_until_ready(stream).await
+    }
+
+    // 消息内容中以 \0 分隔的字符串，不包括整数字段
+    fn strings(body: &[u8]) -> Vec<String> {
+        body.split(|b| *b == 0)
+            .map(|s| String::from_utf8_lossy(s).to_string())
+            .collect()
+    }
+
+    // DataRow 中每一列的文本，NULL 为 None
+    fn data_row(body: &[u8]) -> Vec<Option<String>> {
+        let count = i16::from_be_bytes([body[0], body[1]]);
+        let mut pos = 2;
+        (0..count)
+            .map(|_| {
+                let len = i32::from_be_bytes(body[pos..pos + 4].try_into().unwrap());
+                pos += 4;
+                if len < 0 {
+                    return None;
+                }
+                let s = String::from_utf8_lossy(&body[pos..pos + len as usize]).to_string();
+                pos += len as usize;
+                Some(s)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_pgwire() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(serve(listener, KVEngine::new(MemoryEngine::new())));
+
+        // 先请求 SSL，服务端回复 N 之后发送启动消息
+        let mut stream = TcpStream::connect(addr).await?;
+        stream.write_all(&8i32.to_be_bytes()).await?;
+        stream.write_all(&SSL_REQUEST.to_be_bytes()).await?;
+        assert_eq!(stream.read_u8().await?, b'N');
+        let params = b"user\0postgres\0database\0postgres\0\0";
+        stream
+            .write_all(&(8 + params.len() as i32).to_be_bytes())
+            .await?;
+        stream.write_all(&PROTOCOL_VERSION.to_be_bytes()).await?;
+        stream.write_all(params).await?;
+        let messages = read_until_ready(&mut stream).await?;
+        assert_eq!(messages[0], ('R', vec![0, 0, 0, 0]));
+        assert!(messages.iter().any(|(tag, _)| *tag == 'K'));
+        assert_eq!(messages.last().unwrap(), &('Z', vec![b'I']));
+
+        let messages = query(
+            &mut stream,
+            "create table t (a int primary key, b text, c float); \
+             insert into t values (1, 'x;y', 1.5), (2, null, null)",
+        )
+        .await?;
+        let tags = messages
+            .iter()
+            .map(|(tag, body)| format!("{} {}", tag, strings(body)[0]))
+            .collect::<Vec<_>>();
+        assert_eq!(tags, vec!["C CREATE TABLE", "C INSERT 0 2", "Z I"]);
+
+        // 查询结果：列的名字和类型，每一行的文本
+        let messages = query(&mut stream, "select * from t order by a;").await?;
+        let tags = messages.iter().map(|(tag, _)| *tag).collect::<String>();
+        assert_eq!(tags, "TDDCZ");
+        let desc = strings(&messages[0].1[2..]);
+        assert_eq!(desc[0], "a");
+        let oid = |name: &str| {
+            let body = &messages[0].1;
+            let pos = body
+                .windows(name.len() + 1)
+                .position(|w| w == [name.as_bytes(), &[0]].concat())
+                .unwrap()
+                + name.len()
+                + 1;
+            i32::from_be_bytes(body[pos + 6..pos + 10].try_into().unwrap())
+        };
+        assert_eq!((oid("a"), oid("b"), oid("c")), (20, 25, 701));
+        assert_eq!(
+            data_row(&messages[1].1),
+            vec![Some("1".into()), Some("x;y".into()), Some("1.5".into())]
+        );
+        assert_eq!(data_row(&messages[2].1), vec![Some("2".into()), None, None]);
+        assert_eq!(strings(&messages[3].1)[0], "SELECT 2");
+
+        // 事务状态，出错之后的语句不再执行
+        let messages = query(&mut stream, "begin; select * from t2; delete from t;").await?;
+        assert_eq!(messages[0].0, 'C');
+        assert_eq!(messages[1].0, 'E');
+        assert!(strings(&messages[1].1).contains(&"C42704".to_string()));
+        assert_eq!(messages[2], ('Z', vec![b'T']));
+        let messages = query(&mut stream, "rollback; select count(a) from t;").await?;
+        assert_eq!(data_row(&messages[2].1), vec![Some("2".into())]);
+        assert_eq!(messages.last().unwrap(), &('Z', vec![b'I']));
+
+        // 空语句以及不支持的 extended query 协议
+        let messages = query(&mut stream, " ; ").await?;
+        assert_eq!(messages[0].0, 'I');
+        stream.write_all(b"P\0\0\0\x08\0\0\0\0").await?;
+        stream.write_all(b"S\0\0\0\x04").await?;
+        let messages = read_until_ready(&mut stream).await?;
+        let tags = messages.iter().map(|(tag, _)| *tag).collect::<String>();
+        assert_eq!(tags, "EZ");
+
+        stream.write_all(b"X\0\0\0\x04").await?;
+        assert_eq!(stream.read(&mut [0; 1]).await?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_statements() {
+        assert_eq!(
+            split_statements("select 1; select ';' ;  ;select \"a;b\" from t"),
+            vec!["select 1;", "select ';';", "select \"a;b\" from t;"]
+        );
+        assert!(split_statements(" ; ").is_empty());
+    }
+}
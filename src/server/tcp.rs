@@ -0,0 +1,238 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+use crate::{
+    error::{Error, Result},
+    sql::{
+        engine::{audit::AuditLog, Engine, Session},
+        executor::ResultSet,
+        types::Value,
+    },
+};
+
+// TCP 前端，每个帧是 4 字节大端的长度加上 bincode 编码的 Request 或者 Response
+// 客户端每发送一个请求，服务端返回一个响应
+// 每个连接使用一个 session，同一个连接上的多个请求可以在一个显式事务中执行，连接断开时回滚没有提交的事务
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Request {
+    // 执行一条 SQL 语句，params 依次绑定到 $1、$2 ...
+    Execute { sql: String, params: Vec<Value> },
+    // 所有的表名，每行一个
+    ListTables,
+    // 表结构
+    GetTable(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Response {
+    Execute(ResultSet),
+    // ListTables、GetTable 返回的文本
+    Text(String),
+    Error(Error),
+}
+
+pub async fn serve<E>(
+    listener: TcpListener,
+    engine: E,
+    audit_log: Option<Arc<AuditLog>>,
+) -> Result<()>
+where
+    E: Engine + Send + 'static,
+    E::Transaction: Send,
+{
+    loop {
+        let (socket, peer) = listener.accept().await?;
+        let mut session = engine.session()?;
+        session.set_user(peer.to_string());
+        if let Some(audit_log) = &audit_log {
+            session.set_audit_log(audit_log.clone());
+        }
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(&mut session, socket).await {
+                tracing::warn!(error = %e, %peer, "tcp connection error");
+            }
+            if session.in_transaction() {
+                if let Err(e) = session.execute("rollback;") {
+                    tracing::warn!(error = %e, %peer, "rollback on disconnect failed");
+                }
+            }
+        });
+    }
+}
+
+async fn handle_connection<E: Engine + 'static>(
+    session: &mut Session<E>,
+    socket: TcpStream,
+) -> Result<()> {
+    let mut framed = Framed::new(socket, LengthDelimitedCodec::new());
+    while let Some(frame) = framed.next().await {
+        let request: Request = bincode::deserialize(&frame?)?;
+        let response = match request {
+            Request::Execute { sql, params } => {
+                session.execute_params(&sql, params).map(Response::Execute)
+            }
+            Request::ListTables => session.get_table_names().map(Response::Text),
+            Request::GetTable(table_name) => session.get_table(table_name).map(Response::Text),
+        };
+        let response = response.unwrap_or_else(Response::Error);
+        framed
+            .send(Bytes::from(bincode::serialize(&response)?))
+            .await?;
+    }
+    Ok(())
+}
+
+// 客户端，记录当前连接上显式事务的版本号
+pub struct Client {
+    framed: Framed<TcpStream, LengthDelimitedCodec>,
+    txn_version: Option<u64>,
+}
+
+impl Client {
+    pub async fn connect(addr: impl ToSocketAddrs) -> Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        Ok(Self {
+            framed: Framed::new(stream, LengthDelimitedCodec::new()),
+            txn_version: None,
+        })
+    }
+
+    // 当前显式事务的版本号，不在事务中时为空
+    pub fn txn_version(&self) -> Option<u64> {
+        self.txn_version
+    }
+
+    pub async fn execute(&mut self, sql: &str) -> Result<ResultSet> {
+        self.execute_params(sql, Vec::new()).await
+    }
+
+    pub async fn execute_params(&mut self, sql: &str, params: Vec<Value>) -> Result<ResultSet> {
+        let request = Request::Execute {
+            sql: sql.into(),
+            params,
+        };
+        let result = match self.call(request).await? {
+            Response::Execute(result) => result,
+            response => return Err(unexpected(response)),
+        };
+        match result {
+            ResultSet::Begin { version } => self.txn_version = Some(version),
+            ResultSet::Commit { .. } | ResultSet::Rollback { .. } => self.txn_version = None,
+            _ => {}
+        }
+        Ok(result)
+    }
+
+    pub async fn list_tables(&mut self) -> Result<String> {
+        match self.call(Request::ListTables).await? {
+            Response::Text(names) => Ok(names),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    pub async fn get_table(&mut self, table_name: &str) -> Result<String> {
+        match self.call(Request::GetTable(table_name.into())).await? {
+            Response::Text(table) => Ok(table),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    // 发送一个请求并等待响应，服务端返回的错误转换成 Err
+    async fn call(&mut self, request: Request) -> Result<Response> {
+        self.framed
+            .send(Bytes::from(bincode::serialize(&request)?))
+            .await?;
+        match self.framed.next().await {
+            Some(frame) => match bincode::deserialize(&frame?)? {
+                Response::Error(err) => Err(err),
+                response => Ok(response),
+            },
+            None => Err(Error::Internal("connection closed by server".into())),
+        }
+    }
+}
+
+fn unexpected(response: Response) -> Error {
+    Error::Internal(format!("unexpected response {:?}", response))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::net::TcpListener;
+
+    use crate::{
+        error::{Error, Result},
+        sql::{engine::kv::KVEngine, executor::ResultSet, types::Value},
+        storage::memory::MemoryEngine,
+    };
+
+    use super::{serve, Client};
+
+    #[tokio::test]
+    async fn test_tcp_client() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(serve(listener, KVEngine::new(MemoryEngine::new()), None));
+
+        let mut c1 = Client::connect(addr).await?;
+        let mut c2 = Client::connect(addr).await?;
+        c1.execute("create table t (a int primary key, b text);")
+            .await?;
+        let result = c1
+            .execute_params(
+                "insert into t values ($1, $2);",
+                vec![Value::Integer(1), Value::String("x".into())],
+            )
+            .await?;
+        assert_eq!(result, ResultSet::Insert { count: 1 });
+        assert_eq!(c2.list_tables().await?, "t");
+        assert!(c2.get_table("t").await?.starts_with("CREATE TABLE t"));
+
+        // 事务状态保存在各自连接的 session 中
+        c1.execute("begin;").await?;
+        assert!(c1.txn_version().is_some());
+        c1.execute("insert into t values (2, 'y');").await?;
+        let count = |result: ResultSet| match result {
+            ResultSet::Scan { rows, .. } => rows.len(),
+            _ => unreachable!(),
+        };
+        assert_eq!(count(c2.execute("select * from t;").await?), 1);
+        c1.execute("commit;").await?;
+        assert_eq!(c1.txn_version(), None);
+        assert_eq!(count(c2.execute("select * from t;").await?), 2);
+
+        // 错误原样返回给客户端，连接可以继续使用
+        assert!(matches!(
+            c2.execute("select * from t2;").await,
+            Err(Error::NotFound(_))
+        ));
+        assert!(matches!(
+            c2.execute("insert into t values (1, 'z');").await,
+            Err(Error::Duplicate(_))
+        ));
+
+        // 连接断开时回滚没有提交的事务
+        c2.execute("begin;").await?;
+        c2.execute("insert into t values (3, 'z');").await?;
+        drop(c2);
+        let mut c3 = Client::connect(addr).await?;
+        let mut result = c3.execute("insert into t values (3, 'w');").await;
+        for _ in 0..100 {
+            if !matches!(result, Err(Error::WriteConflict)) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            result = c3.execute("insert into t values (3, 'w');").await;
+        }
+        assert_eq!(result?, ResultSet::Insert { count: 1 });
+        Ok(())
+    }
+}
@@ -0,0 +1,335 @@
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+use crate::error::{Error, Result};
+
+use super::{
+    engine::{Engine, Session},
+    functions::random::Rng,
+};
+
+// 基准测试，用可复现的负载评估存储引擎和执行器的改动
+// prepare 创建 sysbench 风格的表 sbtest1 ... sbtestN 并批量生成数据：
+//
+//   create table sbtestN (id int primary key, k int index, c text, pad text);
+//
+// id 从 1 到 rows 连续，k 在 [1, rows] 中随机，c、pad 是长度为 value_len 的随机字符串
+// run 启动多个线程，每个线程使用一个 session 执行 ops 次操作，统计吞吐和延迟分位数
+// 相同的 seed 生成相同的数据和操作序列
+
+// 插入数据时每条 insert 语句的行数
+const INSERT_BATCH: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Workload {
+    // select c from sbtest where id = ?
+    PointSelect,
+    // select c from sbtest where id > ? limit range
+    Range,
+    // update sbtest set k = k + 1 where id = ?
+    Update,
+    // 按照 k 和另一张表的 id 做 join
+    Join,
+    // 按照 7:1:1:1 的比例混合以上四种操作
+    Mixed,
+}
+
+impl Workload {
+    pub fn parse(name: &str) -> Result<Self> {
+        Ok(match name {
+            "point_select" => Self::PointSelect,
+            "range" => Self::Range,
+            "update" => Self::Update,
+            "join" => Self::Join,
+            "mixed" => Self::Mixed,
+            _ => return Err(Error::InvalidInput(format!("unknown workload {}", name))),
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::PointSelect => "point_select",
+            Self::Range => "range",
+            Self::Update => "update",
+            Self::Join => "join",
+            Self::Mixed => "mixed",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    // 表的个数
+    pub tables: usize,
+    // 每张表的行数
+    pub rows: usize,
+    // c、pad 列的字符串长度
+    pub value_len: usize,
+    // 并发的线程数
+    pub threads: usize,
+    // 每个线程执行的操作数
+    pub ops: usize,
+    // 范围查询返回的行数
+    pub range: usize,
+    pub workload: Workload,
+    pub seed: u64,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            tables: 1,
+            rows: 10000,
+            value_len: 60,
+            threads: 4,
+            ops: 1000,
+            range: 100,
+            workload: Workload::PointSelect,
+            seed: 1,
+        }
+    }
+}
+
+// 测试结果，latencies 是每次成功操作的耗时，从小到大排序
+#[derive(Debug)]
+pub struct BenchReport {
+    pub workload: Workload,
+    pub threads: usize,
+    // 写冲突等失败的操作数，不计入延迟统计
+    pub errors: usize,
+    pub elapsed: Duration,
+    pub latencies: Vec<Duration>,
+}
+
+impl BenchReport {
+    // 每秒完成的操作数
+    pub fn throughput(&self) -> f64 {
+        self.latencies.len() as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    // 延迟分位数，p 在 0 到 100 之间
+    pub fn percentile(&self, p: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = (p / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+}
+
+impl Display for BenchReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        writeln!(f, "workload:   {}", self.workload.name())?;
+        writeln!(f, "threads:    {}", self.threads)?;
+        writeln!(
+            f,
+            "ops:        {} ({} errors)",
+            self.latencies.len(),
+            self.errors
+        )?;
+        writeln!(f, "elapsed:    {:.3}s", self.elapsed.as_secs_f64())?;
+        writeln!(f, "throughput: {:.1} ops/s", self.throughput())?;
+        write!(
+            f,
+            "latency ms: p50 {:.3}, p95 {:.3}, p99 {:.3}, max {:.3}",
+            ms(self.percentile(50.0)),
+            ms(self.percentile(95.0)),
+            ms(self.percentile(99.0)),
+            ms(self.percentile(100.0)),
+        )
+    }
+}
+
+// 创建测试表并生成数据，已经存在的同名表会被删除
+pub fn prepare<E: Engine + 'static>(engine: &E, config: &BenchConfig) -> Result<()> {
+    let mut session = engine.session()?;
+    let mut rng = Rng::new(config.seed);
+    for table in 1..=config.tables {
+        match session.execute(&format!("drop table sbtest{};", table)) {
+            Ok(_) | Err(Error::NotFound(_)) => {}
+            Err(err) => return Err(err),
+        }
+        session.execute(&format!(
+            "create table sbtest{} (id int primary key, k int index, c text, pad text);",
+            table
+        ))?;
+
+        let mut id = 1;
+        while id <= config.rows {
+            let end = (id + INSERT_BATCH).min(config.rows + 1);
+            let values = (id..end)
+                .map(|id| {
+                    format!(
+                        "({}, {}, '{}', '{}')",
+                        id,
+                        random_id(&mut rng, config.rows),
+                        random_string(&mut rng, config.value_len),
+                        random_string(&mut rng, config.value_len)
+                    )
+                })
+                .collect::<Vec<_>>();
+            session.execute(&format!(
+                "insert into sbtest{} values {};",
+                table,
+                values.join(", ")
+            ))?;
+            id = end;
+        }
+    }
+    Ok(())
+}
+
+// 多线程执行负载，需要先调用 prepare 生成数据
+pub fn run<E>(engine: &E, config: &BenchConfig) -> Result<BenchReport>
+where
+    E: Engine + Send + 'static,
+{
+    if config.tables == 0 || config.rows == 0 {
+        return Err(Error::InvalidInput("bench needs at least one row".into()));
+    }
+    let start = Instant::now();
+    let results = std::thread::scope(|scope| {
+        let handles = (0..config.threads)
+            .map(|thread| {
+                let engine = engine.clone();
+                scope.spawn(move || {
+                    let mut session = engine.session()?;
+                    // 每个线程使用不同的 seed，操作序列依然可以复现
+                    let rng = Rng::new(config.seed.wrapping_add(thread as u64 + 1));
+                    run_thread(&mut session, config, rng)
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|h| {
+                h.join()
+                    .unwrap_or_else(|_| Err(Error::Internal("bench thread panicked".into())))
+            })
+            .collect::<Result<Vec<_>>>()
+    })?;
+    let elapsed = start.elapsed();
+
+    let mut report = BenchReport {
+        workload: config.workload,
+        threads: config.threads,
+        errors: 0,
+        elapsed,
+        latencies: Vec::with_capacity(config.threads * config.ops),
+    };
+    for (latencies, errors) in results {
+        report.latencies.extend(latencies);
+        report.errors += errors;
+    }
+    report.latencies.sort();
+    Ok(report)
+}
+
+// 单个线程执行 ops 次操作，返回成功操作的耗时和失败的次数
+fn run_thread<E: Engine + 'static>(
+    session: &mut Session<E>,
+    config: &BenchConfig,
+    mut rng: Rng,
+) -> Result<(Vec<Duration>, usize)> {
+    let mut latencies = Vec::with_capacity(config.ops);
+    let mut errors = 0;
+    for _ in 0..config.ops {
+        let sql = next_statement(&mut rng, config);
+        let start = Instant::now();
+        match session.execute(&sql) {
+            Ok(_) => latencies.push(start.elapsed()),
+            // 并发更新同一行时写冲突是预期的
+            Err(Error::WriteConflict | Error::Serialization) => errors += 1,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok((latencies, errors))
+}
+
+// 按照负载类型随机生成下一条语句
+fn next_statement(rng: &mut Rng, config: &BenchConfig) -> String {
+    let workload = match config.workload {
+        Workload::Mixed => match rng.next_u64() % 10 {
+            0..=6 => Workload::PointSelect,
+            7 => Workload::Range,
+            8 => Workload::Update,
+            _ => Workload::Join,
+        },
+        workload => workload,
+    };
+    let table = rng.next_u64() as usize % config.tables + 1;
+    let id = random_id(rng, config.rows);
+    match workload {
+        Workload::PointSelect => format!("select c from sbtest{} where id = {};", table, id),
+        Workload::Range => format!(
+            "select c from sbtest{} where id > {} limit {};",
+            table, id, config.range
+        ),
+        Workload::Update => format!("update sbtest{} set k = k + 1 where id = {};", table, id),
+        Workload::Join => {
+            let other = table % config.tables + 1;
+            format!(
+                "select a.id, sbtest{0}.c from (select id, k from sbtest{1} where id = {2}) as a \
+                 join sbtest{0} on a.k = sbtest{0}.id;",
+                other, table, id
+            )
+        }
+        Workload::Mixed => unreachable!(),
+    }
+}
+
+fn random_id(rng: &mut Rng, rows: usize) -> usize {
+    rng.next_u64() as usize % rows + 1
+}
+
+fn random_string(rng: &mut Rng, len: usize) -> String {
+    (0..len)
+        .map(|_| (b'a' + (rng.next_u64() % 26) as u8) as char)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::Result,
+        sql::engine::{kv::KVEngine, Engine},
+        storage::memory::MemoryEngine,
+    };
+
+    use super::{prepare, run, BenchConfig, Workload};
+
+    #[test]
+    fn test_bench() -> Result<()> {
+        let engine = KVEngine::new(MemoryEngine::new());
+        let mut config = BenchConfig {
+            tables: 2,
+            rows: 1200,
+            value_len: 10,
+            threads: 3,
+            ops: 20,
+            ..Default::default()
+        };
+        prepare(&engine, &config)?;
+        let mut s = engine.session()?;
+        let rows: Vec<(i64, String)> = s.query_as("select id, c from sbtest2 where id = 1200;")?;
+        assert_eq!(rows[0].1.len(), 10);
+
+        for workload in ["point_select", "range", "update", "join", "mixed"] {
+            config.workload = Workload::parse(workload)?;
+            let report = run(&engine, &config)?;
+            assert_eq!(report.latencies.len() + report.errors, 60);
+            assert!(report.percentile(50.0) <= report.percentile(99.0));
+            assert!(report.to_string().contains(workload));
+        }
+        assert!(Workload::parse("other").is_err());
+
+        // 重新生成数据时删除旧表，相同的 seed 生成相同的数据
+        prepare(&engine, &config)?;
+        let again: Vec<(i64, String)> = s.query_as("select id, c from sbtest2 where id = 1200;")?;
+        assert_eq!(rows, again);
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+};
+
+#[cfg(not(feature = "wasm"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(feature = "wasm")]
+use web_time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    error::{Error, Result},
+    sql::{executor::ResultSet, parser::ast::{CopyDirection, Statement}},
+};
+
+// 审计日志，记录每个 session 执行过的 DML 和 DDL 语句
+// 每条记录占一行，字段之间用 \t 分隔:
+// 时间戳(毫秒)  session  用户  事务版本号  影响行数  执行结果  SQL
+pub struct AuditLog {
+    file: Mutex<File>,
+}
+
+// 一条审计记录
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditRecord {
+    pub timestamp: u128,
+    pub session_id: u64,
+    pub user: Option<String>,
+    pub version: Option<u64>,
+    pub rows: usize,
+    pub error: Option<String>,
+    pub sql: String,
+}
+
+impl AuditLog {
+    // 以追加的方式打开审计日志文件
+    pub fn open(file_path: PathBuf) -> Result<Self> {
+        if let Some(dir) = file_path.parent() {
+            if !dir.exists() {
+                std::fs::create_dir_all(dir)?;
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&file_path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    // 写入一条审计记录
+    pub fn record(&self, record: &AuditRecord) -> Result<()> {
+        let line = format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            record.timestamp,
+            record.session_id,
+            record.user.as_deref().unwrap_or("-"),
+            record.version.map_or("-".into(), |v| v.to_string()),
+            record.rows,
+            record.error.as_deref().unwrap_or("OK").replace(['\t', '\n'], " "),
+            record.sql.replace(['\t', '\n'], " "),
+        );
+        let mut file = self.file.lock()?;
+        file.write_all(line.as_bytes())?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+impl AuditRecord {
+    pub fn new(session_id: u64, user: Option<String>, sql: &str) -> Self {
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis()),
+            session_id,
+            user,
+            version: None,
+            rows: 0,
+            error: None,
+            sql: sql.trim().into(),
+        }
+    }
+
+    // 根据执行结果填充影响行数和错误信息
+    pub fn set_result(&mut self, result: &Result<ResultSet>) {
+        match result {
+            Ok(ResultSet::Insert { count })
+            | Ok(ResultSet::Update { count })
+            | Ok(ResultSet::Delete { count })
+            | Ok(ResultSet::Reindex { count, .. })
+            | Ok(ResultSet::CreateIndex { count, .. })
+            | Ok(ResultSet::TruncateTable { count, .. })
+            | Ok(ResultSet::Vacuum { count })
+            | Ok(ResultSet::Restore { count })
+            | Ok(ResultSet::Copy { count }) => self.rows = *count,
+            Ok(_) => {}
+            Err(err) => self.error = Some(err.to_string()),
+        }
+    }
+}
+
+// 只有 DML 和 DDL 语句需要记录审计日志
+pub fn is_audited(stmt: &Statement) -> bool {
+    if let Statement::Explain {
+        stmt,
+        analyze: true,
+        ..
+    } = stmt
+    {
+        // explain analyze 会实际执行语句
+        return is_audited(stmt);
+    }
+    matches!(
+        stmt,
+        Statement::CreateTable { .. }
+            | Statement::DropTable { .. }
+            | Statement::TruncateTable { .. }
+            | Statement::AlterTable { .. }
+            | Statement::Reindex { .. }
+            | Statement::CreateIndex { .. }
+            | Statement::DropIndex { .. }
+            | Statement::Vacuum
+            | Statement::Restore { .. }
+            | Statement::Insert { .. }
+            | Statement::Update { .. }
+            | Statement::Delete { .. }
+            | Statement::Copy {
+                direction: CopyDirection::From,
+                ..
+            }
+    )
+}
+
+// 解析一行审计日志
+impl TryFrom<&str> for AuditRecord {
+    type Error = Error;
+
+    fn try_from(line: &str) -> Result<Self> {
+        let fields = line.splitn(7, '\t').collect::<Vec<_>>();
+        if fields.len() != 7 {
+            return Err(Error::Internal(format!("invalid audit record: {}", line)));
+        }
+        let optional = |s: &str| Some(s.to_string()).filter(|s| s != "-");
+        Ok(Self {
+            timestamp: fields[0].parse()?,
+            session_id: fields[1].parse()?,
+            user: optional(fields[2]),
+            version: optional(fields[3]).map(|v| v.parse()).transpose()?,
+            rows: fields[4].parse()?,
+            error: Some(fields[5].to_string()).filter(|s| s != "OK"),
+            sql: fields[6].into(),
+        })
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, Result},
+    sql::types::Value,
+};
+
+// 压缩位图，位图索引中保存一个值对应的所有行
+// 按照高 48 位分块，每块保存低 16 位，块中的元素较少时使用有序数组，较多时使用 8KB 的位图
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bitmap {
+    containers: BTreeMap<u64, Container>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum Container {
+    Array(Vec<u16>),
+    Bits(Vec<u64>),
+}
+
+// 数组中的元素超过这个数量时转换成位图，此时两种表示占用的空间相同
+const ARRAY_MAX: usize = 4096;
+const BITS_WORDS: usize = 1024;
+
+impl Bitmap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 加入一个元素，返回元素之前是否不存在
+    pub fn insert(&mut self, value: u64) -> bool {
+        let (high, low) = split(value);
+        let container = self
+            .containers
+            .entry(high)
+            .or_insert_with(|| Container::Array(Vec::new()));
+        let inserted = match container {
+            Container::Array(items) => match items.binary_search(&low) {
+                Ok(_) => false,
+                Err(i) => {
+                    items.insert(i, low);
+                    true
+                }
+            },
+            Container::Bits(words) => {
+                let (word, bit) = (low as usize / 64, 1u64 << (low % 64));
+                let inserted = words[word] & bit == 0;
+                words[word] |= bit;
+                inserted
+            }
+        };
+        container.normalize();
+        inserted
+    }
+
+    // 删除一个元素，返回元素之前是否存在
+    pub fn remove(&mut self, value: u64) -> bool {
+        let (high, low) = split(value);
+        let container = match self.containers.get_mut(&high) {
+            Some(container) => container,
+            None => return false,
+        };
+        let removed = match container {
+            Container::Array(items) => match items.binary_search(&low) {
+                Ok(i) => {
+                    items.remove(i);
+                    true
+                }
+                Err(_) => false,
+            },
+            Container::Bits(words) => {
+                let (word, bit) = (low as usize / 64, 1u64 << (low % 64));
+                let removed = words[word] & bit != 0;
+                words[word] &= !bit;
+                removed
+            }
+        };
+        container.normalize();
+        if container.len() == 0 {
+            self.containers.remove(&high);
+        }
+        removed
+    }
+
+    pub fn contains(&self, value: u64) -> bool {
+        let (high, low) = split(value);
+        match self.containers.get(&high) {
+            Some(Container::Array(items)) => items.binary_search(&low).is_ok(),
+            Some(Container::Bits(words)) => words[low as usize / 64] & (1 << (low % 64)) != 0,
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.containers.values().map(|c| c.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.containers.is_empty()
+    }
+
+    // 按照从小到大的顺序遍历所有元素
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        self.containers.iter().flat_map(|(high, container)| {
+            container
+                .lows()
+                .into_iter()
+                .map(move |low| high << 16 | low as u64)
+        })
+    }
+
+    // 交集，只需要合并两边都有的块
+    pub fn and(&self, other: &Bitmap) -> Bitmap {
+        let mut containers = BTreeMap::new();
+        for (high, l) in &self.containers {
+            if let Some(r) = other.containers.get(high) {
+                let container =
+                    Container::from_words(l.words().iter().zip(r.words()).map(|(a, b)| a & b));
+                if container.len() > 0 {
+                    containers.insert(*high, container);
+                }
+            }
+        }
+        Bitmap { containers }
+    }
+
+    // 并集
+    pub fn or(&self, other: &Bitmap) -> Bitmap {
+        let mut containers = self.containers.clone();
+        for (high, r) in &other.containers {
+            let container = match containers.get(high) {
+                Some(l) => {
+                    Container::from_words(l.words().iter().zip(r.words()).map(|(a, b)| a | b))
+                }
+                None => r.clone(),
+            };
+            containers.insert(*high, container);
+        }
+        Bitmap { containers }
+    }
+}
+
+impl FromIterator<u64> for Bitmap {
+    fn from_iter<I: IntoIterator<Item = u64>>(iter: I) -> Self {
+        let mut bitmap = Bitmap::new();
+        for value in iter {
+            bitmap.insert(value);
+        }
+        bitmap
+    }
+}
+
+impl Container {
+    fn len(&self) -> usize {
+        match self {
+            Container::Array(items) => items.len(),
+            Container::Bits(words) => words.iter().map(|w| w.count_ones() as usize).sum(),
+        }
+    }
+
+    // 元素数量变化之后，选择占用空间更小的表示
+    fn normalize(&mut self) {
+        let len = self.len();
+        match self {
+            Container::Array(_) if len > ARRAY_MAX => *self = Container::Bits(self.words()),
+            Container::Bits(_) if len <= ARRAY_MAX => *self = Container::Array(self.lows()),
+            _ => {}
+        }
+    }
+
+    fn lows(&self) -> Vec<u16> {
+        match self {
+            Container::Array(items) => items.clone(),
+            Container::Bits(words) => (0..BITS_WORDS * 64)
+                .filter(|i| words[i / 64] & (1 << (i % 64)) != 0)
+                .map(|i| i as u16)
+                .collect(),
+        }
+    }
+
+    fn words(&self) -> Vec<u64> {
+        match self {
+            Container::Array(items) => {
+                let mut words = vec![0; BITS_WORDS];
+                for low in items {
+                    words[*low as usize / 64] |= 1 << (low % 64);
+                }
+                words
+            }
+            Container::Bits(words) => words.clone(),
+        }
+    }
+
+    fn from_words(words: impl Iterator<Item = u64>) -> Self {
+        let mut container = Container::Bits(words.collect());
+        container.normalize();
+        container
+    }
+}
+
+// 主键在位图中的位置，翻转符号位使负数排在前面，遍历位图时得到的主键是有序的
+pub fn pk_position(pk: &Value) -> Result<u64> {
+    match pk {
+        Value::Integer(i) => Ok((*i as u64) ^ (1 << 63)),
+        v => Err(Error::TypeMismatch(format!(
+            "bitmap index requires an integer primary key, got {}",
+            v
+        ))),
+    }
+}
+
+pub fn position_pk(position: u64) -> Value {
+    Value::Integer((position ^ (1 << 63)) as i64)
+}
+
+fn split(value: u64) -> (u64, u16) {
+    (value >> 16, value as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bitmap, ARRAY_MAX};
+
+    #[test]
+    fn test_bitmap() {
+        let mut bitmap = Bitmap::new();
+        assert!(bitmap.is_empty());
+        assert!(bitmap.insert(3));
+        assert!(!bitmap.insert(3));
+        assert!(bitmap.insert(1 << 40));
+        assert!(bitmap.insert(1));
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), vec![1, 3, 1 << 40]);
+        assert!(bitmap.contains(3));
+        assert!(!bitmap.contains(2));
+        assert!(bitmap.remove(1 << 40));
+        assert!(!bitmap.remove(1 << 40));
+        assert_eq!(bitmap.len(), 2);
+
+        // 超过数组的上限之后转换成位图，删除之后再转换回来，元素不变
+        let mut dense = (0..ARRAY_MAX as u64 + 10).collect::<Bitmap>();
+        assert_eq!(dense.len(), ARRAY_MAX + 10);
+        for i in 0..20 {
+            dense.remove(i);
+        }
+        assert_eq!(
+            dense.iter().collect::<Vec<_>>(),
+            (20..ARRAY_MAX as u64 + 10).collect::<Vec<_>>()
+        );
+
+        let evens = (0..10_000).filter(|i| i % 2 == 0).collect::<Bitmap>();
+        let small = [1, 2, 3, 4, 70_000].into_iter().collect::<Bitmap>();
+        assert_eq!(evens.and(&small).iter().collect::<Vec<_>>(), vec![2, 4]);
+        assert_eq!(evens.or(&small).len(), 5_000 + 3);
+        assert!(small.and(&Bitmap::new()).is_empty());
+        assert_eq!(Bitmap::new().or(&small), small);
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Mutex,
+};
+
+use crate::{
+    error::Result,
+    sql::{executor::ResultSet, functions, plan::Node},
+};
+
+use super::sys;
+
+// 查询结果缓存，在多个 session 之间共享，用于加速反复执行相同查询的场景，例如监控面板
+// 缓存的 key 是规范化之后的查询语句，每条缓存记录了查询读取的表
+// 事务提交时如果写入了某张表，读取这张表的缓存全部失效
+// 只缓存自动提交的只读查询，显式事务中的查询需要看到事务自己的快照，不走缓存
+pub struct ResultCache {
+    state: Mutex<CacheState>,
+}
+
+struct CacheState {
+    // 最多缓存的查询数，超过之后淘汰最早加入的缓存
+    capacity: usize,
+    entries: HashMap<String, CacheEntry>,
+    order: VecDeque<String>,
+    // 写入版本号，每次有写入的事务提交时加一
+    version: u64,
+    // 每张表最后一次被写入时的版本号
+    written: HashMap<String, u64>,
+    hits: u64,
+    misses: u64,
+}
+
+struct CacheEntry {
+    // 开始执行查询之前的写入版本号
+    version: u64,
+    tables: HashSet<String>,
+    result: ResultSet,
+}
+
+// 缓存的命中情况
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl ResultCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(CacheState {
+                capacity,
+                entries: HashMap::new(),
+                order: VecDeque::new(),
+                version: 0,
+                written: HashMap::new(),
+                hits: 0,
+                misses: 0,
+            }),
+        }
+    }
+
+    // 当前的写入版本号，需要在开启查询事务之前获取
+    // 查询执行期间有其他事务提交了写入时，版本号会落后，这次查询的结果不会被后续的查询使用
+    pub fn version(&self) -> Result<u64> {
+        Ok(self.state.lock()?.version)
+    }
+
+    // 查找缓存，查询读取的表在缓存之后被写入过则缓存失效
+    pub fn get(&self, key: &str) -> Result<Option<ResultSet>> {
+        let mut state = self.state.lock()?;
+        let valid = match state.entries.get(key) {
+            Some(entry) => entry
+                .tables
+                .iter()
+                .all(|t| state.written.get(t).is_none_or(|v| *v <= entry.version)),
+            None => {
+                state.misses += 1;
+                return Ok(None);
+            }
+        };
+        if !valid {
+            state.entries.remove(key);
+            state.order.retain(|k| k != key);
+            state.misses += 1;
+            return Ok(None);
+        }
+        state.hits += 1;
+        Ok(state.entries.get(key).map(|entry| entry.result.clone()))
+    }
+
+    // 保存查询结果，version 是开启查询事务之前获取的写入版本号
+    pub fn insert(
+        &self,
+        key: String,
+        version: u64,
+        tables: HashSet<String>,
+        result: ResultSet,
+    ) -> Result<()> {
+        let mut state = self.state.lock()?;
+        if state.capacity == 0 {
+            return Ok(());
+        }
+        let entry = CacheEntry {
+            version,
+            tables,
+            result,
+        };
+        if state.entries.insert(key.clone(), entry).is_none() {
+            state.order.push_back(key);
+        }
+        while state.order.len() > state.capacity {
+            if let Some(key) = state.order.pop_front() {
+                state.entries.remove(&key);
+            }
+        }
+        Ok(())
+    }
+
+    // 事务提交之后调用，读取了这些表的缓存全部失效
+    pub fn invalidate(&self, tables: &HashSet<String>) -> Result<()> {
+        if tables.is_empty() {
+            return Ok(());
+        }
+        let mut state = self.state.lock()?;
+        state.version += 1;
+        let version = state.version;
+        for table in tables {
+            state.written.insert(table.clone(), version);
+        }
+        let state = &mut *state;
+        state
+            .entries
+            .retain(|_, entry| entry.tables.is_disjoint(tables));
+        let entries = &state.entries;
+        state.order.retain(|k| entries.contains_key(k));
+        Ok(())
+    }
+
+    pub fn stats(&self) -> Result<CacheStats> {
+        let state = self.state.lock()?;
+        Ok(CacheStats {
+            entries: state.entries.len(),
+            hits: state.hits,
+            misses: state.misses,
+        })
+    }
+}
+
+// 查询读取的所有表，结果可以缓存时返回 Some
+// 系统表的数据是实时计算的，now() 等函数每次执行的结果不同，这些查询不能缓存
+pub fn cacheable_tables(node: &Node) -> Option<HashSet<String>> {
+    let mut tables = HashSet::new();
+    let mut nodes = vec![node];
+    while let Some(node) = nodes.pop() {
+        match node {
+            Node::Scan { table_name, .. }
+            | Node::IndexScan { table_name, .. }
+            | Node::RangeIndexScan { table_name, .. }
+            | Node::PrimaryKeyScan { table_name, .. }
+            | Node::IndexPrefixScan { table_name, .. }
+            | Node::PrimaryKeyPrefixScan { table_name, .. }
+            | Node::FullTextScan { table_name, .. }
+            | Node::BitmapScan { table_name, .. } => {
+                if sys::is_sys_table(table_name) {
+                    return None;
+                }
+                tables.insert(table_name.clone());
+            }
+            // 虚拟表的数据随着建表、删表变化
+            Node::VirtualScan { .. } => return None,
+            // 没有指定 seed 的采样每次返回的行不同
+            Node::Sample { seed: None, .. } => return None,
+            _ => {}
+        }
+        // 子查询读取的表不在执行计划中，不缓存
+        if node
+            .expressions()
+            .into_iter()
+            .any(|e| functions::has_volatile(e) || e.has_subquery())
+        {
+            return None;
+        }
+        nodes.extend(node.children());
+    }
+    Some(tables)
+}
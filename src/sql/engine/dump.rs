@@ -0,0 +1,122 @@
+use std::io::Write;
+
+use crate::{
+    error::Result,
+    sql::{
+        parser::{
+            ast::{self, Expression},
+            Parser,
+        },
+        schema::Table,
+        types::Value,
+    },
+};
+
+use super::Transaction;
+
+// 把所有表的结构和数据导出为 SQL 语句，每条语句占一行，按顺序执行可以重建数据库
+// 所有语句包含在一个事务中，先建表、插入数据，最后创建 CREATE INDEX 指定了名字的索引
+pub fn dump<T: Transaction, W: Write>(txn: &T, w: &mut W) -> Result<()> {
+    writeln!(w, "BEGIN;")?;
+    for table_name in txn.get_table_names()? {
+        let table = txn.must_get_table(table_name)?;
+        dump_table(txn, &table, w)?;
+    }
+    writeln!(w, "COMMIT;")?;
+    Ok(())
+}
+
+fn dump_table<T: Transaction, W: Write>(txn: &T, table: &Table, w: &mut W) -> Result<()> {
+    writeln!(w, "{};", create_table(table)?)?;
+
+    // 隐藏的 _rowid 列不导出，插入时重新分配
+    let positions = (0..table.columns.len())
+        .filter(|i| !table.columns[*i].is_hidden())
+        .collect::<Vec<_>>();
+    let columns = positions
+        .iter()
+        .map(|i| table.columns[*i].name.clone())
+        .collect::<Vec<_>>();
+    for row in txn.scan_table(table.name.clone(), None)? {
+        let mut row = row?;
+        let values = positions
+            .iter()
+            .map(|i| Expression::from(std::mem::replace(&mut row[*i], Value::Null)))
+            .collect();
+        let stmt = ast::Statement::Insert {
+            table_name: table.name.clone(),
+            columns: Some(columns.clone()),
+            values: vec![values],
+        };
+        writeln!(w, "{};", stmt)?;
+    }
+
+    // 数据插入完之后再创建索引
+    for col in table.columns.iter().filter(|c| c.index) {
+        if let Some(name) = &col.index_name {
+            let stmt = ast::Statement::CreateIndex {
+                name: Some(name.clone()),
+                table_name: table.name.clone(),
+                column: col.name.clone(),
+                index_type: col.index_type,
+            };
+            writeln!(w, "{};", stmt)?;
+        }
+    }
+    Ok(())
+}
+
+// 根据表结构生成建表语句，复合主键写在表级别的 PRIMARY KEY 中
+fn create_table(table: &Table) -> Result<ast::Statement> {
+    let composite = table.is_composite_key();
+    let columns = table
+        .columns
+        .iter()
+        .filter(|c| !c.is_hidden())
+        .map(|c| {
+            Ok(ast::Column {
+                name: c.name.clone(),
+                datatype: c.datatype.clone(),
+                // 没有声明时主键不能为空，其他列可以为空
+                nullable: (!c.nullable && !c.primary_key).then_some(false),
+                // 可以为空的列默认值为 NULL，不需要写出来
+                default: c
+                    .default
+                    .clone()
+                    .filter(|v| *v != Value::Null)
+                    .map(Expression::from),
+                primary_key: c.primary_key && !composite,
+                index: c.index && c.index_name.is_none(),
+                index_type: c.index_type,
+                unique: c.unique,
+                auto_increment: c.auto_increment,
+                check: c
+                    .check
+                    .as_ref()
+                    .map(|check| Parser::new(check).parse_expr())
+                    .transpose()?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let primary_key = match composite {
+        true => table
+            .columns
+            .iter()
+            .filter(|c| c.primary_key)
+            .map(|c| c.name.clone())
+            .collect(),
+        false => Vec::new(),
+    };
+    let checks = table
+        .checks
+        .iter()
+        .map(|check| Parser::new(check).parse_expr())
+        .collect::<Result<Vec<_>>>()?;
+    Ok(ast::Statement::CreateTable {
+        name: table.name.clone(),
+        columns,
+        primary_key,
+        checks,
+        layout: table.layout,
+    })
+}
@@ -1,28 +1,74 @@
-use std::collections::HashSet;
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Bound,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+#[cfg(feature = "native")]
+use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
 
 use crate::{
     error::{Error, Result},
     sql::{
-        parser::ast::{evaluate_expr, Expression},
-        schema::Table,
-        types::{Row, Value},
+        executor::memory::MemoryTracker,
+        functions::fulltext,
+        parser::ast::{evaluate_expr, AlterTableAction, Expression},
+        schema::{self, Column, IndexType, Layout, Table},
+        types::{
+            coerce::cast_value,
+            encoding::{decode_columns, decode_row, encode_row},
+            Row, Value,
+        },
+    },
+    storage::{
+        self,
+        engine::Engine as StorageEngine,
+        keycode::{deserialize_key, deserialize_legacy_key, serialize_key},
+        mvcc::{IsolationLevel, ScanIterator},
     },
-    storage::{self, engine::Engine as StorageEngine, keycode::serialize_key},
 };
 
-use super::{Engine, Transaction};
+use super::{
+    bitmap::{self, Bitmap},
+    cache::ResultCache,
+    lock::LockManager,
+    profile::ExecStats,
+    sys, Engine, RowIter, Transaction,
+};
+
+// 数据格式的版本号，保存在 Key::Format 中
+// 行数据、key 的编码格式变化时加一，并在 upgrade_format 中增加从上一个版本升级的步骤
+// 0: 加入版本号之前的格式，表结构中没有之后加入的字段，行数据使用 bincode 编码，
+//    key 中的整数和浮点数直接按大端编码
+// 1: 表结构加入存储方式等字段，行数据使用 encoding 中带版本号的格式，key 使用保序编码
+pub const FORMAT_VERSION: u32 = 1;
 
 // KV Engine 定义
 pub struct KVEngine<E: StorageEngine> {
     pub kv: storage::mvcc::Mvcc<E>,
+    // 引擎级别的 tracing subscriber
+    dispatch: Option<tracing::Dispatch>,
+    // 查询结果缓存
+    result_cache: Option<Arc<ResultCache>>,
+    // 是否已经检查过数据格式的版本
+    format_checked: Arc<Mutex<bool>>,
+    // 行锁，在所有 session 之间共享
+    locks: Arc<LockManager>,
 }
 
 impl<E: StorageEngine> Clone for KVEngine<E> {
     fn clone(&self) -> Self {
         Self {
             kv: self.kv.clone(),
+            dispatch: self.dispatch.clone(),
+            result_cache: self.result_cache.clone(),
+            format_checked: self.format_checked.clone(),
+            locks: self.locks.clone(),
         }
     }
 }
@@ -31,78 +77,665 @@ impl<E: StorageEngine> KVEngine<E> {
     pub fn new(engine: E) -> Self {
         Self {
             kv: storage::mvcc::Mvcc::new(engine),
+            dispatch: None,
+            result_cache: None,
+            format_checked: Arc::new(Mutex::new(false)),
+            locks: Arc::new(LockManager::new()),
+        }
+    }
+
+    // 第一次开启事务之前检查数据格式的版本，旧版本的数据在一个事务中逐个版本升级到当前版本
+    // 比当前版本新的数据无法正确读取，直接报错，避免读错或者写坏数据
+    fn check_format(&self) -> Result<()> {
+        let mut checked = self.format_checked.lock()?;
+        if *checked {
+            return Ok(());
+        }
+        let txn = KVTransaction::new(self.kv.begin()?);
+        match txn.upgrade_format() {
+            Ok(()) => txn.commit()?,
+            Err(err) => {
+                txn.rollback()?;
+                return Err(err);
+            }
         }
+        *checked = true;
+        Ok(())
+    }
+
+    // 设置 tracing subscriber，通过这个引擎执行的语句都会上报给它
+    pub fn with_subscriber<S>(mut self, subscriber: S) -> Self
+    where
+        S: tracing::Subscriber + Send + Sync + 'static,
+    {
+        self.dispatch = Some(tracing::Dispatch::new(subscriber));
+        self
+    }
+
+    // 开启查询结果缓存，最多缓存 capacity 条查询的结果
+    pub fn with_result_cache(mut self, capacity: usize) -> Self {
+        self.result_cache = Some(Arc::new(ResultCache::new(capacity)));
+        self
+    }
+}
+
+#[cfg(feature = "native")]
+impl KVEngine<storage::disk::DiskEngine> {
+    // 使用磁盘存储引擎打开数据库，options 中可以配置压缩以及同步到磁盘的时机，
+    // 例如测试和批量导入数据时使用 Durability::Periodic 减少 fsync 的次数
+    pub fn open(path: PathBuf, options: storage::disk::DiskOptions) -> Result<Self> {
+        Ok(Self::new(storage::disk::DiskEngine::with_options(path, options)?))
     }
 }
 
 impl<E: StorageEngine> Engine for KVEngine<E> {
     type Transaction = KVTransaction<E>;
 
-    fn begin(&self) -> Result<Self::Transaction> {
-        Ok(Self::Transaction::new(self.kv.begin()?))
+    fn begin_with(&self, isolation: IsolationLevel) -> Result<Self::Transaction> {
+        self.check_format()?;
+        let mut txn = Self::Transaction::new(self.kv.begin_with(isolation)?);
+        txn.result_cache = self.result_cache.clone();
+        txn.locks = Some(self.locks.clone());
+        Ok(txn)
+    }
+
+    fn begin_read_only(&self, as_of: Option<u64>) -> Result<Self::Transaction> {
+        self.check_format()?;
+        Ok(Self::Transaction::new(self.kv.begin_read_only(as_of)?))
+    }
+
+    fn vacuum(&self) -> Result<usize> {
+        self.kv.vacuum()
+    }
+
+    #[cfg(feature = "native")]
+    fn backup(&self, path: PathBuf) -> Result<usize> {
+        self.kv.backup(path)
+    }
+
+    // 恢复在一个事务中完成，失败时数据库保持原样
+    // 备份文件可能是旧版本的数据格式，恢复之后重新检查
+    #[cfg(feature = "native")]
+    fn restore(&self, path: PathBuf) -> Result<usize> {
+        let mut txn = self.begin()?;
+        let count = match txn.restore(path) {
+            Ok(count) => count,
+            Err(err) => {
+                txn.rollback()?;
+                return Err(err);
+            }
+        };
+        txn.commit()?;
+        *self.format_checked.lock()? = false;
+        Ok(count)
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.kv.flush()
+    }
+
+    fn close(&self) -> Result<()> {
+        self.kv.close()
+    }
+
+    fn dispatch(&self) -> Option<tracing::Dispatch> {
+        self.dispatch.clone()
+    }
+
+    fn result_cache(&self) -> Option<Arc<ResultCache>> {
+        self.result_cache.clone()
     }
 }
 
 // KV Transaction 定义，实际上对存储引擎中 MvccTransaction 的封装
 pub struct KVTransaction<E: StorageEngine> {
     txn: storage::mvcc::MvccTransaction<E>,
+    // 是否禁止写入时的隐式类型转换
+    strict_types: bool,
+    // 查询结果缓存，提交时让写入过的表的缓存失效
+    result_cache: Option<Arc<ResultCache>>,
+    // 事务中写入过的表
+    written_tables: HashSet<String>,
+    // 当前查询的内存统计
+    memory: MemoryTracker,
+    // 事务中从存储读取的行数
+    rows_scanned: Arc<AtomicU64>,
+    // 写入之前对行加锁，为空时不加锁，例如升级数据格式的内部事务
+    locks: Option<Arc<LockManager>>,
+    lock_timeout: Duration,
 }
 
 impl<E: StorageEngine> KVTransaction<E> {
     pub fn new(txn: storage::mvcc::MvccTransaction<E>) -> Self {
-        Self { txn }
+        Self {
+            txn,
+            strict_types: false,
+            result_cache: None,
+            written_tables: HashSet::new(),
+            memory: MemoryTracker::default(),
+            rows_scanned: Arc::new(AtomicU64::new(0)),
+            locks: None,
+            lock_timeout: Duration::ZERO,
+        }
+    }
+
+    fn add_rows_scanned(&self, rows: usize) {
+        self.rows_scanned.fetch_add(rows as u64, Ordering::Relaxed);
+    }
+
+    // 写入一行之前锁住这一行，直到事务结束
+    fn lock_row(&self, table_name: &str, pk: &Value) -> Result<()> {
+        match &self.locks {
+            Some(locks) => locks.lock(self.txn.version(), table_name, pk, self.lock_timeout),
+            None => Ok(()),
+        }
+    }
+
+    fn release_locks(&self) -> Result<()> {
+        match &self.locks {
+            Some(locks) => locks.release(self.txn.version()),
+            None => Ok(()),
+        }
+    }
+}
+
+// 没有提交或者回滚就被丢弃的事务同样要释放锁
+impl<E: StorageEngine> Drop for KVTransaction<E> {
+    fn drop(&mut self) {
+        if let Err(err) = self.release_locks() {
+            tracing::warn!(error = %err, "failed to release row locks");
+        }
+    }
+}
+
+impl<E: StorageEngine> KVTransaction<E> {
+    // 获取 MVCC 以及底层存储引擎的状态信息
+    pub(super) fn status(&self) -> Result<storage::mvcc::Status> {
+        self.txn.status()
+    }
+
+    // 数据格式的版本号，没有版本号的空数据库使用当前版本，有数据的是加入版本号之前创建的数据库
+    // 旧版本的表结构不能按照当前的格式解码，只检查是否存在表
+    fn format_version(&self) -> Result<u32> {
+        Ok(match self.txn.get(Key::Format.encode()?)? {
+            Some(value) => bincode::deserialize(&value)?,
+            None if self.txn.scan_prefix(KeyPrefix::Table.encode()?)?.is_empty() => FORMAT_VERSION,
+            None => 0,
+        })
+    }
+
+    // 将数据升级到当前版本，并保存版本号
+    fn upgrade_format(&self) -> Result<()> {
+        let mut version = self.format_version()?;
+        if version > FORMAT_VERSION {
+            return Err(Error::InvalidState(format!(
+                "database format version {} is newer than supported version {}",
+                version, FORMAT_VERSION
+            )));
+        }
+        while version < FORMAT_VERSION {
+            match version {
+                0 => self.upgrade_legacy_tables()?,
+                _ => unreachable!(),
+            }
+            version += 1;
+            tracing::info!(version, "upgraded database format");
+        }
+        self.txn.set(Key::Format.encode()?, bincode::serialize(&FORMAT_VERSION)?)
+    }
+
+    // 从版本 0 升级：表结构补上之后加入的字段，行数据从 bincode 改成 encode_row 的格式，
+    // 行和索引的 key 按照保序编码重新写入。版本 0 中只有按行存储的表和有序索引
+    fn upgrade_legacy_tables(&self) -> Result<()> {
+        for result in self.txn.scan_prefix(KeyPrefix::Table.encode()?)? {
+            let table = Table::decode_legacy(&result.value)?;
+            self.txn.set(result.key, bincode::serialize(&table)?)?;
+
+            // 旧的 key 可能和另一个值新的 key 相同，先删除全部旧的 key 再写入
+            let mut entries = Vec::new();
+            for result in self.txn.scan_prefix(KeyPrefix::Row(table.name.clone()).encode()?)? {
+                let key: Key = deserialize_legacy_key(&result.key)?;
+                let row: Row = bincode::deserialize(&result.value)?;
+                entries.push((result.key, key.encode()?, encode_row(&row)?));
+            }
+            for col in table.columns.iter().filter(|c| c.index) {
+                let prefix = KeyPrefix::Index(table.name.clone(), col.name.clone()).encode()?;
+                for result in self.txn.scan_prefix(prefix)? {
+                    let key: Key = deserialize_legacy_key(&result.key)?;
+                    entries.push((result.key, key.encode()?, result.value));
+                }
+            }
+            for (old_key, _, _) in &entries {
+                self.txn.delete(old_key.clone())?;
+            }
+            for (_, key, value) in entries {
+                self.txn.set(key, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    // 扫描表中的数据，positions 不为空时只解码这些列，filter 中引用的列也必须在其中
+    // 返回的迭代器在遍历时才解码和过滤每一行
+    fn scan_rows(
+        &self,
+        table_name: String,
+        filter: Option<Expression>,
+        positions: Option<&[usize]>,
+        limit: usize,
+    ) -> Result<RowIter> {
+        let table = self.must_get_table(table_name.clone())?;
+        let mut cols = table.columns.iter().map(|c| c.name.clone()).collect::<Vec<_>>();
+        if let Some(positions) = positions {
+            cols = positions.iter().map(|i| cols[*i].clone()).collect();
+        }
+        // 系统表的数据是实时计算出来的
+        let rows: RowIter = if sys::is_sys_table(&table_name) {
+            let mut rows = sys::scan_table(self, &table_name)?;
+            rows.truncate(limit);
+            self.add_rows_scanned(rows.len());
+            let positions = positions.map(|p| p.to_vec());
+            Box::new(rows.into_iter().map(move |row| {
+                Ok(match &positions {
+                    Some(positions) => positions.iter().map(|i| row[*i].clone()).collect(),
+                    None => row,
+                })
+            }))
+        } else if table.layout == Layout::Columnar {
+            let all = (0..table.columns.len()).collect::<Vec<_>>();
+            let mut columns = Vec::new();
+            for i in positions.unwrap_or(&all) {
+                let col = &table.columns[*i];
+                let prefix = KeyPrefix::Column(table.name.clone(), col.name.clone()).encode()?;
+                columns.push(self.txn.scan_prefix_iter(prefix, limit)?);
+            }
+            let rows_scanned = self.rows_scanned.clone();
+            Box::new(std::iter::from_fn(move || {
+                let row = next_columnar_row(&table.name, &mut columns).transpose()?;
+                rows_scanned.fetch_add(1, Ordering::Relaxed);
+                Some(row)
+            }))
+        } else {
+            let prefix = KeyPrefix::Row(table_name).encode()?;
+            let rows_scanned = self.rows_scanned.clone();
+            let positions = positions.map(|p| p.to_vec());
+            Box::new(self.txn.scan_prefix_iter(prefix, limit)?.map(move |result| {
+                let result = result?;
+                rows_scanned.fetch_add(1, Ordering::Relaxed);
+                match &positions {
+                    Some(positions) => decode_columns(&result.value, positions),
+                    None => decode_row(&result.value),
+                }
+            }))
+        };
+
+        // 过滤数据
+        let filter = match filter {
+            Some(filter) => filter,
+            None => return Ok(rows),
+        };
+        Ok(Box::new(rows.filter_map(move |row| {
+            let row = match row {
+                Ok(row) => row,
+                Err(err) => return Some(Err(err)),
+            };
+            match evaluate_expr(&filter, &cols, &row, &cols, &row) {
+                Ok(Value::Null) | Ok(Value::Boolean(false)) => None,
+                Ok(Value::Boolean(true)) => Some(Ok(row)),
+                Ok(v) => Some(Err(Error::TypeMismatch(format!(
+                    "where condition must be boolean, got {}",
+                    v
+                )))),
+                Err(err) => Some(Err(err)),
+            }
+        })))
+    }
+
+    // 写入一行数据，已存在则覆盖
+    fn write_row(&self, table: &Table, pk: &Value, row: &Row) -> Result<()> {
+        match table.layout {
+            Layout::Row => {
+                let key = Key::Row(table.name.clone(), pk.clone()).encode()?;
+                self.txn.set(key, encode_row(row)?)
+            }
+            Layout::Columnar => {
+                for (col, value) in table.columns.iter().zip(row) {
+                    let key = Key::Column(table.name.clone(), col.name.clone(), pk.clone());
+                    self.txn.set(key.encode()?, bincode::serialize(value)?)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // 删除一行数据
+    fn remove_row(&self, table: &Table, pk: &Value) -> Result<()> {
+        match table.layout {
+            Layout::Row => self.txn.delete(Key::Row(table.name.clone(), pk.clone()).encode()?),
+            Layout::Columnar => {
+                for col in table.columns.iter() {
+                    let key = Key::Column(table.name.clone(), col.name.clone(), pk.clone());
+                    self.txn.delete(key.encode()?)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // 根据主键读取一行数据
+    fn read_row(&self, table: &Table, pk: &Value) -> Result<Option<Row>> {
+        match table.layout {
+            Layout::Row => self
+                .txn
+                .get(Key::Row(table.name.clone(), pk.clone()).encode()?)?
+                .map(|v| decode_row(&v))
+                .transpose(),
+            Layout::Columnar => {
+                let mut row = Vec::with_capacity(table.columns.len());
+                for col in table.columns.iter() {
+                    let key = Key::Column(table.name.clone(), col.name.clone(), pk.clone());
+                    match self.txn.get(key.encode()?)? {
+                        Some(v) => row.push(bincode::deserialize(&v)?),
+                        None => return Ok(None),
+                    }
+                }
+                Ok(Some(row))
+            }
+        }
+    }
+
+    // 按照前缀删除表的所有数据和索引，不需要逐行读取和维护索引，返回删除的行数
+    fn delete_table_data(&self, table: &Table) -> Result<usize> {
+        let count = match table.layout {
+            Layout::Row => self.txn.delete_prefix(KeyPrefix::Row(table.name.clone()).encode()?)?,
+            Layout::Columnar => {
+                let mut count = 0;
+                for col in table.columns.iter() {
+                    let prefix = KeyPrefix::Column(table.name.clone(), col.name.clone()).encode()?;
+                    let n = self.txn.delete_prefix(prefix)?;
+                    if col.primary_key {
+                        count = n;
+                    }
+                }
+                count
+            }
+        };
+        for col in table.columns.iter().filter(|c| c.index) {
+            self.txn.delete_prefix(index_prefix(&table.name, col)?)?;
+        }
+        for col in table.columns.iter().filter(|c| c.unique) {
+            let prefix = KeyPrefix::Unique(table.name.clone(), col.name.clone()).encode()?;
+            self.txn.delete_prefix(prefix)?;
+        }
+        // 清空数据之后 _rowid、自增列重新从 1 开始分配
+        if table.columns.iter().any(|c| c.auto_increment) {
+            self.txn.delete(Key::Sequence(table.name.clone()).encode()?)?;
+        }
+        Ok(count)
+    }
+
+    // 在索引中加入或者删除一行的主键，全文索引按照分词之后的每个词维护倒排表
+    fn update_index(
+        &self,
+        table: &Table,
+        col: &Column,
+        value: &Value,
+        pk: &Value,
+        insert: bool,
+    ) -> Result<()> {
+        let update = |index: &mut HashSet<Value>| match insert {
+            true => index.insert(pk.clone()),
+            false => index.remove(pk),
+        };
+        // 位图索引直接修改位图，不需要转换成主键集合
+        if col.index_type == IndexType::Bitmap {
+            let mut bitmap = self.load_bitmap_index(&table.name, &col.name, value)?;
+            match insert {
+                true => bitmap.insert(bitmap::pk_position(pk)?),
+                false => bitmap.remove(bitmap::pk_position(pk)?),
+            };
+            return self.save_bitmap_index(&table.name, &col.name, value, &bitmap);
+        }
+        if col.index_type != IndexType::FullText {
+            let mut index = self.load_index(&table.name, &col.name, value)?;
+            update(&mut index);
+            return self.save_index(&table.name, &col.name, value, index);
+        }
+        let text = match value {
+            Value::String(text) => text,
+            _ => return Ok(()),
+        };
+        for word in fulltext::tokenize(text) {
+            let mut posting = self.load_posting(&table.name, &col.name, &word)?;
+            update(&mut posting);
+            let key = Key::Posting(table.name.clone(), col.name.clone(), word).encode()?;
+            match posting.is_empty() {
+                true => self.txn.delete(key)?,
+                false => self.txn.set(key, bincode::serialize(&posting)?)?,
+            }
+        }
+        Ok(())
+    }
+
+    // 校验唯一列的值没有被其他行使用，然后记录每个列值对应的主键，NULL 不参与唯一性校验
+    fn insert_unique(&self, table: &Table, row: &Row, pk: &Value) -> Result<()> {
+        let mut keys = Vec::new();
+        for (i, col) in table.columns.iter().enumerate().filter(|(_, c)| c.unique) {
+            if matches!(row[i], Value::Null) {
+                continue;
+            }
+            let key = Key::Unique(table.name.clone(), col.name.clone(), row[i].clone()).encode()?;
+            if let Some(v) = self.txn.get(key.clone())? {
+                if bincode::deserialize::<Value>(&v)? != *pk {
+                    return Err(Error::Constraint(format!(
+                        "duplicate value {} for unique column {} in table {}",
+                        row[i], col.name, table.name
+                    )));
+                }
+            }
+            keys.push(key);
+        }
+        // 全部校验通过之后再写入，避免只写入一部分
+        for key in keys {
+            self.txn.set(key, bincode::serialize(pk)?)?;
+        }
+        Ok(())
+    }
+
+    // 删除一行在唯一列上的记录，only 不为空时只删除值发生变化的列
+    fn remove_unique(&self, table: &Table, row: &Row, only: Option<&Row>) -> Result<()> {
+        for (i, col) in table.columns.iter().enumerate().filter(|(_, c)| c.unique) {
+            if matches!(row[i], Value::Null) || only.is_some_and(|new| new[i] == row[i]) {
+                continue;
+            }
+            let key = Key::Unique(table.name.clone(), col.name.clone(), row[i].clone());
+            self.txn.delete(key.encode()?)?;
+        }
+        Ok(())
+    }
+
+    // 全文索引中一个词的倒排表
+    fn load_posting(&self, table_name: &str, col_name: &str, word: &str) -> Result<HashSet<Value>> {
+        let key = Key::Posting(table_name.into(), col_name.into(), word.into()).encode()?;
+        Ok(self.txn.get(key)?.map(|v| bincode::deserialize(&v)).transpose()?.unwrap_or_default())
+    }
+
+    // 位图索引中一个值对应的位图
+    fn load_bitmap_index(&self, table_name: &str, col_name: &str, col_value: &Value) -> Result<Bitmap> {
+        let key = Key::Index(table_name.into(), col_name.into(), col_value.clone()).encode()?;
+        Ok(self.txn.get(key)?.map(|v| bincode::deserialize(&v)).transpose()?.unwrap_or_default())
+    }
+
+    fn save_bitmap_index(
+        &self,
+        table_name: &str,
+        col_name: &str,
+        col_value: &Value,
+        bitmap: &Bitmap,
+    ) -> Result<()> {
+        let key = Key::Index(table_name.into(), col_name.into(), col_value.clone()).encode()?;
+        match bitmap.is_empty() {
+            true => self.txn.delete(key),
+            false => self.txn.set(key, bincode::serialize(bitmap)?),
+        }
+    }
+
+    // 索引列的索引类型
+    fn index_type(&self, table_name: &str, col_name: &str) -> Result<IndexType> {
+        let table = self.must_get_table(table_name.into())?;
+        Ok(table.columns[table.get_col_index(col_name)?].index_type)
+    }
+
+    // 哈希索引的一个桶，保存哈希值相同的所有列值和对应的主键集合
+    fn load_hash_bucket(
+        &self,
+        table_name: &str,
+        col_name: &str,
+        col_value: &Value,
+    ) -> Result<Vec<(Value, HashSet<Value>)>> {
+        let key = hash_index_key(table_name, col_name, col_value)?;
+        Ok(self.txn.get(key)?.map(|v| bincode::deserialize(&v)).transpose()?.unwrap_or_default())
+    }
+
+    // 自增列（包括 _rowid）没有给出值时分配表的下一个序号，给出的值不小于序号时推进序号
+    fn assign_auto_increment(&self, table: &Table, mut row: Row) -> Result<Row> {
+        let i = match table.columns.iter().position(|c| c.auto_increment) {
+            Some(i) if i < row.len() => i,
+            _ => return Ok(row),
+        };
+        let key = Key::Sequence(table.name.clone()).encode()?;
+        let next = match self.txn.get(key.clone())? {
+            Some(value) => bincode::deserialize(&value)?,
+            None => 1,
+        };
+        let id = match row[i] {
+            Value::Null => {
+                row[i] = Value::Integer(next);
+                next
+            }
+            Value::Integer(id) => id,
+            _ => return Ok(row),
+        };
+        if id >= next {
+            self.txn.set(key, bincode::serialize(&(id + 1))?)?;
+        }
+        Ok(row)
+    }
+
+    // 保存表的元数据，已存在则覆盖
+    fn save_table(&mut self, table: &Table) -> Result<()> {
+        self.written_tables.insert(table.name.clone());
+        let key = Key::Table(table.name.clone()).encode()?;
+        let value = bincode::serialize(table)?;
+        self.txn.set(key, value)
+    }
+
+    // 使用备份文件中的数据替换当前的所有数据，恢复前后存在的表的查询缓存都要失效
+    #[cfg(feature = "native")]
+    fn restore(&mut self, path: PathBuf) -> Result<usize> {
+        let results = storage::mvcc::load_backup(path)?;
+        self.written_tables.extend(self.get_table_names()?);
+        self.txn.delete_prefix(Vec::new())?;
+        for result in results.iter() {
+            self.txn.set(result.key.clone(), result.value.clone())?;
+        }
+        self.written_tables.extend(self.get_table_names()?);
+        Ok(results.len())
     }
 }
 
 impl<E: StorageEngine> Transaction for KVTransaction<E> {
 
     fn commit(&self) -> Result<()> {
-        self.txn.commit()
+        // 提交失败时事务已经回滚，同样释放锁
+        let result = self.txn.commit();
+        self.release_locks()?;
+        result?;
+        if let Some(cache) = &self.result_cache {
+            cache.invalidate(&self.written_tables)?;
+        }
+        Ok(())
     }
 
     fn rollback(&self) -> Result<()> {
-        self.txn.rollback()
+        self.txn.rollback()?;
+        self.release_locks()
+    }
+
+    fn savepoint(&mut self, name: String) -> Result<()> {
+        self.txn.savepoint(name)
+    }
+
+    fn rollback_to_savepoint(&mut self, name: String) -> Result<()> {
+        self.txn.rollback_to_savepoint(&name)
+    }
+
+    fn release_savepoint(&mut self, name: String) -> Result<()> {
+        self.txn.release_savepoint(&name)
     }
 
     fn version(&self) -> u64 {
         self.txn.version()
     }
 
+    fn begin_statement(&self) -> Result<()> {
+        self.txn.begin_statement()
+    }
+
+    fn set_strict_types(&mut self, strict: bool) {
+        self.strict_types = strict;
+    }
+
+    fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.memory.set_limit(limit);
+    }
+
+    fn set_lock_timeout(&mut self, timeout: Duration) {
+        self.lock_timeout = timeout;
+    }
+
+    fn memory(&self) -> &MemoryTracker {
+        &self.memory
+    }
+
+    fn exec_stats(&self) -> ExecStats {
+        let io = self.txn.io_stats();
+        ExecStats {
+            rows_scanned: self.rows_scanned.load(Ordering::Relaxed),
+            keys_read: io.keys_read,
+            keys_written: io.keys_written,
+        }
+    }
+
     fn create_row(&mut self, table_name: String, row: Row) -> Result<()> {
+        sys::check_writable(&table_name)?;
+        self.written_tables.insert(table_name.clone());
         let table = self.must_get_table(table_name.clone())?;
-        // 校验行的有效性
-        for (i, col) in table.columns.iter().enumerate() {
-            match row[i].datatype() {
-                None if col.nullable => {}
-                None => {
-                    return Err(Error::Internal(format!(
-                        "column {} cannot be null",
-                        col.name
-                    )))
-                }
-                Some(dt) if dt != col.datatype => {
-                    return Err(Error::Internal(format!(
-                        "column {} type mismatch",
-                        col.name
-                    )))
-                }
-                _ => {}
-            }
-        }
+        let row = self.assign_auto_increment(&table, row)?;
+        // 校验行的有效性，并按照列类型做隐式转换
+        let row = table.coerce_row(row, self.strict_types)?;
+        table.check_row(&row)?;
 
         // 找到 此行的主键, 作为该行数据的唯一标识;
         let pk = table.get_primary_key(&row)?;
+        self.lock_row(&table_name, &pk)?;
         // 查看主键对应的数据是否已经存在了;
-        let id = Key::Row(table_name.clone(), pk.clone()).encode()?;
-        // key: tableName_primaryKey 是否已经存在;
-        if self.txn.get(id.clone())?.is_some() {
-            return Err(Error::Internal(format!("Duplicate data for primary key {} in table {}", pk, table_name)));
+        // 按行存储时 key 是 tableName_primaryKey，按列存储时看主键列的 key 是否存在
+        let id = match table.layout {
+            Layout::Row => Key::Row(table_name.clone(), pk.clone()),
+            Layout::Columnar => {
+                let pk_col = table.columns.iter().find(|c| c.primary_key).unwrap();
+                Key::Column(table_name.clone(), pk_col.name.clone(), pk.clone())
+            }
+        };
+        if self.txn.get(id.encode()?)?.is_some() {
+            return Err(Error::Duplicate(format!("Duplicate data for primary key {} in table {}", pk, table_name)));
         }
+        // 唯一约束
+        self.insert_unique(&table, &row, &pk)?;
 
         // 存放数据
-        let value = bincode::serialize(&row)?;
-        // mvcc 存放写数据;
-        self.txn.set(id, value)?;
+        self.write_row(&table, &pk, &row)?;
 
         // 维护索引
         let index_cols = table
@@ -112,20 +745,21 @@ impl<E: StorageEngine> Transaction for KVTransaction<E> {
             .filter(|(_, c)| c.index)
             .collect::<Vec<_>>();
 
-        // 多个索引;
+        // 多个索引; 主键加入到列值对应的主键集合中, 以便回表查询;
         for (i, index_col) in index_cols {
-            // 加载索引数据: key: tableName_cloName_colValue; 返回主键set集合;
-            let mut primary_index_set = self.load_index(&table_name, &index_col.name, &row[i])?;
-            // 主键索引 Set.add();
-            primary_index_set.insert(pk.clone());
-            // 再次保存 索引:[主键索引,以便回表查询];
-            self.save_index(&table_name, &index_col.name, &row[i], primary_index_set)?;
+            self.update_index(&table, index_col, &row[i], &pk, true)?;
         }
 
         Ok(())
     }
 
     fn update_row(&mut self, table: &Table, primary_id: &Value, row: Row) -> Result<()> {
+        sys::check_writable(&table.name)?;
+        self.lock_row(&table.name, primary_id)?;
+        self.written_tables.insert(table.name.clone());
+        // 校验行的有效性，并按照列类型做隐式转换
+        let row = table.coerce_row(row, self.strict_types)?;
+        table.check_row(&row)?;
         // 尝试获得 新行的主键值;
         let new_pk = table.get_primary_key(&row)?;
         // 更新了主键，则删除旧的数据，加一条新的数据,直接返回;
@@ -137,6 +771,14 @@ impl<E: StorageEngine> Transaction for KVTransaction<E> {
 
         // 没有更新主键的情况:
 
+        // 唯一列的值发生变化时，删除旧值的记录之后再校验新值
+        if table.columns.iter().any(|c| c.unique) {
+            if let Some(old_row) = self.read_by_id(&table.name, primary_id)? {
+                self.remove_unique(table, &old_row, Some(&row))?;
+                self.insert_unique(table, &row, primary_id)?;
+            }
+        }
+
         // 查询当前表的所有索引列; 判断是否更新了索引列;
         let index_cols = table
             .columns
@@ -157,26 +799,20 @@ impl<E: StorageEngine> Transaction for KVTransaction<E> {
                     continue;
                 }
 
-                let mut old_index = self.load_index(&table.name, &index_col.name, &old_row[i])?;
-                old_index.remove(primary_id);
-                self.save_index(&table.name, &index_col.name, &old_row[i], old_index)?;
-
-                let mut new_index = self.load_index(&table.name, &index_col.name, &row[i])?;
-                new_index.insert(primary_id.clone());
-                self.save_index(&table.name, &index_col.name, &row[i], new_index)?;
+                self.update_index(table, index_col, &old_row[i], primary_id, false)?;
+                self.update_index(table, index_col, &row[i], primary_id, true)?;
             }
         }
 
-        //
-        let key = Key::Row(table.name.clone(), new_pk).encode()?;
-        let value = bincode::serialize(&row)?;
-        //
-        self.txn.set(key, value)?;
+        self.write_row(table, &new_pk, &row)?;
 
         Ok(())
     }
 
     fn delete_row(&mut self, table: &Table, primary_id_delete: &Value) -> Result<()> {
+        sys::check_writable(&table.name)?;
+        self.lock_row(&table.name, primary_id_delete)?;
+        self.written_tables.insert(table.name.clone());
         // 维护索引, table 中有几个 索引列;
         let index_cols = table
             .columns
@@ -189,16 +825,19 @@ impl<E: StorageEngine> Transaction for KVTransaction<E> {
         for (i, index_col) in index_cols {
             // 将要删除的主键行数据查询出来;
             if let Some(row) = self.read_by_id(&table.name, primary_id_delete)? {
-                // < tableName_colName_colValue, Set>
-                let mut index = self.load_index(&table.name, &index_col.name, &row[i])?;
-                index.remove(primary_id_delete); // 在set中, 删除与之有关系的 主键;
-                self.save_index(&table.name, &index_col.name, &row[i], index)?; // 重新保存子列的索引信息;
+                // < tableName_colName_colValue, Set>, 在set中, 删除与之有关系的 主键;
+                self.update_index(table, index_col, &row[i], primary_id_delete, false)?;
+            }
+        }
+
+        if table.columns.iter().any(|c| c.unique) {
+            if let Some(row) = self.read_by_id(&table.name, primary_id_delete)? {
+                self.remove_unique(table, &row, None)?;
             }
         }
 
-        let key = Key::Row(table.name.clone(), primary_id_delete.clone()).encode()?;
         // tableName_primaryColValue 删除;
-        self.txn.delete(key)
+        self.remove_row(table, primary_id_delete)
     }
 
     fn load_index(
@@ -207,6 +846,27 @@ impl<E: StorageEngine> Transaction for KVTransaction<E> {
         col_name: &str,
         col_value: &Value,
     ) -> Result<HashSet<Value>> {
+        match self.index_type(table_name, col_name)? {
+            IndexType::Ordered => {}
+            IndexType::Hash => {
+                let bucket = self.load_hash_bucket(table_name, col_name, col_value)?;
+                return Ok(bucket
+                    .into_iter()
+                    .find(|(v, _)| v == col_value)
+                    .map(|(_, index)| index)
+                    .unwrap_or_default());
+            }
+            IndexType::FullText => {
+                return Err(Error::InvalidInput(format!(
+                    "fulltext index on column {} only supports match",
+                    col_name
+                )))
+            }
+            IndexType::Bitmap => {
+                let bitmap = self.load_bitmap_index(table_name, col_name, col_value)?;
+                return Ok(bitmap.iter().map(bitmap::position_pk).collect());
+            }
+        }
         // 返回相关的主键set集合;
         let key = Key::Index(table_name.into(), col_name.into(), col_value.clone()).encode()?;
         Ok(self.txn.get(key)?.map(|v| bincode::deserialize(&v)).transpose()?.unwrap_or_default())
@@ -219,6 +879,29 @@ impl<E: StorageEngine> Transaction for KVTransaction<E> {
         col_value: &Value,
         index: HashSet<Value>,
     ) -> Result<()> {
+        let index_type = self.index_type(table_name, col_name)?;
+        if index_type == IndexType::FullText {
+            return Err(Error::InvalidInput(format!(
+                "fulltext index on column {} only supports match",
+                col_name
+            )));
+        }
+        if index_type == IndexType::Bitmap {
+            let bitmap = index.iter().map(bitmap::pk_position).collect::<Result<Bitmap>>()?;
+            return self.save_bitmap_index(table_name, col_name, col_value, &bitmap);
+        }
+        if index_type == IndexType::Hash {
+            let mut bucket = self.load_hash_bucket(table_name, col_name, col_value)?;
+            bucket.retain(|(v, _)| v != col_value);
+            if !index.is_empty() {
+                bucket.push((col_value.clone(), index));
+            }
+            let key = hash_index_key(table_name, col_name, col_value)?;
+            return match bucket.is_empty() {
+                true => self.txn.delete(key),
+                false => self.txn.set(key, bincode::serialize(&bucket)?),
+            };
+        }
         let key = Key::Index(table_name.into(), col_name.into(), col_value.clone()).encode()?;
         if index.is_empty() {
             self.txn.delete(key)
@@ -227,74 +910,505 @@ impl<E: StorageEngine> Transaction for KVTransaction<E> {
         }
     }
 
-    fn read_by_id(&self, table_name: &str, primary_id: &Value) -> Result<Option<Row>> {
-        // 根据主键 primary_id 查询行数据;
-        Ok(self.txn.get(Key::Row(table_name.into(), primary_id.clone()).encode()?)?
-            .map(|v| bincode::deserialize(&v)).transpose()?)
+    fn load_index_prefix(
+        &self,
+        table_name: &str,
+        col_name: &str,
+        prefix: &str,
+    ) -> Result<HashSet<Value>> {
+        if self.index_type(table_name, col_name)? != IndexType::Ordered {
+            return Err(Error::InvalidInput(format!(
+                "index on column {} does not support prefix scan",
+                col_name
+            )));
+        }
+        let key = Key::Index(table_name.into(), col_name.into(), Value::String(prefix.into()));
+        let mut ids = HashSet::new();
+        for result in self.txn.scan_prefix(string_key_prefix(&key)?)? {
+            let index: HashSet<Value> = bincode::deserialize(&result.value)?;
+            ids.extend(index);
+        }
+        Ok(ids)
     }
 
-    // 扫描数据时, 需要过滤一些数据;
-    fn scan_table(&self, table_name: String, filter: Option<Expression>) -> Result<Vec<Row>> {
-        let prefix = KeyPrefix::Row(table_name.clone()).encode()?;
-        let table = self.must_get_table(table_name)?;
-        let results = self.txn.scan_prefix(prefix)?;
-
-        let mut rows = Vec::new();
-        for result in results {
-            // 过滤数据
-            let row: Row = bincode::deserialize(&result.value)?;
-            if let Some(expr) = &filter {
-                // 获得 表的所有列;
-                let cols = table.columns.iter().map(|c| c.name.clone()).collect();
-                //
-                match evaluate_expr(expr, &cols, &row, &cols, &row)? {
-                    Value::Null => {}
-                    Value::Boolean(false) => {}
-                    Value::Boolean(true) => {
-                        rows.push(row);
-                    }
-                    _ => return Err(Error::Internal("Unexpected expression".into())),
-                }
-            } else {
-                rows.push(row);
+    fn load_index_range(
+        &self,
+        table_name: &str,
+        col_name: &str,
+        start: Bound<Value>,
+        end: Bound<Value>,
+    ) -> Result<Vec<(Value, Value)>> {
+        if self.index_type(table_name, col_name)? != IndexType::Ordered {
+            return Err(Error::InvalidInput(format!(
+                "index on column {} does not support range scan",
+                col_name
+            )));
+        }
+        // 索引的 key 按照列值的顺序编码，边界直接转换为 key 的边界
+        // 没有下界时从 NULL 之后开始，比较运算不会匹配 NULL
+        let key = |value: Value| Key::Index(table_name.into(), col_name.into(), value).encode();
+        let start = match start {
+            Bound::Included(v) => Bound::Included(key(v)?),
+            Bound::Excluded(v) => Bound::Excluded(key(v)?),
+            Bound::Unbounded => Bound::Excluded(key(Value::Null)?),
+        };
+        let end = match end {
+            Bound::Included(v) => Bound::Included(key(v)?),
+            Bound::Excluded(v) => Bound::Excluded(key(v)?),
+            Bound::Unbounded => {
+                storage::engine::prefix_range(
+                    KeyPrefix::Index(table_name.into(), col_name.into()).encode()?,
+                )
+                .1
             }
+        };
+        let mut ids = Vec::new();
+        for result in self.txn.scan_range(start, end)? {
+            // 列值从索引的 key 中解码，覆盖索引不需要回表
+            let value = match deserialize_key(&result.key)? {
+                Key::Index(_, _, value) => value,
+                key => return Err(Error::Internal(format!("unexpected index key {:?}", key))),
+            };
+            let index: HashSet<Value> = bincode::deserialize(&result.value)?;
+            let mut pks = index.into_iter().collect::<Vec<_>>();
+            pks.sort_by(|v1, v2| v1.partial_cmp(v2).unwrap_or(std::cmp::Ordering::Equal));
+            ids.extend(pks.into_iter().map(|pk| (value.clone(), pk)));
         }
-        Ok(rows)
+        Ok(ids)
     }
 
-    fn create_table(&mut self, table: Table) -> Result<()> {
-        // 判断表是否已经存在
-        if self.get_table(table.name.clone())?.is_some() {
-            return Err(Error::Internal(format!(
-                "table {} already exists",
-                table.name
+    fn load_fulltext(
+        &self,
+        table_name: &str,
+        col_name: &str,
+        query: &str,
+    ) -> Result<HashSet<Value>> {
+        if self.index_type(table_name, col_name)? != IndexType::FullText {
+            return Err(Error::InvalidInput(format!(
+                "column {} has no fulltext index",
+                col_name
             )));
         }
-
-        // 判断表的有效性
-        table.validate()?;
-
-        let key = Key::Table(table.name.clone()).encode()?;
-        let value = bincode::serialize(&table)?;
-        self.txn.set(key, value)?;
-
-        Ok(())
+        // 每个词的主键集合取交集，从最短的倒排表开始
+        let mut postings = Vec::new();
+        for word in fulltext::tokenize(query) {
+            postings.push(self.load_posting(table_name, col_name, &word)?);
+        }
+        postings.sort_by_key(|p| p.len());
+        let mut postings = postings.into_iter();
+        let mut ids = match postings.next() {
+            Some(ids) => ids,
+            None => return Ok(HashSet::new()),
+        };
+        for posting in postings {
+            ids.retain(|id| posting.contains(id));
+        }
+        Ok(ids)
     }
 
-    fn drop_table(&mut self, table_name: String) -> Result<()> {
-        let table = self.must_get_table(table_name.clone())?;
-        // 删除表的数据
-        let rows = self.scan_table(table_name, None)?;
-        for row in rows {
-            self.delete_row(&table, &table.get_primary_key(&row)?)?;
+    fn load_bitmap(&self, table_name: &str, col_name: &str, col_value: &Value) -> Result<Bitmap> {
+        if self.index_type(table_name, col_name)? != IndexType::Bitmap {
+            return Err(Error::InvalidInput(format!(
+                "column {} has no bitmap index",
+                col_name
+            )));
+        }
+        self.load_bitmap_index(table_name, col_name, col_value)
+    }
+
+    fn scan_pk_prefix(&self, table_name: &str, prefix: &str) -> Result<Vec<Row>> {
+        let table = self.must_get_table(table_name.into())?;
+        let mut rows = Vec::new();
+        if table.layout == Layout::Columnar {
+            // 先扫描主键列得到主键，再按主键读取其他列
+            let pk_col = table.columns.iter().find(|c| c.primary_key).unwrap();
+            let key = Key::Column(
+                table.name.clone(),
+                pk_col.name.clone(),
+                Value::String(prefix.into()),
+            );
+            for result in self.txn.scan_prefix(string_key_prefix(&key)?)? {
+                let pk: Value = bincode::deserialize(&result.value)?;
+                rows.extend(self.read_row(&table, &pk)?);
+            }
+            self.add_rows_scanned(rows.len());
+            return Ok(rows);
+        }
+        let key = Key::Row(table_name.into(), Value::String(prefix.into()));
+        for result in self.txn.scan_prefix(string_key_prefix(&key)?)? {
+            rows.push(decode_row(&result.value)?);
+        }
+        self.add_rows_scanned(rows.len());
+        Ok(rows)
+    }
+
+    fn read_by_id(&self, table_name: &str, primary_id: &Value) -> Result<Option<Row>> {
+        if sys::is_sys_table(table_name) {
+            let table = self.must_get_table(table_name.into())?;
+            let rows = sys::scan_table(self, table_name)?;
+            for row in rows {
+                if table.get_primary_key(&row)? == *primary_id {
+                    return Ok(Some(row));
+                }
+            }
+            return Ok(None);
+        }
+        // 根据主键 primary_id 查询行数据;
+        let table = self.must_get_table(table_name.into())?;
+        let row = self.read_row(&table, primary_id)?;
+        self.add_rows_scanned(row.iter().len());
+        Ok(row)
+    }
+
+    // 扫描数据时, 需要过滤一些数据;
+    fn scan_table(&self, table_name: String, filter: Option<Expression>) -> Result<RowIter> {
+        self.scan_rows(table_name, filter, None, usize::MAX)
+    }
+
+    fn scan_columns(
+        &self,
+        table_name: String,
+        filter: Option<Expression>,
+        positions: &[usize],
+    ) -> Result<RowIter> {
+        self.scan_rows(table_name, filter, Some(positions), usize::MAX)
+    }
+
+    fn scan_limit(
+        &self,
+        table_name: String,
+        positions: Option<&[usize]>,
+        limit: usize,
+    ) -> Result<RowIter> {
+        self.scan_rows(table_name, None, positions, limit)
+    }
+
+    fn create_table(&mut self, table: Table) -> Result<()> {
+        sys::check_writable(&table.name)?;
+        // 判断表是否已经存在
+        if self.get_table(table.name.clone())?.is_some() {
+            return Err(Error::Duplicate(format!(
+                "table {} already exists",
+                table.name
+            )));
         }
 
+        // 判断表的有效性
+        table.validate()?;
+        self.save_table(&table)
+    }
+
+    fn drop_table(&mut self, table_name: String) -> Result<()> {
+        sys::check_writable(&table_name)?;
+        self.written_tables.insert(table_name.clone());
+        let table = self.must_get_table(table_name)?;
+        // 删除表的数据
+        self.delete_table_data(&table)?;
+
         // 删除表元数据
         let key = Key::Table(table.name).encode()?;
         self.txn.delete(key)
     }
 
+    fn truncate_table(&mut self, table_name: String) -> Result<usize> {
+        sys::check_writable(&table_name)?;
+        self.written_tables.insert(table_name.clone());
+        let table = self.must_get_table(table_name)?;
+        self.delete_table_data(&table)
+    }
+
+    fn alter_table(&mut self, table_name: String, action: AlterTableAction) -> Result<()> {
+        sys::check_writable(&table_name)?;
+        let table = self.must_get_table(table_name)?;
+        let mut new_table = table.clone();
+
+        match action {
+            AlterTableAction::AlterColumnDefault { column, default } => {
+                let col = &mut new_table.columns[table.get_col_index(&column)?];
+                col.default = match default {
+                    Some(expr) => Some(schema::default_value(&column, &col.datatype, &expr)?),
+                    None if col.nullable => Some(Value::Null),
+                    None => None,
+                };
+                new_table.validate()?;
+                // 只修改元数据，已有的数据不受影响
+                self.save_table(&new_table)
+            }
+            AlterTableAction::AlterColumnType { column, datatype } => {
+                let i = table.get_col_index(&column)?;
+                if table.columns[i].datatype == datatype {
+                    return Ok(());
+                }
+                let col = &mut new_table.columns[i];
+                col.default = col.default.take().map(|v| cast_value(v, &datatype)).transpose()?;
+                col.datatype = datatype.clone();
+                new_table.validate()?;
+
+                let rows = self.scan_table(table.name.clone(), None)?;
+                let old = &table.columns[i];
+                if old.primary_key || old.index || old.unique {
+                    // 主键、索引或者唯一列的值变化后，key 也会跟着变化，需要先删除旧数据再重新写入
+                    let rows = rows.collect::<Result<Vec<_>>>()?;
+                    for row in rows.iter() {
+                        self.delete_row(&table, &table.get_primary_key(row)?)?;
+                    }
+                    self.save_table(&new_table)?;
+                    for mut row in rows {
+                        row[i] = cast_value(row[i].clone(), &datatype)?;
+                        self.create_row(new_table.name.clone(), row)?;
+                    }
+                } else {
+                    self.save_table(&new_table)?;
+                    for row in rows {
+                        let mut row = row?;
+                        row[i] = cast_value(row[i].clone(), &datatype)?;
+                        new_table.check_row(&row)?;
+                        self.write_row(&new_table, &new_table.get_primary_key(&row)?, &row)?;
+                    }
+                }
+                Ok(())
+            }
+            AlterTableAction::AddColumn(column) => {
+                let col = schema::build_column(column)?;
+                if table.columns.iter().any(|c| c.name == col.name) {
+                    return Err(Error::Duplicate(format!(
+                        "column {} already exists in table {}",
+                        col.name, table.name
+                    )));
+                }
+                if col.primary_key {
+                    return Err(Error::InvalidInput(format!(
+                        "cannot add primary key column {}",
+                        col.name
+                    )));
+                }
+                // 新的列放在隐藏的 _rowid 列之前，已有的行使用默认值填充
+                let i = table.columns.iter().position(|c| c.is_hidden()).unwrap_or(table.columns.len());
+                new_table.columns.insert(i, col.clone());
+                new_table.validate()?;
+
+                let rows = self.scan_table(table.name.clone(), None)?.collect::<Result<Vec<_>>>()?;
+                let value = match (&col.default, rows.is_empty()) {
+                    (Some(value), _) => value.clone(),
+                    (None, true) => Value::Null,
+                    (None, false) => {
+                        return Err(Error::Constraint(format!(
+                            "column {} is not null and has no default value",
+                            col.name
+                        )))
+                    }
+                };
+                self.save_table(&new_table)?;
+                for mut row in rows {
+                    row.insert(i, value.clone());
+                    new_table.check_row(&row)?;
+                    let pk = new_table.get_primary_key(&row)?;
+                    self.write_row(&new_table, &pk, &row)?;
+                    if col.index {
+                        self.update_index(&new_table, &col, &value, &pk, true)?;
+                    }
+                    // 多行使用同一个非 NULL 的默认值时违反唯一约束
+                    if col.unique {
+                        self.insert_unique(&new_table, &row, &pk)?;
+                    }
+                }
+                Ok(())
+            }
+            AlterTableAction::DropColumn(column) => {
+                let i = table.get_col_index(&column)?;
+                let col = &table.columns[i];
+                if col.primary_key {
+                    return Err(Error::InvalidInput(format!(
+                        "cannot drop primary key column {}",
+                        column
+                    )));
+                }
+                new_table.columns.remove(i);
+                // 其他 CHECK 约束引用了这一列时不能删除
+                new_table.validate()?;
+                // 删除列上的索引数据
+                if col.index {
+                    self.txn.delete_prefix(index_prefix(&table.name, col)?)?;
+                }
+                if col.unique {
+                    let prefix = KeyPrefix::Unique(table.name.clone(), column.clone()).encode()?;
+                    self.txn.delete_prefix(prefix)?;
+                }
+                match table.layout {
+                    Layout::Row => {
+                        let rows = self.scan_table(table.name.clone(), None)?;
+                        for row in rows {
+                            let mut row = row?;
+                            row.remove(i);
+                            self.write_row(&new_table, &new_table.get_primary_key(&row)?, &row)?;
+                        }
+                    }
+                    Layout::Columnar => {
+                        let prefix = KeyPrefix::Column(table.name.clone(), column).encode()?;
+                        self.txn.delete_prefix(prefix)?;
+                    }
+                }
+                self.save_table(&new_table)
+            }
+            AlterTableAction::RenameColumn { column, new_name } => {
+                let i = table.get_col_index(&column)?;
+                if new_name == schema::ROWID || table.columns[i].is_hidden() {
+                    return Err(Error::InvalidInput(format!(
+                        "column name {} is reserved",
+                        schema::ROWID
+                    )));
+                }
+                if table.columns.iter().any(|c| c.name == new_name) {
+                    return Err(Error::Duplicate(format!(
+                        "column {} already exists in table {}",
+                        new_name, table.name
+                    )));
+                }
+                new_table.columns[i].name = new_name.clone();
+                new_table.rename_check_column(&column, &new_name)?;
+
+                // 按列存储时 key 中带有列名，需要把这一列的数据移动到新的列名下
+                if table.layout == Layout::Columnar {
+                    let mut positions = table.primary_key_positions();
+                    positions.push(i);
+                    for row in self.scan_columns(table.name.clone(), None, &positions)? {
+                        let mut row = row?;
+                        let value = row.pop().unwrap();
+                        let key = Key::Column(table.name.clone(), new_name.clone(), schema::primary_key_value(row));
+                        self.txn.set(key.encode()?, bincode::serialize(&value)?)?;
+                    }
+                    let prefix = KeyPrefix::Column(table.name.clone(), column.clone()).encode()?;
+                    self.txn.delete_prefix(prefix)?;
+                }
+                self.save_table(&new_table)?;
+                // 唯一约束和索引的 key 中同样带有列名，删除旧的数据之后重建
+                if table.columns[i].unique {
+                    let prefix = KeyPrefix::Unique(table.name.clone(), column.clone()).encode()?;
+                    self.txn.delete_prefix(prefix)?;
+                    for row in self.scan_table(table.name.clone(), None)? {
+                        let row = row?;
+                        self.insert_unique(&new_table, &row, &new_table.get_primary_key(&row)?)?;
+                    }
+                }
+                if table.columns[i].index {
+                    self.txn.delete_prefix(index_prefix(&table.name, &table.columns[i])?)?;
+                    self.reindex(table.name.clone(), Some(new_name))?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn reindex(&mut self, table_name: String, col_name: Option<String>) -> Result<usize> {
+        sys::check_writable(&table_name)?;
+        self.written_tables.insert(table_name.clone());
+        let table = self.must_get_table(table_name)?;
+        let index_cols = match col_name {
+            // 可以使用索引名或者索引列的列名
+            Some(col_name) => {
+                let i = match table.get_index_col(&col_name) {
+                    Ok(i) => i,
+                    Err(_) => table.get_col_index(&col_name)?,
+                };
+                if !table.columns[i].index {
+                    return Err(Error::InvalidInput(format!(
+                        "column {} in table {} is not indexed",
+                        col_name, table.name
+                    )));
+                }
+                vec![i]
+            }
+            None => (0..table.columns.len())
+                .filter(|i| table.columns[*i].index)
+                .collect(),
+        };
+
+        let rows = self.scan_table(table.name.clone(), None)?.collect::<Result<Vec<_>>>()?;
+        let mut count = 0;
+        for i in index_cols {
+            let col_name = &table.columns[i].name;
+            // 删除旧的索引数据
+            for result in self.txn.scan_prefix(index_prefix(&table.name, &table.columns[i])?)? {
+                self.txn.delete(result.key)?;
+            }
+
+            // 全文索引按照每一行分词之后重建
+            if table.columns[i].index_type == IndexType::FullText {
+                for row in rows.iter() {
+                    let pk = table.get_primary_key(row)?;
+                    self.update_index(&table, &table.columns[i], &row[i], &pk, true)?;
+                    count += 1;
+                }
+                continue;
+            }
+
+            // 根据表中的数据重新生成索引
+            let mut indexes: HashMap<Value, HashSet<Value>> = HashMap::new();
+            for row in rows.iter() {
+                indexes
+                    .entry(row[i].clone())
+                    .or_default()
+                    .insert(table.get_primary_key(row)?);
+            }
+            for (value, index) in indexes {
+                count += index.len();
+                self.save_index(&table.name, col_name, &value, index)?;
+            }
+        }
+        Ok(count)
+    }
+
+    fn create_index(
+        &mut self,
+        table_name: String,
+        index_name: Option<String>,
+        col_name: String,
+        index_type: IndexType,
+    ) -> Result<usize> {
+        sys::check_writable(&table_name)?;
+        let mut table = self.must_get_table(table_name)?;
+        let i = table.get_col_index(&col_name)?;
+        // 复合主键中的单个列可以建索引
+        if (table.columns[i].primary_key && !table.is_composite_key()) || table.columns[i].index {
+            return Err(Error::Duplicate(format!(
+                "column {} in table {} is already indexed",
+                col_name, table.name
+            )));
+        }
+        if let Some(name) = &index_name {
+            if table.get_index_col(name).is_ok() {
+                return Err(Error::Duplicate(format!(
+                    "index {} already exists in table {}",
+                    name, table.name
+                )));
+            }
+        }
+        let col = &mut table.columns[i];
+        col.index = true;
+        col.index_type = index_type;
+        col.index_name = index_name;
+        table.validate()?;
+        self.save_table(&table)?;
+        // 根据表中已有的数据生成索引
+        self.reindex(table.name, Some(col_name))
+    }
+
+    fn drop_index(&mut self, table_name: String, index_name: String) -> Result<()> {
+        sys::check_writable(&table_name)?;
+        let mut table = self.must_get_table(table_name)?;
+        let i = table.get_index_col(&index_name)?;
+        self.txn.delete_prefix(index_prefix(&table.name, &table.columns[i])?)?;
+        let col = &mut table.columns[i];
+        col.index = false;
+        col.index_type = IndexType::Ordered;
+        col.index_name = None;
+        self.save_table(&table)
+    }
+
     fn get_table(&self, table_name: String) -> Result<Option<Table>> {
+        if sys::is_sys_table(&table_name) {
+            return Ok(sys::get_table(&table_name));
+        }
         let key = Key::Table(table_name).encode()?;
         Ok(self
             .txn
@@ -320,6 +1434,16 @@ enum Key {
     Table(String),
     Row(String, Value),
     Index(String, String, Value),
+    // 按列存储的表，每一列的值: 表名、列名、主键
+    Column(String, String, Value),
+    // 数据格式的版本号
+    Format,
+    // 表中自增列（包括没有主键的表的 _rowid）下一个分配的序号
+    Sequence(String),
+    // 全文索引的倒排表: 表名、列名、词
+    Posting(String, String, String),
+    // 唯一约束: 表名、列名、列值，value 是使用这个列值的行的主键
+    Unique(String, String, Value),
 }
 
 impl Key {
@@ -328,10 +1452,77 @@ impl Key {
     }
 }
 
+// 按列存储的表，每一列分别扫描，各列的值都按照主键排序，每一列取出下一个值拼成一行
+// 每一列都只读取前 limit 个值，对应的是相同的 limit 行
+fn next_columnar_row<E: StorageEngine>(
+    table_name: &str,
+    columns: &mut [ScanIterator<E>],
+) -> Result<Option<Row>> {
+    let mut values = Vec::with_capacity(columns.len());
+    for iter in columns.iter_mut() {
+        values.push(iter.next().transpose()?);
+    }
+    match values.iter().filter(|v| v.is_some()).count() {
+        0 => Ok(None),
+        n if n == values.len() => Ok(Some(
+            values
+                .into_iter()
+                .flatten()
+                .map(|result| bincode::deserialize(&result.value))
+                .collect::<std::result::Result<_, _>>()?,
+        )),
+        _ => Err(Error::Internal(format!(
+            "columns of table {} have different numbers of values",
+            table_name
+        ))),
+    }
+}
+
+// 哈希索引同样保存在 Key::Index 中，列值换成列值编码的 FNV-1a 哈希
+// 哈希值需要持久化，不能使用标准库中不保证跨版本稳定的哈希算法
+fn hash_index_key(table_name: &str, col_name: &str, col_value: &Value) -> Result<Vec<u8>> {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in serialize_key(col_value)? {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    Key::Index(table_name.into(), col_name.into(), Value::Integer(hash as i64)).encode()
+}
+
+// 一个索引列的所有索引数据的 key 前缀
+fn index_prefix(table_name: &str, col: &Column) -> Result<Vec<u8>> {
+    match col.index_type {
+        IndexType::Ordered | IndexType::Hash | IndexType::Bitmap => {
+            KeyPrefix::Index(table_name.into(), col.name.clone()).encode()
+        }
+        // 空字符串的前缀可以匹配到所有的词
+        IndexType::FullText => string_key_prefix(&Key::Posting(
+            table_name.into(),
+            col.name.clone(),
+            String::new(),
+        )),
+    }
+}
+
+// 以字符串结尾的 key 去掉最后的 [0, 0] 结束符，得到字符串前缀对应的 key 前缀
+// 例如 Row("t", String("ab")) 可以匹配到 Row("t", String("abc"))
+fn string_key_prefix(key: &Key) -> Result<Vec<u8>> {
+    let mut prefix = key.encode()?;
+    prefix.truncate(prefix.len() - 2);
+    Ok(prefix)
+}
+
+// 前缀编码之后要和 Key 编码之后的开头一致，变体的顺序需要和 Key 相同
 #[derive(Debug, Serialize, Deserialize)]
 enum KeyPrefix {
     Table,
     Row(String),
+    Index(String, String),
+    Column(String, String),
+    Format,
+    Sequence(String),
+    Posting(String, String),
+    Unique(String, String),
 }
 
 impl KeyPrefix {
@@ -343,18 +1534,29 @@ impl KeyPrefix {
 #[cfg(test)]
 mod tests {
 
-    use super::KVEngine;
+    use std::collections::HashSet;
+
+    use super::{KVEngine, KeyPrefix};
     use crate::storage::engine::Engine as StorageEngine;
     use crate::{
-        error::Result,
+        error::{Error, Result},
         sql::{
-            engine::{Engine, Session},
-            executor::ResultSet,
-            types::{Row, Value},
+            engine::{
+                audit::{AuditLog, AuditRecord},
+                Engine, Session, Transaction,
+            },
+            executor::{ColumnDesc, ResultSet},
+            parser::{ast, Parser},
+            plan::Plan,
+            types::{DataType, Row, Value},
         },
-        storage::disk::DiskEngine,
+        storage::disk::{DiskEngine, DiskOptions, Durability},
     };
 
+    fn names(columns: &[ColumnDesc]) -> Vec<&str> {
+        columns.iter().map(|c| c.name.as_str()).collect()
+    }
+
     fn setup_table<E: StorageEngine + 'static>(s: &mut Session<KVEngine<E>>) -> Result<()> {
         s.execute(
             "create table t1 (
@@ -516,6 +1718,55 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_insert_default() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        setup_table(&mut s)?;
+
+        s.execute("insert into t1 values (1, default, 2);")?;
+        s.execute("insert into t1 (c, a) values (default, 2);")?;
+        scan_table_and_compare(
+            &mut s,
+            "t1",
+            vec![
+                vec![
+                    Value::Integer(1),
+                    Value::String("vv".to_string()),
+                    Value::Integer(2),
+                ],
+                vec![
+                    Value::Integer(2),
+                    Value::String("vv".to_string()),
+                    Value::Integer(100),
+                ],
+            ],
+        )?;
+
+        // 所有列都使用默认值
+        s.execute("create table t5 (a int primary key default 1, b text default 'x');")?;
+        s.execute("insert into t5 default values;")?;
+        scan_table_and_compare(
+            &mut s,
+            "t5",
+            vec![vec![Value::Integer(1), Value::String("x".to_string())]],
+        )?;
+
+        // 没有默认值的列不能使用 DEFAULT
+        assert!(matches!(
+            s.execute("insert into t3 values (1, 2, 3, default);"),
+            Err(Error::Constraint(_))
+        ));
+        assert!(matches!(
+            s.execute("insert into t1 default values;"),
+            Err(Error::Constraint(_))
+        ));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
     #[test]
     fn test_update() -> Result<()> {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
@@ -586,6 +1837,45 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_update_from() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table accounts (id int primary key, balance int, note text);")?;
+        s.execute("create table deposits (id int primary key, account_id int, amount int);")?;
+        s.execute("insert into accounts values (1, 10, null), (2, 20, null), (3, 30, null);")?;
+        s.execute("insert into deposits values (1, 1, 5), (2, 2, 7), (3, 2, 9);")?;
+
+        // 等值条件使用 Hash Join，账户 2 匹配了两笔存款，只更新一次
+        let res = s.execute(
+            "update accounts set balance = balance + deposits.amount, note = 'deposit' from deposits where accounts.id = deposits.account_id;",
+        )?;
+        assert_eq!(res, ResultSet::Update { count: 2 });
+        let rows: Vec<(i64, i64, Option<String>)> =
+            s.query_as("select * from accounts order by id;")?;
+        assert_eq!(rows[0], (1, 15, Some("deposit".into())));
+        assert!(rows[1] == (2, 27, Some("deposit".into())) || rows[1] == (2, 29, Some("deposit".into())));
+        assert_eq!(rows[2], (3, 30, None));
+
+        // 其他条件对 join 的结果过滤
+        let res = s.execute("update accounts set note = 'big' from deposits where deposits.amount > 8;")?;
+        assert_eq!(res, ResultSet::Update { count: 3 });
+        let res = s.execute("update accounts set note = 'none' from deposits where accounts.balance = deposits.amount;")?;
+        assert_eq!(res, ResultSet::Update { count: 0 });
+        match s.execute("explain update accounts set balance = 0 from deposits where accounts.id = deposits.id;")? {
+            ResultSet::Explain { plan } => assert!(plan.contains("Hash Join"), "{}", plan),
+            _ => unreachable!(),
+        }
+        assert!(matches!(
+            s.execute("update accounts set balance = 0 from deposits where id = 1;"),
+            Err(Error::InvalidInput(_))
+        ));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
     #[test]
     fn test_delete() -> Result<()> {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
@@ -662,6 +1952,34 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_delete_using() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table a (id int primary key, x int);")?;
+        s.execute("create table b (id int primary key, aid int, flag bool);")?;
+        s.execute("insert into a values (1, 10), (2, 20), (3, 30), (4, 40);")?;
+        s.execute("insert into b values (1, 1, true), (2, 2, false), (3, 2, true);")?;
+
+        // 2 和 b 中的两行匹配，只删除一次
+        let res = s.execute("delete from a using b where a.id = b.aid;")?;
+        assert_eq!(res, ResultSet::Delete { count: 2 });
+        let rows: Vec<(i64, i64)> = s.query_as("select * from a order by id;")?;
+        assert_eq!(rows, vec![(3, 30), (4, 40)]);
+
+        // 只引用 using 中的列时，using 中有满足条件的行就删除所有行
+        let res = s.execute("delete from a using b where b.id = 5;")?;
+        assert_eq!(res, ResultSet::Delete { count: 0 });
+        let res = s.execute("delete from a using b where a.id = b.id;")?;
+        assert_eq!(res, ResultSet::Delete { count: 1 });
+        let rows: Vec<(i64, i64)> = s.query_as("select * from a;")?;
+        assert_eq!(rows, vec![(4, 40)]);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
     #[test]
     fn test_sort() -> Result<()> {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
@@ -745,109 +2063,455 @@ mod tests {
     }
 
     #[test]
-    fn test_agg() -> Result<()> {
+    fn test_join_columns() -> Result<()> {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
         let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
         let mut s = kvengine.session()?;
-        s.execute("create table t1 (a int primary key, b text, c float);")?;
-
-        s.execute("insert into t1 values (1, 'aa', 3.1);")?;
-        s.execute("insert into t1 values (2, 'cc', 5.3);")?;
-        s.execute("insert into t1 values (3, null, NULL);")?;
-        s.execute("insert into t1 values (4, 'dd', 4.6);")?;
+        s.execute("create table t1 (id int primary key, a text);")?;
+        s.execute("create table t2 (id int primary key, b text);")?;
+        s.execute("insert into t1 values (1, 'a1'), (2, 'a2');")?;
+        s.execute("insert into t2 values (2, 'b2'), (3, 'b3');")?;
 
-        match s.execute("select count(a) as total, max(b), min(a), sum(c), avg(c) from t1;")? {
+        // join 的结果集列名带有表名前缀
+        match s.execute("select * from t1 left join t2 on t1.id = t2.id;")? {
             ResultSet::Scan { columns, rows } => {
-                assert_eq!(columns, vec!["total", "max", "min", "sum", "avg"]);
+                assert_eq!(names(&columns), vec!["t1.id", "t1.a", "t2.id", "t2.b"]);
                 assert_eq!(
                     rows,
-                    vec![vec![
-                        Value::Integer(4),
-                        Value::String("dd".to_string()),
-                        Value::Integer(1),
-                        Value::Float(13.0),
-                        Value::Float(13.0 / 3.0)
-                    ]]
+                    vec![
+                        vec![
+                            Value::Integer(1),
+                            Value::String("a1".into()),
+                            Value::Null,
+                            Value::Null
+                        ],
+                        vec![
+                            Value::Integer(2),
+                            Value::String("a2".into()),
+                            Value::Integer(2),
+                            Value::String("b2".into())
+                        ],
+                    ]
                 );
             }
             _ => unreachable!(),
         }
 
-        s.execute("create table t2 (a int primary key, b text, c float);")?;
-        s.execute("insert into t2 values (1, NULL, NULL);")?;
-        s.execute("insert into t2 values (2, NULL, NULL);")?;
-        match s.execute("select count(a) as total, max(b), min(a), sum(c), avg(c) from t2;")? {
+        // 使用表名区分同名列，on 条件的两边可以交换顺序
+        match s.execute("select t2.id, a, b from t1 join t2 on t2.id = t1.id order by t1.id;")? {
             ResultSet::Scan { columns, rows } => {
-                assert_eq!(columns, vec!["total", "max", "min", "sum", "avg"]);
+                assert_eq!(names(&columns), vec!["t2.id", "a", "b"]);
                 assert_eq!(
                     rows,
                     vec![vec![
                         Value::Integer(2),
-                        Value::Null,
-                        Value::Integer(1),
-                        Value::Null,
-                        Value::Null
+                        Value::String("a2".into()),
+                        Value::String("b2".into())
                     ]]
                 );
             }
             _ => unreachable!(),
         }
 
+        // 不带表名的同名列有歧义
+        assert!(matches!(
+            s.execute("select id from t1 join t2 on t1.id = t2.id;"),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            s.execute("select t3.id from t1 join t2 on t1.id = t2.id;"),
+            Err(Error::NotFound(_))
+        ));
+
+        // 单表查询的列名不变，也可以使用表名前缀
+        match s.execute("select t1.a from t1 where id = 1;")? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(names(&columns), vec!["t1.a"]);
+                assert_eq!(rows, vec![vec![Value::String("a1".into())]]);
+            }
+            _ => unreachable!(),
+        }
+
         std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
     #[test]
-    fn test_group_by() -> Result<()> {
+    fn test_derived_table() -> Result<()> {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
         let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
         let mut s = kvengine.session()?;
-        s.execute("create table t1 (a int primary key, b text, c float);")?;
-
-        s.execute("insert into t1 values (1, 'aa', 3.1);")?;
-        s.execute("insert into t1 values (2, 'bb', 5.3);")?;
-        s.execute("insert into t1 values (3, null, NULL);")?;
-        s.execute("insert into t1 values (4, null, 4.6);")?;
-        s.execute("insert into t1 values (5, 'bb', 5.8);")?;
-        s.execute("insert into t1 values (6, 'dd', 1.4);")?;
+        s.execute("create table t1 (a int primary key, b text);")?;
+        s.execute("create table t2 (c int primary key, d text);")?;
+        s.execute("insert into t1 values (1, 'a'), (2, 'b'), (3, 'c');")?;
+        s.execute("insert into t2 values (2, 'x'), (3, 'y');")?;
 
-        match s.execute("select b, min(c), max(a), avg(c) from t1 group by b order by avg;")? {
+        match s.execute("select * from (select a, b from t1 where a > 1) x;")? {
             ResultSet::Scan { columns, rows } => {
-                assert_eq!(columns, vec!["b", "min", "max", "avg"]);
+                assert_eq!(names(&columns), vec!["a", "b"]);
                 assert_eq!(
                     rows,
                     vec![
-                        vec![
-                            Value::String("dd".to_string()),
-                            Value::Float(1.4),
-                            Value::Integer(6),
-                            Value::Float(1.4)
-                        ],
-                        vec![
-                            Value::String("aa".to_string()),
-                            Value::Float(3.1),
-                            Value::Integer(1),
-                            Value::Float(3.1)
-                        ],
-                        vec![
-                            Value::Null,
-                            Value::Float(4.6),
-                            Value::Integer(4),
-                            Value::Float(4.6)
-                        ],
-                        vec![
-                            Value::String("bb".to_string()),
-                            Value::Float(5.3),
-                            Value::Integer(5),
-                            Value::Float(5.55)
-                        ],
+                        vec![Value::Integer(2), Value::String("b".into())],
+                        vec![Value::Integer(3), Value::String("c".into())],
                     ]
                 );
             }
             _ => unreachable!(),
         }
 
-        std::fs::remove_dir_all(p.parent().unwrap())?;
+        // 外层的 where、order by 作用在子查询的结果上
+        match s.execute(
+            "select v from (select b as v, a from t1) as x where a < 3 order by v desc;",
+        )? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(names(&columns), vec!["v"]);
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![Value::String("b".into())],
+                        vec![Value::String("a".into())],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        // join 时子查询的列名使用别名作为前缀
+        match s.execute("select x.a, d from (select a from t1) x join t2 on x.a = c;")? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(names(&columns), vec!["x.a", "d"]);
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![Value::Integer(2), Value::String("x".into())],
+                        vec![Value::Integer(3), Value::String("y".into())],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_as() -> Result<()> {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Row {
+            a: i64,
+            b: Option<String>,
+        }
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text, c float);")?;
+        s.execute("insert into t1 values (1, 'x', 1.5), (2, null, 2.5);")?;
+
+        let rows: Vec<Row> = s.query_as("select a, b from t1;")?;
+        assert_eq!(
+            rows,
+            vec![
+                Row {
+                    a: 1,
+                    b: Some("x".into())
+                },
+                Row { a: 2, b: None },
+            ]
+        );
+
+        let rows: Vec<(i64, f64)> = s.query_as("select a, c from t1 where a = 2;")?;
+        assert_eq!(rows, vec![(2, 2.5)]);
+
+        let count: Vec<i64> = s.query_as("select count(a) from t1;")?;
+        assert_eq!(count, vec![2]);
+
+        assert!(matches!(
+            s.query_as::<Row>("select c as a, b from t1;"),
+            Err(Error::TypeMismatch(_))
+        ));
+        assert!(matches!(
+            s.query_as::<Row>("insert into t1 values (3, 'z', 1.0);"),
+            Err(Error::InvalidInput(_))
+        ));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_result_cache() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?).with_result_cache(2);
+        let cache = kvengine.result_cache().unwrap();
+        let mut s1 = kvengine.session()?;
+        let mut s2 = kvengine.session()?;
+        s1.execute("create table t1 (a int primary key, b int);")?;
+        s1.execute("create table t2 (a int primary key);")?;
+        s1.execute("insert into t1 values (1, 1), (2, 2);")?;
+
+        // 相同的查询，空格和关键字大小写不同也能命中
+        let count = |s: &mut Session<_>, sql| -> Result<usize> {
+            match s.execute(sql)? {
+                ResultSet::Scan { rows, .. } => Ok(rows.len()),
+                _ => unreachable!(),
+            }
+        };
+        assert_eq!(count(&mut s1, "select * from t1;")?, 2);
+        assert_eq!(count(&mut s2, "SELECT *  FROM t1 ;")?, 2);
+        assert_eq!(cache.stats()?.hits, 1);
+
+        // 其他表的写入不影响缓存，未提交的写入也不影响
+        s2.execute("insert into t2 values (1);")?;
+        s2.execute("begin;")?;
+        s2.execute("insert into t1 values (3, 3);")?;
+        assert_eq!(count(&mut s2, "select * from t1;")?, 3);
+        assert_eq!(count(&mut s1, "select * from t1;")?, 2);
+        assert_eq!(cache.stats()?.hits, 2);
+
+        // 提交之后缓存失效
+        s2.execute("commit;")?;
+        assert_eq!(count(&mut s1, "select * from t1;")?, 3);
+        assert_eq!(cache.stats()?.hits, 2);
+        s1.execute("update t1 set b = 10 where a = 1;")?;
+        let rows: Vec<(i64, i64)> = s1.query_as("select * from t1 where a = 1;")?;
+        assert_eq!(rows, vec![(1, 10)]);
+        s1.execute("drop table t1;")?;
+        assert!(s1.execute("select * from t1 where a = 1;").is_err());
+
+        // 系统表以及包含 now() 的查询不缓存，超过容量时淘汰最早的缓存
+        let before = cache.stats()?;
+        for sql in ["select now() from t2;", "select * from sys.transactions;"] {
+            s1.execute(sql)?;
+            s1.execute(sql)?;
+        }
+        assert_eq!(cache.stats()?.hits, before.hits);
+        for sql in ["select * from t2;", "select a from t2;", "select * from t2 where a = 1;"] {
+            s1.execute(sql)?;
+        }
+        assert_eq!(cache.stats()?.entries, 2);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_txn() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b int);")?;
+
+        // 执行成功则提交
+        let count = kvengine.with_txn(|txn| {
+            txn.execute("insert into t1 values (1, 1);")?;
+            txn.execute("insert into t1 values (2, 2);")?;
+            Ok(2)
+        })?;
+        assert_eq!(count, 2);
+        let rows: Vec<(i64, i64)> = s.query_as("select * from t1;")?;
+        assert_eq!(rows, vec![(1, 1), (2, 2)]);
+
+        // 执行失败则回滚
+        let result = kvengine.with_txn(|txn| {
+            txn.execute("update t1 set b = 10 where a = 1;")?;
+            txn.execute("insert into t1 values (2, 2);")
+        });
+        assert!(matches!(result, Err(Error::Duplicate(_))));
+        let rows: Vec<(i64, i64)> = s.query_as("select * from t1;")?;
+        assert_eq!(rows, vec![(1, 1), (2, 2)]);
+
+        // 写冲突时重试，第一次执行时其他 session 修改了同一行
+        let mut attempts = 0;
+        kvengine.with_txn_retry(3, |txn| {
+            attempts += 1;
+            if attempts == 1 {
+                s.execute("update t1 set b = 20 where a = 1;")?;
+            }
+            txn.execute("update t1 set b = 30 where a = 1;")?;
+            Ok(())
+        })?;
+        assert_eq!(attempts, 2);
+        let rows: Vec<(i64, i64)> = s.query_as("select * from t1;")?;
+        assert_eq!(rows, vec![(1, 30), (2, 2)]);
+
+        // 不重试时返回写冲突
+        let result = kvengine.with_txn(|txn| {
+            s.execute("update t1 set b = 40 where a = 2;")?;
+            txn.execute("update t1 set b = 50 where a = 2;")?;
+            Ok(())
+        });
+        assert_eq!(result, Err(Error::WriteConflict));
+
+        // 自动提交的语句构建执行计划失败时，事务也会回滚
+        assert!(s.execute("select * from t9;").is_err());
+        match s.execute("select * from sys.transactions;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 1),
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_agg() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text, c float);")?;
+
+        s.execute("insert into t1 values (1, 'aa', 3.1);")?;
+        s.execute("insert into t1 values (2, 'cc', 5.3);")?;
+        s.execute("insert into t1 values (3, null, NULL);")?;
+        s.execute("insert into t1 values (4, 'dd', 4.6);")?;
+
+        match s.execute("select count(a) as total, max(b), min(a), sum(c), avg(c) from t1;")? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(names(&columns), vec!["total", "max", "min", "sum", "avg"]);
+                assert_eq!(
+                    rows,
+                    vec![vec![
+                        Value::Integer(4),
+                        Value::String("dd".to_string()),
+                        Value::Integer(1),
+                        Value::Float(13.0),
+                        Value::Float(13.0 / 3.0)
+                    ]]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        s.execute("create table t2 (a int primary key, b text, c float);")?;
+        s.execute("insert into t2 values (1, NULL, NULL);")?;
+        s.execute("insert into t2 values (2, NULL, NULL);")?;
+        match s.execute("select count(a) as total, max(b), min(a), sum(c), avg(c) from t2;")? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(names(&columns), vec!["total", "max", "min", "sum", "avg"]);
+                assert_eq!(
+                    rows,
+                    vec![vec![
+                        Value::Integer(2),
+                        Value::Null,
+                        Value::Integer(1),
+                        Value::Null,
+                        Value::Null
+                    ]]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        // 近似去重计数，基数较小时结果是精确的
+        s.execute("insert into t2 values (3, 'x', NULL), (4, 'y', NULL), (5, 'x', NULL);")?;
+        let rows: Vec<(i64, i64)> =
+            s.query_as("select approx_count_distinct(b), approx_count_distinct(c) from t2;")?;
+        assert_eq!(rows, vec![(2, 0)]);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_by() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text, c float);")?;
+
+        s.execute("insert into t1 values (1, 'aa', 3.1);")?;
+        s.execute("insert into t1 values (2, 'bb', 5.3);")?;
+        s.execute("insert into t1 values (3, null, NULL);")?;
+        s.execute("insert into t1 values (4, null, 4.6);")?;
+        s.execute("insert into t1 values (5, 'bb', 5.8);")?;
+        s.execute("insert into t1 values (6, 'dd', 1.4);")?;
+
+        match s.execute("select b, min(c), max(a), avg(c) from t1 group by b order by avg;")? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(names(&columns), vec!["b", "min", "max", "avg"]);
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![
+                            Value::String("dd".to_string()),
+                            Value::Float(1.4),
+                            Value::Integer(6),
+                            Value::Float(1.4)
+                        ],
+                        vec![
+                            Value::String("aa".to_string()),
+                            Value::Float(3.1),
+                            Value::Integer(1),
+                            Value::Float(3.1)
+                        ],
+                        vec![
+                            Value::Null,
+                            Value::Float(4.6),
+                            Value::Integer(4),
+                            Value::Float(4.6)
+                        ],
+                        vec![
+                            Value::String("bb".to_string()),
+                            Value::Float(5.3),
+                            Value::Integer(5),
+                            Value::Float(5.55)
+                        ],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        // 多个分组列的值组合在一起分组
+        s.execute("create table t2 (a int primary key, b text, c int, d int);")?;
+        s.execute(
+            "insert into t2 values (1, 'x', 1, 10), (2, 'x', 2, 20), (3, 'x', 1, 30), (4, 'y', 1, 40), (5, null, 1, 50), (6, null, 1, 60);",
+        )?;
+        let rows: Vec<(Option<String>, i64, i64, f64)> =
+            s.query_as("select b, c, count(a), sum(d) from t2 group by b, c order by sum, count desc;")?;
+        assert_eq!(
+            rows,
+            vec![
+                (Some("x".into()), 2, 1, 20.0),
+                (Some("x".into()), 1, 2, 40.0),
+                (Some("y".into()), 1, 1, 40.0),
+                (None, 1, 2, 110.0),
+            ]
+        );
+        // 分组列的顺序不影响结果，可以只输出部分分组列
+        let rows: Vec<(i64, f64)> = s.query_as("select c, sum(d) as s from t2 group by b, c order by s;")?;
+        assert_eq!(rows, vec![(2, 20.0), (1, 40.0), (1, 40.0), (1, 110.0)]);
+        let rows: Vec<(String, i64)> =
+            s.query_as("select t2.b, count(a) from t2 where a < 5 group by c, b order by count;")?;
+        assert_eq!(rows.len(), 3);
+
+        // 不在聚集函数中的列必须出现在 GROUP BY 中
+        for sql in [
+            "select b, c, count(a) from t2 group by b;",
+            "select b, count(a) from t2;",
+            "select count(a) from t2 group by 1;",
+        ] {
+            assert!(matches!(s.execute(sql), Err(Error::InvalidInput(_))), "{}", sql);
+        }
+
+        // having 中可以直接使用聚集函数，select 中没有的聚集函数不会输出
+        let rows: Vec<(Option<String>, f64)> =
+            s.query_as("select b, sum(d) as s from t2 group by b having SUM(d) > 40 order by s;")?;
+        assert_eq!(rows, vec![(Some("x".into()), 60.0), (None, 110.0)]);
+        match s.execute("select b from t2 group by b having count(a) > 1 and max(d) < 60 order by b;")? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(names(&columns), vec!["b"]);
+                assert_eq!(rows, vec![vec![Value::String("x".into())]]);
+            }
+            _ => unreachable!(),
+        }
+        let rows: Vec<i64> = s.query_as("select count(a) from t2 having count(a) > 5;")?;
+        assert_eq!(rows, vec![6]);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
@@ -886,73 +2550,3978 @@ mod tests {
     }
 
     #[test]
-    fn test_index() -> Result<()> {
+    fn test_is_distinct_from() -> Result<()> {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
         let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
         let mut s = kvengine.session()?;
-        s.execute("create table t (a int primary key, b text index, c float index, d bool);")?;
-        s.execute("insert into t values (1, 'a', 1.1, true);")?;
-        s.execute("insert into t values (2, 'b', 2.1, true);")?;
-        s.execute("insert into t values (3, 'a', 3.2, false);")?;
-        s.execute("insert into t values (4, 'c', 1.1, true);")?;
-        s.execute("insert into t values (5, 'd', 2.1, false);")?;
+        s.execute("create table t1 (a int primary key, b int index);")?;
+        s.execute("create table t2 (c int primary key, d int);")?;
+        s.execute("insert into t1 values (1, 1), (2, null), (3, 3);")?;
+        s.execute("insert into t2 values (1, 1), (2, null), (3, 4);")?;
 
-        s.execute("delete from t where a = 4;")?;
+        // = NULL 没有结果，也不会通过索引查找 NULL
+        let rows: Vec<i64> = s.query_as("select a from t1 where b = null;")?;
+        assert!(rows.is_empty());
+        let rows: Vec<i64> = s.query_as("select a from t1 where b is not distinct from null;")?;
+        assert_eq!(rows, vec![2]);
+        let rows: Vec<i64> = s.query_as("select a from t1 where b is distinct from null;")?;
+        assert_eq!(rows, vec![1, 3]);
+        let rows: Vec<i64> = s.query_as("select a from t1 where b is distinct from 1;")?;
+        assert_eq!(rows, vec![2, 3]);
+        match s.execute("explain select * from t1 where b is not distinct from null;")? {
+            ResultSet::Explain { plan } => assert!(plan.contains("Index Scan"), "{}", plan),
+            _ => unreachable!(),
+        }
 
-        match s.execute("select * from t where c = 1.1;")? {
-            ResultSet::Scan { columns, rows } => {
-                assert_eq!(columns.len(), 4);
-                assert_eq!(rows.len(), 1);
-            }
+        // 等值 join 时 NULL 不匹配，IS NOT DISTINCT FROM 时 NULL 和 NULL 匹配
+        let rows: Vec<(i64, i64)> = s.query_as("select a, c from t1 join t2 on b = d;")?;
+        assert_eq!(rows, vec![(1, 1)]);
+        let rows: Vec<(i64, i64)> =
+            s.query_as("select a, c from t1 join t2 on b is not distinct from d;")?;
+        assert_eq!(rows, vec![(1, 1), (2, 2)]);
+        let rows: Vec<(i64, i64)> =
+            s.query_as("select a, c from t1 join t2 on b is distinct from d;")?;
+        assert_eq!(rows.len(), 7);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_table_iter() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int);")?;
+        s.execute("insert into t values (1, 1), (2, 2), (3, 3), (4, 4);")?;
+
+        let txn = kvengine.begin()?;
+        let mut rows = txn.scan_table("t".into(), None)?;
+        assert_eq!(rows.next().transpose()?, Some(vec![Value::Integer(1), Value::Integer(1)]));
+        assert_eq!(rows.count(), 3);
+        let filter = Parser::new("select * from t where b > 2;").parse()?;
+        let filter = match filter {
+            ast::Statement::Select { where_clause, .. } => where_clause,
+            _ => unreachable!(),
+        };
+        let rows = txn.scan_table("t".into(), filter)?.collect::<Result<Vec<_>>>()?;
+        assert_eq!(rows.len(), 2);
+        txn.commit()?;
+
+        // 边扫描边修改时，只会处理扫描开始时已有的行
+        match s.execute("update t set a = a + 10;")? {
+            ResultSet::Update { count } => assert_eq!(count, 4),
             _ => unreachable!(),
         }
+        let rows: Vec<i64> = s.query_as("select a from t;")?;
+        assert_eq!(rows, vec![11, 12, 13, 14]);
+        match s.execute("delete from t where b < 3;")? {
+            ResultSet::Delete { count } => assert_eq!(count, 2),
+            _ => unreachable!(),
+        }
+
+        // 存储引擎分批扫描，修改主键之后的行可能落在还没有扫描的批次中
+        let values = (0..1000).map(|i| format!("({}, {})", i, i)).collect::<Vec<_>>().join(", ");
+        s.execute("create table t2 (a int primary key, b int);")?;
+        s.execute("create table t3 (a int primary key, b int) with (layout = 'columnar');")?;
+        for table in ["t2", "t3"] {
+            s.execute(&format!("insert into {} values {};", table, values))?;
+            for sql in ["update {} set a = a + 1000;", "update {} set b = b + 1;"] {
+                match s.execute(&sql.replace("{}", table))? {
+                    ResultSet::Update { count } => assert_eq!(count, 1000),
+                    _ => unreachable!(),
+                }
+            }
+            let rows: Vec<(i64, i64)> = s.query_as(&format!("select * from {};", table))?;
+            assert_eq!(rows, (0..1000).map(|i| (i + 1000, i + 1)).collect::<Vec<_>>());
+            match s.execute(&format!("delete from {};", table))? {
+                ResultSet::Delete { count } => assert_eq!(count, 1000),
+                _ => unreachable!(),
+            }
+        }
 
         std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
     #[test]
-    fn test_primary_key_scan() -> Result<()> {
+    fn test_scan_columns() -> Result<()> {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
         let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
         let mut s = kvengine.session()?;
-        s.execute("create table t (a int primary key, b text index, c float index, d bool);")?;
-        s.execute("insert into t values (1, 'a', 1.1, true);")?;
-        s.execute("insert into t values (2, 'b', 2.1, true);")?;
-        s.execute("insert into t values (3, 'a', 3.2, false);")?;
+        s.execute("create table t (a int primary key, b int, c varchar, d float);")?;
+        s.execute("insert into t values (1, 10, 'x', 1.5), (2, 20, 'y', 2.5), (3, 10, 'z', 3.5);")?;
 
-        match s.execute("select * from t where a = 2;")? {
-            ResultSet::Scan { columns, rows } => {
-                assert_eq!(columns.len(), 4);
-                assert_eq!(rows.len(), 1);
+        // 只解码查询用到的列
+        match s.execute("explain select c from t where b = 10 order by a;")? {
+            ResultSet::Explain { plan } => {
+                assert!(plan.contains("Seq Scan On t [a, b, c] (b = 10)"), "{}", plan)
             }
             _ => unreachable!(),
         }
+        match s.execute("explain select * from t where b = 10;")? {
+            ResultSet::Explain { plan } => assert!(!plan.contains('['), "{}", plan),
+            _ => unreachable!(),
+        }
+
+        let rows: Vec<String> = s.query_as("select c from t where b = 10 order by a desc;")?;
+        assert_eq!(rows, vec!["z", "x"]);
+        let rows: Vec<(i64, f64)> = s.query_as("select t.b, d + 1 as d from t where a = 2;")?;
+        assert_eq!(rows, vec![(20, 3.5)]);
+        let mut rows: Vec<(i64, i64)> = s.query_as("select b, count(c) from t group by b;")?;
+        rows.sort();
+        assert_eq!(rows, vec![(10, 2), (20, 1)]);
+        let rows: Vec<i64> = s.query_as("select 1 from t;")?;
+        assert_eq!(rows, vec![1, 1, 1]);
+        assert!(matches!(
+            s.execute("select e from t;"),
+            Err(Error::NotFound(_))
+        ));
 
         std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
     #[test]
-    fn test_hash_join() -> Result<()> {
+    fn test_columnar_table() -> Result<()> {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
         let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
         let mut s = kvengine.session()?;
-        s.execute("create table t1 (a int primary key);")?;
-        s.execute("create table t2 (b int primary key);")?;
-        s.execute("create table t3 (c int primary key);")?;
+        s.execute(
+            "create table t (a varchar primary key, b int index, c float default 1.5)
+                with (layout = 'columnar');",
+        )?;
+        assert!(s.get_table("t".into())?.ends_with("WITH (layout = 'columnar')"));
+        s.execute("insert into t values ('x', 1, 0.5), ('y', 2, null), ('z', 1, 2.5);")?;
+        s.execute("insert into t (a, b) values ('w', 3);")?;
+        assert!(matches!(
+            s.execute("insert into t values ('x', 9, 9.0);"),
+            Err(Error::Duplicate(_))
+        ));
 
-        s.execute("insert into t1 values (1), (2), (3);")?;
-        s.execute("insert into t2 values (2), (3), (4);")?;
-        s.execute("insert into t3 values (3), (8), (9);")?;
+        scan_table_and_compare(
+            &mut s,
+            "t",
+            vec![
+                vec![Value::String("w".into()), Value::Integer(3), Value::Float(1.5)],
+                vec![Value::String("x".into()), Value::Integer(1), Value::Float(0.5)],
+                vec![Value::String("y".into()), Value::Integer(2), Value::Null],
+                vec![Value::String("z".into()), Value::Integer(1), Value::Float(2.5)],
+            ],
+        )?;
+        let mut rows: Vec<(i64, i64)> = s.query_as("select b, count(a) from t group by b;")?;
+        rows.sort();
+        assert_eq!(rows, vec![(1, 2), (2, 1), (3, 1)]);
+        let rows: Vec<String> = s.query_as("select a from t where b = 1;")?;
+        assert_eq!(rows, vec!["x", "z"]);
+        let rows: Vec<f64> = s.query_as("select c from t where a = 'z';")?;
+        assert_eq!(rows, vec![2.5]);
+        let rows: Vec<i64> = s.query_as("select b from t where a like 'y%';")?;
+        assert_eq!(rows, vec![2]);
 
-        match s.execute("select * from t1 join t2 on a = b join t3 on a = c;")? {
-            ResultSet::Scan { columns, rows } => {
-                assert_eq!(columns.len(), 3);
-                assert_eq!(rows.len(), 1);
+        // 更新、删除、修改主键
+        s.execute("update t set c = 3.5 where b = 2;")?;
+        s.execute("update t set a = 'v' where a = 'w';")?;
+        s.execute("delete from t where a = 'x';")?;
+        let rows: Vec<(String, i64, Option<f64>)> = s.query_as("select * from t;")?;
+        assert_eq!(
+            rows,
+            vec![
+                ("v".into(), 3, Some(1.5)),
+                ("y".into(), 2, Some(3.5)),
+                ("z".into(), 1, Some(2.5))
+            ]
+        );
+        s.execute("alter table t alter column c type int;")?;
+        let rows: Vec<i64> = s.query_as("select c from t where b = 1;")?;
+        assert_eq!(rows, vec![3]);
+
+        assert!(matches!(
+            s.execute("create table t2 (a int primary key) with (layout = 'x');"),
+            Err(Error::Parse(_))
+        ));
+        s.execute("drop table t;")?;
+        s.execute("create table t (a int primary key);")?;
+        scan_table_and_compare(&mut s, "t", vec![])?;
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_like_prefix_scan() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a varchar primary key, b varchar index, c varchar);")?;
+        s.execute("create table t2 (a int primary key, b int index);")?;
+        s.execute("insert into t2 values (1, 10);")?;
+        s.execute(
+            "insert into t1 values ('aa', 'xy1', 'm'), ('ab', 'xz', 'n'), ('abc', 'xy2', null),
+                ('b', 'y', 'mn'), ('a_c', 'x%', 'o');",
+        )?;
+
+        let explain = |s: &mut Session<_>, sql: &str| match s.execute(sql) {
+            Ok(ResultSet::Explain { plan }) => plan,
+            _ => unreachable!(),
+        };
+        let plan = explain(&mut s, "explain select * from t1 where a like 'ab%';");
+        assert!(plan.contains("Primary Key Prefix Scan On t1('ab%')"), "{}", plan);
+        assert!(!plan.contains("Filter"), "{}", plan);
+        let plan = explain(&mut s, "explain select * from t1 where b like 'xy_';");
+        assert!(plan.contains("Index Prefix Scan On t1.b('xy%')"), "{}", plan);
+        assert!(plan.contains("Filter (b LIKE 'xy_')"), "{}", plan);
+        // 没有常量前缀、不是索引列、不是字符串类型时全表扫描
+        for sql in [
+            "explain select * from t1 where a like '%b';",
+            "explain select * from t1 where c like 'm%';",
+            "explain select * from t2 where b like '1%';",
+        ] {
+            let plan = explain(&mut s, sql);
+            assert!(plan.contains("Seq Scan"), "{}", plan);
+        }
+
+        let rows: Vec<String> = s.query_as("select a from t1 where a like 'ab%';")?;
+        assert_eq!(rows, vec!["ab", "abc"]);
+        let rows: Vec<String> = s.query_as("select a from t1 where a like 'a_';")?;
+        assert_eq!(rows, vec!["aa", "ab"]);
+        let rows: Vec<String> = s.query_as("select a from t1 where b like 'xy%';")?;
+        assert_eq!(rows, vec!["aa", "abc"]);
+        let rows: Vec<String> = s.query_as("select a from t1 where b like 'x_';")?;
+        assert_eq!(rows, vec!["a_c", "ab"]);
+        let rows: Vec<String> = s.query_as("select a from t1 where c like '%n';")?;
+        assert_eq!(rows, vec!["ab", "b"]);
+        let rows: Vec<String> = s.query_as("select a from t1 where a like 'abc';")?;
+        assert_eq!(rows, vec!["abc"]);
+        assert!(matches!(
+            s.execute("select * from t2 where b like '1%';"),
+            Err(Error::TypeMismatch(_))
+        ));
+
+        // 事务中未提交的修改也能通过前缀扫描看到
+        s.execute("begin;")?;
+        s.execute("insert into t1 values ('abd', 'xy3', null);")?;
+        s.execute("delete from t1 where a = 'aa';")?;
+        let rows: Vec<String> = s.query_as("select a from t1 where b like 'xy%';")?;
+        assert_eq!(rows, vec!["abc", "abd"]);
+        s.execute("rollback;")?;
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_null_functions() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int, c text);")?;
+        s.execute("insert into t values (1, 1, 'x'), (2, null, null), (3, 3, '');")?;
+
+        let rows: Vec<(i64, i64)> = s.query_as("select a, coalesce(b, c, 0) from t;")?;
+        assert_eq!(rows, vec![(1, 1), (2, 0), (3, 3)]);
+        let rows: Vec<(i64, Option<String>)> =
+            s.query_as("select a, nullif(c, '') as c from t;")?;
+        assert_eq!(rows, vec![(1, Some("x".into())), (2, None), (3, None)]);
+        match s.execute("select ifnull(c, 'none') from t where ifnull(b, 0) = 0;")? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(names(&columns), vec!["ifnull(c, 'none')"]);
+                assert_eq!(rows, vec![vec![Value::String("none".into())]]);
+            }
+            _ => unreachable!(),
+        }
+
+        let rows: Vec<(i64, i64, i64)> =
+            s.query_as("select a, greatest(a, b, 2), least(b, 3) from t where a < 3;")?;
+        assert_eq!(rows, vec![(1, 2, 1), (2, 2, 3)]);
+
+        // insert、update 中也可以使用
+        s.execute("insert into t values (4, coalesce(null, 4), ifnull(null, 'y'));")?;
+        s.execute("update t set b = ifnull(b, 2) where a = 2;")?;
+        let rows: Vec<(i64, i64)> = s.query_as("select a, b from t;")?;
+        assert_eq!(rows, vec![(1, 1), (2, 2), (3, 3), (4, 4)]);
+
+        assert!(matches!(
+            s.execute("select nullif(b) from t;"),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            s.execute("update t set b = d where a = 1;"),
+            Err(Error::NotFound(_))
+        ));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_datetime_functions() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, ts text);")?;
+        s.execute("insert into t values (1, '2024-01-20 08:00:00'), (2, '2024-01-28 12:30:00');")?;
+        s.execute("insert into t values (3, '2024-02-03 23:59:59'), (4, null);")?;
+
+        // 按时间过滤
+        let rows: Vec<i64> =
+            s.query_as("select a from t where ts > date_sub('2024-02-04', 7, 'day');")?;
+        assert_eq!(rows, vec![2, 3]);
+
+        let rows: Vec<(i64, Option<i64>, Option<String>)> = s.query_as(
+            "select a, extract(month from ts), date_format(ts, '%H:%M') as hm from t;",
+        )?;
+        assert_eq!(rows[0], (1, Some(1), Some("08:00".into())));
+        assert_eq!(rows[3], (4, None, None));
+
+        // 按月分组
+        let rows: Vec<(String, i64)> = s.query_as(
+            "select m, count(a) from (select a, date_trunc('month', ts) as m from t where a < 4) x group by m order by m;",
+        )?;
+        assert_eq!(
+            rows,
+            vec![
+                ("2024-01-01 00:00:00".into(), 2),
+                ("2024-02-01 00:00:00".into(), 1)
+            ]
+        );
+
+        match s.execute("select current_date, now() from t where a = 1;")? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(names(&columns), vec!["current_date()", "now()"]);
+                assert_eq!(rows.len(), 1);
+            }
+            _ => unreachable!(),
+        }
+        assert!(matches!(
+            s.execute("select date_add(ts, 1, 'fortnight') from t;"),
+            Err(Error::InvalidInput(_))
+        ));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_interval() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, ts text index, d interval);")?;
+        s.execute("insert into t values (1, '2024-01-31 10:00:00', '1 month');")?;
+        s.execute("insert into t values (2, '2024-02-01 10:00:00', interval '2 hours');")?;
+        s.execute("insert into t values (3, '2024-02-10', interval 3 day);")?;
+
+        let rows: Vec<(i64, String, String)> = s.query_as("select a, ts + d, d from t;")?;
+        assert_eq!(
+            rows,
+            vec![
+                (1, "2024-02-29 10:00:00".into(), "1 month".into()),
+                (2, "2024-02-01 12:00:00".into(), "02:00:00".into()),
+                (3, "2024-02-13".into(), "3 days".into()),
+            ]
+        );
+
+        // where 条件中的日期运算
+        let rows: Vec<i64> =
+            s.query_as("select a from t where ts - interval '1 day' > '2024-01-31 12:00:00';")?;
+        assert_eq!(rows, vec![3]);
+        let rows: Vec<i64> = s.query_as("select a from t where d > interval '1 day';")?;
+        assert_eq!(rows, vec![1, 3]);
+        let rows: Vec<String> =
+            s.query_as("select ts - '2024-01-01' from t where a = 3;")?;
+        assert_eq!(rows, vec!["40 days".to_string()]);
+
+        // 常量在解析时计算，等值条件依然可以走索引
+        let sql = "select a from t where ts = '2024-02-01' + interval '10:00';";
+        match s.execute(&format!("explain {}", sql))? {
+            ResultSet::Explain { plan } => assert!(plan.contains("Index Only Scan"), "{}", plan),
+            _ => unreachable!(),
+        }
+        let rows: Vec<i64> = s.query_as(sql)?;
+        assert_eq!(rows, vec![2]);
+
+        s.execute("update t set d = d + interval '1 day' where a = 2;")?;
+        let rows: Vec<String> = s.query_as("select d from t where a = 2;")?;
+        assert_eq!(rows, vec!["1 day 02:00:00".to_string()]);
+
+        assert!(matches!(
+            s.execute("insert into t values (4, null, '1 fortnight');"),
+            Err(Error::TypeMismatch(_))
+        ));
+        assert!(matches!(
+            s.execute("select d + 1 from t;"),
+            Err(Error::TypeMismatch(_))
+        ));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text index, c float index, d bool);")?;
+        s.execute("insert into t values (1, 'a', 1.1, true);")?;
+        s.execute("insert into t values (2, 'b', 2.1, true);")?;
+        s.execute("insert into t values (3, 'a', 3.2, false);")?;
+        s.execute("insert into t values (4, 'c', 1.1, true);")?;
+        s.execute("insert into t values (5, 'd', 2.1, false);")?;
+
+        s.execute("delete from t where a = 4;")?;
+
+        match s.execute("select * from t where c = 1.1;")? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(columns.len(), 4);
+                assert_eq!(rows.len(), 1);
+            }
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_primary_key_scan() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text index, c float index, d bool);")?;
+        s.execute("insert into t values (1, 'a', 1.1, true);")?;
+        s.execute("insert into t values (2, 'b', 2.1, true);")?;
+        s.execute("insert into t values (3, 'a', 3.2, false);")?;
+
+        match s.execute("select * from t where a = 2;")? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(columns.len(), 4);
+                assert_eq!(rows.len(), 1);
+            }
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_primary_key_order() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key);")?;
+        s.execute("create table t2 (a float primary key);")?;
+        let mut txn = kvengine.begin()?;
+        for i in [3, -1, 0, -100, 42, -2] {
+            txn.create_row("t1".into(), vec![Value::Integer(i)])?;
+        }
+        for f in [1.5, -0.5, 0.0, -20.25, 3.0] {
+            txn.create_row("t2".into(), vec![Value::Float(f)])?;
+        }
+        txn.commit()?;
+
+        // 按照主键的编码顺序扫描，负数排在正数之前
+        let rows: Vec<i64> = s.query_as("select a from t1;")?;
+        assert_eq!(rows, vec![-100, -2, -1, 0, 3, 42]);
+        let rows: Vec<f64> = s.query_as("select a from t2;")?;
+        assert_eq!(rows, vec![-20.25, -0.5, 0.0, 1.5, 3.0]);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_version() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text);")?;
+
+        // 新建的数据库保存当前的版本号
+        let format_key = super::Key::Format.encode()?;
+        let txn = kvengine.kv.begin()?;
+        let version = txn.get(format_key.clone())?.unwrap();
+        assert_eq!(bincode::deserialize::<u32>(&version)?, super::FORMAT_VERSION);
+        txn.rollback()?;
+        drop((txn, s, kvengine));
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        // 加入版本号之前的代码写入的数据：create table t (a int primary key, b float index, c text)
+        // 以及 (-2, -1.5, 'x'), (1, 2.5, 'y'), (3, -1.5, 'z') 三行
+        let legacy: [(&[u8], &[u8]); 6] = [
+            (
+                &[0, 116, 0, 0],
+                &[
+                    1, 0, 0, 0, 0, 0, 0, 0, 116, 3, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0,
+                    97, 1, 0, 0, 0, 0, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 98, 2, 0, 0, 0, 1, 1, 0, 0,
+                    0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 99, 3, 0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0,
+                ],
+            ),
+            (
+                &[1, 116, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 1],
+                &[
+                    3, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0,
+                    0, 0, 0, 0, 4, 64, 4, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 121,
+                ],
+            ),
+            (
+                &[1, 116, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 3],
+                &[
+                    3, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0,
+                    0, 0, 0, 0, 248, 191, 4, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 122,
+                ],
+            ),
+            (
+                &[1, 116, 0, 0, 2, 255, 255, 255, 255, 255, 255, 255, 254],
+                &[
+                    3, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 254, 255, 255, 255, 255, 255, 255, 255, 3,
+                    0, 0, 0, 0, 0, 0, 0, 0, 0, 248, 191, 4, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 120,
+                ],
+            ),
+            (
+                &[2, 116, 0, 0, 98, 0, 0, 3, 64, 4, 0, 0, 0, 0, 0, 0],
+                &[1, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0],
+            ),
+            (
+                &[2, 116, 0, 0, 98, 0, 0, 3, 191, 248, 0, 0, 0, 0, 0, 0],
+                &[
+                    2, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 254,
+                    255, 255, 255, 255, 255, 255, 255,
+                ],
+            ),
+        ];
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let txn = kvengine.kv.begin()?;
+        for (key, value) in legacy {
+            txn.set(key.to_vec(), value.to_vec())?;
+        }
+        txn.commit()?;
+        drop((txn, kvengine));
+
+        // 重新打开时自动升级，主键按照保序编码重新写入，负数排在前面
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        let rows: Vec<(i64, f64, String)> = s.query_as("select * from t;")?;
+        assert_eq!(
+            rows,
+            vec![(-2, -1.5, "x".into()), (1, 2.5, "y".into()), (3, -1.5, "z".into())]
+        );
+        let rows: Vec<i64> = s.query_as("select a from t where a < 2;")?;
+        assert_eq!(rows, vec![-2, 1]);
+        s.execute("insert into t values (0, 2.5, 'w');")?;
+        let rows: Vec<i64> = s.query_as("select a from t;")?;
+        assert_eq!(rows, vec![-2, 0, 1, 3]);
+        let mut rows: Vec<i64> = s.query_as("select a from t where b = 2.5;")?;
+        rows.sort();
+        assert_eq!(rows, vec![0, 1]);
+
+        // 旧格式的 key 已经删除
+        let txn = kvengine.kv.begin()?;
+        assert_eq!(txn.scan_prefix(super::KeyPrefix::Row("t".into()).encode()?)?.len(), 4);
+        let index = super::KeyPrefix::Index("t".into(), "b".into()).encode()?;
+        assert_eq!(txn.scan_prefix(index)?.len(), 2);
+        let index = super::Key::Index("t".into(), "b".into(), Value::Float(-1.5)).encode()?;
+        let pks: HashSet<Value> = bincode::deserialize(&txn.get(index)?.unwrap())?;
+        assert_eq!(pks, HashSet::from([Value::Integer(-2), Value::Integer(3)]));
+        for (key, _) in legacy {
+            if key[0] != 0 {
+                assert_eq!(txn.get(key.to_vec())?, None);
+            }
+        }
+        let version = txn.get(format_key.clone())?.unwrap();
+        assert_eq!(bincode::deserialize::<u32>(&version)?, super::FORMAT_VERSION);
+
+        // 比当前版本新的数据库无法打开
+        txn.set(format_key, bincode::serialize(&(super::FORMAT_VERSION + 1))?)?;
+        txn.commit()?;
+        drop((txn, s, kvengine));
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        assert!(matches!(kvengine.begin(), Err(Error::InvalidState(_))));
+        assert!(kvengine.session()?.execute("select * from t;").is_err());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_join() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key);")?;
+        s.execute("create table t2 (b int primary key);")?;
+        s.execute("create table t3 (c int primary key);")?;
+
+        s.execute("insert into t1 values (1), (2), (3);")?;
+        s.execute("insert into t2 values (2), (3), (4);")?;
+        s.execute("insert into t3 values (3), (8), (9);")?;
+
+        match s.execute("select * from t1 join t2 on a = b join t3 on a = c;")? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(columns.len(), 3);
+                assert_eq!(rows.len(), 1);
+            }
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_limit() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text);")?;
+        s.execute("create table t2 (c int primary key);")?;
+        for i in 0..100 {
+            s.execute(&format!("insert into t1 values ({}, 'value-{}');", i, i % 10))?;
+            s.execute(&format!("insert into t2 values ({});", i))?;
+        }
+
+        let queries = [
+            "select * from t1 order by b desc;",
+            "select * from t1 join t2 on a = c;",
+            "select b, count(a) from t1 group by b;",
+        ];
+        s.execute("set memory_limit = 1024;")?;
+        let err = s.execute(queries[2]).unwrap_err();
+        assert!(matches!(err, Error::OutOfMemory(_)), "{}: {}", queries[2], err);
+        assert_eq!(err.sqlstate(), Some("53200"));
+        // 排序超过限制时写入临时文件再归并，结果和内存中排序相同
+        let rows: Vec<(i64, String)> = s.query_as(queries[0])?;
+        assert_eq!(rows.len(), 100);
+        assert_eq!(rows[0], (9, "value-9".into()));
+        assert_eq!(rows[99], (90, "value-0".into()));
+        // 没有 group by 的聚集同样在读取时统计缓存的行
+        assert!(matches!(s.execute("select count(a) from t1;"), Err(Error::OutOfMemory(_))));
+        // 不缓存数据的查询不受影响
+        assert!(s.execute("select * from t1 where a > 10;").is_ok());
+
+        // 限制足够大时可以执行，统计的是单个查询的使用量
+        s.execute("set memory_limit = 1048576;")?;
+        let mut txn = kvengine.begin()?;
+        txn.set_memory_limit(Some(1 << 20));
+        for sql in queries {
+            assert!(s.execute(sql).is_ok());
+            let stmt = Parser::new(sql).parse()?;
+            Plan::build(stmt, &mut txn)?.execute(&mut txn)?;
+            assert!(txn.memory().peak() > 0);
+            assert_eq!(txn.memory().used(), 0);
+        }
+        // 分组时复制出来的 key 同样计入，比不分组时多占用分组 key 的内存
+        let mut peaks = Vec::new();
+        for sql in ["select count(a) from t1;", "select b, count(a) from t1 group by b;"] {
+            Plan::build(Parser::new(sql).parse()?, &mut txn)?.execute(&mut txn)?;
+            peaks.push(txn.memory().peak());
+        }
+        assert!(peaks[1] > peaks[0], "{:?}", peaks);
+        txn.commit()?;
+
+        s.execute("set memory_limit = 0;")?;
+        assert!(s.execute(queries[0]).is_ok());
+        assert!(matches!(
+            s.execute("set memory_limit = 'abc';"),
+            Err(Error::InvalidInput(_))
+        ));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_grace_hash_join() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text);")?;
+        s.execute("create table t2 (c int primary key, d int);")?;
+        for i in 0..200 {
+            s.execute(&format!("insert into t1 values ({}, 'value-{}');", i, i))?;
+            if i % 2 == 0 {
+                s.execute(&format!("insert into t2 values ({}, 1);", i))?;
+            }
+        }
+        s.execute("insert into t2 values (1000, null);")?;
+
+        let query = |s: &mut Session<_>, sql: &str| -> Result<Vec<Row>> {
+            match s.execute(sql)? {
+                ResultSet::Scan { mut rows, .. } => {
+                    rows.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                    Ok(rows)
+                }
+                _ => unreachable!(),
+            }
+        };
+        let sqls = [
+            "select * from t1 join t2 on a = c;",
+            "select * from t1 left join t2 on a = c;",
+            "select * from t2 join t1 on c = a;",
+        ];
+        let expected = sqls
+            .iter()
+            .map(|sql| query(&mut s, sql))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(expected[0].len(), 100);
+        assert_eq!(expected[1].len(), 200);
+
+        // 右边的数据超过内存限制时，分区写入临时文件后 join，结果不变
+        s.execute("set memory_limit = 2048;")?;
+        for (sql, rows) in sqls.iter().zip(expected) {
+            assert_eq!(query(&mut s, sql)?, rows, "{}", sql);
+        }
+
+        // 大量行 join 列的值相同时，单个分区依然放不下内存
+        assert!(matches!(
+            s.execute("select * from t1 join t2 on a = d;"),
+            Err(Error::OutOfMemory(_))
+        ));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_type_coercion() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b float default 1, c int);")?;
+
+        // 整数写入浮点列、整数值的浮点写入整数列
+        s.execute("insert into t values (1, 2, 3.0);")?;
+        s.execute("insert into t (a, c) values (2.0, 4);")?;
+        assert!(s.execute("insert into t values (3, 1.5, 2.5);").is_err());
+        assert!(s.execute("insert into t values (3, true, 1);").is_err());
+        scan_table_and_compare(
+            &mut s,
+            "t",
+            vec![
+                vec![Value::Integer(1), Value::Float(2.0), Value::Integer(3)],
+                vec![Value::Integer(2), Value::Float(1.0), Value::Integer(4)],
+            ],
+        )?;
+
+        // update 同样遵循转换规则
+        s.execute("update t set b = 5 where a = 1;")?;
+        assert!(s.execute("update t set c = 0.5 where a = 1;").is_err());
+
+        // 主键查找使用相同的规则
+        match s.execute("select * from t where a = 2.0;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 1),
+            _ => unreachable!(),
+        }
+        match s.execute("select * from t where a = 2.5;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 0),
+            _ => unreachable!(),
+        }
+
+        // strict 模式下拒绝隐式转换
+        s.execute("set strict_types = on;")?;
+        assert!(s.execute("insert into t values (3, 1, 1);").is_err());
+        assert!(s.execute("insert into t values (3, 1.0, 1.0);").is_err());
+        s.execute("insert into t values (3, 1.0, 1);")?;
+        s.execute("begin;")?;
+        assert!(s.execute("update t set b = 7 where a = 3;").is_err());
+        s.execute("set strict_types = off;")?;
+        s.execute("update t set b = 7 where a = 3;")?;
+        s.execute("commit;")?;
+        scan_table_and_compare(
+            &mut s,
+            "t",
+            vec![
+                vec![Value::Integer(1), Value::Float(5.0), Value::Integer(3)],
+                vec![Value::Integer(2), Value::Float(1.0), Value::Integer(4)],
+                vec![Value::Integer(3), Value::Float(7.0), Value::Integer(1)],
+            ],
+        )?;
+        assert!(s.execute("set strict_types = 'yes';").is_err());
+        assert!(s.execute("set unknown_var = on;").is_err());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_alter_column() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a float primary key, b int index, c string, d int default 1);")?;
+        s.execute("insert into t values (1.4, 1, '10', 2), (2.4, 2, '20', 3);")?;
+
+        // 修改默认值，只影响之后写入的数据
+        s.execute("alter table t alter column d set default 5;")?;
+        s.execute("insert into t (a, b, c) values (3.0, 1, '30');")?;
+        assert!(s.execute("alter table t alter column d set default 'x';").is_err());
+        s.execute("alter table t alter d drop default;")?;
+        s.execute("insert into t (a, b, c) values (4.0, 2, '40');")?;
+        scan_table_and_compare(
+            &mut s,
+            "t",
+            vec![
+                vec![Value::Float(1.4), Value::Integer(1), Value::String("10".into()), Value::Integer(2)],
+                vec![Value::Float(2.4), Value::Integer(2), Value::String("20".into()), Value::Integer(3)],
+                vec![Value::Float(3.0), Value::Integer(1), Value::String("30".into()), Value::Integer(5)],
+                vec![Value::Float(4.0), Value::Integer(2), Value::String("40".into()), Value::Null],
+            ],
+        )?;
+
+        // 修改普通列、主键列和索引列的类型，已有的数据会被转换
+        s.execute("alter table t alter column c type int;")?;
+        s.execute("alter table t alter column a type int;")?;
+        s.execute("alter table t alter column b set data type string;")?;
+        scan_table_and_compare(
+            &mut s,
+            "t",
+            vec![
+                vec![Value::Integer(1), Value::String("1".into()), Value::Integer(10), Value::Integer(2)],
+                vec![Value::Integer(2), Value::String("2".into()), Value::Integer(20), Value::Integer(3)],
+                vec![Value::Integer(3), Value::String("1".into()), Value::Integer(30), Value::Integer(5)],
+                vec![Value::Integer(4), Value::String("2".into()), Value::Integer(40), Value::Null],
+            ],
+        )?;
+        match s.execute("select * from t where b = '2';")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 2),
+            _ => unreachable!(),
+        }
+        match s.execute("select * from t where a = 4;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 1),
+            _ => unreachable!(),
+        }
+
+        // 转换失败或者主键冲突时整个语句回滚，表结构不变
+        s.execute("insert into t values (5, 'x', 50, 1);")?;
+        assert!(s.execute("alter table t alter column b type int;").is_err());
+        assert!(s.get_table("t".into())?.contains("b String"));
+        s.execute("alter table t alter column a type string;")?;
+        s.execute("insert into t values ('1.0', '1', 60, 1);")?;
+        assert!(s.execute("alter table t alter column a type float;").is_err());
+        assert!(s.get_table("t".into())?.contains("a String"));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_alter_table_columns() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        for layout in ["row", "columnar"] {
+            s.execute(&format!(
+                "create table t (a int primary key, b int index, c string) with (layout = '{}');",
+                layout
+            ))?;
+            s.execute("insert into t values (1, 10, 'x'), (2, 20, 'y');")?;
+
+            // 新增的列使用默认值填充已有的行
+            s.execute("alter table t add column d int default 5 index;")?;
+            s.execute("alter table t add e string;")?;
+            s.execute("insert into t values (3, 30, 'z', 6, 'w');")?;
+            let rows: Vec<(i64, i64, String, i64, Option<String>)> =
+                s.query_as("select * from t;")?;
+            assert_eq!(
+                rows,
+                vec![
+                    (1, 10, "x".into(), 5, None),
+                    (2, 20, "y".into(), 5, None),
+                    (3, 30, "z".into(), 6, Some("w".into())),
+                ]
+            );
+            let rows: Vec<(i64,)> = s.query_as("select a from t where d = 5;")?;
+            assert_eq!(rows, vec![(1,), (2,)]);
+            assert!(s.execute("alter table t add column d int;").is_err());
+            assert!(s.execute("alter table t add column f int not null;").is_err());
+            assert!(s.execute("alter table t add column f int primary key;").is_err());
+
+            // 删除索引列时同时删除索引数据
+            s.execute("alter table t drop column b;")?;
+            let txn = kvengine.kv.begin()?;
+            let prefix = super::KeyPrefix::Index("t".into(), "b".into()).encode()?;
+            assert!(txn.scan_prefix(prefix)?.is_empty());
+            txn.commit()?;
+            let rows: Vec<(i64, String, i64)> = s.query_as("select a, c, d from t;")?;
+            assert_eq!(rows, vec![(1, "x".into(), 5), (2, "y".into(), 5), (3, "z".into(), 6)]);
+            assert!(s.execute("alter table t drop column a;").is_err());
+            assert!(s.execute("alter table t drop column b;").is_err());
+
+            // 重命名的索引列依然可以走索引查询
+            s.execute("alter table t rename column d to k;")?;
+            s.execute("alter table t rename c to name;")?;
+            let rows: Vec<(i64, String)> = s.query_as("select a, name from t where k = 6;")?;
+            assert_eq!(rows, vec![(3, "z".into())]);
+            assert!(s.execute("alter table t rename column k to a;").is_err());
+            assert!(s.execute("select d from t;").is_err());
+            s.execute("drop table t;")?;
+        }
+
+        // 没有主键的表，新增的列放在隐藏的 _rowid 列之前
+        s.execute("create table t2 (a int);")?;
+        s.execute("insert into t2 values (1), (2);")?;
+        s.execute("alter table t2 add column b int default 7;")?;
+        let rows: Vec<(i64, i64)> = s.query_as("select * from t2;")?;
+        assert_eq!(rows, vec![(1, 7), (2, 7)]);
+        s.execute("alter table t2 drop column a;")?;
+        let rows: Vec<(i64,)> = s.query_as("select * from t2;")?;
+        assert_eq!(rows, vec![(7,), (7,)]);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_reindex() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int index, c int index);")?;
+        s.execute("insert into t values (1, 10, 100), (2, 10, 200), (3, 20, 300);")?;
+
+        // 绕过索引维护，模拟索引数据和表数据不一致
+        let txn = kvengine.begin()?;
+        txn.save_index("t", "b", &Value::Integer(10), [Value::Integer(1)].into())?;
+        txn.save_index("t", "b", &Value::Integer(30), [Value::Integer(3)].into())?;
+        txn.save_index("t", "c", &Value::Integer(100), Default::default())?;
+        txn.commit()?;
+        let count = |s: &mut Session<_>, sql: &str| match s.execute(sql) {
+            Ok(ResultSet::Scan { rows, .. }) => rows.len(),
+            _ => unreachable!(),
+        };
+        assert_eq!(count(&mut s, "select * from t where b = 10;"), 1);
+        assert_eq!(count(&mut s, "select * from t where b = 30;"), 1);
+
+        // 只重建 b 列的索引
+        assert_eq!(
+            s.execute("reindex index b on t;")?,
+            ResultSet::Reindex { table_name: "t".into(), count: 3 }
+        );
+        assert_eq!(count(&mut s, "select * from t where b = 10;"), 2);
+        assert_eq!(count(&mut s, "select * from t where b = 30;"), 0);
+        assert_eq!(count(&mut s, "select * from t where c = 100;"), 0);
+
+        // 重建整张表的索引
+        assert_eq!(
+            s.execute("reindex table t;")?,
+            ResultSet::Reindex { table_name: "t".into(), count: 6 }
+        );
+        assert_eq!(count(&mut s, "select * from t where c = 100;"), 1);
+
+        assert!(s.execute("reindex index a on t;").is_err());
+        assert!(s.execute("reindex table t2;").is_err());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncate_table() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int index, c text);")?;
+        s.execute("insert into t values (1, 10, 'x'), (2, 10, 'y'), (3, 20, 'z');")?;
+        s.execute(
+            "create table t2 (a int primary key, b int) with (layout = 'columnar');",
+        )?;
+        s.execute("insert into t2 values (1, 1), (2, 2);")?;
+        let count = |s: &mut Session<_>, sql: &str| match s.execute(sql) {
+            Ok(ResultSet::Scan { rows, .. }) => rows.len(),
+            _ => unreachable!(),
+        };
+
+        // 回滚之后数据恢复
+        s.execute("begin;")?;
+        assert_eq!(
+            s.execute("truncate table t;")?,
+            ResultSet::TruncateTable { table_name: "t".into(), count: 3 }
+        );
+        assert_eq!(count(&mut s, "select * from t;"), 0);
+        s.execute("rollback;")?;
+        assert_eq!(count(&mut s, "select * from t where b = 10;"), 2);
+
+        // 数据和索引一起清空，表结构保留
+        assert_eq!(
+            s.execute("truncate t;")?,
+            ResultSet::TruncateTable { table_name: "t".into(), count: 3 }
+        );
+        assert_eq!(count(&mut s, "select * from t;"), 0);
+        let txn = kvengine.begin()?;
+        assert!(txn.load_index("t", "b", &Value::Integer(10))?.is_empty());
+        txn.commit()?;
+        s.execute("insert into t values (1, 30, 'x');")?;
+        assert_eq!(count(&mut s, "select * from t where b = 30;"), 1);
+        assert_eq!(count(&mut s, "select * from t where b = 10;"), 0);
+
+        assert_eq!(
+            s.execute("truncate table t2;")?,
+            ResultSet::TruncateTable { table_name: "t2".into(), count: 2 }
+        );
+        assert_eq!(count(&mut s, "select * from t2;"), 0);
+
+        // 删除表之后再创建同名的表，不会读到之前的数据
+        s.execute("drop table t;")?;
+        s.execute("create table t (a int primary key, b int index, c text);")?;
+        assert_eq!(count(&mut s, "select * from t;"), 0);
+        assert_eq!(count(&mut s, "select * from t where b = 30;"), 0);
+
+        assert!(s.execute("truncate table t3;").is_err());
+        let mut txn = kvengine.begin()?;
+        assert!(matches!(
+            txn.truncate_table("sys.storage".into()),
+            Err(Error::ReadOnly(_))
+        ));
+        txn.rollback()?;
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_vacuum() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int index);")?;
+        s.execute("insert into t values (1, 1), (2, 2), (3, 3);")?;
+        s.execute("update t set b = 10 where a = 1;")?;
+        s.execute("delete from t where a = 2;")?;
+        let size = std::fs::metadata(&p)?.len();
+
+        // 事务中不允许执行 vacuum
+        s.execute("begin;")?;
+        assert!(s.execute("vacuum;").is_err());
+        s.execute("commit;")?;
+
+        // 其他 session 存在活跃事务时，不清理旧版本
+        let mut s2 = kvengine.session()?;
+        s2.execute("begin;")?;
+        assert_eq!(s.execute("vacuum;")?, ResultSet::Vacuum { count: 0 });
+        s2.execute("commit;")?;
+
+        match s.execute("vacuum;")? {
+            ResultSet::Vacuum { count } => assert!(count > 0),
+            _ => unreachable!(),
+        }
+        assert!(std::fs::metadata(&p)?.len() < size);
+        scan_table_and_compare(
+            &mut s,
+            "t",
+            vec![
+                vec![Value::Integer(1), Value::Integer(10)],
+                vec![Value::Integer(3), Value::Integer(3)],
+            ],
+        )?;
+        match s.execute("select * from t where b = 10;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 1),
+            _ => unreachable!(),
+        }
+
+        // 重新打开后数据不变
+        drop(s);
+        drop(s2);
+        drop(kvengine);
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        scan_table_and_compare(
+            &mut s,
+            "t",
+            vec![
+                vec![Value::Integer(1), Value::Integer(10)],
+                vec![Value::Integer(3), Value::Integer(3)],
+            ],
+        )?;
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_sys_tables() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b int index);")?;
+        s.execute("create table t2 (a int primary key);")?;
+        s.execute("insert into t1 values (1, 1), (2, 2);")?;
+
+        scan_table_and_compare(
+            &mut s,
+            "sys.tables_stats",
+            vec![
+                vec![Value::String("t1".into()), Value::Integer(2), Value::Integer(1), Value::Integer(74)],
+                vec![Value::String("t2".into()), Value::Integer(0), Value::Integer(0), Value::Integer(0)],
+            ],
+        )?;
+        match s.execute("select row_count from sys.tables_stats where table_name = 't1';")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Integer(2)]]),
+            _ => unreachable!(),
+        }
+
+        // 活跃事务
+        let mut s2 = kvengine.session()?;
+        s2.execute("begin;")?;
+        s2.execute("insert into t2 values (1);")?;
+        s.execute("begin;")?;
+        match s.execute("select * from sys.transactions;")? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows.len(), 2);
+                assert_eq!(rows[0][1], Value::Integer(1));
+                assert_eq!(rows[0][2], Value::Boolean(false));
+                assert_eq!(rows[1][1], Value::Integer(0));
+                assert_eq!(rows[1][2], Value::Boolean(true));
+            }
+            _ => unreachable!(),
+        }
+        s.execute("commit;")?;
+        s2.execute("commit;")?;
+
+        // 存储状态，vacuum 之后压缩次数增加
+        s.execute("vacuum;")?;
+        match s.execute("select engine, compactions, garbage from sys.storage;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                // 查询本身开启事务时覆盖写入了 NextVersion，产生 22 字节的无效数据
+                vec![vec![Value::String("disk".into()), Value::Integer(1), Value::Integer(22)]]
+            ),
+            _ => unreachable!(),
+        }
+        assert!(s.execute("select * from sys.unknown;").is_err());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_audit_log() -> Result<()> {
+        let dir = tempfile::tempdir()?.into_path();
+        let p = dir.join("sqldb-log");
+        let audit_path = dir.join("audit-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let audit_log = std::sync::Arc::new(AuditLog::open(audit_path.clone())?);
+        let mut s = kvengine.session()?;
+        s.set_user("alice".into());
+        s.set_audit_log(audit_log.clone());
+
+        s.execute("create table t (a int primary key, b int);")?;
+        s.execute("insert into t values (1, 1), (2, 2);")?;
+        s.execute("select * from t;")?;
+        s.execute("begin;")?;
+        s.execute("update t set b = 3;")?;
+        s.execute("delete from t where a = 1;")?;
+        s.execute("commit;")?;
+        assert!(s.execute("insert into t values (2, 2);").is_err());
+
+        // 没有开启审计日志的 session 不记录
+        let mut s2 = kvengine.session()?;
+        s2.execute("insert into t values (3, 3);")?;
+
+        let records = std::fs::read_to_string(&audit_path)?
+            .lines()
+            .map(AuditRecord::try_from)
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            records
+                .iter()
+                .map(|r| (r.sql.as_str(), r.rows, r.error.is_some()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("create table t (a int primary key, b int);", 0, false),
+                ("insert into t values (1, 1), (2, 2);", 2, false),
+                ("update t set b = 3;", 2, false),
+                ("delete from t where a = 1;", 1, false),
+                ("insert into t values (2, 2);", 0, true),
+            ]
+        );
+        assert!(records.iter().all(|r| r.session_id == s.id()));
+        assert!(records.iter().all(|r| r.user.as_deref() == Some("alice")));
+        // 同一个事务中的语句版本号相同
+        assert_eq!(records[2].version, records[3].version);
+        assert!(records[1].version < records[2].version);
+
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    // span 名称，父 span 的 id，以及字段
+    type SpanRecord = (String, Option<u64>, Vec<(String, String)>);
+
+    // 记录所有 span 的 subscriber，用于测试 tracing
+    #[derive(Default)]
+    struct SpanCollector {
+        spans: std::sync::Mutex<Vec<SpanRecord>>,
+        stack: std::sync::Mutex<Vec<u64>>,
+    }
+
+    struct FieldVisitor<'a>(&'a mut Vec<(String, String)>);
+
+    impl tracing::field::Visit for FieldVisitor<'_> {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0.push((field.name().into(), format!("{:?}", value)));
+        }
+    }
+
+    impl tracing::Subscriber for &'static SpanCollector {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, attrs: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            let mut fields = Vec::new();
+            attrs.record(&mut FieldVisitor(&mut fields));
+            let parent = self.stack.lock().unwrap().last().copied();
+            let mut spans = self.spans.lock().unwrap();
+            spans.push((attrs.metadata().name().into(), parent, fields));
+            tracing::span::Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, id: &tracing::span::Id, values: &tracing::span::Record<'_>) {
+            let mut spans = self.spans.lock().unwrap();
+            values.record(&mut FieldVisitor(&mut spans[id.into_u64() as usize - 1].2));
+        }
+
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+        fn event(&self, _: &tracing::Event<'_>) {}
+
+        fn enter(&self, id: &tracing::span::Id) {
+            self.stack.lock().unwrap().push(id.into_u64());
+        }
+
+        fn exit(&self, _: &tracing::span::Id) {
+            self.stack.lock().unwrap().pop();
+        }
+    }
+
+    #[test]
+    fn test_tracing() -> Result<()> {
+        let collector: &'static SpanCollector = Box::leak(Box::default());
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?).with_subscriber(collector);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int);")?;
+        s.execute("insert into t values (1, 1), (2, 2), (3, 3);")?;
+        collector.spans.lock().unwrap().clear();
+        s.execute("select * from t order by b desc;")?;
+
+        let spans = collector.spans.lock().unwrap();
+        let field = |i: usize, name: &str| {
+            spans[i]
+                .2
+                .iter()
+                .rev()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.clone())
+        };
+        let find = |name: &str, node: Option<&str>| {
+            (0..spans.len())
+                .find(|i| spans[*i].0 == name && (node.is_none() || field(*i, "node").as_deref() == node))
+                .unwrap()
+        };
+
+        // 语句 -> 执行节点 -> 存储调用，逐层嵌套
+        let stmt = find("statement", None);
+        assert_eq!(spans[stmt].1, None);
+        assert_eq!(field(stmt, "sql").as_deref(), Some("\"select * from t order by b desc;\""));
+        let order = find("execute", Some("\"Order\""));
+        let scan = find("execute", Some("\"Scan\""));
+        assert_eq!(spans[order].1, Some(stmt as u64 + 1));
+        assert_eq!(spans[scan].1, Some(order as u64 + 1));
+        // 排序逐行读取下层的结果，行数记录在排序节点上
+        assert_eq!(field(order, "rows").as_deref(), Some("3"));
+        // 扫描在遍历时才分批读取存储，读取同样嵌套在扫描节点中
+        let storage = find("storage.scan_batch", None);
+        assert_eq!(spans[storage].1, Some(scan as u64 + 1));
+        assert_eq!(field(storage, "keys").as_deref(), Some("3"));
+
+        drop(spans);
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_codes() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int not null, c int index);")?;
+        s.execute("insert into t values (1, 1, 1);")?;
+
+        let err = |s: &mut Session<_>, sql: &str| s.execute(sql).unwrap_err();
+        assert!(matches!(err(&mut s, "select * from t2;"), Error::NotFound(_)));
+        assert!(matches!(err(&mut s, "select d from t;"), Error::NotFound(_)));
+        assert!(matches!(err(&mut s, "create table t (a int primary key);"), Error::Duplicate(_)));
+        assert!(matches!(err(&mut s, "insert into t values (1, 2, 2);"), Error::Duplicate(_)));
+        assert!(matches!(err(&mut s, "insert into t values (2, 'a', 2);"), Error::TypeMismatch(_)));
+        assert!(matches!(err(&mut s, "insert into t values (2, null, 2);"), Error::Constraint(_)));
+        assert!(matches!(err(&mut s, "reindex index b on t;"), Error::InvalidInput(_)));
+        assert!(matches!(err(&mut s, "commit;"), Error::InvalidState(_)));
+        assert!(matches!(err(&mut s, "selec * from t;"), Error::Parse(_)));
+
+        // 系统表只读
+        let mut txn = kvengine.begin()?;
+        let e = txn.create_row("sys.storage".into(), vec![Value::Integer(1)]).unwrap_err();
+        assert!(matches!(e, Error::ReadOnly(_)));
+        assert_eq!(e.sqlstate(), Some("25006"));
+        txn.rollback()?;
+
+        // 写冲突
+        let mut s2 = kvengine.session()?;
+        s.execute("begin;")?;
+        s2.execute("begin;")?;
+        s.execute("update t set b = 2 where a = 1;")?;
+        let e = err(&mut s2, "update t set b = 3 where a = 1;");
+        assert_eq!(e, Error::WriteConflict);
+        assert_eq!(e.sqlstate(), Some("40001"));
+        s.execute("commit;")?;
+        s2.execute("rollback;")?;
+
+        // 读取的数据在提交之前被其他事务修改
+        s.execute("begin isolation level serializable;")?;
+        s.execute("select * from t where a = 1;")?;
+        s.execute("insert into t values (2, 2, 2);")?;
+        s2.execute("update t set b = 3 where a = 1;")?;
+        let e = err(&mut s, "commit;");
+        assert_eq!(e, Error::Serialization);
+        assert_eq!(e.sqlstate(), Some("40001"));
+        assert!(!s.in_transaction());
+        match s2.execute("select * from t where a = 2;")? {
+            ResultSet::Scan { rows, .. } => assert!(rows.is_empty()),
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_cursor() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int);")?;
+        s.execute("insert into t values (1, 1), (2, 2), (3, 3), (4, 4), (5, 5);")?;
+
+        // 游标只能在事务中使用
+        assert!(matches!(
+            s.execute("declare c cursor for select * from t;"),
+            Err(Error::InvalidState(_))
+        ));
+
+        s.execute("begin;")?;
+        s.execute("declare c cursor for select b from t where b > 1 order by b desc;")?;
+        assert!(matches!(
+            s.execute("declare c cursor for select * from t;"),
+            Err(Error::Duplicate(_))
+        ));
+        let fetch = |s: &mut Session<_>, sql: &str| match s.execute(sql) {
+            Ok(ResultSet::Scan { columns, rows }) => {
+                assert_eq!(names(&columns), vec!["b".to_string()]);
+                rows.into_iter().map(|r| r[0].clone()).collect::<Vec<_>>()
+            }
+            _ => unreachable!(),
+        };
+        assert_eq!(fetch(&mut s, "fetch 2 from c;"), vec![Value::Integer(5), Value::Integer(4)]);
+        assert_eq!(fetch(&mut s, "fetch next from c;"), vec![Value::Integer(3)]);
+        // 游标声明之后的修改不影响游标中的数据
+        s.execute("delete from t where a = 2;")?;
+        assert_eq!(fetch(&mut s, "fetch all from c;"), vec![Value::Integer(2)]);
+        assert_eq!(fetch(&mut s, "fetch c;"), vec![]);
+        s.execute("close c;")?;
+        assert!(matches!(s.execute("fetch c;"), Err(Error::NotFound(_))));
+
+        // 事务结束时自动关闭游标
+        s.execute("declare c2 cursor for select * from t;")?;
+        s.execute("commit;")?;
+        s.execute("begin;")?;
+        assert!(matches!(s.execute("fetch c2;"), Err(Error::NotFound(_))));
+        s.execute("rollback;")?;
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_dot() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b int);")?;
+        s.execute("create table t2 (c int primary key, d varchar);")?;
+        for i in 0..10 {
+            s.execute(&format!("insert into t1 values ({}, {});", i, i % 3))?;
+        }
+        s.execute("insert into t2 values (1, 'x'), (2, 'y\"z'), (3, 'w');")?;
+
+        let plan = match s.execute("explain (format dot) select * from t1 join t2 on a = c limit 5;")? {
+            ResultSet::Explain { plan } => plan,
+            _ => unreachable!(),
+        };
+        assert!(plan.starts_with("digraph plan {"), "{}", plan);
+        assert!(plan.ends_with('}'), "{}", plan);
+        assert!(plan.contains("[label=\"Limit 5\\nrows=5\"]"), "{}", plan);
+        assert!(plan.contains("[label=\"Seq Scan On t1\\nrows=10\"]"), "{}", plan);
+        assert!(plan.contains("[label=\"Seq Scan On t2\\nrows=3\"]"), "{}", plan);
+        assert_eq!(plan.matches(" -> ").count(), plan.matches("[label=").count() - 1);
+
+        // 过滤条件按照选择率估算行数
+        let plan = match s.execute("explain (format dot) select * from t2 where d = 'y\"z';")? {
+            ResultSet::Explain { plan } => plan,
+            _ => unreachable!(),
+        };
+        assert!(plan.contains("(d = 'y\\\"z')\\nrows=1\""), "{}", plan);
+
+        // format text 和默认的输出相同
+        assert_eq!(
+            s.execute("explain (format text) select * from t1;")?,
+            s.execute("explain select * from t1;")?
+        );
+        assert!(matches!(
+            s.execute("explain (format json) select * from t1;"),
+            Err(Error::Parse(_))
+        ));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_show_profile() -> Result<()> {
+        #[derive(Debug, serde::Deserialize)]
+        struct Profile {
+            statement: String,
+            parse_us: i64,
+            rows_scanned: i64,
+            rows_returned: i64,
+            keys_read: i64,
+            keys_written: i64,
+        }
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int index);")?;
+        s.execute("insert into t values (1, 10), (2, 20), (3, 10);")?;
+        let profile = s.query_as::<Profile>("show profile;")?.remove(0);
+        assert_eq!(profile.statement, "insert into t values (1, 10), (2, 20), (3, 10);");
+        assert!(profile.parse_us >= 0);
+        assert_eq!(profile.rows_returned, 3);
+        // 每行写入行数据和索引
+        assert!(profile.keys_written >= 6, "{:?}", profile);
+
+        s.execute("select * from t where a > 2;")?;
+        let profile = s.query_as::<Profile>("show profile;")?.remove(0);
+        assert_eq!((profile.rows_scanned, profile.rows_returned), (3, 1));
+        assert!(profile.keys_read >= 3, "{:?}", profile);
+        assert_eq!(profile.keys_written, 0);
+
+        // 索引范围查询只读取范围内的行
+        s.execute("select * from t where b > 10;")?;
+        let profile = s.query_as::<Profile>("show profile;")?.remove(0);
+        assert_eq!((profile.rows_scanned, profile.rows_returned), (1, 1));
+
+        // 主键查询只读取一行
+        s.execute("select * from t where a = 2;")?;
+        let profile = s.query_as::<Profile>("show profile;")?.remove(0);
+        assert_eq!((profile.rows_scanned, profile.rows_returned), (1, 1));
+
+        // show profile 不覆盖上一条语句的统计，事务中同样记录
+        let again = s.query_as::<Profile>("show profile;")?.remove(0);
+        assert_eq!(again.statement, "select * from t where a = 2;");
+        s.execute("begin;")?;
+        s.execute("delete from t where b = 10;")?;
+        let profile = s.query_as::<Profile>("show profile;")?.remove(0);
+        assert_eq!(profile.rows_returned, 2);
+        assert!(profile.keys_written >= 2, "{:?}", profile);
+        s.execute("commit;")?;
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_engine_close() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key);")?;
+        s.execute("insert into t values (1), (2);")?;
+        kvengine.flush()?;
+        kvengine.close()?;
+        assert!(matches!(s.execute("select * from t;"), Err(Error::InvalidState(_))));
+
+        // 关闭之后不需要等到 drop 就可以重新打开
+        let kvengine2 = KVEngine::new(DiskEngine::new(p.clone())?);
+        let rows: Vec<i64> = kvengine2.session()?.query_as("select * from t;")?;
+        assert_eq!(rows, vec![1, 2]);
+        drop((s, kvengine));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_implicit_rowid() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int, b text);")?;
+        s.execute("insert into t values (10, 'x'), (20, 'y');")?;
+        s.execute("insert into t (b) values ('z');")?;
+
+        // select * 不返回 _rowid，显式查询时可以看到
+        match s.execute("select * from t;")? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(names(&columns), vec!["a", "b"]);
+                assert_eq!(rows.len(), 3);
+            }
+            _ => unreachable!(),
+        }
+        let rows: Vec<(i64, Option<i64>)> = s.query_as("select _rowid, a from t;")?;
+        assert_eq!(rows, vec![(1, Some(10)), (2, Some(20)), (3, None)]);
+        let rows: Vec<String> = s.query_as("select b from t where _rowid = 2;")?;
+        assert_eq!(rows, vec!["y"]);
+
+        // 按位置插入时不能给 _rowid 赋值，显式给出的值会推进序号
+        assert!(matches!(
+            s.execute("insert into t values (1, 'a', 5);"),
+            Err(Error::InvalidInput(_))
+        ));
+        s.execute("insert into t (_rowid, a) values (10, 1);")?;
+        s.execute("insert into t (a) values (2);")?;
+        let rows: Vec<i64> = s.query_as("select _rowid from t where _rowid > 3;")?;
+        assert_eq!(rows, vec![10, 11]);
+        assert!(matches!(
+            s.execute("insert into t (_rowid) values (10);"),
+            Err(Error::Duplicate(_))
+        ));
+
+        // join 中同样隐藏
+        s.execute("create table u (a int primary key, c int);")?;
+        s.execute("insert into u values (10, 100);")?;
+        match s.execute("select * from t join u on t.a = u.a;")? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(names(&columns), vec!["t.a", "t.b", "u.a", "u.c"]);
+                assert_eq!(
+                    rows,
+                    vec![vec![
+                        Value::Integer(10),
+                        Value::String("x".into()),
+                        Value::Integer(10),
+                        Value::Integer(100)
+                    ]]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        // 表结构中不显示 _rowid，清空表之后重新从 1 开始
+        assert!(!s.get_table("t".into())?.contains("_rowid"));
+        s.execute("truncate table t;")?;
+        s.execute("insert into t values (1, 'a');")?;
+        let rows: Vec<i64> = s.query_as("select _rowid from t;")?;
+        assert_eq!(rows, vec![1]);
+
+        // _rowid 是保留的列名
+        assert!(matches!(
+            s.execute("create table v (_rowid int primary key);"),
+            Err(Error::InvalidInput(_))
+        ));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_index() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text index using hash, c int);")?;
+        s.execute("insert into t values (1, 'x', 10), (2, 'y', 20), (3, 'x', 30), (4, null, 40);")?;
+        assert!(s.get_table("t".into())?.contains("b String DEFAULT NULL INDEX USING HASH"));
+
+        let explain = |s: &mut Session<_>, sql: &str| match s.execute(sql) {
+            Ok(ResultSet::Explain { plan }) => plan,
+            _ => unreachable!(),
+        };
+        // 等值条件使用哈希索引，前缀查找只能全表扫描
+        let plan = explain(&mut s, "explain select * from t where b = 'x';");
+        assert!(plan.contains("Index Scan On t.b"), "{}", plan);
+        let plan = explain(&mut s, "explain select * from t where b like 'x%';");
+        assert!(plan.contains("Seq Scan"), "{}", plan);
+
+        let rows: Vec<i64> = s.query_as("select a from t where b = 'x';")?;
+        assert_eq!(rows, vec![1, 3]);
+        let rows: Vec<i64> = s.query_as("select a from t where b like 'x%';")?;
+        assert_eq!(rows, vec![1, 3]);
+
+        // 更新和删除时维护索引
+        s.execute("update t set b = 'y' where a = 1;")?;
+        s.execute("delete from t where a = 2;")?;
+        let rows: Vec<i64> = s.query_as("select a from t where b = 'y';")?;
+        assert_eq!(rows, vec![1]);
+        let rows: Vec<i64> = s.query_as("select a from t where b = 'x';")?;
+        assert_eq!(rows, vec![3]);
+        assert_eq!(s.execute("reindex index b on t;")?, ResultSet::Reindex { table_name: "t".into(), count: 3 });
+        let rows: Vec<i64> = s.query_as("select a from t where b = 'x';")?;
+        assert_eq!(rows, vec![3]);
+        let rows: Vec<i64> = s.query_as("select a from t where b = 'z';")?;
+        assert!(rows.is_empty());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_bitmap_index() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b bool index using bitmap, c text);")?;
+        for i in -3..10 {
+            // 没有负数字面量，负数主键写成减法
+            let a = match i < 0 {
+                true => format!("0 - {}", -i),
+                false => i.to_string(),
+            };
+            s.execute(&format!("insert into t values ({}, {}, 'c{}');", a, i % 3 == 0, i))?;
+        }
+        s.execute("insert into t values (100, null, 'x');")?;
+        assert!(s.get_table("t".into())?.contains("b Boolean DEFAULT NULL INDEX USING BITMAP"));
+        assert!(matches!(
+            s.execute("create table t2 (a text primary key, b bool index using bitmap);"),
+            Err(Error::InvalidInput(_))
+        ));
+
+        let explain = |s: &mut Session<_>, sql: &str| match s.execute(sql) {
+            Ok(ResultSet::Explain { plan }) => plan,
+            _ => unreachable!(),
+        };
+        let plan = explain(&mut s, "explain select a from t where b = true;");
+        assert!(plan.contains("Bitmap Scan On t(b = TRUE)"), "{}", plan);
+
+        // 按照主键的顺序返回，负数主键排在前面
+        let rows: Vec<i64> = s.query_as("select a from t where b = true;")?;
+        assert_eq!(rows, vec![-3, 0, 3, 6, 9]);
+        let rows: Vec<i64> = s.query_as("select a from t where b is not distinct from null;")?;
+        assert_eq!(rows, vec![100]);
+
+        // 更新、删除时维护位图，重建索引之后结果不变
+        s.execute("update t set b = false where a = 3;")?;
+        s.execute("delete from t where a = 0;")?;
+        let rows: Vec<i64> = s.query_as("select a from t where b = true;")?;
+        assert_eq!(rows, vec![-3, 6, 9]);
+        s.execute("reindex table t;")?;
+        let rows: Vec<i64> = s.query_as("select a from t where b = true;")?;
+        assert_eq!(rows, vec![-3, 6, 9]);
+        let rows: Vec<i64> = s.query_as("select count(a) from t where b = false;")?;
+        assert_eq!(rows, vec![9]);
+
+        // 没有主键的表使用 _rowid 作为位图中的位置
+        s.execute("create table t3 (status text index using bitmap, n int);")?;
+        s.execute("insert into t3 values ('ok', 1), ('err', 2), ('ok', 3);")?;
+        let rows: Vec<i64> = s.query_as("select n from t3 where status = 'ok';")?;
+        assert_eq!(rows, vec![1, 3]);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_fulltext_index() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, body text index using fulltext);")?;
+        s.execute(
+            "insert into t values (1, 'Rust is a systems language'),
+                (2, 'An embedded database written in Rust'), (3, 'A database in Go'), (4, null);",
+        )?;
+        assert!(matches!(
+            s.execute("create table t2 (a int primary key, b int index using fulltext);"),
+            Err(Error::InvalidInput(_))
+        ));
+
+        let explain = |s: &mut Session<_>, sql: &str| match s.execute(sql) {
+            Ok(ResultSet::Explain { plan }) => plan,
+            _ => unreachable!(),
+        };
+        let plan = explain(&mut s, "explain select a from t where match(body, 'rust database');");
+        assert!(plan.contains("Full Text Scan On t.body('rust database')"), "{}", plan);
+        // 全文索引不能用于等值查找
+        let plan = explain(&mut s, "explain select a from t where body = 'x';");
+        assert!(plan.contains("Seq Scan"), "{}", plan);
+
+        let rows: Vec<i64> = s.query_as("select a from t where match(body, 'rust database');")?;
+        assert_eq!(rows, vec![2]);
+        let rows: Vec<i64> = s.query_as("select a from t where match(body, 'RUST');")?;
+        assert_eq!(rows, vec![1, 2]);
+        let rows: Vec<i64> = s.query_as("select a from t where match(body, 'python');")?;
+        assert!(rows.is_empty());
+
+        // 更新、删除时维护倒排表，重建索引之后结果不变
+        s.execute("update t set body = 'Go and Rust' where a = 3;")?;
+        s.execute("delete from t where a = 1;")?;
+        let rows: Vec<i64> = s.query_as("select a from t where match(body, 'rust');")?;
+        assert_eq!(rows, vec![2, 3]);
+        s.execute("reindex table t;")?;
+        let rows: Vec<i64> = s.query_as("select a from t where match(body, 'rust');")?;
+        assert_eq!(rows, vec![2, 3]);
+        let rows: Vec<i64> = s.query_as("select a from t where match(body, 'database');")?;
+        assert_eq!(rows, vec![2]);
+
+        // 没有全文索引的列逐行求值
+        s.execute("create table t3 (a int primary key, body text);")?;
+        s.execute("insert into t3 values (1, 'rust database'), (2, 'rust');")?;
+        let rows: Vec<i64> = s.query_as("select a from t3 where match(body, 'database rust');")?;
+        assert_eq!(rows, vec![1]);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_array_unnest() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, tags text[]);")?;
+        s.execute("insert into t values (1, ['x', 'y']), (2, array['z']), (3, []), (4, null);")?;
+        assert!(s.get_table("t".into())?.contains("tags String[]"));
+        assert!(matches!(
+            s.execute("insert into t values (5, [1]);"),
+            Err(Error::TypeMismatch(_))
+        ));
+        assert!(matches!(
+            s.execute("create table t2 (a int[] primary key);"),
+            Err(Error::InvalidInput(_))
+        ));
+
+        // 下标从 1 开始，越界返回 NULL
+        let rows: Vec<(i64, Option<String>)> = s.query_as("select a, tags[1] from t;")?;
+        assert_eq!(
+            rows,
+            vec![(1, Some("x".into())), (2, Some("z".into())), (3, None), (4, None)]
+        );
+        let rows: Vec<Option<String>> = s.query_as("select tags[2] from t where a = 1;")?;
+        assert_eq!(rows, vec![Some("y".into())]);
+        // Vec 按照列的位置匹配，数组列需要放在元组中
+        let rows: Vec<(Vec<String>,)> = s.query_as("select tags from t where a = 1;")?;
+        assert_eq!(rows, vec![(vec!["x".to_string(), "y".to_string()],)]);
+
+        let rows: Vec<i64> = s.query_as("select x from unnest([1, 2, 3]) as x where x > 1;")?;
+        assert_eq!(rows, vec![2, 3]);
+
+        // 每一行展开数组，空数组和 NULL 不输出行
+        let rows: Vec<(i64, String)> =
+            s.query_as("select t.a, tag from t cross join unnest(t.tags) as tag;")?;
+        assert_eq!(
+            rows,
+            vec![(1, "x".into()), (1, "y".into()), (2, "z".into())]
+        );
+        let rows: Vec<i64> =
+            s.query_as("select t.a from t cross join unnest(t.tags) as tag where tag = 'y';")?;
+        assert_eq!(rows, vec![1]);
+        // left join 保留没有匹配元素的行
+        let rows: Vec<(i64, Option<String>)> =
+            s.query_as("select t.a, tag from t left join unnest(t.tags) as tag on tag = 'x';")?;
+        assert_eq!(
+            rows,
+            vec![(1, Some("x".into())), (2, None), (3, None), (4, None)]
+        );
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_sample() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int);")?;
+        let values = (0..2000)
+            .map(|i| format!("({}, {})", i, i % 10))
+            .collect::<Vec<_>>();
+        s.execute(&format!("insert into t values {};", values.join(", ")))?;
+
+        // 采样的行数在期望值 200 附近
+        let rows: Vec<(i64, i64)> = s.query_as("select * from t tablesample (10 percent);")?;
+        assert!((100..300).contains(&rows.len()), "{}", rows.len());
+        // 指定 seed 时结果相同
+        let sql = "select * from t tablesample (10 percent) repeatable (7);";
+        let rows: Vec<(i64, i64)> = s.query_as(sql)?;
+        assert_eq!(rows, s.query_as::<(i64, i64)>(sql)?);
+        assert!(rows.windows(2).all(|w| w[0].0 < w[1].0));
+
+        // 先过滤再采样
+        let rows: Vec<(i64, i64)> =
+            s.query_as("select * from t tablesample (50 percent) where b = 3;")?;
+        assert!(rows.iter().all(|(_, b)| *b == 3));
+        assert!((50..150).contains(&rows.len()), "{}", rows.len());
+
+        let rows: Vec<(i64, i64)> = s.query_as("select * from t tablesample (0 percent);")?;
+        assert!(rows.is_empty());
+        let rows: Vec<(i64, i64)> = s.query_as("select * from t tablesample (100 percent);")?;
+        assert_eq!(rows.len(), 2000);
+
+        // join 中的采样表同样带有表名前缀
+        s.execute("create table t2 (a int primary key);")?;
+        s.execute("insert into t2 values (1), (2), (3);")?;
+        let rows: Vec<i64> = s.query_as(
+            "select t2.a from t2 tablesample (100 percent) repeatable (1) join t on t2.a = t.a;",
+        )?;
+        assert_eq!(rows, vec![1, 2, 3]);
+
+        match s.execute("explain select * from t tablesample (1.5 percent) where b = 1;")? {
+            ResultSet::Explain { plan } => {
+                assert!(plan.contains("Sample (1.5%)\n  ->  Seq Scan On t (b = 1)"), "{}", plan)
+            }
+            _ => unreachable!(),
+        }
+        assert!(s.execute("select * from t tablesample (101 percent);").is_err());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_volatile_functions() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, id text, r float);")?;
+        s.execute("insert into t values (1, uuid(), random()), (2, gen_random_uuid(), random());")?;
+        let rows: Vec<(i64, String, f64)> = s.query_as("select * from t;")?;
+        assert_eq!(rows[0].1.len(), 36);
+        assert_ne!(rows[0].1, rows[1].1);
+        assert!(rows.iter().all(|(_, _, r)| (0.0..1.0).contains(r)));
+
+        // 每一行单独计算
+        let values = (3..200).map(|i| format!("({}, 'x', 0.0)", i)).collect::<Vec<_>>();
+        s.execute(&format!("insert into t values {};", values.join(", ")))?;
+        let rows: Vec<(i64, f64)> = s.query_as("select a, random() from t;")?;
+        assert!(rows.windows(2).any(|w| w[0].1 != w[1].1));
+        let rows: Vec<i64> = s.query_as("select a from t where random() < 0.5;")?;
+        assert!((40..160).contains(&rows.len()), "{}", rows.len());
+
+        // 默认值不能使用易变函数
+        assert!(s
+            .execute("create table t2 (a int primary key, b float default random());")
+            .is_err());
+        s.execute("create table t3 (a int primary key, b int default coalesce(null, 3));")?;
+        s.execute("insert into t3 (a) values (1);")?;
+        let rows: Vec<(i64, i64)> = s.query_as("select * from t3;")?;
+        assert_eq!(rows, vec![(1, 3)]);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_row_value_compare() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int, c text);")?;
+        s.execute(
+            "insert into t values (1, 1, 'x'), (2, 1, 'y'), (3, 2, 'z'), (4, 2, null), (5, 3, 'w');",
+        )?;
+
+        // 字典序比较，先比较 b，b 相等时再比较 a
+        let rows: Vec<i64> = s.query_as("select a from t where (b, a) > (1, 2) order by a;")?;
+        assert_eq!(rows, vec![3, 4, 5]);
+        let rows: Vec<i64> = s.query_as("select a from t where (b, a) < (2, 4) order by a;")?;
+        assert_eq!(rows, vec![1, 2, 3]);
+        let rows: Vec<i64> = s.query_as("select a from t where (b, c) = (2, 'z');")?;
+        assert_eq!(rows, vec![3]);
+
+        // 比较到 NULL 元素时结果为 NULL，前面的元素已经决定结果时不受影响
+        let rows: Vec<i64> = s.query_as("select a from t where (b, c) > (2, 'a') order by a;")?;
+        assert_eq!(rows, vec![3, 5]);
+        let rows: Vec<i64> =
+            s.query_as("select a from t where (b, c) is not distinct from (2, null);")?;
+        assert_eq!(rows, vec![4]);
+
+        // 键集分页
+        let mut pages = Vec::new();
+        let (mut b, mut a) = (0, 0);
+        loop {
+            let sql = format!(
+                "select b, a from t where (b, a) > ({}, {}) order by b, a limit 2;",
+                b, a
+            );
+            let rows: Vec<(i64, i64)> = s.query_as(&sql)?;
+            match rows.last() {
+                Some(last) => (b, a) = *last,
+                None => break,
+            }
+            pages.push(rows.iter().map(|r| r.1).collect::<Vec<_>>());
+        }
+        assert_eq!(pages, vec![vec![1, 2], vec![3, 4], vec![5]]);
+
+        assert!(s.execute("select a from t where (b, c) > (1, 2);").is_err());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_logical_operators() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int index, c bool);")?;
+        s.execute("insert into t values (1, 1, true), (2, 1, false), (3, 2, null), (4, 3, true);")?;
+
+        let query = |s: &mut Session<_>, sql: &str| -> Result<Vec<i64>> {
+            s.query_as(&format!("select a from t where {} order by a;", sql))
+        };
+        assert_eq!(query(&mut s, "a = 1 and b > 2 or not c = true")?, vec![2]);
+        assert_eq!(query(&mut s, "(a = 1 or a = 4) and c")?, vec![1, 4]);
+        assert_eq!(query(&mut s, "not (a = 1 or b = 1)")?, vec![3, 4]);
+        // 三值逻辑：NULL AND false 为 false，NULL OR true 为 true，其他情况为 NULL
+        assert_eq!(query(&mut s, "not (c and a = 1)")?, vec![2, 3, 4]);
+        assert_eq!(query(&mut s, "c or a = 3")?, vec![1, 3, 4]);
+        assert_eq!(query(&mut s, "not c")?, vec![2]);
+        assert_eq!(query(&mut s, "not (c or a = 2)")?, Vec::<i64>::new());
+        assert!(query(&mut s, "a and c").is_err());
+
+        // 选择一个可以走主键或者索引的条件，其余的条件在扫描的结果上过滤
+        match s.execute("explain select a from t where b = 1 and c and a = 2;")? {
+            ResultSet::Explain { plan } => assert!(
+                plan.contains("Filter (b = 1 AND c)\n    ->  Primary Key Scan On t(2)"),
+                "{}",
+                plan
+            ),
+            _ => unreachable!(),
+        }
+        assert_eq!(query(&mut s, "b = 1 and c")?, vec![1]);
+        assert_eq!(query(&mut s, "b = 1 and a = 2")?, vec![2]);
+
+        // update ... from 的 where 条件中的等值条件作为 join 条件
+        s.execute("create table u (a int primary key, v int);")?;
+        s.execute("insert into u values (1, 10), (2, 20), (3, 30);")?;
+        s.execute("update t set b = u.v from u where t.a = u.a and u.v > 10;")?;
+        let rows: Vec<(i64, i64)> = s.query_as("select a, b from t order by a;")?;
+        assert_eq!(rows, vec![(1, 1), (2, 20), (3, 30), (4, 3)]);
+        s.execute("delete from t using u where u.a = t.a and not t.c;")?;
+        assert_eq!(query(&mut s, "true")?, vec![1, 3, 4]);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_between_in() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int, c text index using bitmap, d text);")?;
+        s.execute(
+            "insert into t values (1, 1, 'x', 'abc'), (2, 5, 'y', 'abd'), (3, null, 'z', null), (4, 10, 'x', 'xbc');",
+        )?;
+
+        let query = |s: &mut Session<_>, sql: &str| -> Result<Vec<i64>> {
+            s.query_as(&format!("select a from t where {} order by a;", sql))
+        };
+        assert_eq!(query(&mut s, "b between 1 and 5")?, vec![1, 2]);
+        assert_eq!(query(&mut s, "b not between 2 and 9")?, vec![1, 4]);
+        assert_eq!(query(&mut s, "b between 5 and 1")?, Vec::<i64>::new());
+        assert_eq!(query(&mut s, "a between 2 and 3 or a in (4)")?, vec![2, 3, 4]);
+        assert_eq!(query(&mut s, "d between 'abd' and 'z'")?, vec![2, 4]);
+
+        // 列表中有 NULL 并且没有相等的值时结果为 NULL，NOT IN 不返回任何行
+        assert_eq!(query(&mut s, "b in (1, 10, null)")?, vec![1, 4]);
+        assert_eq!(query(&mut s, "b not in (1, 5)")?, vec![4]);
+        assert_eq!(query(&mut s, "b not in (1, null)")?, Vec::<i64>::new());
+        assert_eq!(query(&mut s, "d not like 'ab%'")?, vec![4]);
+
+        // 位图索引列上的 IN 转换成位图的并集
+        match s.execute("explain select a from t where c in ('x', 'z');")? {
+            ResultSet::Explain { plan } => {
+                assert!(plan.contains("Bitmap Scan On t((c = 'x' OR c = 'z'))"), "{}", plan)
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(query(&mut s, "c in ('x', 'z')")?, vec![1, 3, 4]);
+        assert_eq!(query(&mut s, "c in ('x', 'z') and b between 0 and 5")?, vec![1]);
+
+        s.execute("update t set d = 'in' where b in (5, 10);")?;
+        assert_eq!(query(&mut s, "d = 'in'")?, vec![2, 4]);
+        s.execute("delete from t where b not between 2 and 100;")?;
+        assert_eq!(query(&mut s, "true")?, vec![2, 3, 4]);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_range_scan() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute(
+            "create table t (a int primary key, b int index, c float index, d text index, e int index using hash);",
+        )?;
+        s.execute(
+            "insert into t values (1, 10, 1.5, 'a', 1), (2, 20, 0.5, 'b', 2), (3, null, 3.0, 'c', 3),
+                (4, 5, 0.0, null, 4), (5, 30, 2.0, 'bb', 5), (6, 20, 2.5, 'ab', 6);",
+        )?;
+
+        let explain = |s: &mut Session<_>, sql: &str| match s.execute(sql) {
+            Ok(ResultSet::Explain { plan }) => plan,
+            _ => unreachable!(),
+        };
+        let plan = explain(&mut s, "explain select * from t where b > 10;");
+        assert!(plan.contains("Index Range Scan On t.b(10, +inf)"), "{}", plan);
+        assert!(!plan.contains("Filter"), "{}", plan);
+        // 同一列上的多个条件合并为一个范围，其余条件在扫描结果上过滤
+        let plan = explain(&mut s, "explain select * from t where b between 5 and 20 and b < 20 and a > 1;");
+        assert!(plan.contains("Index Range Scan On t.b[5, 20)"), "{}", plan);
+        assert!(plan.contains("Filter (a > 1)"), "{}", plan);
+        // 有等值条件时优先使用等值索引
+        let plan = explain(&mut s, "explain select * from t where b > 10 and d = 'b';");
+        assert!(plan.contains("Index Scan On t.d"), "{}", plan);
+        // 主键、哈希索引、类型无法转换的常量不走范围扫描
+        for sql in [
+            "explain select * from t where a > 1;",
+            "explain select * from t where e > 1;",
+            "explain select * from t where b > 10.5;",
+            "explain select * from t where b > null;",
+        ] {
+            let plan = explain(&mut s, sql);
+            assert!(plan.contains("Seq Scan"), "{}", plan);
+        }
+
+        // 结果按照索引列的值排序，NULL 不在任何范围中
+        let rows: Vec<i64> = s.query_as("select a from t where b > 10;")?;
+        assert_eq!(rows, vec![2, 6, 5]);
+        let rows: Vec<i64> = s.query_as("select a from t where 20 > b;")?;
+        assert_eq!(rows, vec![4, 1]);
+        let rows: Vec<i64> = s.query_as("select a from t where b between 10 and 20;")?;
+        assert_eq!(rows, vec![1, 2, 6]);
+        let rows: Vec<i64> = s.query_as("select a from t where b > 20 and b < 10;")?;
+        assert!(rows.is_empty());
+        let rows: Vec<i64> = s.query_as("select a from t where c > 1;")?;
+        assert_eq!(rows, vec![1, 5, 6, 3]);
+        let rows: Vec<i64> = s.query_as("select a from t where c < 1.5;")?;
+        assert_eq!(rows, vec![4, 2]);
+        let rows: Vec<i64> = s.query_as("select a from t where d > 'a' and d < 'c';")?;
+        assert_eq!(rows, vec![6, 2, 5]);
+        let rows: Vec<i64> = s.query_as("select a from t where b > 10.5;")?;
+        assert_eq!(rows, vec![2, 5, 6]);
+
+        // 事务中未提交的修改也能通过范围扫描看到，更新和删除走范围扫描时维护索引
+        s.execute("begin;")?;
+        s.execute("insert into t values (7, 25, 1.0, 'x', 7);")?;
+        let rows: Vec<i64> = s.query_as("select a from t where b > 20;")?;
+        assert_eq!(rows, vec![7, 5]);
+        s.execute("update t set b = 100 where b < 10;")?;
+        s.execute("delete from t where b > 20 and b < 30;")?;
+        s.execute("commit;")?;
+        let rows: Vec<(i64, i64)> = s.query_as("select a, b from t where b > 20;")?;
+        assert_eq!(rows, vec![(5, 30), (4, 100)]);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_date_timestamp() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, d date index, ts timestamp);")?;
+        s.execute(
+            "insert into t values (1, '2024-01-31', '2024-01-31 10:20:30'),
+                (2, date '2023-12-25', timestamp '2023-12-25 08:00:00.25'),
+                (3, '2024-03-01', date '2024-03-01'), (4, null, null);",
+        )?;
+        assert!(s.execute("insert into t values (5, '2024-13-01', null);").is_err());
+        assert!(s.execute("insert into t values (5, 20240101, null);").is_err());
+
+        // 按照时间顺序排序、比较，NULL 排在最前面
+        let rows: Vec<(i64, Option<String>, Option<String>)> = s.query_as("select * from t order by d;")?;
+        assert_eq!(
+            rows,
+            vec![
+                (4, None, None),
+                (2, Some("2023-12-25".into()), Some("2023-12-25 08:00:00.250000".into())),
+                (1, Some("2024-01-31".into()), Some("2024-01-31 10:20:30".into())),
+                (3, Some("2024-03-01".into()), Some("2024-03-01 00:00:00".into())),
+            ]
+        );
+        let rows: Vec<(String, String)> = s.query_as("select min(d), max(ts) from t;")?;
+        assert_eq!(rows, vec![("2023-12-25".into(), "2024-03-01 00:00:00".into())]);
+        let rows: Vec<i64> = s.query_as("select id from t where ts < '2024-01-01';")?;
+        assert_eq!(rows, vec![2]);
+        let rows: Vec<i64> = s.query_as("select id from t where d = ts;")?;
+        assert_eq!(rows, vec![3]);
+
+        // 日期索引可以用于等值查找和范围查找
+        let rows: Vec<i64> = s.query_as("select id from t where d = '2024-01-31';")?;
+        assert_eq!(rows, vec![1]);
+        let rows: Vec<i64> = s.query_as("select id from t where d > date '2024-01-01';")?;
+        assert_eq!(rows, vec![1, 3]);
+        match s.execute("explain select id from t where d between '2024-01-01' and '2024-02-01';")? {
+            ResultSet::Explain { plan } => {
+                assert!(plan.contains("Index Only Range Scan On t.d[2024-01-01, 2024-02-01]"), "{}", plan)
+            }
+            _ => unreachable!(),
+        }
+
+        // 加减时间间隔、天数，两个日期时间相减得到时间间隔
+        let rows: Vec<(String, String, String, String)> = s.query_as(
+            "select d + interval '1 month', d - 31, ts + interval '1 hour', ts - date '2024-01-31' from t where id = 1;",
+        )?;
+        assert_eq!(
+            rows,
+            vec![(
+                "2024-02-29".into(),
+                "2023-12-31".into(),
+                "2024-01-31 11:20:30".into(),
+                "10:20:30".into()
+            )]
+        );
+        let rows: Vec<(String, i64)> =
+            s.query_as("select date_trunc('month', ts), extract(day from d) from t where id = 1;")?;
+        assert_eq!(rows, vec![("2024-01-01 00:00:00".into(), 31)]);
+
+        // 修改列类型时时间戳去掉时间部分
+        s.execute("alter table t alter column ts type date;")?;
+        let rows: Vec<i64> = s.query_as("select id from t where ts = d;")?;
+        assert_eq!(rows, vec![1, 2, 3]);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_drop_index() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int, c text);")?;
+        s.execute("insert into t values (1, 10, 'red apple'), (2, 20, 'green apple'), (3, 20, 'pear');")?;
+        let plan = |s: &mut Session<_>, sql: &str| match s.execute(sql) {
+            Ok(ResultSet::Explain { plan }) => plan,
+            _ => unreachable!(),
+        };
+        assert!(plan(&mut s, "explain select * from t where b = 20;").contains("Seq Scan"));
+
+        // 根据已有的数据生成索引，之后的写入同样会维护索引
+        assert_eq!(
+            s.execute("create index idx_b on t (b);")?,
+            ResultSet::CreateIndex { index_name: "idx_b".into(), table_name: "t".into(), count: 3 }
+        );
+        assert!(plan(&mut s, "explain select * from t where b = 20;").contains("Index Scan On t.b"));
+        s.execute("insert into t values (4, 20, 'plum');")?;
+        let rows: Vec<i64> = s.query_as("select a from t where b = 20 order by a;")?;
+        assert_eq!(rows, vec![2, 3, 4]);
+        assert_eq!(
+            s.execute("reindex index idx_b on t;")?,
+            ResultSet::Reindex { table_name: "t".into(), count: 4 }
+        );
+
+        // 不指定索引名时使用列名，可以指定索引类型
+        s.execute("create index on t (c) using fulltext;")?;
+        let rows: Vec<i64> = s.query_as("select a from t where match(c, 'apple') order by a;")?;
+        assert_eq!(rows, vec![1, 2]);
+
+        assert!(matches!(s.execute("create index idx_b on t (c);"), Err(Error::Duplicate(_))));
+        assert!(matches!(s.execute("create index x on t (a);"), Err(Error::Duplicate(_))));
+        assert!(matches!(s.execute("create index x on t (d);"), Err(Error::NotFound(_))));
+        assert!(matches!(s.execute("create index x on t2 (b);"), Err(Error::NotFound(_))));
+
+        // 删除索引之后索引数据也被删除，查询退回到全表扫描
+        s.execute("drop index idx_b on t;")?;
+        s.execute("drop index c on t;")?;
+        assert!(plan(&mut s, "explain select * from t where b = 20;").contains("Seq Scan"));
+        let rows: Vec<i64> = s.query_as("select a from t where b = 20 order by a;")?;
+        assert_eq!(rows, vec![2, 3, 4]);
+        let txn = kvengine.begin()?;
+        let prefix = KeyPrefix::Index("t".into(), "b".into()).encode()?;
+        assert!(txn.txn.scan_prefix(prefix)?.is_empty());
+        txn.commit()?;
+        assert!(matches!(s.execute("drop index idx_b on t;"), Err(Error::NotFound(_))));
+
+        // 回滚之后索引不存在
+        s.execute("begin;")?;
+        s.execute("create index idx_b on t (b);")?;
+        s.execute("rollback;")?;
+        assert!(plan(&mut s, "explain select * from t where b = 20;").contains("Seq Scan"));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_streaming_pipeline() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int);")?;
+        s.execute("insert into t values (1, 1), (2, 1), (3, 9223372036854775807), (4, 2);")?;
+
+        // 第三行计算时溢出，limit 读取够两行之后不再计算后面的行
+        assert!(s.execute("select a + b from t;").is_err());
+        let rows: Vec<i64> = s.query_as("select a + b as x from t where b > 0 limit 2;")?;
+        assert_eq!(rows, vec![2, 3]);
+        let rows: Vec<i64> = s.query_as("select a from t where b < 3 limit 2 offset 1;")?;
+        assert_eq!(rows, vec![2, 4]);
+        let rows: Vec<i64> = s.query_as("select a + b as x from t offset 3;")?;
+        assert_eq!(rows, vec![6]);
+
+        // 游标每次 fetch 时才计算需要的行
+        s.execute("begin;")?;
+        s.execute("declare c cursor for select a, a + b as x from t where a > 0;")?;
+        match s.execute("fetch 2 from c;")? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(names(&columns), vec!["a", "x"]);
+                assert_eq!(rows.len(), 2);
+            }
+            _ => unreachable!(),
+        }
+        assert!(s.execute("fetch 1 from c;").is_err());
+        s.execute("rollback;")?;
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_unique_constraint() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int unique, c text unique index);")?;
+        s.execute("insert into t values (1, 10, 'x'), (2, 20, null), (3, null, null);")?;
+        let err = |s: &mut Session<_>, sql: &str| s.execute(sql).unwrap_err();
+
+        // 非 NULL 的值不能重复，NULL 可以有多个
+        assert!(matches!(err(&mut s, "insert into t values (4, 10, 'y');"), Error::Constraint(_)));
+        assert!(matches!(err(&mut s, "insert into t values (4, 40, 'x');"), Error::Constraint(_)));
+        s.execute("insert into t values (4, null, null);")?;
+        // 同一条语句中的重复值同样报错，并且整条语句回滚
+        assert!(matches!(
+            err(&mut s, "insert into t values (5, 50, 'z'), (6, 60, 'z');"),
+            Error::Constraint(_)
+        ));
+        let rows: Vec<i64> = s.query_as("select a from t;")?;
+        assert_eq!(rows, vec![1, 2, 3, 4]);
+
+        // 更新时旧值被释放，新值不能和其他行重复
+        assert!(matches!(err(&mut s, "update t set b = 20 where a = 1;"), Error::Constraint(_)));
+        s.execute("update t set b = 11, c = 'x' where a = 1;")?;
+        s.execute("insert into t values (5, 10, 'w');")?;
+        s.execute("update t set a = 100 where a = 1;")?;
+        assert!(matches!(err(&mut s, "insert into t values (6, 11, 'v');"), Error::Constraint(_)));
+        s.execute("delete from t where a = 100;")?;
+        s.execute("insert into t values (6, 11, 'x');")?;
+
+        // 并发的事务写入相同的值时产生写冲突
+        let mut s2 = kvengine.session()?;
+        s.execute("begin;")?;
+        s2.execute("begin;")?;
+        s.execute("insert into t values (7, 70, null);")?;
+        assert!(matches!(s2.execute("insert into t values (8, 70, null);"), Err(Error::WriteConflict)));
+        s.execute("commit;")?;
+        s2.execute("rollback;")?;
+
+        // 修改表结构时同样维护唯一约束
+        assert!(matches!(
+            err(&mut s, "alter table t add column d int unique default 1;"),
+            Error::Constraint(_)
+        ));
+        s.execute("alter table t rename column b to bb;")?;
+        assert!(matches!(err(&mut s, "insert into t values (8, 70, null);"), Error::Constraint(_)));
+        s.execute("truncate table t;")?;
+        s.execute("insert into t values (8, 70, 'x');")?;
+        s.execute("alter table t drop column bb;")?;
+        s.execute("alter table t add column bb int unique;")?;
+        s.execute("insert into t values (9, 'y', 70);")?;
+
+        assert!(s.execute("create table t2 (a int primary key, b int[] unique);").is_err());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_analyze() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int index, c text);")?;
+        s.execute("insert into t values (1, 1, 'x'), (2, 2, 'y'), (3, 1, 'z'), (4, 5, 'w');")?;
+        let plan = |s: &mut Session<_>, sql: &str| match s.execute(sql) {
+            Ok(ResultSet::Explain { plan }) => plan,
+            r => panic!("unexpected result {:?}", r),
+        };
+
+        let p1 = plan(&mut s, "explain analyze select b, count(a) from t where a > 1 group by b;");
+        assert!(p1.contains("Aggregate (b, count(a)) (actual rows=3 time="), "{}", p1);
+        assert!(p1.contains("Seq Scan On t [a, b] (a > 1) (actual rows=3 time="), "{}", p1);
+        assert!(p1.contains("Execution Time: "), "{}", p1);
+
+        // limit 读取到足够的行之后不再从下层读取
+        let p2 = plan(&mut s, "explain analyze select * from t limit 2;");
+        assert!(p2.contains("Limit 2 (actual rows=2"), "{}", p2);
+        assert!(p2.contains("Seq Scan On t Limit 2 (actual rows=2"), "{}", p2);
+
+        // join 的两个子节点分别统计
+        s.execute("create table t2 (a int primary key);")?;
+        s.execute("insert into t2 values (1), (3);")?;
+        let p3 = plan(&mut s, "explain analyze select * from t join t2 on t.a = t2.a;");
+        assert!(p3.contains("Join(t.a = t2.a) (actual rows=2"), "{}", p3);
+        assert!(p3.contains("Seq Scan On t (actual rows=4"), "{}", p3);
+        assert!(p3.contains("Seq Scan On t2 (actual rows=2"), "{}", p3);
+
+        // 实际执行语句，修改的数据随事务提交
+        let p4 = plan(&mut s, "explain analyze update t set c = 'q' where b = 1;");
+        assert!(p4.contains("Update On t (actual rows=2"), "{}", p4);
+        assert!(p4.contains("Index Scan On t.b (actual rows=2"), "{}", p4);
+        let rows: Vec<String> = s.query_as("select c from t where c = 'q';")?;
+        assert_eq!(rows.len(), 2);
+        s.execute("begin;")?;
+        plan(&mut s, "explain analyze delete from t;");
+        s.execute("rollback;")?;
+        let rows: Vec<i64> = s.query_as("select a from t;")?;
+        assert_eq!(rows.len(), 4);
+
+        // 普通的 explain 不执行语句，不输出统计
+        let p5 = plan(&mut s, "explain delete from t;");
+        assert!(!p5.contains("actual"), "{}", p5);
+        let rows: Vec<i64> = s.query_as("select a from t;")?;
+        assert_eq!(rows.len(), 4);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_subquery() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int index, c text);")?;
+        s.execute("insert into t values (1, 1, 'x'), (2, 2, 'y'), (3, 1, 'z'), (4, 5, 'w');")?;
+        s.execute("create table u (x int primary key, y int);")?;
+        s.execute("insert into u values (1, 5), (2, null);")?;
+
+        // IN 子查询，子查询的结果中有 NULL 时 NOT IN 没有匹配的行
+        let rows: Vec<i64> = s.query_as("select a from t where b in (select y from u);")?;
+        assert_eq!(rows, vec![4]);
+        let rows: Vec<i64> = s.query_as("select a from t where b not in (select y from u);")?;
+        assert!(rows.is_empty());
+        let rows: Vec<i64> =
+            s.query_as("select a from t where b not in (select y from u where y is distinct from null);")?;
+        assert_eq!(rows, vec![1, 2, 3]);
+
+        // EXISTS 子查询
+        let rows: Vec<i64> = s.query_as("select a from t where exists (select * from u where x = 2);")?;
+        assert_eq!(rows.len(), 4);
+        let rows: Vec<i64> =
+            s.query_as("select a from t where a > 2 and not exists (select * from u where x = 2);")?;
+        assert!(rows.is_empty());
+
+        // 标量子查询，没有数据时为 NULL，返回多行或者多列时报错
+        let rows: Vec<(i64, i64)> = s.query_as(
+            "select a, (select max(x) from u) as m from t where a = (select min(x) from u) + 1;",
+        )?;
+        assert_eq!(rows, vec![(2, 2)]);
+        // 没有别名时列名是子查询本身，不是子查询的结果
+        let sql = "select a, (select max(x) from u), exists (select * from u) from t where a = 1;";
+        match s.execute(sql)? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(
+                    names(&columns),
+                    vec!["a", "(SELECT max(x) FROM u)", "EXISTS (SELECT * FROM u)"]
+                );
+                let row = vec![Value::Integer(1), Value::Integer(2), Value::Boolean(true)];
+                assert_eq!(rows, vec![row]);
+            }
+            _ => unreachable!(),
+        }
+        let rows: Vec<i64> = s.query_as("select a from t where b = (select y from u where x = 3);")?;
+        assert!(rows.is_empty());
+        assert!(matches!(
+            s.execute("select a from t where b = (select y from u);"),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            s.execute("select a from t where b in (select x, y from u);"),
+            Err(Error::InvalidInput(_))
+        ));
+
+        // 嵌套的子查询，以及 update、delete、insert 中的子查询
+        s.execute("update t set c = 'm' where a in (select x from u);")?;
+        let rows: Vec<i64> = s.query_as(
+            "select a from t where a in (select x from u where x in (select b from t where c = 'm'));",
+        )?;
+        assert_eq!(rows, vec![1, 2]);
+        s.execute("insert into t values ((select max(a) from t) + 1, 0, 'n');")?;
+        s.execute("delete from t where b in (select y from u);")?;
+        let rows: Vec<i64> = s.query_as("select a from t;")?;
+        assert_eq!(rows, vec![1, 2, 3, 5]);
+
+        // 不支持引用外层查询的列，报错而不是按照子查询中的同名列计算
+        s.execute("create table v (a int primary key, y int);")?;
+        s.execute("insert into v values (1, 10), (7, 70);")?;
+        for sql in [
+            "select a from t where exists (select a from v where v.a = t.a);",
+            "select a from t where a in (select a from v where v.y = t.b);",
+            "select a from t where exists (select * from u where x = t.a);",
+        ] {
+            assert_eq!(
+                s.execute(sql),
+                Err(Error::NotFound("missing FROM-clause entry for table t".into())),
+                "{}",
+                sql
+            );
+        }
+        // 不带表名引用外层查询的列时，即使子查询没有数据也报错
+        s.execute("create table w (z int primary key);")?;
+        assert_eq!(
+            s.execute("select a from t where exists (select * from w where z = b);"),
+            Err(Error::NotFound("column b is not in table".into()))
+        );
+        // 子查询中的列引用子查询自己的表
+        let rows: Vec<i64> = s.query_as("select a from t where a in (select a from v where a < 5);")?;
+        assert_eq!(rows, vec![1]);
+        let rows: Vec<i64> = s.query_as("select a from t where exists (select a from v where v.a = 7);")?;
+        assert_eq!(rows, vec![1, 2, 3, 5]);
+        let rows: Vec<i64> = s.query_as("select a from t where exists (select a from v where a = 2);")?;
+        assert!(rows.is_empty());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_composite_primary_key() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        for layout in ["row", "columnar"] {
+            s.execute(&format!(
+                "create table t (a int, b text, c int, primary key (a, b)) with (layout = '{}');",
+                layout
+            ))?;
+            s.execute("insert into t values (2, 'x', 30), (1, 'y', 20), (1, 'x', 10);")?;
+            assert!(matches!(
+                s.execute("insert into t values (1, 'x', 5);"),
+                Err(Error::Duplicate(_))
+            ));
+
+            // 按照主键列依次排序
+            let rows: Vec<(i64, String, i64)> = s.query_as("select * from t;")?;
+            assert_eq!(
+                rows,
+                vec![(1, "x".into(), 10), (1, "y".into(), 20), (2, "x".into(), 30)]
+            );
+
+            // 所有主键列都有等值条件时按主键查找
+            match s.execute("explain select c from t where b = 'y' and a = 1.0 and c > 0;")? {
+                ResultSet::Explain { plan } => assert!(plan.contains("Primary Key Scan On t")),
+                _ => unreachable!(),
+            }
+            let rows: Vec<i64> = s.query_as("select c from t where b = 'y' and a = 1.0 and c > 0;")?;
+            assert_eq!(rows, vec![20]);
+            let rows: Vec<i64> = s.query_as("select c from t where a = 1;")?;
+            assert_eq!(rows, vec![10, 20]);
+
+            // 修改主键列，复合主键中的单个列上的索引
+            s.execute("create index ib on t (b);")?;
+            s.execute("update t set b = 'z' where a = 2;")?;
+            assert!(matches!(
+                s.execute("update t set b = 'x' where a = 1 and b = 'y';"),
+                Err(Error::Duplicate(_))
+            ));
+            let rows: Vec<i64> = s.query_as("select a from t where b = 'x';")?;
+            assert_eq!(rows, vec![1]);
+            s.execute("alter table t rename column c to d;")?;
+            s.execute("delete from t where a = 1 and b = 'x';")?;
+            let rows: Vec<(i64, String, i64)> = s.query_as("select * from t;")?;
+            assert_eq!(rows, vec![(1, "y".into(), 20), (2, "z".into(), 30)]);
+            s.execute("drop table t;")?;
+        }
+
+        s.execute("create table t (a int not null, b text not null, primary key (b, a));")?;
+        assert!(s.get_table("t".into())?.contains("PRIMARY KEY (a, b)"));
+        assert!(s.execute("create table t2 (a int primary key, b int primary key);").is_err());
+        assert!(s.execute("create table t2 (a int primary key, b int, primary key (b));").is_err());
+        assert!(s.execute("create table t2 (a int, primary key (a, a));").is_err());
+        assert!(s.execute("create table t2 (a int, primary key (b));").is_err());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_auto_increment() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key auto_increment, b text);")?;
+        s.execute("insert into t (b) values ('x'), ('y');")?;
+        s.execute("insert into t values (default, 'z');")?;
+        // 显式给出的值推进序号，NULL 同样分配序号
+        s.execute("insert into t values (10, 'w');")?;
+        s.execute("insert into t values (null, 'v');")?;
+        let rows: Vec<(i64, String)> = s.query_as("select * from t;")?;
+        assert_eq!(
+            rows,
+            vec![
+                (1, "x".into()),
+                (2, "y".into()),
+                (3, "z".into()),
+                (10, "w".into()),
+                (11, "v".into())
+            ]
+        );
+        assert!(matches!(
+            s.execute("insert into t values (2, 'a');"),
+            Err(Error::Duplicate(_))
+        ));
+        assert!(s.get_table("t".into())?.contains("id Integer PRIMARY KEY AUTO_INCREMENT"));
+
+        // 回滚的事务中分配的序号一起撤销
+        s.execute("begin;")?;
+        s.execute("insert into t (b) values ('r');")?;
+        s.execute("rollback;")?;
+        s.execute("insert into t (b) values ('u');")?;
+        let rows: Vec<i64> = s.query_as("select id from t where b = 'u';")?;
+        assert_eq!(rows, vec![12]);
+
+        s.execute("truncate table t;")?;
+        s.execute("insert into t (b) values ('a');")?;
+        let rows: Vec<i64> = s.query_as("select id from t;")?;
+        assert_eq!(rows, vec![1]);
+
+        // 只能是没有默认值的单个整数主键列
+        for sql in [
+            "create table t2 (id text primary key auto_increment);",
+            "create table t2 (id int auto_increment, b int primary key);",
+            "create table t2 (id int primary key auto_increment default 1);",
+            "create table t2 (a int auto_increment, b int, primary key (a, b));",
+            "alter table t alter column id set default 3;",
+        ] {
+            assert!(matches!(s.execute(sql), Err(Error::InvalidInput(_))), "{}", sql);
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_distinct() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int, c text);")?;
+        s.execute(
+            "insert into t values (1, 1, 'x'), (2, 1, 'x'), (3, 2, 'y'), (4, 2, 'x'), (5, null, 'x'), (6, null, 'x');",
+        )?;
+
+        // 按照整行去重，NULL 和 NULL 相同，保留第一次出现的顺序
+        let rows: Vec<(Option<i64>, String)> = s.query_as("select distinct b, c from t;")?;
+        assert_eq!(
+            rows,
+            vec![
+                (Some(1), "x".into()),
+                (Some(2), "y".into()),
+                (Some(2), "x".into()),
+                (None, "x".into())
+            ]
+        );
+        let rows: Vec<Option<i64>> = s.query_as("select distinct b + 1 as x from t;")?;
+        assert_eq!(rows, vec![Some(2), Some(3), None]);
+
+        // order by 之后去重，offset、limit 作用在去重之后的结果上
+        let rows: Vec<String> = s.query_as("select distinct c from t order by c desc;")?;
+        assert_eq!(rows, vec!["y", "x"]);
+        let rows: Vec<String> = s.query_as("select distinct c from t order by c limit 1 offset 1;")?;
+        assert_eq!(rows, vec!["y"]);
+        let rows: Vec<i64> = s.query_as("select distinct a from t limit 2;")?;
+        assert_eq!(rows, vec![1, 2]);
+        match s.execute("explain select distinct c from t limit 1;")? {
+            ResultSet::Explain { plan } => {
+                assert!(plan.contains("Limit 1\n  ->  Distinct\n    ->  Projection (c)"))
+            }
+            _ => unreachable!(),
+        }
+
+        // 分组聚集之后同样可以去重
+        let rows: Vec<i64> = s.query_as("select distinct count(c) from t group by b;")?;
+        assert_eq!(rows, vec![2]);
+
+        // 去重使用的内存受查询内存限制的约束
+        s.execute("set memory_limit = 10;")?;
+        assert!(matches!(
+            s.execute("select distinct b, c from t;"),
+            Err(Error::OutOfMemory(_))
+        ));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_csv() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        let dir = p.parent().unwrap();
+        s.execute("create table t (a int primary key, b text, c float default 1.5, d date, e bool);")?;
+
+        // 按照顺序对应表中的列，空字段是 NULL，缺少的字段使用默认值
+        let input = dir.join("input.csv");
+        std::fs::write(&input, "1,\"x, y\",2.5,2024-01-31,true\n2,,,,FALSE\n3,z\n")?;
+        let result = s.execute(&format!("copy t from '{}';", input.display()))?;
+        assert_eq!(result, ResultSet::Copy { count: 3 });
+        let rows: Vec<(i64, Option<String>, Option<f64>)> =
+            s.query_as("select a, b, c from t order by a;")?;
+        assert_eq!(
+            rows,
+            vec![
+                (1, Some("x, y".into()), Some(2.5)),
+                (2, None, None),
+                (3, Some("z".into()), Some(1.5)),
+            ]
+        );
+        let rows: Vec<(i64, Option<bool>)> = s.query_as("select a, e from t order by a;")?;
+        assert_eq!(rows, vec![(1, Some(true)), (2, Some(false)), (3, None)]);
+        let rows: Vec<(i64, String)> = s.query_as("select a, d from t where a = 1;")?;
+        assert_eq!(rows, vec![(1, "2024-01-31".into())]);
+
+        // 表头指定列名和顺序，分隔符可以修改
+        let input = dir.join("header.csv");
+        std::fs::write(&input, "e;a\ntrue;4\n")?;
+        s.execute(&format!(
+            "copy t from '{}' with (header = true, delimiter = ';');",
+            input.display()
+        ))?;
+        let rows: Vec<(i64, Option<String>, f64, bool)> =
+            s.query_as("select a, b, c, e from t where a = 4;")?;
+        assert_eq!(rows, vec![(4, None, 1.5, true)]);
+
+        // 导出之后再导入到另一张表中，数据不变
+        let output = dir.join("output.csv");
+        let result = s.execute(&format!(
+            "copy t to '{}' with (header = true);",
+            output.display()
+        ))?;
+        assert_eq!(result, ResultSet::Copy { count: 4 });
+        let text = std::fs::read_to_string(&output)?;
+        assert!(text.starts_with("a,b,c,d,e\n1,\"x, y\",2.5,2024-01-31,TRUE\n"));
+        s.execute("create table t2 (a int primary key, b text, c float, d date, e bool);")?;
+        s.execute(&format!(
+            "copy t2 from '{}' with (header = true);",
+            output.display()
+        ))?;
+        let all = |s: &mut Session<KVEngine<DiskEngine>>, table: &str| {
+            match s.execute(&format!("select * from {} order by a;", table)) {
+                Ok(ResultSet::Scan { rows, .. }) => rows,
+                _ => unreachable!(),
+            }
+        };
+        assert_eq!(all(&mut s, "t"), all(&mut s, "t2"));
+
+        // 类型错误、多余的字段、未知的列，整条语句回滚
+        let input = dir.join("bad.csv");
+        std::fs::write(&input, "5,a\n6,b,x\n")?;
+        assert!(matches!(
+            s.execute(&format!("copy t from '{}';", input.display())),
+            Err(Error::TypeMismatch(_))
+        ));
+        std::fs::write(&input, "5,a,1,2024-01-01,true,1\n")?;
+        assert!(matches!(
+            s.execute(&format!("copy t from '{}';", input.display())),
+            Err(Error::InvalidInput(_))
+        ));
+        std::fs::write(&input, "a,f\n5,1\n")?;
+        assert!(matches!(
+            s.execute(&format!("copy t from '{}' with (header = true);", input.display())),
+            Err(Error::NotFound(_))
+        ));
+        assert_eq!(all(&mut s, "t").len(), 4);
+        assert!(s.execute("copy t from 'no/such/file.csv';").is_err());
+        assert!(s.execute("copy t from 'a.csv' with (delimiter = ';;');").is_err());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_alias() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b int);")?;
+        s.execute("create table t2 (a int primary key, b int);")?;
+        s.execute("insert into t1 values (1, 10), (2, 20), (3, 30);")?;
+        s.execute("insert into t2 values (1, 20), (2, 10), (3, 3);")?;
+
+        let rows: Vec<(i64, i64)> =
+            s.query_as("select t1.a, x.b from t1 join t2 as x on t1.a = x.b;")?;
+        assert_eq!(rows, vec![(3, 3)]);
+        match s.execute("select * from t1 join t2 x on t1.a = x.a;")? {
+            ResultSet::Scan { columns, .. } => assert_eq!(names(&columns), vec!["t1.a", "t1.b", "x.a", "x.b"]),
+            _ => unreachable!(),
+        }
+
+        // 同一张表用不同的别名 join 自身
+        let rows: Vec<(i64, i64)> =
+            s.query_as("select x.a, y.a from t1 x join t1 y on x.b = y.b order by x.a;")?;
+        assert_eq!(rows, vec![(1, 1), (2, 2), (3, 3)]);
+
+        // where 条件在 join 的结果上过滤，带表名的列只匹配对应的表
+        let rows: Vec<(i64, i64)> =
+            s.query_as("select t1.b, t2.b from t1 join t2 on t1.a = t2.a where t2.b = 10;")?;
+        assert_eq!(rows, vec![(20, 10)]);
+        let rows: Vec<(i64, i64)> = s.query_as(
+            "select x.a, count(y.a) from t1 x left join t2 y on x.a = y.b where x.a < 3 group by x.a order by x.a;",
+        )?;
+        assert_eq!(rows, vec![(1, 0), (2, 0)]);
+
+        // 不带表名的列在两边都存在时报错，表名被别名替换之后不能再使用
+        for sql in [
+            "select b from t1 join t2 on t1.a = t2.a;",
+            "select * from t1 join t2 on t1.a = t2.a where b = 10;",
+        ] {
+            assert!(matches!(s.execute(sql), Err(Error::InvalidInput(_))), "{}", sql);
+        }
+        for sql in [
+            "select t2.b from t1 join t2 as x on t1.a = x.a;",
+            "select t1.a from t1 x join t2 on x.a = t2.a order by b;",
+        ] {
+            assert!(matches!(s.execute(sql), Err(Error::NotFound(_))), "{}", sql);
+        }
+
+        // 单表查询也可以使用别名
+        let rows: Vec<(i64, i64)> = s.query_as("select x.a, b from t1 as x where x.a = 2;")?;
+        assert_eq!(rows, vec![(2, 20)]);
+        let rows: Vec<i64> = s.query_as("select t1.b from t1 where t1.a = 3;")?;
+        assert_eq!(rows, vec![30]);
+
+        // 单表查询中的表名前缀同样需要是 from 中的表名或者别名
+        for (sql, table) in [
+            ("select nosuch.a from t1;", "nosuch"),
+            ("select a from t1 where t2.a = 2;", "t2"),
+            ("select t1.a from t1 as x;", "t1"),
+            ("select count(t2.a) from t1;", "t2"),
+            ("update t1 set b = t2.b where a = 1;", "t2"),
+            ("delete from t1 where t2.a = 1;", "t2"),
+        ] {
+            assert_eq!(
+                s.execute(sql),
+                Err(Error::NotFound(format!("missing FROM-clause entry for table {}", table))),
+                "{}",
+                sql
+            );
+        }
+        let rows: Vec<i64> = s.query_as("select b from t1 order by a;")?;
+        assert_eq!(rows, vec![10, 20, 30]);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_savepoint() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text index);")?;
+        s.execute("insert into t values (1, 'a');")?;
+        assert!(matches!(s.execute("savepoint sp;"), Err(Error::InvalidState(_))));
+
+        s.execute("begin;")?;
+        s.execute("insert into t values (2, 'b');")?;
+        assert_eq!(
+            s.execute("savepoint sp1;")?,
+            ResultSet::Savepoint { name: "sp1".into() }
+        );
+        s.execute("update t set b = 'x' where a = 1;")?;
+        s.execute("insert into t values (3, 'c');")?;
+        s.execute("savepoint sp2;")?;
+        s.execute("delete from t where a = 2;")?;
+        s.execute("create table t2 (a int primary key);")?;
+
+        // 只撤销 sp2 之后的写入，包括建表
+        s.execute("rollback to sp2;")?;
+        let rows: Vec<(i64, String)> = s.query_as("select * from t order by a;")?;
+        assert_eq!(rows, vec![(1, "x".into()), (2, "b".into()), (3, "c".into())]);
+        assert!(matches!(s.execute("select * from t2;"), Err(Error::NotFound(_))));
+
+        // 释放 sp2 之后不能再回滚到 sp2，回滚到 sp1 时索引的数据同样恢复
+        s.execute("release savepoint sp2;")?;
+        assert!(matches!(s.execute("rollback to sp2;"), Err(Error::NotFound(_))));
+        s.execute("rollback to savepoint sp1;")?;
+        let rows: Vec<(i64, String)> = s.query_as("select * from t where b = 'a';")?;
+        assert_eq!(rows, vec![(1, "a".into())]);
+        let rows: Vec<(i64, String)> = s.query_as("select * from t order by a;")?;
+        assert_eq!(rows, vec![(1, "a".into()), (2, "b".into())]);
+
+        // 回滚到保存点之后可以继续写入，提交时保留保存点之前的写入
+        s.execute("insert into t values (4, 'd');")?;
+        s.execute("commit;")?;
+        let rows: Vec<(i64, String)> = s.query_as("select * from t order by a;")?;
+        assert_eq!(rows, vec![(1, "a".into()), (2, "b".into()), (4, "d".into())]);
+
+        // 事务回滚时保存点之前和之后的写入都回滚
+        s.execute("begin;")?;
+        s.execute("delete from t where a = 1;")?;
+        s.execute("savepoint sp;")?;
+        s.execute("delete from t where a = 2;")?;
+        s.execute("rollback;")?;
+        let rows: Vec<(i64, String)> = s.query_as("select * from t order by a;")?;
+        assert_eq!(rows.len(), 3);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_constraint() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+
+        s.execute("create table t (a int primary key, b int check (b > 0), c int, check (b < c));")?;
+        s.execute("insert into t values (1, 1, 2);")?;
+        // 结果是 NULL 时视为满足约束
+        s.execute("insert into t values (2, null, 1);")?;
+        assert!(matches!(s.execute("insert into t values (3, 0, 2);"), Err(Error::Constraint(_))));
+        assert!(matches!(s.execute("insert into t values (3, 5, 2);"), Err(Error::Constraint(_))));
+        assert!(matches!(s.execute("update t set b = 0 where a = 1;"), Err(Error::Constraint(_))));
+        // 更新主键时同样校验
+        assert!(matches!(s.execute("update t set a = 9, c = 1 where a = 1;"), Err(Error::Constraint(_))));
+        s.execute("update t set a = 9 where a = 1;")?;
+        let rows: Vec<(i64, Option<i64>, i64)> = s.query_as("select * from t order by a;")?;
+        assert_eq!(rows, vec![(2, None, 1), (9, Some(1), 2)]);
+
+        // 约束只能引用当前行的列
+        for sql in [
+            "create table t2 (a int primary key check (x > 1));",
+            "create table t2 (a int primary key check (count(a) > 1));",
+            "create table t2 (a int primary key check (random() > 0.5));",
+            "create table t2 (a int primary key, check (a in (select a from t)));",
+        ] {
+            assert!(matches!(s.execute(sql), Err(Error::InvalidInput(_))));
+        }
+
+        // 新增的列用默认值填充已有的行，同样需要满足约束
+        assert!(matches!(
+            s.execute("alter table t add column d int default 0 check (d > 0);"),
+            Err(Error::Constraint(_))
+        ));
+        s.execute("alter table t add column d int default 1 check (d > 0);")?;
+        // 被约束引用的列不能删除，重命名时约束中的列名一起修改
+        assert!(matches!(s.execute("alter table t drop column c;"), Err(Error::InvalidInput(_))));
+        s.execute("alter table t rename column b to bb;")?;
+        assert!(s.get_table("t".into())?.contains("CHECK (bb < c)"));
+        assert!(matches!(s.execute("insert into t values (3, 5, 2, 1);"), Err(Error::Constraint(_))));
+        s.execute("alter table t drop column d;")?;
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_predicate_pushdown() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+
+        s.execute("create table t1 (a int primary key, b int index, c int);")?;
+        s.execute("create table t2 (a int primary key, b int, c int);")?;
+        s.execute("insert into t1 values (1, 10, 1), (2, 20, 2), (3, 30, 3);")?;
+        s.execute("insert into t2 values (1, 10, 1), (2, 20, 5), (4, 40, 4);")?;
+
+        let explain = |s: &mut Session<_>, sql: &str| match s.execute(sql) {
+            Ok(ResultSet::Explain { plan }) => plan,
+            _ => unreachable!(),
+        };
+        // 只引用一边的条件下推到扫描上，可以走索引，引用两边的条件留在 join 上
+        let sql = "select t1.a, t2.c from t1 join t2 on t1.a = t2.a where t1.b = 20 and t2.c > 0 and t1.c < t2.c;";
+        let plan = explain(&mut s, &format!("explain {}", sql));
+        assert!(plan.contains("Filter (t1.c < t2.c)"), "{}", plan);
+        assert!(plan.contains("Index Scan On t1.b"), "{}", plan);
+        assert!(plan.contains("Seq Scan On t2 (c > 0)"), "{}", plan);
+        let rows: Vec<(i64, i64)> = s.query_as(sql)?;
+        assert_eq!(rows, vec![(2, 5)]);
+
+        // 别名和嵌套的 join 同样下推
+        let plan = explain(
+            &mut s,
+            "explain select * from t1 x join t2 y on x.a = y.a join t2 z on z.a = y.a where z.c = 4 and x.b < 100;",
+        );
+        assert!(plan.contains("Index Range Scan On t1.b(-inf, 100)"), "{}", plan);
+        assert!(plan.contains("Seq Scan On t2 (c = 4)"), "{}", plan);
+
+        // 外连接中右边的条件在补 NULL 之后过滤，不能下推
+        let sql = "select x.a, y.c from t1 x left join t2 y on x.a = y.a where x.b > 15 and y.c is not distinct from null;";
+        let plan = explain(&mut s, &format!("explain {}", sql));
+        assert!(plan.contains("Filter (y.c IS NOT DISTINCT FROM NULL)"), "{}", plan);
+        assert!(plan.contains("Index Range Scan On t1.b(15, +inf)"), "{}", plan);
+        let rows: Vec<(i64, Option<i64>)> = s.query_as(sql)?;
+        assert_eq!(rows, vec![(3, None)]);
+
+        // 不带表名的列在两边都存在时不下推，依然报错
+        assert!(s.execute("select * from t1 join t2 on t1.a = t2.a where c = 1;").is_err());
+
+        // update ... from 中只引用一边的条件同样下推
+        let plan = explain(
+            &mut s,
+            "explain update t1 set c = 9 from t2 where t1.a = t2.a and t2.c = 5 and t1.b = 20;",
+        );
+        assert!(plan.contains("Hash Join(t1.a = t2.a)"), "{}", plan);
+        assert!(plan.contains("Index Scan On t1.b"), "{}", plan);
+        s.execute("update t1 set c = 9 from t2 where t1.a = t2.a and t2.c = 5 and t1.b = 20;")?;
+        let rows: Vec<(i64, i64)> = s.query_as("select a, c from t1 order by a;")?;
+        assert_eq!(rows, vec![(1, 1), (2, 9), (3, 3)]);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_external_sort() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int, c text);")?;
+        let values = (0..500)
+            .map(|i| format!("({}, {}, 'c-{}')", i, (i * 37) % 11, (i * 13) % 7))
+            .collect::<Vec<_>>();
+        s.execute(&format!("insert into t values {};", values.join(", ")))?;
+
+        // 内存限制很小时分成多个有序的临时文件再归并，结果和内存中排序相同，相等的行保持扫描的顺序
+        let queries = [
+            "select * from t order by b;",
+            "select * from t order by c desc, b;",
+            "select a, b from t where a > 100 order by b desc limit 20;",
+        ];
+        for sql in queries {
+            let expected: Vec<(i64, i64)> = s.query_as(&sql.replace("*", "a, b"))?;
+            s.execute("set memory_limit = 2048;")?;
+            let rows: Vec<(i64, i64)> = s.query_as(&sql.replace("*", "a, b"))?;
+            s.execute("set memory_limit = 0;")?;
+            assert_eq!(rows, expected, "{}", sql);
+        }
+
+        // 单独一行就超过限制时依然报错
+        s.execute("set memory_limit = 10;")?;
+        assert!(matches!(s.execute(queries[0]), Err(Error::OutOfMemory(_))));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_arithmetic() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int, c float);")?;
+        s.execute("insert into t values (1, 2, 1.5), (2, null, 2.0), (7, 3, 0.5);")?;
+
+        // 包含列的运算逐行计算，乘除的优先级高于加减，有 NULL 时结果为 NULL
+        match s.execute("select a * b + 1, a / b, c * 2, (a + 1) * 2 from t;")? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(names(&columns), vec!["a * b + 1", "a / b", "c * 2", "(a + 1) * 2"]);
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![Value::Integer(3), Value::Integer(0), Value::Float(3.0), Value::Integer(4)],
+                        vec![Value::Null, Value::Null, Value::Float(4.0), Value::Integer(6)],
+                        vec![Value::Integer(22), Value::Integer(2), Value::Float(1.0), Value::Integer(16)],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+        let rows: Vec<i64> = s.query_as("select a from t where a * 4 > b * 2 - 1;")?;
+        assert_eq!(rows, vec![1, 7]);
+
+        // 常量在解析时计算，整数运算的结果依然是整数
+        let rows: Vec<(i64, f64)> = s.query_as("select 7 / 2 * 3, 7 / 2.0 from t where a = 1;")?;
+        assert_eq!(rows, vec![(9, 3.5)]);
+
+        s.execute("update t set c = c * a / 2 where b is not distinct from 3;")?;
+        let rows: Vec<f64> = s.query_as("select c from t where a = 7;")?;
+        assert_eq!(rows, vec![1.75]);
+
+        assert!(matches!(s.execute("select a / (b - 2) from t;"), Err(Error::InvalidInput(_))));
+        assert!(matches!(s.execute("select 1 / 0.0 from t;"), Err(Error::InvalidInput(_))));
+        assert!(matches!(s.execute("select a * 'x' from t;"), Err(Error::TypeMismatch(_))));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_information_schema() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text default 'x', c float null);")?;
+        s.execute("create table t2 (id int primary key, v int[]);")?;
+
+        let rows: Vec<(String, String)> = s.query_as("select * from information_schema.tables;")?;
+        assert_eq!(
+            rows,
+            vec![("t1".into(), "BASE TABLE".into()), ("t2".into(), "BASE TABLE".into())]
+        );
+        match s.execute("select * from information_schema.columns where table_name = 't1';")? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(names(&columns)[..4], ["table_name", "column_name", "ordinal_position", "data_type"]);
+                let s = |v: &str| Value::String(v.into());
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![s("t1"), s("a"), Value::Integer(1), s("INTEGER"), Value::Boolean(false), Value::Null, Value::Boolean(true)],
+                        vec![s("t1"), s("b"), Value::Integer(2), s("STRING"), Value::Boolean(true), s("'x'"), Value::Boolean(false)],
+                        vec![s("t1"), s("c"), Value::Integer(3), s("FLOAT"), Value::Boolean(true), Value::Null, Value::Boolean(false)],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        // 虚拟表可以和其他表 join，建表、删表之后立即可见
+        s.execute("drop table t2;")?;
+        s.execute("create table t3 (k text primary key);")?;
+        let sql = "select t.table_name, count(c.column_name) from information_schema.tables t \
+                   join information_schema.columns c on t.table_name = c.table_name \
+                   group by t.table_name order by t.table_name;";
+        let rows: Vec<(String, i64)> = s.query_as(sql)?;
+        assert_eq!(rows, vec![("t1".into(), 3), ("t3".into(), 1)]);
+
+        match s.execute("explain select * from information_schema.tables where table_name = 't1';")? {
+            ResultSet::Explain { plan } => assert!(plan.contains("Virtual Scan On information_schema.tables"), "{}", plan),
+            _ => unreachable!(),
+        }
+        assert!(matches!(
+            s.execute("select * from information_schema.views;"),
+            Err(Error::NotFound(_))
+        ));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_isolation_level() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        let mut s2 = kvengine.session()?;
+        s.execute("create table t (a int primary key, b bool);")?;
+        s.execute("insert into t values (1, true), (2, true);")?;
+
+        // READ COMMITTED 每条语句都能看到之前提交的数据
+        s.execute("begin transaction isolation level read committed;")?;
+        s2.execute("insert into t values (3, true);")?;
+        match s.execute("select * from t;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 3),
+            _ => unreachable!(),
+        }
+        s.execute("commit;")?;
+
+        // REPEATABLE READ 看不到开启之后提交的数据
+        s.execute("begin isolation level repeatable read;")?;
+        match s.execute("select * from t;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 3),
+            _ => unreachable!(),
+        }
+        s2.execute("delete from t where a = 3;")?;
+        match s.execute("select * from t;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 3),
+            _ => unreachable!(),
+        }
+        s.execute("commit;")?;
+
+        // 写偏斜：两个事务都检查还有其他行为 true，然后各自修改不同的行
+        // REPEATABLE READ 下两个事务都能提交，SERIALIZABLE 下后提交的事务失败
+        let write_skew = |s: &mut Session<_>, s2: &mut Session<_>, begin: &str| -> Result<()> {
+            s.execute("update t set b = true;")?;
+            s.execute(begin)?;
+            s2.execute(begin)?;
+            s.execute("select * from t where b = true;")?;
+            s2.execute("select * from t where b = true;")?;
+            s.execute("update t set b = false where a = 1;")?;
+            s2.execute("update t set b = false where a = 2;")?;
+            s.execute("commit;")?;
+            s2.execute("commit;")?;
+            Ok(())
+        };
+        write_skew(&mut s, &mut s2, "begin;")?;
+        assert_eq!(
+            write_skew(&mut s, &mut s2, "begin transaction isolation level serializable;"),
+            Err(Error::Serialization)
+        );
+        assert!(!s2.in_transaction());
+        scan_table_and_compare(
+            &mut s,
+            "t",
+            vec![
+                vec![Value::Integer(1), Value::Boolean(false)],
+                vec![Value::Integer(2), Value::Boolean(true)],
+            ],
+        )?;
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_only_transaction() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int);")?;
+        s.execute("insert into t values (1, 1);")?;
+        let version = match s.execute("begin;")? {
+            ResultSet::Begin { version } => version,
+            _ => unreachable!(),
+        };
+        s.execute("commit;")?;
+        s.execute("update t set b = 2 where a = 1;")?;
+        s.execute("insert into t values (2, 2);")?;
+
+        // 只读事务不能写入
+        match s.execute("begin read only;")? {
+            ResultSet::Begin { version: v } => assert_eq!(v, version + 2),
+            _ => unreachable!(),
+        }
+        assert_eq!(
+            s.execute("insert into t values (3, 3);"),
+            Err(Error::ReadOnly("cannot write in a read-only transaction".into()))
+        );
+        s.execute("commit;")?;
+
+        // 读取历史版本
+        assert_eq!(
+            s.execute(&format!("begin transaction read only as of version {};", version))?,
+            ResultSet::Begin { version }
+        );
+        scan_table_and_compare(&mut s, "t", vec![vec![Value::Integer(1), Value::Integer(1)]])?;
+        // 存在只读事务时 vacuum 不清理旧版本
+        let mut s2 = kvengine.session()?;
+        assert_eq!(s2.execute("vacuum;")?, ResultSet::Vacuum { count: 0 });
+        scan_table_and_compare(&mut s, "t", vec![vec![Value::Integer(1), Value::Integer(1)]])?;
+        s.execute("rollback;")?;
+
+        assert_eq!(
+            s.execute("begin read only as of version 100;"),
+            Err(Error::InvalidInput(format!(
+                "version 100 does not exist, the latest version is {}",
+                version + 2
+            )))
+        );
+        match s2.execute("vacuum;")? {
+            ResultSet::Vacuum { count } => assert!(count > 0),
+            _ => unreachable!(),
+        }
+        // vacuum 之后历史版本已经被清理
+        assert_eq!(
+            s.execute(&format!("begin read only as of version {};", version)),
+            Err(Error::InvalidInput(format!(
+                "version {} has been removed by vacuum, the earliest version is {}",
+                version,
+                version + 2
+            )))
+        );
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_cast_and_coercion() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b float, c text, d bool);")?;
+        // 整数写入浮点数列时提升为浮点数
+        s.execute("insert into t values (1, 1, '10', true), (2, 2.5, '9', false);")?;
+        s.execute("update t set b = a + 1 where a = 1;")?;
+        scan_table_and_compare(
+            &mut s,
+            "t",
+            vec![
+                vec![Value::Integer(1), Value::Float(2.0), Value::String("10".into()), Value::Boolean(true)],
+                vec![Value::Integer(2), Value::Float(2.5), Value::String("9".into()), Value::Boolean(false)],
+            ],
+        )?;
+
+        // 数字、布尔值和字符串比较时，字符串按照对应的类型解析，主键查找和过滤的结果一致
+        let count = |s: &mut Session<_>, sql: &str| -> Result<usize> {
+            match s.execute(sql)? {
+                ResultSet::Scan { rows, .. } => Ok(rows.len()),
+                _ => unreachable!(),
+            }
+        };
+        assert_eq!(count(&mut s, "select * from t where a = '1';")?, 1);
+        assert_eq!(count(&mut s, "select * from t where a + 0 = '1';")?, 1);
+        assert_eq!(count(&mut s, "select * from t where b > '2';")?, 1);
+        assert_eq!(count(&mut s, "select * from t where d = 't';")?, 1);
+        assert!(matches!(
+            s.execute("select * from t where b = 'abc';"),
+            Err(Error::TypeMismatch(_))
+        ));
+        // 字符串之间按照字典序比较，转换之后按照数值比较
+        assert_eq!(count(&mut s, "select * from t where c > '9';")?, 0);
+        assert_eq!(count(&mut s, "select * from t where cast(c as int) > 9;")?, 1);
+
+        match s.execute("select cast(b as int), cast(a as text), cast(c as float), cast('t' as bool) from t where a = 2;")? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(columns[0].name, "CAST(b AS INTEGER)");
+                assert_eq!(
+                    rows,
+                    vec![vec![
+                        Value::Integer(3),
+                        Value::String("2".into()),
+                        Value::Float(9.0),
+                        Value::Boolean(true),
+                    ]]
+                );
+            }
+            _ => unreachable!(),
+        }
+        assert!(matches!(
+            s.execute("select cast(c as int) from t where a = 2 and cast('x' as float) = 1;"),
+            Err(Error::TypeMismatch(_))
+        ));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_decimal() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b decimal(10, 2) index, c numeric default 7);")?;
+        // 写入时四舍五入到列的小数位数
+        s.execute("insert into t (a, b) values (1, 0.1), (2, '0.2'), (3, decimal '1.005'), (4, 3);")?;
+        let query = |s: &mut Session<_>, sql: &str| -> Result<Vec<Vec<String>>> {
+            match s.execute(sql)? {
+                ResultSet::Scan { rows, .. } => Ok(rows
+                    .into_iter()
+                    .map(|row| row.iter().map(|v| v.to_string()).collect())
+                    .collect()),
+                _ => unreachable!(),
+            }
+        };
+        assert_eq!(
+            query(&mut s, "select b, c, b * 3 from t where a = 3;")?,
+            vec![vec!["1.01".to_string(), "7".into(), "3.03".into()]]
+        );
+
+        // 定点小数的运算和聚集是精确的
+        assert_eq!(
+            query(&mut s, "select b from t where b = decimal '0.3' - 0.2;")?,
+            vec![vec!["0.10".to_string()]]
+        );
+        assert_eq!(
+            query(&mut s, "select sum(b), avg(b) from t where a < 3;")?,
+            vec![vec!["0.30".to_string(), "0.150000".into()]]
+        );
+        assert_eq!(
+            query(&mut s, "select a from t where b > '0.15' and b < 2 order by b;")?,
+            vec![vec!["2".to_string()], vec!["3".into()]]
+        );
+        assert_eq!(query(&mut s, "select a from t where b = 1.015;")?.len(), 0);
+
+        // 超过列的精度时报错
+        assert!(s.execute("insert into t (a, b) values (5, 123456789.5);").is_err());
+        assert!(matches!(
+            s.execute("select * from t where b > 'abc';"),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(s.execute("select b / 0 from t;").is_err());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_blob() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a bytea primary key, b blob index, c int);")?;
+        // 二进制数据中可以有 0
+        s.execute("insert into t values (x'DEADBEEF', x'00', 1), (x'00ff00', x'', 2), ('\\x01', 'ab', 3);")?;
+        let blob = |b: &[u8]| Value::Blob(b.into());
+        scan_table_and_compare(
+            &mut s,
+            "t",
+            vec![
+                vec![blob(&[0, 255, 0]), blob(&[]), Value::Integer(2)],
+                vec![blob(&[1]), blob(b"ab"), Value::Integer(3)],
+                vec![blob(&[0xde, 0xad, 0xbe, 0xef]), blob(&[0]), Value::Integer(1)],
+            ],
+        )?;
+
+        let query = |s: &mut Session<_>, sql: &str| -> Result<Vec<Row>> {
+            match s.execute(sql)? {
+                ResultSet::Scan { rows, .. } => Ok(rows),
+                _ => unreachable!(),
+            }
+        };
+        // 主键、索引的等值查找和过滤
+        assert_eq!(
+            query(&mut s, "select c from t where a = x'deadbeef';")?,
+            vec![vec![Value::Integer(1)]]
+        );
+        assert_eq!(
+            query(&mut s, "select c from t where b = x'00';")?,
+            vec![vec![Value::Integer(1)]]
+        );
+        assert!(query(&mut s, "select c from t where b = 'ab' and a > x'01';")?.is_empty());
+        assert_eq!(
+            query(&mut s, "select cast(a as text) from t where c = 2;")?,
+            vec![vec![Value::String("\\x00ff00".into())]]
+        );
+        assert!(matches!(
+            s.execute("select * from t where a > 1 or c = 1;"),
+            Err(Error::TypeMismatch(_))
+        ));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_string_functions() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text, c text);")?;
+        s.execute("insert into t values (1, 'Hello', 'World'), (2, 'rust', null);")?;
+
+        let query = |s: &mut Session<_>, sql: &str| -> Result<Vec<Row>> {
+            match s.execute(sql)? {
+                ResultSet::Scan { rows, .. } => Ok(rows),
+                _ => unreachable!(),
+            }
+        };
+        let string = |s: &str| Value::String(s.into());
+        assert_eq!(
+            query(&mut s, "select upper(b), lower(c), length(b), substr(b, 2, 3), concat(b, ' ', c, a) from t;")?,
+            vec![
+                vec![string("HELLO"), string("world"), Value::Integer(5), string("ell"), string("Hello World1")],
+                vec![string("RUST"), Value::Null, Value::Integer(4), string("ust"), string("rust 2")],
+            ]
+        );
+        // 在 where 条件和 update 中使用
+        assert_eq!(
+            query(&mut s, "select a from t where upper(substring(b, 1, 1)) = 'R';")?,
+            vec![vec![Value::Integer(2)]]
+        );
+        s.execute("update t set c = concat(upper(b), '!') where length(b) > 4;")?;
+        assert_eq!(
+            query(&mut s, "select c from t where a = 1;")?,
+            vec![vec![string("HELLO!")]]
+        );
+        assert!(matches!(
+            s.execute("select upper(a) from t;"),
+            Err(Error::TypeMismatch(_))
+        ));
+        assert!(matches!(
+            s.execute("select length(b, c) from t;"),
+            Err(Error::InvalidInput(_))
+        ));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_operations() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b int, c text);")?;
+        s.execute("create table t2 (a int primary key, b int);")?;
+        s.execute("insert into t1 values (1, 1, 'x'), (2, 2, 'y'), (3, 2, 'z'), (4, 3, null);")?;
+        s.execute("insert into t2 values (1, 2), (2, 3), (3, 3), (4, 4);")?;
+
+        let query = |s: &mut Session<_>, sql: &str| -> Result<Vec<i64>> {
+            match s.execute(sql)? {
+                ResultSet::Scan { rows, .. } => Ok(rows
+                    .into_iter()
+                    .map(|row| match row[0] {
+                        Value::Integer(i) => i,
+                        _ => unreachable!(),
+                    })
+                    .collect()),
+                _ => unreachable!(),
+            }
+        };
+        assert_eq!(
+            query(&mut s, "select b from t1 union select b from t2 order by b;")?,
+            vec![1, 2, 3, 4]
+        );
+        assert_eq!(
+            query(&mut s, "select b from t1 union all select b from t2 order by b desc limit 3 offset 1;")?,
+            vec![3, 3, 3]
+        );
+        assert_eq!(
+            query(&mut s, "select b from t1 intersect select b from t2 order by b;")?,
+            vec![2, 3]
+        );
+        // t1 有 2 个 2、1 个 3，t2 有 1 个 2、2 个 3
+        assert_eq!(
+            query(&mut s, "select b from t1 intersect all select b from t2 order by b;")?,
+            vec![2, 3]
+        );
+        assert_eq!(query(&mut s, "select b from t1 except select b from t2;")?, vec![1]);
+        assert_eq!(
+            query(&mut s, "select b from t1 except all select b from t2 order by b;")?,
+            vec![1, 2]
+        );
+        // 从左到右结合：(t1 union t2) except t1
+        assert_eq!(
+            query(&mut s, "select b from t1 union select b from t2 except select b from t1;")?,
+            vec![4]
+        );
+        // 结果的列名取左边的列名，子查询中同样可以使用
+        match s.execute("select a as x, b from t1 where a = 1 union select b, a from t2 where a = 1;")? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(names(&columns), vec!["x".to_string(), "b".to_string()]);
+                assert_eq!(rows.len(), 2);
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(
+            query(&mut s, "select a from t1 where b in (select b from t2 where a = 1 union select b from t1 where a = 1) order by a;")?,
+            vec![1, 2, 3]
+        );
+
+        // 列数不同，或者同一列的类型不同
+        assert!(matches!(
+            s.execute("select a, b from t1 union select a from t2;"),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            s.execute("select c from t1 intersect select b from t2;"),
+            Err(Error::TypeMismatch(_))
+        ));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_limit_pushdown() -> Result<()> {
+        #[derive(Debug, serde::Deserialize)]
+        struct Profile {
+            rows_scanned: i64,
+            rows_returned: i64,
+        }
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int index, c text);")?;
+        s.execute("create table t2 (a int primary key, b int) with (layout = 'columnar');")?;
+        for i in 1..=20 {
+            s.execute(&format!("insert into t values ({}, {}, 'x');", i, i % 2))?;
+            s.execute(&format!("insert into t2 values ({}, {});", i, i * 10))?;
+        }
+        let plan = |s: &mut Session<_>, sql: &str| match s.execute(sql) {
+            Ok(ResultSet::Explain { plan }) => plan,
+            r => panic!("unexpected result {:?}", r),
+        };
+        let query = |s: &mut Session<_>, sql: &str| -> Result<Vec<Row>> {
+            match s.execute(sql)? {
+                ResultSet::Scan { rows, .. } => Ok(rows),
+                _ => unreachable!(),
+            }
+        };
+        let scanned = |s: &mut Session<_>| -> Result<(i64, i64)> {
+            let profile = s.query_as::<Profile>("show profile;")?.remove(0);
+            Ok((profile.rows_scanned, profile.rows_returned))
+        };
+
+        // 扫描只读取 limit + offset 行
+        let rows = query(&mut s, "select a from t limit 3 offset 2;")?;
+        assert_eq!(rows, vec![vec![Value::Integer(3)], vec![Value::Integer(4)], vec![Value::Integer(5)]]);
+        assert_eq!(scanned(&mut s)?, (5, 3));
+        let p1 = plan(&mut s, "explain select a from t limit 3 offset 2;");
+        assert!(p1.contains("Seq Scan On t [a] Limit 5"), "{}", p1);
+        assert_eq!(query(&mut s, "select * from t2 limit 2;")?.len(), 2);
+        assert_eq!(scanned(&mut s)?, (2, 2));
+
+        // 索引查询按照主键顺序读取前面的行
+        let rows = query(&mut s, "select a from t where b = 0 limit 2;")?;
+        assert_eq!(rows, vec![vec![Value::Integer(2)], vec![Value::Integer(4)]]);
+        let p2 = plan(&mut s, "explain select a from t where b = 0 limit 2;");
+        assert!(p2.contains("Index Only Scan On t.b Limit 2"), "{}", p2);
+
+        // 有过滤条件、排序、聚合时不下推，有过滤条件时扫描逐行读取，得到足够的行之后同样不再读取
+        assert_eq!(query(&mut s, "select a from t where c = 'x' limit 2;")?.len(), 2);
+        assert_eq!(scanned(&mut s)?, (2, 2));
+        let p3 = plan(&mut s, "explain select a from t order by a desc limit 2;");
+        assert!(!p3.contains("Seq Scan On t [a] Limit"), "{}", p3);
+        assert_eq!(query(&mut s, "select a from t order by a desc limit 1;")?, vec![vec![Value::Integer(20)]]);
+        assert_eq!(query(&mut s, "select a from t limit 0;")?.len(), 0);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_order_by_expression() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int, c text);")?;
+        s.execute("insert into t values (1, 3, 'x'), (2, 1, 'y'), (3, 2, 'x'), (4, 2, 'y');")?;
+
+        let query = |s: &mut Session<_>, sql: &str| -> Result<Vec<Row>> {
+            match s.execute(sql)? {
+                ResultSet::Scan { rows, .. } => Ok(rows),
+                _ => unreachable!(),
+            }
+        };
+        let first = |rows: Vec<Row>| rows.into_iter().map(|r| r[0].clone()).collect::<Vec<_>>();
+        let ints = |v: &[i64]| v.iter().map(|i| Value::Integer(*i)).collect::<Vec<_>>();
+
+        // select 中的别名、位置
+        assert_eq!(first(query(&mut s, "select a, b * 10 as x from t order by x, a desc;")?), ints(&[2, 4, 3, 1]));
+        assert_eq!(first(query(&mut s, "select a, b from t order by 2 desc, 1;")?), ints(&[1, 3, 4, 2]));
+        assert_eq!(first(query(&mut s, "select * from t order by 2, 1 desc;")?), ints(&[2, 4, 3, 1]));
+        // 表达式，可以引用 select 中没有的列
+        assert_eq!(first(query(&mut s, "select a from t order by b - a, a;")?), ints(&[4, 2, 3, 1]));
+        assert_eq!(
+            first(query(&mut s, "select distinct c from t order by concat(c, 'z') desc;")?),
+            vec![Value::String("y".into()), Value::String("x".into())]
+        );
+
+        // 聚集函数、分组时引用输出的列
+        assert_eq!(
+            query(&mut s, "select b, count(a) as n from t group by b order by n desc, b;")?,
+            vec![
+                vec![Value::Integer(2), Value::Integer(2)],
+                vec![Value::Integer(1), Value::Integer(1)],
+                vec![Value::Integer(3), Value::Integer(1)],
+            ]
+        );
+        assert_eq!(first(query(&mut s, "select c from t group by c order by sum(b) desc;")?).len(), 2);
+        assert_eq!(
+            query(&mut s, "select c from t group by c order by sum(b), c;")?,
+            vec![vec![Value::String("y".into())], vec![Value::String("x".into())]]
+        );
+        assert_eq!(first(query(&mut s, "select b as x, max(a) from t group by b order by x;")?), ints(&[1, 2, 3]));
+
+        // 集合操作的结果按照位置排序
+        assert_eq!(
+            first(query(&mut s, "select a from t where a < 3 union select b from t order by 1 desc;")?),
+            ints(&[3, 2, 1])
+        );
+        assert!(matches!(s.execute("select a from t order by 3;"), Err(Error::InvalidInput(_))));
+        assert!(matches!(s.execute("select * from t order by 0;"), Err(Error::InvalidInput(_))));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_covering_index() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int index, c text);")?;
+        s.execute("create table t2 (x int, y int, z int, primary key (x, y));")?;
+        s.execute("create index on t2 (z) using hash;")?;
+        for i in 1..=6 {
+            s.execute(&format!("insert into t values ({}, {}, 'c{}');", i, i % 3, i))?;
+            s.execute(&format!("insert into t2 values ({}, {}, {});", i, i * 10, i % 2))?;
+        }
+        let plan = |s: &mut Session<_>, sql: &str| match s.execute(sql) {
+            Ok(ResultSet::Explain { plan }) => plan,
+            r => panic!("unexpected result {:?}", r),
+        };
+
+        // 只用到索引列和主键时直接从索引得到结果
+        let rows: Vec<(i64, i64)> = s.query_as("select a, b from t where b = 1;")?;
+        assert_eq!(rows, vec![(1, 1), (4, 1)]);
+        let p1 = plan(&mut s, "explain select a, b from t where b = 1;");
+        assert!(p1.contains("Index Only Scan On t.b"), "{}", p1);
+        let rows: Vec<(i64,)> = s.query_as("select count(a) from t where b = 0;")?;
+        assert_eq!(rows, vec![(2,)]);
+        let rows: Vec<(i64, i64)> = s.query_as("select b, a from t where b > 0 and a < 5;")?;
+        assert_eq!(rows, vec![(1, 1), (1, 4), (2, 2)]);
+        let p2 = plan(&mut s, "explain select b, a from t where b > 0 and a < 5;");
+        assert!(p2.contains("Index Only Range Scan On t.b(0, +inf)"), "{}", p2);
+
+        // 用到其他列时依然需要回表
+        let rows: Vec<(String,)> = s.query_as("select c from t where b = 2;")?;
+        assert_eq!(rows, vec![("c2".to_string(),), ("c5".to_string(),)]);
+        let p3 = plan(&mut s, "explain select c from t where b = 2;");
+        assert!(p3.contains("Index Scan On t.b"), "{}", p3);
+        assert!(!p3.contains("Index Only"), "{}", p3);
+
+        // 复合主键的每一列从主键数组中取出
+        let rows: Vec<(i64, i64, i64)> = s.query_as("select y, x, z from t2 where z = 0 order by x;")?;
+        assert_eq!(rows, vec![(20, 2, 0), (40, 4, 0), (60, 6, 0)]);
+        let p4 = plan(&mut s, "explain select y, x, z from t2 where z = 0;");
+        assert!(p4.contains("Index Only Scan On t2.z"), "{}", p4);
+
+        // 更新、删除之后索引中的数据保持一致
+        s.execute("update t set b = 5 where a = 1;")?;
+        s.execute("delete from t where a = 4;")?;
+        let rows: Vec<(i64,)> = s.query_as("select a from t where b = 1;")?;
+        assert!(rows.is_empty());
+        let rows: Vec<(i64, i64)> = s.query_as("select a, b from t where b > 1;")?;
+        assert_eq!(rows, vec![(2, 2), (5, 2), (1, 5)]);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_with_options() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let options = DiskOptions {
+            durability: Durability::Periodic(std::time::Duration::from_secs(60)),
+            ..Default::default()
+        };
+        let kvengine = KVEngine::open(p.clone(), options)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text);")?;
+        for i in 1..=10 {
+            s.execute(&format!("insert into t values ({}, 'x');", i))?;
+        }
+        // 关闭时同步所有的数据
+        kvengine.close()?;
+        drop(s);
+
+        let kvengine = KVEngine::open(p.clone(), DiskOptions::default())?;
+        let mut s = kvengine.session()?;
+        let rows: Vec<(i64,)> = s.query_as("select count(a) from t;")?;
+        assert_eq!(rows, vec![(10,)]);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_backup_restore() -> Result<()> {
+        let dir = tempfile::tempdir()?.into_path();
+        let p = dir.join("sqldb-log");
+        let backup = dir.join("sqldb-backup");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?).with_result_cache(16);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int index);")?;
+        s.execute("insert into t values (1, 1), (2, 2), (3, 3);")?;
+        s.execute("update t set b = 10 where a = 1;")?;
+
+        // 事务中不允许备份和恢复
+        s.execute("begin;")?;
+        assert!(s.execute(&format!("backup to '{}';", backup.display())).is_err());
+        assert!(s.execute(&format!("restore from '{}';", backup.display())).is_err());
+        s.execute("commit;")?;
+
+        // 其他 session 的未提交数据不会出现在备份中
+        let mut s2 = kvengine.session()?;
+        s2.execute("begin;")?;
+        s2.execute("insert into t values (4, 4);")?;
+        assert!(matches!(
+            s.execute(&format!("backup to '{}';", backup.display()))?,
+            ResultSet::Backup { count } if count > 0
+        ));
+        s2.execute("commit;")?;
+        // 备份文件已经存在
+        assert!(s.execute(&format!("backup to '{}';", backup.display())).is_err());
+
+        // 修改数据之后恢复，结果缓存也要失效
+        scan_table_and_compare(
+            &mut s,
+            "t",
+            vec![
+                vec![Value::Integer(1), Value::Integer(10)],
+                vec![Value::Integer(2), Value::Integer(2)],
+                vec![Value::Integer(3), Value::Integer(3)],
+                vec![Value::Integer(4), Value::Integer(4)],
+            ],
+        )?;
+        s.execute("delete from t where a = 2;")?;
+        s.execute("create table t2 (a int primary key);")?;
+        assert!(matches!(
+            s.execute(&format!("restore from '{}';", backup.display()))?,
+            ResultSet::Restore { count } if count > 0
+        ));
+        let expect = vec![
+            vec![Value::Integer(1), Value::Integer(10)],
+            vec![Value::Integer(2), Value::Integer(2)],
+            vec![Value::Integer(3), Value::Integer(3)],
+        ];
+        scan_table_and_compare(&mut s, "t", expect.clone())?;
+        assert!(s.execute("select * from t2;").is_err());
+        match s.execute("select * from t where b = 10;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 1),
+            _ => unreachable!(),
+        }
+        s.execute("insert into t values (5, 5);")?;
+        assert!(s.execute("restore from '/path/not/exists';").is_err());
+
+        // 备份文件可以直接作为数据库打开
+        let kvengine = KVEngine::new(DiskEngine::new(backup.clone())?);
+        let mut s = kvengine.session()?;
+        scan_table_and_compare(&mut s, "t", expect)?;
+        s.execute("insert into t values (6, 6);")?;
+
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_dump() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute(
+            "create table t1 (a int primary key auto_increment, b text default 'it''s' not null, \
+             c float check (c > 0.0), d decimal(5, 2) unique, e blob);",
+        )?;
+        s.execute(
+            "create table t2 (a int, b text, primary key (a, b), check (a > 0)) \
+             with (layout = 'columnar');",
+        )?;
+        s.execute("create table t3 (a int index using hash, b date);")?;
+        s.execute("create index idx_b on t1 (b) using bitmap;")?;
+        s.execute("insert into t1 (b, c, d, e) values ('x', 1.5, 1.25, x'00ff');")?;
+        s.execute("insert into t1 (c) values (null);")?;
+        s.execute("insert into t2 values (1, 'a'), (2, 'b');")?;
+        s.execute("insert into t3 values (0 - 1, '2024-01-02'), (null, null);")?;
+
+        let mut buf = Vec::new();
+        s.dump(&mut buf)?;
+        let dump = String::from_utf8(buf).unwrap();
+        let lines = dump.lines().collect::<Vec<_>>();
+        assert_eq!(lines.first(), Some(&"BEGIN;"));
+        assert_eq!(lines.last(), Some(&"COMMIT;"));
+        assert!(lines.contains(&"CREATE INDEX idx_b ON t1 (b) USING BITMAP;"));
+        // 隐藏的 _rowid 列不导出
+        assert!(!dump.contains("_rowid"));
+
+        // 在新的数据库中执行导出的语句，数据和再次导出的结果都相同
+        let p2 = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine2 = KVEngine::new(DiskEngine::new(p2.clone())?);
+        let mut s2 = kvengine2.session()?;
+        for line in lines.iter() {
+            s2.execute(line)?;
+        }
+        let mut buf = Vec::new();
+        s2.dump(&mut buf)?;
+        assert_eq!(String::from_utf8(buf).unwrap(), dump);
+        for table in ["t1", "t2", "t3"] {
+            let sql = format!("select * from {};", table);
+            assert_eq!(s.execute(&sql)?, s2.execute(&sql)?);
+        }
+        assert_eq!(s.get_table("t1".into())?, s2.get_table("t1".into())?);
+        // 自增序号也恢复了
+        s2.execute("insert into t1 (c) values (2.0);")?;
+        match s2.execute("select a from t1 where c = 2.0;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Integer(3)]]),
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        std::fs::remove_dir_all(p2.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_column_types() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s = kvengine.session()?;
+        s.execute(
+            "create table t (a int primary key, b text not null, c float, d decimal(10, 2));",
+        )?;
+        s.execute("create table u (a int primary key, e bool not null);")?;
+        s.execute("insert into t values (1, 'x', 1.5, 2.25), (2, 'y', null, null);")?;
+        s.execute("insert into u values (1, true);")?;
+
+        let columns = |s: &mut Session<_>, sql: &str| match s.execute(sql) {
+            Ok(ResultSet::Scan { columns, .. }) => columns
+                .into_iter()
+                .map(|c| (c.name, c.datatype, c.nullable))
+                .collect::<Vec<_>>(),
+            r => panic!("{:?}", r),
+        };
+        let col = |name: &str, datatype: Option<DataType>, nullable: bool| {
+            (name.to_string(), datatype, nullable)
+        };
+
+        // 列保留表中的类型，别名只改变名字，表达式推断结果的类型
+        assert_eq!(
+            columns(&mut s, "select a, b as x, a + 1, c * 2, cast(a as text), \
+                             upper(b), null from t;"),
+            vec![
+                col("a", Some(DataType::Integer), false),
+                col("x", Some(DataType::String), false),
+                col("a + 1", Some(DataType::Integer), false),
+                col("c * 2", Some(DataType::Float), true),
+                col("CAST(a AS STRING)", Some(DataType::String), false),
+                col("upper(b)", Some(DataType::String), false),
+                col("NULL", None, true),
+            ]
+        );
+        // 聚集函数：count 不会为 NULL，sum 和 avg 的结果类型取决于输入
+        assert_eq!(
+            columns(&mut s, "select b, count(a) as n, sum(d), avg(a), max(c) from t group by b;"),
+            vec![
+                col("b", Some(DataType::String), false),
+                col("n", Some(DataType::Integer), false),
+                col("sum", Some(DataType::Decimal(38, 2)), true),
+                col("avg", Some(DataType::Float), true),
+                col("max", Some(DataType::Float), true),
+            ]
+        );
+        // left join 右边的列都可能为 NULL
+        assert_eq!(
+            columns(&mut s, "select * from t left join u on t.a = u.a;")[4..],
+            vec![
+                col("u.a", Some(DataType::Integer), true),
+                col("u.e", Some(DataType::Boolean), true),
+            ]
+        );
+        // union 两边的类型不能确定相同时结果类型未知
+        assert_eq!(
+            columns(&mut s, "select a, b from t union select a, null from u;"),
+            vec![col("a", Some(DataType::Integer), false), col("b", None, true)]
+        );
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_sessions() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        kvengine.session()?.execute("create table t (a int primary key, b int);")?;
+
+        // 多个线程各自使用一个 session，同时写入不同的行并读取
+        let handles = (0..4)
+            .map(|i| {
+                let mut s = kvengine.session()?;
+                Ok(std::thread::spawn(move || -> Result<()> {
+                    for j in 0..25 {
+                        s.execute(&format!("insert into t values ({}, {});", i * 100 + j, i))?;
+                        match s.execute(&format!("select a from t where b = {};", i))? {
+                            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), j + 1),
+                            _ => unreachable!(),
+                        }
+                    }
+                    Ok(())
+                }))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+
+        let mut s = kvengine.session()?;
+        match s.execute("select count(a) from t;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Integer(100)]]),
+            _ => unreachable!(),
+        }
+
+        // 一个 session 的事务没有提交时，其他 session 可以同时读写
+        let mut s2 = kvengine.session()?;
+        s.execute("begin;")?;
+        s.execute("update t set b = 10 where a = 0;")?;
+        s2.execute("insert into t values (1000, 0);")?;
+        match s2.execute("select b from t where a = 0;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Integer(0)]]),
+            _ => unreachable!(),
+        }
+        assert_eq!(s2.execute("update t set b = 20 where a = 0;"), Err(Error::WriteConflict));
+        s.execute("commit;")?;
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_row_locks() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let mut s1 = kvengine.session()?;
+        let mut s2 = kvengine.session()?;
+        s1.execute("create table t (a int primary key, b int);")?;
+        s1.execute("insert into t values (1, 0), (2, 0);")?;
+        s1.execute("set lock_timeout = 5000;")?;
+        s2.execute("set lock_timeout = 5000;")?;
+        // 等待另一个线程中的 session 进入锁等待
+        let locks = kvengine.locks.clone();
+        let wait = || -> Result<()> {
+            while locks.waiting_txns()? == 0 {
+                std::thread::yield_now();
+            }
+            Ok(())
+        };
+
+        // 默认不等待，行被锁住时直接返回写冲突
+        let mut s3 = kvengine.session()?;
+        s1.execute("begin;")?;
+        s1.execute("update t set b = 1 where a = 1;")?;
+        assert_eq!(s3.execute("delete from t where a = 1;"), Err(Error::WriteConflict));
+
+        // 等待持有锁的事务回滚之后继续写入
+        s2.execute("begin;")?;
+        let handle = std::thread::spawn(move || -> Result<_> {
+            s2.execute("update t set b = 2 where a = 1;")?;
+            s2.execute("commit;")?;
+            Ok(s2)
+        });
+        wait()?;
+        s1.execute("rollback;")?;
+        let mut s2 = handle.join().unwrap()?;
+        match s1.execute("select b from t where a = 1;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Integer(2)]]),
+            _ => unreachable!(),
+        }
+
+        // s1 持有 1 等待 2，s2 持有 2 再等待 1 时形成死锁，s2 的事务被回滚
+        s1.execute("begin;")?;
+        s1.execute("update t set b = 3 where a = 1;")?;
+        s2.execute("begin;")?;
+        s2.execute("update t set b = 4 where a = 2;")?;
+        let handle = std::thread::spawn(move || -> Result<_> {
+            s1.execute("update t set b = 3 where a = 2;")?;
+            s1.execute("commit;")?;
+            Ok(s1)
+        });
+        wait()?;
+        assert_eq!(s2.execute("update t set b = 4 where a = 1;"), Err(Error::Deadlock));
+        assert!(!s2.in_transaction());
+        let mut s1 = handle.join().unwrap()?;
+        match s1.execute("select b from t;")? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows, vec![vec![Value::Integer(3)], vec![Value::Integer(3)]])
             }
             _ => unreachable!(),
         }
+        assert_eq!(kvengine.locks.locked_rows()?, 0);
 
         std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
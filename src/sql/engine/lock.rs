@@ -0,0 +1,185 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Condvar, Mutex},
+    time::Duration,
+};
+
+// wasm32-unknown-unknown 上 std::time::Instant 不可用，使用 web-time 提供的实现
+#[cfg(not(feature = "wasm"))]
+use std::time::Instant;
+#[cfg(feature = "wasm")]
+use web_time::Instant;
+
+use crate::{
+    error::{Error, Result},
+    sql::types::Value,
+};
+
+// 行锁表，在多个 session 之间共享，key 是表名和主键，value 是持有锁的事务的版本号
+// 事务写入一行之前对这一行加锁，提交或者回滚时释放所有的锁，同一个事务可以重复加锁
+// 行已经被其他事务锁住时最多等待 timeout，超时返回 WriteConflict，timeout 为 0 时不等待
+// 等待之前沿着等待图检查，持有者直接或者间接地在等待当前事务时会形成死锁，当前事务返回 Deadlock
+pub struct LockManager {
+    state: Mutex<LockState>,
+    // 有事务释放锁时唤醒所有等待的事务
+    released: Condvar,
+}
+
+type RowKey = (String, Value);
+
+#[derive(Default)]
+struct LockState {
+    owners: HashMap<RowKey, u64>,
+    // 每个事务持有的锁，释放时使用
+    held: HashMap<u64, HashSet<RowKey>>,
+    // 等待图，正在等待的事务以及它等待的锁的持有者，每个事务同时只等待一把锁
+    waits_for: HashMap<u64, u64>,
+}
+
+impl LockState {
+    // 从 owner 开始沿着等待图查找，能回到 txn 说明 txn 等待 owner 会形成环
+    // 加入等待图之前都检查过，图中没有环，查找一定会结束
+    fn would_deadlock(&self, txn: u64, owner: u64) -> bool {
+        let mut next = Some(owner);
+        while let Some(t) = next {
+            if t == txn {
+                return true;
+            }
+            next = self.waits_for.get(&t).copied();
+        }
+        false
+    }
+}
+
+impl Default for LockManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LockManager {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(LockState::default()),
+            released: Condvar::new(),
+        }
+    }
+
+    // 事务 txn 锁住 table 中主键为 pk 的行
+    pub fn lock(&self, txn: u64, table: &str, pk: &Value, timeout: Duration) -> Result<()> {
+        let key = (table.to_string(), pk.clone());
+        let deadline = Instant::now() + timeout;
+        let mut state = self.state.lock()?;
+        loop {
+            let owner = match state.owners.get(&key) {
+                Some(owner) if *owner == txn => return Ok(()),
+                Some(owner) => *owner,
+                None => {
+                    state.owners.insert(key.clone(), txn);
+                    state.held.entry(txn).or_default().insert(key);
+                    return Ok(());
+                }
+            };
+            if state.would_deadlock(txn, owner) {
+                return Err(Error::Deadlock);
+            }
+            // wasm 中只有一个线程，等待的锁不会被释放
+            let now = Instant::now();
+            if cfg!(feature = "wasm") || now >= deadline {
+                return Err(Error::WriteConflict);
+            }
+            state.waits_for.insert(txn, owner);
+            state = self.released.wait_timeout(state, deadline - now)?.0;
+            state.waits_for.remove(&txn);
+        }
+    }
+
+    // 释放事务持有的所有锁，事务没有持有锁时什么也不做
+    pub fn release(&self, txn: u64) -> Result<()> {
+        let mut state = self.state.lock()?;
+        let Some(keys) = state.held.remove(&txn) else {
+            return Ok(());
+        };
+        for key in keys {
+            state.owners.remove(&key);
+        }
+        // 等待这个事务的边已经失效，被唤醒的事务重新检查
+        state.waits_for.retain(|_, owner| *owner != txn);
+        self.released.notify_all();
+        Ok(())
+    }
+
+    // 当前被锁住的行数
+    pub fn locked_rows(&self) -> Result<usize> {
+        Ok(self.state.lock()?.owners.len())
+    }
+
+    // 当前正在等待锁的事务数
+    pub fn waiting_txns(&self) -> Result<usize> {
+        Ok(self.state.lock()?.waits_for.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use super::LockManager;
+    use crate::{
+        error::{Error, Result},
+        sql::types::Value,
+    };
+
+    // 等待另一个线程中的事务进入等待状态
+    fn wait_for_waiter(locks: &LockManager) -> Result<()> {
+        while locks.waiting_txns()? == 0 {
+            std::thread::yield_now();
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock_manager() -> Result<()> {
+        let locks = Arc::new(LockManager::new());
+        let (a, b) = (Value::Integer(1), Value::Integer(2));
+        let no_wait = Duration::ZERO;
+
+        locks.lock(1, "t", &a, no_wait)?;
+        locks.lock(1, "t", &a, no_wait)?;
+        locks.lock(2, "u", &a, no_wait)?;
+        assert_eq!(locks.lock(2, "t", &a, no_wait), Err(Error::WriteConflict));
+        assert_eq!(
+            locks.lock(2, "t", &a, Duration::from_millis(20)),
+            Err(Error::WriteConflict)
+        );
+        assert_eq!(locks.locked_rows()?, 2);
+
+        // 事务 2 等待事务 1 释放锁
+        let waiter = {
+            let locks = locks.clone();
+            let a = a.clone();
+            std::thread::spawn(move || locks.lock(2, "t", &a, Duration::from_secs(10)))
+        };
+        wait_for_waiter(&locks)?;
+        locks.release(1)?;
+        waiter.join().unwrap()?;
+        locks.release(2)?;
+        assert_eq!(locks.locked_rows()?, 0);
+
+        // 事务 1 持有 a 等待 b，事务 2 持有 b 再等待 a 时形成死锁
+        locks.lock(1, "t", &a, no_wait)?;
+        locks.lock(2, "t", &b, no_wait)?;
+        let waiter = {
+            let locks = locks.clone();
+            let b = b.clone();
+            std::thread::spawn(move || locks.lock(1, "t", &b, Duration::from_secs(10)))
+        };
+        wait_for_waiter(&locks)?;
+        assert_eq!(locks.lock(2, "t", &a, Duration::from_secs(10)), Err(Error::Deadlock));
+        locks.release(2)?;
+        waiter.join().unwrap()?;
+        locks.release(1)?;
+        assert_eq!(locks.locked_rows()?, 0);
+        Ok(())
+    }
+}
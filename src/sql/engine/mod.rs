@@ -1,34 +1,163 @@
-use std::collections::HashSet;
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Bound,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+// wasm32-unknown-unknown 上 std::time::Instant 不可用，使用 web-time 提供的实现
+#[cfg(not(feature = "wasm"))]
+use std::time::Instant;
+#[cfg(feature = "wasm")]
+use web_time::Instant;
 
-use crate::error::{Error, Result};
+use serde::de::DeserializeOwned;
+
+use crate::{
+    error::{Error, Result},
+    storage::mvcc::IsolationLevel,
+};
+use bitmap::Bitmap;
 
 use super::{
-    executor::ResultSet,
+    executor::{column_names, memory::MemoryTracker, ColumnDesc, ResultSet},
     parser::{
-        ast::{self, Expression},
+        ast::{self, AlterTableAction, Expression},
         Parser,
     },
-    plan::Plan,
-    schema::Table,
-    types::{Row, Value},
+    plan::{dot, Node, Plan},
+    schema::{IndexType, Table},
+    types::{de::from_row, Row, Value},
 };
 
+pub mod audit;
+pub mod bitmap;
+pub mod cache;
+pub mod dump;
 pub mod kv;
+pub mod lock;
+pub mod profile;
+pub(crate) mod sys;
+
+use audit::{AuditLog, AuditRecord};
+use cache::ResultCache;
+use profile::{ExecStats, Profile};
+
+// 全局的 session id 分配器
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
 
 // 抽象的 SQL 引擎层定义，目前只有一个 KVEngine
 pub trait Engine: Clone {
     type Transaction: Transaction;
 
-    fn begin(&self) -> Result<Self::Transaction>;
+    fn begin(&self) -> Result<Self::Transaction> {
+        self.begin_with(IsolationLevel::default())
+    }
+
+    // 使用指定的隔离级别开启事务
+    fn begin_with(&self, isolation: IsolationLevel) -> Result<Self::Transaction>;
+
+    // 开启只读事务，as_of 不为空时读取这个版本的快照
+    fn begin_read_only(&self, as_of: Option<u64>) -> Result<Self::Transaction>;
+
+    // 清理旧版本数据并压缩存储，返回清理掉的版本数
+    fn vacuum(&self) -> Result<usize>;
+
+    // 把当前的数据备份到一个新的文件中，返回备份的 key 数量
+    // 没有文件系统的环境（wasm）不支持备份和恢复
+    fn backup(&self, _path: PathBuf) -> Result<usize> {
+        Err(Error::InvalidState("backup is not supported in this build".into()))
+    }
+
+    // 使用备份文件中的数据替换当前的所有数据，返回恢复的 key 数量
+    fn restore(&self, _path: PathBuf) -> Result<usize> {
+        Err(Error::InvalidState("restore is not supported in this build".into()))
+    }
+
+    // 把数据同步到磁盘上
+    fn flush(&self) -> Result<()>;
+
+    // 关闭引擎，同步数据并释放文件锁，之后不能再开启事务
+    fn close(&self) -> Result<()>;
+
+    // 引擎级别的 tracing subscriber，为空时使用全局的 subscriber
+    fn dispatch(&self) -> Option<tracing::Dispatch> {
+        None
+    }
+
+    // 查询结果缓存，为空时不缓存
+    fn result_cache(&self) -> Option<Arc<ResultCache>> {
+        None
+    }
 
     fn session(&self) -> Result<Session<Self>> {
         Ok(Session {
             engine: self.clone(),
             txn: None,
+            strict_types: false,
+            memory_limit: None,
+            lock_timeout: Duration::ZERO,
+            id: NEXT_SESSION_ID.fetch_add(1, Ordering::SeqCst),
+            user: None,
+            audit_log: None,
+            last_version: None,
+            cursors: HashMap::new(),
+            profile: Profile::default(),
         })
     }
+
+    // 在事务中执行 f：先开启事务，f 执行成功则提交，失败则回滚
+    fn with_txn<F, R>(&self, f: F) -> Result<R>
+    where
+        Self: 'static,
+        F: FnMut(&mut Session<Self>) -> Result<R>,
+    {
+        self.with_txn_retry(0, f)
+    }
+
+    // 和 with_txn 相同，遇到写冲突时回滚并重新执行 f，最多重试 retries 次
+    fn with_txn_retry<F, R>(&self, retries: usize, mut f: F) -> Result<R>
+    where
+        Self: 'static,
+        F: FnMut(&mut Session<Self>) -> Result<R>,
+    {
+        let mut session = self.session()?;
+        let mut attempts = 0;
+        loop {
+            session.execute_stmt(ast::Statement::Begin {
+                isolation: None,
+                read_only: false,
+                as_of: None,
+            })?;
+            let result = f(&mut session);
+            // f 中可能已经手动提交或者回滚了事务
+            let result = match (result, session.txn.is_some()) {
+                (Ok(value), true) => session.execute_stmt(ast::Statement::Commit).map(|_| value),
+                (Err(err), true) => {
+                    session.execute_stmt(ast::Statement::Rollback)?;
+                    Err(err)
+                }
+                (result, false) => result,
+            };
+            match result {
+                Err(Error::WriteConflict | Error::Serialization | Error::Deadlock)
+                    if attempts < retries =>
+                {
+                    attempts += 1
+                }
+                result => return result,
+            }
+        }
+    }
 }
 
+// 扫描表得到的行，按需解码和过滤，不需要一次性把整张表读到内存中
+pub type RowIter = Box<dyn Iterator<Item = Result<Row>> + Send>;
+
 // 抽象的事务信息，包含了 DDL 和 DML 操作
 // 底层可以接入普通的 KV 存储引擎，也可以接入分布式存储引擎
 pub trait Transaction {
@@ -38,6 +167,24 @@ pub trait Transaction {
     fn rollback(&self) -> Result<()>;
     // 版本号
     fn version(&self) -> u64;
+    // 每条语句执行之前调用，READ COMMITTED 事务在这里重新获取快照
+    fn begin_statement(&self) -> Result<()>;
+    // 创建保存点
+    fn savepoint(&mut self, name: String) -> Result<()>;
+    // 撤销保存点之后的写入
+    fn rollback_to_savepoint(&mut self, name: String) -> Result<()>;
+    // 删除保存点，保留写入
+    fn release_savepoint(&mut self, name: String) -> Result<()>;
+    // 设置是否禁止写入时的隐式类型转换
+    fn set_strict_types(&mut self, strict: bool);
+    // 设置单个查询中缓存数据的算子最多使用的内存，None 表示不限制
+    fn set_memory_limit(&mut self, limit: Option<usize>);
+    // 设置写入的行被其他事务锁住时最多等待的时间，0 表示不等待
+    fn set_lock_timeout(&mut self, timeout: Duration);
+    // 当前查询的内存统计
+    fn memory(&self) -> &MemoryTracker;
+    // 事务中累计读取的行数、读写的 key 数量
+    fn exec_stats(&self) -> ExecStats;
 
     // 创建行
     fn create_row(&mut self, table_name: String, row: Row) -> Result<()>;
@@ -46,7 +193,21 @@ pub trait Transaction {
     // 删除行
     fn delete_row(&mut self, table: &Table, id: &Value) -> Result<()>;
     // 扫描表
-    fn scan_table(&self, table_name: String, filter: Option<Expression>) -> Result<Vec<Row>>;
+    fn scan_table(&self, table_name: String, filter: Option<Expression>) -> Result<RowIter>;
+    // 扫描表，只返回 positions 指定的列，filter 按照这些列求值
+    fn scan_columns(
+        &self,
+        table_name: String,
+        filter: Option<Expression>,
+        positions: &[usize],
+    ) -> Result<RowIter>;
+    // 按照主键顺序扫描表的前 limit 行，读取到足够的行之后不再扫描，positions 不为空时只返回这些列
+    fn scan_limit(
+        &self,
+        table_name: String,
+        positions: Option<&[usize]>,
+        limit: usize,
+    ) -> Result<RowIter>;
     // 获取索引
     fn load_index(
         &self,
@@ -62,6 +223,32 @@ pub trait Transaction {
         col_value: &Value,
         index: HashSet<Value>,
     ) -> Result<()>;
+    // 获取索引列的值以 prefix 开头的所有索引，返回主键的并集
+    fn load_index_prefix(
+        &self,
+        table_name: &str,
+        col_name: &str,
+        prefix: &str,
+    ) -> Result<HashSet<Value>>;
+    // 获取索引列的值在 start 和 end 之间的所有索引，返回按照索引列的值排序的 (列值, 主键)
+    fn load_index_range(
+        &self,
+        table_name: &str,
+        col_name: &str,
+        start: Bound<Value>,
+        end: Bound<Value>,
+    ) -> Result<Vec<(Value, Value)>>;
+    // 全文索引中同时包含 query 中所有词的行的主键
+    fn load_fulltext(
+        &self,
+        table_name: &str,
+        col_name: &str,
+        query: &str,
+    ) -> Result<HashSet<Value>>;
+    // 位图索引中列值等于 col_value 的所有行，位置是主键的值
+    fn load_bitmap(&self, table_name: &str, col_name: &str, col_value: &Value) -> Result<Bitmap>;
+    // 扫描主键以 prefix 开头的所有行
+    fn scan_pk_prefix(&self, table_name: &str, prefix: &str) -> Result<Vec<Row>>;
     // 根据 id 获取行
     fn read_by_id(&self, table_name: &str, id: &Value) -> Result<Option<Row>>;
 
@@ -70,6 +257,22 @@ pub trait Transaction {
     fn create_table(&mut self, table: Table) -> Result<()>;
     // 删除表
     fn drop_table(&mut self, table_name: String) -> Result<()>;
+    // 清空表中的数据和索引，保留表结构，返回删除的行数
+    fn truncate_table(&mut self, table_name: String) -> Result<usize>;
+    // 修改表结构，必要时按新的表结构重写表中的数据
+    fn alter_table(&mut self, table_name: String, action: AlterTableAction) -> Result<()>;
+    // 根据表中的数据重建索引，不指定列时重建所有索引，返回重建的索引条目数
+    fn reindex(&mut self, table_name: String, col_name: Option<String>) -> Result<usize>;
+    // 在已有的表上创建索引，并根据表中的数据生成索引，返回生成的索引条目数
+    fn create_index(
+        &mut self,
+        table_name: String,
+        index_name: Option<String>,
+        col_name: String,
+        index_type: IndexType,
+    ) -> Result<usize>;
+    // 删除索引以及索引数据
+    fn drop_index(&mut self, table_name: String, index_name: String) -> Result<()>;
     // 获取所有的表名
     fn get_table_names(&self) -> Result<Vec<String>>;
     // 获取表信息
@@ -77,7 +280,7 @@ pub trait Transaction {
     // 获取表信息，不存在则报错
     fn must_get_table(&self, table_name: String) -> Result<Table> {
         self.get_table(table_name.clone())?
-            .ok_or(Error::Internal(format!(
+            .ok_or(Error::NotFound(format!(
                 "table {} does not exist",
                 table_name
             )))
@@ -88,60 +291,304 @@ pub trait Transaction {
 pub struct Session<E: Engine> {
     engine: E,
     txn: Option<E::Transaction>,
+    // set strict_types = on; 之后写入数据不再做隐式类型转换
+    strict_types: bool,
+    // set memory_limit = n; 之后单个查询最多使用 n 字节内存缓存数据，0 表示不限制
+    memory_limit: Option<usize>,
+    // set lock_timeout = n; 之后写入被其他事务锁住的行时最多等待 n 毫秒，0 表示不等待
+    lock_timeout: Duration,
+    // session 的唯一标识
+    id: u64,
+    // 当前 session 的用户，用于审计日志
+    user: Option<String>,
+    // 审计日志，为空则不记录
+    audit_log: Option<Arc<AuditLog>>,
+    // 最近一次执行语句所在事务的版本号
+    last_version: Option<u64>,
+    // 当前事务中声明的游标，事务结束时关闭
+    cursors: HashMap<String, Cursor>,
+    // 最近一条语句的执行统计
+    profile: Profile,
+}
+
+// 游标，保存查询结果的迭代器，每次 fetch 按需读取一部分
+struct Cursor {
+    columns: Vec<ColumnDesc>,
+    rows: RowIter,
 }
 
 impl<E: Engine + 'static> Session<E> {
+    // 开启事务，并带上当前 session 的设置
+    fn begin_txn(&self) -> Result<E::Transaction> {
+        self.begin_txn_with(IsolationLevel::default())
+    }
+
+    fn begin_txn_with(&self, isolation: IsolationLevel) -> Result<E::Transaction> {
+        let mut txn = self.engine.begin_with(isolation)?;
+        txn.set_strict_types(self.strict_types);
+        txn.set_memory_limit(self.memory_limit);
+        txn.set_lock_timeout(self.lock_timeout);
+        Ok(txn)
+    }
+
+    fn begin_read_only_txn(&self, as_of: Option<u64>) -> Result<E::Transaction> {
+        let mut txn = self.engine.begin_read_only(as_of)?;
+        txn.set_memory_limit(self.memory_limit);
+        Ok(txn)
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    // 是否在显式事务中
+    pub fn in_transaction(&self) -> bool {
+        self.txn.is_some()
+    }
+
+    // 设置当前 session 的用户
+    pub fn set_user(&mut self, user: String) {
+        self.user = Some(user);
+    }
+
+    // 开启审计日志，之后执行的 DML 和 DDL 语句都会被记录
+    pub fn set_audit_log(&mut self, audit_log: Arc<AuditLog>) {
+        self.audit_log = Some(audit_log);
+    }
+
     // 执行客户端 SQL 语句
     pub fn execute(&mut self, sql: &str) -> Result<ResultSet> {
-        //
-        match Parser::new(sql).parse()? {
-            ast::Statement::Begin if self.txn.is_some() => {
-                Err(Error::Internal("Already in transaction".into()))
+        self.execute_params(sql, Vec::new())
+    }
+
+    // 执行带有绑定参数的 SQL 语句，语句中的 $1、$2 依次替换成 params 中的值
+    pub fn execute_params(&mut self, sql: &str, params: Vec<Value>) -> Result<ResultSet> {
+        match self.engine.dispatch() {
+            Some(dispatch) => {
+                tracing::dispatcher::with_default(&dispatch, || self.execute_traced(sql, params))
+            }
+            None => self.execute_traced(sql, params),
+        }
+    }
+
+    fn execute_traced(&mut self, sql: &str, params: Vec<Value>) -> Result<ResultSet> {
+        let _enter = tracing::info_span!("statement", session = self.id, sql).entered();
+        let start = Instant::now();
+        let stmt = Parser::with_params(sql, params).parse()?;
+        // show profile 查看的是上一条语句，不覆盖统计
+        if stmt == ast::Statement::ShowProfile {
+            return Ok(self.profile.to_result_set());
+        }
+        self.profile = Profile {
+            statement: sql.trim().to_string(),
+            parse_time: start.elapsed(),
+            ..Default::default()
+        };
+
+        let audit_log = match &self.audit_log {
+            Some(audit_log) if audit::is_audited(&stmt) => audit_log.clone(),
+            _ => return self.execute_profiled(stmt),
+        };
+
+        let mut record = AuditRecord::new(self.id, self.user.clone(), sql);
+        self.last_version = None;
+        let result = self.execute_profiled(stmt);
+        record.version = self.last_version;
+        record.set_result(&result);
+        audit_log.record(&record)?;
+        result
+    }
+
+    fn execute_profiled(&mut self, stmt: ast::Statement) -> Result<ResultSet> {
+        let result = self.execute_stmt(stmt);
+        if let Ok(result) = &result {
+            self.profile.record_result(result);
+        }
+        result
+    }
+
+    fn execute_stmt(&mut self, stmt: ast::Statement) -> Result<ResultSet> {
+        match stmt {
+            ast::Statement::Begin { .. } if self.txn.is_some() => {
+                Err(Error::InvalidState("Already in transaction".into()))
             }
             ast::Statement::Commit | ast::Statement::Rollback if self.txn.is_none() => {
-                Err(Error::Internal("Not in transaction".into()))
+                Err(Error::InvalidState("Not in transaction".into()))
             }
-            ast::Statement::Begin => {
-                let txn = self.engine.begin()?;
+            ast::Statement::Begin {
+                isolation,
+                read_only,
+                as_of,
+            } => {
+                let txn = match read_only {
+                    true => self.begin_read_only_txn(as_of)?,
+                    false => self.begin_txn_with(isolation.unwrap_or_default())?,
+                };
                 let version = txn.version();
                 self.txn = Some(txn);
                 Ok(ResultSet::Begin { version })
             }
             ast::Statement::Commit => {
+                self.cursors.clear();
                 let txn = self.txn.take().unwrap();
                 let version = txn.version();
                 txn.commit()?;
                 Ok(ResultSet::Commit { version })
             }
             ast::Statement::Rollback => {
+                self.cursors.clear();
                 let txn = self.txn.take().unwrap();
                 let version = txn.version();
                 txn.rollback()?;
                 Ok(ResultSet::Rollback { version })
             }
-            ast::Statement::Explain { stmt } => {
-                let plan = match self.txn.as_ref() {
-                    Some(_) => Plan::build(*stmt, self.txn.as_mut().unwrap())?,
+            ast::Statement::Savepoint { .. }
+            | ast::Statement::RollbackToSavepoint { .. }
+            | ast::Statement::ReleaseSavepoint { .. }
+                if self.txn.is_none() =>
+            {
+                Err(Error::InvalidState("savepoints can only be used in a transaction".into()))
+            }
+            ast::Statement::Savepoint { name } => {
+                self.txn.as_mut().unwrap().savepoint(name.clone())?;
+                Ok(ResultSet::Savepoint { name })
+            }
+            ast::Statement::RollbackToSavepoint { name } => {
+                self.txn.as_mut().unwrap().rollback_to_savepoint(name.clone())?;
+                Ok(ResultSet::RollbackToSavepoint { name })
+            }
+            ast::Statement::ReleaseSavepoint { name } => {
+                self.txn.as_mut().unwrap().release_savepoint(name.clone())?;
+                Ok(ResultSet::ReleaseSavepoint { name })
+            }
+            ast::Statement::Explain {
+                stmt,
+                format,
+                analyze,
+            } => {
+                let plan = match self.txn.as_mut() {
+                    Some(txn) => {
+                        self.last_version = Some(txn.version());
+                        txn.begin_statement()?;
+                        explain(*stmt, format, analyze, txn)?
+                    }
                     None => {
-                        let mut txn = self.engine.begin()?;
-                        let plan = Plan::build(*stmt, &mut txn)?;
-                        txn.commit()?;
-                        plan
+                        let mut txn = self.begin_txn()?;
+                        self.last_version = Some(txn.version());
+                        match explain(*stmt, format, analyze, &mut txn) {
+                            Ok(plan) => {
+                                txn.commit()?;
+                                plan
+                            }
+                            Err(err) => {
+                                txn.rollback()?;
+                                return Err(err);
+                            }
+                        }
                     }
                 };
-                Ok(ResultSet::Explain {
-                    plan: plan.0.to_string(),
-                })
+                Ok(ResultSet::Explain { plan })
+            }
+            ast::Statement::Set { name, value } => {
+                match (name.as_str(), &value) {
+                    ("strict_types", Value::Boolean(strict)) => {
+                        self.strict_types = *strict;
+                        if let Some(txn) = self.txn.as_mut() {
+                            txn.set_strict_types(*strict);
+                        }
+                    }
+                    ("memory_limit", Value::Integer(limit)) if *limit >= 0 => {
+                        self.memory_limit = match *limit {
+                            0 => None,
+                            limit => Some(limit as usize),
+                        };
+                        if let Some(txn) = self.txn.as_mut() {
+                            txn.set_memory_limit(self.memory_limit);
+                        }
+                    }
+                    ("lock_timeout", Value::Integer(ms)) if *ms >= 0 => {
+                        self.lock_timeout = Duration::from_millis(*ms as u64);
+                        if let Some(txn) = self.txn.as_mut() {
+                            txn.set_lock_timeout(self.lock_timeout);
+                        }
+                    }
+                    ("strict_types" | "memory_limit" | "lock_timeout", v) => {
+                        return Err(Error::InvalidInput(format!(
+                            "invalid value {} for {}",
+                            v, name
+                        )))
+                    }
+                    (name, _) => {
+                        return Err(Error::NotFound(format!("unknown variable {}", name)))
+                    }
+                }
+                Ok(ResultSet::Set { name, value })
+            }
+            ast::Statement::Vacuum if self.txn.is_some() => {
+                Err(Error::InvalidState("VACUUM cannot run inside a transaction".into()))
+            }
+            ast::Statement::Vacuum => Ok(ResultSet::Vacuum {
+                count: self.engine.vacuum()?,
+            }),
+            ast::Statement::Backup { .. } if self.txn.is_some() => {
+                Err(Error::InvalidState("BACKUP cannot run inside a transaction".into()))
+            }
+            ast::Statement::Restore { .. } if self.txn.is_some() => {
+                Err(Error::InvalidState("RESTORE cannot run inside a transaction".into()))
             }
+            ast::Statement::Backup { path } => Ok(ResultSet::Backup {
+                count: self.engine.backup(path.into())?,
+            }),
+            ast::Statement::Restore { path } => Ok(ResultSet::Restore {
+                count: self.engine.restore(path.into())?,
+            }),
+            ast::Statement::DeclareCursor { .. }
+            | ast::Statement::Fetch { .. }
+            | ast::Statement::CloseCursor { .. }
+                if self.txn.is_none() =>
+            {
+                Err(Error::InvalidState("cursors can only be used in a transaction".into()))
+            }
+            ast::Statement::DeclareCursor { name, query } => {
+                if self.cursors.contains_key(&name) {
+                    return Err(Error::Duplicate(format!("cursor {} already exists", name)));
+                }
+                let txn = self.txn.as_mut().unwrap();
+                txn.begin_statement()?;
+                let (columns, rows) =
+                    run_plan(*query, txn, &mut self.profile, |_| {}, |plan, txn| plan.scan(txn))?;
+                self.cursors.insert(name.clone(), Cursor { columns, rows });
+                Ok(ResultSet::DeclareCursor { name })
+            }
+            ast::Statement::Fetch { name, count } => self.fetch(&name, count),
+            ast::Statement::CloseCursor { name } => match self.cursors.remove(&name) {
+                Some(_) => Ok(ResultSet::CloseCursor { name }),
+                None => Err(Error::NotFound(format!("cursor {} does not exist", name))),
+            },
             // 当事务存才时:
             stmt if self.txn.is_some() => {
-                Plan::build(stmt, self.txn.as_mut().unwrap())?.execute(self.txn.as_mut().unwrap())
+                let txn = self.txn.as_mut().unwrap();
+                self.last_version = Some(txn.version());
+                txn.begin_statement()?;
+                let result = execute_plan(stmt, txn, &mut self.profile, |_| {});
+                // 死锁时回滚整个事务，释放持有的锁，让等待的事务可以继续执行
+                if let Err(Error::Deadlock) = result {
+                    self.cursors.clear();
+                    self.txn.take().unwrap().rollback()?;
+                }
+                result
+            }
+            stmt @ (ast::Statement::Select { .. } | ast::Statement::SetOperation { .. })
+                if self.engine.result_cache().is_some() =>
+            {
+                self.execute_cached(stmt)
             }
             stmt => {
                 // 自动开启事务;
-                let mut txn = self.engine.begin()?;
-                // 构建 plan，执行 SQL 语句
-                match Plan::build(stmt, &mut txn)?.execute(&mut txn) {
+                let mut txn = self.begin_txn()?;
+                self.last_version = Some(txn.version());
+                // 构建 plan，执行 SQL 语句，构建失败时同样需要回滚事务
+                match execute_plan(stmt, &mut txn, &mut self.profile, |_| {}) {
                     Ok(result) => {
                         txn.commit()?;
                         Ok(result)
@@ -155,6 +602,66 @@ impl<E: Engine + 'static> Session<E> {
         }
     }
 
+    // 自动提交的查询先查找结果缓存，没有命中时执行查询并缓存结果
+    fn execute_cached(&mut self, stmt: ast::Statement) -> Result<ResultSet> {
+        let cache = self.engine.result_cache().unwrap();
+        // 解析之后的语句和 SQL 文本中的空格、大小写无关
+        let key = stmt.to_string();
+        if let Some(result) = cache.get(&key)? {
+            self.last_version = None;
+            return Ok(result);
+        }
+
+        let version = cache.version()?;
+        let mut txn = self.begin_txn()?;
+        self.last_version = Some(txn.version());
+        let mut tables = None;
+        let result = execute_plan(stmt, &mut txn, &mut self.profile, |plan| {
+            tables = cache::cacheable_tables(&plan.0);
+        })
+        .map(|result| (tables, result));
+        match result {
+            Ok((tables, result)) => {
+                txn.commit()?;
+                if let Some(tables) = tables {
+                    cache.insert(key, version, tables, result.clone())?;
+                }
+                Ok(result)
+            }
+            Err(err) => {
+                txn.rollback()?;
+                Err(err)
+            }
+        }
+    }
+
+    // 执行查询语句，并将结果的每一行转换成用户定义的类型
+    pub fn query_as<T: DeserializeOwned>(&mut self, sql: &str) -> Result<Vec<T>> {
+        match self.execute(sql)? {
+            ResultSet::Scan { columns, rows } => {
+                let columns = column_names(&columns);
+                rows.into_iter().map(|row| from_row(&columns, row)).collect()
+            }
+            result => Err(Error::InvalidInput(format!("not a query: {}", result))),
+        }
+    }
+
+    // 从游标中读取最多 count 行数据，count 为空时读取剩余的全部数据
+    pub fn fetch(&mut self, name: &str, count: Option<usize>) -> Result<ResultSet> {
+        let cursor = self
+            .cursors
+            .get_mut(name)
+            .ok_or(Error::NotFound(format!("cursor {} does not exist", name)))?;
+        let rows = match count {
+            Some(count) => cursor.rows.by_ref().take(count).collect::<Result<_>>()?,
+            None => cursor.rows.by_ref().collect::<Result<_>>()?,
+        };
+        Ok(ResultSet::Scan {
+            columns: cursor.columns.clone(),
+            rows,
+        })
+    }
+
     pub fn get_table(&self, table_name: String) -> Result<String> {
         let table = match self.txn.as_ref() {
             Some(txn) => txn.must_get_table(table_name)?,
@@ -182,4 +689,81 @@ impl<E: Engine + 'static> Session<E> {
 
         Ok(names.join("\n"))
     }
+
+    // 把所有表的结构和数据导出为 SQL 语句，写入 w 中
+    pub fn dump<W: std::io::Write>(&self, w: &mut W) -> Result<()> {
+        match self.txn.as_ref() {
+            Some(txn) => dump::dump(txn, w),
+            None => {
+                let txn = self.engine.begin_read_only(None)?;
+                let result = dump::dump(&txn, w);
+                txn.commit()?;
+                result
+            }
+        }
+    }
+}
+
+// 构建并执行 plan，记录构建、执行的耗时以及读写的数据量
+// on_plan 在执行之前调用，可以用来检查 plan
+fn execute_plan<T: Transaction + 'static>(
+    stmt: ast::Statement,
+    txn: &mut T,
+    profile: &mut Profile,
+    on_plan: impl FnOnce(&Plan),
+) -> Result<ResultSet> {
+    run_plan(stmt, txn, profile, on_plan, |plan, txn| plan.execute(txn))
+}
+
+// 构建 plan 并调用 run 执行，run 返回迭代器时只统计到返回迭代器为止的耗时
+fn run_plan<T: Transaction + 'static, R>(
+    stmt: ast::Statement,
+    txn: &mut T,
+    profile: &mut Profile,
+    on_plan: impl FnOnce(&Plan),
+    run: impl FnOnce(Plan, &mut T) -> Result<R>,
+) -> Result<R> {
+    let before = txn.exec_stats();
+    let start = Instant::now();
+    let plan = Plan::build(stmt, txn);
+    profile.plan_time = start.elapsed();
+    let plan = plan?;
+    on_plan(&plan);
+
+    let start = Instant::now();
+    let result = run(plan, txn);
+    profile.execute_time = start.elapsed();
+    profile.record_stats(before, txn.exec_stats());
+    result
+}
+
+// 生成执行计划的文本，dot 格式需要扫描每张表获取行数，用于估算每个节点的输出行数
+// analyze 时实际执行语句，修改的数据和普通语句一样随事务提交
+fn explain<T: Transaction + 'static>(
+    stmt: ast::Statement,
+    format: ast::ExplainFormat,
+    analyze: bool,
+    txn: &mut T,
+) -> Result<String> {
+    let plan = Plan::build(stmt, txn)?;
+    if analyze {
+        return Ok(plan.analyze(txn)?.to_string());
+    }
+    if format == ast::ExplainFormat::Text {
+        return Ok(plan.0.to_string());
+    }
+    let mut table_rows = HashMap::new();
+    let mut nodes = vec![&plan.0];
+    while let Some(node) = nodes.pop() {
+        // 子查询节点返回的是别名，不是表名
+        let name = node
+            .scan_table_name()
+            .filter(|_| !matches!(node, Node::Derived { .. }));
+        if let Some(name) = name.filter(|n| !table_rows.contains_key(n)) {
+            let count = txn.scan_table(name.clone(), None)?.count();
+            table_rows.insert(name, count);
+        }
+        nodes.extend(node.children());
+    }
+    Ok(dot::to_dot(&plan.0, &table_rows))
 }
@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use crate::sql::{
+    executor::{ColumnDesc, ResultSet},
+    types::{DataType, Value},
+};
+
+// 事务中累计的执行统计，执行语句前后的差值就是这条语句的统计
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ExecStats {
+    // 从存储中读取的行数，包括被过滤掉的行
+    pub rows_scanned: u64,
+    pub keys_read: u64,
+    pub keys_written: u64,
+}
+
+// 最近一条语句的执行统计，通过 show profile 查看
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Profile {
+    pub statement: String,
+    pub parse_time: Duration,
+    pub plan_time: Duration,
+    pub execute_time: Duration,
+    // 返回给客户端的行数，写入语句是影响的行数
+    pub rows_returned: u64,
+    pub stats: ExecStats,
+}
+
+impl Profile {
+    // 记录语句执行前后的统计差值
+    pub fn record_stats(&mut self, before: ExecStats, after: ExecStats) {
+        self.stats = ExecStats {
+            rows_scanned: after.rows_scanned - before.rows_scanned,
+            keys_read: after.keys_read - before.keys_read,
+            keys_written: after.keys_written - before.keys_written,
+        };
+    }
+
+    // 记录返回的行数
+    pub fn record_result(&mut self, result: &ResultSet) {
+        self.rows_returned = match result {
+            ResultSet::Scan { rows, .. } => rows.len(),
+            ResultSet::Insert { count }
+            | ResultSet::Copy { count }
+            | ResultSet::Update { count }
+            | ResultSet::Delete { count } => *count,
+            _ => 0,
+        } as u64;
+    }
+
+    // show profile 的结果，时间的单位是微秒
+    pub fn to_result_set(&self) -> ResultSet {
+        let columns = [
+            "statement",
+            "parse_us",
+            "plan_us",
+            "execute_us",
+            "rows_scanned",
+            "rows_returned",
+            "keys_read",
+            "keys_written",
+        ];
+        let us = |d: Duration| Value::Integer(d.as_micros() as i64);
+        ResultSet::Scan {
+            columns: columns
+                .iter()
+                .map(|&c| match c {
+                    "statement" => ColumnDesc::new(c, Some(DataType::String), false),
+                    _ => ColumnDesc::new(c, Some(DataType::Integer), false),
+                })
+                .collect(),
+            rows: vec![vec![
+                Value::String(self.statement.clone()),
+                us(self.parse_time),
+                us(self.plan_time),
+                us(self.execute_time),
+                Value::Integer(self.stats.rows_scanned as i64),
+                Value::Integer(self.rows_returned as i64),
+                Value::Integer(self.stats.keys_read as i64),
+                Value::Integer(self.stats.keys_written as i64),
+            ]],
+        }
+    }
+}
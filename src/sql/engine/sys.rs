@@ -0,0 +1,137 @@
+use crate::{
+    error::{Error, Result},
+    sql::{
+        executor::information_schema,
+        schema::{Column, IndexType, Layout, Table},
+        types::{encoding::encode_row, DataType, Row, Value},
+    },
+    storage::engine::Engine as StorageEngine,
+};
+
+use super::{kv::KVTransaction, Transaction};
+
+// 系统表，数据在查询时实时计算，只能通过 select 查询
+// sys.tables_stats: 每张表的行数、索引数、数据大小
+// sys.transactions: 当前活跃的事务
+// sys.storage: 存储引擎的状态，包括数据大小、版本数、压缩次数等
+// information_schema 中的虚拟表同样是系统表，只是表结构和数据由 executor::information_schema 提供
+const TABLES_STATS: &str = "sys.tables_stats";
+const TRANSACTIONS: &str = "sys.transactions";
+const STORAGE: &str = "sys.storage";
+
+pub fn is_sys_table(table_name: &str) -> bool {
+    table_name.starts_with("sys.") || information_schema::is_information_schema(table_name)
+}
+
+// 系统表不允许修改
+pub fn check_writable(table_name: &str) -> Result<()> {
+    if is_sys_table(table_name) {
+        return Err(Error::ReadOnly(format!(
+            "system table {} cannot be modified",
+            table_name
+        )));
+    }
+    Ok(())
+}
+
+// 获取系统表的表结构
+pub fn get_table(table_name: &str) -> Option<Table> {
+    if information_schema::is_information_schema(table_name) {
+        return information_schema::get_table(table_name);
+    }
+    let columns = match table_name {
+        TABLES_STATS => vec![
+            column("table_name", DataType::String, true),
+            column("row_count", DataType::Integer, false),
+            column("index_count", DataType::Integer, false),
+            column("bytes", DataType::Integer, false),
+        ],
+        TRANSACTIONS => vec![
+            column("version", DataType::Integer, true),
+            column("writes", DataType::Integer, false),
+            column("current", DataType::Boolean, false),
+        ],
+        STORAGE => vec![
+            column("engine", DataType::String, true),
+            column("keys", DataType::Integer, false),
+            column("versions", DataType::Integer, false),
+            column("size", DataType::Integer, false),
+            column("disk_size", DataType::Integer, false),
+            column("garbage", DataType::Integer, false),
+            column("compactions", DataType::Integer, false),
+        ],
+        _ => return None,
+    };
+    Some(Table {
+        name: table_name.into(),
+        columns,
+        layout: Layout::Row,
+        checks: Vec::new(),
+    })
+}
+
+// 扫描系统表，获取实时的数据
+pub fn scan_table<E: StorageEngine>(txn: &KVTransaction<E>, table_name: &str) -> Result<Vec<Row>> {
+    if information_schema::is_information_schema(table_name) {
+        return information_schema::scan_rows(txn, table_name);
+    }
+    let mut rows = Vec::new();
+    match table_name {
+        TABLES_STATS => {
+            for name in txn.get_table_names()? {
+                let table = txn.must_get_table(name.clone())?;
+                let (mut count, mut bytes) = (0, 0);
+                for row in txn.scan_table(name.clone(), None)? {
+                    count += 1;
+                    bytes += encode_row(&row?)?.len();
+                }
+                rows.push(vec![
+                    Value::String(name),
+                    Value::Integer(count),
+                    Value::Integer(table.columns.iter().filter(|c| c.index).count() as i64),
+                    Value::Integer(bytes as i64),
+                ]);
+            }
+        }
+        TRANSACTIONS => {
+            for (version, writes) in txn.status()?.active_txns {
+                rows.push(vec![
+                    Value::Integer(version as i64),
+                    Value::Integer(writes as i64),
+                    Value::Boolean(version == txn.version()),
+                ]);
+            }
+        }
+        STORAGE => {
+            let status = txn.status()?;
+            let storage = status.storage;
+            rows.push(vec![
+                Value::String(storage.name),
+                Value::Integer(storage.keys as i64),
+                Value::Integer(status.versions as i64),
+                Value::Integer(storage.size as i64),
+                Value::Integer(storage.disk_size as i64),
+                Value::Integer(storage.disk_size.saturating_sub(storage.size) as i64),
+                Value::Integer(storage.compactions as i64),
+            ]);
+        }
+        _ => {}
+    }
+    Ok(rows)
+}
+
+fn column(name: &str, datatype: DataType, primary_key: bool) -> Column {
+    Column {
+        name: name.into(),
+        datatype,
+        nullable: false,
+        default: None,
+        primary_key,
+        index: false,
+        index_type: IndexType::Ordered,
+        index_name: None,
+        unique: false,
+        auto_increment: false,
+        check: None,
+    }
+}
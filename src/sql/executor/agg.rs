@@ -1,27 +1,30 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+};
 
 use crate::{
     error::{Error, Result},
     sql::{
-        engine::Transaction,
-        parser::ast::{self, Expression},
-        types::Value,
+        engine::{RowIter, Transaction},
+        parser::ast::{self, column_position, field_position, Expression},
+        types::{decimal::MAX_PRECISION, DataType, Decimal, Row, Value},
     },
 };
 
-use super::{Executor, ResultSet};
+use super::{column_names, memory::row_size, ColumnDesc, Executor, ResultSet};
 
 pub struct Aggregate<T: Transaction> {
     source: Box<dyn Executor<T>>,
     exprs: Vec<(Expression, Option<String>)>,
-    group_by: Option<Expression>,
+    group_by: Vec<Expression>,
 }
 
 impl<T: Transaction> Aggregate<T> {
     pub fn new(
         source: Box<dyn Executor<T>>,
         exprs: Vec<(Expression, Option<String>)>,
-        group_by: Option<Expression>,
+        group_by: Vec<Expression>,
     ) -> Box<Self> {
         Box::new(Self {
             source,
@@ -33,121 +36,168 @@ impl<T: Transaction> Aggregate<T> {
 
 impl<T: Transaction> Executor<T> for Aggregate<T> {
     fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
-        if let ResultSet::Scan { columns, rows } = self.source.execute(txn)? {
-            let mut new_cols = Vec::new();
-            let mut new_rows = Vec::new();
-
-            // 计算函数
-            let mut calc = |col_val: Option<&Value>, rows: &Vec<Vec<Value>>|
-             -> Result<Vec<Value>> {
-                let mut new_row = Vec::new();
-                for (expr, alias) in &self.exprs {
-                    match expr {
-                        ast::Expression::Function(func_name, col_name) => {
-                            let calculator = <dyn Calculator>::build(&func_name)?;
-                            let val = calculator.calc(&col_name, &columns, rows)?;
-
-                            // min(a)            -> min
-                            // min(a) as min_val -> min_val
-                            if new_cols.len() < self.exprs.len() {
-                                new_cols.push(if let Some(a) = alias {
-                                    a.clone()
-                                } else {
-                                    func_name.clone()
-                                });
-                            }
-                            new_row.push(val);
-                        }
-                        ast::Expression::Field(col) => {
-                            if let Some(ast::Expression::Field(group_col)) = &self.group_by {
-                                if *col != *group_col {
-                                    return Err(Error::Internal(format!("{} must appear in the GROUP BY clause or aggregate function", col)));
-                                }
-                            }
-
-                            if new_cols.len() < self.exprs.len() {
-                                new_cols.push(if let Some(a) = alias {
-                                    a.clone()
-                                } else {
-                                    col.clone()
-                                });
-                            }
-                            new_row.push(col_val.unwrap().clone());
-                        }
-                        _ => return Err(Error::Internal("unexpected expression".into())),
+        let (source_columns, rows) = self.source.scan(txn)?;
+        let columns = column_names(&source_columns);
+        // 找到要分组的列的位置
+        let group_pos = self
+            .group_by
+            .iter()
+            .map(|expr| match expr {
+                ast::Expression::Field(table, col) => {
+                    field_position(&columns, table.as_deref(), col)
+                }
+                expr => Err(Error::InvalidInput(format!(
+                    "GROUP BY only supports columns, got {}",
+                    expr
+                ))),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // 输出的列名
+        // min(a)            -> min
+        // min(a) as min_val -> min_val
+        // 不在聚集函数中的列必须是分组的列，记录是第几个分组列
+        let mut new_cols = Vec::new();
+        let mut key_index = Vec::new();
+        for (expr, alias) in &self.exprs {
+            let column = match expr {
+                ast::Expression::Function(func_name, col_name) => {
+                    key_index.push(None);
+                    let arg = column_position(&columns, col_name)
+                        .ok()
+                        .map(|i| &source_columns[i]);
+                    let (datatype, nullable) = agg_type(func_name, arg);
+                    ColumnDesc::new(func_name, datatype, nullable)
+                }
+                ast::Expression::Field(table, col) => {
+                    let pos = field_position(&columns, table.as_deref(), col)?;
+                    match group_pos.iter().position(|p| *p == pos) {
+                        Some(i) => key_index.push(Some(i)),
+                        None => return Err(Error::InvalidInput(format!("{} must appear in the GROUP BY clause or aggregate function", expr))),
                     }
+                    source_columns[pos].clone().renamed(expr.to_string())
                 }
-                Ok(new_row)
+                _ => return Err(Error::Internal("unexpected expression".into())),
             };
+            new_cols.push(match alias {
+                Some(alias) => column.renamed(alias),
+                None => column,
+            });
+        }
 
-            // 判断有没有 Group By
-            // select c2, min(c1), max(c3) from t group by c2;
-            // c1 c2 c3
-            // 1 aa 4.6
-            // 3 cc 3.4
-            // 2 bb 5.2
-            // 4 cc 6.1
-            // 5 aa 8.3
-            // ----|------
-            // ----|------
-            // ----v------
-            // 1 aa 4.6
-            // 5 aa 8.3
-            //
-            // 2 bb 5.2
-            //
-            // 3 cc 3.4
-            // 4 cc 6.1
-            if let Some(ast::Expression::Field(group_col)) = &self.group_by {
-                // 对数据进行分组，然后计算每组的统计, 找到要分组的列索引index;
-                let pos = match columns.iter().position(|c| *c == *group_col) {
-                    Some(pos) => pos,
-                    None => {
-                        return Err(Error::Internal(format!("group by column {} not in table", group_col)))
+        // 计算一个分组的结果，key 是这个分组中分组列的值
+        let calc = |key: &[Value], rows: &[Row]| -> Result<Row> {
+            let mut new_row = Vec::new();
+            for ((expr, _), index) in self.exprs.iter().zip(&key_index) {
+                match (expr, index) {
+                    (ast::Expression::Function(func_name, col_name), _) => {
+                        let calculator = <dyn Calculator>::build(func_name)?;
+                        new_row.push(calculator.calc(col_name, &columns, rows)?);
                     }
-                };
-
-                // 针对 Group By 的列进行分组
-                let mut agg_map = HashMap::new();
-                for row in rows.iter() {
-                    let key = &row[pos];
-                    let value = agg_map.entry(key).or_insert(Vec::new());
-                    value.push(row.clone());
+                    (_, Some(i)) => new_row.push(key[*i].clone()),
+                    _ => return Err(Error::Internal("unexpected expression".into())),
                 }
+            }
+            Ok(new_row)
+        };
 
-                for (key, row) in agg_map {
-                    let row = calc(Some(key), &row)?;
-                    new_rows.push(row);
-                }
-            } else {
-                let row = calc(None, &rows)?;
-                new_rows.push(row);
+        // 按照 Group By 的列分组
+        // select c2, min(c1), max(c3) from t group by c2;
+        // c1 c2 c3
+        // 1 aa 4.6
+        // 3 cc 3.4
+        // 2 bb 5.2
+        // 4 cc 6.1
+        // 5 aa 8.3
+        // ----|------
+        // ----|------
+        // ----v------
+        // 1 aa 4.6
+        // 5 aa 8.3
+        //
+        // 2 bb 5.2
+        //
+        // 3 cc 3.4
+        // 4 cc 6.1
+        //
+        // 边读取边分组，读取每一行时申请这一行的内存，新建分组时复制出来的分组列的值同样计入
+        // 没有 Group By 时所有行的 key 都为空，属于同一个分组，没有输入行时也输出一行
+        let mut groups = HashMap::new();
+        let mut bytes = 0;
+        let new_rows = group_rows(txn, rows, &group_pos, &mut groups, &mut bytes).and_then(|_| {
+            if group_pos.is_empty() && groups.is_empty() {
+                groups.insert(Vec::new(), Vec::new());
             }
+            groups.iter().map(|(key, rows)| calc(key, rows)).collect::<Result<Vec<_>>>()
+        });
+        txn.memory().release(bytes);
 
-            return Ok(ResultSet::Scan {
-                columns: new_cols,
-                rows: new_rows,
-            });
-        }
+        Ok(ResultSet::Scan {
+            columns: new_cols,
+            rows: new_rows?,
+        })
+    }
+}
 
-        Err(Error::Internal("Unexpected result set".into()))
+// 针对 Group By 的列进行分组，多个分组列的值组合在一起作为分组的 key
+// bytes 累计已经申请的内存，出错时同样由调用方释放
+fn group_rows<T: Transaction>(
+    txn: &T,
+    rows: RowIter,
+    group_pos: &[usize],
+    groups: &mut HashMap<Vec<Value>, Vec<Row>>,
+    bytes: &mut usize,
+) -> Result<()> {
+    for row in rows {
+        let row = row?;
+        let key = group_pos.iter().map(|i| row[*i].clone()).collect::<Vec<_>>();
+        let mut size = row_size(&row);
+        let group = groups.get_mut(&key);
+        if group.is_none() {
+            size += row_size(&key);
+        }
+        txn.memory().reserve("Aggregate", size)?;
+        *bytes += size;
+        match group {
+            Some(group) => group.push(row),
+            None => {
+                groups.insert(key, vec![row]);
+            }
+        }
     }
+    Ok(())
+}
+
+// 聚集函数结果的类型，count 不会返回 NULL，其他函数在没有输入行或者输入都是 NULL 时返回 NULL
+// sum、avg 对整数和浮点数的计算结果是浮点数，对定点小数的计算结果是定点小数，avg 至少保留 6 位小数
+fn agg_type(func_name: &str, arg: Option<&ColumnDesc>) -> (Option<DataType>, bool) {
+    let datatype = arg.and_then(|c| c.datatype.clone());
+    let datatype = match (func_name.to_uppercase().as_str(), datatype) {
+        ("COUNT" | "APPROX_COUNT_DISTINCT", _) => return (Some(DataType::Integer), false),
+        ("MIN" | "MAX", datatype) => datatype,
+        ("SUM", Some(DataType::Decimal(_, s))) => Some(DataType::Decimal(MAX_PRECISION, s)),
+        ("AVG", Some(DataType::Decimal(_, s))) => Some(DataType::Decimal(MAX_PRECISION, s.max(6))),
+        ("SUM" | "AVG", Some(DataType::Integer | DataType::Float)) => Some(DataType::Float),
+        _ => None,
+    };
+    (datatype, true)
 }
 
 // 通用 Agg 计算定义
 pub trait Calculator {
-    fn calc(&self, col_name: &String, cols: &Vec<String>, rows: &Vec<Vec<Value>>) -> Result<Value>;
+    fn calc(&self, col_name: &str, cols: &[String], rows: &[Vec<Value>]) -> Result<Value>;
 }
 
 impl dyn Calculator {
-    pub fn build(func_name: &String) -> Result<Box<dyn Calculator>> {
+    pub fn build(func_name: &str) -> Result<Box<dyn Calculator>> {
         Ok(match func_name.to_uppercase().as_ref() {
             "COUNT" => Count::new(),
             "SUM" => Sum::new(),
             "MIN" => Min::new(),
             "MAX" => Max::new(),
             "AVG" => Avg::new(),
-            _ => return Err(Error::Internal("unknown aggregate function".into())),
+            "APPROX_COUNT_DISTINCT" => ApproxCountDistinct::new(),
+            _ => return Err(Error::NotFound("unknown aggregate function".into())),
         })
     }
 }
@@ -161,11 +211,8 @@ impl Count {
 }
 
 impl Calculator for Count {
-    fn calc(&self, col_name: &String, cols: &Vec<String>, rows: &Vec<Vec<Value>>) -> Result<Value> {
-        let pos = match cols.iter().position(|c| *c == *col_name) {
-            Some(pos) => pos,
-            None => return Err(Error::Internal(format!("column {} not in table", col_name))),
-        };
+    fn calc(&self, col_name: &str, cols: &[String], rows: &[Vec<Value>]) -> Result<Value> {
+        let pos = column_position(cols, col_name)?;
 
         // a b      c
         // 1 X     3.1
@@ -190,11 +237,8 @@ impl Min {
 }
 
 impl Calculator for Min {
-    fn calc(&self, col_name: &String, cols: &Vec<String>, rows: &Vec<Vec<Value>>) -> Result<Value> {
-        let pos = match cols.iter().position(|c| *c == *col_name) {
-            Some(pos) => pos,
-            None => return Err(Error::Internal(format!("column {} not in table", col_name))),
-        };
+    fn calc(&self, col_name: &str, cols: &[String], rows: &[Vec<Value>]) -> Result<Value> {
+        let pos = column_position(cols, col_name)?;
 
         // a b      c
         // 1 X     NULL
@@ -224,11 +268,8 @@ impl Max {
 }
 
 impl Calculator for Max {
-    fn calc(&self, col_name: &String, cols: &Vec<String>, rows: &Vec<Vec<Value>>) -> Result<Value> {
-        let pos = match cols.iter().position(|c| *c == *col_name) {
-            Some(pos) => pos,
-            None => return Err(Error::Internal(format!("column {} not in table", col_name))),
-        };
+    fn calc(&self, col_name: &str, cols: &[String], rows: &[Vec<Value>]) -> Result<Value> {
+        let pos = column_position(cols, col_name)?;
 
         // a b      c
         // 1 X     NULL
@@ -256,39 +297,45 @@ impl Sum {
     }
 }
 impl Calculator for Sum {
-    fn calc(&self, col_name: &String, cols: &Vec<String>, rows: &Vec<Vec<Value>>) -> Result<Value> {
-        let pos = match cols.iter().position(|c| *c == *col_name) {
-            Some(pos) => pos,
-            None => return Err(Error::Internal(format!("column {} not in table", col_name))),
-        };
+    fn calc(&self, col_name: &str, cols: &[String], rows: &[Vec<Value>]) -> Result<Value> {
+        let pos = column_position(cols, col_name)?;
 
         // a b      c
         // 1 X     NULL
         // 2 NULL  6.4
         // 3 Z     1.5
         let mut sum = None;
+        // 定点小数精确累加，结果仍然是定点小数
+        let mut decimal_sum: Option<Decimal> = None;
         for row in rows.iter() {
             match row[pos] {
                 Value::Null => {}
                 Value::Integer(v) => {
-                    if sum == None {
+                    if sum.is_none() {
                         sum = Some(0.0);
                     }
                     sum = Some(sum.unwrap() + v as f64);
                 }
                 Value::Float(v) => {
-                    if sum == None {
+                    if sum.is_none() {
                         sum = Some(0.0);
                     }
                     sum = Some(sum.unwrap() + v);
                 }
+                Value::Decimal(ref v) => {
+                    decimal_sum = Some(match decimal_sum {
+                        Some(s) => s.checked_add(**v)?,
+                        None => **v,
+                    });
+                }
                 _ => return Err(Error::Internal(format!("can not calc column {}", col_name))),
             }
         }
 
-        Ok(match sum {
-            Some(s) => Value::Float(s),
-            None => Value::Null,
+        Ok(match (sum, decimal_sum) {
+            (_, Some(s)) => s.into(),
+            (Some(s), None) => Value::Float(s),
+            (None, None) => Value::Null,
         })
     }
 }
@@ -302,12 +349,106 @@ impl Avg {
 }
 
 impl Calculator for Avg {
-    fn calc(&self, col_name: &String, cols: &Vec<String>, rows: &Vec<Vec<Value>>) -> Result<Value> {
+    fn calc(&self, col_name: &str, cols: &[String], rows: &[Vec<Value>]) -> Result<Value> {
         let sum = Sum::new().calc(col_name, cols, rows)?;
         let count = Count::new().calc(col_name, cols, rows)?;
         Ok(match (sum, count) {
             (Value::Float(s), Value::Integer(c)) => Value::Float(s / c as f64),
+            (Value::Decimal(s), Value::Integer(c)) => {
+                s.checked_div(Decimal::from_i64(c))?.into()
+            }
             _ => Value::Null,
         })
     }
 }
+
+// 近似的去重计数，使用 HyperLogLog 估算，不需要保存所有不同的值
+// 固定使用 2^14 个寄存器，内存占用 16KB，标准误差约为 0.8%
+pub struct ApproxCountDistinct;
+
+impl ApproxCountDistinct {
+    fn new() -> Box<Self> {
+        Box::new(Self {})
+    }
+}
+
+impl Calculator for ApproxCountDistinct {
+    fn calc(&self, col_name: &str, cols: &[String], rows: &[Vec<Value>]) -> Result<Value> {
+        let pos = column_position(cols, col_name)?;
+
+        let mut hll = HyperLogLog::new();
+        for row in rows.iter() {
+            if row[pos] != Value::Null {
+                hll.add(&row[pos]);
+            }
+        }
+        Ok(Value::Integer(hll.estimate().round() as i64))
+    }
+}
+
+// 寄存器个数的位数
+const HLL_PRECISION: u32 = 14;
+
+struct HyperLogLog {
+    // 每个寄存器记录分到这个寄存器的哈希值中，前导 0 的最大个数加一
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    fn new() -> Self {
+        Self {
+            registers: vec![0; 1 << HLL_PRECISION],
+        }
+    }
+
+    // 哈希值的高 14 位选择寄存器，剩余的位计算前导 0 的个数
+    fn add(&mut self, value: &Value) {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+        let index = (hash >> (64 - HLL_PRECISION)) as usize;
+        let rank = ((hash << HLL_PRECISION).leading_zeros() + 1).min(64 - HLL_PRECISION + 1);
+        self.registers[index] = self.registers[index].max(rank as u8);
+    }
+
+    fn estimate(&self) -> f64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|r| 2f64.powi(-(*r as i32))).sum();
+        let estimate = alpha * m * m / sum;
+        // 基数较小时还有空的寄存器，使用线性计数修正
+        let zeros = self.registers.iter().filter(|r| **r == 0).count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            estimate
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sql::types::Value;
+
+    use super::HyperLogLog;
+
+    #[test]
+    fn test_hyperloglog() {
+        let hll = HyperLogLog::new();
+        assert_eq!(hll.estimate(), 0.0);
+
+        let mut hll = HyperLogLog::new();
+        for i in 0..100_000 {
+            hll.add(&Value::Integer(i % 50_000));
+        }
+        let estimate = hll.estimate();
+        assert!((estimate - 50_000.0).abs() < 50_000.0 * 0.03, "{}", estimate);
+
+        // 基数较小时接近精确值
+        let mut hll = HyperLogLog::new();
+        for i in 0..100 {
+            hll.add(&Value::String(format!("value-{}", i % 10)));
+        }
+        assert_eq!(hll.estimate().round(), 10.0);
+    }
+}
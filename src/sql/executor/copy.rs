@@ -0,0 +1,160 @@
+use crate::{
+    error::{Error, Result},
+    sql::{
+        engine::Transaction,
+        parser::ast::CopyOptions,
+        schema::{Column, Table},
+        types::{coerce::coerce_value, DataType, Value},
+    },
+};
+
+use super::{mutation::make_row, Executor, ResultSet};
+
+// csv 文件中的空字段表示 NULL，导出时 NULL 也写成空字段
+// 其他的值按照列的类型解析，格式和查询结果中显示的一致
+
+// Copy From 执行器，逐行读取 csv 文件并插入到表中
+pub struct CopyFrom {
+    table_name: String,
+    path: String,
+    options: CopyOptions,
+}
+
+impl CopyFrom {
+    pub fn new(table_name: String, path: String, options: CopyOptions) -> Box<Self> {
+        Box::new(Self {
+            table_name,
+            path,
+            options,
+        })
+    }
+}
+
+impl<T: Transaction> Executor<T> for CopyFrom {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let table = txn.must_get_table(self.table_name.clone())?;
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(self.options.header)
+            .delimiter(self.options.delimiter)
+            .flexible(true)
+            .from_path(&self.path)?;
+
+        // 有表头时按照表头中的列名赋值，否则按照顺序对应表中可见的列
+        let columns = if self.options.header {
+            let columns = reader
+                .headers()?
+                .iter()
+                .map(|name| name.trim().to_string())
+                .collect::<Vec<_>>();
+            for name in &columns {
+                table.get_col_index(name)?;
+            }
+            columns
+        } else {
+            table
+                .columns
+                .iter()
+                .filter(|c| !c.is_hidden())
+                .map(|c| c.name.clone())
+                .collect()
+        };
+
+        let mut count = 0;
+        for record in reader.records() {
+            let record = record?;
+            if record.len() > columns.len() {
+                return Err(Error::InvalidInput(format!(
+                    "line {} has {} fields but only {} columns",
+                    record.position().map_or(0, |p| p.line()),
+                    record.len(),
+                    columns.len()
+                )));
+            }
+            // 缺少的字段使用列的默认值填充
+            let values = record
+                .iter()
+                .zip(&columns)
+                .map(|(field, name)| {
+                    let column = &table.columns[table.get_col_index(name)?];
+                    parse_field(field, column).map(Some)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let row = make_row(&table, &columns[..values.len()], &values)?;
+            txn.create_row(self.table_name.clone(), row)?;
+            count += 1;
+        }
+
+        Ok(ResultSet::Copy { count })
+    }
+}
+
+// 按照列的类型解析一个字段
+fn parse_field(field: &str, column: &Column) -> Result<Value> {
+    if field.is_empty() {
+        return Ok(Value::Null);
+    }
+    let mismatch = || {
+        Error::TypeMismatch(format!(
+            "invalid {} value {} for column {}",
+            column.datatype, field, column.name
+        ))
+    };
+    Ok(match column.datatype {
+        DataType::Boolean if field.eq_ignore_ascii_case("true") => Value::Boolean(true),
+        DataType::Boolean if field.eq_ignore_ascii_case("false") => Value::Boolean(false),
+        DataType::Boolean => return Err(mismatch()),
+        DataType::Integer => Value::Integer(field.trim().parse().map_err(|_| mismatch())?),
+        DataType::Float => Value::Float(field.trim().parse().map_err(|_| mismatch())?),
+        DataType::String => Value::String(field.to_string()),
+        ref datatype => coerce_value(Value::String(field.to_string()), datatype, false)?,
+    })
+}
+
+// Copy To 执行器，扫描表并逐行写入 csv 文件，文件已经存在时会被覆盖
+pub struct CopyTo {
+    table_name: String,
+    path: String,
+    options: CopyOptions,
+}
+
+impl CopyTo {
+    pub fn new(table_name: String, path: String, options: CopyOptions) -> Box<Self> {
+        Box::new(Self {
+            table_name,
+            path,
+            options,
+        })
+    }
+}
+
+impl<T: Transaction> Executor<T> for CopyTo {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let table: Table = txn.must_get_table(self.table_name.clone())?;
+        // 不导出隐藏的 _rowid 列
+        let visible = table
+            .columns
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| !c.is_hidden())
+            .collect::<Vec<_>>();
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(self.options.delimiter)
+            .from_path(&self.path)?;
+        if self.options.header {
+            writer.write_record(visible.iter().map(|(_, c)| c.name.as_str()))?;
+        }
+
+        let mut count = 0;
+        for row in txn.scan_table(self.table_name.clone(), None)? {
+            let row = row?;
+            writer.write_record(visible.iter().map(|(i, _)| match &row[*i] {
+                Value::Null => String::new(),
+                value => value.to_string(),
+            }))?;
+            count += 1;
+        }
+        writer.flush()?;
+
+        Ok(ResultSet::Copy { count })
+    }
+}
@@ -0,0 +1,151 @@
+use crate::{
+    error::{Error, Result},
+    sql::{
+        engine::{RowIter, Transaction},
+        parser::ast::{evaluate_expr, Expression},
+        schema::{Column, IndexType, Layout, Table},
+        types::{DataType, Row, Value},
+    },
+};
+
+use super::{collect_rows, column_names, ColumnDesc, Executor, ResultSet};
+
+// information_schema 中的虚拟表，数据在查询时根据表结构生成，只能通过 select 查询
+// information_schema.tables: 所有的表
+// information_schema.columns: 所有表的列，按照表名和列的顺序排列，不包括隐藏的 _rowid 列
+const TABLES: &str = "information_schema.tables";
+const COLUMNS: &str = "information_schema.columns";
+
+pub fn is_information_schema(table_name: &str) -> bool {
+    table_name.starts_with("information_schema.")
+}
+
+// 获取虚拟表的表结构
+pub fn get_table(table_name: &str) -> Option<Table> {
+    let columns = match table_name {
+        TABLES => vec![
+            column("table_name", DataType::String, true, false),
+            column("table_type", DataType::String, false, false),
+        ],
+        COLUMNS => vec![
+            column("table_name", DataType::String, true, false),
+            column("column_name", DataType::String, true, false),
+            column("ordinal_position", DataType::Integer, false, false),
+            column("data_type", DataType::String, false, false),
+            column("is_nullable", DataType::Boolean, false, false),
+            column("column_default", DataType::String, false, true),
+            column("is_primary_key", DataType::Boolean, false, false),
+        ],
+        _ => return None,
+    };
+    Some(Table {
+        name: table_name.into(),
+        columns,
+        layout: Layout::Row,
+        checks: Vec::new(),
+    })
+}
+
+// 通过 Transaction 获取所有的表结构，生成虚拟表的数据
+pub fn scan_rows<T: Transaction>(txn: &T, table_name: &str) -> Result<Vec<Row>> {
+    let mut rows = Vec::new();
+    match table_name {
+        TABLES => {
+            for name in txn.get_table_names()? {
+                rows.push(vec![
+                    Value::String(name),
+                    Value::String("BASE TABLE".into()),
+                ]);
+            }
+        }
+        COLUMNS => {
+            for name in txn.get_table_names()? {
+                let table = txn.must_get_table(name.clone())?;
+                let visible = table.columns.iter().filter(|c| !c.is_hidden());
+                for (i, col) in visible.enumerate() {
+                    rows.push(vec![
+                        Value::String(name.clone()),
+                        Value::String(col.name.clone()),
+                        Value::Integer(i as i64 + 1),
+                        Value::String(col.datatype.to_string()),
+                        Value::Boolean(col.nullable),
+                        // 可以为 NULL 的列默认值是 NULL，和没有默认值一样输出 NULL
+                        match &col.default {
+                            Some(Value::Null) | None => Value::Null,
+                            Some(v) => Value::String(Expression::from(v.clone()).to_string()),
+                        },
+                        Value::Boolean(col.primary_key),
+                    ]);
+                }
+            }
+        }
+        _ => {
+            return Err(Error::NotFound(format!(
+                "table {} does not exist",
+                table_name
+            )))
+        }
+    }
+    Ok(rows)
+}
+
+// 扫描虚拟表，filter 在生成的行上过滤
+pub struct VirtualScan {
+    table_name: String,
+    filter: Option<Expression>,
+}
+
+impl VirtualScan {
+    pub fn new(table_name: String, filter: Option<Expression>) -> Box<Self> {
+        Box::new(Self { table_name, filter })
+    }
+}
+
+impl<T: Transaction> Executor<T> for VirtualScan {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let (columns, rows) = self.scan(txn)?;
+        collect_rows(columns, rows)
+    }
+
+    fn scan(self: Box<Self>, txn: &mut T) -> Result<(Vec<ColumnDesc>, RowIter)> {
+        let table = txn.must_get_table(self.table_name.clone())?;
+        let descs = ColumnDesc::from_table(&table);
+        let columns = column_names(&descs);
+        let mut rows = Vec::new();
+        for row in scan_rows(txn, &self.table_name)? {
+            let keep = match &self.filter {
+                Some(filter) => match evaluate_expr(filter, &columns, &row, &columns, &row)? {
+                    Value::Null | Value::Boolean(false) => false,
+                    Value::Boolean(true) => true,
+                    v => {
+                        return Err(Error::TypeMismatch(format!(
+                            "where condition must be boolean, got {}",
+                            v
+                        )))
+                    }
+                },
+                None => true,
+            };
+            if keep {
+                rows.push(row);
+            }
+        }
+        Ok((descs, Box::new(rows.into_iter().map(Ok))))
+    }
+}
+
+fn column(name: &str, datatype: DataType, primary_key: bool, nullable: bool) -> Column {
+    Column {
+        name: name.into(),
+        datatype,
+        nullable,
+        default: None,
+        primary_key,
+        index: false,
+        index_type: IndexType::Ordered,
+        index_name: None,
+        unique: false,
+        auto_increment: false,
+        check: None,
+    }
+}
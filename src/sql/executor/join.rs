@@ -1,15 +1,23 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+};
 
 use crate::{
     error::{Error, Result},
     sql::{
-        engine::Transaction,
-        parser::ast::{self, evaluate_expr, Expression},
-        types::Value,
+        engine::{RowIter, Transaction},
+        parser::ast::{self, column_position, evaluate_expr, Expression},
+        types::{DataType, Row, Value},
     },
 };
 
-use super::{Executor, ResultSet};
+use super::{
+    memory::{row_size, value_size},
+    query::expr_type,
+    spill::SpillFile,
+    collect_rows, column_names, ColumnDesc, Executor, ResultSet,
+};
 
 pub struct NestedLoopJoin<T: Transaction> {
     left: Box<dyn Executor<T>>,
@@ -42,13 +50,15 @@ impl<T: Transaction> Executor<T> for NestedLoopJoin<T> {
             rows: lrows, } = self.left.execute(txn)? {
             let mut new_rows = Vec::new();
             let mut new_cols = lcols.clone();
+            let lcols = column_names(&lcols);
 
             // 再执行右边的 所有行;
             if let ResultSet::Scan {
                 columns: rcols,
                 rows: rrows, } = self.right.execute(txn)? {
                 // 左边列+右边列; 最后再统一进行取舍;
-                new_cols.extend(rcols.clone());
+                new_cols.extend(joined_columns(rcols.clone(), self.outer));
+                let rcols = column_names(&rcols);
 
                 // 左边多个行;
                 for lrow in &lrows {
@@ -82,9 +92,7 @@ impl<T: Transaction> Executor<T> for NestedLoopJoin<T> {
                     if self.outer && !matched {
                         // 右边行 的每一列都置为空;
                         let mut row = lrow.clone();
-                        for _ in 0..rrows[0].len() {
-                            row.push(Value::Null);
-                        }
+                        row.extend(std::iter::repeat_n(Value::Null, rcols.len()));
                         new_rows.push(row);
                     }
                 }
@@ -125,101 +133,328 @@ impl<T: Transaction> HashJoin<T> {
 
 impl<T: Transaction> Executor<T> for HashJoin<T> {
     fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
-        // 先执行左边的
-        if let ResultSet::Scan {
-            columns: lcols,
-            rows: lrows,
-        } = self.left.execute(txn)?
-        {
-            let mut new_rows = Vec::new();
-            let mut new_cols = lcols.clone();
-            // 再执行右边的
-            if let ResultSet::Scan {
-                columns: rcols,
-                rows: rrows,
-            } = self.right.execute(txn)?
-            {
-                new_cols.extend(rcols.clone());
-
-                // 解析 HashJoin 条件
-                let (lfield, rfield) = match parse_join_filter(self.predicate) {
-                    Some(filter) => filter,
-                    None => return Err(Error::Internal("failed to parse join predicate".into())),
-                };
-                // 获取 join 列在表中列的位置
-                let lpos = match lcols.iter().position(|c| *c == lfield) {
-                    Some(pos) => pos,
-                    None => {
-                        return Err(Error::Internal(format!(
-                            "column {} not exist in table",
-                            lfield
-                        )))
-                    }
-                };
-                let rpos = match rcols.iter().position(|c| *c == rfield) {
-                    Some(pos) => pos,
-                    None => {
-                        return Err(Error::Internal(format!(
-                            "column {} not exist in table",
-                            rfield
-                        )))
-                    }
-                };
+        // 右边的行边读取边构建哈希表，左边的行逐行读取查找哈希表，都不需要先全部读入内存
+        let (lcols, lrows) = self.left.scan(txn)?;
+        let (rcols, mut rrows) = self.right.scan(txn)?;
+        let mut new_cols = lcols.clone();
+        new_cols.extend(joined_columns(rcols.clone(), self.outer));
+        let lcols = column_names(&lcols);
+        let rcols = column_names(&rcols);
 
-                // 构建哈希表
-                let mut table = HashMap::new();
-                for row in &rrows {
-                    let rows = table.entry(row[rpos].clone()).or_insert(Vec::new());
-                    rows.push(row.clone());
-                }
+        // 等值条件中 NULL 和任何值都不相等，IS NOT DISTINCT FROM 时 NULL 和 NULL 相等
+        let null_safe = matches!(
+            self.predicate,
+            Some(Expression::Operation(ast::Operation::IsNotDistinctFrom(..)))
+        );
+        // 解析 HashJoin 条件
+        let (lfield, rfield) = match parse_join_filter(self.predicate) {
+            Some(filter) => filter,
+            None => return Err(Error::Internal("failed to parse join predicate".into())),
+        };
+        // 获取 join 列在表中列的位置，on 条件两边的列可以交换顺序
+        let (lpos, rpos) = match (
+            column_position(&lcols, &lfield),
+            column_position(&rcols, &rfield),
+        ) {
+            (Ok(lpos), Ok(rpos)) => (lpos, rpos),
+            (lres, rres) => match (
+                column_position(&lcols, &rfield),
+                column_position(&rcols, &lfield),
+            ) {
+                (Ok(lpos), Ok(rpos)) => (lpos, rpos),
+                _ => return Err(lres.and(rres).unwrap_err()),
+            },
+        };
 
-                // 扫描左边获取记录
-                for lrow in lrows {
-                    match table.get(&lrow[lpos]) {
-                        Some(rows) => {
-                            for r in rows {
-                                let mut row = lrow.clone();
-                                row.extend(r.clone());
-                                new_rows.push(row);
-                            }
-                        }
-                        None => {
-                            if self.outer {
-                                let mut row = lrow.clone();
-                                for _ in 0..rrows[0].len() {
-                                    row.push(Value::Null);
-                                }
-                                new_rows.push(row);
-                            }
-                        }
-                    }
-                }
+        // 右边的数据放得下内存时直接构建哈希表，否则分区写入临时文件后逐个分区 join
+        let mut new_rows = Vec::new();
+        match build_table(txn, &mut rrows, rpos, null_safe)? {
+            Build::Table(table, bytes) => {
+                let probed = probe(&table, lrows, lpos, self.outer, rcols.len(), &mut new_rows);
+                txn.memory().release(bytes);
+                probed?;
+            }
+            Build::Overflow(read, _) => grace_join(
+                txn,
+                (lrows, lpos),
+                (read.into_iter().map(Ok).chain(rrows), rpos),
+                null_safe,
+                (self.outer, rcols.len()),
+                &mut new_rows,
+            )?,
+        }
 
-                return Ok(ResultSet::Scan {
-                    columns: new_cols,
-                    rows: new_rows,
-                });
+        Ok(ResultSet::Scan {
+            columns: new_cols,
+            rows: new_rows,
+        })
+    }
+}
+
+// left join 时右边没有匹配的行输出 NULL，右边的列都可能为 NULL
+fn joined_columns(columns: Vec<ColumnDesc>, outer: bool) -> Vec<ColumnDesc> {
+    columns
+        .into_iter()
+        .map(|c| ColumnDesc {
+            nullable: c.nullable || outer,
+            ..c
+        })
+        .collect()
+}
+
+type HashTable = HashMap<Value, Vec<Row>>;
+
+// 最多分区数，每个分区左右两边各一个临时文件
+const MAX_PARTITIONS: usize = 64;
+
+// 构建哈希表的结果，超过内存限制时释放已经申请的内存，返回已经读取的行和内存不足的错误
+// 剩余没有读取的行还留在输入的迭代器中
+enum Build {
+    Table(HashTable, usize),
+    Overflow(Vec<Row>, Error),
+}
+
+// 边读取边构建哈希表，每读取一行申请这一行和哈希表 key 的内存，Table 中包含申请的内存大小
+fn build_table<T: Transaction>(
+    txn: &T,
+    rows: &mut impl Iterator<Item = Result<Row>>,
+    pos: usize,
+    null_safe: bool,
+) -> Result<Build> {
+    let mut table = HashTable::new();
+    let mut bytes = 0;
+    for row in rows {
+        let row = row?;
+        if row[pos] == Value::Null && !null_safe {
+            continue;
+        }
+        let size = row_size(&row) + value_size(&row[pos]);
+        if let Err(err) = txn.memory().reserve("HashJoin", size) {
+            txn.memory().release(bytes);
+            let read = table.into_values().flatten().chain(Some(row)).collect();
+            return Ok(Build::Overflow(read, err));
+        }
+        bytes += size;
+        table.entry(row[pos].clone()).or_default().push(row);
+    }
+    Ok(Build::Table(table, bytes))
+}
+
+// 用左边的每一行查找哈希表，rwidth 是右边的列数
+fn probe(
+    table: &HashTable,
+    lrows: impl Iterator<Item = Result<Row>>,
+    lpos: usize,
+    outer: bool,
+    rwidth: usize,
+    new_rows: &mut Vec<Row>,
+) -> Result<()> {
+    for lrow in lrows {
+        let lrow = lrow?;
+        match table.get(&lrow[lpos]) {
+            Some(rows) => {
+                for r in rows {
+                    let mut row = lrow.clone();
+                    row.extend(r.clone());
+                    new_rows.push(row);
+                }
+            }
+            None => {
+                if outer {
+                    let mut row = lrow;
+                    row.extend(std::iter::repeat_n(Value::Null, rwidth));
+                    new_rows.push(row);
+                }
             }
         }
-        Err(Error::Internal("Unexpected result set".into()))
     }
+    Ok(())
+}
+
+// Grace Hash Join
+// 左右两边按照 join 列的哈希值分到相同数量的分区，写入临时文件，相同的值一定在同一个分区中
+// 然后逐个分区读取右边的数据构建哈希表，再读取左边的数据查找，每次只需要一个分区的哈希表在内存中
+fn grace_join<T: Transaction>(
+    txn: &T,
+    (lrows, lpos): (RowIter, usize),
+    (rrows, rpos): (impl Iterator<Item = Result<Row>>, usize),
+    null_safe: bool,
+    (outer, rwidth): (bool, usize),
+    new_rows: &mut Vec<Row>,
+) -> Result<()> {
+    // 右边剩余的数据先写入一个临时文件，同时统计大小来决定分区数
+    let mut rfile = SpillFile::new()?;
+    let mut bytes = 0;
+    for row in rrows {
+        let row = row?;
+        bytes += row_size(&row);
+        rfile.write(&row)?;
+    }
+    let limit = txn.memory().limit().unwrap_or(bytes).max(1);
+    let partitions = ((bytes / limit + 1) * 2).min(MAX_PARTITIONS);
+    tracing::debug!(partitions, bytes, "hash join spilled to disk");
+
+    let lparts = partition(lrows, lpos, partitions)?;
+    let rparts = partition(rfile.into_rows()?, rpos, partitions)?;
+    for (lpart, rpart) in lparts.into_iter().zip(rparts) {
+        // 某个分区的数据依然放不下内存时返回错误，例如大量的行 join 列的值都相同
+        let (table, bytes) = match build_table(txn, &mut rpart.into_rows()?, rpos, null_safe)? {
+            Build::Table(table, bytes) => (table, bytes),
+            Build::Overflow(_, err) => return Err(err),
+        };
+        let probed = probe(&table, lpart.into_rows()?, lpos, outer, rwidth, new_rows);
+        txn.memory().release(bytes);
+        probed?;
+    }
+    Ok(())
+}
+
+fn partition(
+    rows: impl Iterator<Item = Result<Row>>,
+    pos: usize,
+    partitions: usize,
+) -> Result<Vec<SpillFile>> {
+    let mut files = (0..partitions)
+        .map(|_| SpillFile::new())
+        .collect::<Result<Vec<_>>>()?;
+    for row in rows {
+        let row = row?;
+        let mut hasher = DefaultHasher::new();
+        row[pos].hash(&mut hasher);
+        files[(hasher.finish() % partitions as u64) as usize].write(&row)?;
+    }
+    Ok(files)
 }
 
 fn parse_join_filter(predicate: Option<Expression>) -> Option<(String, String)> {
     match predicate {
         Some(expr) => match expr {
-            Expression::Field(f) => Some((f, "".into())),
-            Expression::Operation(operation) => match operation {
-                ast::Operation::Equal(l, r) => {
-                    let lv = parse_join_filter(Some(*l));
-                    let rv = parse_join_filter(Some(*r));
+            field @ Expression::Field(..) => Some((field.to_string(), "".into())),
+            Expression::Operation(
+                ast::Operation::Equal(l, r) | ast::Operation::IsNotDistinctFrom(l, r),
+            ) => {
+                let lv = parse_join_filter(Some(*l));
+                let rv = parse_join_filter(Some(*r));
 
-                    Some((lv.unwrap().0, rv.unwrap().0))
-                }
-                _ => None,
-            },
+                Some((lv.unwrap().0, rv.unwrap().0))
+            }
             _ => None,
         },
         None => None,
     }
 }
+
+// 展开数组，source 的每一行对 expr 求值，每个元素输出一行
+// 没有 source 时只对 expr 求值一次，expr 中不能引用列
+pub struct Unnest<T: Transaction> {
+    source: Option<Box<dyn Executor<T>>>,
+    expr: Expression,
+    alias: String,
+    predicate: Option<Expression>,
+    outer: bool,
+}
+
+impl<T: Transaction> Unnest<T> {
+    pub fn new(
+        source: Option<Box<dyn Executor<T>>>,
+        expr: Expression,
+        alias: String,
+        predicate: Option<Expression>,
+        outer: bool,
+    ) -> Box<Self> {
+        Box::new(Self {
+            source,
+            expr,
+            alias,
+            predicate,
+            outer,
+        })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Unnest<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let (source_columns, rows) = match self.source {
+            Some(source) => match source.execute(txn)? {
+                ResultSet::Scan { columns, rows } => (columns, rows),
+                _ => return Err(Error::Internal("Unexpected result set".into())),
+            },
+            None => (Vec::new(), vec![Vec::new()]),
+        };
+        // 展开的列的类型是数组元素的类型，数组中可能有 NULL
+        let datatype = match expr_type(&self.expr, &source_columns).0 {
+            Some(DataType::Array(datatype)) => Some(*datatype),
+            _ => None,
+        };
+        let columns = column_names(&source_columns);
+        let mut new_cols = columns.clone();
+        new_cols.push(self.alias.clone());
+        let mut result_columns = source_columns;
+        result_columns.push(ColumnDesc::new(self.alias, datatype, true));
+
+        let mut new_rows = Vec::new();
+        for row in rows {
+            let items = match evaluate_expr(&self.expr, &columns, &row, &columns, &row)? {
+                Value::Array(items) => items.into_vec(),
+                Value::Null => Vec::new(),
+                v => return Err(Error::TypeMismatch(format!("cannot unnest {}", v))),
+            };
+            let mut matched = false;
+            for item in items {
+                let mut new_row = row.clone();
+                new_row.push(item);
+                if let Some(predicate) = &self.predicate {
+                    match evaluate_expr(predicate, &new_cols, &new_row, &new_cols, &new_row)? {
+                        Value::Boolean(true) => {}
+                        Value::Null | Value::Boolean(false) => continue,
+                        _ => return Err(Error::Internal("Unexpected expression".into())),
+                    }
+                }
+                new_rows.push(new_row);
+                matched = true;
+            }
+            // left join 时没有展开出任何元素的行，展开的列为 NULL
+            if self.outer && !matched {
+                let mut new_row = row;
+                new_row.push(Value::Null);
+                new_rows.push(new_row);
+            }
+        }
+
+        Ok(ResultSet::Scan {
+            columns: result_columns,
+            rows: new_rows,
+        })
+    }
+}
+
+// join 的输入是单表扫描时，给输出的列名加上表名前缀，例如 t1.a
+pub struct Qualify<T: Transaction> {
+    table_name: String,
+    source: Box<dyn Executor<T>>,
+}
+
+impl<T: Transaction> Qualify<T> {
+    pub fn new(table_name: String, source: Box<dyn Executor<T>>) -> Box<Self> {
+        Box::new(Self { table_name, source })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Qualify<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let (columns, rows) = self.scan(txn)?;
+        collect_rows(columns, rows)
+    }
+
+    fn scan(self: Box<Self>, txn: &mut T) -> Result<(Vec<ColumnDesc>, RowIter)> {
+        let (columns, rows) = self.source.scan(txn)?;
+        let columns = columns
+            .into_iter()
+            .map(|c| {
+                let name = format!("{}.{}", self.table_name, c.name);
+                c.renamed(name)
+            })
+            .collect();
+        Ok((columns, rows))
+    }
+}
@@ -0,0 +1,116 @@
+use std::cell::Cell;
+
+use crate::{
+    error::{Error, Result},
+    sql::types::{Row, Value},
+};
+
+// 单个查询的内存统计，HashJoin、Aggregate、Order 等需要缓存全部数据的算子在使用内存之前先申请
+// 超过限制时查询失败并返回 OutOfMemory 错误，而不是耗尽进程的内存
+#[derive(Debug, Default)]
+pub struct MemoryTracker {
+    // 最多可以使用的内存，None 表示不限制
+    limit: Option<usize>,
+    used: Cell<usize>,
+    // 查询执行过程中使用内存的峰值
+    peak: Cell<usize>,
+}
+
+impl MemoryTracker {
+    pub fn new(limit: Option<usize>) -> Self {
+        Self {
+            limit,
+            ..Default::default()
+        }
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    pub fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+    }
+
+    pub fn used(&self) -> usize {
+        self.used.get()
+    }
+
+    pub fn peak(&self) -> usize {
+        self.peak.get()
+    }
+
+    // 每个查询开始执行之前清空统计
+    pub fn reset(&self) {
+        self.used.set(0);
+        self.peak.set(0);
+    }
+
+    // 申请内存，超过限制时返回错误，op 是申请内存的算子名称
+    pub fn reserve(&self, op: &str, bytes: usize) -> Result<()> {
+        let used = self.used.get().saturating_add(bytes);
+        if let Some(limit) = self.limit {
+            if used > limit {
+                return Err(Error::OutOfMemory(format!(
+                    "{} exceeded the memory limit of {} bytes",
+                    op, limit
+                )));
+            }
+        }
+        self.used.set(used);
+        self.peak.set(self.peak.get().max(used));
+        Ok(())
+    }
+
+    // 算子执行结束后释放申请的内存
+    pub fn release(&self, bytes: usize) {
+        self.used.set(self.used.get().saturating_sub(bytes));
+    }
+}
+
+// 估算一行数据占用的内存
+pub fn row_size(row: &Row) -> usize {
+    std::mem::size_of::<Row>() + row.iter().map(value_size).sum::<usize>()
+}
+
+pub fn value_size(value: &Value) -> usize {
+    std::mem::size_of::<Value>()
+        + match value {
+            Value::String(s) => s.len(),
+            Value::Blob(b) => b.len(),
+            _ => 0,
+        }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::{Error, Result},
+        sql::types::Value,
+    };
+
+    use super::{row_size, MemoryTracker};
+
+    #[test]
+    fn test_memory_tracker() -> Result<()> {
+        let tracker = MemoryTracker::new(Some(100));
+        tracker.reserve("Order", 60)?;
+        assert!(matches!(tracker.reserve("Order", 50), Err(Error::OutOfMemory(_))));
+        assert_eq!(tracker.used(), 60);
+        tracker.release(60);
+        tracker.reserve("HashJoin", 100)?;
+        assert_eq!(tracker.peak(), 100);
+        tracker.reset();
+        assert_eq!((tracker.used(), tracker.peak()), (0, 0));
+
+        // 不限制时只做统计
+        let tracker = MemoryTracker::default();
+        tracker.reserve("Aggregate", usize::MAX)?;
+        tracker.reserve("Aggregate", 1)?;
+        assert_eq!(tracker.used(), usize::MAX);
+
+        let row = vec![Value::Integer(1), Value::String("abc".into())];
+        assert!(row_size(&row) > row_size(&vec![Value::Integer(1), Value::Null]));
+        Ok(())
+    }
+}
@@ -1,73 +1,377 @@
-use super::{engine::Transaction, plan::Node, types::Row};
-use crate::error::Result;
+use std::{
+    fmt::Display,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    engine::{RowIter, Transaction},
+    plan::Node,
+    schema::{Column, Table},
+    types::{DataType, Row, Value},
+};
+use crate::error::{Error, Result};
 use agg::Aggregate;
-use join::{HashJoin, NestedLoopJoin};
+use copy::{CopyFrom, CopyTo};
+use information_schema::VirtualScan;
+use join::{HashJoin, NestedLoopJoin, Qualify, Unnest};
 use mutation::{Delete, Insert, Update};
-use query::{Filter, IndexScan, Limit, Offset, Order, PrimaryKeyScan, Projection, Scan};
-use schema::{CreateTable, DropTable};
+use query::{
+    BitmapScan, Distinct, Filter, FullTextScan, IndexPrefixScan, IndexScan, Limit, Offset, Order, PrimaryKeyPrefixScan,
+    PrimaryKeyScan, Projection, RangeIndexScan, Sample, Scan, SetOperation,
+};
+use schema::{AlterTable, CreateIndex, CreateTable, DropIndex, DropTable, Reindex, TruncateTable};
 
 mod agg;
+mod copy;
+pub mod information_schema;
 mod join;
+pub mod memory;
 mod mutation;
 mod query;
 mod schema;
+pub mod spill;
 
 // 执行器定义
 pub trait Executor<T: Transaction> {
     fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet>;
+
+    // 以迭代器的方式返回查询结果的列名和行，Update、Delete 可以边扫描边修改
+    // 默认先执行得到全部结果，排序、聚合、join 这些需要全部输入的节点使用默认实现
+    // 扫描、过滤、投影、limit 等节点逐行处理，上层每读取一行才从下层读取，不需要缓存全部结果
+    fn scan(self: Box<Self>, txn: &mut T) -> Result<(Vec<ColumnDesc>, RowIter)> {
+        match self.execute(txn)? {
+            ResultSet::Scan { columns, rows } => Ok((columns, Box::new(rows.into_iter().map(Ok)))),
+            _ => Err(Error::Internal("Unexpected result set".into())),
+        }
+    }
+}
+
+// 读取 scan 返回的全部行，逐行处理的节点用它实现 execute
+fn collect_rows(columns: Vec<ColumnDesc>, rows: RowIter) -> Result<ResultSet> {
+    Ok(ResultSet::Scan {
+        columns,
+        rows: rows.collect::<Result<_>>()?,
+    })
 }
 
+// explain analyze 记录的单个节点的执行统计，耗时包括子节点的耗时
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NodeStats {
+    pub rows: usize,
+    pub elapsed: Duration,
+}
+
+// 执行计划中所有节点的执行统计，按照前序遍历的顺序排列
+pub type PlanStats = Arc<Mutex<Vec<NodeStats>>>;
+
 impl<T: Transaction + 'static> dyn Executor<T> {
     pub fn build(node: Node) -> Box<dyn Executor<T>> {
-        match node {
+        Self::build_node(node, None)
+    }
+
+    // 构建执行器，执行时把每个节点的行数和耗时记录到 stats 中
+    pub fn build_analyzed(node: Node, stats: &PlanStats) -> Box<dyn Executor<T>> {
+        Self::build_node(node, Some(stats))
+    }
+
+    fn build_node(node: Node, stats: Option<&PlanStats>) -> Box<dyn Executor<T>> {
+        let name = node.name();
+        // 先于子节点分配编号，和执行计划前序遍历的顺序一致
+        let slot = stats.map(|stats| {
+            let mut nodes = stats.lock().unwrap_or_else(|e| e.into_inner());
+            nodes.push(NodeStats::default());
+            StatsSlot {
+                id: nodes.len() - 1,
+                stats: stats.clone(),
+            }
+        });
+        let executor: Box<dyn Executor<T>> = match node {
             Node::CreateTable { schema } => CreateTable::new(schema),
             Node::DropTable { name } => DropTable::new(name),
+            Node::TruncateTable { name } => TruncateTable::new(name),
+            Node::AlterTable { table_name, action } => AlterTable::new(table_name, action),
+            Node::Reindex { table_name, column } => Reindex::new(table_name, column),
+            Node::CreateIndex {
+                name,
+                table_name,
+                column,
+                index_type,
+            } => CreateIndex::new(name, table_name, column, index_type),
+            Node::DropIndex { name, table_name } => DropIndex::new(name, table_name),
             Node::Insert {
                 table_name,
                 columns,
                 values,
             } => Insert::new(table_name, columns, values),
-            Node::Scan { table_name, filter } => Scan::new(table_name, filter),
+            Node::CopyFrom {
+                table_name,
+                path,
+                options,
+            } => CopyFrom::new(table_name, path, options),
+            Node::CopyTo {
+                table_name,
+                path,
+                options,
+            } => CopyTo::new(table_name, path, options),
+            Node::Scan {
+                table_name,
+                filter,
+                columns,
+                limit,
+            } => Scan::new(table_name, filter, columns, limit),
+            Node::VirtualScan { table_name, filter } => VirtualScan::new(table_name, filter),
             Node::Update {
                 table_name,
                 source,
                 columns,
-            } => Update::new(table_name, Self::build(*source), columns),
-            Node::Delete { table_name, source } => Delete::new(table_name, Self::build(*source)),
-            Node::Order { source, order_by } => Order::new(Self::build(*source), order_by),
-            Node::Limit { source, limit } => Limit::new(Self::build(*source), limit),
-            Node::Offset { source, offset } => Offset::new(Self::build(*source), offset),
-            Node::Projection { source, exprs } => Projection::new(Self::build(*source), exprs),
+            } => Update::new(table_name, Self::build_node(*source, stats), columns),
+            Node::Delete { table_name, source } => Delete::new(table_name, Self::build_node(*source, stats)),
+            Node::Order { source, order_by } => Order::new(Self::build_node(*source, stats), order_by),
+            Node::Limit { source, limit } => Limit::new(Self::build_node(*source, stats), limit),
+            Node::Sample {
+                source,
+                percent,
+                seed,
+            } => Sample::new(Self::build_node(*source, stats), percent, seed),
+            Node::Offset { source, offset } => Offset::new(Self::build_node(*source, stats), offset),
+            Node::Distinct { source } => Distinct::new(Self::build_node(*source, stats)),
+            Node::SetOperation {
+                op,
+                all,
+                left,
+                right,
+            } => SetOperation::new(
+                op,
+                all,
+                Self::build_node(*left, stats),
+                Self::build_node(*right, stats),
+            ),
+            Node::Projection { source, exprs } => Projection::new(Self::build_node(*source, stats), exprs),
+            Node::Unnest {
+                source,
+                expr,
+                alias,
+                predicate,
+                outer,
+            } => Unnest::new(
+                source.map(|s| Self::build_join_source(*s, stats)),
+                expr,
+                alias,
+                predicate,
+                outer,
+            ),
             Node::NestedLoopJoin {
                 left,
                 right,
                 predicate,
                 outer,
-            } => NestedLoopJoin::new(Self::build(*left), Self::build(*right), predicate, outer),
+            } => NestedLoopJoin::new(
+                Self::build_join_source(*left, stats),
+                Self::build_join_source(*right, stats),
+                predicate,
+                outer,
+            ),
             Node::Aggregate {
                 source,
                 exprs,
                 group_by,
-            } => Aggregate::new(Self::build(*source), exprs, group_by),
-            Node::Filter { source, predicate } => Filter::new(Self::build(*source), predicate),
+            } => Aggregate::new(Self::build_node(*source, stats), exprs, group_by),
+            Node::Filter { source, predicate } => Filter::new(Self::build_node(*source, stats), predicate),
             Node::IndexScan {
                 table_name,
                 field,
                 value,
-            } => IndexScan::new(table_name, field, value),
+                limit,
+                columns,
+            } => IndexScan::new(table_name, field, value, limit, columns),
             Node::PrimaryKeyScan { table_name, value } => PrimaryKeyScan::new(table_name, value),
+            Node::RangeIndexScan {
+                table_name,
+                field,
+                start,
+                end,
+                columns,
+            } => RangeIndexScan::new(table_name, field, start, end, columns),
+            Node::IndexPrefixScan {
+                table_name,
+                field,
+                prefix,
+            } => IndexPrefixScan::new(table_name, field, prefix),
+            Node::PrimaryKeyPrefixScan { table_name, prefix } => {
+                PrimaryKeyPrefixScan::new(table_name, prefix)
+            }
+            Node::FullTextScan {
+                table_name,
+                field,
+                query,
+            } => FullTextScan::new(table_name, field, query),
+            Node::BitmapScan {
+                table_name,
+                condition,
+            } => BitmapScan::new(table_name, condition),
+            // 子查询直接执行，外层通过别名引用它的结果
+            Node::Derived { source, .. } => Self::build_node(*source, stats),
             Node::HashJoin {
                 left,
                 right,
                 predicate,
                 outer,
-            } => HashJoin::new(Self::build(*left), Self::build(*right), predicate, outer),
+            } => HashJoin::new(
+                Self::build_join_source(*left, stats),
+                Self::build_join_source(*right, stats),
+                predicate,
+                outer,
+            ),
+        };
+        Box::new(Traced {
+            name,
+            inner: executor,
+            slot,
+        })
+    }
+
+    // join 两边的列名带上表名前缀，避免不同表的同名列无法区分
+    fn build_join_source(node: Node, stats: Option<&PlanStats>) -> Box<dyn Executor<T>> {
+        match node.scan_table_name() {
+            Some(table_name) => Qualify::new(table_name, Self::build_node(node, stats)),
+            None => Self::build_node(node, stats),
+        }
+    }
+}
+
+// 记录 tracing span 的执行节点，子节点的 span 嵌套在父节点的 span 中
+// explain analyze 时同时记录节点输出的行数和耗时
+struct Traced<T: Transaction> {
+    name: &'static str,
+    inner: Box<dyn Executor<T>>,
+    slot: Option<StatsSlot>,
+}
+
+impl<T: Transaction> Executor<T> for Traced<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let span = tracing::debug_span!("execute", node = self.name, rows = tracing::field::Empty);
+        let _enter = span.enter();
+        let start = Instant::now();
+        let result = self.inner.execute(txn);
+        let rows = match &result {
+            Ok(ResultSet::Scan { rows, .. }) => Some(rows.len()),
+            Ok(ResultSet::Insert { count })
+            | Ok(ResultSet::Copy { count })
+            | Ok(ResultSet::Update { count })
+            | Ok(ResultSet::Delete { count }) => Some(*count),
+            Ok(_) => None,
+            Err(err) => {
+                tracing::debug!(error = %err, "execute failed");
+                None
+            }
+        };
+        if let Some(rows) = rows {
+            span.record("rows", rows);
+        }
+        if let Some(slot) = &self.slot {
+            slot.record(rows.unwrap_or(0), start.elapsed());
+        }
+        result
+    }
+
+    // 以迭代器返回时行数在遍历之后才知道，span 中不记录行数
+    // 遍历时重新进入这个节点的 span，遍历时才读取存储的调用同样嵌套在其中
+    // explain analyze 时每读取一行累加一次耗时
+    fn scan(self: Box<Self>, txn: &mut T) -> Result<(Vec<ColumnDesc>, RowIter)> {
+        let Traced { name, inner, slot } = *self;
+        let span = tracing::debug_span!("execute", node = name);
+        let start = Instant::now();
+        let result = span.in_scope(|| {
+            let result = inner.scan(txn);
+            if let Err(err) = &result {
+                tracing::debug!(error = %err, "execute failed");
+            }
+            result
+        });
+        if let Some(slot) = &slot {
+            slot.record(0, start.elapsed());
         }
+        let (columns, mut rows) = result?;
+        let rows = std::iter::from_fn(move || {
+            let _enter = span.enter();
+            let start = Instant::now();
+            let row = rows.next();
+            if let Some(slot) = &slot {
+                slot.record(matches!(row, Some(Ok(_))) as usize, start.elapsed());
+            }
+            row
+        });
+        Ok((columns, Box::new(rows)))
+    }
+}
+
+// 单个节点在 PlanStats 中的位置
+struct StatsSlot {
+    id: usize,
+    stats: PlanStats,
+}
+
+impl StatsSlot {
+    fn record(&self, rows: usize, elapsed: Duration) {
+        let mut nodes = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        nodes[self.id].rows += rows;
+        nodes[self.id].elapsed += elapsed;
+    }
+}
+
+// 查询结果中一列的信息，datatype 为空表示执行之前无法确定类型，例如 NULL 常量、标量子查询
+// nullable 为 false 时这一列一定不会有 NULL，无法确定时为 true
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ColumnDesc {
+    pub name: String,
+    pub datatype: Option<DataType>,
+    pub nullable: bool,
+}
+
+impl ColumnDesc {
+    pub fn new(name: impl Into<String>, datatype: Option<DataType>, nullable: bool) -> Self {
+        Self {
+            name: name.into(),
+            datatype,
+            nullable,
+        }
+    }
+
+    // 类型未知、可能为 NULL 的列
+    pub fn unknown(name: impl Into<String>) -> Self {
+        Self::new(name, None, true)
     }
+
+    // 表的所有列，包括隐藏的 _rowid 列
+    pub fn from_table(table: &Table) -> Vec<Self> {
+        table.columns.iter().map(Self::from).collect()
+    }
+
+    // 改名之后的列，类型不变
+    pub fn renamed(self, name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ..self
+        }
+    }
+}
+
+impl From<&Column> for ColumnDesc {
+    fn from(col: &Column) -> Self {
+        Self::new(col.name.clone(), Some(col.datatype.clone()), col.nullable)
+    }
+}
+
+// 表达式求值使用列名
+pub fn column_names(columns: &[ColumnDesc]) -> Vec<String> {
+    columns.iter().map(|c| c.name.clone()).collect()
 }
 
 // 执行结果集
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum ResultSet {
     CreateTable {
         table_name: String,
@@ -75,11 +379,31 @@ pub enum ResultSet {
     DropTable {
         table_name: String,
     },
+    TruncateTable {
+        table_name: String,
+        count: usize,
+    },
+    AlterTable {
+        table_name: String,
+    },
+    Reindex {
+        table_name: String,
+        count: usize,
+    },
+    CreateIndex {
+        index_name: String,
+        table_name: String,
+        count: usize,
+    },
+    DropIndex {
+        index_name: String,
+        table_name: String,
+    },
     Insert {
         count: usize,
     },
     Scan {
-        columns: Vec<String>,
+        columns: Vec<ColumnDesc>,
         rows: Vec<Row>,
     },
     Update {
@@ -97,22 +421,69 @@ pub enum ResultSet {
     Rollback {
         version: u64,
     },
+    Savepoint {
+        name: String,
+    },
+    RollbackToSavepoint {
+        name: String,
+    },
+    ReleaseSavepoint {
+        name: String,
+    },
     Explain {
         plan: String,
     },
+    Set {
+        name: String,
+        value: Value,
+    },
+    Vacuum {
+        count: usize,
+    },
+    Backup {
+        count: usize,
+    },
+    Restore {
+        count: usize,
+    },
+    DeclareCursor {
+        name: String,
+    },
+    CloseCursor {
+        name: String,
+    },
+    Copy {
+        count: usize,
+    },
 }
 
-impl ResultSet {
-    pub fn to_string(&self) -> String {
-        match self {
+impl Display for ResultSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&match self {
             ResultSet::CreateTable { table_name } => format!("CREATE TABLE {}", table_name),
             ResultSet::DropTable { table_name } => format!("DROP TABLE {}", table_name),
+            ResultSet::TruncateTable { table_name, count } => {
+                format!("TRUNCATE TABLE {} ({} rows)", table_name, count)
+            }
+            ResultSet::AlterTable { table_name } => format!("ALTER TABLE {}", table_name),
+            ResultSet::Reindex { table_name, count } => {
+                format!("REINDEX TABLE {} ({} entries)", table_name, count)
+            }
+            ResultSet::CreateIndex {
+                index_name,
+                table_name,
+                count,
+            } => format!("CREATE INDEX {} ON {} ({} entries)", index_name, table_name, count),
+            ResultSet::DropIndex {
+                index_name,
+                table_name,
+            } => format!("DROP INDEX {} ON {}", index_name, table_name),
             ResultSet::Insert { count } => format!("INSERT {} rows", count),
             ResultSet::Scan { columns, rows } => {
                 let rows_len = rows.len();
 
                 // 找到每一列最大的长度
-                let mut max_len = columns.iter().map(|c| c.len()).collect::<Vec<_>>();
+                let mut max_len = columns.iter().map(|c| c.name.len()).collect::<Vec<_>>();
                 for one_row in rows {
                     for (i, v) in one_row.iter().enumerate() {
                         if v.to_string().len() > max_len[i] {
@@ -125,14 +496,14 @@ impl ResultSet {
                 let columns = columns
                     .iter()
                     .zip(max_len.iter())
-                    .map(|(col, &len)| format!("{:width$}", col, width = len))
+                    .map(|(col, &len)| format!("{:width$}", col.name, width = len))
                     .collect::<Vec<_>>()
                     .join(" |");
 
                 // 展示分隔符
                 let sep = max_len
                     .iter()
-                    .map(|v| format!("{}", "-".repeat(*v + 1)))
+                    .map(|v| "-".repeat(*v + 1).to_string())
                     .collect::<Vec<_>>()
                     .join("+");
 
@@ -156,7 +527,17 @@ impl ResultSet {
             ResultSet::Begin { version } => format!("TRANSACTION {} BEGIN", version),
             ResultSet::Commit { version } => format!("TRANSACTION {} COMMIT", version),
             ResultSet::Rollback { version } => format!("TRANSACTION {} ROLLBACK", version),
+            ResultSet::Savepoint { name } => format!("SAVEPOINT {}", name),
+            ResultSet::RollbackToSavepoint { name } => format!("ROLLBACK TO SAVEPOINT {}", name),
+            ResultSet::ReleaseSavepoint { name } => format!("RELEASE SAVEPOINT {}", name),
             ResultSet::Explain { plan } => plan.to_string(),
-        }
+            ResultSet::Set { name, value } => format!("SET {} = {}", name, value),
+            ResultSet::Vacuum { count } => format!("VACUUM ({} versions removed)", count),
+            ResultSet::Backup { count } => format!("BACKUP {} keys", count),
+            ResultSet::Restore { count } => format!("RESTORE {} keys", count),
+            ResultSet::DeclareCursor { name } => format!("DECLARE CURSOR {}", name),
+            ResultSet::CloseCursor { name } => format!("CLOSE CURSOR {}", name),
+            ResultSet::Copy { count } => format!("COPY {} rows", count),
+        })
     }
 }
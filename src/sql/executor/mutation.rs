@@ -1,16 +1,16 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use crate::{
     error::{Error, Result},
     sql::{
-        engine::Transaction,
-        parser::ast::Expression,
-        schema::Table,
+        engine::{RowIter, Transaction},
+        parser::ast::{column_position, evaluate_expr, Expression},
+        schema::{primary_key_value, Table},
         types::{Row, Value},
     },
 };
 
-use super::{Executor, ResultSet};
+use super::{column_names, memory::row_size, Executor, ResultSet};
 
 
 // Insert 执行器;
@@ -39,16 +39,25 @@ impl Insert {
 // insert into tbl values(1, 2, 3);
 // a       b       c          d
 // 1       2       3      default 填充
-fn pad_row(table: &Table, row: &Row) -> Result<Row> {
-    let mut results = row.clone();
-    for column in table.columns.iter().skip(row.len()) {
-        if let Some(default) = &column.default {
-            results.push(default.clone());
-        } else {
-            return Err(Error::Internal(format!(
-                "No default value for column {}",
-                column.name
-            )));
+fn pad_row(table: &Table, row: &[Option<Value>]) -> Result<Row> {
+    // 隐藏的 _rowid 列不能按位置赋值
+    let visible = table.columns.iter().filter(|c| !c.is_hidden()).count();
+    if row.len() > visible {
+        return Err(Error::InvalidInput(format!(
+            "row has {} values but table {} has {} columns",
+            row.len(),
+            table.name,
+            visible
+        )));
+    }
+    let mut results = Vec::new();
+    let mut values = row.iter();
+    for (i, col) in table.columns.iter().enumerate() {
+        let value = if col.is_hidden() { None } else { values.next() };
+        match value {
+            Some(Some(value)) => results.push(value.clone()),
+            // 没有给出的值，或者指定了 DEFAULT，使用默认值填充
+            _ => results.push(default_value(table, i)?),
         }
     }
 
@@ -59,10 +68,10 @@ fn pad_row(table: &Table, row: &Row) -> Result<Row> {
 // insert into tbl(d, c) values(1, 2);
 //    a          b       c          d
 // default   default     2          1
-fn make_row(table: &Table, columns: &Vec<String>, values: &Row) -> Result<Row> {
+pub(super) fn make_row(table: &Table, columns: &[String], values: &[Option<Value>]) -> Result<Row> {
     // 判断列数是否和value数一致
     if columns.len() != values.len() {
-        return Err(Error::Internal(format!("columns and values num mismatch")));
+        return Err(Error::InvalidInput("columns and values num mismatch".to_string()));
     }
 
     let mut inputs = HashMap::new();
@@ -71,22 +80,25 @@ fn make_row(table: &Table, columns: &Vec<String>, values: &Row) -> Result<Row> {
     }
 
     let mut results = Vec::new();
-    for col in table.columns.iter() {
-        if let Some(value) = inputs.get(&col.name) {
-            results.push(value.clone());
-        } else if let Some(value) = &col.default {
-            results.push(value.clone());
-        } else {
-            return Err(Error::Internal(format!(
-                "No value given for the column {}",
-                col.name
-            )));
+    for (i, col) in table.columns.iter().enumerate() {
+        match inputs.get(&col.name) {
+            Some(Some(value)) => results.push(value.clone()),
+            _ => results.push(default_value(table, i)?),
         }
     }
 
     Ok(results)
 }
 
+// 获取列的默认值，没有默认值则报错
+fn default_value(table: &Table, i: usize) -> Result<Value> {
+    let column = &table.columns[i];
+    column.default.clone().ok_or(Error::Constraint(format!(
+        "No default value for column {}",
+        column.name
+    )))
+}
+
 impl<T: Transaction> Executor<T> for Insert {
     fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
         let mut count = 0;
@@ -94,10 +106,15 @@ impl<T: Transaction> Executor<T> for Insert {
         let table = txn.must_get_table(self.table_name.clone())?;
         for exprs in self.values {
             // 将表达式转换成 value
+            // DEFAULT 转换成 None，由列的默认值填充
+            // 其他表达式求值，例如 coalesce(null, 1)
             let row = exprs
                 .into_iter()
-                .map(|e| Value::from_expression(e))
-                .collect::<Vec<_>>();
+                .map(|expr| match expr {
+                    Expression::Default => Ok(None),
+                    expr => evaluate_expr(&expr, &vec![], &vec![], &vec![], &vec![]).map(Some),
+                })
+                .collect::<Result<Vec<_>>>()?;
             // 如果没有指定插入的列
             let insert_row = if self.columns.is_empty() {
                 pad_row(&table, &row)?
@@ -131,38 +148,76 @@ impl<T: Transaction> Update<T> {
 impl<T: Transaction> Executor<T> for Update<T> {
     fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
         let mut updated = 0;
-        // 执行扫描操作，获取到扫描的结果;
-        match self.source.execute(txn)? {
-            // 期待的是 扫描结果;
-            ResultSet::Scan { columns, rows } => {
-                // 必须得到表;
-                let table = txn.must_get_table(self.table_name)?;
-                // 遍历所有需要更新的行;
-                for row in rows {
-                    // update user set name='kk' where id = 1; // 可能存在多行需要更新;
-                    let mut new_row = row.clone();
-
-                    // 从每一行中获得 主键;
-                    let pk = table.get_primary_key(&row)?;
-
-                    for (i, col) in columns.iter().enumerate() {
-                        // 存在这个列的 更新值;
-                        if let Some(expr) = self.columns.get(col) {
-                            // 赋值最新值; 这里有可能 将主键列进行了更新;
-                            new_row[i] = Value::from_expression(expr.clone());
-                        }
-                    }
-
-                    // 执行更新操作;
-                    // 1.如果有主键更新，删除原来的数据，新增一条新的数据
-                    // 2.否则就 table_name + primary key => 更新数据
-                    // 所有行的存储结构是: tableName_primaryKey_
-                    txn.update_row(&table, &pk, new_row)?;
-                    updated += 1;
+        // 执行扫描操作，边扫描边更新;
+        let (columns, rows) = self.source.scan(txn)?;
+        let columns = column_names(&columns);
+        // 必须得到表;
+        let table = txn.must_get_table(self.table_name)?;
+        // update ... from 时输入是 join 的结果，列名带有表名前缀，找到要更新的表的每一列的位置
+        let positions = table
+            .columns
+            .iter()
+            .map(|c| column_position(&columns, &format!("{}.{}", table.name, c.name)))
+            .collect::<Result<Vec<_>>>()?;
+        // 修改主键时行会移动到新的 key，边扫描边更新可能再次扫描到移动之后的行，先读取所有要更新的行
+        let mut bytes = 0;
+        let rows: RowIter = if table
+            .primary_key_positions()
+            .iter()
+            .any(|i| self.columns.contains_key(&table.columns[*i].name))
+        {
+            let mut buffered = Vec::new();
+            for row in rows {
+                let row = row?;
+                let size = row_size(&row);
+                if let Err(err) = txn.memory().reserve("Update", size) {
+                    txn.memory().release(bytes);
+                    return Err(err);
+                }
+                bytes += size;
+                buffered.push(row);
+            }
+            Box::new(buffered.into_iter().map(Ok))
+        } else {
+            rows
+        };
+        // 一行和 from 中的多行匹配时只更新一次
+        let joined = columns.len() > table.columns.len();
+        let mut seen = HashSet::new();
+        // 遍历所有需要更新的行;
+        for source_row in rows {
+            let source_row = source_row?;
+            // update user set name='kk' where id = 1; // 可能存在多行需要更新;
+            let row = positions
+                .iter()
+                .map(|i| source_row[*i].clone())
+                .collect::<Vec<_>>();
+            let mut new_row = row.clone();
+
+            // 从每一行中获得 主键;
+            let pk = table.get_primary_key(&row)?;
+            if joined && !seen.insert(pk.clone()) {
+                continue;
+            }
+
+            for (i, col) in table.columns.iter().enumerate() {
+                // 存在这个列的 更新值;
+                if let Some(expr) = self.columns.get(&col.name) {
+                    // 赋值最新值; 这里有可能 将主键列进行了更新;
+                    // 表达式中可以引用这一行原来的值，例如 b = ifnull(b, 0)，以及 from 中的列
+                    new_row[i] =
+                        evaluate_expr(expr, &columns, &source_row, &columns, &source_row)?;
                 }
             }
-            _ => return Err(Error::Internal("Unexpected result set".into())),
+
+            // 执行更新操作;
+            // 1.如果有主键更新，删除原来的数据，新增一条新的数据
+            // 2.否则就 table_name + primary key => 更新数据
+            // 所有行的存储结构是: tableName_primaryKey_
+            txn.update_row(&table, &pk, new_row)?;
+            updated += 1;
         }
+        txn.memory().release(bytes);
         Ok(ResultSet::Update { count: updated })
     }
 }
@@ -182,25 +237,34 @@ impl<T: Transaction> Delete<T> {
 impl<T: Transaction> Executor<T> for Delete<T> {
     //
     fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
-        // 执行扫描操作，获取到扫描的结果;
-        match self.source.execute(txn)? {
-            // delete from user where id=0;     // 主键列;
-            // delete from user where name=0;   // 索引列;
-            // delete from user where tel=0;    // 普通列;
-            ResultSet::Scan { columns: _, rows } => {
-                let mut count = 0;
-                let table = txn.must_get_table(self.table_name)?;
-                for row in rows {
-                    // 取出每行的主键;
-                    let pk = table.get_primary_key(&row)?;
-                    // 直接删除掉;
-                    txn.delete_row(&table, &pk)?;
-                    count += 1;
-                }
-
-                Ok(ResultSet::Delete { count })
+        // 执行扫描操作，边扫描边删除;
+        // delete from user where id=0;     // 主键列;
+        // delete from user where name=0;   // 索引列;
+        // delete from user where tel=0;    // 普通列;
+        let (columns, rows) = self.source.scan(txn)?;
+        let columns = column_names(&columns);
+        let mut count = 0;
+        let table = txn.must_get_table(self.table_name)?;
+        // delete ... using 时输入是 join 的结果，主键列带有表名前缀，一行可能和 using 中的多行匹配
+        let joined = columns.len() > table.columns.len();
+        let pk_pos = table
+            .primary_key_positions()
+            .into_iter()
+            .map(|i| column_position(&columns, &format!("{}.{}", table.name, table.columns[i].name)))
+            .collect::<Result<Vec<_>>>()?;
+        let mut seen = HashSet::new();
+        for row in rows {
+            // 取出每行的主键;
+            let row = row?;
+            let pk = primary_key_value(pk_pos.iter().map(|i| row[*i].clone()).collect());
+            if joined && !seen.insert(pk.clone()) {
+                continue;
             }
-            _ => Err(Error::Internal("Unexpected result set".into())),
+            // 直接删除掉;
+            txn.delete_row(&table, &pk)?;
+            count += 1;
         }
+
+        Ok(ResultSet::Delete { count })
     }
 }
@@ -1,35 +1,89 @@
-use std::{cmp::Ordering, collections::HashMap};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    ops::Bound,
+};
 
 use crate::{
     error::{Error, Result},
     sql::{
-        engine::Transaction,
-        parser::ast::{evaluate_expr, Expression, OrderDirection},
-        types::Value,
+        engine::{
+            bitmap::{self, Bitmap},
+            RowIter, Transaction,
+        },
+        parser::ast::{
+            evaluate_expr, field_position, Consts, Expression, Operation, OrderDirection,
+            SetOperator,
+        },
+        plan::BitmapCondition,
+        functions::{self, random::Rng},
+        schema::{primary_key_value, Table},
+        types::{coerce::coerce_lookup, DataType, Row, Value},
     },
 };
 
-use super::{Executor, ResultSet};
+use super::{
+    collect_rows, column_names, memory::row_size, spill::SpillFile, ColumnDesc, Executor, ResultSet,
+};
 
 pub struct Scan {
     table_name: String,
     filter: Option<Expression>,
+    columns: Option<Vec<String>>,
+    limit: Option<usize>,
 }
 
 impl Scan {
-    pub fn new(table_name: String, filter: Option<Expression>) -> Box<Self> {
-        Box::new(Self { table_name, filter })
+    pub fn new(
+        table_name: String,
+        filter: Option<Expression>,
+        columns: Option<Vec<String>>,
+        limit: Option<usize>,
+    ) -> Box<Self> {
+        Box::new(Self {
+            table_name,
+            filter,
+            columns,
+            limit,
+        })
     }
 }
 
 impl<T: Transaction> Executor<T> for Scan {
     fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let (columns, rows) = self.scan(txn)?;
+        collect_rows(columns, rows)
+    }
+
+    fn scan(self: Box<Self>, txn: &mut T) -> Result<(Vec<ColumnDesc>, RowIter)> {
         let table = txn.must_get_table(self.table_name.clone())?;
-        let rows = txn.scan_table(self.table_name.clone(), self.filter)?;
-        Ok(ResultSet::Scan {
-            columns: table.columns.into_iter().map(|c| c.name.clone()).collect(),
-            rows,
-        })
+        match (self.columns, self.limit) {
+            // 只解码查询用到的列
+            (Some(columns), limit) => {
+                let positions = columns
+                    .iter()
+                    .map(|c| table.get_col_index(c))
+                    .collect::<Result<Vec<_>>>()?;
+                let rows = match limit {
+                    // 下推了 limit 的扫描没有过滤条件
+                    Some(limit) => txn.scan_limit(self.table_name, Some(&positions), limit)?,
+                    None => txn.scan_columns(self.table_name, self.filter, &positions)?,
+                };
+                let columns = positions
+                    .iter()
+                    .map(|i| ColumnDesc::from(&table.columns[*i]))
+                    .collect();
+                Ok((columns, rows))
+            }
+            (None, Some(limit)) => {
+                let rows = txn.scan_limit(self.table_name, None, limit)?;
+                Ok((ColumnDesc::from_table(&table), rows))
+            }
+            (None, None) => {
+                let rows = txn.scan_table(self.table_name, self.filter)?;
+                Ok((ColumnDesc::from_table(&table), rows))
+            }
+        }
     }
 }
 
@@ -38,14 +92,24 @@ pub struct IndexScan {
     table_name: String,
     field: String,
     value: Value,
+    limit: Option<usize>,
+    columns: Option<Vec<String>>,
 }
 
 impl IndexScan {
-    pub fn new(table_name: String, field: String, value: Value) -> Box<Self> {
+    pub fn new(
+        table_name: String,
+        field: String,
+        value: Value,
+        limit: Option<usize>,
+        columns: Option<Vec<String>>,
+    ) -> Box<Self> {
         Box::new(Self {
             table_name,
             field,
             value,
+            limit,
+            columns,
         })
     }
 }
@@ -53,13 +117,38 @@ impl IndexScan {
 impl<T: Transaction> Executor<T> for IndexScan {
     fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
         let table = txn.must_get_table(self.table_name.clone())?;
+        // 查找值按照索引列的类型做转换，和比较运算的语义保持一致
+        let col = &table.columns[table.get_col_index(&self.field)?];
+        let value = match coerce_lookup(&self.value, &col.datatype) {
+            Some(v) => v,
+            None => {
+                return Ok(ResultSet::Scan {
+                    columns: ColumnDesc::from_table(&table),
+                    rows: Vec::new(),
+                })
+            }
+        };
         // <tableName_fieldName_fieldValue, >
-        let index = txn.load_index(&self.table_name, &self.field, &self.value)?;
+        let index = txn.load_index(&self.table_name, &self.field, &value)?;
         let mut pks = index.iter().collect::<Vec<_>>();
         pks.sort_by(|v1, v2| match v1.partial_cmp(v2) {
             Some(ord) => ord,
             None => Ordering::Equal,
         });
+        // 下推了 limit 时只读取前面的行
+        pks.truncate(self.limit.unwrap_or(usize::MAX));
+
+        // 覆盖索引不需要回表
+        if let Some(columns) = self.columns {
+            let rows = pks
+                .into_iter()
+                .map(|pk| covered_row(&table, &columns, &self.field, &value, pk))
+                .collect::<Result<_>>()?;
+            return Ok(ResultSet::Scan {
+                columns: covered_columns(&table, &columns)?,
+                rows,
+            });
+        }
 
         let mut rows = Vec::new();
         for pk in pks {
@@ -69,12 +158,41 @@ impl<T: Transaction> Executor<T> for IndexScan {
         }
 
         Ok(ResultSet::Scan {
-            columns: table.columns.into_iter().map(|c| c.name.clone()).collect(),
+            columns: ColumnDesc::from_table(&table),
             rows,
         })
     }
 }
 
+// 覆盖索引中的一行，索引列取索引中的值，主键列取主键，复合主键是各个主键列的值组成的数组
+fn covered_row(table: &Table, columns: &[String], field: &str, value: &Value, pk: &Value) -> Result<Row> {
+    let pk_cols = table.primary_key_positions();
+    columns
+        .iter()
+        .map(|name| {
+            if name == field {
+                return Ok(value.clone());
+            }
+            let i = table.get_col_index(name)?;
+            match (pk_cols.iter().position(|p| *p == i), pk) {
+                (Some(k), Value::Array(values)) if pk_cols.len() > 1 => Ok(values[k].clone()),
+                (Some(_), pk) => Ok(pk.clone()),
+                (None, _) => Err(Error::Internal(format!(
+                    "column {} is not covered by index on {}",
+                    name, field
+                ))),
+            }
+        })
+        .collect()
+}
+
+fn covered_columns(table: &Table, columns: &[String]) -> Result<Vec<ColumnDesc>> {
+    columns
+        .iter()
+        .map(|name| Ok(ColumnDesc::from(&table.columns[table.get_col_index(name)?])))
+        .collect()
+}
+
 // 扫描过程: 针对 主键id 进行扫描过滤;
 pub struct PrimaryKeyScan {
     table_name: String,
@@ -91,18 +209,237 @@ impl<T: Transaction> Executor<T> for PrimaryKeyScan {
     fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
         let table = txn.must_get_table(self.table_name.clone())?;
         let mut rows = Vec::new();
-        let mut id = self.value.clone();
-        if let Value::Float(v) = self.value {
-            if v.fract() == 0.0 {
-                id = Value::Integer(v as i64);
+        // 查找值按照主键列的类型做转换，例如 a = 2.0 可以查到整数主键 2
+        // 复合主键的查找值是数组，每个元素按照对应的主键列转换
+        let pk_cols = table.primary_key_positions();
+        let id = match (&self.value, pk_cols.as_slice()) {
+            (value, [pos]) => coerce_lookup(value, &table.columns[*pos].datatype),
+            (Value::Array(values), _) if values.len() == pk_cols.len() => values
+                .iter()
+                .zip(&pk_cols)
+                .map(|(v, pos)| coerce_lookup(v, &table.columns[*pos].datatype))
+                .collect::<Option<Vec<_>>>()
+                .map(primary_key_value),
+            _ => None,
+        };
+        if let Some(id) = id {
+            if let Some(row) = txn.read_by_id(&self.table_name, &id)? {
+                rows.push(row);
             }
         }
-        if let Some(row) = txn.read_by_id(&self.table_name, &id)? {
-            rows.push(row);
+
+        Ok(ResultSet::Scan {
+            columns: ColumnDesc::from_table(&table),
+            rows,
+        })
+    }
+}
+
+// 扫描过程: 索引列的值以 prefix 开头，用于 like 'ab%' 这样的条件;
+pub struct IndexPrefixScan {
+    table_name: String,
+    field: String,
+    prefix: String,
+}
+
+impl IndexPrefixScan {
+    pub fn new(table_name: String, field: String, prefix: String) -> Box<Self> {
+        Box::new(Self {
+            table_name,
+            field,
+            prefix,
+        })
+    }
+}
+
+impl<T: Transaction> Executor<T> for IndexPrefixScan {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let table = txn.must_get_table(self.table_name.clone())?;
+        let index = txn.load_index_prefix(&self.table_name, &self.field, &self.prefix)?;
+        let mut pks = index.iter().collect::<Vec<_>>();
+        pks.sort_by(|v1, v2| match v1.partial_cmp(v2) {
+            Some(ord) => ord,
+            None => Ordering::Equal,
+        });
+
+        let mut rows = Vec::new();
+        for pk in pks {
+            if let Some(row) = txn.read_by_id(&self.table_name, pk)? {
+                rows.push(row);
+            }
         }
 
         Ok(ResultSet::Scan {
-            columns: table.columns.into_iter().map(|c| c.name.clone()).collect(),
+            columns: ColumnDesc::from_table(&table),
+            rows,
+        })
+    }
+}
+
+// 扫描过程: 索引列的值在一个范围内，用于 b > 10、b between 1 and 5 这样的条件;
+pub struct RangeIndexScan {
+    table_name: String,
+    field: String,
+    start: Bound<Value>,
+    end: Bound<Value>,
+    columns: Option<Vec<String>>,
+}
+
+impl RangeIndexScan {
+    pub fn new(
+        table_name: String,
+        field: String,
+        start: Bound<Value>,
+        end: Bound<Value>,
+        columns: Option<Vec<String>>,
+    ) -> Box<Self> {
+        Box::new(Self {
+            table_name,
+            field,
+            start,
+            end,
+            columns,
+        })
+    }
+}
+
+impl<T: Transaction> Executor<T> for RangeIndexScan {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let table = txn.must_get_table(self.table_name.clone())?;
+        // 主键按照索引列的值排序，结果也按照索引列的值排序
+        let entries = txn.load_index_range(&self.table_name, &self.field, self.start, self.end)?;
+        if let Some(columns) = self.columns {
+            let rows = entries
+                .iter()
+                .map(|(value, pk)| covered_row(&table, &columns, &self.field, value, pk))
+                .collect::<Result<_>>()?;
+            return Ok(ResultSet::Scan {
+                columns: covered_columns(&table, &columns)?,
+                rows,
+            });
+        }
+        let mut rows = Vec::new();
+        for (_, pk) in entries.iter() {
+            if let Some(row) = txn.read_by_id(&self.table_name, pk)? {
+                rows.push(row);
+            }
+        }
+
+        Ok(ResultSet::Scan {
+            columns: ColumnDesc::from_table(&table),
+            rows,
+        })
+    }
+}
+
+// 扫描过程: 全文索引中包含 query 所有词的行，用于 match(col, 'words') 条件;
+pub struct FullTextScan {
+    table_name: String,
+    field: String,
+    query: String,
+}
+
+impl FullTextScan {
+    pub fn new(table_name: String, field: String, query: String) -> Box<Self> {
+        Box::new(Self {
+            table_name,
+            field,
+            query,
+        })
+    }
+}
+
+impl<T: Transaction> Executor<T> for FullTextScan {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let table = txn.must_get_table(self.table_name.clone())?;
+        let index = txn.load_fulltext(&self.table_name, &self.field, &self.query)?;
+        let mut pks = index.iter().collect::<Vec<_>>();
+        pks.sort_by(|v1, v2| match v1.partial_cmp(v2) {
+            Some(ord) => ord,
+            None => Ordering::Equal,
+        });
+
+        let mut rows = Vec::new();
+        for pk in pks {
+            if let Some(row) = txn.read_by_id(&self.table_name, pk)? {
+                rows.push(row);
+            }
+        }
+
+        Ok(ResultSet::Scan {
+            columns: ColumnDesc::from_table(&table),
+            rows,
+        })
+    }
+}
+
+pub struct BitmapScan {
+    table_name: String,
+    condition: BitmapCondition,
+}
+
+impl BitmapScan {
+    pub fn new(table_name: String, condition: BitmapCondition) -> Box<Self> {
+        Box::new(Self {
+            table_name,
+            condition,
+        })
+    }
+
+    // 递归合并条件中的位图，And 取交集，Or 取并集
+    fn load<T: Transaction>(&self, txn: &T, condition: &BitmapCondition) -> Result<Bitmap> {
+        Ok(match condition {
+            BitmapCondition::Equal(field, value) => {
+                let table = txn.must_get_table(self.table_name.clone())?;
+                let col = &table.columns[table.get_col_index(field)?];
+                match coerce_lookup(value, &col.datatype) {
+                    Some(value) => txn.load_bitmap(&self.table_name, field, &value)?,
+                    None => Bitmap::new(),
+                }
+            }
+            BitmapCondition::And(l, r) => self.load(txn, l)?.and(&self.load(txn, r)?),
+            BitmapCondition::Or(l, r) => self.load(txn, l)?.or(&self.load(txn, r)?),
+        })
+    }
+}
+
+impl<T: Transaction> Executor<T> for BitmapScan {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let table = txn.must_get_table(self.table_name.clone())?;
+        // 位图按照主键的顺序遍历，合并完成之后才读取行
+        let bitmap = self.load(txn, &self.condition)?;
+        let mut rows = Vec::new();
+        for position in bitmap.iter() {
+            if let Some(row) = txn.read_by_id(&self.table_name, &bitmap::position_pk(position))? {
+                rows.push(row);
+            }
+        }
+
+        Ok(ResultSet::Scan {
+            columns: ColumnDesc::from_table(&table),
+            rows,
+        })
+    }
+}
+
+// 扫描过程: 主键以 prefix 开头的行，主键按顺序存储，只需要扫描这一段;
+pub struct PrimaryKeyPrefixScan {
+    table_name: String,
+    prefix: String,
+}
+
+impl PrimaryKeyPrefixScan {
+    pub fn new(table_name: String, prefix: String) -> Box<Self> {
+        Box::new(Self { table_name, prefix })
+    }
+}
+
+impl<T: Transaction> Executor<T> for PrimaryKeyPrefixScan {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let table = txn.must_get_table(self.table_name.clone())?;
+        let rows = txn.scan_pk_prefix(&self.table_name, &self.prefix)?;
+        Ok(ResultSet::Scan {
+            columns: ColumnDesc::from_table(&table),
             rows,
         })
     }
@@ -122,26 +459,30 @@ impl<T: Transaction> Filter<T> {
 
 impl<T: Transaction> Executor<T> for Filter<T> {
     fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
-        match self.source.execute(txn)? {
-            ResultSet::Scan { columns, rows } => {
-                let mut new_rows = Vec::new();
-                for row in rows {
-                    match evaluate_expr(&self.predicate, &columns, &row, &columns, &row)? {
-                        Value::Null => {}
-                        Value::Boolean(false) => {}
-                        Value::Boolean(true) => {
-                            new_rows.push(row);
-                        }
-                        _ => return Err(Error::Internal("Unexpected expression".into())),
-                    }
-                }
-                Ok(ResultSet::Scan {
-                    columns,
-                    rows: new_rows,
-                })
+        let (columns, rows) = self.scan(txn)?;
+        collect_rows(columns, rows)
+    }
+
+    fn scan(self: Box<Self>, txn: &mut T) -> Result<(Vec<ColumnDesc>, RowIter)> {
+        let (columns, rows) = self.source.scan(txn)?;
+        let cols = column_names(&columns);
+        let predicate = self.predicate;
+        let rows = rows.filter_map(move |row| {
+            let row = match row {
+                Ok(row) => row,
+                Err(err) => return Some(Err(err)),
+            };
+            match evaluate_expr(&predicate, &cols, &row, &cols, &row) {
+                Ok(Value::Null) | Ok(Value::Boolean(false)) => None,
+                Ok(Value::Boolean(true)) => Some(Ok(row)),
+                Ok(v) => Some(Err(Error::TypeMismatch(format!(
+                    "where condition must be boolean, got {}",
+                    v
+                )))),
+                Err(err) => Some(Err(err)),
             }
-            _ => return Err(Error::Internal("Unexpected result set".into())),
-        }
+        });
+        Ok((columns, Box::new(rows)))
     }
 }
 
@@ -162,114 +503,591 @@ impl<T: Transaction> Projection<T> {
 
 impl<T: Transaction> Executor<T> for Projection<T> {
     fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
-        match self.source.execute(txn)? {
-            ResultSet::Scan { columns, rows } => {
-                // 找到需要输出哪些列;
-                // 列的下标值;
-                let mut selected = Vec::new();
-                // 输出列的名字;
-                let mut new_columns = Vec::new();
-                // 并且判断是否存在 别名;
-                for (expr, alias) in self.exprs {
-                    if let Expression::Field(col_name) = expr {
-                        let pos = match columns.iter().position(|c| *c == col_name) {
-                            Some(pos) => pos,
-                            None => {
-                                return Err(Error::Internal(format!("column {} not in table", col_name)))
-                            }
-                        };
-                        selected.push(pos);
-                        new_columns.push(if alias.is_some() {
-                            alias.unwrap()
-                        } else {
-                            col_name
-                        });
-                    }
-                }
+        let (columns, rows) = self.scan(txn)?;
+        collect_rows(columns, rows)
+    }
 
-                // 很多行;
-                let mut new_rows = Vec::new();
-                for row in rows.into_iter() {
-                    // 每一行的 新列;
-                    let mut new_row_columns = Vec::new();
-                    for i in selected.iter() {
-                        new_row_columns.push(row[*i].clone());
-                    }
-                    new_rows.push(new_row_columns);
-                };
+    fn scan(self: Box<Self>, txn: &mut T) -> Result<(Vec<ColumnDesc>, RowIter)> {
+        let (source_columns, rows) = self.source.scan(txn)?;
+        let columns = column_names(&source_columns);
+        // 找到需要输出哪些列;
+        // 列直接取下标值，其他表达式对每一行求值;
+        let mut selected = Vec::new();
+        // 输出列的名字和类型，没有别名时列取列名，其他表达式取表达式本身;
+        let mut new_columns = Vec::new();
+        for (expr, alias) in self.exprs {
+            match expr {
+                Expression::Field(ref table, ref col_name) => {
+                    let pos = field_position(&columns, table.as_deref(), col_name)?;
+                    selected.push(Ok(pos));
+                    let column = source_columns[pos].clone();
+                    new_columns.push(column.renamed(alias.unwrap_or_else(|| expr.to_string())));
+                }
+                expr => {
+                    let (datatype, nullable) = expr_type(&expr, &source_columns);
+                    let name = alias.unwrap_or_else(|| expr.to_string());
+                    new_columns.push(ColumnDesc::new(name, datatype, nullable));
+                    selected.push(Err(expr));
+                }
+            }
+        }
 
-                Ok(ResultSet::Scan {
-                    columns: new_columns,
-                    rows: new_rows,
+        // 每读取一行，计算这一行的新列;
+        let rows = rows.map(move |row| {
+            let row = row?;
+            selected
+                .iter()
+                .map(|s| match s {
+                    Ok(i) => Ok(row[*i].clone()),
+                    Err(expr) => evaluate_expr(expr, &columns, &row, &columns, &row),
                 })
+                .collect()
+        });
+        Ok((new_columns, Box::new(rows)))
+    }
+}
+
+// 推断表达式结果的类型以及是否可能为 NULL，类型取决于运行时的值时类型为 None
+pub(super) fn expr_type(expr: &Expression, columns: &[ColumnDesc]) -> (Option<DataType>, bool) {
+    let operands = |exprs: &[&Expression]| {
+        let types = exprs.iter().map(|e| expr_type(e, columns)).collect::<Vec<_>>();
+        let nullable = types.iter().any(|(_, nullable)| *nullable);
+        (types, nullable)
+    };
+    match expr {
+        Expression::Field(table, name) => {
+            match field_position(&column_names(columns), table.as_deref(), name) {
+                Ok(pos) => (columns[pos].datatype.clone(), columns[pos].nullable),
+                Err(_) => (None, true),
             }
-            _ =>  Err(Error::Internal("Unexpected result set".into())),
         }
+        Expression::Consts(Consts::Null) => (None, true),
+        Expression::Consts(_) => {
+            let value = evaluate_expr(expr, &Vec::new(), &Vec::new(), &Vec::new(), &Vec::new());
+            (value.ok().and_then(|v| v.datatype()), false)
+        }
+        Expression::Operation(op) => match op {
+            Operation::Add(l, r)
+            | Operation::Subtract(l, r)
+            | Operation::Multiply(l, r)
+            | Operation::Divide(l, r) => {
+                let (types, nullable) = operands(&[l, r]);
+                let datatype = match (&types[0].0, &types[1].0) {
+                    (Some(DataType::Integer), Some(DataType::Integer)) => Some(DataType::Integer),
+                    (Some(DataType::Float), Some(DataType::Integer | DataType::Float))
+                    | (Some(DataType::Integer), Some(DataType::Float)) => Some(DataType::Float),
+                    // 定点小数的精度、日期和区间的运算结果取决于具体的值
+                    _ => None,
+                };
+                // 整数除以 0 等情况会报错，不会产生 NULL
+                (datatype, nullable)
+            }
+            Operation::IsDistinctFrom(..) | Operation::IsNotDistinctFrom(..) => {
+                (Some(DataType::Boolean), false)
+            }
+            Operation::Equal(l, r)
+            | Operation::GreaterThan(l, r)
+            | Operation::LessThan(l, r)
+            | Operation::Like(l, r)
+            | Operation::And(l, r)
+            | Operation::Or(l, r) => (Some(DataType::Boolean), operands(&[l, r]).1),
+            Operation::Not(e) => (Some(DataType::Boolean), operands(&[e]).1),
+            Operation::Between(e, low, high) => {
+                (Some(DataType::Boolean), operands(&[e, low, high]).1)
+            }
+            Operation::In(..) | Operation::InSubquery(..) => (Some(DataType::Boolean), true),
+        },
+        Expression::Call(name, args) => {
+            let types = args.iter().map(|e| expr_type(e, columns)).collect::<Vec<_>>();
+            functions::return_type(name, &types)
+        }
+        Expression::Cast(e, datatype) => (Some(datatype.clone()), expr_type(e, columns).1),
+        Expression::Array(items) => {
+            let types = items.iter().map(|e| expr_type(e, columns).0).collect::<Vec<_>>();
+            let datatype = match types.first() {
+                Some(Some(first)) if types.iter().all(|t| t.as_ref() == Some(first)) => {
+                    Some(DataType::Array(Box::new(first.clone())))
+                }
+                _ => None,
+            };
+            (datatype, false)
+        }
+        // 下标越界时为 NULL
+        Expression::Index(array, _) => match expr_type(array, columns).0 {
+            Some(DataType::Array(datatype)) => (Some(*datatype), true),
+            _ => (None, true),
+        },
+        Expression::Exists(_) => (Some(DataType::Boolean), false),
+        Expression::Function(..)
+        | Expression::Row(_)
+        | Expression::Subquery(_)
+        | Expression::Default => (None, true),
     }
 }
 
 // 针对结果: 进行多列排序;
 pub struct Order<T: Transaction> {
     source: Box<dyn Executor<T>>,
-    order_by: Vec<(String, OrderDirection)>,
+    order_by: Vec<(Expression, OrderDirection)>,
 }
 
 impl<T: Transaction> Order<T> {
-    pub fn new(source: Box<dyn Executor<T>>, order_by: Vec<(String, OrderDirection)>) -> Box<Self> {
+    pub fn new(source: Box<dyn Executor<T>>, order_by: Vec<(Expression, OrderDirection)>) -> Box<Self> {
         Box::new(Self { source, order_by })
     }
 }
 
 impl<T: Transaction> Executor<T> for Order<T> {
     fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
-        match self.source.execute(txn)? {
-            ResultSet::Scan { columns, mut rows } => {
-                // 找到 order by 的列对应表中的列的位置;
-                let mut order_col_index = HashMap::new();
-                // <order_by_index, column_index>
-                for (i, (col_name, _)) in self.order_by.iter().enumerate() {
-                    match columns.iter().position(|c| *c == *col_name) {
-                        Some(pos) => order_col_index.insert(i, pos),
-                        None => {
-                            return Err(Error::Internal(format!("order by column {} is not in table", col_name)))
+        let (columns, rows) = self.scan(txn)?;
+        collect_rows(columns, rows)
+    }
+
+    // 排序需要缓存全部数据，超过内存限制时把已经缓存的行排好序写入临时文件，清空之后继续读取
+    // 最后多路归并所有有序的临时文件（外部排序），归并时每个临时文件只需要在内存中保留一行
+    fn scan(self: Box<Self>, txn: &mut T) -> Result<(Vec<ColumnDesc>, RowIter)> {
+        let (source_columns, rows) = self.source.scan(txn)?;
+        let columns = column_names(&source_columns);
+        // 找到 order by 的列对应结果中的列的位置，整数常量是结果中的位置（从 1 开始）;
+        // 其他表达式对每一行求值之后追加到行的末尾，按照追加的列排序，输出之前去掉
+        let width = columns.len();
+        let mut exprs = Vec::new();
+        let key = SortKey(
+            self.order_by
+                .into_iter()
+                .map(|(expr, direction)| {
+                    let pos = match expr {
+                        Expression::Field(table, col_name) => {
+                            field_position(&columns, table.as_deref(), &col_name)?
+                        }
+                        Expression::Consts(Consts::Integer(n)) => match usize::try_from(n) {
+                            Ok(n @ 1..) if n <= width => n - 1,
+                            _ => {
+                                return Err(Error::InvalidInput(format!(
+                                    "ORDER BY position {} is not in select list",
+                                    n
+                                )))
+                            }
+                        },
+                        expr => {
+                            exprs.push(expr);
+                            width + exprs.len() - 1
                         }
                     };
+                    Ok((pos, direction))
+                })
+                .collect::<Result<_>>()?,
+        );
+
+        let mut buffer = Vec::new();
+        let mut bytes = 0;
+        let mut runs = Vec::new();
+        for row in rows {
+            let mut row = row?;
+            for expr in &exprs {
+                let value = evaluate_expr(expr, &columns, &row, &columns, &row)?;
+                row.push(value);
+            }
+            let size = row_size(&row);
+            if let Err(err) = txn.memory().reserve("Order", size) {
+                txn.memory().release(bytes);
+                bytes = 0;
+                // 单独一行就超过限制时无法排序
+                if buffer.is_empty() {
+                    return Err(err);
                 }
+                runs.push(key.spill(std::mem::take(&mut buffer))?);
+                txn.memory().reserve("Order", size)?;
+            }
+            bytes += size;
+            buffer.push(row);
+        }
+        txn.memory().release(bytes);
+        let strip = move |mut row: Row| {
+            row.truncate(width);
+            row
+        };
+        if runs.is_empty() {
+            key.sort(&mut buffer);
+            return Ok((source_columns, Box::new(buffer.into_iter().map(strip).map(Ok))));
+        }
 
-                // 多个行(容器)参与比较;
-                rows.sort_by(|col1, col2| {
-                    // select a,b from user order by c,d desc e asc;
-                    // 迭代 order_by 参数, 可能存在多个 desc asc 列值;
-                    for (i, (_, direction)) in self.order_by.iter().enumerate() {
-                        let col_index = order_col_index.get(&i).unwrap();
-                        // 每一行的固定列值来参与 排序;
-                        let x = &col1[*col_index];
-                        let y = &col2[*col_index];
-
-                        match x.partial_cmp(y) {
-                            Some(Ordering::Equal) => {}
-                            Some(o) => {
-                                // 升序;否则降序;
-                                return if *direction == OrderDirection::Asc {
-                                    o
-                                } else {
-                                    o.reverse()
-                                }
-                            }
-                            None => {}
-                        }
-                    }
-                    Ordering::Equal
-                });
+        // 最后一批数据同样写入临时文件，归并时不再占用内存
+        runs.push(key.spill(buffer)?);
+        tracing::debug!(runs = runs.len(), "order spilled to disk");
+        let runs = runs
+            .into_iter()
+            .map(|run| Ok(Box::new(run.into_rows()?) as RowIter))
+            .collect::<Result<Vec<_>>>()?;
+        let rows = MergeRuns::new(key, runs)?.map(move |row| row.map(strip));
+        Ok((source_columns, Box::new(rows)))
+    }
+}
+
+// order by 的列在结果中的位置以及排序方向
+struct SortKey(Vec<(usize, OrderDirection)>);
+
+impl SortKey {
+    // select a,b from user order by c,d desc e asc;
+    // 依次比较每个 order by 的列，前面的列相等时才比较后面的列
+    fn compare(&self, row1: &Row, row2: &Row) -> Ordering {
+        for (i, direction) in &self.0 {
+            match row1[*i].partial_cmp(&row2[*i]) {
+                Some(Ordering::Equal) | None => {}
+                // 升序;否则降序;
+                Some(o) if *direction == OrderDirection::Asc => return o,
+                Some(o) => return o.reverse(),
+            }
+        }
+        Ordering::Equal
+    }
+
+    // 稳定排序，order by 的列相等的行保持原来的顺序
+    fn sort(&self, rows: &mut [Row]) {
+        rows.sort_by(|row1, row2| self.compare(row1, row2));
+    }
+
+    // 排好序之后写入一个临时文件
+    fn spill(&self, mut rows: Vec<Row>) -> Result<SpillFile> {
+        self.sort(&mut rows);
+        let mut file = SpillFile::new()?;
+        for row in rows.iter() {
+            file.write(row)?;
+        }
+        Ok(file)
+    }
+}
+
+// 多路归并有序的临时文件，每次输出所有文件当前行中最小的一行
+// 相等时取前面的文件，前面的文件中的行先读取到，因此归并的结果依然是稳定的
+struct MergeRuns {
+    key: SortKey,
+    runs: Vec<RowIter>,
+    // 每个文件当前的行，读取完的文件为 None
+    heads: Vec<Option<Row>>,
+}
+
+impl MergeRuns {
+    fn new(key: SortKey, mut runs: Vec<RowIter>) -> Result<Self> {
+        let heads = runs
+            .iter_mut()
+            .map(|run| run.next().transpose())
+            .collect::<Result<_>>()?;
+        Ok(Self { key, runs, heads })
+    }
+}
+
+impl Iterator for MergeRuns {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut min: Option<usize> = None;
+        for (i, head) in self.heads.iter().enumerate() {
+            if let Some(row) = head {
+                let less = match min {
+                    Some(m) => self.key.compare(row, self.heads[m].as_ref()?) == Ordering::Less,
+                    None => true,
+                };
+                if less {
+                    min = Some(i);
+                }
+            }
+        }
+        let i = min?;
+        let row = self.heads[i].take()?;
+        match self.runs[i].next().transpose() {
+            Ok(next) => self.heads[i] = next,
+            Err(err) => return Some(Err(err)),
+        }
+        Some(Ok(row))
+    }
+}
+
+// 针对结果: 去掉重复的行，保留每行第一次出现的位置，因此不会打乱 order by 的顺序;
+pub struct Distinct<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+}
 
-                Ok(ResultSet::Scan { columns, rows })
+impl<T: Transaction> Distinct<T> {
+    pub fn new(source: Box<dyn Executor<T>>) -> Box<Self> {
+        Box::new(Self { source })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Distinct<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let (columns, rows) = self.source.scan(txn)?;
+        // 哈希表中缓存所有不重复的行
+        let mut seen = HashSet::new();
+        let mut results = Vec::new();
+        let mut bytes = 0;
+        for row in rows {
+            let row = row?;
+            if seen.contains(&row) {
+                continue;
+            }
+            let size = row_size(&row);
+            if let Err(err) = txn.memory().reserve("Distinct", size) {
+                txn.memory().release(bytes);
+                return Err(err);
             }
-            _ => return Err(Error::Internal("Unexpected result set".into())),
+            bytes += size;
+            seen.insert(row.clone());
+            results.push(row);
         }
+        txn.memory().release(bytes);
+        Ok(ResultSet::Scan {
+            columns,
+            rows: results,
+        })
+    }
+}
+
+// 集合操作: union 依次输出左右两边的行，intersect 输出两边都有的行，except 输出左边有、右边没有的行
+// all 为 false 时结果去重；为 true 时按照行出现的次数计算，例如左边有 3 行 r、右边有 1 行 r，
+// intersect all 输出 1 行，except all 输出 2 行
+pub struct SetOperation<T: Transaction> {
+    op: SetOperator,
+    all: bool,
+    left: Box<dyn Executor<T>>,
+    right: Box<dyn Executor<T>>,
+}
+
+impl<T: Transaction> SetOperation<T> {
+    pub fn new(
+        op: SetOperator,
+        all: bool,
+        left: Box<dyn Executor<T>>,
+        right: Box<dyn Executor<T>>,
+    ) -> Box<Self> {
+        Box::new(Self {
+            op,
+            all,
+            left,
+            right,
+        })
+    }
+}
+
+impl<T: Transaction> Executor<T> for SetOperation<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let (columns, rows) = self.scan(txn)?;
+        collect_rows(columns, rows)
+    }
+
+    // union all 逐行输出，其他情况需要缓存右边的行或者已经输出的行
+    fn scan(self: Box<Self>, txn: &mut T) -> Result<(Vec<ColumnDesc>, RowIter)> {
+        let Self {
+            op,
+            all,
+            left,
+            right,
+        } = *self;
+        let (columns, left) = left.scan(txn)?;
+        let (right_columns, right) = right.scan(txn)?;
+        if columns.len() != right_columns.len() {
+            return Err(Error::InvalidInput(format!(
+                "each {} query must have the same number of columns",
+                op
+            )));
+        }
+        let mut types = ColumnTypes {
+            op,
+            types: vec![None; columns.len()],
+        };
+        // 结果的列名取左边的列名，两边的类型确定且相同时才能确定结果的类型
+        let columns = columns
+            .into_iter()
+            .zip(right_columns)
+            .map(|(l, r)| ColumnDesc {
+                datatype: l.datatype.filter(|t| r.datatype.as_ref() == Some(t)),
+                nullable: l.nullable || r.nullable,
+                ..l
+            })
+            .collect();
+        if op == SetOperator::Union && all {
+            let rows = left.chain(right).map(move |row| {
+                let row = row?;
+                types.check(&row)?;
+                Ok(row)
+            });
+            return Ok((columns, Box::new(rows)));
+        }
+
+        let mut bytes = 0;
+        let result = merge_rows(txn, all, left, right, &mut types, &mut bytes);
+        txn.memory().release(bytes);
+        Ok((columns, Box::new(result?.into_iter().map(Ok))))
+    }
+}
+
+// 合并两边的行，bytes 记录占用的内存，由调用方释放
+fn merge_rows<T: Transaction>(
+    txn: &mut T,
+    all: bool,
+    left: RowIter,
+    right: RowIter,
+    types: &mut ColumnTypes,
+    bytes: &mut usize,
+) -> Result<Vec<Row>> {
+    let mut reserve = |txn: &mut T, row: &Row| -> Result<()> {
+        let size = row_size(row);
+        txn.memory().reserve("SetOperation", size)?;
+        *bytes += size;
+        Ok(())
+    };
+
+    // intersect、except 先读取右边，记录每一行出现的次数
+    let mut counts: HashMap<Row, usize> = HashMap::new();
+    let op = types.op;
+    let (left, right): (RowIter, Option<RowIter>) = match op {
+        SetOperator::Union => (Box::new(left.chain(right)), None),
+        _ => (left, Some(right)),
+    };
+    for row in right.into_iter().flatten() {
+        let row = row?;
+        types.check(&row)?;
+        match counts.get_mut(&row) {
+            Some(count) => *count += 1,
+            None => {
+                reserve(txn, &row)?;
+                counts.insert(row, 1);
+            }
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut results = Vec::new();
+    for row in left {
+        let row = row?;
+        types.check(&row)?;
+        // all 为 true 时右边的每一行只能和左边的一行匹配
+        let matched = match counts.get_mut(&row) {
+            Some(count) if all && *count > 0 => {
+                *count -= 1;
+                true
+            }
+            Some(_) => !all,
+            None => false,
+        };
+        let keep = match op {
+            SetOperator::Union => true,
+            SetOperator::Intersect => matched,
+            SetOperator::Except => !matched,
+        };
+        if !keep || (!all && seen.contains(&row)) {
+            continue;
+        }
+        reserve(txn, &row)?;
+        if !all {
+            seen.insert(row.clone());
+        }
+        results.push(row);
+    }
+    Ok(results)
+}
+
+// 结果中列的类型可能未知，例如 NULL 常量、标量子查询，按照实际的值检查两边同一列的类型是否相同，
+// NULL 和任意类型兼容
+// DECIMAL 的值和小数位数无关，不同小数位数的 DECIMAL 可以合并
+struct ColumnTypes {
+    op: SetOperator,
+    types: Vec<Option<DataType>>,
+}
+
+impl ColumnTypes {
+    fn check(&mut self, row: &Row) -> Result<()> {
+        for (expected, value) in self.types.iter_mut().zip(row) {
+            let Some(datatype) = value.datatype() else {
+                continue;
+            };
+            match expected {
+                None => *expected = Some(datatype),
+                Some(DataType::Decimal(..)) if matches!(datatype, DataType::Decimal(..)) => {}
+                Some(expected) if *expected == datatype => {}
+                Some(expected) => {
+                    return Err(Error::TypeMismatch(format!(
+                        "{} types {} and {} cannot be matched",
+                        self.op, expected, datatype
+                    )))
+                }
+            }
+        }
+        Ok(())
     }
 }
 
 // 针对结果: 限制条数;
+// 表采样，每一行独立地以 percent% 的概率输出
+// 不对每一行生成随机数，而是按照几何分布生成下一个被选中的行之前要跳过的行数，跳过的行直接丢弃
+pub struct Sample<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    percent: f64,
+    seed: Option<u64>,
+}
+
+impl<T: Transaction> Sample<T> {
+    pub fn new(source: Box<dyn Executor<T>>, percent: f64, seed: Option<u64>) -> Box<Self> {
+        Box::new(Self {
+            source,
+            percent,
+            seed,
+        })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Sample<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let (columns, rows) = self.scan(txn)?;
+        collect_rows(columns, rows)
+    }
+
+    fn scan(self: Box<Self>, txn: &mut T) -> Result<(Vec<ColumnDesc>, RowIter)> {
+        let (columns, rows) = self.source.scan(txn)?;
+        let rng = match self.seed {
+            Some(seed) => Rng::new(seed),
+            None => Rng::from_entropy(),
+        };
+        let iter = SampleIter {
+            rows,
+            rng,
+            probability: self.percent / 100.0,
+        };
+        Ok((columns, Box::new(iter)))
+    }
+}
+
+struct SampleIter {
+    rows: RowIter,
+    rng: Rng,
+    probability: f64,
+}
+
+impl Iterator for SampleIter {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.probability <= 0.0 {
+            return None;
+        }
+        // 跳过的行数 floor(ln(u) / ln(1 - p))，u 在 (0, 1] 之间均匀分布
+        let skip = match self.probability >= 1.0 {
+            true => 0,
+            false => {
+                let u = 1.0 - self.rng.next_f64();
+                (u.ln() / (1.0 - self.probability).ln()).floor() as usize
+            }
+        };
+        for _ in 0..skip {
+            // 读取出错时返回错误，而不是跳过
+            if let Err(err) = self.rows.next()? {
+                return Some(Err(err));
+            }
+        }
+        self.rows.next()
+    }
+}
+
 pub struct Limit<T: Transaction> {
     source: Box<dyn Executor<T>>,
     limit: usize,
@@ -283,14 +1101,14 @@ impl<T: Transaction> Limit<T> {
 
 impl<T: Transaction> Executor<T> for Limit<T> {
     fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
-        // limit 10 offset 10;
-        match self.source.execute(txn)? {
-            ResultSet::Scan { columns, rows } => Ok(ResultSet::Scan {
-                columns,
-                rows: rows.into_iter().take(self.limit).collect(),
-            }),
-            _ =>  Err(Error::Internal("Unexpected result set".into())),
-        }
+        let (columns, rows) = self.scan(txn)?;
+        collect_rows(columns, rows)
+    }
+
+    // 读取够 limit 行之后不再从下层读取
+    fn scan(self: Box<Self>, txn: &mut T) -> Result<(Vec<ColumnDesc>, RowIter)> {
+        let (columns, rows) = self.source.scan(txn)?;
+        Ok((columns, Box::new(rows.take(self.limit))))
     }
 }
 
@@ -309,13 +1127,22 @@ impl<T: Transaction> Offset<T> {
 impl<T: Transaction> Executor<T> for Offset<T> {
     // limit 10 offset 10;
     fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
-        match self.source.execute(txn)? {
-            ResultSet::Scan { columns, rows } => Ok(ResultSet::Scan {
-                columns,
-                rows: rows.into_iter().skip(self.offset).collect(),
-            }),
-            _ => Err(Error::Internal("Unexpected result set".into())),
-        }
+        let (columns, rows) = self.scan(txn)?;
+        collect_rows(columns, rows)
+    }
+
+    fn scan(self: Box<Self>, txn: &mut T) -> Result<(Vec<ColumnDesc>, RowIter)> {
+        let (columns, rows) = self.source.scan(txn)?;
+        // 跳过的行读取出错时同样返回错误
+        let mut skip = self.offset;
+        let rows = rows.filter(move |row| match (row, skip) {
+            (Ok(_), 1..) => {
+                skip -= 1;
+                false
+            }
+            _ => true,
+        });
+        Ok((columns, Box::new(rows)))
     }
 }
 
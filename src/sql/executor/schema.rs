@@ -1,6 +1,10 @@
 use crate::{
     error::Result,
-    sql::{engine::Transaction, schema::Table},
+    sql::{
+        engine::Transaction,
+        parser::ast::AlterTableAction,
+        schema::{IndexType, Table},
+    },
 };
 
 use super::{Executor, ResultSet};
@@ -45,3 +49,129 @@ impl<T: Transaction> Executor<T> for DropTable {
         })
     }
 }
+
+// 清空表;
+pub struct TruncateTable {
+    name: String,
+}
+
+impl TruncateTable {
+    pub fn new(name: String) -> Box<Self> {
+        Box::new(Self { name })
+    }
+}
+
+impl<T: Transaction> Executor<T> for TruncateTable {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let count = txn.truncate_table(self.name.clone())?;
+        Ok(ResultSet::TruncateTable {
+            table_name: self.name,
+            count,
+        })
+    }
+}
+
+// 修改表结构;
+pub struct AlterTable {
+    table_name: String,
+    action: AlterTableAction,
+}
+
+impl AlterTable {
+    pub fn new(table_name: String, action: AlterTableAction) -> Box<Self> {
+        Box::new(Self { table_name, action })
+    }
+}
+
+impl<T: Transaction> Executor<T> for AlterTable {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        txn.alter_table(self.table_name.clone(), self.action)?;
+        Ok(ResultSet::AlterTable {
+            table_name: self.table_name,
+        })
+    }
+}
+
+// 重建索引;
+pub struct Reindex {
+    table_name: String,
+    column: Option<String>,
+}
+
+impl Reindex {
+    pub fn new(table_name: String, column: Option<String>) -> Box<Self> {
+        Box::new(Self { table_name, column })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Reindex {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let count = txn.reindex(self.table_name.clone(), self.column)?;
+        Ok(ResultSet::Reindex {
+            table_name: self.table_name,
+            count,
+        })
+    }
+}
+
+// 在已有的表上创建索引;
+pub struct CreateIndex {
+    name: Option<String>,
+    table_name: String,
+    column: String,
+    index_type: IndexType,
+}
+
+impl CreateIndex {
+    pub fn new(
+        name: Option<String>,
+        table_name: String,
+        column: String,
+        index_type: IndexType,
+    ) -> Box<Self> {
+        Box::new(Self {
+            name,
+            table_name,
+            column,
+            index_type,
+        })
+    }
+}
+
+impl<T: Transaction> Executor<T> for CreateIndex {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let count = txn.create_index(
+            self.table_name.clone(),
+            self.name.clone(),
+            self.column.clone(),
+            self.index_type,
+        )?;
+        Ok(ResultSet::CreateIndex {
+            index_name: self.name.unwrap_or(self.column),
+            table_name: self.table_name,
+            count,
+        })
+    }
+}
+
+// 删除索引;
+pub struct DropIndex {
+    name: String,
+    table_name: String,
+}
+
+impl DropIndex {
+    pub fn new(name: String, table_name: String) -> Box<Self> {
+        Box::new(Self { name, table_name })
+    }
+}
+
+impl<T: Transaction> Executor<T> for DropIndex {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        txn.drop_index(self.table_name.clone(), self.name.clone())?;
+        Ok(ResultSet::DropIndex {
+            index_name: self.name,
+            table_name: self.table_name,
+        })
+    }
+}
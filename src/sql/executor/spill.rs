@@ -0,0 +1,81 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Seek, SeekFrom, Write},
+};
+
+use crate::{error::Result, sql::types::Row};
+
+// 临时文件，保存内存中放不下的数据，按照写入的顺序读出
+// 使用匿名的临时文件，drop 之后自动删除
+// 没有文件系统的环境（wasm）不支持落盘，超出内存限制的查询直接报错
+pub struct SpillFile {
+    writer: BufWriter<File>,
+    rows: usize,
+}
+
+impl SpillFile {
+    #[cfg(feature = "native")]
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(tempfile::tempfile()?),
+            rows: 0,
+        })
+    }
+
+    #[cfg(not(feature = "native"))]
+    pub fn new() -> Result<Self> {
+        Err(crate::error::Error::OutOfMemory(
+            "spilling to disk is not supported in this build".into(),
+        ))
+    }
+
+    pub fn write(&mut self, row: &Row) -> Result<()> {
+        bincode::serialize_into(&mut self.writer, row)?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows == 0
+    }
+
+    // 写入结束，从头读取所有的行
+    pub fn into_rows(mut self) -> Result<impl Iterator<Item = Result<Row>>> {
+        self.writer.flush()?;
+        let mut file = self.writer.into_inner().map_err(|err| err.into_error())?;
+        file.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::new(file);
+        Ok((0..self.rows).map(move |_| Ok(bincode::deserialize_from(&mut reader)?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::Result,
+        sql::types::{Row, Value},
+    };
+
+    use super::SpillFile;
+
+    #[test]
+    fn test_spill_file() -> Result<()> {
+        let rows = (0..1000)
+            .map(|i| vec![Value::Integer(i), Value::String(format!("row-{}", i)), Value::Null])
+            .collect::<Vec<Row>>();
+        let mut file = SpillFile::new()?;
+        assert!(file.is_empty());
+        for row in rows.iter() {
+            file.write(row)?;
+        }
+        assert_eq!(file.len(), 1000);
+        assert_eq!(file.into_rows()?.collect::<Result<Vec<_>>>()?, rows);
+
+        assert_eq!(SpillFile::new()?.into_rows()?.count(), 0);
+        Ok(())
+    }
+}
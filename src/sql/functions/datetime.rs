@@ -0,0 +1,413 @@
+use chrono::{
+    format::{Item, StrftimeItems},
+    Datelike, Duration, Months, NaiveDate, NaiveDateTime, Timelike, Utc,
+};
+
+use crate::{
+    error::{Error, Result},
+    sql::types::{temporal, Interval, Value},
+};
+
+// 日期、时间函数
+// 参数可以是 DATE、TIMESTAMP 类型，也可以是 YYYY-MM-DD、YYYY-MM-DD HH:MM:SS 格式的字符串
+// 参数是 DATE、TIMESTAMP 时返回对应的类型，参数是字符串时返回同样格式的字符串
+// 参数中有 NULL 时结果为 NULL
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+
+// 解析后的日期时间，date 表示只有日期部分，typed 表示参数是 DATE、TIMESTAMP 类型而不是字符串
+struct DateTime {
+    ts: NaiveDateTime,
+    date: bool,
+    typed: bool,
+}
+
+impl DateTime {
+    fn parse(value: &Value) -> Result<Option<Self>> {
+        let s = match value {
+            Value::Null => return Ok(None),
+            Value::String(s) => s.trim(),
+            Value::Date(d) => {
+                return Ok(Some(Self {
+                    ts: temporal::date_to_naive(*d)?.and_hms_opt(0, 0, 0).unwrap(),
+                    date: true,
+                    typed: true,
+                }))
+            }
+            Value::Timestamp(ts) => {
+                return Ok(Some(Self {
+                    ts: temporal::timestamp_to_naive(*ts)?,
+                    date: false,
+                    typed: true,
+                }))
+            }
+            v => return Err(invalid(v)),
+        };
+        for fmt in ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"] {
+            if let Ok(ts) = NaiveDateTime::parse_from_str(s, fmt) {
+                return Ok(Some(Self {
+                    ts,
+                    date: false,
+                    typed: false,
+                }));
+            }
+        }
+        match NaiveDate::parse_from_str(s, DATE_FORMAT) {
+            Ok(d) => Ok(Some(Self {
+                ts: d.and_hms_opt(0, 0, 0).unwrap(),
+                date: true,
+                typed: false,
+            })),
+            Err(_) => Err(invalid(value)),
+        }
+    }
+
+    // 只有日期部分的值，加减、截断的单位不小于天时依然只输出日期
+    fn into_value(self, unit: Unit) -> Value {
+        let date = self.date && unit >= Unit::Day;
+        match (self.typed, date) {
+            (true, true) => Value::Date(temporal::date_from_naive(self.ts.date())),
+            (true, false) => Value::Timestamp(temporal::timestamp_from_naive(self.ts)),
+            (false, true) => Value::String(self.ts.format(DATE_FORMAT).to_string()),
+            (false, false) => Value::String(self.ts.format(DATETIME_FORMAT).to_string()),
+        }
+    }
+}
+
+fn invalid(value: &Value) -> Error {
+    Error::InvalidInput(format!("invalid datetime value {}", value))
+}
+
+// 时间单位，按照从小到大排列
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum Unit {
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl Unit {
+    fn parse(value: &Value) -> Result<Self> {
+        let s = match value {
+            Value::String(s) => s.to_lowercase(),
+            v => return Err(Error::InvalidInput(format!("invalid time unit {}", v))),
+        };
+        // 单位可以是复数形式，例如 days
+        Ok(match s.trim().trim_end_matches('s') {
+            "second" => Unit::Second,
+            "minute" => Unit::Minute,
+            "hour" => Unit::Hour,
+            "day" => Unit::Day,
+            "week" => Unit::Week,
+            "month" => Unit::Month,
+            "year" => Unit::Year,
+            _ => return Err(Error::InvalidInput(format!("invalid time unit {}", s))),
+        })
+    }
+}
+
+// 当前的 UTC 时间，精确到秒
+pub fn now() -> Value {
+    let ts = Utc::now().naive_utc().with_nanosecond(0).unwrap();
+    DateTime {
+        ts,
+        date: false,
+        typed: false,
+    }
+    .into_value(Unit::Second)
+}
+
+// 当前的 UTC 日期
+pub fn current_date() -> Value {
+    Value::String(Utc::now().date_naive().format(DATE_FORMAT).to_string())
+}
+
+// date_add(ts, n, unit)，加上 n 个时间单位，sub 为 true 时减去
+// 也可以写成 date_add(ts, interval '1 day')
+pub fn date_add(ts: &Value, n: &Value, unit: Option<&Value>, sub: bool) -> Result<Value> {
+    let unit = match (n, unit) {
+        (Value::Interval(i), None) => return add_interval(ts, *i, sub),
+        (Value::Null, None) => return Ok(Value::Null),
+        (n, None) => return Err(Error::TypeMismatch(format!("invalid interval {}", n))),
+        (_, Some(unit)) => unit,
+    };
+    let (mut dt, n) = match (DateTime::parse(ts)?, n) {
+        (Some(dt), Value::Integer(n)) => (dt, if sub { -*n } else { *n }),
+        (None, _) | (_, Value::Null) => return Ok(Value::Null),
+        (_, n) => return Err(Error::TypeMismatch(format!("invalid interval count {}", n))),
+    };
+    let unit = Unit::parse(unit)?;
+    let overflow = || Error::InvalidInput("datetime out of range".into());
+    dt.ts = match unit {
+        Unit::Month | Unit::Year => {
+            let months = if unit == Unit::Year { n * 12 } else { n };
+            let m = Months::new(u32::try_from(months.unsigned_abs()).map_err(|_| overflow())?);
+            if months >= 0 {
+                dt.ts.checked_add_months(m)
+            } else {
+                dt.ts.checked_sub_months(m)
+            }
+        }
+        _ => {
+            let d = match unit {
+                Unit::Second => Duration::try_seconds(n),
+                Unit::Minute => Duration::try_minutes(n),
+                Unit::Hour => Duration::try_hours(n),
+                Unit::Day => Duration::try_days(n),
+                _ => Duration::try_weeks(n),
+            };
+            d.and_then(|d| dt.ts.checked_add_signed(d))
+        }
+    }
+    .ok_or_else(overflow)?;
+    Ok(dt.into_value(unit))
+}
+
+// 日期时间加上时间间隔，sub 为 true 时减去
+// 只有日期部分的值加上整天数的时间间隔时，结果依然只有日期部分
+pub fn add_interval(ts: &Value, interval: Interval, sub: bool) -> Result<Value> {
+    let mut dt = match DateTime::parse(ts)? {
+        Some(dt) => dt,
+        None => return Ok(Value::Null),
+    };
+    let interval = match sub {
+        true => interval.checked_neg(),
+        false => Some(interval),
+    };
+    let overflow = || Error::InvalidInput("datetime out of range".into());
+    let interval = interval.ok_or_else(overflow)?;
+    let months = u32::try_from(interval.months.unsigned_abs()).map_err(|_| overflow())?;
+    let months = Months::new(months);
+    dt.ts = if interval.months >= 0 {
+        dt.ts.checked_add_months(months)
+    } else {
+        dt.ts.checked_sub_months(months)
+    }
+    .and_then(|ts| ts.checked_add_signed(Duration::try_seconds(interval.seconds)?))
+    .ok_or_else(overflow)?;
+    let unit = if interval.seconds % Duration::days(1).num_seconds() == 0 {
+        Unit::Day
+    } else {
+        Unit::Second
+    };
+    Ok(dt.into_value(unit))
+}
+
+// 两个日期时间相减，得到时间间隔
+pub fn datetime_diff(l: &Value, r: &Value) -> Result<Value> {
+    Ok(match (DateTime::parse(l)?, DateTime::parse(r)?) {
+        (Some(l), Some(r)) => Value::Interval(Interval {
+            months: 0,
+            seconds: (l.ts - r.ts).num_seconds(),
+        }),
+        _ => Value::Null,
+    })
+}
+
+// extract(part FROM ts)，取出日期时间的一部分
+// dow 是星期几，星期日为 0；doy 是一年中的第几天；epoch 是 unix 时间戳
+pub fn extract(part: &Value, ts: &Value) -> Result<Value> {
+    let dt = match DateTime::parse(ts)? {
+        Some(dt) => dt.ts,
+        None => return Ok(Value::Null),
+    };
+    let part = match part {
+        Value::String(s) => s.to_lowercase(),
+        v => return Err(Error::InvalidInput(format!("invalid datetime part {}", v))),
+    };
+    Ok(Value::Integer(match part.as_str() {
+        "year" => dt.year() as i64,
+        "month" => dt.month() as i64,
+        "day" => dt.day() as i64,
+        "hour" => dt.hour() as i64,
+        "minute" => dt.minute() as i64,
+        "second" => dt.second() as i64,
+        "week" => dt.iso_week().week() as i64,
+        "dow" => dt.weekday().num_days_from_sunday() as i64,
+        "doy" => dt.ordinal() as i64,
+        "epoch" => dt.and_utc().timestamp(),
+        _ => {
+            return Err(Error::InvalidInput(format!(
+                "invalid datetime part {}",
+                part
+            )))
+        }
+    }))
+}
+
+// date_trunc(unit, ts)，按照时间单位截断，用于按天、按月分组
+pub fn date_trunc(unit: &Value, ts: &Value) -> Result<Value> {
+    let mut dt = match DateTime::parse(ts)? {
+        Some(dt) => dt,
+        None => return Ok(Value::Null),
+    };
+    let unit = Unit::parse(unit)?;
+    let date = dt.ts.date();
+    let (h, m, s) = (dt.ts.hour(), dt.ts.minute(), dt.ts.second());
+    dt.ts = match unit {
+        Unit::Second => date.and_hms_opt(h, m, s),
+        Unit::Minute => date.and_hms_opt(h, m, 0),
+        Unit::Hour => date.and_hms_opt(h, 0, 0),
+        Unit::Day => date.and_hms_opt(0, 0, 0),
+        Unit::Week => (date - Duration::days(date.weekday().num_days_from_monday() as i64))
+            .and_hms_opt(0, 0, 0),
+        Unit::Month => date.with_day(1).and_then(|d| d.and_hms_opt(0, 0, 0)),
+        Unit::Year => date.with_ordinal(1).and_then(|d| d.and_hms_opt(0, 0, 0)),
+    }
+    .unwrap();
+    Ok(dt.into_value(unit))
+}
+
+// date_format(ts, format)，按照 strftime 格式输出，例如 %Y-%m
+pub fn date_format(ts: &Value, fmt: &Value) -> Result<Value> {
+    let (dt, fmt) = match (DateTime::parse(ts)?, fmt) {
+        (Some(dt), Value::String(fmt)) => (dt, fmt),
+        (None, _) | (_, Value::Null) => return Ok(Value::Null),
+        (_, v) => {
+            return Err(Error::InvalidInput(format!(
+                "invalid datetime format {}",
+                v
+            )))
+        }
+    };
+    let items = strftime_items(fmt)?;
+    Ok(Value::String(
+        dt.ts.format_with_items(items.into_iter()).to_string(),
+    ))
+}
+
+// str_to_date(s, format)，按照 strftime 格式解析字符串，没有时间部分时返回日期
+pub fn str_to_date(s: &Value, fmt: &Value) -> Result<Value> {
+    let (s, fmt) = match (s, fmt) {
+        (Value::String(s), Value::String(fmt)) => (s, fmt),
+        (Value::Null, _) | (_, Value::Null) => return Ok(Value::Null),
+        (s, fmt) => {
+            return Err(Error::TypeMismatch(format!(
+                "can not parse {} as {}",
+                s, fmt
+            )))
+        }
+    };
+    strftime_items(fmt)?;
+    let unparsed = || Error::InvalidInput(format!("can not parse {} as {}", s, fmt));
+    let dt = match NaiveDateTime::parse_from_str(s, fmt) {
+        Ok(ts) => DateTime {
+            ts,
+            date: false,
+            typed: false,
+        },
+        Err(_) => DateTime {
+            ts: NaiveDate::parse_from_str(s, fmt)
+                .map_err(|_| unparsed())?
+                .and_hms_opt(0, 0, 0)
+                .unwrap(),
+            date: true,
+            typed: false,
+        },
+    };
+    Ok(dt.into_value(Unit::Day))
+}
+
+// 检查格式字符串，不合法的格式在输出时会 panic
+fn strftime_items(fmt: &str) -> Result<Vec<Item<'_>>> {
+    let items = StrftimeItems::new(fmt).collect::<Vec<_>>();
+    if items.contains(&Item::Error) {
+        return Err(Error::InvalidInput(format!(
+            "invalid datetime format {}",
+            fmt
+        )));
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::Result,
+        sql::types::{temporal, Value},
+    };
+
+    use super::{date_add, date_format, date_trunc, extract, now, str_to_date};
+
+    fn s(v: &str) -> Value {
+        Value::String(v.into())
+    }
+
+    #[test]
+    fn test_datetime_functions() -> Result<()> {
+        let ts = s("2024-01-31 10:20:30");
+        assert_eq!(
+            date_add(&ts, &Value::Integer(1), Some(&s("month")), false)?,
+            s("2024-02-29 10:20:30")
+        );
+        assert_eq!(
+            date_add(&ts, &Value::Integer(2), Some(&s("hours")), true)?,
+            s("2024-01-31 08:20:30")
+        );
+        assert_eq!(
+            date_add(&s("2024-03-01"), &Value::Integer(1), Some(&s("day")), true)?,
+            s("2024-02-29")
+        );
+        assert_eq!(
+            date_add(&s("2024-03-01"), &Value::Integer(90), Some(&s("minute")), false)?,
+            s("2024-03-01 01:30:00")
+        );
+        assert_eq!(
+            date_add(&Value::Null, &Value::Integer(1), Some(&s("day")), false)?,
+            Value::Null
+        );
+
+        assert_eq!(extract(&s("year"), &ts)?, Value::Integer(2024));
+        assert_eq!(extract(&s("second"), &ts)?, Value::Integer(30));
+        assert_eq!(extract(&s("dow"), &ts)?, Value::Integer(3));
+        assert_eq!(
+            extract(&s("epoch"), &s("1970-01-02"))?,
+            Value::Integer(86400)
+        );
+
+        assert_eq!(date_trunc(&s("month"), &ts)?, s("2024-01-01 00:00:00"));
+        assert_eq!(date_trunc(&s("week"), &s("2024-01-31"))?, s("2024-01-29"));
+        assert_eq!(date_format(&ts, &s("%Y/%m %H"))?, s("2024/01 10"));
+        assert_eq!(
+            str_to_date(&s("31/01/2024"), &s("%d/%m/%Y"))?,
+            s("2024-01-31")
+        );
+        assert_eq!(
+            str_to_date(&s("31/01/2024 10:20:30"), &s("%d/%m/%Y %H:%M:%S"))?,
+            ts
+        );
+
+        // DATE、TIMESTAMP 类型的参数返回对应的类型
+        let d = Value::Date(temporal::parse_date("2024-01-31")?);
+        assert_eq!(
+            date_add(&d, &Value::Integer(1), Some(&s("month")), false)?,
+            Value::Date(temporal::parse_date("2024-02-29")?)
+        );
+        assert_eq!(
+            date_add(&d, &Value::Integer(1), Some(&s("hour")), false)?,
+            Value::Timestamp(temporal::parse_timestamp("2024-01-31 01:00:00")?)
+        );
+        let ts = Value::Timestamp(temporal::parse_timestamp("2024-01-31 10:20:30")?);
+        assert_eq!(
+            date_trunc(&s("day"), &ts)?,
+            Value::Timestamp(temporal::parse_timestamp("2024-01-31")?)
+        );
+        assert_eq!(extract(&s("minute"), &ts)?, Value::Integer(20));
+        assert_eq!(date_format(&d, &s("%d/%m"))?, s("31/01"));
+
+        // now 的结果可以再次解析
+        assert!(extract(&s("year"), &now())? != Value::Null);
+
+        assert!(date_add(&s("2024-13-01"), &Value::Integer(1), Some(&s("day")), false).is_err());
+        assert!(date_add(&ts, &Value::Integer(1), Some(&s("fortnight")), false).is_err());
+        assert!(extract(&s("century"), &ts).is_err());
+        assert!(date_format(&ts, &s("%Q")).is_err());
+        assert!(str_to_date(&s("2024"), &s("%d/%m/%Y")).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+use std::collections::BTreeSet;
+
+use crate::{
+    error::{Error, Result},
+    sql::types::Value,
+};
+
+// 全文检索
+// 文本按照非字母数字的字符切分成词，转换成小写之后去重，全文索引为每个词保存包含它的主键集合
+// match(col, 'rust database') 表示文本中同时包含查询中的所有词
+
+// 切分文本，返回去重之后的词
+pub fn tokenize(text: &str) -> BTreeSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+// 文本中是否包含查询中的所有词，查询中没有任何词时不匹配
+pub fn text_match(text: &Value, query: &Value) -> Result<Value> {
+    let (text, query) = match (text, query) {
+        (Value::Null, _) | (_, Value::Null) => return Ok(Value::Null),
+        (Value::String(text), Value::String(query)) => (text, query),
+        (text, query) => {
+            return Err(Error::TypeMismatch(format!(
+                "match requires string arguments, got {} and {}",
+                text, query
+            )))
+        }
+    };
+    let words = tokenize(query);
+    Ok(Value::Boolean(
+        !words.is_empty() && words.is_subset(&tokenize(text)),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{error::Result, sql::types::Value};
+
+    use super::{text_match, tokenize};
+
+    #[test]
+    fn test_text_match() -> Result<()> {
+        assert_eq!(
+            tokenize("Rust, a fast database-engine; RUST!")
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec!["a", "database", "engine", "fast", "rust"]
+        );
+        let text = Value::String("An embedded SQL database written in Rust".into());
+        let query = |q: &str| Value::String(q.into());
+        assert_eq!(
+            text_match(&text, &query("rust database"))?,
+            Value::Boolean(true)
+        );
+        assert_eq!(text_match(&text, &query("RUST"))?, Value::Boolean(true));
+        assert_eq!(text_match(&text, &query("rust go"))?, Value::Boolean(false));
+        assert_eq!(text_match(&text, &query("dat"))?, Value::Boolean(false));
+        assert_eq!(text_match(&text, &query(" ,"))?, Value::Boolean(false));
+        assert_eq!(text_match(&Value::Null, &query("rust"))?, Value::Null);
+        assert!(text_match(&Value::Integer(1), &query("1")).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,232 @@
+use std::cmp::Ordering;
+
+use crate::error::{Error, Result};
+
+use super::{
+    parser::ast::{equal_values, Expression},
+    types::{DataType, Value},
+};
+
+mod datetime;
+pub mod fulltext;
+pub mod random;
+mod string;
+
+pub use datetime::{add_interval, datetime_diff};
+
+// 标量函数，对每一行的参数求值之后计算结果
+// 聚集函数 count、sum 等在 executor::agg 中计算
+
+// 判断是否是标量函数
+pub fn is_scalar(name: &str) -> bool {
+    matches!(
+        name,
+        "coalesce"
+            | "nullif"
+            | "ifnull"
+            | "greatest"
+            | "least"
+            | "now"
+            | "current_date"
+            | "current_timestamp"
+            | "date_add"
+            | "date_sub"
+            | "extract"
+            | "date_trunc"
+            | "date_format"
+            | "str_to_date"
+            | "match"
+            | "random"
+            | "uuid"
+            | "gen_random_uuid"
+            | "upper"
+            | "lower"
+            | "length"
+            | "substr"
+            | "substring"
+            | "concat"
+    )
+}
+
+// 返回布尔值的函数，可以直接作为 where 条件，例如 where match(body, 'rust')
+pub fn is_predicate(name: &str) -> bool {
+    name == "match"
+}
+
+// 每次调用结果都可能不同的函数，在执行时对每一行分别求值，解析和生成计划时不能当作常量计算
+// 包含这些函数的查询结果不能缓存
+pub fn is_volatile(name: &str) -> bool {
+    matches!(
+        name,
+        "now" | "current_date" | "current_timestamp" | "random" | "uuid" | "gen_random_uuid"
+    )
+}
+
+// 表达式中是否调用了 volatile 函数
+pub fn has_volatile(expr: &Expression) -> bool {
+    let mut volatile = false;
+    expr.walk(&mut |e| {
+        if let Expression::Call(name, _) = e {
+            volatile |= is_volatile(name);
+        }
+    });
+    volatile
+}
+
+// 检查参数的个数
+pub fn check_args(name: &str, count: usize) -> Result<()> {
+    let valid = match name {
+        "coalesce" | "greatest" | "least" | "concat" => count >= 1,
+        "upper" | "lower" | "length" => count == 1,
+        "substr" | "substring" => count == 2 || count == 3,
+        "nullif" | "ifnull" => count == 2,
+        "now" | "current_date" | "current_timestamp" => count == 0,
+        "random" | "uuid" | "gen_random_uuid" => count == 0,
+        "extract" | "date_trunc" | "date_format" | "str_to_date" | "match" => count == 2,
+        "date_add" | "date_sub" => count == 2 || count == 3,
+        _ => return Err(Error::NotFound(format!("function {} does not exist", name))),
+    };
+    if !valid {
+        return Err(Error::InvalidInput(format!(
+            "function {} does not accept {} arguments",
+            name, count
+        )));
+    }
+    Ok(())
+}
+
+// 函数结果的类型以及是否可能为 NULL，args 是每个参数的类型和是否可能为 NULL
+// 日期函数的结果和参数的类型相同，字符串参数返回字符串，DATE、TIMESTAMP 参数返回对应的类型
+pub fn return_type(name: &str, args: &[(Option<DataType>, bool)]) -> (Option<DataType>, bool) {
+    let arg = |i: usize| args.get(i).and_then(|(datatype, _)| datatype.clone());
+    let any_null = args.iter().any(|(_, nullable)| *nullable);
+    match name {
+        // 所有参数都是 NULL 时才返回 NULL
+        "coalesce" | "ifnull" | "greatest" | "least" => (
+            args.iter().find_map(|(datatype, _)| datatype.clone()),
+            args.iter().all(|(_, nullable)| *nullable),
+        ),
+        "nullif" => (arg(0), true),
+        "now" | "current_timestamp" | "current_date" => (Some(DataType::String), false),
+        "date_add" | "date_sub" | "substr" | "substring" => (arg(0), any_null),
+        "date_trunc" => (arg(1), any_null),
+        "extract" | "length" => (Some(DataType::Integer), any_null),
+        "date_format" | "str_to_date" | "upper" | "lower" => (Some(DataType::String), any_null),
+        "match" => (Some(DataType::Boolean), any_null),
+        "concat" | "uuid" | "gen_random_uuid" => (Some(DataType::String), false),
+        "random" => (Some(DataType::Float), false),
+        _ => (None, true),
+    }
+}
+
+// 计算标量函数
+pub fn call(name: &str, args: Vec<Value>) -> Result<Value> {
+    check_args(name, args.len())?;
+    Ok(match name {
+        // 返回第一个不为 NULL 的参数
+        "coalesce" | "ifnull" => args
+            .into_iter()
+            .find(|v| *v != Value::Null)
+            .unwrap_or(Value::Null),
+        // 两个参数相等时返回 NULL，否则返回第一个参数
+        "nullif" => {
+            let mut args = args.into_iter();
+            let (l, r) = (args.next().unwrap(), args.next().unwrap());
+            match equal_values(l.clone(), r)? {
+                Value::Boolean(true) => Value::Null,
+                _ => l,
+            }
+        }
+        // 忽略 NULL，所有参数都是 NULL 时返回 NULL
+        "greatest" => extreme(args, Ordering::Greater)?,
+        "least" => extreme(args, Ordering::Less)?,
+        "now" | "current_timestamp" => datetime::now(),
+        "current_date" => datetime::current_date(),
+        "date_add" => datetime::date_add(&args[0], &args[1], args.get(2), false)?,
+        "date_sub" => datetime::date_add(&args[0], &args[1], args.get(2), true)?,
+        "extract" => datetime::extract(&args[0], &args[1])?,
+        "date_trunc" => datetime::date_trunc(&args[0], &args[1])?,
+        "date_format" => datetime::date_format(&args[0], &args[1])?,
+        "str_to_date" => datetime::str_to_date(&args[0], &args[1])?,
+        "match" => fulltext::text_match(&args[0], &args[1])?,
+        "upper" => string::change_case(&args[0], true)?,
+        "lower" => string::change_case(&args[0], false)?,
+        "length" => string::length(&args[0])?,
+        "substr" | "substring" => string::substr(&args[0], &args[1], args.get(2))?,
+        // 忽略 NULL 参数，其他参数转换为字符串之后拼接
+        "concat" => string::concat(&args),
+        // [0, 1) 之间的随机浮点数
+        "random" => Value::Float(random::random()),
+        // 随机生成的 UUID（版本 4）
+        "uuid" | "gen_random_uuid" => Value::String(random::uuid()),
+        _ => unreachable!(),
+    })
+}
+
+// 找出参数中的最大值或者最小值，整数和浮点数可以比较，其他不同类型的值不能比较
+fn extreme(args: Vec<Value>, ord: Ordering) -> Result<Value> {
+    let mut result = Value::Null;
+    for arg in args.into_iter().filter(|v| *v != Value::Null) {
+        if result == Value::Null {
+            result = arg;
+            continue;
+        }
+        match arg.partial_cmp(&result) {
+            Some(o) if o == ord => result = arg,
+            Some(_) => {}
+            None => {
+                return Err(Error::TypeMismatch(format!(
+                    "can not compare {} and {}",
+                    arg, result
+                )))
+            }
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{error::Result, sql::types::Value};
+
+    use super::call;
+
+    #[test]
+    fn test_null_functions() -> Result<()> {
+        let null = Value::Null;
+        let one = Value::Integer(1);
+        assert_eq!(call("coalesce", vec![null.clone(), null.clone(), one.clone()])?, one);
+        assert_eq!(call("coalesce", vec![null.clone()])?, null);
+        assert_eq!(call("ifnull", vec![null.clone(), one.clone()])?, one);
+        assert_eq!(call("ifnull", vec![Value::Integer(2), one.clone()])?, Value::Integer(2));
+        assert_eq!(call("nullif", vec![one.clone(), Value::Float(1.0)])?, null);
+        assert_eq!(call("nullif", vec![one.clone(), Value::Integer(2)])?, one);
+        assert_eq!(call("nullif", vec![null.clone(), one.clone()])?, null);
+
+        assert!(call("coalesce", vec![]).is_err());
+        assert!(call("ifnull", vec![one.clone()]).is_err());
+        assert!(call("nullif", vec![one, Value::String("a".into())]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_greatest_least() -> Result<()> {
+        let args = vec![
+            Value::Integer(2),
+            Value::Null,
+            Value::Float(3.5),
+            Value::Integer(-1),
+        ];
+        assert_eq!(call("greatest", args.clone())?, Value::Float(3.5));
+        assert_eq!(call("least", args)?, Value::Integer(-1));
+        assert_eq!(
+            call("greatest", vec![Value::String("a".into()), Value::String("b".into())])?,
+            Value::String("b".into())
+        );
+        assert_eq!(call("least", vec![Value::Null, Value::Null])?, Value::Null);
+
+        assert!(call("greatest", vec![]).is_err());
+        assert!(call("least", vec![Value::Integer(1), Value::String("a".into())]).is_err());
+        Ok(())
+    }
+}
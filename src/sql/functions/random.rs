@@ -0,0 +1,88 @@
+use std::{
+    cell::RefCell,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+};
+
+// 伪随机数生成器（SplitMix64），不需要密码学强度，tablesample 采样以及 random()、uuid() 使用
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    // 使用随机的种子，RandomState 每次创建时的 key 不同
+    pub fn from_entropy() -> Self {
+        Self::new(RandomState::new().build_hasher().finish())
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // [0, 1) 之间均匀分布的浮点数
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+thread_local! {
+    // random()、uuid() 共用的随机数生成器，每个线程一个
+    static RNG: RefCell<Rng> = RefCell::new(Rng::from_entropy());
+}
+
+pub fn random() -> f64 {
+    RNG.with(|rng| rng.borrow_mut().next_f64())
+}
+
+// 版本 4 的 UUID，除了版本号和变体之外的 122 位都是随机数
+pub fn uuid() -> String {
+    let (hi, lo) = RNG.with(|rng| {
+        let mut rng = rng.borrow_mut();
+        (rng.next_u64(), rng.next_u64())
+    });
+    let hi = (hi & !0xf000) | 0x4000;
+    let lo = (lo & !(0b11 << 62)) | (0b10 << 62);
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        hi >> 32,
+        (hi >> 16) & 0xffff,
+        hi & 0xffff,
+        lo >> 48,
+        lo & 0xffff_ffff_ffff
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{uuid, Rng};
+
+    #[test]
+    fn test_rng() {
+        let (mut a, mut b) = (Rng::new(42), Rng::new(42));
+        let values = (0..1000).map(|_| a.next_f64()).collect::<Vec<_>>();
+        assert!(values.iter().all(|v| (0.0..1.0).contains(v)));
+        assert_eq!(values, (0..1000).map(|_| b.next_f64()).collect::<Vec<_>>());
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        assert!((mean - 0.5).abs() < 0.05, "{}", mean);
+        assert_ne!(Rng::new(1).next_u64(), Rng::new(2).next_u64());
+    }
+
+    #[test]
+    fn test_uuid() {
+        let id = uuid();
+        let parts = id.split('-').map(|p| p.len()).collect::<Vec<_>>();
+        assert_eq!(parts, vec![8, 4, 4, 4, 12]);
+        assert!(id.chars().all(|c| c == '-' || c.is_ascii_hexdigit()));
+        assert_eq!(&id[14..15], "4");
+        assert!(matches!(&id[19..20], "8" | "9" | "a" | "b"), "{}", id);
+        assert_ne!(uuid(), id);
+    }
+}
@@ -0,0 +1,117 @@
+use crate::{
+    error::{Error, Result},
+    sql::types::Value,
+};
+
+// 字符串函数，按照字符而不是字节计算长度和位置
+// 参数中有 NULL 时结果为 NULL，concat 除外
+
+fn invalid(name: &str, v: &Value) -> Error {
+    Error::TypeMismatch(format!("function {} does not accept argument {}", name, v))
+}
+
+// upper(s)、lower(s)
+pub fn change_case(s: &Value, upper: bool) -> Result<Value> {
+    Ok(match s {
+        Value::Null => Value::Null,
+        Value::String(s) if upper => Value::String(s.to_uppercase()),
+        Value::String(s) => Value::String(s.to_lowercase()),
+        v => return Err(invalid(if upper { "upper" } else { "lower" }, v)),
+    })
+}
+
+// length(s)，字符串返回字符数，二进制数据返回字节数
+pub fn length(s: &Value) -> Result<Value> {
+    Ok(match s {
+        Value::Null => Value::Null,
+        Value::String(s) => Value::Integer(s.chars().count() as i64),
+        Value::Blob(b) => Value::Integer(b.len() as i64),
+        v => return Err(invalid("length", v)),
+    })
+}
+
+// substr(s, start[, count])，位置从 1 开始，和 Postgres 一样 start 可以小于 1，
+// 例如 substr('hello', 0, 3) 返回 'he'，超出范围的部分忽略
+pub fn substr(s: &Value, start: &Value, count: Option<&Value>) -> Result<Value> {
+    let count = match count {
+        None => None,
+        Some(Value::Null) => return Ok(Value::Null),
+        Some(Value::Integer(n)) if *n < 0 => {
+            return Err(Error::InvalidInput(
+                "negative substring length not allowed".into(),
+            ))
+        }
+        Some(Value::Integer(n)) => Some(*n),
+        Some(v) => return Err(invalid("substr", v)),
+    };
+    let start = match start {
+        Value::Null => return Ok(Value::Null),
+        Value::Integer(n) => *n,
+        v => return Err(invalid("substr", v)),
+    };
+    // 转换为从 0 开始的 [begin, end) 区间
+    let range = |len: usize| {
+        let begin = start.saturating_sub(1);
+        let end = count.map_or(i64::MAX, |n| begin.saturating_add(n));
+        let clamp = |i: i64| i.clamp(0, len as i64) as usize;
+        (clamp(begin), clamp(end).max(clamp(begin)))
+    };
+    Ok(match s {
+        Value::Null => Value::Null,
+        Value::String(s) => {
+            let (begin, end) = range(s.chars().count());
+            Value::String(s.chars().skip(begin).take(end - begin).collect())
+        }
+        Value::Blob(b) => {
+            let (begin, end) = range(b.len());
+            Value::Blob(b[begin..end].into())
+        }
+        v => return Err(invalid("substr", v)),
+    })
+}
+
+// concat(a, b, ...)，忽略 NULL，其他类型的参数转换为字符串
+pub fn concat(args: &[Value]) -> Value {
+    let mut result = String::new();
+    for arg in args {
+        match arg {
+            Value::Null => {}
+            Value::String(s) => result.push_str(s),
+            v => result.push_str(&v.to_string()),
+        }
+    }
+    Value::String(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{change_case, concat, length, substr};
+    use crate::{error::Result, sql::types::Value};
+
+    #[test]
+    fn test_string_functions() -> Result<()> {
+        let s = |s: &str| Value::String(s.into());
+        let i = Value::Integer;
+        assert_eq!(change_case(&s("Straße"), true)?, s("STRASSE"));
+        assert_eq!(change_case(&s("AbC"), false)?, s("abc"));
+        assert_eq!(change_case(&Value::Null, true)?, Value::Null);
+        assert!(change_case(&i(1), true).is_err());
+
+        assert_eq!(length(&s("héllo"))?, i(5));
+        assert_eq!(length(&Value::Blob(vec![0, 1].into()))?, i(2));
+
+        assert_eq!(substr(&s("héllo"), &i(2), Some(&i(3)))?, s("éll"));
+        assert_eq!(substr(&s("hello"), &i(0), Some(&i(3)))?, s("he"));
+        assert_eq!(substr(&s("hello"), &i(4), None)?, s("lo"));
+        assert_eq!(substr(&s("hello"), &i(10), None)?, s(""));
+        assert_eq!(substr(&s("hello"), &i(-5), Some(&i(3)))?, s(""));
+        assert_eq!(substr(&s("hello"), &Value::Null, None)?, Value::Null);
+        assert!(substr(&s("hello"), &i(1), Some(&i(-1))).is_err());
+
+        assert_eq!(
+            concat(&[s("a"), Value::Null, i(1), Value::Boolean(true)]),
+            s("a1TRUE")
+        );
+        Ok(())
+    }
+}
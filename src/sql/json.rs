@@ -0,0 +1,130 @@
+// SQL 的值、执行结果和 JSON 之间的转换，HTTP 接口和 wasm 模块共用
+use serde_json::{json, Map, Number};
+
+use crate::{
+    error::{Error, Result},
+    sql::{
+        executor::{column_names, ResultSet},
+        types::Value,
+    },
+};
+
+// JSON 参数转换成 SQL 的值，整数转换成 INTEGER，其他数字转换成 FLOAT
+pub fn json_value(value: &serde_json::Value) -> Result<Value> {
+    Ok(match value {
+        serde_json::Value::Null => Value::Null,
+        serde_json::Value::Bool(b) => Value::Boolean(*b),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => Value::Float(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        serde_json::Value::String(s) => Value::String(s.clone()),
+        serde_json::Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(json_value)
+                .collect::<Result<Vec<_>>>()?
+                .into(),
+        ),
+        serde_json::Value::Object(_) => {
+            return Err(Error::InvalidInput(format!(
+                "unsupported parameter {}",
+                value
+            )))
+        }
+    })
+}
+
+pub fn to_json(value: Value) -> serde_json::Value {
+    match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Boolean(b) => b.into(),
+        Value::Integer(i) => i.into(),
+        // NaN、Infinity 不能表示成 JSON 数字
+        Value::Float(f) => Number::from_f64(f).map_or(serde_json::Value::Null, Into::into),
+        Value::String(s) => s.into(),
+        Value::Interval(i) => i.to_string().into(),
+        // 定点小数输出成字符串，避免转换成浮点数丢失精度
+        // 二进制数据输出成 \x 开头的十六进制字符串
+        v @ (Value::Date(_) | Value::Timestamp(_) | Value::Decimal(_) | Value::Blob(_)) => {
+            v.to_string().into()
+        }
+        Value::Array(items) => items.into_vec().into_iter().map(to_json).collect(),
+    }
+}
+
+// 结果的 type 字段是结果的类型，查询返回 columns、schema 和 rows，其他语句返回各自的字段
+// schema 中是每一列的名字、类型和是否可能为 NULL，类型未知时为 null
+pub fn result_json(result: ResultSet) -> serde_json::Value {
+    let (kind, mut fields) = match result {
+        ResultSet::Scan { columns, rows } => {
+            let rows = rows
+                .into_iter()
+                .map(|row| row.into_iter().map(to_json).collect())
+                .collect::<Vec<serde_json::Value>>();
+            let schema = columns
+                .iter()
+                .map(|c| {
+                    json!({
+                        "name": c.name,
+                        "type": c.datatype.as_ref().map(|t| t.to_string()),
+                        "nullable": c.nullable,
+                    })
+                })
+                .collect::<Vec<_>>();
+            let columns = column_names(&columns);
+            ("scan", json!({"columns": columns, "schema": schema, "rows": rows}))
+        }
+        ResultSet::CreateTable { table_name } => ("create_table", json!({"table": table_name})),
+        ResultSet::DropTable { table_name } => ("drop_table", json!({"table": table_name})),
+        ResultSet::AlterTable { table_name } => ("alter_table", json!({"table": table_name})),
+        ResultSet::TruncateTable { table_name, count } => (
+            "truncate_table",
+            json!({"table": table_name, "count": count}),
+        ),
+        ResultSet::Reindex { table_name, count } => {
+            ("reindex", json!({"table": table_name, "count": count}))
+        }
+        ResultSet::CreateIndex {
+            index_name,
+            table_name,
+            count,
+        } => (
+            "create_index",
+            json!({"index": index_name, "table": table_name, "count": count}),
+        ),
+        ResultSet::DropIndex {
+            index_name,
+            table_name,
+        } => (
+            "drop_index",
+            json!({"index": index_name, "table": table_name}),
+        ),
+        ResultSet::Insert { count } => ("insert", json!({ "count": count })),
+        ResultSet::Update { count } => ("update", json!({ "count": count })),
+        ResultSet::Delete { count } => ("delete", json!({ "count": count })),
+        ResultSet::Vacuum { count } => ("vacuum", json!({ "count": count })),
+        ResultSet::Backup { count } => ("backup", json!({ "count": count })),
+        ResultSet::Restore { count } => ("restore", json!({ "count": count })),
+        ResultSet::Copy { count } => ("copy", json!({ "count": count })),
+        ResultSet::Begin { version } => ("begin", json!({ "version": version })),
+        ResultSet::Commit { version } => ("commit", json!({ "version": version })),
+        ResultSet::Rollback { version } => ("rollback", json!({ "version": version })),
+        ResultSet::Savepoint { name } => ("savepoint", json!({ "name": name })),
+        ResultSet::RollbackToSavepoint { name } => {
+            ("rollback_to_savepoint", json!({ "name": name }))
+        }
+        ResultSet::ReleaseSavepoint { name } => ("release_savepoint", json!({ "name": name })),
+        ResultSet::Explain { plan } => ("explain", json!({ "plan": plan })),
+        ResultSet::Set { name, value } => ("set", json!({"name": name, "value": to_json(value)})),
+        ResultSet::DeclareCursor { name } => ("declare_cursor", json!({ "name": name })),
+        ResultSet::CloseCursor { name } => ("close_cursor", json!({ "name": name })),
+    };
+    let map: &mut Map<String, serde_json::Value> = fields.as_object_mut().unwrap();
+    map.insert("type".into(), kind.into());
+    fields
+}
+
+pub fn error_json(err: &Error) -> serde_json::Value {
+    json!({"error": err.to_string(), "code": err.sqlstate()})
+}
@@ -0,0 +1,375 @@
+use std::path::Path;
+
+use crate::error::{Error, Result};
+
+use super::{
+    engine::{Engine, Session},
+    executor::ResultSet,
+    types::Value,
+};
+
+// sqllogictest 格式的测试脚本执行器，每条记录之间用空行分隔:
+//
+// statement ok
+// create table t (a int primary key, b text);
+//
+// statement error not found
+// select * from t2;
+//
+// query IT rowsort
+// select a, b from t;
+// ----
+// 1 x
+// 2 NULL
+//
+// query I nosort
+// select a from t;
+// ----
+// 2 values hashing to 6ddb4095eb719e2a9f0a3f95677d24e0
+//
+// query 的类型字符串中每个字符对应一列：I 整数、R 浮点数(保留 3 位小数)、T 文本
+// 排序方式：nosort(默认)、rowsort(按行排序)、valuesort(所有值排序)
+// 结果可以逐行给出(同一行的值用空格分隔)，也可以给出所有值的 md5
+// 支持 skipif/onlyif 条件，本数据库的名字为 sqldb；halt 停止执行后面的记录
+
+// 数据库名字，用于 skipif/onlyif
+const DB_NAME: &str = "sqldb";
+
+#[derive(Debug, PartialEq)]
+enum SortMode {
+    Unsorted,
+    Rows,
+    Values,
+}
+
+#[derive(Debug, PartialEq)]
+enum Record {
+    Statement {
+        line: usize,
+        sql: String,
+        // None 表示执行成功，Some 表示执行失败，并且错误信息包含给定的内容
+        error: Option<String>,
+    },
+    Query {
+        line: usize,
+        sql: String,
+        types: Vec<char>,
+        sort: SortMode,
+        expected: Vec<String>,
+    },
+    Halt,
+}
+
+// 执行测试脚本，返回执行的记录数
+pub fn run<E: Engine + 'static>(session: &mut Session<E>, script: &str) -> Result<usize> {
+    let mut count = 0;
+    for record in parse(script)? {
+        match record {
+            Record::Statement { line, sql, error } => {
+                match (session.execute(&sql), error) {
+                    (Ok(_), None) => {}
+                    (Err(err), Some(expected)) if err.to_string().contains(&expected) => {}
+                    (Ok(_), Some(_)) => return Err(failure(line, &sql, "expected error")),
+                    (Err(err), _) => {
+                        return Err(failure(line, &sql, &format!("unexpected error: {}", err)))
+                    }
+                }
+            }
+            Record::Query {
+                line,
+                sql,
+                types,
+                sort,
+                expected,
+            } => {
+                let rows = match session.execute(&sql) {
+                    Ok(ResultSet::Scan { rows, .. }) => rows,
+                    Ok(result) => {
+                        return Err(failure(line, &sql, &format!("not a query: {}", result)))
+                    }
+                    Err(err) => {
+                        return Err(failure(line, &sql, &format!("unexpected error: {}", err)))
+                    }
+                };
+                check_query(&rows, &types, &sort, &expected)
+                    .map_err(|msg| failure(line, &sql, &msg))?;
+            }
+            Record::Halt => break,
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+// 执行测试脚本文件
+pub fn run_file<E: Engine + 'static>(session: &mut Session<E>, path: &Path) -> Result<usize> {
+    let script = std::fs::read_to_string(path)?;
+    run(session, &script).map_err(|err| match err {
+        Error::Internal(msg) => Error::Internal(format!("{}:{}", path.display(), msg)),
+        err => err,
+    })
+}
+
+fn failure(line: usize, sql: &str, msg: &str) -> Error {
+    Error::Internal(format!("{}: {}\n{}", line, msg, sql))
+}
+
+// 解析测试脚本
+fn parse(script: &str) -> Result<Vec<Record>> {
+    let mut records = Vec::new();
+    let mut lines = script.lines().enumerate().map(|(i, l)| (i + 1, l)).peekable();
+    // 当前记录是否需要跳过
+    let mut skip = false;
+
+    while let Some((line, text)) = lines.next() {
+        let text = text.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+
+        let args = text.split_whitespace().collect::<Vec<_>>();
+        // 读取 SQL，直到空行或者 ----
+        let mut read_sql = || {
+            let mut sql = Vec::new();
+            while let Some((_, l)) = lines.next_if(|(_, l)| !l.trim().is_empty() && l.trim() != "----") {
+                sql.push(l);
+            }
+            sql.join("\n")
+        };
+
+        let record = match args[0] {
+            "skipif" | "onlyif" => {
+                let matched = args.get(1) == Some(&DB_NAME);
+                skip |= (args[0] == "skipif") == matched;
+                continue;
+            }
+            "hash-threshold" => continue,
+            "halt" => Record::Halt,
+            "statement" => {
+                let error = match args.get(1) {
+                    Some(&"ok") => None,
+                    Some(&"error") => Some(args[2..].join(" ")),
+                    _ => return Err(Error::Parse(format!("{}: invalid statement record", line))),
+                };
+                Record::Statement {
+                    line,
+                    sql: read_sql(),
+                    error,
+                }
+            }
+            "query" => {
+                let types = args.get(1).map(|t| t.chars().collect()).unwrap_or_default();
+                let sort = match args.get(2) {
+                    None | Some(&"nosort") => SortMode::Unsorted,
+                    Some(&"rowsort") => SortMode::Rows,
+                    Some(&"valuesort") => SortMode::Values,
+                    Some(mode) => {
+                        return Err(Error::Parse(format!("{}: invalid sort mode {}", line, mode)))
+                    }
+                };
+                let sql = read_sql();
+                let mut expected = Vec::new();
+                if lines.next_if(|(_, l)| l.trim() == "----").is_some() {
+                    while let Some((_, l)) = lines.next_if(|(_, l)| !l.trim().is_empty()) {
+                        expected.push(l.trim().to_string());
+                    }
+                }
+                Record::Query {
+                    line,
+                    sql,
+                    types,
+                    sort,
+                    expected,
+                }
+            }
+            _ => return Err(Error::Parse(format!("{}: unknown record {}", line, args[0]))),
+        };
+
+        if !skip {
+            records.push(record);
+        }
+        skip = false;
+    }
+    Ok(records)
+}
+
+// 比较查询结果
+fn check_query(
+    rows: &[Vec<Value>],
+    types: &[char],
+    sort: &SortMode,
+    expected: &[String],
+) -> std::result::Result<(), String> {
+    let mut results = Vec::new();
+    for row in rows {
+        if row.len() != types.len() {
+            return Err(format!("expected {} columns, got {}", types.len(), row.len()));
+        }
+        results.push(
+            row.iter()
+                .zip(types)
+                .map(|(v, t)| format_value(v, *t))
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    match sort {
+        SortMode::Unsorted => {}
+        SortMode::Rows => results.sort(),
+        SortMode::Values => {
+            let mut values = results.into_iter().flatten().collect::<Vec<_>>();
+            values.sort();
+            results = values.into_iter().map(|v| vec![v]).collect();
+        }
+    }
+
+    let values = results.iter().flatten().collect::<Vec<_>>();
+    // N values hashing to <md5>
+    if let [line] = expected {
+        if line.contains(" values hashing to ") {
+            // 每个值后面跟一个换行符，计算 md5
+            let mut data = String::new();
+            for v in values.iter() {
+                data.push_str(v);
+                data.push('\n');
+            }
+            let actual = format!("{} values hashing to {:x}", values.len(), md5::compute(data));
+            if actual != *line {
+                return Err(format!("expected {}, got {}", line, actual));
+            }
+            return Ok(());
+        }
+    }
+
+    let actual = results.iter().map(|r| r.join(" ")).collect::<Vec<_>>();
+    // 期望结果也可以每行一个值
+    let matched = actual == expected
+        || (expected.len() == values.len() && values.iter().zip(expected).all(|(v, e)| *v == e));
+    if !matched {
+        return Err(format!(
+            "expected:\n{}\ngot:\n{}",
+            expected.join("\n"),
+            actual.join("\n")
+        ));
+    }
+    Ok(())
+}
+
+// 按照列的类型格式化值
+fn format_value(value: &Value, t: char) -> String {
+    match (value, t) {
+        (Value::Null, _) => "NULL".into(),
+        (Value::Boolean(b), 'I') => (*b as i64).to_string(),
+        (Value::Float(f), 'I') => (*f as i64).to_string(),
+        (Value::Integer(i), 'R') => format!("{:.3}", *i as f64),
+        (Value::Float(f), 'R') => format!("{:.3}", f),
+        (Value::String(s), _) if s.is_empty() => "(empty)".into(),
+        (v, _) => v.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        error::Result,
+        sql::engine::{kv::KVEngine, Engine},
+        storage::memory::MemoryEngine,
+    };
+
+    use super::{parse, run, run_file, Record, SortMode};
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        let script = "
+# comment
+statement ok
+create table t (a int primary key);
+
+skipif sqldb
+statement ok
+select * from t2;
+
+onlyif sqldb
+statement error not found
+select * from t2;
+
+query IT rowsort
+select a,
+  b from t;
+----
+1 x
+";
+        let records = parse(script)?;
+        assert_eq!(
+            records,
+            vec![
+                Record::Statement {
+                    line: 3,
+                    sql: "create table t (a int primary key);".into(),
+                    error: None,
+                },
+                Record::Statement {
+                    line: 11,
+                    sql: "select * from t2;".into(),
+                    error: Some("not found".into()),
+                },
+                Record::Query {
+                    line: 14,
+                    sql: "select a,\n  b from t;".into(),
+                    types: vec!['I', 'T'],
+                    sort: SortMode::Rows,
+                    expected: vec!["1 x".into()],
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_run() -> Result<()> {
+        let mut s = KVEngine::new(MemoryEngine::new()).session()?;
+        let script = "
+statement ok
+create table t (a int primary key, b float);
+
+statement ok
+insert into t values (1, 1.5), (2, 2.25);
+
+query IR nosort
+select * from t;
+----
+1 1.500
+2 2.250
+
+query R valuesort
+select b from t;
+----
+2 values hashing to f4b9e2bb19ec1bfb6e2bd4c31b37c5b4
+";
+        // hash 不一致时报错，并带有行号
+        let err = run(&mut s, script).unwrap_err().to_string();
+        assert!(err.contains("14: expected 2 values hashing to"), "{}", err);
+
+        let mut s = KVEngine::new(MemoryEngine::new()).session()?;
+        let hash = format!("{:x}", md5::compute("1.500\n2.250\n"));
+        assert_eq!(run(&mut s, &script.replace("f4b9e2bb19ec1bfb6e2bd4c31b37c5b4", &hash))?, 4);
+        Ok(())
+    }
+
+    // 执行 tests/slt 目录下的所有测试脚本
+    #[test]
+    fn test_slt_files() -> Result<()> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/slt");
+        let mut paths = std::fs::read_dir(dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        paths.sort();
+        for path in paths {
+            let mut s = KVEngine::new(MemoryEngine::new()).session()?;
+            run_file(&mut s, &path)?;
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,10 @@
+#[cfg(feature = "native")]
+pub mod bench;
 pub mod engine;
+pub mod logictest;
 pub mod executor;
+pub mod functions;
+pub mod json;
 pub mod parser;
 pub mod plan;
 pub mod schema;
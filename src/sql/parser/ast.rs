@@ -1,60 +1,550 @@
-use std::{collections::BTreeMap, fmt::Display};
+use std::{cmp::Ordering, collections::BTreeMap, fmt::Display};
 
 use crate::{
     error::{Error, Result},
-    sql::types::{DataType, Value},
+    sql::{
+        functions,
+        schema::{IndexType, Layout},
+        types::{
+            coerce::{cast_value, coerce_comparison},
+            blob, temporal, DataType, Decimal, Interval, Value,
+        },
+    },
+    storage::mvcc::IsolationLevel,
 };
 
 // Abstract Syntax Tree 抽象语法树定义
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
+#[allow(clippy::large_enum_variant)]
 pub enum Statement {
     CreateTable {
         name: String,
         columns: Vec<Column>,
+        // 表级别的 PRIMARY KEY (a, b) 约束，没有时为空
+        primary_key: Vec<String>,
+        // 表级别的 CHECK (expr) 约束
+        checks: Vec<Expression>,
+        layout: Layout,
     },
     DropTable {
         name: String,
     },
+    TruncateTable {
+        name: String,
+    },
+    AlterTable {
+        name: String,
+        action: AlterTableAction,
+    },
+    Reindex {
+        table_name: String,
+        column: Option<String>,
+    },
+    // create index [name] on t (col) [using type]
+    CreateIndex {
+        name: Option<String>,
+        table_name: String,
+        column: String,
+        index_type: IndexType,
+    },
+    DropIndex {
+        name: String,
+        table_name: String,
+    },
     Insert {
         table_name: String,
         columns: Option<Vec<String>>,
         values: Vec<Vec<Expression>>,
     },
     Select {
+        // SELECT DISTINCT，去掉结果中重复的行
+        distinct: bool,
         select: Vec<(Expression, Option<String>)>,
         from: FromItem,
         where_clause: Option<Expression>,
-        group_by: Option<Expression>,
+        // GROUP BY 的列，没有分组时为空
+        group_by: Vec<Expression>,
         having: Option<Expression>,
-        order_by: Vec<(String, OrderDirection)>,
+        order_by: Vec<(Expression, OrderDirection)>,
+        limit: Option<Expression>,
+        offset: Option<Expression>,
+    },
+    // select ... union [all] select ...，order by、limit、offset 作用在合并之后的结果上
+    // 多个集合操作从左到右依次结合，left 可以是另一个 SetOperation
+    SetOperation {
+        op: SetOperator,
+        all: bool,
+        left: Box<Statement>,
+        right: Box<Statement>,
+        order_by: Vec<(Expression, OrderDirection)>,
         limit: Option<Expression>,
         offset: Option<Expression>,
     },
+    // from 不为空时和 from 中的表 join，set 表达式可以引用 from 中的列
+    // update a set x = b.y from b where a.id = b.id;
     Update {
         table_name: String,
         columns: BTreeMap<String, Expression>,
+        from: Option<FromItem>,
         where_clause: Option<Expression>,
     },
+    // using 不为空时和 using 中的表 join，删除能和 using 中的行匹配的行
+    // delete from a using b where a.id = b.id;
     Delete {
         table_name: String,
+        using: Option<FromItem>,
         where_clause: Option<Expression>,
     },
-    Begin,
+    // begin [transaction] [isolation level read committed | repeatable read | serializable]
+    // begin [transaction] read only [as of version n]
+    Begin {
+        isolation: Option<IsolationLevel>,
+        read_only: bool,
+        // 只读事务读取的历史版本，为空时读取最新的快照
+        as_of: Option<u64>,
+    },
     Commit,
     Rollback,
+    // savepoint name、rollback to [savepoint] name、release [savepoint] name
+    Savepoint {
+        name: String,
+    },
+    RollbackToSavepoint {
+        name: String,
+    },
+    ReleaseSavepoint {
+        name: String,
+    },
+    // analyze 为 true 时实际执行语句，输出每个节点的行数和耗时
     Explain {
         stmt: Box<Statement>,
+        format: ExplainFormat,
+        analyze: bool,
+    },
+    Set {
+        name: String,
+        value: Value,
+    },
+    Vacuum,
+    DeclareCursor {
+        name: String,
+        query: Box<Statement>,
+    },
+    // count 为空表示读取剩余的全部数据
+    Fetch {
+        name: String,
+        count: Option<usize>,
+    },
+    CloseCursor {
+        name: String,
     },
+    // 查看上一条语句的执行统计
+    ShowProfile,
+    // copy t from|to 'file.csv' [with (header = true, delimiter = ';')]
+    Copy {
+        table_name: String,
+        path: String,
+        direction: CopyDirection,
+        options: CopyOptions,
+    },
+    // backup to 'file'，把数据库的快照备份到一个新的文件中
+    Backup {
+        path: String,
+    },
+    // restore from 'file'，使用备份文件中的数据替换数据库中的所有数据
+    Restore {
+        path: String,
+    },
+}
+
+// explain 的输出格式
+// explain (format dot) select ... 输出 Graphviz 格式的执行计划
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ExplainFormat {
+    Text,
+    Dot,
+}
+
+// copy 的方向，from 从文件导入到表中，to 把表导出到文件
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CopyDirection {
+    From,
+    To,
+}
+
+// csv 文件的格式，header 表示第一行是列名，delimiter 是字段的分隔符
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct CopyOptions {
+    pub header: bool,
+    pub delimiter: u8,
+}
+
+impl Default for CopyOptions {
+    fn default() -> Self {
+        Self {
+            header: false,
+            delimiter: b',',
+        }
+    }
+}
+
+// alter table 支持的操作
+#[derive(Debug, PartialEq, Clone)]
+pub enum AlterTableAction {
+    // alter column c set default expr / drop default
+    AlterColumnDefault {
+        column: String,
+        default: Option<Expression>,
+    },
+    // alter column c [set data] type datatype
+    AlterColumnType {
+        column: String,
+        datatype: DataType,
+    },
+    // add [column] c datatype ...，不能添加主键列
+    AddColumn(Column),
+    // drop [column] c，不能删除主键列
+    DropColumn(String),
+    // rename [column] c to new_name
+    RenameColumn {
+        column: String,
+        new_name: String,
+    },
+}
+
+impl Display for AlterTableAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AlterTableAction::AlterColumnDefault {
+                column,
+                default: Some(default),
+            } => write!(f, "ALTER COLUMN {} SET DEFAULT {}", column, Operand(default)),
+            AlterTableAction::AlterColumnDefault {
+                column,
+                default: None,
+            } => write!(f, "ALTER COLUMN {} DROP DEFAULT", column),
+            AlterTableAction::AlterColumnType { column, datatype } => {
+                write!(f, "ALTER COLUMN {} TYPE {}", column, datatype)
+            }
+            AlterTableAction::AddColumn(column) => write!(f, "ADD COLUMN {}", column),
+            AlterTableAction::DropColumn(column) => write!(f, "DROP COLUMN {}", column),
+            AlterTableAction::RenameColumn { column, new_name } => {
+                write!(f, "RENAME COLUMN {} TO {}", column, new_name)
+            }
+        }
+    }
+}
+
+// 输出规范化的 SQL 文本，关键字大写，不带结尾的分号
+// 重新解析输出的文本会得到相同的语法树，可以用来做语句的归一化，例如结果缓存的 key
+impl Display for Statement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Statement::CreateTable {
+                name,
+                columns,
+                primary_key,
+                checks,
+                layout,
+            } => {
+                write!(f, "CREATE TABLE {} ({}", name, join(columns))?;
+                if !primary_key.is_empty() {
+                    write!(f, ", PRIMARY KEY ({})", primary_key.join(", "))?;
+                }
+                for check in checks {
+                    write!(f, ", CHECK ({})", check)?;
+                }
+                write!(f, ")")?;
+                if *layout == Layout::Columnar {
+                    write!(f, " WITH (layout = 'columnar')")?;
+                }
+                Ok(())
+            }
+            Statement::DropTable { name } => write!(f, "DROP TABLE {}", name),
+            Statement::TruncateTable { name } => write!(f, "TRUNCATE TABLE {}", name),
+            Statement::AlterTable { name, action } => write!(f, "ALTER TABLE {} {}", name, action),
+            Statement::Reindex {
+                table_name,
+                column: None,
+            } => write!(f, "REINDEX TABLE {}", table_name),
+            Statement::Reindex {
+                table_name,
+                column: Some(column),
+            } => write!(f, "REINDEX INDEX {} ON {}", column, table_name),
+            Statement::CreateIndex {
+                name,
+                table_name,
+                column,
+                index_type,
+            } => {
+                write!(f, "CREATE INDEX ")?;
+                if let Some(name) = name {
+                    write!(f, "{} ", name)?;
+                }
+                write!(f, "ON {} ({})", table_name, column)?;
+                match index_type {
+                    IndexType::Ordered => Ok(()),
+                    IndexType::Hash => write!(f, " USING HASH"),
+                    IndexType::FullText => write!(f, " USING FULLTEXT"),
+                    IndexType::Bitmap => write!(f, " USING BITMAP"),
+                }
+            }
+            Statement::DropIndex { name, table_name } => {
+                write!(f, "DROP INDEX {} ON {}", name, table_name)
+            }
+            Statement::Insert {
+                table_name,
+                columns: None,
+                values,
+            } if values.len() == 1 && values[0].is_empty() => {
+                write!(f, "INSERT INTO {} DEFAULT VALUES", table_name)
+            }
+            Statement::Insert {
+                table_name,
+                columns,
+                values,
+            } => {
+                write!(f, "INSERT INTO {}", table_name)?;
+                if let Some(columns) = columns {
+                    write!(f, " ({})", columns.join(", "))?;
+                }
+                let values = values
+                    .iter()
+                    .map(|row| format!("({})", join(row)))
+                    .collect::<Vec<_>>();
+                write!(f, " VALUES {}", values.join(", "))
+            }
+            Statement::Select {
+                distinct,
+                select,
+                from,
+                where_clause,
+                group_by,
+                having,
+                order_by,
+                limit,
+                offset,
+            } => {
+                write!(f, "SELECT ")?;
+                if *distinct {
+                    write!(f, "DISTINCT ")?;
+                }
+                if select.is_empty() {
+                    write!(f, "*")?;
+                }
+                let select = select
+                    .iter()
+                    .map(|(expr, alias)| match alias {
+                        Some(alias) => format!("{} AS {}", expr, alias),
+                        None => expr.to_string(),
+                    })
+                    .collect::<Vec<_>>();
+                write!(f, "{} FROM {}", select.join(", "), from)?;
+                if let Some(expr) = where_clause {
+                    write!(f, " WHERE {}", expr)?;
+                }
+                if !group_by.is_empty() {
+                    let group_by = group_by.iter().map(|e| Operand(e).to_string()).collect::<Vec<_>>();
+                    write!(f, " GROUP BY {}", group_by.join(", "))?;
+                }
+                if let Some(expr) = having {
+                    write!(f, " HAVING {}", expr)?;
+                }
+                if !order_by.is_empty() {
+                    let order_by = order_by
+                        .iter()
+                        .map(|(expr, dir)| format!("{} {}", Operand(expr), dir))
+                        .collect::<Vec<_>>();
+                    write!(f, " ORDER BY {}", order_by.join(", "))?;
+                }
+                if let Some(expr) = limit {
+                    write!(f, " LIMIT {}", Operand(expr))?;
+                }
+                if let Some(expr) = offset {
+                    write!(f, " OFFSET {}", Operand(expr))?;
+                }
+                Ok(())
+            }
+            Statement::SetOperation {
+                op,
+                all,
+                left,
+                right,
+                order_by,
+                limit,
+                offset,
+            } => {
+                write!(f, "{} {}", left, op)?;
+                if *all {
+                    write!(f, " ALL")?;
+                }
+                write!(f, " {}", right)?;
+                if !order_by.is_empty() {
+                    let order_by = order_by
+                        .iter()
+                        .map(|(expr, dir)| format!("{} {}", Operand(expr), dir))
+                        .collect::<Vec<_>>();
+                    write!(f, " ORDER BY {}", order_by.join(", "))?;
+                }
+                if let Some(expr) = limit {
+                    write!(f, " LIMIT {}", Operand(expr))?;
+                }
+                if let Some(expr) = offset {
+                    write!(f, " OFFSET {}", Operand(expr))?;
+                }
+                Ok(())
+            }
+            Statement::Update {
+                table_name,
+                columns,
+                from,
+                where_clause,
+            } => {
+                let columns = columns
+                    .iter()
+                    .map(|(col, expr)| format!("{} = {}", col, expr))
+                    .collect::<Vec<_>>();
+                write!(f, "UPDATE {} SET {}", table_name, columns.join(", "))?;
+                if let Some(from) = from {
+                    write!(f, " FROM {}", from)?;
+                }
+                if let Some(expr) = where_clause {
+                    write!(f, " WHERE {}", expr)?;
+                }
+                Ok(())
+            }
+            Statement::Delete {
+                table_name,
+                using,
+                where_clause,
+            } => {
+                write!(f, "DELETE FROM {}", table_name)?;
+                if let Some(using) = using {
+                    write!(f, " USING {}", using)?;
+                }
+                if let Some(expr) = where_clause {
+                    write!(f, " WHERE {}", expr)?;
+                }
+                Ok(())
+            }
+            Statement::Begin {
+                isolation,
+                read_only,
+                as_of,
+            } => {
+                write!(f, "BEGIN")?;
+                if let Some(isolation) = isolation {
+                    write!(f, " ISOLATION LEVEL {}", isolation)?;
+                }
+                if *read_only {
+                    write!(f, " READ ONLY")?;
+                }
+                if let Some(version) = as_of {
+                    write!(f, " AS OF VERSION {}", version)?;
+                }
+                Ok(())
+            }
+            Statement::Commit => write!(f, "COMMIT"),
+            Statement::Rollback => write!(f, "ROLLBACK"),
+            Statement::Savepoint { name } => write!(f, "SAVEPOINT {}", name),
+            Statement::RollbackToSavepoint { name } => write!(f, "ROLLBACK TO SAVEPOINT {}", name),
+            Statement::ReleaseSavepoint { name } => write!(f, "RELEASE SAVEPOINT {}", name),
+            Statement::Explain {
+                stmt,
+                format: ExplainFormat::Text,
+                analyze: false,
+            } => write!(f, "EXPLAIN {}", stmt),
+            Statement::Explain {
+                stmt,
+                format: ExplainFormat::Text,
+                analyze: true,
+            } => write!(f, "EXPLAIN ANALYZE {}", stmt),
+            Statement::Explain {
+                stmt,
+                format: ExplainFormat::Dot,
+                ..
+            } => write!(f, "EXPLAIN (FORMAT DOT) {}", stmt),
+            Statement::Set { name, value } => {
+                write!(f, "SET {} = {}", name, Expression::from(value.clone()))
+            }
+            Statement::Vacuum => write!(f, "VACUUM"),
+            Statement::DeclareCursor { name, query } => {
+                write!(f, "DECLARE {} CURSOR FOR {}", name, query)
+            }
+            Statement::Fetch { name, count: None } => write!(f, "FETCH ALL FROM {}", name),
+            Statement::Fetch {
+                name,
+                count: Some(count),
+            } => write!(f, "FETCH {} FROM {}", count, name),
+            Statement::CloseCursor { name } => write!(f, "CLOSE {}", name),
+            Statement::ShowProfile => write!(f, "SHOW PROFILE"),
+            Statement::Copy {
+                table_name,
+                path,
+                direction,
+                options,
+            } => {
+                let direction = match direction {
+                    CopyDirection::From => "FROM",
+                    CopyDirection::To => "TO",
+                };
+                let path = Expression::from(Value::String(path.clone()));
+                let delimiter = Expression::from(Value::String((options.delimiter as char).into()));
+                write!(
+                    f,
+                    "COPY {} {} {} WITH (header = {}, delimiter = {})",
+                    table_name,
+                    direction,
+                    path,
+                    Expression::from(Value::Boolean(options.header)),
+                    delimiter
+                )
+            }
+            Statement::Backup { path } => {
+                write!(f, "BACKUP TO {}", Expression::from(Value::String(path.clone())))
+            }
+            Statement::Restore { path } => {
+                write!(f, "RESTORE FROM {}", Expression::from(Value::String(path.clone())))
+            }
+        }
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum OrderDirection {
     Asc,
     Desc,
 }
 
+impl Display for OrderDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrderDirection::Asc => write!(f, "ASC"),
+            OrderDirection::Desc => write!(f, "DESC"),
+        }
+    }
+}
+
+// 集合操作
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SetOperator {
+    Union,
+    Intersect,
+    Except,
+}
+
+impl Display for SetOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SetOperator::Union => write!(f, "UNION"),
+            SetOperator::Intersect => write!(f, "INTERSECT"),
+            SetOperator::Except => write!(f, "EXCEPT"),
+        }
+    }
+}
+
 // 列定义
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Column {
     pub name: String,
     pub datatype: DataType,
@@ -62,12 +552,63 @@ pub struct Column {
     pub default: Option<Expression>,
     pub primary_key: bool,
     pub index: bool,
+    pub index_type: IndexType,
+    pub unique: bool,
+    pub auto_increment: bool,
+    pub check: Option<Expression>,
 }
 
-#[derive(Debug, PartialEq)]
+impl Display for Column {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.name, self.datatype)?;
+        if self.primary_key {
+            write!(f, " PRIMARY KEY")?;
+        }
+        if self.auto_increment {
+            write!(f, " AUTO_INCREMENT")?;
+        }
+        match self.nullable {
+            Some(true) => write!(f, " NULL")?,
+            Some(false) => write!(f, " NOT NULL")?,
+            None => {}
+        }
+        if let Some(default) = &self.default {
+            write!(f, " DEFAULT {}", Operand(default))?;
+        }
+        if self.unique {
+            write!(f, " UNIQUE")?;
+        }
+        if self.index {
+            write!(f, " INDEX")?;
+            match self.index_type {
+                IndexType::Ordered => {}
+                IndexType::Hash => write!(f, " USING HASH")?,
+                IndexType::FullText => write!(f, " USING FULLTEXT")?,
+                IndexType::Bitmap => write!(f, " USING BITMAP")?,
+            }
+        }
+        if let Some(check) = &self.check {
+            write!(f, " CHECK ({})", check)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum FromItem {
+    // sample 不为空时只随机返回一部分行，例如 select * from t tablesample (1 percent);
+    // alias 不为空时在 join 中用别名引用表的列，例如 select x.a from t1 join t2 as x on t1.a = x.b;
     Table {
         name: String,
+        alias: Option<String>,
+        sample: Option<TableSample>,
+    },
+
+    // from 子句中的子查询，必须指定别名
+    // select * from (select a, b from t where a > 1) x;
+    Derived {
+        query: Box<Statement>,
+        alias: String,
     },
 
     Join {
@@ -76,9 +617,79 @@ pub enum FromItem {
         join_type: JoinType,
         predicate: Option<Expression>,
     },
+
+    // 把数组展开成一列，每个元素一行，没有指定别名时列名是 unnest
+    // 在 join 的右边时可以引用左边的列，例如 select * from t cross join unnest(t.tags) as tag;
+    Unnest {
+        expr: Expression,
+        alias: String,
+    },
 }
 
-#[derive(Debug, PartialEq)]
+impl Display for FromItem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FromItem::Table {
+                name,
+                alias,
+                sample,
+            } => {
+                write!(f, "{}", name)?;
+                if let Some(alias) = alias {
+                    write!(f, " AS {}", alias)?;
+                }
+                if let Some(sample) = sample {
+                    write!(f, " {}", sample)?;
+                }
+                Ok(())
+            }
+            FromItem::Derived { query, alias } => write!(f, "({}) AS {}", query, alias),
+            FromItem::Unnest { expr, alias } => write!(f, "UNNEST({}) AS {}", expr, alias),
+            FromItem::Join {
+                left,
+                right,
+                join_type,
+                predicate,
+            } => {
+                write!(f, "{} {} {}", left, join_type, right)?;
+                let Some(Expression::Operation(op)) = predicate else {
+                    return Ok(());
+                };
+                let (l, r, cmp) = match op {
+                    Operation::Equal(l, r) => (l, r, "="),
+                    Operation::IsDistinctFrom(l, r) => (l, r, "IS DISTINCT FROM"),
+                    Operation::IsNotDistinctFrom(l, r) => (l, r, "IS NOT DISTINCT FROM"),
+                    _ => return write!(f, " ON {}", Operand(predicate.as_ref().unwrap())),
+                };
+                // 解析 right join 时交换了条件的左右两边，这里交换回来
+                let (l, r) = match join_type {
+                    JoinType::Right => (r, l),
+                    _ => (l, r),
+                };
+                write!(f, " ON {} {} {}", Operand(l), cmp, Operand(r))
+            }
+        }
+    }
+}
+
+// 表采样，每一行以 percent% 的概率返回，指定 seed 时每次返回相同的行
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TableSample {
+    pub percent: f64,
+    pub seed: Option<u64>,
+}
+
+impl Display for TableSample {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "TABLESAMPLE ({} PERCENT)", self.percent)?;
+        if let Some(seed) = self.seed {
+            write!(f, " REPEATABLE ({})", seed)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum JoinType {
     Cross,
     Inner,
@@ -86,13 +697,159 @@ pub enum JoinType {
     Right,
 }
 
+impl Display for JoinType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JoinType::Cross => write!(f, "CROSS JOIN"),
+            JoinType::Inner => write!(f, "JOIN"),
+            JoinType::Left => write!(f, "LEFT JOIN"),
+            JoinType::Right => write!(f, "RIGHT JOIN"),
+        }
+    }
+}
+
 // 表达式定义，目前只有常量和列名
 #[derive(Debug, PartialEq, Clone)]
 pub enum Expression {
-    Field(String),
+    // 列名以及可选的表名或者别名前缀，例如 a、t1.a
+    Field(Option<String>, String),
     Consts(Consts),
     Operation(Operation),
     Function(String, String),
+    // 标量函数调用，例如 coalesce(a, 0)
+    Call(String, Vec<Expression>),
+    // insert 语句中的 DEFAULT，表示使用列的默认值
+    Default,
+    // 数组，例如 ARRAY[1, 2, 3]、[1, 2, 3]
+    Array(Vec<Expression>),
+    // 数组下标，从 1 开始，例如 tags[1]
+    Index(Box<Expression>, Box<Expression>),
+    // 行值，例如 (a, b) > (1, 2)，按照字典序逐个元素比较
+    Row(Vec<Expression>),
+    // 标量子查询，最多返回一行一列，没有数据时为 NULL
+    Subquery(Box<Statement>),
+    // EXISTS (SELECT ...)，子查询有数据时为 true
+    Exists(Box<Statement>),
+    // 显式类型转换，CAST(expr AS type)
+    Cast(Box<Expression>, DataType),
+}
+
+impl Expression {
+    // 依次访问表达式本身以及所有的子表达式
+    pub fn walk(&self, visit: &mut impl FnMut(&Expression)) {
+        visit(self);
+        match self {
+            Expression::Operation(
+                Operation::Equal(l, r)
+                | Operation::GreaterThan(l, r)
+                | Operation::LessThan(l, r)
+                | Operation::IsDistinctFrom(l, r)
+                | Operation::IsNotDistinctFrom(l, r)
+                | Operation::Add(l, r)
+                | Operation::Subtract(l, r)
+                | Operation::Multiply(l, r)
+                | Operation::Divide(l, r)
+                | Operation::Like(l, r)
+                | Operation::And(l, r)
+                | Operation::Or(l, r),
+            ) => {
+                l.walk(visit);
+                r.walk(visit);
+            }
+            Expression::Operation(Operation::Not(expr)) => expr.walk(visit),
+            Expression::Operation(Operation::Between(expr, low, high)) => {
+                expr.walk(visit);
+                low.walk(visit);
+                high.walk(visit);
+            }
+            Expression::Operation(Operation::In(expr, list)) => {
+                expr.walk(visit);
+                list.iter().for_each(|item| item.walk(visit));
+            }
+            Expression::Operation(Operation::InSubquery(expr, _)) => expr.walk(visit),
+            Expression::Call(_, args) | Expression::Array(args) | Expression::Row(args) => {
+                args.iter().for_each(|arg| arg.walk(visit))
+            }
+            Expression::Index(array, index) => {
+                array.walk(visit);
+                index.walk(visit);
+            }
+            Expression::Cast(expr, _) => expr.walk(visit),
+            // 子查询中的表达式属于子查询，不访问
+            Expression::Field(..)
+            | Expression::Consts(_)
+            | Expression::Function(..)
+            | Expression::Default
+            | Expression::Subquery(_)
+            | Expression::Exists(_) => {}
+        }
+    }
+
+    // 和 walk 相同，可以修改访问到的表达式，先访问表达式本身再访问修改之后的子表达式
+    pub fn walk_mut(
+        &mut self,
+        visit: &mut impl FnMut(&mut Expression) -> Result<()>,
+    ) -> Result<()> {
+        visit(self)?;
+        match self {
+            Expression::Operation(
+                Operation::Equal(l, r)
+                | Operation::GreaterThan(l, r)
+                | Operation::LessThan(l, r)
+                | Operation::IsDistinctFrom(l, r)
+                | Operation::IsNotDistinctFrom(l, r)
+                | Operation::Add(l, r)
+                | Operation::Subtract(l, r)
+                | Operation::Multiply(l, r)
+                | Operation::Divide(l, r)
+                | Operation::Like(l, r)
+                | Operation::And(l, r)
+                | Operation::Or(l, r),
+            ) => {
+                l.walk_mut(visit)?;
+                r.walk_mut(visit)
+            }
+            Expression::Operation(Operation::Not(expr))
+            | Expression::Operation(Operation::InSubquery(expr, _)) => expr.walk_mut(visit),
+            Expression::Operation(Operation::Between(expr, low, high)) => {
+                expr.walk_mut(visit)?;
+                low.walk_mut(visit)?;
+                high.walk_mut(visit)
+            }
+            Expression::Operation(Operation::In(expr, list)) => {
+                expr.walk_mut(visit)?;
+                list.iter_mut().try_for_each(|item| item.walk_mut(visit))
+            }
+            Expression::Call(_, args) | Expression::Array(args) | Expression::Row(args) => {
+                args.iter_mut().try_for_each(|arg| arg.walk_mut(visit))
+            }
+            Expression::Index(array, index) => {
+                array.walk_mut(visit)?;
+                index.walk_mut(visit)
+            }
+            Expression::Cast(expr, _) => expr.walk_mut(visit),
+            Expression::Field(..)
+            | Expression::Consts(_)
+            | Expression::Function(..)
+            | Expression::Default
+            | Expression::Subquery(_)
+            | Expression::Exists(_) => Ok(()),
+        }
+    }
+
+    // 是否包含子查询
+    pub fn has_subquery(&self) -> bool {
+        let mut found = false;
+        self.walk(&mut |e| {
+            found |= matches!(
+                e,
+                Expression::Subquery(_)
+                    | Expression::Exists(_)
+                    | Expression::Operation(Operation::InSubquery(..))
+            )
+        });
+        found
+    }
 }
 
 impl From<Consts> for Expression {
@@ -108,6 +865,36 @@ pub enum Consts {
     Integer(i64),
     Float(f64),
     String(String),
+    // interval '1 day'
+    Interval(Interval),
+    // date '2024-01-01'
+    Date(i64),
+    // timestamp '2024-01-01 10:00:00'
+    Timestamp(i64),
+    // decimal '12.50'
+    Decimal(Decimal),
+    // x'DEADBEEF'
+    Blob(Vec<u8>),
+}
+
+impl From<Value> for Expression {
+    fn from(value: Value) -> Self {
+        Expression::Consts(match value {
+            Value::Null => Consts::Null,
+            Value::Boolean(b) => Consts::Boolean(b),
+            Value::Integer(i) => Consts::Integer(i),
+            Value::Float(f) => Consts::Float(f),
+            Value::String(s) => Consts::String(s),
+            Value::Interval(i) => Consts::Interval(i),
+            Value::Date(d) => Consts::Date(d),
+            Value::Timestamp(ts) => Consts::Timestamp(ts),
+            Value::Decimal(d) => Consts::Decimal(*d),
+            Value::Blob(b) => Consts::Blob(b.into_vec()),
+            Value::Array(items) => {
+                return Expression::Array(items.into_vec().into_iter().map(Expression::from).collect())
+            }
+        })
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -115,25 +902,214 @@ pub enum Operation {
     Equal(Box<Expression>, Box<Expression>),
     GreaterThan(Box<Expression>, Box<Expression>),
     LessThan(Box<Expression>, Box<Expression>),
+    // 把 NULL 当作普通的值比较，NULL IS NOT DISTINCT FROM NULL 为 true
+    IsDistinctFrom(Box<Expression>, Box<Expression>),
+    IsNotDistinctFrom(Box<Expression>, Box<Expression>),
+    // 四则运算，两边都是常量时在解析时直接计算
+    Add(Box<Expression>, Box<Expression>),
+    Subtract(Box<Expression>, Box<Expression>),
+    Multiply(Box<Expression>, Box<Expression>),
+    Divide(Box<Expression>, Box<Expression>),
+    // 模式匹配，% 匹配任意多个字符，_ 匹配一个字符
+    Like(Box<Expression>, Box<Expression>),
+    // 逻辑运算，NULL 表示未知，按照三值逻辑计算
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    // a BETWEEN low AND high，等价于 a >= low AND a <= high
+    Between(Box<Expression>, Box<Expression>, Box<Expression>),
+    // a IN (1, 2, 3)
+    In(Box<Expression>, Vec<Expression>),
+    // a IN (SELECT ...)，执行时先执行子查询，转换成 In
+    InSubquery(Box<Expression>, Box<Statement>),
 }
 
+// 输出规范化的 SQL 文本，重新解析之后得到相同的表达式
 impl Display for Expression {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Expression::Field(v) => write!(f, "{}", v),
-            Expression::Consts(c) => write!(f, "{}",
-                Value::from_expression(Expression::Consts(c.clone()))
-            ),
+            Expression::Field(Some(table), name) => write!(f, "{}.{}", table, name),
+            Expression::Field(None, name) => write!(f, "{}", name),
+            Expression::Consts(c) => write!(f, "{}", c),
             Expression::Operation(operation) => match operation {
                 Operation::Equal(l, r) => write!(f, "{} = {}", l, r),
                 Operation::GreaterThan(l, r) => write!(f, "{} > {}", l, r),
                 Operation::LessThan(l, r) => write!(f, "{} < {}", l, r),
+                Operation::IsDistinctFrom(l, r) => write!(f, "{} IS DISTINCT FROM {}", l, r),
+                Operation::IsNotDistinctFrom(l, r) => {
+                    write!(f, "{} IS NOT DISTINCT FROM {}", l, r)
+                }
+                // 四则运算是左结合的，左边优先级更低、右边优先级不高于当前运算时需要加括号
+                Operation::Add(l, r)
+                | Operation::Subtract(l, r)
+                | Operation::Multiply(l, r)
+                | Operation::Divide(l, r) => {
+                    let (op, prec) = match operation {
+                        Operation::Add(..) => ("+", 1),
+                        Operation::Subtract(..) => ("-", 1),
+                        Operation::Multiply(..) => ("*", 2),
+                        _ => ("/", 2),
+                    };
+                    write!(f, "{} {} {}", Arith(l, prec), op, Arith(r, prec + 1))
+                }
+                Operation::Like(l, r) => write!(f, "{} LIKE {}", l, r),
+                // AND 的优先级高于 OR，都是左结合的，需要时给操作数加上括号
+                Operation::And(l, r) => {
+                    write!(f, "{} AND {}", Logical(l, false), Logical(r, true))
+                }
+                Operation::Or(l, r) => write!(f, "{} OR {}", l, Logical(r, false)),
+                Operation::Not(expr) => match expr.as_ref() {
+                    Expression::Operation(Operation::And(..) | Operation::Or(..)) => {
+                        write!(f, "NOT ({})", expr)
+                    }
+                    _ => write!(f, "NOT {}", expr),
+                },
+                Operation::Between(expr, low, high) => {
+                    write!(f, "{} BETWEEN {} AND {}", expr, Operand(low), Operand(high))
+                }
+                Operation::In(expr, list) => write!(f, "{} IN ({})", expr, join(list)),
+                Operation::InSubquery(expr, query) => write!(f, "{} IN ({})", expr, query),
             },
             Expression::Function(name, field) => write!(f, "{}({})", name, field),
+            Expression::Call(name, args) if name == "extract" => match &args[0] {
+                Expression::Consts(Consts::String(field)) => {
+                    write!(f, "extract({} FROM {})", field, args[1])
+                }
+                field => write!(f, "extract({} FROM {})", field, args[1]),
+            },
+            Expression::Call(name, args) => write!(f, "{}({})", name, join(args)),
+            Expression::Default => write!(f, "DEFAULT"),
+            Expression::Array(items) => write!(f, "ARRAY[{}]", join(items)),
+            Expression::Row(items) => write!(f, "({})", join(items)),
+            Expression::Index(array, index) => write!(f, "{}[{}]", Operand(array), index),
+            Expression::Subquery(query) => write!(f, "({})", query),
+            Expression::Exists(query) => write!(f, "EXISTS ({})", query),
+            Expression::Cast(expr, datatype) => write!(f, "CAST({} AS {})", expr, datatype),
         }
     }
 }
 
+impl Display for Consts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Consts::Null => write!(f, "NULL"),
+            Consts::Boolean(true) => write!(f, "TRUE"),
+            Consts::Boolean(false) => write!(f, "FALSE"),
+            // 不支持负数字面量，负数写成减法，解析时会直接计算出常量
+            Consts::Integer(i) if *i < 0 => write!(f, "(0 - {})", i.unsigned_abs()),
+            Consts::Integer(i) => write!(f, "{}", i),
+            Consts::Float(v) if v.is_sign_negative() && *v != 0.0 => {
+                write!(f, "(0.0 - {})", Consts::Float(-v))
+            }
+            // 浮点数始终带上小数点，避免重新解析成整数
+            Consts::Float(v) => match v.to_string() {
+                s if s.contains('.') || !v.is_finite() => write!(f, "{}", s),
+                s => write!(f, "{}.0", s),
+            },
+            Consts::String(s) => write!(f, "'{}'", s.replace('\'', "''")),
+            Consts::Interval(i) => write!(f, "INTERVAL '{}'", i),
+            Consts::Date(d) => write!(f, "DATE '{}'", Value::Date(*d)),
+            Consts::Timestamp(ts) => write!(f, "TIMESTAMP '{}'", Value::Timestamp(*ts)),
+            Consts::Decimal(d) => write!(f, "DECIMAL '{}'", d),
+            Consts::Blob(b) => write!(f, "X'{}'", blob::to_hex(b)),
+        }
+    }
+}
+
+// 只能是单个表达式的位置，例如列的默认值、GROUP BY、LIMIT，运算表达式需要加上括号
+struct Operand<'a>(&'a Expression);
+
+impl Display for Operand<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Expression::Operation(_) => write!(f, "({})", self.0),
+            expr => write!(f, "{}", expr),
+        }
+    }
+}
+
+// 四则运算的操作数，优先级低于第二个字段时加上括号，非四则运算的运算表达式始终加上括号
+struct Arith<'a>(&'a Expression, u8);
+
+impl Display for Arith<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let prec = match self.0 {
+            Expression::Operation(Operation::Add(..) | Operation::Subtract(..)) => 1,
+            Expression::Operation(Operation::Multiply(..) | Operation::Divide(..)) => 2,
+            Expression::Operation(_) => 0,
+            _ => 3,
+        };
+        match prec < self.1 {
+            true => write!(f, "({})", self.0),
+            false => write!(f, "{}", self.0),
+        }
+    }
+}
+
+// 逻辑运算的操作数，是 OR 运算时加上括号，第二个字段为 true 时 AND 运算也加上括号
+struct Logical<'a>(&'a Expression, bool);
+
+impl Display for Logical<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Expression::Operation(Operation::Or(..)) => write!(f, "({})", self.0),
+            Expression::Operation(Operation::And(..)) if self.1 => write!(f, "({})", self.0),
+            expr => write!(f, "{}", expr),
+        }
+    }
+}
+
+// 逗号分隔的列表
+fn join<T: Display>(items: &[T]) -> String {
+    items
+        .iter()
+        .map(|i| i.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// 在结果集的列中查找名称可能带有表名前缀的列的位置，例如 t1.a、count(a)
+pub fn column_position(columns: &[String], name: &str) -> Result<usize> {
+    match name.rsplit_once('.') {
+        Some((table, col)) => field_position(columns, Some(table), col),
+        None => field_position(columns, None, name),
+    }
+}
+
+// 在结果集的列中查找列 table.name 的位置
+// join 的结果集中列名带有表名前缀，例如 t1.a，此时可以用 t1.a 或者 a 引用该列，
+// 不带表名的引用如果匹配到多个列则报错；单表的结果集中列名没有前缀，t1.a 按照 a 查找，
+// 前缀是否是 from 中的表名或者别名在生成执行计划时已经检查过
+pub fn field_position(columns: &[String], table: Option<&str>, name: &str) -> Result<usize> {
+    let full_name = match table {
+        Some(table) => format!("{}.{}", table, name),
+        None => name.to_string(),
+    };
+    if let Some(pos) = columns.iter().position(|c| *c == full_name) {
+        return Ok(pos);
+    }
+
+    let matched = match table {
+        Some(_) => columns
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| !c.contains('.') && *c == name)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>(),
+        None => columns
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.rsplit_once('.').is_some_and(|(_, col)| col == name))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>(),
+    };
+    match matched[..] {
+        [pos] => Ok(pos),
+        [] => Err(Error::NotFound(format!("column {} is not in table", full_name))),
+        _ => Err(Error::InvalidInput(format!("column reference {} is ambiguous", full_name))),
+    }
+}
+
 pub fn evaluate_expr(
     expr: &Expression,
     lcols: &Vec<String>,
@@ -143,13 +1119,8 @@ pub fn evaluate_expr(
 ) -> Result<Value> {
     match expr {
         //
-        Expression::Field(col_name) => {
-            let pos = match lcols.iter().position(|c| *c == *col_name) {
-                Some(pos) => pos,
-                None => {
-                    return Err(Error::Internal(format!("column {} is not in table", col_name)))
-                }
-            };
+        Expression::Field(table, col_name) => {
+            let pos = field_position(lcols, table.as_deref(), col_name)?;
             Ok(lrows[pos].clone())
         }
 
@@ -160,74 +1131,431 @@ pub fn evaluate_expr(
             Consts::Integer(i) => Value::Integer(*i),
             Consts::Float(f) => Value::Float(*f),
             Consts::String(s) => Value::String(s.clone()),
+            Consts::Interval(i) => Value::Interval(*i),
+            Consts::Date(d) => Value::Date(*d),
+            Consts::Timestamp(ts) => Value::Timestamp(*ts),
+            Consts::Decimal(d) => (*d).into(),
+            Consts::Blob(b) => Value::Blob(b.as_slice().into()),
         }),
 
+        // 行值按照数组计算，比较时逐个元素比较
+        Expression::Array(items) | Expression::Row(items) => Ok(Value::Array(
+            items
+                .iter()
+                .map(|item| evaluate_expr(item, lcols, lrows, rcols, rrows))
+                .collect::<Result<_>>()?,
+        )),
+
+        Expression::Cast(expr, datatype) => {
+            cast_value(evaluate_expr(expr, lcols, lrows, rcols, rrows)?, datatype)
+        }
+
+        // 下标从 1 开始，越界或者有一边是 NULL 时结果为 NULL
+        Expression::Index(array, index) => {
+            let array = evaluate_expr(array, lcols, lrows, rcols, rrows)?;
+            let index = evaluate_expr(index, lcols, lrows, rcols, rrows)?;
+            match (array, index) {
+                (Value::Null, _) | (_, Value::Null) => Ok(Value::Null),
+                (Value::Array(items), Value::Integer(i)) => Ok(usize::try_from(i - 1)
+                    .ok()
+                    .and_then(|i| items.into_vec().into_iter().nth(i))
+                    .unwrap_or(Value::Null)),
+                (array, index) => Err(Error::TypeMismatch(format!(
+                    "cannot index {} with {}",
+                    array, index
+                ))),
+            }
+        }
+
         //
         Expression::Operation(operation) => match operation {
             //
             Operation::Equal(lexpr, rexpr) => {
-                let lv = evaluate_expr(&lexpr, lcols, lrows, rcols, rrows)?;
-                let rv = evaluate_expr(&rexpr, rcols, rrows, lcols, lrows)?;
+                let lv = evaluate_expr(lexpr, lcols, lrows, rcols, rrows)?;
+                let rv = evaluate_expr(rexpr, rcols, rrows, lcols, lrows)?;
+                equal_values(lv, rv)
+            }
+
+            // 两边都是 NULL 时相等，只有一边是 NULL 时不相等，结果不会是 NULL
+            Operation::IsDistinctFrom(lexpr, rexpr) | Operation::IsNotDistinctFrom(lexpr, rexpr) => {
+                let lv = evaluate_expr(lexpr, lcols, lrows, rcols, rrows)?;
+                let rv = evaluate_expr(rexpr, rcols, rrows, lcols, lrows)?;
+                let equal = not_distinct(lv, rv)?;
+                Ok(Value::Boolean(
+                    equal == matches!(operation, Operation::IsNotDistinctFrom(..)),
+                ))
+            }
+
+            Operation::Add(lexpr, rexpr) | Operation::Subtract(lexpr, rexpr) => {
+                let lv = evaluate_expr(lexpr, lcols, lrows, rcols, rrows)?;
+                let rv = evaluate_expr(rexpr, lcols, lrows, rcols, rrows)?;
+                add_values(lv, rv, matches!(operation, Operation::Subtract(..)))
+            }
+
+            Operation::Multiply(lexpr, rexpr) | Operation::Divide(lexpr, rexpr) => {
+                let lv = evaluate_expr(lexpr, lcols, lrows, rcols, rrows)?;
+                let rv = evaluate_expr(rexpr, lcols, lrows, rcols, rrows)?;
+                mul_values(lv, rv, matches!(operation, Operation::Divide(..)))
+            }
+
+            Operation::Like(lexpr, rexpr) => {
+                let lv = evaluate_expr(lexpr, lcols, lrows, rcols, rrows)?;
+                let rv = evaluate_expr(rexpr, lcols, lrows, rcols, rrows)?;
+                like_values(lv, rv)
+            }
+
+            // false AND NULL 为 false，true OR NULL 为 true，其他有 NULL 的情况为 NULL
+            Operation::And(lexpr, rexpr) | Operation::Or(lexpr, rexpr) => {
+                let and = matches!(operation, Operation::And(..));
+                let lv = logical_value(evaluate_expr(lexpr, lcols, lrows, rcols, rrows)?)?;
+                // 左边已经决定结果时不再计算右边
+                if lv == Some(!and) {
+                    return Ok(Value::Boolean(!and));
+                }
+                let rv = logical_value(evaluate_expr(rexpr, lcols, lrows, rcols, rrows)?)?;
                 Ok(match (lv, rv) {
-                    (Value::Boolean(l), Value::Boolean(r)) => Value::Boolean(l == r),
-                    (Value::Integer(l), Value::Integer(r)) => Value::Boolean(l == r),
-                    (Value::Integer(l), Value::Float(r)) => Value::Boolean(l as f64 == r),
-                    (Value::Float(l), Value::Integer(r)) => Value::Boolean(l == r as f64),
-                    (Value::Float(l), Value::Float(r)) => Value::Boolean(l == r),
-                    (Value::String(l), Value::String(r)) => Value::Boolean(l == r),
-                    (Value::Null, _) => Value::Null,
-                    (_, Value::Null) => Value::Null,
-                    (l, r) => {
-                        return Err(Error::Internal(format!("can not compare exression {} and {}", l, r)))
-                    }
+                    (_, Some(r)) if r != and => Value::Boolean(r),
+                    (Some(_), Some(_)) => Value::Boolean(and),
+                    _ => Value::Null,
                 })
             }
 
-            //
-            Operation::GreaterThan(lexpr, rexpr) => {
-                let lv = evaluate_expr(&lexpr, lcols, lrows, rcols, rrows)?;
-                let rv = evaluate_expr(&rexpr, rcols, rrows, lcols, lrows)?;
-                Ok(match (lv, rv) {
-                    (Value::Boolean(l), Value::Boolean(r)) => Value::Boolean(l > r),
-                    (Value::Integer(l), Value::Integer(r)) => Value::Boolean(l > r),
-                    (Value::Integer(l), Value::Float(r)) => Value::Boolean(l as f64 > r),
-                    (Value::Float(l), Value::Integer(r)) => Value::Boolean(l > r as f64),
-                    (Value::Float(l), Value::Float(r)) => Value::Boolean(l > r),
-                    (Value::String(l), Value::String(r)) => Value::Boolean(l > r),
-                    (Value::Null, _) => Value::Null,
-                    (_, Value::Null) => Value::Null,
-                    (l, r) => {
-                        return Err(Error::Internal(format!(
-                            "can not compare exression {} and {}",
-                            l, r
-                        )))
-                    }
+            Operation::Not(expr) => {
+                let v = logical_value(evaluate_expr(expr, lcols, lrows, rcols, rrows)?)?;
+                Ok(v.map_or(Value::Null, |v| Value::Boolean(!v)))
+            }
+
+            // low > a 和 a > high 都为 false 时结果为 true
+            Operation::Between(expr, low, high) => {
+                let v = evaluate_expr(expr, lcols, lrows, rcols, rrows)?;
+                let low = evaluate_expr(low, lcols, lrows, rcols, rrows)?;
+                let high = evaluate_expr(high, lcols, lrows, rcols, rrows)?;
+                let below = logical_value(compare_values(low, v.clone(), true)?)?;
+                let above = logical_value(compare_values(v, high, true)?)?;
+                Ok(match (below, above) {
+                    (Some(true), _) | (_, Some(true)) => Value::Boolean(false),
+                    (Some(false), Some(false)) => Value::Boolean(true),
+                    _ => Value::Null,
                 })
             }
 
-            //
-            Operation::LessThan(lexpr, rexpr) => {
-                let lv = evaluate_expr(&lexpr, lcols, lrows, rcols, rrows)?;
-                let rv = evaluate_expr(&rexpr, rcols, rrows, lcols, lrows)?;
-                Ok(match (lv, rv) {
-                    (Value::Boolean(l), Value::Boolean(r)) => Value::Boolean(l < r),
-                    (Value::Integer(l), Value::Integer(r)) => Value::Boolean(l < r),
-                    (Value::Integer(l), Value::Float(r)) => Value::Boolean((l as f64) < r),
-                    (Value::Float(l), Value::Integer(r)) => Value::Boolean(l < r as f64),
-                    (Value::Float(l), Value::Float(r)) => Value::Boolean(l < r),
-                    (Value::String(l), Value::String(r)) => Value::Boolean(l < r),
-                    (Value::Null, _) => Value::Null,
-                    (_, Value::Null) => Value::Null,
-                    (l, r) => {
-                        return Err(Error::Internal(format!(
-                            "can not compare exression {} and {}",
-                            l, r
-                        )))
+            // 有相等的元素时为 true，否则有 NULL 参与比较时为 NULL
+            Operation::In(expr, list) => {
+                let v = evaluate_expr(expr, lcols, lrows, rcols, rrows)?;
+                let mut result = Value::Boolean(false);
+                for item in list {
+                    let item = evaluate_expr(item, lcols, lrows, rcols, rrows)?;
+                    match equal_values(v.clone(), item)? {
+                        Value::Boolean(true) => return Ok(Value::Boolean(true)),
+                        Value::Null => result = Value::Null,
+                        _ => {}
                     }
-                })
+                }
+                Ok(result)
             }
+
+            Operation::GreaterThan(lexpr, rexpr) | Operation::LessThan(lexpr, rexpr) => {
+                let lv = evaluate_expr(lexpr, lcols, lrows, rcols, rrows)?;
+                let rv = evaluate_expr(rexpr, rcols, rrows, lcols, lrows)?;
+                compare_values(lv, rv, matches!(operation, Operation::GreaterThan(..)))
+            }
+
+            // 子查询在执行之前已经被替换成常量
+            Operation::InSubquery(..) => Err(Error::Internal("unresolved subquery".into())),
         },
 
+        Expression::Call(name, args) => {
+            let args = args
+                .iter()
+                .map(|arg| evaluate_expr(arg, lcols, lrows, rcols, rrows))
+                .collect::<Result<Vec<_>>>()?;
+            functions::call(name, args)
+        }
+
         _ => Err(Error::Internal("unexpected expression".into())),
     }
 }
+
+// 比较两个值是否相等，有一边是 NULL 时结果为 NULL
+pub fn equal_values(lv: Value, rv: Value) -> Result<Value> {
+    Ok(match coerce_comparison(lv, rv)? {
+        (Value::Boolean(l), Value::Boolean(r)) => Value::Boolean(l == r),
+        (Value::Integer(l), Value::Integer(r)) => Value::Boolean(l == r),
+        (Value::Integer(l), Value::Float(r)) => Value::Boolean(l as f64 == r),
+        (Value::Float(l), Value::Integer(r)) => Value::Boolean(l == r as f64),
+        (Value::Float(l), Value::Float(r)) => Value::Boolean(l == r),
+        (Value::String(l), Value::String(r)) => Value::Boolean(l == r),
+        (Value::Blob(l), Value::Blob(r)) => Value::Boolean(l == r),
+        (Value::Interval(l), Value::Interval(r)) => {
+            Value::Boolean(l.partial_cmp(&r) == Some(Ordering::Equal))
+        }
+        (l, r) if is_decimal_pair(&l, &r) => {
+            Value::Boolean(l.partial_cmp(&r) == Some(Ordering::Equal))
+        }
+        (l, r) if is_temporal_pair(&l, &r) => Value::Boolean(temporal_micros(&l)? == temporal_micros(&r)?),
+        // 数组逐个元素比较，有元素不相等时为 false，否则有 NULL 元素时为 NULL
+        (Value::Array(l), Value::Array(r)) => {
+            if l.len() != r.len() {
+                return Ok(Value::Boolean(false));
+            }
+            let mut result = Value::Boolean(true);
+            for (l, r) in l.into_vec().into_iter().zip(r.into_vec()) {
+                match equal_values(l, r)? {
+                    Value::Boolean(false) => return Ok(Value::Boolean(false)),
+                    Value::Null => result = Value::Null,
+                    _ => {}
+                }
+            }
+            result
+        }
+        (Value::Null, _) => Value::Null,
+        (_, Value::Null) => Value::Null,
+        (l, r) => {
+            return Err(Error::TypeMismatch(format!(
+                "can not compare exression {} and {}",
+                l, r
+            )))
+        }
+    })
+}
+
+// 逻辑运算的操作数，NULL 返回 None
+fn logical_value(value: Value) -> Result<Option<bool>> {
+    match value {
+        Value::Boolean(b) => Ok(Some(b)),
+        Value::Null => Ok(None),
+        v => Err(Error::TypeMismatch(format!(
+            "argument of logical operator must be boolean, got {}",
+            v
+        ))),
+    }
+}
+
+// 两个值是否不可区分，NULL 和 NULL 相同，数组逐个元素比较
+fn not_distinct(lv: Value, rv: Value) -> Result<bool> {
+    Ok(match (lv, rv) {
+        (Value::Null, Value::Null) => true,
+        (Value::Null, _) | (_, Value::Null) => false,
+        (Value::Array(l), Value::Array(r)) => {
+            if l.len() != r.len() {
+                return Ok(false);
+            }
+            for (l, r) in l.into_vec().into_iter().zip(r.into_vec()) {
+                if !not_distinct(l, r)? {
+                    return Ok(false);
+                }
+            }
+            true
+        }
+        (lv, rv) => equal_values(lv, rv)? == Value::Boolean(true),
+    })
+}
+
+// 比较大小，greater 为 true 时计算 lv > rv，否则计算 lv < rv，有一边是 NULL 时结果为 NULL
+// 数组和行值按照字典序比较，从第一个不相等的元素决定结果，比较到 NULL 元素时结果为 NULL，前缀相同时短的更小
+pub fn compare_values(lv: Value, rv: Value, greater: bool) -> Result<Value> {
+    let (lv, rv) = if greater { (lv, rv) } else { (rv, lv) };
+    Ok(match coerce_comparison(lv, rv)? {
+        (Value::Boolean(l), Value::Boolean(r)) => Value::Boolean(l & !r),
+        (Value::Integer(l), Value::Integer(r)) => Value::Boolean(l > r),
+        (Value::Integer(l), Value::Float(r)) => Value::Boolean(l as f64 > r),
+        (Value::Float(l), Value::Integer(r)) => Value::Boolean(l > r as f64),
+        (Value::Float(l), Value::Float(r)) => Value::Boolean(l > r),
+        (Value::String(l), Value::String(r)) => Value::Boolean(l > r),
+        (Value::Blob(l), Value::Blob(r)) => Value::Boolean(l > r),
+        (Value::Interval(l), Value::Interval(r)) => Value::Boolean(l > r),
+        (l, r) if is_decimal_pair(&l, &r) => {
+            Value::Boolean(l.partial_cmp(&r) == Some(Ordering::Greater))
+        }
+        (l, r) if is_temporal_pair(&l, &r) => Value::Boolean(temporal_micros(&l)? > temporal_micros(&r)?),
+        (Value::Array(l), Value::Array(r)) => {
+            let (llen, rlen) = (l.len(), r.len());
+            for (l, r) in l.into_vec().into_iter().zip(r.into_vec()) {
+                match equal_values(l.clone(), r.clone())? {
+                    Value::Boolean(true) => continue,
+                    Value::Null => return Ok(Value::Null),
+                    _ => return compare_values(l, r, true),
+                }
+            }
+            Value::Boolean(llen > rlen)
+        }
+        (Value::Null, _) => Value::Null,
+        (_, Value::Null) => Value::Null,
+        (l, r) => {
+            return Err(Error::TypeMismatch(format!(
+                "can not compare exression {} and {}",
+                l, r
+            )))
+        }
+    })
+}
+
+// 定点小数可以和定点小数、整数、浮点数比较和运算
+fn is_decimal_pair(lv: &Value, rv: &Value) -> bool {
+    let numeric = |v: &Value| matches!(v, Value::Integer(_) | Value::Float(_) | Value::Decimal(_));
+    (matches!(lv, Value::Decimal(_)) || matches!(rv, Value::Decimal(_))) && numeric(lv) && numeric(rv)
+}
+
+// 整数、浮点数先转换为定点小数再运算，结果是精确的定点小数
+fn to_decimal(v: Value) -> Result<Decimal> {
+    match v {
+        Value::Decimal(d) => Ok(*d),
+        Value::Integer(i) => Ok(Decimal::from_i64(i)),
+        Value::Float(f) => Decimal::from_f64(f),
+        v => Err(Error::TypeMismatch(format!("{} is not a numeric value", v))),
+    }
+}
+
+// 日期、时间戳之间可以比较，也可以和字符串比较，字符串按照时间戳解析
+fn is_temporal_pair(lv: &Value, rv: &Value) -> bool {
+    let temporal = |v: &Value| matches!(v, Value::Date(_) | Value::Timestamp(_));
+    let comparable = |v: &Value| temporal(v) || matches!(v, Value::String(_));
+    (temporal(lv) && comparable(rv)) || (comparable(lv) && temporal(rv))
+}
+
+// 日期按照当天 00:00:00 转换为时间戳，统一按照微秒比较
+fn temporal_micros(v: &Value) -> Result<i64> {
+    match v {
+        Value::Date(d) => temporal::date_to_timestamp(*d),
+        Value::Timestamp(ts) => Ok(*ts),
+        Value::String(s) => temporal::parse_timestamp(s),
+        v => Err(Error::TypeMismatch(format!("{} is not a datetime value", v))),
+    }
+}
+
+// LIKE 模式匹配，有一边是 NULL 时结果为 NULL
+pub fn like_values(lv: Value, rv: Value) -> Result<Value> {
+    Ok(match (lv, rv) {
+        (Value::String(s), Value::String(pattern)) => {
+            let s = s.chars().collect::<Vec<_>>();
+            let pattern = pattern.chars().collect::<Vec<_>>();
+            Value::Boolean(like_match(&s, &pattern))
+        }
+        (Value::Null, _) | (_, Value::Null) => Value::Null,
+        (l, r) => {
+            return Err(Error::TypeMismatch(format!(
+                "can not match {} LIKE {}",
+                l, r
+            )))
+        }
+    })
+}
+
+// 动态规划匹配，matched[j] 表示 s 的前 i 个字符能否匹配 pattern 的前 j 个字符
+fn like_match(s: &[char], pattern: &[char]) -> bool {
+    let mut matched = vec![false; pattern.len() + 1];
+    matched[0] = true;
+    for (j, p) in pattern.iter().enumerate() {
+        matched[j + 1] = matched[j] && *p == '%';
+    }
+    for c in s {
+        let mut prev = matched[0];
+        matched[0] = false;
+        for (j, p) in pattern.iter().enumerate() {
+            let cur = matched[j + 1];
+            matched[j + 1] = match p {
+                '%' => matched[j] || cur,
+                '_' => prev,
+                p => prev && p == c,
+            };
+            prev = cur;
+        }
+    }
+    matched[pattern.len()]
+}
+
+// LIKE 模式中第一个通配符之前的常量前缀
+pub fn like_prefix(pattern: &str) -> &str {
+    match pattern.find(['%', '_']) {
+        Some(i) => &pattern[..i],
+        None => pattern,
+    }
+}
+
+// 加减运算，sub 为 true 时是减法
+// 日期时间加减时间间隔得到日期时间，两个日期时间相减得到时间间隔
+// 乘除运算，整数相除时向零取整，除数为 0 时报错
+pub fn mul_values(lv: Value, rv: Value, div: bool) -> Result<Value> {
+    let overflow = || Error::InvalidInput("value out of range".into());
+    Ok(match (lv, rv) {
+        (Value::Null, _) | (_, Value::Null) => Value::Null,
+        (_, Value::Integer(0)) if div => {
+            return Err(Error::InvalidInput("division by zero".into()))
+        }
+        (_, Value::Float(r)) if div && r == 0.0 => {
+            return Err(Error::InvalidInput("division by zero".into()))
+        }
+        (Value::Integer(l), Value::Integer(r)) => Value::Integer(
+            if div { l.checked_div(r) } else { l.checked_mul(r) }.ok_or_else(overflow)?,
+        ),
+        (Value::Integer(l), Value::Float(r)) => {
+            mul_values(Value::Float(l as f64), Value::Float(r), div)?
+        }
+        (Value::Float(l), Value::Integer(r)) => {
+            mul_values(Value::Float(l), Value::Float(r as f64), div)?
+        }
+        (Value::Float(l), Value::Float(r)) => Value::Float(if div { l / r } else { l * r }),
+        (l, r) if is_decimal_pair(&l, &r) => {
+            let (l, r) = (to_decimal(l)?, to_decimal(r)?);
+            Value::from(if div { l.checked_div(r)? } else { l.checked_mul(r)? })
+        }
+        (l, r) => {
+            return Err(Error::TypeMismatch(format!(
+                "can not compute {} {} {}",
+                l,
+                if div { "/" } else { "*" },
+                r
+            )))
+        }
+    })
+}
+
+pub fn add_values(lv: Value, rv: Value, sub: bool) -> Result<Value> {
+    let overflow = || Error::InvalidInput("value out of range".into());
+    Ok(match (lv, rv) {
+        (Value::Null, _) | (_, Value::Null) => Value::Null,
+        (Value::Integer(l), Value::Integer(r)) => Value::Integer(
+            if sub { l.checked_sub(r) } else { l.checked_add(r) }.ok_or_else(overflow)?,
+        ),
+        (Value::Integer(l), Value::Float(r)) => {
+            add_values(Value::Float(l as f64), Value::Float(r), sub)?
+        }
+        (Value::Float(l), Value::Integer(r)) => {
+            add_values(Value::Float(l), Value::Float(r as f64), sub)?
+        }
+        (Value::Float(l), Value::Float(r)) => Value::Float(if sub { l - r } else { l + r }),
+        (l, r) if is_decimal_pair(&l, &r) => {
+            let (l, r) = (to_decimal(l)?, to_decimal(r)?);
+            Value::from(l.checked_add(if sub { r.checked_neg()? } else { r })?)
+        }
+        (Value::Interval(l), Value::Interval(r)) => {
+            let r = if sub { r.checked_neg() } else { Some(r) };
+            Value::Interval(r.and_then(|r| l.checked_add(r)).ok_or_else(overflow)?)
+        }
+        (ts @ (Value::String(_) | Value::Date(_) | Value::Timestamp(_)), Value::Interval(i)) => {
+            functions::add_interval(&ts, i, sub)?
+        }
+        (Value::Interval(i), ts @ (Value::String(_) | Value::Date(_) | Value::Timestamp(_)))
+            if !sub =>
+        {
+            functions::add_interval(&ts, i, false)?
+        }
+        // 日期加减整数表示加减天数
+        (Value::Date(d), Value::Integer(n)) => Value::Date(
+            if sub { d.checked_sub(n) } else { d.checked_add(n) }.ok_or_else(overflow)?,
+        ),
+        (Value::Integer(n), Value::Date(d)) if !sub => {
+            Value::Date(d.checked_add(n).ok_or_else(overflow)?)
+        }
+        (l @ Value::String(_), r @ Value::String(_)) if sub => functions::datetime_diff(&l, &r)?,
+        (l, r) if sub && is_temporal_pair(&l, &r) => functions::datetime_diff(&l, &r)?,
+        (l, r) => {
+            return Err(Error::TypeMismatch(format!(
+                "can not compute {} {} {}",
+                l,
+                if sub { "-" } else { "+" },
+                r
+            )))
+        }
+    })
+}
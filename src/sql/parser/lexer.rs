@@ -1,8 +1,11 @@
 use std::{fmt::Display, iter::Peekable, str::Chars};
 
-use crate::error::{Error, Result};
+use crate::{
+    error::{Error, Result},
+    sql::types::blob,
+};
 
-use super::ast::{Consts, Expression};
+use super::ast::{evaluate_expr, Expression, Operation};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
@@ -36,15 +39,25 @@ pub enum Token {
     GreaterThan,
     // 小于
     LessThan,
+    // 点 .
+    Period,
+    // 左方括号 [
+    OpenBracket,
+    // 右方括号 ]
+    CloseBracket,
+    // 绑定参数 $1、$2，从 1 开始编号
+    Param(usize),
+    // 十六进制的二进制数据 x'DEADBEEF'
+    Blob(Vec<u8>),
 }
 
 impl Token {
     // 判断是不是运算符
     pub fn is_operator(&self) -> bool {
-        match self {
-            Token::Plus | Token::Minus | Token::Asterisk | Token::Slash => true,
-            _ => false,
-        }
+        matches!(
+            self,
+            Token::Plus | Token::Minus | Token::Asterisk | Token::Slash
+        )
     }
 
     // 获取运算符的优先级
@@ -56,37 +69,23 @@ impl Token {
         }
     }
 
-    // 根据运算符进行计算
+    // 根据运算符生成运算表达式，两边都是常量时直接计算出结果
+    // 例如 1 + 2、'2024-01-01' + interval '1 day'，包含列的运算在执行时逐行计算
     pub fn compute_expr(&self, l: Expression, r: Expression) -> Result<Expression> {
-        let val = match (l, r) {
-            (Expression::Consts(c1), Expression::Consts(c2)) => match (c1, c2) {
-                (super::ast::Consts::Integer(l), super::ast::Consts::Integer(r)) => {
-                    self.compute(l as f64, r as f64)?
-                }
-                (super::ast::Consts::Integer(l), super::ast::Consts::Float(r)) => {
-                    self.compute(l as f64, r)?
-                }
-                (super::ast::Consts::Float(l), super::ast::Consts::Integer(r)) => {
-                    self.compute(l, r as f64)?
-                }
-                (super::ast::Consts::Float(l), super::ast::Consts::Float(r)) => {
-                    self.compute(l, r)?
-                }
-                _ => return Err(Error::Parse("cannot compute the expresssion".into())),
-            },
-            _ => return Err(Error::Parse("cannot compute the expresssion".into())),
-        };
-        Ok(Expression::Consts(Consts::Float(val)))
-    }
-
-    fn compute(&self, l: f64, r: f64) -> Result<f64> {
-        Ok(match self {
-            Token::Asterisk => l * r,
-            Token::Plus => l + r,
-            Token::Minus => l - r,
-            Token::Slash => l / r,
+        let constant = matches!((&l, &r), (Expression::Consts(_), Expression::Consts(_)));
+        let (l, r) = (Box::new(l), Box::new(r));
+        let expr = Expression::Operation(match self {
+            Token::Plus => Operation::Add(l, r),
+            Token::Minus => Operation::Subtract(l, r),
+            Token::Asterisk => Operation::Multiply(l, r),
+            Token::Slash => Operation::Divide(l, r),
             _ => return Err(Error::Parse("cannot compute the expresssion".into())),
-        })
+        });
+        if !constant {
+            return Ok(expr);
+        }
+        let (cols, row) = (Vec::new(), Vec::new());
+        Ok(evaluate_expr(&expr, &cols, &row, &cols, &row)?.into())
     }
 }
 
@@ -108,6 +107,11 @@ impl Display for Token {
             Token::Equal => "=",
             Token::GreaterThan => ">",
             Token::LessThan => "<",
+            Token::Period => ".",
+            Token::OpenBracket => "[",
+            Token::CloseBracket => "]",
+            Token::Param(n) => return write!(f, "${}", n),
+            Token::Blob(b) => return write!(f, "x'{}'", blob::to_hex(b)),
         })
     }
 }
@@ -161,6 +165,41 @@ pub enum Keyword {
     Index,
     Explain,
     Drop,
+    Alter,
+    Column,
+    Reindex,
+    Vacuum,
+    Declare,
+    Cursor,
+    For,
+    Fetch,
+    Close,
+    All,
+    Is,
+    Distinct,
+    Interval,
+    Date,
+    Timestamp,
+    Like,
+    With,
+    Truncate,
+    Show,
+    Using,
+    And,
+    Or,
+    Between,
+    In,
+    Unique,
+    AutoIncrement,
+    Copy,
+    Savepoint,
+    Release,
+    Check,
+    Union,
+    Intersect,
+    Except,
+    Backup,
+    Restore,
 }
 
 impl Keyword {
@@ -214,6 +253,41 @@ impl Keyword {
             "INDEX" => Keyword::Index,
             "EXPLAIN" => Keyword::Explain,
             "DROP" => Keyword::Drop,
+            "ALTER" => Keyword::Alter,
+            "COLUMN" => Keyword::Column,
+            "REINDEX" => Keyword::Reindex,
+            "VACUUM" => Keyword::Vacuum,
+            "DECLARE" => Keyword::Declare,
+            "CURSOR" => Keyword::Cursor,
+            "FOR" => Keyword::For,
+            "FETCH" => Keyword::Fetch,
+            "CLOSE" => Keyword::Close,
+            "ALL" => Keyword::All,
+            "IS" => Keyword::Is,
+            "DISTINCT" => Keyword::Distinct,
+            "INTERVAL" => Keyword::Interval,
+            "DATE" => Keyword::Date,
+            "TIMESTAMP" => Keyword::Timestamp,
+            "LIKE" => Keyword::Like,
+            "WITH" => Keyword::With,
+            "TRUNCATE" => Keyword::Truncate,
+            "SHOW" => Keyword::Show,
+            "USING" => Keyword::Using,
+            "AND" => Keyword::And,
+            "OR" => Keyword::Or,
+            "BETWEEN" => Keyword::Between,
+            "IN" => Keyword::In,
+            "UNIQUE" => Keyword::Unique,
+            "AUTO_INCREMENT" => Keyword::AutoIncrement,
+            "COPY" => Keyword::Copy,
+            "SAVEPOINT" => Keyword::Savepoint,
+            "RELEASE" => Keyword::Release,
+            "CHECK" => Keyword::Check,
+            "UNION" => Keyword::Union,
+            "INTERSECT" => Keyword::Intersect,
+            "EXCEPT" => Keyword::Except,
+            "BACKUP" => Keyword::Backup,
+            "RESTORE" => Keyword::Restore,
             _ => return None,
         })
     }
@@ -267,6 +341,41 @@ impl Keyword {
             Keyword::Index => "INDEX",
             Keyword::Explain => "EXPLAIN",
             Keyword::Drop => "DROP",
+            Keyword::Alter => "ALTER",
+            Keyword::Column => "COLUMN",
+            Keyword::Reindex => "REINDEX",
+            Keyword::Vacuum => "VACUUM",
+            Keyword::Declare => "DECLARE",
+            Keyword::Cursor => "CURSOR",
+            Keyword::For => "FOR",
+            Keyword::Fetch => "FETCH",
+            Keyword::Close => "CLOSE",
+            Keyword::All => "ALL",
+            Keyword::Is => "IS",
+            Keyword::Distinct => "DISTINCT",
+            Keyword::Interval => "INTERVAL",
+            Keyword::Date => "DATE",
+            Keyword::Timestamp => "TIMESTAMP",
+            Keyword::Like => "LIKE",
+            Keyword::With => "WITH",
+            Keyword::Truncate => "TRUNCATE",
+            Keyword::Show => "SHOW",
+            Keyword::Using => "USING",
+            Keyword::And => "AND",
+            Keyword::Or => "OR",
+            Keyword::Between => "BETWEEN",
+            Keyword::In => "IN",
+            Keyword::Unique => "UNIQUE",
+            Keyword::AutoIncrement => "AUTO_INCREMENT",
+            Keyword::Copy => "COPY",
+            Keyword::Savepoint => "SAVEPOINT",
+            Keyword::Release => "RELEASE",
+            Keyword::Check => "CHECK",
+            Keyword::Union => "UNION",
+            Keyword::Intersect => "INTERSECT",
+            Keyword::Except => "EXCEPT",
+            Keyword::Backup => "BACKUP",
+            Keyword::Restore => "RESTORE",
         }
     }
 }
@@ -347,7 +456,8 @@ impl<'a> Lexer<'a> {
         match self.iter.peek() {
             Some('\'') => self.scan_string(), // 扫描字符串
             Some(c) if c.is_ascii_digit() => Ok(self.scan_number()), // 扫描数字
-            Some(c) if c.is_alphabetic() => Ok(self.scan_ident()), // 扫描 Ident 类型
+            Some(c) if c.is_alphabetic() || *c == '_' => self.scan_ident(), // 扫描 Ident 类型
+            Some('$') => self.scan_param(), // 扫描绑定参数
             Some(_) => Ok(self.scan_symbol()), // 扫描符号, + - * / = > < 之类的;
             None => Ok(None),
         }
@@ -363,9 +473,11 @@ impl<'a> Lexer<'a> {
         let mut val = String::new();
         loop {
             match self.iter.next() {
+                // 连续两个单引号表示字符串中的一个单引号
+                Some('\'') if self.next_if(|c| c == '\'').is_some() => val.push('\''),
                 Some('\'') => break,
                 Some(c) => val.push(c),
-                None => return Err(Error::Parse(format!("[Lexer] Unexpected end of string"))),
+                None => return Err(Error::Parse("[Lexer] Unexpected end of string".to_string())),
             }
         }
 
@@ -373,6 +485,15 @@ impl<'a> Lexer<'a> {
     }
 
     // 扫描数字
+    // 扫描绑定参数，$ 后面是参数的序号
+    fn scan_param(&mut self) -> Result<Option<Token>> {
+        self.next_if(|c| c == '$');
+        match self.next_while(|c| c.is_ascii_digit()) {
+            Some(n) => Ok(Some(Token::Param(n.parse()?))),
+            None => Err(Error::Parse("[Lexer] Expected parameter number after $".into())),
+        }
+    }
+
     fn scan_number(&mut self) -> Option<Token> {
         // 先扫描一部分
         let mut num = self.next_while(|c| c.is_ascii_digit())?;
@@ -389,16 +510,30 @@ impl<'a> Lexer<'a> {
     }
 
     // 扫描 Ident 类型，例如表名、列名等，也有可能是关键字，true / false
-    fn scan_ident(&mut self) -> Option<Token> {
+    // 标识符可以用下划线开头，例如隐藏列 _rowid
+    fn scan_ident(&mut self) -> Result<Option<Token>> {
         //
-        let mut value = self.next_if(|c| c.is_alphabetic())?.to_string();
+        let Some(first) = self.next_if(|c| c.is_alphabetic() || c == '_') else {
+            return Ok(None);
+        };
+        // x'DEADBEEF' 是二进制数据
+        if first.eq_ignore_ascii_case(&'x') && self.iter.peek() == Some(&'\'') {
+            return match self.scan_string()? {
+                Some(Token::String(s)) => Ok(Some(Token::Blob(blob::parse_hex(&s)?))),
+                _ => unreachable!(),
+            };
+        }
+        let mut value = first.to_string();
         //
         while let Some(c) = self.next_if(|c| c.is_alphanumeric() || c == '_') {
             value.push(c);
         }
 
         // 返回关键字类型; 成功是Keyword, 失败是Ident;
-        Some(Keyword::from_str(&value).map_or(Token::Ident(value.to_lowercase()), Token::Keyword))
+        Ok(Some(Keyword::from_str(&value).map_or(
+            Token::Ident(value.to_lowercase()),
+            Token::Keyword,
+        )))
     }
 
     // 扫描符号
@@ -415,6 +550,9 @@ impl<'a> Lexer<'a> {
             '=' => Some(Token::Equal),
             '>' => Some(Token::GreaterThan),
             '<' => Some(Token::LessThan),
+            '.' => Some(Token::Period),
+            '[' => Some(Token::OpenBracket),
+            ']' => Some(Token::CloseBracket),
             _ => None,
         })
     }
@@ -482,7 +620,7 @@ mod tests {
             .peekable()
             .collect::<Result<Vec<_>>>()?;
 
-        assert!(tokens2.len() > 0);
+        assert!(!tokens2.is_empty());
 
         Ok(())
     }
@@ -566,4 +704,25 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_lexer_blob() -> Result<()> {
+        let tokens = Lexer::new("select x, X'00Ff' from x;")
+            .peekable()
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Keyword(Keyword::Select),
+                Token::Ident("x".to_string()),
+                Token::Comma,
+                Token::Blob(vec![0, 255]),
+                Token::Keyword(Keyword::From),
+                Token::Ident("x".to_string()),
+                Token::Semicolon,
+            ]
+        );
+        assert!(Lexer::new("x'abc'").collect::<Result<Vec<_>>>().is_err());
+        Ok(())
+    }
 }
@@ -1,24 +1,43 @@
 use std::{collections::BTreeMap, iter::Peekable};
 
-use ast::{Column, Expression, Operation, OrderDirection};
+use ast::{Column, CopyDirection, CopyOptions, Expression, Operation, OrderDirection};
 use lexer::{Keyword, Lexer, Token};
 
-use crate::error::{Error, Result};
+use crate::{
+    error::{Error, Result},
+    storage::mvcc::IsolationLevel,
+};
 
-use super::types::DataType;
+use super::{
+    functions,
+    schema::{IndexType, Layout},
+    types::{decimal, temporal, DataType, Decimal, Interval, Value},
+};
 
 pub mod ast;
 mod lexer;
 
+// 不是关键字、但是在 from 中有特殊含义的单词，不能作为省略了 AS 的表别名
+// 否则 from a full join b 会把 full 当作 a 的别名，静默地执行成 inner join
+const RESERVED_ALIASES: [&str; 6] = ["full", "inner", "lateral", "natural", "outer", "tablesample"];
+
 // 解析器定义
 pub struct Parser<'a> {
     lexer: Peekable<Lexer<'a>>,
+    // 绑定参数的值，$1 对应第一个
+    params: Vec<Value>,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(input: &'a str) -> Self {
+        Self::with_params(input, Vec::new())
+    }
+
+    // 语句中的 $n 替换成 params 中对应的值，值不会被当作 SQL 解析
+    pub fn with_params(input: &'a str, params: Vec<Value>) -> Self {
         Parser {
             lexer: Lexer::new(input).peekable(),
+            params,
         }
     }
 
@@ -35,11 +54,23 @@ impl<'a> Parser<'a> {
         Ok(stmt)
     }
 
+    // 解析单独的一个表达式，例如表结构中保存的 CHECK 约束
+    pub fn parse_expr(&mut self) -> Result<ast::Expression> {
+        let expr = self.parse_operation_expr()?;
+        if let Some(token) = self.peek()? {
+            return Err(Error::Parse(format!("[Parser] Unexpected token {}", token)));
+        }
+        Ok(expr)
+    }
+
     fn parse_statement(&mut self) -> Result<ast::Statement> {
         // 查看第一个 Token 类型
         match self.peek()? {
             Some(Token::Keyword(Keyword::Create)) => self.parse_ddl(),
             Some(Token::Keyword(Keyword::Drop)) => self.parse_ddl(),
+            Some(Token::Keyword(Keyword::Alter)) => self.parse_ddl(),
+            Some(Token::Keyword(Keyword::Reindex)) => self.parse_ddl(),
+            Some(Token::Keyword(Keyword::Truncate)) => self.parse_ddl(),
             Some(Token::Keyword(Keyword::Select)) => self.parse_select(),
             Some(Token::Keyword(Keyword::Insert)) => self.parse_insert(),
             Some(Token::Keyword(Keyword::Update)) => self.parse_update(),
@@ -47,24 +78,131 @@ impl<'a> Parser<'a> {
             Some(Token::Keyword(Keyword::Begin)) => self.parse_transaction(),
             Some(Token::Keyword(Keyword::Commit)) => self.parse_transaction(),
             Some(Token::Keyword(Keyword::Rollback)) => self.parse_transaction(),
+            Some(Token::Keyword(Keyword::Savepoint)) => self.parse_transaction(),
+            Some(Token::Keyword(Keyword::Release)) => self.parse_transaction(),
             Some(Token::Keyword(Keyword::Explain)) => self.parse_explain(),
+            Some(Token::Keyword(Keyword::Set)) => self.parse_set(),
+            Some(Token::Keyword(Keyword::Declare)) => self.parse_declare_cursor(),
+            Some(Token::Keyword(Keyword::Fetch)) => self.parse_fetch(),
+            Some(Token::Keyword(Keyword::Close)) => {
+                self.next()?;
+                Ok(ast::Statement::CloseCursor {
+                    name: self.next_ident()?,
+                })
+            }
+            Some(Token::Keyword(Keyword::Copy)) => self.parse_copy(),
+            Some(Token::Keyword(Keyword::Backup)) => {
+                self.next()?;
+                self.next_expect(Token::Ident("to".into()))?;
+                Ok(ast::Statement::Backup {
+                    path: self.parse_file_path()?,
+                })
+            }
+            Some(Token::Keyword(Keyword::Restore)) => {
+                self.next()?;
+                self.next_expect(Token::Keyword(Keyword::From))?;
+                Ok(ast::Statement::Restore {
+                    path: self.parse_file_path()?,
+                })
+            }
+            Some(Token::Keyword(Keyword::Vacuum)) => {
+                self.next()?;
+                Ok(ast::Statement::Vacuum)
+            }
+            // show profile
+            Some(Token::Keyword(Keyword::Show)) => {
+                self.next()?;
+                self.next_expect(Token::Ident("profile".into()))?;
+                Ok(ast::Statement::ShowProfile)
+            }
             Some(t) => Err(Error::Parse(format!("[Parser] Unexpected token {}", t))),
-            None => Err(Error::Parse(format!("[Parser] Unexpected end of input"))),
+            None => Err(Error::Parse("[Parser] Unexpected end of input".to_string())),
         }
     }
 
     // 解析 DDL 类型
     fn parse_ddl(&mut self) -> Result<ast::Statement> {
         match self.next()? {
-            Token::Keyword(Keyword::Create) => self.parse_ddl_create_table(),
-            Token::Keyword(Keyword::Drop) => self.parse_ddl_drop_table(),
+            Token::Keyword(Keyword::Create) => match self.peek()? {
+                Some(Token::Keyword(Keyword::Index)) => self.parse_ddl_create_index(),
+                _ => self.parse_ddl_create_table(),
+            },
+            Token::Keyword(Keyword::Drop) => match self.peek()? {
+                Some(Token::Keyword(Keyword::Index)) => self.parse_ddl_drop_index(),
+                _ => self.parse_ddl_drop_table(),
+            },
+            Token::Keyword(Keyword::Alter) => self.parse_ddl_alter_table(),
+            Token::Keyword(Keyword::Reindex) => self.parse_ddl_reindex(),
+            Token::Keyword(Keyword::Truncate) => self.parse_ddl_truncate_table(),
             token => Err(Error::Parse(format!("[Parser] Unexpected token {}", token))),
         }
     }
 
-    // 解析 Select 语句
+    // 解析 Select 语句，包括 union、intersect、except 连接的多个 select
+    // 集合操作的优先级相同，从左到右结合，只有最后一个 select 可以带 order by、limit、offset，作用在合并之后的结果上
     fn parse_select(&mut self) -> Result<ast::Statement> {
+        let mut stmt = self.parse_simple_select()?;
+        while let Some(token) = self.next_if(|t| {
+            matches!(
+                t,
+                Token::Keyword(Keyword::Union | Keyword::Intersect | Keyword::Except)
+            )
+        }) {
+            let op = match token {
+                Token::Keyword(Keyword::Union) => ast::SetOperator::Union,
+                Token::Keyword(Keyword::Intersect) => ast::SetOperator::Intersect,
+                _ => ast::SetOperator::Except,
+            };
+            if let ast::Statement::Select {
+                order_by,
+                limit,
+                offset,
+                ..
+            }
+            | ast::Statement::SetOperation {
+                order_by,
+                limit,
+                offset,
+                ..
+            } = &stmt
+            {
+                if !order_by.is_empty() || limit.is_some() || offset.is_some() {
+                    return Err(Error::Parse(format!(
+                        "[Parser] ORDER BY, LIMIT and OFFSET must come after the last query of {}",
+                        op
+                    )));
+                }
+            }
+            let all = self.next_if_token(Token::Keyword(Keyword::All)).is_some();
+            let mut right = self.parse_simple_select()?;
+            let (order_by, limit, offset) = match &mut right {
+                ast::Statement::Select {
+                    order_by,
+                    limit,
+                    offset,
+                    ..
+                } => (std::mem::take(order_by), limit.take(), offset.take()),
+                _ => unreachable!(),
+            };
+            stmt = ast::Statement::SetOperation {
+                op,
+                all,
+                left: Box::new(stmt),
+                right: Box::new(right),
+                order_by,
+                limit,
+                offset,
+            };
+        }
+        Ok(stmt)
+    }
+
+    fn parse_simple_select(&mut self) -> Result<ast::Statement> {
+        self.next_expect(Token::Keyword(Keyword::Select))?;
         Ok(ast::Statement::Select {
+            distinct: self
+                .next_if_token(Token::Keyword(Keyword::Distinct))
+                .is_some(),
             select: self.parse_select_clause()?,
             from: self.parse_from_clause()?,
             where_clause: self.parse_where_clause()?,
@@ -117,6 +255,16 @@ impl<'a> Parser<'a> {
             None
         };
 
+        // insert into tbl default values; 所有列都使用默认值
+        if columns.is_none() && self.next_if_token(Token::Keyword(Keyword::Default)).is_some() {
+            self.next_expect(Token::Keyword(Keyword::Values))?;
+            return Ok(ast::Statement::Insert {
+                table_name,
+                columns,
+                values: vec![vec![]],
+            });
+        }
+
         // 解析 value 信息
         self.next_expect(Token::Keyword(Keyword::Values))?;
         // insert into tbl(a, b, c) values (1, 2, 3),(4, 5, 6);
@@ -125,7 +273,12 @@ impl<'a> Parser<'a> {
             self.next_expect(Token::OpenParen)?;
             let mut exprs = Vec::new();
             loop {
-                exprs.push(self.parse_expression()?);
+                // values 中的 DEFAULT 表示使用列的默认值
+                if self.next_if_token(Token::Keyword(Keyword::Default)).is_some() {
+                    exprs.push(ast::Expression::Default);
+                } else {
+                    exprs.push(self.compute_math_operator(1)?);
+                }
                 match self.next()? {
                     Token::CloseParen => break,
                     Token::Comma => {}
@@ -157,9 +310,32 @@ impl<'a> Parser<'a> {
 
         // 解析列信息
         let mut columns = Vec::new();
+        let mut primary_key = Vec::new();
+        let mut checks = Vec::new();
         // 循环解析列信息;
         loop {
-            columns.push(self.parse_ddl_column()?);
+            // 表级别的主键约束 PRIMARY KEY (a, b)
+            if self.next_if_token(Token::Keyword(Keyword::Primary)).is_some() {
+                self.next_expect(Token::Keyword(Keyword::Key))?;
+                if !primary_key.is_empty() {
+                    return Err(Error::Parse(
+                        "[Parser] Multiple PRIMARY KEY clauses".to_string(),
+                    ));
+                }
+                self.next_expect(Token::OpenParen)?;
+                loop {
+                    primary_key.push(self.next_ident()?);
+                    if self.next_if_token(Token::Comma).is_none() {
+                        break;
+                    }
+                }
+                self.next_expect(Token::CloseParen)?;
+            } else if self.next_if_token(Token::Keyword(Keyword::Check)).is_some() {
+                // 表级别的 CHECK (expr) 约束，可以引用多个列
+                checks.push(self.parse_ddl_check()?);
+            } else {
+                columns.push(self.parse_ddl_column()?);
+            }
             // 如果没有逗号，列解析完成，跳出
             if self.next_if_token(Token::Comma).is_none() {
                 break;
@@ -170,6 +346,106 @@ impl<'a> Parser<'a> {
         Ok(ast::Statement::CreateTable {
             name: table_name,
             columns,
+            primary_key,
+            checks,
+            layout: self.parse_ddl_table_options()?,
+        })
+    }
+
+    // 解析建表语句最后的 WITH (layout = 'columnar') 选项
+    fn parse_ddl_table_options(&mut self) -> Result<Layout> {
+        let mut layout = Layout::Row;
+        if self.next_if_token(Token::Keyword(Keyword::With)).is_none() {
+            return Ok(layout);
+        }
+        self.next_expect(Token::OpenParen)?;
+        loop {
+            let option = self.next_ident()?;
+            self.next_expect(Token::Equal)?;
+            let value = self.next()?;
+            match (option.as_str(), &value) {
+                ("layout", Token::String(v)) if v.eq_ignore_ascii_case("row") => {
+                    layout = Layout::Row
+                }
+                ("layout", Token::String(v)) if v.eq_ignore_ascii_case("columnar") => {
+                    layout = Layout::Columnar
+                }
+                _ => {
+                    return Err(Error::Parse(format!(
+                        "[Parser] Invalid table option {} = {}",
+                        option, value
+                    )))
+                }
+            }
+            if self.next_if_token(Token::Comma).is_none() {
+                break;
+            }
+        }
+        self.next_expect(Token::CloseParen)?;
+        Ok(layout)
+    }
+
+    // 解析 copy t from|to 'file.csv' [with (header = true, delimiter = ';')]
+    // 文件路径是一个字符串常量
+    fn parse_file_path(&mut self) -> Result<String> {
+        match self.next()? {
+            Token::String(path) => Ok(path),
+            token => Err(Error::Parse(format!(
+                "[Parser] Expected file path, got {}",
+                token
+            ))),
+        }
+    }
+
+    fn parse_copy(&mut self) -> Result<ast::Statement> {
+        self.next_expect(Token::Keyword(Keyword::Copy))?;
+        let table_name = self.next_ident()?;
+        let direction = match self.next()? {
+            Token::Keyword(Keyword::From) => CopyDirection::From,
+            Token::Ident(ident) if ident == "to" => CopyDirection::To,
+            token => {
+                return Err(Error::Parse(format!(
+                    "[Parser] Expected FROM or TO, got {}",
+                    token
+                )))
+            }
+        };
+        let path = self.parse_file_path()?;
+
+        let mut options = CopyOptions::default();
+        if self.next_if_token(Token::Keyword(Keyword::With)).is_some() {
+            self.next_expect(Token::OpenParen)?;
+            loop {
+                let option = self.next_ident()?;
+                self.next_expect(Token::Equal)?;
+                let value = self.next()?;
+                match (option.as_str(), &value) {
+                    ("header", Token::Keyword(Keyword::True)) => options.header = true,
+                    ("header", Token::Keyword(Keyword::False)) => options.header = false,
+                    // 分隔符只能是单个 ASCII 字符，不能是引号和换行
+                    ("delimiter", Token::String(v))
+                        if v.len() == 1 && v.is_ascii() && !matches!(v.as_str(), "\"" | "\n" | "\r") =>
+                    {
+                        options.delimiter = v.as_bytes()[0]
+                    }
+                    _ => {
+                        return Err(Error::Parse(format!(
+                            "[Parser] Invalid copy option {} = {}",
+                            option, value
+                        )))
+                    }
+                }
+                if self.next_if_token(Token::Comma).is_none() {
+                    break;
+                }
+            }
+            self.next_expect(Token::CloseParen)?;
+        }
+        Ok(ast::Statement::Copy {
+            table_name,
+            path,
+            direction,
+            options,
         })
     }
 
@@ -177,24 +453,15 @@ impl<'a> Parser<'a> {
     fn parse_ddl_column(&mut self) -> Result<ast::Column> {
         let mut column = Column {
             name: self.next_ident()?,
-
-            datatype: match self.next()? {
-                Token::Keyword(Keyword::Int) | Token::Keyword(Keyword::Integer) => {
-                    DataType::Integer
-                }
-                Token::Keyword(Keyword::Bool) | Token::Keyword(Keyword::Boolean) => {
-                    DataType::Boolean
-                }
-                Token::Keyword(Keyword::Float) | Token::Keyword(Keyword::Double) => DataType::Float,
-                Token::Keyword(Keyword::String)
-                | Token::Keyword(Keyword::Text)
-                | Token::Keyword(Keyword::Varchar) => DataType::String,
-                token => return Err(Error::Parse(format!("[Parser] Unexpected token {}", token))),
-            },
+            datatype: self.parse_ddl_datatype()?,
             nullable: None,
             default: None,
             primary_key: false,
             index: false,
+            index_type: IndexType::Ordered,
+            unique: false,
+            auto_increment: false,
+            check: None,
         };
 
         // 解析列的默认值，以及是否可以为空;
@@ -210,7 +477,13 @@ impl<'a> Parser<'a> {
                     self.next_expect(Token::Keyword(Keyword::Key))?;
                     column.primary_key = true;
                 }
-                Keyword::Index => column.index = true,
+                Keyword::Index => {
+                    column.index = true;
+                    column.index_type = self.parse_ddl_index_type()?;
+                }
+                Keyword::Unique => column.unique = true,
+                Keyword::AutoIncrement => column.auto_increment = true,
+                Keyword::Check => column.check = Some(self.parse_ddl_check()?),
                 k => return Err(Error::Parse(format!("[Parser] Unexpected keyword {}", k))),
             }
         }
@@ -218,6 +491,28 @@ impl<'a> Parser<'a> {
         Ok(column)
     }
 
+    // 解析 CHECK 之后括号中的表达式
+    fn parse_ddl_check(&mut self) -> Result<ast::Expression> {
+        self.next_expect(Token::OpenParen)?;
+        let expr = self.parse_operation_expr()?;
+        self.next_expect(Token::CloseParen)?;
+        Ok(expr)
+    }
+
+    // 解析索引列后面可选的 USING btree | hash | fulltext | bitmap
+    fn parse_ddl_index_type(&mut self) -> Result<IndexType> {
+        if self.next_if_token(Token::Keyword(Keyword::Using)).is_none() {
+            return Ok(IndexType::Ordered);
+        }
+        match self.next_ident()?.as_str() {
+            "btree" => Ok(IndexType::Ordered),
+            "hash" => Ok(IndexType::Hash),
+            "fulltext" => Ok(IndexType::FullText),
+            "bitmap" => Ok(IndexType::Bitmap),
+            t => Err(Error::Parse(format!("[Parser] Unknown index type {}", t))),
+        }
+    }
+
     // 解析 Drop Table 语句
     fn parse_ddl_drop_table(&mut self) -> Result<ast::Statement> {
         self.next_expect(Token::Keyword(Keyword::Table))?;
@@ -226,6 +521,190 @@ impl<'a> Parser<'a> {
         })
     }
 
+    // 解析 Truncate Table 语句，TABLE 关键字可以省略
+    fn parse_ddl_truncate_table(&mut self) -> Result<ast::Statement> {
+        self.next_if_token(Token::Keyword(Keyword::Table));
+        Ok(ast::Statement::TruncateTable {
+            name: self.next_ident()?,
+        })
+    }
+
+    // 解析列的类型
+    fn parse_ddl_datatype(&mut self) -> Result<DataType> {
+        let mut datatype = match self.next()? {
+            Token::Keyword(Keyword::Int) | Token::Keyword(Keyword::Integer) => DataType::Integer,
+            Token::Keyword(Keyword::Bool) | Token::Keyword(Keyword::Boolean) => DataType::Boolean,
+            Token::Keyword(Keyword::Float) | Token::Keyword(Keyword::Double) => DataType::Float,
+            Token::Keyword(Keyword::String)
+            | Token::Keyword(Keyword::Text)
+            | Token::Keyword(Keyword::Varchar) => DataType::String,
+            Token::Keyword(Keyword::Interval) => DataType::Interval,
+            Token::Keyword(Keyword::Date) => DataType::Date,
+            Token::Keyword(Keyword::Timestamp) => DataType::Timestamp,
+            Token::Ident(ident) if ident == "decimal" || ident == "numeric" => {
+                self.parse_ddl_decimal()?
+            }
+            Token::Ident(ident) if ident == "blob" || ident == "bytea" => DataType::Blob,
+            token => return Err(Error::Parse(format!("[Parser] Unexpected token {}", token))),
+        };
+        // 数组类型，例如 int[]
+        while self.next_if_token(Token::OpenBracket).is_some() {
+            self.next_expect(Token::CloseBracket)?;
+            datatype = DataType::Array(Box::new(datatype));
+        }
+        Ok(datatype)
+    }
+
+    // decimal、decimal(p)、decimal(p, s)，默认为 decimal(10, 0)
+    fn parse_ddl_decimal(&mut self) -> Result<DataType> {
+        let number = |parser: &mut Self| match parser.next()? {
+            Token::Number(n) => Ok(n.parse::<u8>()?),
+            token => Err(Error::Parse(format!("[Parser] Unexpected token {}", token))),
+        };
+        let (mut precision, mut scale) = (10, 0);
+        if self.next_if_token(Token::OpenParen).is_some() {
+            precision = number(self)?;
+            if self.next_if_token(Token::Comma).is_some() {
+                scale = number(self)?;
+            }
+            self.next_expect(Token::CloseParen)?;
+        }
+        if precision == 0 || precision > decimal::MAX_PRECISION || scale > precision {
+            return Err(Error::Parse(format!(
+                "[Parser] Invalid decimal precision ({}, {})",
+                precision, scale
+            )));
+        }
+        Ok(DataType::Decimal(precision, scale))
+    }
+
+    // 解析 Alter Table 语句
+    // alter table t alter [column] c set default expr
+    // alter table t alter [column] c drop default
+    // alter table t alter [column] c [set data] type datatype
+    // alter table t add [column] c datatype ...
+    // alter table t drop [column] c
+    // alter table t rename [column] c to new_name
+    fn parse_ddl_alter_table(&mut self) -> Result<ast::Statement> {
+        self.next_expect(Token::Keyword(Keyword::Table))?;
+        let name = self.next_ident()?;
+        let action = match self.next()? {
+            Token::Keyword(Keyword::Alter) => self.parse_ddl_alter_column()?,
+            Token::Ident(ident) if ident == "add" => {
+                self.next_if_token(Token::Keyword(Keyword::Column));
+                ast::AlterTableAction::AddColumn(self.parse_ddl_column()?)
+            }
+            Token::Keyword(Keyword::Drop) => {
+                self.next_if_token(Token::Keyword(Keyword::Column));
+                ast::AlterTableAction::DropColumn(self.next_ident()?)
+            }
+            Token::Ident(ident) if ident == "rename" => {
+                self.next_if_token(Token::Keyword(Keyword::Column));
+                let column = self.next_ident()?;
+                self.next_expect(Token::Ident("to".into()))?;
+                ast::AlterTableAction::RenameColumn {
+                    column,
+                    new_name: self.next_ident()?,
+                }
+            }
+            token => return Err(Error::Parse(format!("[Parser] Unexpected token {}", token))),
+        };
+        Ok(ast::Statement::AlterTable { name, action })
+    }
+
+    // alter [column] 之后修改列的默认值或者类型，alter 已经被解析
+    fn parse_ddl_alter_column(&mut self) -> Result<ast::AlterTableAction> {
+        self.next_if_token(Token::Keyword(Keyword::Column));
+        let column = self.next_ident()?;
+
+        let action = match self.next()? {
+            Token::Keyword(Keyword::Set) => match self.next()? {
+                Token::Keyword(Keyword::Default) => ast::AlterTableAction::AlterColumnDefault {
+                    column,
+                    default: Some(self.parse_expression()?),
+                },
+                Token::Ident(ident) if ident == "data" => {
+                    self.next_expect(Token::Ident("type".into()))?;
+                    ast::AlterTableAction::AlterColumnType {
+                        column,
+                        datatype: self.parse_ddl_datatype()?,
+                    }
+                }
+                token => return Err(Error::Parse(format!("[Parser] Unexpected token {}", token))),
+            },
+            Token::Keyword(Keyword::Drop) => {
+                self.next_expect(Token::Keyword(Keyword::Default))?;
+                ast::AlterTableAction::AlterColumnDefault {
+                    column,
+                    default: None,
+                }
+            }
+            Token::Ident(ident) if ident == "type" => ast::AlterTableAction::AlterColumnType {
+                column,
+                datatype: self.parse_ddl_datatype()?,
+            },
+            token => return Err(Error::Parse(format!("[Parser] Unexpected token {}", token))),
+        };
+        Ok(action)
+    }
+
+    // 解析 Reindex 语句
+    // reindex table t
+    // reindex index c on t
+    fn parse_ddl_reindex(&mut self) -> Result<ast::Statement> {
+        match self.next()? {
+            Token::Keyword(Keyword::Table) => Ok(ast::Statement::Reindex {
+                table_name: self.next_ident()?,
+                column: None,
+            }),
+            Token::Keyword(Keyword::Index) => {
+                let column = self.next_ident()?;
+                self.next_expect(Token::Keyword(Keyword::On))?;
+                Ok(ast::Statement::Reindex {
+                    table_name: self.next_ident()?,
+                    column: Some(column),
+                })
+            }
+            token => Err(Error::Parse(format!("[Parser] Unexpected token {}", token))),
+        }
+    }
+
+    // 解析 Create Index 语句，索引名可以省略
+    // create index idx on t (c) using hash
+    fn parse_ddl_create_index(&mut self) -> Result<ast::Statement> {
+        self.next_expect(Token::Keyword(Keyword::Index))?;
+        let name = match self.next_if_token(Token::Keyword(Keyword::On)) {
+            Some(_) => None,
+            None => {
+                let name = self.next_ident()?;
+                self.next_expect(Token::Keyword(Keyword::On))?;
+                Some(name)
+            }
+        };
+        let table_name = self.next_ident()?;
+        self.next_expect(Token::OpenParen)?;
+        let column = self.next_ident()?;
+        self.next_expect(Token::CloseParen)?;
+        Ok(ast::Statement::CreateIndex {
+            name,
+            table_name,
+            column,
+            index_type: self.parse_ddl_index_type()?,
+        })
+    }
+
+    // 解析 Drop Index 语句，索引名只在一张表中唯一，需要指定表名
+    // drop index idx on t
+    fn parse_ddl_drop_index(&mut self) -> Result<ast::Statement> {
+        self.next_expect(Token::Keyword(Keyword::Index))?;
+        let name = self.next_ident()?;
+        self.next_expect(Token::Keyword(Keyword::On))?;
+        Ok(ast::Statement::DropIndex {
+            name,
+            table_name: self.next_ident()?,
+        })
+    }
+
     // 解析 Update 语句,成语法树;
     fn parse_update(&mut self) -> Result<ast::Statement> {
         self.next_expect(Token::Keyword(Keyword::Update))?;
@@ -237,7 +716,7 @@ impl<'a> Parser<'a> {
         loop {
             let col = self.next_ident()?;
             self.next_expect(Token::Equal)?;
-            let value = self.parse_expression()?;
+            let value = self.compute_math_operator(1)?;
             if columns.contains_key(&col) {
                 return Err(Error::Parse(format!("[parser] Duplicate column {} for update", col)));
             }
@@ -248,9 +727,14 @@ impl<'a> Parser<'a> {
             }
         }
 
+        let from = match self.peek()? {
+            Some(Token::Keyword(Keyword::From)) => Some(self.parse_from_clause()?),
+            _ => None,
+        };
         Ok(ast::Statement::Update {
             table_name,
             columns,
+            from,
             where_clause: self.parse_where_clause()?,
         })
     }
@@ -261,9 +745,14 @@ impl<'a> Parser<'a> {
         self.next_expect(Token::Keyword(Keyword::From))?;
         // 表名
         let table_name = self.next_ident()?;
+        let using = match self.next_if_token(Token::Keyword(Keyword::Using)) {
+            Some(_) => Some(self.parse_from_items()?),
+            None => None,
+        };
 
         Ok(ast::Statement::Delete {
             table_name,
+            using,
             where_clause: self.parse_where_clause()?,
         })
     }
@@ -271,25 +760,181 @@ impl<'a> Parser<'a> {
     // 解析事务语句
     fn parse_transaction(&mut self) -> Result<ast::Statement> {
         Ok(match self.next()? {
-            Token::Keyword(Keyword::Begin) => ast::Statement::Begin,
+            Token::Keyword(Keyword::Begin) => {
+                self.next_if_token(Token::Ident("transaction".into()));
+                let isolation = self.parse_isolation_level()?;
+                let mut read_only = false;
+                let mut as_of = None;
+                if isolation.is_none() && self.next_if_token(Token::Ident("read".into())).is_some() {
+                    self.next_expect(Token::Ident("only".into()))?;
+                    read_only = true;
+                    // as of version n 读取历史版本
+                    if self.next_if_token(Token::Keyword(Keyword::As)).is_some() {
+                        self.next_expect(Token::Ident("of".into()))?;
+                        self.next_expect(Token::Ident("version".into()))?;
+                        as_of = match self.next()? {
+                            Token::Number(n) => Some(n.parse::<u64>()?),
+                            token => {
+                                return Err(Error::Parse(format!(
+                                    "[Parser] Unexpected token {}",
+                                    token
+                                )))
+                            }
+                        };
+                    }
+                }
+                ast::Statement::Begin {
+                    isolation,
+                    read_only,
+                    as_of,
+                }
+            }
             Token::Keyword(Keyword::Commit) => ast::Statement::Commit,
-            Token::Keyword(Keyword::Rollback) => ast::Statement::Rollback,
+            // rollback to [savepoint] name 回滚到保存点
+            Token::Keyword(Keyword::Rollback) => match self.next_if_token(Token::Ident("to".into())) {
+                Some(_) => {
+                    self.next_if_token(Token::Keyword(Keyword::Savepoint));
+                    ast::Statement::RollbackToSavepoint {
+                        name: self.next_ident()?,
+                    }
+                }
+                None => ast::Statement::Rollback,
+            },
+            Token::Keyword(Keyword::Savepoint) => ast::Statement::Savepoint {
+                name: self.next_ident()?,
+            },
+            Token::Keyword(Keyword::Release) => {
+                self.next_if_token(Token::Keyword(Keyword::Savepoint));
+                ast::Statement::ReleaseSavepoint {
+                    name: self.next_ident()?,
+                }
+            }
             _ => return Err(Error::Parse("unknown transaction command".into())),
         })
     }
 
+    // 解析 isolation level read committed | repeatable read | serializable
+    fn parse_isolation_level(&mut self) -> Result<Option<IsolationLevel>> {
+        if self.next_if_token(Token::Ident("isolation".into())).is_none() {
+            return Ok(None);
+        }
+        self.next_expect(Token::Ident("level".into()))?;
+        let isolation = match self.next()? {
+            Token::Ident(s) if s == "read" => {
+                self.next_expect(Token::Ident("committed".into()))?;
+                IsolationLevel::ReadCommitted
+            }
+            Token::Ident(s) if s == "repeatable" => {
+                self.next_expect(Token::Ident("read".into()))?;
+                IsolationLevel::RepeatableRead
+            }
+            Token::Ident(s) if s == "serializable" => IsolationLevel::Serializable,
+            token => {
+                return Err(Error::Parse(format!(
+                    "[Parser] Unexpected isolation level {}",
+                    token
+                )))
+            }
+        };
+        Ok(Some(isolation))
+    }
+
     // 解析 explain 语句
     fn parse_explain(&mut self) -> Result<ast::Statement> {
         self.next_expect(Token::Keyword(Keyword::Explain))?;
+        // explain (format text | dot)
+        let mut format = ast::ExplainFormat::Text;
+        if self.next_if_token(Token::OpenParen).is_some() {
+            self.next_expect(Token::Ident("format".into()))?;
+            format = match self.next()? {
+                Token::Keyword(Keyword::Text) => ast::ExplainFormat::Text,
+                Token::Ident(ident) if ident == "dot" => ast::ExplainFormat::Dot,
+                t => return Err(Error::Parse(format!("[Parser] Unknown explain format {}", t))),
+            };
+            self.next_expect(Token::CloseParen)?;
+        }
+        // explain analyze 实际执行语句，只支持文本格式
+        let analyze = self
+            .next_if_token(Token::Ident("analyze".into()))
+            .is_some();
+        if analyze && format != ast::ExplainFormat::Text {
+            return Err(Error::Parse(
+                "[Parser] explain analyze only supports text format".into(),
+            ));
+        }
         if let Some(Token::Keyword(Keyword::Explain)) = self.peek()? {
             return Err(Error::Parse("canno nest explain statement".into()));
         }
         let stmt = self.parse_statement()?;
         Ok(ast::Statement::Explain {
             stmt: Box::new(stmt),
+            format,
+            analyze,
+        })
+    }
+
+    // 解析 declare cursor 语句
+    // declare c cursor for select ...
+    fn parse_declare_cursor(&mut self) -> Result<ast::Statement> {
+        self.next_expect(Token::Keyword(Keyword::Declare))?;
+        let name = self.next_ident()?;
+        self.next_expect(Token::Keyword(Keyword::Cursor))?;
+        self.next_expect(Token::Keyword(Keyword::For))?;
+        if self.peek()? != Some(Token::Keyword(Keyword::Select)) {
+            return Err(Error::Parse("[Parser] Cursor must be declared for a select statement".into()));
+        }
+        Ok(ast::Statement::DeclareCursor {
+            name,
+            query: Box::new(self.parse_select()?),
+        })
+    }
+
+    // 解析 fetch 语句
+    // fetch [next | all | count] [from] c
+    fn parse_fetch(&mut self) -> Result<ast::Statement> {
+        self.next_expect(Token::Keyword(Keyword::Fetch))?;
+        let count = match self.peek()? {
+            Some(Token::Keyword(Keyword::All)) => {
+                self.next()?;
+                None
+            }
+            Some(Token::Number(n)) => {
+                self.next()?;
+                Some(n.parse()?)
+            }
+            Some(Token::Ident(ident)) if ident == "next" => {
+                self.next()?;
+                Some(1)
+            }
+            _ => Some(1),
+        };
+        self.next_if_token(Token::Keyword(Keyword::From));
+        Ok(ast::Statement::Fetch {
+            name: self.next_ident()?,
+            count,
         })
     }
 
+    // 解析 set 语句
+    // set strict_types = on;
+    fn parse_set(&mut self) -> Result<ast::Statement> {
+        self.next_expect(Token::Keyword(Keyword::Set))?;
+        let name = self.next_ident()?;
+        self.next_expect(Token::Equal)?;
+        let value = match self.next()? {
+            Token::Keyword(Keyword::On) | Token::Keyword(Keyword::True) => Value::Boolean(true),
+            Token::Ident(ident) if ident == "off" => Value::Boolean(false),
+            Token::Keyword(Keyword::False) => Value::Boolean(false),
+            Token::Number(n) if n.chars().all(|c| c.is_ascii_digit()) => {
+                Value::Integer(n.parse()?)
+            }
+            Token::Number(n) => Value::Float(n.parse()?),
+            Token::String(s) | Token::Ident(s) => Value::String(s),
+            token => return Err(Error::Parse(format!("[Parser] Unexpected token {}", token))),
+        };
+        Ok(ast::Statement::Set { name, value })
+    }
+
     fn parse_where_clause(&mut self) -> Result<Option<Expression>> {
         if self.next_if_token(Token::Keyword(Keyword::Where)).is_none() {
             return Ok(None);
@@ -309,7 +954,8 @@ impl<'a> Parser<'a> {
         Ok(Some(self.parse_operation_expr()?))
     }
 
-    fn parse_order_clause(&mut self) -> Result<Vec<(String, OrderDirection)>> {
+    // order by 后面可以是列名、select 中的别名、select 中的位置（从 1 开始）或者表达式
+    fn parse_order_clause(&mut self) -> Result<Vec<(Expression, OrderDirection)>> {
         let mut orders = Vec::new();
         if self.next_if_token(Token::Keyword(Keyword::Order)).is_none() {
             return Ok(orders);
@@ -317,7 +963,7 @@ impl<'a> Parser<'a> {
         self.next_expect(Token::Keyword(Keyword::By))?;
 
         loop {
-            let col = self.next_ident()?;
+            let expr = self.compute_math_operator(1)?;
             let ord = match self.next_if(|t| {
                 matches!(
                     t,
@@ -328,7 +974,7 @@ impl<'a> Parser<'a> {
                 Some(Token::Keyword(Keyword::Desc)) => OrderDirection::Desc,
                 _ => OrderDirection::Asc,
             };
-            orders.push((col, ord));
+            orders.push((expr, ord));
 
             if self.next_if_token(Token::Comma).is_none() {
                 break;
@@ -339,8 +985,6 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_select_clause(&mut self) -> Result<Vec<(Expression, Option<String>)>> {
-        self.next_expect(Token::Keyword(Keyword::Select))?;
-
         let mut select = Vec::new();
         // select * 情况;
         if self.next_if_token(Token::Asterisk).is_some() {
@@ -348,7 +992,7 @@ impl<'a> Parser<'a> {
         }
 
         loop {
-            let expr = self.parse_expression()?;
+            let expr = self.compute_math_operator(1)?;
             // 查看是否有别名
             let alias = match self.next_if_token(Token::Keyword(Keyword::As)) {
                 Some(_) => Some(self.next_ident()?),
@@ -366,7 +1010,11 @@ impl<'a> Parser<'a> {
     fn parse_from_clause(&mut self) -> Result<ast::FromItem> {
         // From 关键字
         self.next_expect(Token::Keyword(Keyword::From))?;
+        self.parse_from_items()
+    }
 
+    // from 中的表以及 join，delete 的 using 也使用相同的语法
+    fn parse_from_items(&mut self) -> Result<ast::FromItem> {
         // 第一个表名
         let mut item = self.parse_from_table_clause()?;
         // 是否有 Join
@@ -380,7 +1028,17 @@ impl<'a> Parser<'a> {
                 _ => {
                     self.next_expect(Token::Keyword(Keyword::On))?;
                     let l = self.parse_expression()?;
-                    self.next_expect(Token::Equal)?;
+                    // on 条件支持 = 和 IS [NOT] DISTINCT FROM
+                    let distinct = match self.next()? {
+                        Token::Equal => None,
+                        Token::Keyword(Keyword::Is) => Some(self.parse_distinct_from()?),
+                        t => {
+                            return Err(Error::Parse(format!(
+                                "[Parser] Unexpected join condition token {}",
+                                t
+                            )))
+                        }
+                    };
                     let r = self.parse_expression()?;
 
                     let (l, r) = match join_type {
@@ -388,7 +1046,11 @@ impl<'a> Parser<'a> {
                         _ => (l, r),
                     };
 
-                    let cond = Operation::Equal(Box::new(l), Box::new(r));
+                    let cond = match distinct {
+                        None => Operation::Equal(Box::new(l), Box::new(r)),
+                        Some(false) => Operation::IsDistinctFrom(Box::new(l), Box::new(r)),
+                        Some(true) => Operation::IsNotDistinctFrom(Box::new(l), Box::new(r)),
+                    };
                     Some(ast::Expression::Operation(cond))
                 }
             };
@@ -404,21 +1066,117 @@ impl<'a> Parser<'a> {
         Ok(item)
     }
 
-    fn parse_group_clause(&mut self) -> Result<Option<Expression>> {
+    fn parse_group_clause(&mut self) -> Result<Vec<Expression>> {
+        let mut group_by = Vec::new();
         if self.next_if_token(Token::Keyword(Keyword::Group)).is_none() {
-            return Ok(None);
+            return Ok(group_by);
         }
 
         self.next_expect(Token::Keyword(Keyword::By))?;
-        Ok(Some(self.parse_expression()?))
+        // GROUP BY a, b 按照多个列的值组合分组
+        loop {
+            group_by.push(self.parse_expression()?);
+            if self.next_if_token(Token::Comma).is_none() {
+                break;
+            }
+        }
+        Ok(group_by)
     }
 
     fn parse_from_table_clause(&mut self) -> Result<ast::FromItem> {
+        // 子查询
+        if self.next_if_token(Token::OpenParen).is_some() {
+            let query = self.parse_select()?;
+            self.next_expect(Token::CloseParen)?;
+            self.next_if_token(Token::Keyword(Keyword::As));
+            let alias = self.next_ident()?;
+            return Ok(ast::FromItem::Derived {
+                query: Box::new(query),
+                alias,
+            });
+        }
+
+        // 带有 schema 的表名，例如 sys.storage
+        let ident = self.next_ident()?;
+        // unnest(expr) [as alias]
+        if ident == "unnest" && self.next_if_token(Token::OpenParen).is_some() {
+            let expr = self.compute_math_operator(1)?;
+            self.next_expect(Token::CloseParen)?;
+            self.next_if_token(Token::Keyword(Keyword::As));
+            let alias = match self.next_if(is_implicit_alias) {
+                Some(Token::Ident(alias)) => alias,
+                _ => ident,
+            };
+            return Ok(ast::FromItem::Unnest { expr, alias });
+        }
+        let name = self.parse_qualified_name(ident)?;
+        // 表的别名，AS 可以省略
+        let alias = match self.next_if_token(Token::Keyword(Keyword::As)) {
+            Some(_) => Some(self.next_ident()?),
+            None => match self.next_if(is_implicit_alias) {
+                Some(Token::Ident(alias)) => Some(alias),
+                _ => None,
+            },
+        };
         Ok(ast::FromItem::Table {
-            name: self.next_ident()?,
+            name,
+            alias,
+            sample: self.parse_table_sample()?,
         })
     }
 
+    // tablesample (percent percent) [repeatable (seed)]
+    fn parse_table_sample(&mut self) -> Result<Option<ast::TableSample>> {
+        if self
+            .next_if(|t| matches!(t, Token::Ident(i) if i == "tablesample"))
+            .is_none()
+        {
+            return Ok(None);
+        }
+        self.next_expect(Token::OpenParen)?;
+        let percent = match self.next()? {
+            Token::Number(n) => n.parse::<f64>()?,
+            token => return Err(Error::Parse(format!("[Parser] Unexpected token {}", token))),
+        };
+        if !(0.0..=100.0).contains(&percent) {
+            return Err(Error::Parse(format!(
+                "[Parser] sample percentage {} must be between 0 and 100",
+                percent
+            )));
+        }
+        self.next_expect(Token::Ident("percent".into()))?;
+        self.next_expect(Token::CloseParen)?;
+
+        let seed = match self.next_if(|t| matches!(t, Token::Ident(i) if i == "repeatable")) {
+            Some(_) => {
+                self.next_expect(Token::OpenParen)?;
+                let seed = match self.next()? {
+                    Token::Number(n) => n.parse::<u64>()?,
+                    token => {
+                        return Err(Error::Parse(format!("[Parser] Unexpected token {}", token)))
+                    }
+                };
+                self.next_expect(Token::CloseParen)?;
+                Some(seed)
+            }
+            None => None,
+        };
+        Ok(Some(ast::TableSample { percent, seed }))
+    }
+
+    // 列名，可以带有表名前缀，例如 t1.a、sys.storage.keys
+    fn parse_column_name(&mut self) -> Result<String> {
+        let ident = self.next_ident()?;
+        self.parse_qualified_name(ident)
+    }
+
+    fn parse_qualified_name(&mut self, mut name: String) -> Result<String> {
+        while self.next_if_token(Token::Period).is_some() {
+            name = format!("{}.{}", name, self.next_ident()?);
+        }
+        Ok(name)
+    }
+
     fn parse_from_clause_join(&mut self) -> Result<Option<ast::JoinType>> {
         // 是否是 Cross Join
         if self.next_if_token(Token::Keyword(Keyword::Cross)).is_some() {
@@ -437,40 +1195,271 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // 解析条件表达式，优先级从低到高依次是 OR、AND、NOT、比较运算
     fn parse_operation_expr(&mut self) -> Result<ast::Expression> {
-        let left = self.parse_expression()?;
+        let mut left = self.parse_and_expr()?;
+        while self.next_if_token(Token::Keyword(Keyword::Or)).is_some() {
+            let right = self.parse_and_expr()?;
+            left = ast::Expression::Operation(Operation::Or(Box::new(left), Box::new(right)));
+        }
+        Ok(left)
+    }
+
+    fn parse_and_expr(&mut self) -> Result<ast::Expression> {
+        let mut left = self.parse_not_expr()?;
+        while self.next_if_token(Token::Keyword(Keyword::And)).is_some() {
+            let right = self.parse_not_expr()?;
+            left = ast::Expression::Operation(Operation::And(Box::new(left), Box::new(right)));
+        }
+        Ok(left)
+    }
+
+    fn parse_not_expr(&mut self) -> Result<ast::Expression> {
+        if self.next_if_token(Token::Keyword(Keyword::Not)).is_some() {
+            let expr = self.parse_not_expr()?;
+            return Ok(ast::Expression::Operation(Operation::Not(Box::new(expr))));
+        }
+        self.parse_compare_expr()
+    }
+
+    // 比较运算，没有比较运算符时表达式本身作为条件，例如布尔类型的列、match(col, 'words')
+    fn parse_compare_expr(&mut self) -> Result<ast::Expression> {
+        let left = self.compute_math_operator(1)?;
+        let compare = matches!(
+            self.peek()?,
+            Some(
+                Token::Equal
+                    | Token::GreaterThan
+                    | Token::LessThan
+                    | Token::Keyword(Keyword::Is)
+                    | Token::Keyword(Keyword::Like)
+                    | Token::Keyword(Keyword::Between)
+                    | Token::Keyword(Keyword::In)
+                    | Token::Keyword(Keyword::Not)
+            )
+        );
+        if !compare {
+            return Ok(left);
+        }
+        // a NOT LIKE、NOT IN、NOT BETWEEN 解析成 NOT (a LIKE ...)
+        if self.next_if_token(Token::Keyword(Keyword::Not)).is_some() {
+            let expr = match self.next()? {
+                Token::Keyword(Keyword::Like) => self.parse_like(left)?,
+                Token::Keyword(Keyword::In) => self.parse_in(left)?,
+                Token::Keyword(Keyword::Between) => self.parse_between(left)?,
+                t => {
+                    return Err(Error::Parse(format!(
+                        "[Parser] Unexpected token {} after NOT",
+                        t
+                    )))
+                }
+            };
+            return Ok(ast::Expression::Operation(Operation::Not(Box::new(expr))));
+        }
         Ok(match self.next()? {
-            Token::Equal => ast::Expression::Operation(Operation::Equal(
-                Box::new(left),
-                Box::new(self.compute_math_operator(1)?),
-            )),
-            Token::GreaterThan => ast::Expression::Operation(Operation::GreaterThan(
-                Box::new(left),
-                Box::new(self.compute_math_operator(1)?),
-            )),
-            Token::LessThan => ast::Expression::Operation(Operation::LessThan(
-                Box::new(left),
-                Box::new(self.compute_math_operator(1)?),
-            )),
+            Token::Equal => {
+                let right = self.parse_compare_operand(&left)?;
+                ast::Expression::Operation(Operation::Equal(Box::new(left), Box::new(right)))
+            }
+            Token::GreaterThan => {
+                let right = self.parse_compare_operand(&left)?;
+                ast::Expression::Operation(Operation::GreaterThan(Box::new(left), Box::new(right)))
+            }
+            Token::LessThan => {
+                let right = self.parse_compare_operand(&left)?;
+                ast::Expression::Operation(Operation::LessThan(Box::new(left), Box::new(right)))
+            }
+            Token::Keyword(Keyword::Is) => {
+                let not = self.parse_distinct_from()?;
+                let right = self.parse_compare_operand(&left)?;
+                let (left, right) = (Box::new(left), Box::new(right));
+                ast::Expression::Operation(match not {
+                    true => Operation::IsNotDistinctFrom(left, right),
+                    false => Operation::IsDistinctFrom(left, right),
+                })
+            }
+            Token::Keyword(Keyword::Like) => self.parse_like(left)?,
+            Token::Keyword(Keyword::In) => self.parse_in(left)?,
+            Token::Keyword(Keyword::Between) => self.parse_between(left)?,
             _ => return Err(Error::Internal("Unexpected token".into())),
         })
     }
 
-    // 解析表达式
-    fn parse_expression(&mut self) -> Result<ast::Expression> {
-        Ok(match self.next()? {
+    // LIKE 之后的模式，LIKE 已经被解析
+    fn parse_like(&mut self, left: ast::Expression) -> Result<ast::Expression> {
+        let pattern = self.compute_math_operator(1)?;
+        Ok(ast::Expression::Operation(Operation::Like(
+            Box::new(left),
+            Box::new(pattern),
+        )))
+    }
+
+    // IN 之后括号中的值列表或者子查询，IN 已经被解析
+    fn parse_in(&mut self, left: ast::Expression) -> Result<ast::Expression> {
+        self.next_expect(Token::OpenParen)?;
+        if let Some(Token::Keyword(Keyword::Select)) = self.peek()? {
+            let query = self.parse_select()?;
+            self.next_expect(Token::CloseParen)?;
+            return Ok(ast::Expression::Operation(Operation::InSubquery(
+                Box::new(left),
+                Box::new(query),
+            )));
+        }
+        let mut list = Vec::new();
+        loop {
+            list.push(self.compute_math_operator(1)?);
+            if self.next_if_token(Token::Comma).is_none() {
+                break;
+            }
+        }
+        self.next_expect(Token::CloseParen)?;
+        Ok(ast::Expression::Operation(Operation::In(Box::new(left), list)))
+    }
+
+    // BETWEEN low AND high，BETWEEN 已经被解析，这里的 AND 不是逻辑运算
+    fn parse_between(&mut self, left: ast::Expression) -> Result<ast::Expression> {
+        let low = self.compute_math_operator(1)?;
+        self.next_expect(Token::Keyword(Keyword::And))?;
+        let high = self.compute_math_operator(1)?;
+        Ok(ast::Expression::Operation(Operation::Between(
+            Box::new(left),
+            Box::new(low),
+            Box::new(high),
+        )))
+    }
+
+    // 解析比较运算符右边的表达式，两边都是行值时元素个数必须相同
+    fn parse_compare_operand(&mut self, left: &ast::Expression) -> Result<ast::Expression> {
+        let right = self.compute_math_operator(1)?;
+        if let (ast::Expression::Row(l), ast::Expression::Row(r)) = (left, &right) {
+            if l.len() != r.len() {
+                return Err(Error::Parse(format!(
+                    "[Parser] Row value {} and {} have different number of elements",
+                    left, right
+                )));
+            }
+        }
+        Ok(right)
+    }
+
+    // 解析 IS 之后的 [NOT] DISTINCT FROM，返回是否带有 NOT
+    fn parse_distinct_from(&mut self) -> Result<bool> {
+        let not = self.next_if_token(Token::Keyword(Keyword::Not)).is_some();
+        self.next_expect(Token::Keyword(Keyword::Distinct))?;
+        self.next_expect(Token::Keyword(Keyword::From))?;
+        Ok(not)
+    }
+
+    // 解析表达式，以及后面的数组下标，例如 tags[1]
+    fn parse_expression(&mut self) -> Result<ast::Expression> {
+        let mut expr = self.parse_term()?;
+        while self.next_if_token(Token::OpenBracket).is_some() {
+            let index = self.compute_math_operator(1)?;
+            self.next_expect(Token::CloseBracket)?;
+            expr = ast::Expression::Index(Box::new(expr), Box::new(index));
+        }
+        Ok(expr)
+    }
+
+    // 数组的元素，[ 已经被解析
+    fn parse_array(&mut self) -> Result<ast::Expression> {
+        let mut items = Vec::new();
+        if self.next_if_token(Token::CloseBracket).is_none() {
+            loop {
+                items.push(self.compute_math_operator(1)?);
+                if self.next_if_token(Token::Comma).is_none() {
+                    break;
+                }
+            }
+            self.next_expect(Token::CloseBracket)?;
+        }
+        Ok(ast::Expression::Array(items))
+    }
+
+    fn parse_term(&mut self) -> Result<ast::Expression> {
+        Ok(match self.next()? {
+            // ARRAY[1, 2]
+            Token::Ident(ident)
+                if ident == "array" && self.next_if_token(Token::OpenBracket).is_some() =>
+            {
+                self.parse_array()?
+            }
+            // CAST(expr AS type)
+            Token::Ident(ident)
+                if ident == "cast" && self.next_if_token(Token::OpenParen).is_some() =>
+            {
+                let expr = self.compute_math_operator(1)?;
+                self.next_expect(Token::Keyword(Keyword::As))?;
+                let datatype = self.parse_ddl_datatype()?;
+                self.next_expect(Token::CloseParen)?;
+                ast::Expression::Cast(Box::new(expr), datatype)
+            }
+            // decimal '12.50'
+            Token::Ident(ident)
+                if matches!(ident.as_str(), "decimal" | "numeric")
+                    && matches!(self.peek()?, Some(Token::String(_))) =>
+            {
+                match self.next()? {
+                    Token::String(s) => ast::Consts::Decimal(Decimal::parse(&s)?).into(),
+                    _ => unreachable!(),
+                }
+            }
+            // EXISTS (SELECT ...)
+            Token::Ident(ident)
+                if ident == "exists" && self.next_if_token(Token::OpenParen).is_some() =>
+            {
+                let query = self.parse_select()?;
+                self.next_expect(Token::CloseParen)?;
+                ast::Expression::Exists(Box::new(query))
+            }
             Token::Ident(ident) => {
-                // 函数
+                // 标量函数
+                // coalesce(a, 0)
+                if functions::is_scalar(&ident) && self.next_if_token(Token::OpenParen).is_some() {
+                    let mut args = Vec::new();
+                    // extract(year FROM ts)
+                    if ident == "extract" {
+                        args.push(ast::Consts::String(self.next_ident()?).into());
+                        self.next_expect(Token::Keyword(Keyword::From))?;
+                        args.push(self.compute_math_operator(1)?);
+                        self.next_expect(Token::CloseParen)?;
+                    } else if self.next_if_token(Token::CloseParen).is_none() {
+                        loop {
+                            args.push(self.compute_math_operator(1)?);
+                            if self.next_if_token(Token::Comma).is_none() {
+                                break;
+                            }
+                        }
+                        self.next_expect(Token::CloseParen)?;
+                    }
+                    functions::check_args(&ident, args.len())?;
+                    ast::Expression::Call(ident, args)
+                }
+                // 不带括号的 current_date、current_timestamp
+                else if matches!(ident.as_str(), "current_date" | "current_timestamp") {
+                    ast::Expression::Call(ident, Vec::new())
+                }
+                // 聚集函数
                 // count(col_name)
-                if self.next_if_token(Token::OpenParen).is_some() {
-                    let col_name = self.next_ident()?;
+                else if self.next_if_token(Token::OpenParen).is_some() {
+                    let col_name = self.parse_column_name()?;
                     self.next_expect(Token::CloseParen)?;
                     ast::Expression::Function(ident, col_name)
                 } else {
-                    // 列名
-                    ast::Expression::Field(ident)
+                    // 列名，可以带有表名前缀，例如 t1.a、sys.storage.keys
+                    let name = self.parse_qualified_name(ident)?;
+                    match name.rsplit_once('.') {
+                        Some((table, col)) => {
+                            ast::Expression::Field(Some(table.into()), col.into())
+                        }
+                        None => ast::Expression::Field(None, name),
+                    }
                 }
             }
+            Token::Param(n) => match n.checked_sub(1).and_then(|i| self.params.get(i)) {
+                Some(value) => value.clone().into(),
+                None => return Err(Error::Parse(format!("[Parser] No value for parameter ${}", n))),
+            },
             Token::Number(n) => {
                 if n.chars().all(|c| c.is_ascii_digit()) {
                     // 整数
@@ -480,15 +1469,66 @@ impl<'a> Parser<'a> {
                     ast::Consts::Float(n.parse()?).into()
                 }
             }
+            // 括号中是 SELECT 时为标量子查询
+            Token::OpenParen if self.peek()? == Some(Token::Keyword(Keyword::Select)) => {
+                let query = self.parse_select()?;
+                self.next_expect(Token::CloseParen)?;
+                ast::Expression::Subquery(Box::new(query))
+            }
+            // 括号中可以是条件表达式，例如 (a = 1 OR b = 2)
             Token::OpenParen => {
-                let expr = self.compute_math_operator(1)?;
+                let expr = self.parse_operation_expr()?;
+                // 多个表达式是行值，例如 (a, b)
+                if self.next_if_token(Token::Comma).is_some() {
+                    let mut items = vec![expr];
+                    loop {
+                        items.push(self.compute_math_operator(1)?);
+                        if self.next_if_token(Token::Comma).is_none() {
+                            break;
+                        }
+                    }
+                    self.next_expect(Token::CloseParen)?;
+                    return Ok(ast::Expression::Row(items));
+                }
                 self.next_expect(Token::CloseParen)?;
                 expr
             }
             Token::String(s) => ast::Consts::String(s).into(),
+            Token::Blob(b) => ast::Consts::Blob(b).into(),
+            Token::OpenBracket => self.parse_array()?,
             Token::Keyword(Keyword::True) => ast::Consts::Boolean(true).into(),
             Token::Keyword(Keyword::False) => ast::Consts::Boolean(false).into(),
             Token::Keyword(Keyword::Null) => ast::Consts::Null.into(),
+            // interval '1 day'、interval '1' day、interval 1 day
+            Token::Keyword(Keyword::Interval) => {
+                let mut text = match self.next()? {
+                    Token::String(s) | Token::Number(s) => s,
+                    t => {
+                        return Err(Error::Parse(format!(
+                            "[Parser] Unexpected interval token {}",
+                            t
+                        )))
+                    }
+                };
+                if text.trim().chars().all(|c| c.is_ascii_digit() || c == '-') {
+                    text = format!("{} {}", text, self.next_ident()?);
+                }
+                ast::Consts::Interval(Interval::parse(&text)?).into()
+            }
+            // date '2024-01-01'、timestamp '2024-01-01 10:00:00'
+            Token::Keyword(Keyword::Date) => match self.next()? {
+                Token::String(s) => ast::Consts::Date(temporal::parse_date(&s)?).into(),
+                t => return Err(Error::Parse(format!("[Parser] Unexpected date token {}", t))),
+            },
+            Token::Keyword(Keyword::Timestamp) => match self.next()? {
+                Token::String(s) => ast::Consts::Timestamp(temporal::parse_timestamp(&s)?).into(),
+                t => {
+                    return Err(Error::Parse(format!(
+                        "[Parser] Unexpected timestamp token {}",
+                        t
+                    )))
+                }
+            },
             t => {
                 return Err(Error::Parse(format!(
                     "[Parser] Unexpected expression token {}",
@@ -503,13 +1543,8 @@ impl<'a> Parser<'a> {
     // 5 + 2 * 1
     fn compute_math_operator(&mut self, min_prec: i32) -> Result<Expression> {
         let mut left = self.parse_expression()?;
-        loop {
-            // 当前 Token
-            let token = match self.peek()? {
-                Some(t) => t,
-                None => break,
-            };
-
+        // 当前 Token
+        while let Some(token) = self.peek()? {
             if !token.is_operator() || token.precedence() < min_prec {
                 break;
             }
@@ -532,7 +1567,7 @@ impl<'a> Parser<'a> {
     fn next(&mut self) -> Result<Token> {
         self.lexer
             .next()
-            .unwrap_or_else(|| Err(Error::Parse(format!("[Parser] Unexpected end of input"))))
+            .unwrap_or_else(|| Err(Error::Parse("[Parser] Unexpected end of input".to_string())))
     }
 
     fn next_ident(&mut self) -> Result<String> {
@@ -572,11 +1607,20 @@ impl<'a> Parser<'a> {
     }
 }
 
+// 省略 AS 的表别名只能是普通的标识符
+fn is_implicit_alias(token: &Token) -> bool {
+    matches!(token, Token::Ident(i) if !RESERVED_ALIASES.contains(&i.as_str()))
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
         error::Result,
-        sql::parser::ast::{self, Consts, Expression, OrderDirection},
+        sql::{
+            parser::ast::{self, Consts, Expression, OrderDirection},
+            schema::IndexType,
+            types::{DataType, Value},
+        },
     };
 
     use super::Parser;
@@ -659,6 +1703,32 @@ mod tests {
             }
         );
 
+        let sql3 = "insert into tbl3 values (1, default, 'x');";
+        let stmt3 = Parser::new(sql3).parse()?;
+        assert_eq!(
+            stmt3,
+            ast::Statement::Insert {
+                table_name: "tbl3".to_string(),
+                columns: None,
+                values: vec![vec![
+                    ast::Consts::Integer(1).into(),
+                    ast::Expression::Default,
+                    ast::Consts::String("x".to_string()).into(),
+                ]],
+            }
+        );
+
+        let sql4 = "insert into tbl4 default values;";
+        let stmt4 = Parser::new(sql4).parse()?;
+        assert_eq!(
+            stmt4,
+            ast::Statement::Insert {
+                table_name: "tbl4".to_string(),
+                columns: None,
+                values: vec![vec![]],
+            }
+        );
+
         Ok(())
     }
 
@@ -669,15 +1739,18 @@ mod tests {
         assert_eq!(
             stmt,
             ast::Statement::Select {
+                distinct: false,
                 select: vec![],
                 from: ast::FromItem::Table {
-                    name: "tbl1".into()
+                    name: "tbl1".into(),
+                    alias: None,
+                    sample: None,
                 },
                 where_clause: Some(ast::Expression::Operation(ast::Operation::Equal(
-                    Box::new(ast::Expression::Field("a".into())),
+                    Box::new(ast::Expression::Field(None, "a".into())),
                     Box::new(ast::Expression::Consts(Consts::Integer(100)))
                 ))),
-                group_by: None,
+                group_by: vec![],
                 having: None,
                 order_by: vec![],
                 limit: Some(Expression::Consts(Consts::Integer(10))),
@@ -685,21 +1758,77 @@ mod tests {
             }
         );
 
+        let sql = "select * from tbl1 where a is not distinct from null;";
+        let stmt = Parser::new(sql).parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::Select {
+                distinct: false,
+                select: vec![],
+                from: ast::FromItem::Table {
+                    name: "tbl1".into(),
+                    alias: None,
+                    sample: None,
+                },
+                where_clause: Some(ast::Expression::Operation(
+                    ast::Operation::IsNotDistinctFrom(
+                        Box::new(ast::Expression::Field(None, "a".into())),
+                        Box::new(ast::Expression::Consts(Consts::Null))
+                    )
+                )),
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+                limit: None,
+                offset: None,
+            }
+        );
+        assert!(Parser::new("select * from tbl1 where a is distinct null;")
+            .parse()
+            .is_err());
+
+        let sql = "select * from tbl1 where name like 'ab%';";
+        let stmt = Parser::new(sql).parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::Select {
+                distinct: false,
+                select: vec![],
+                from: ast::FromItem::Table {
+                    name: "tbl1".into(),
+                    alias: None,
+                    sample: None,
+                },
+                where_clause: Some(ast::Expression::Operation(ast::Operation::Like(
+                    Box::new(ast::Expression::Field(None, "name".into())),
+                    Box::new(ast::Expression::Consts(Consts::String("ab%".into())))
+                ))),
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+                limit: None,
+                offset: None,
+            }
+        );
+
         let sql = "select * from tbl1 order by a, b asc, c desc;";
         let stmt = Parser::new(sql).parse()?;
         assert_eq!(
             stmt,
             ast::Statement::Select {
+                distinct: false,
                 select: vec![],
                 from: ast::FromItem::Table {
-                    name: "tbl1".into()
+                    name: "tbl1".into(),
+                    alias: None,
+                    sample: None,
                 },
                 where_clause: None,
-                group_by: None,
+                group_by: vec![],
                 order_by: vec![
-                    ("a".to_string(), OrderDirection::Asc),
-                    ("b".to_string(), OrderDirection::Asc),
-                    ("c".to_string(), OrderDirection::Desc),
+                    (Expression::Field(None, "a".into()), OrderDirection::Asc),
+                    (Expression::Field(None, "b".into()), OrderDirection::Asc),
+                    (Expression::Field(None, "c".into()), OrderDirection::Desc),
                 ],
                 having: None,
                 limit: None,
@@ -712,21 +1841,24 @@ mod tests {
         assert_eq!(
             stmt,
             ast::Statement::Select {
+                distinct: false,
                 select: vec![
-                    (Expression::Field("a".into()), Some("col1".into())),
-                    (Expression::Field("b".into()), Some("col2".into())),
-                    (Expression::Field("c".into()), None),
+                    (Expression::Field(None, "a".into()), Some("col1".into())),
+                    (Expression::Field(None, "b".into()), Some("col2".into())),
+                    (Expression::Field(None, "c".into()), None),
                 ],
                 from: ast::FromItem::Table {
-                    name: "tbl1".into()
+                    name: "tbl1".into(),
+                    alias: None,
+                    sample: None,
                 },
                 where_clause: None,
-                group_by: None,
+                group_by: vec![],
                 having: None,
                 order_by: vec![
-                    ("a".to_string(), OrderDirection::Asc),
-                    ("b".to_string(), OrderDirection::Asc),
-                    ("c".to_string(), OrderDirection::Desc),
+                    (Expression::Field(None, "a".into()), OrderDirection::Asc),
+                    (Expression::Field(None, "b".into()), OrderDirection::Asc),
+                    (Expression::Field(None, "c".into()), OrderDirection::Desc),
                 ],
                 limit: None,
                 offset: None,
@@ -738,26 +1870,33 @@ mod tests {
         assert_eq!(
             stmt,
             ast::Statement::Select {
+                distinct: false,
                 select: vec![],
                 from: ast::FromItem::Join {
                     left: Box::new(ast::FromItem::Join {
                         left: Box::new(ast::FromItem::Table {
-                            name: "tbl1".into()
+                            name: "tbl1".into(),
+                            alias: None,
+                            sample: None,
                         }),
                         right: Box::new(ast::FromItem::Table {
-                            name: "tbl2".into()
+                            name: "tbl2".into(),
+                            alias: None,
+                            sample: None,
                         }),
                         join_type: ast::JoinType::Cross,
                         predicate: None
                     }),
                     right: Box::new(ast::FromItem::Table {
-                        name: "tbl3".into()
+                        name: "tbl3".into(),
+                        alias: None,
+                        sample: None,
                     }),
                     join_type: ast::JoinType::Cross,
                     predicate: None
                 },
                 where_clause: None,
-                group_by: None,
+                group_by: vec![],
                 having: None,
                 order_by: vec![],
                 limit: None,
@@ -770,18 +1909,21 @@ mod tests {
         assert_eq!(
             stmt,
             ast::Statement::Select {
+                distinct: false,
                 select: vec![
                     (ast::Expression::Function("count".into(), "a".into()), None),
                     (ast::Expression::Function("min".into(), "b".into()), None),
                     (ast::Expression::Function("max".into(), "c".into()), None),
                 ],
                 from: ast::FromItem::Table {
-                    name: "tbl1".into()
+                    name: "tbl1".into(),
+                    alias: None,
+                    sample: None,
                 },
                 where_clause: None,
-                group_by: Some(ast::Expression::Field("a".into())),
+                group_by: vec![ast::Expression::Field(None, "a".into())],
                 having: Some(ast::Expression::Operation(ast::Operation::Equal(
-                    Box::new(ast::Expression::Field("min".into())),
+                    Box::new(ast::Expression::Field(None, "min".into())),
                     Box::new(ast::Expression::Consts(Consts::Integer(10)))
                 ))),
                 order_by: vec![],
@@ -790,6 +1932,128 @@ mod tests {
             }
         );
 
+        let sql = "select * from (select a from tbl1 where a > 1) as x;";
+        let stmt = Parser::new(sql).parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::Select {
+                distinct: false,
+                select: vec![],
+                from: ast::FromItem::Derived {
+                    query: Box::new(ast::Statement::Select {
+                        distinct: false,
+                        select: vec![(ast::Expression::Field(None, "a".into()), None)],
+                        from: ast::FromItem::Table {
+                            name: "tbl1".into(),
+                            alias: None,
+                            sample: None,
+                        },
+                        where_clause: Some(ast::Expression::Operation(
+                            ast::Operation::GreaterThan(
+                                Box::new(ast::Expression::Field(None, "a".into())),
+                                Box::new(ast::Expression::Consts(Consts::Integer(1)))
+                            )
+                        )),
+                        group_by: vec![],
+                        having: None,
+                        order_by: vec![],
+                        limit: None,
+                        offset: None,
+                    }),
+                    alias: "x".into(),
+                },
+                where_clause: None,
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+                limit: None,
+                offset: None,
+            }
+        );
+
+        // NOT 的优先级高于 AND，AND 高于 OR
+        let sql = "select * from tbl1 where a = 1 and b > 2 or not c = 3;";
+        let field = |name: &str| Box::new(ast::Expression::Field(None, name.into()));
+        let int = |i: i64| Box::new(ast::Expression::Consts(Consts::Integer(i)));
+        match Parser::new(sql).parse()? {
+            ast::Statement::Select { where_clause, .. } => assert_eq!(
+                where_clause,
+                Some(ast::Expression::Operation(ast::Operation::Or(
+                    Box::new(ast::Expression::Operation(ast::Operation::And(
+                        Box::new(ast::Expression::Operation(ast::Operation::Equal(
+                            field("a"),
+                            int(1)
+                        ))),
+                        Box::new(ast::Expression::Operation(ast::Operation::GreaterThan(
+                            field("b"),
+                            int(2)
+                        ))),
+                    ))),
+                    Box::new(ast::Expression::Operation(ast::Operation::Not(Box::new(
+                        ast::Expression::Operation(ast::Operation::Equal(field("c"), int(3)))
+                    )))),
+                )))
+            ),
+            _ => unreachable!(),
+        }
+
+        // BETWEEN 中的 AND 不是逻辑运算
+        let sql = "select * from tbl1 where a between 1 and 10 and b not in (1, 2);";
+        match Parser::new(sql).parse()? {
+            ast::Statement::Select { where_clause, .. } => assert_eq!(
+                where_clause,
+                Some(ast::Expression::Operation(ast::Operation::And(
+                    Box::new(ast::Expression::Operation(ast::Operation::Between(
+                        field("a"),
+                        int(1),
+                        int(10)
+                    ))),
+                    Box::new(ast::Expression::Operation(ast::Operation::Not(Box::new(
+                        ast::Expression::Operation(ast::Operation::In(
+                            field("b"),
+                            vec![*int(1), *int(2)]
+                        ))
+                    )))),
+                )))
+            ),
+            _ => unreachable!(),
+        }
+        assert!(Parser::new("select * from tbl1 where a in ();").parse().is_err());
+        assert!(Parser::new("select * from tbl1 where a not = 1;").parse().is_err());
+
+        let sql = "select * from tbl1 where (a, b) > (1, 'x');";
+        match Parser::new(sql).parse()? {
+            ast::Statement::Select { where_clause, .. } => assert_eq!(
+                where_clause,
+                Some(ast::Expression::Operation(ast::Operation::GreaterThan(
+                    Box::new(ast::Expression::Row(vec![
+                        ast::Expression::Field(None, "a".into()),
+                        ast::Expression::Field(None, "b".into()),
+                    ])),
+                    Box::new(ast::Expression::Row(vec![
+                        Consts::Integer(1).into(),
+                        Consts::String("x".into()).into(),
+                    ])),
+                )))
+            ),
+            _ => unreachable!(),
+        }
+        // 行值的元素个数必须相同
+        assert!(Parser::new("select * from tbl1 where (a, b) = (1, 2, 3);")
+            .parse()
+            .is_err());
+
+        // 不支持的 join 语法中的单词不能被当作表别名
+        for sql in [
+            "select * from t1 full join t2 on a = b;",
+            "select * from t1 natural join t2;",
+            "select * from t1 inner join t2 on a = b;",
+            "select * from t1 outer join t2 on a = b;",
+        ] {
+            assert!(Parser::new(sql).parse().is_err(), "{}", sql);
+        }
+        assert!(Parser::new("select * from t1 as full join t2 on a = b;").parse().is_ok());
+
         Ok(())
     }
 
@@ -808,13 +2072,467 @@ mod tests {
                 ]
                 .into_iter()
                 .collect(),
+                from: None,
                 where_clause: Some(ast::Expression::Operation(ast::Operation::Equal(
-                    Box::new(ast::Expression::Field("c".into())),
+                    Box::new(ast::Expression::Field(None, "c".into())),
                     Box::new(ast::Expression::Consts(Consts::String("a".into())))
                 ))),
             }
         );
 
+        let stmt = Parser::new("update t1 set a = t2.b from t2 where t1.id = t2.id;").parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::Update {
+                table_name: "t1".into(),
+                columns: [(
+                    "a".into(),
+                    ast::Expression::Field(Some("t2".into()), "b".into())
+                )]
+                .into(),
+                from: Some(ast::FromItem::Table {
+                    name: "t2".into(),
+                    alias: None,
+                    sample: None,
+                }),
+                where_clause: Some(ast::Expression::Operation(ast::Operation::Equal(
+                    Box::new(ast::Expression::Field(Some("t1".into()), "id".into())),
+                    Box::new(ast::Expression::Field(Some("t2".into()), "id".into()))
+                ))),
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_alter_table() -> Result<()> {
+        let stmt = Parser::new("alter table tbl alter column a set default 10;").parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::AlterTable {
+                name: "tbl".into(),
+                action: ast::AlterTableAction::AlterColumnDefault {
+                    column: "a".into(),
+                    default: Some(ast::Consts::Integer(10).into()),
+                },
+            }
+        );
+
+        let stmt = Parser::new("alter table tbl alter a drop default;").parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::AlterTable {
+                name: "tbl".into(),
+                action: ast::AlterTableAction::AlterColumnDefault {
+                    column: "a".into(),
+                    default: None,
+                },
+            }
+        );
+
+        let stmt1 = Parser::new("alter table tbl alter column a type float;").parse()?;
+        let stmt2 = Parser::new("alter table tbl alter a set data type double;").parse()?;
+        assert_eq!(stmt1, stmt2);
+        assert_eq!(
+            stmt1,
+            ast::Statement::AlterTable {
+                name: "tbl".into(),
+                action: ast::AlterTableAction::AlterColumnType {
+                    column: "a".into(),
+                    datatype: DataType::Float,
+                },
+            }
+        );
+
+        assert!(Parser::new("alter table tbl alter column a set b;").parse().is_err());
+
+        let stmt1 = Parser::new("alter table tbl add column c int default 1 index;").parse()?;
+        let stmt2 = Parser::new("alter table tbl add c int default 1 index;").parse()?;
+        assert_eq!(stmt1, stmt2);
+        assert!(matches!(
+            stmt1,
+            ast::Statement::AlterTable {
+                action: ast::AlterTableAction::AddColumn(ast::Column { ref name, index: true, .. }),
+                ..
+            } if name == "c"
+        ));
+
+        let stmt = Parser::new("alter table tbl drop column c;").parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::AlterTable {
+                name: "tbl".into(),
+                action: ast::AlterTableAction::DropColumn("c".into()),
+            }
+        );
+
+        let stmt = Parser::new("alter table tbl rename a to b;").parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::AlterTable {
+                name: "tbl".into(),
+                action: ast::AlterTableAction::RenameColumn {
+                    column: "a".into(),
+                    new_name: "b".into(),
+                },
+            }
+        );
+        assert!(Parser::new("alter table tbl rename column a b;").parse().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_cursor() -> Result<()> {
+        let stmt = Parser::new("declare c cursor for select * from tbl;").parse()?;
+        assert!(matches!(
+            stmt,
+            ast::Statement::DeclareCursor { name, query }
+                if name == "c" && matches!(*query, ast::Statement::Select { .. })
+        ));
+        assert!(Parser::new("declare c cursor for delete from tbl;").parse().is_err());
+
+        let fetch = |sql: &str| Parser::new(sql).parse();
+        assert_eq!(
+            fetch("fetch 10 from c;")?,
+            ast::Statement::Fetch {
+                name: "c".into(),
+                count: Some(10)
+            }
+        );
+        assert_eq!(fetch("fetch next c;")?, fetch("fetch c;")?);
+        assert_eq!(
+            fetch("fetch all from c;")?,
+            ast::Statement::Fetch {
+                name: "c".into(),
+                count: None
+            }
+        );
+        assert_eq!(
+            fetch("close c;")?,
+            ast::Statement::CloseCursor { name: "c".into() }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_reindex() -> Result<()> {
+        let stmt = Parser::new("reindex table tbl;").parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::Reindex {
+                table_name: "tbl".into(),
+                column: None,
+            }
+        );
+
+        let stmt = Parser::new("reindex index a on tbl;").parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::Reindex {
+                table_name: "tbl".into(),
+                column: Some("a".into()),
+            }
+        );
+
+        assert!(Parser::new("reindex index a;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_create_drop_index() -> Result<()> {
+        let stmt = Parser::new("create index idx on tbl (a);").parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::CreateIndex {
+                name: Some("idx".into()),
+                table_name: "tbl".into(),
+                column: "a".into(),
+                index_type: IndexType::Ordered,
+            }
+        );
+
+        let stmt = Parser::new("create index on tbl (a) using hash;").parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::CreateIndex {
+                name: None,
+                table_name: "tbl".into(),
+                column: "a".into(),
+                index_type: IndexType::Hash,
+            }
+        );
+
+        let stmt = Parser::new("drop index idx on tbl;").parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::DropIndex {
+                name: "idx".into(),
+                table_name: "tbl".into(),
+            }
+        );
+
+        assert!(Parser::new("create index idx on tbl a;").parse().is_err());
+        assert!(Parser::new("create index idx on tbl (a, b);").parse().is_err());
+        assert!(Parser::new("drop index idx;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_truncate_table() -> Result<()> {
+        for sql in ["truncate table tbl;", "truncate tbl;"] {
+            assert_eq!(
+                Parser::new(sql).parse()?,
+                ast::Statement::TruncateTable { name: "tbl".into() }
+            );
+        }
+        assert!(Parser::new("truncate table;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_params() -> Result<()> {
+        let params = vec![Value::Integer(1), Value::String("it's $2".into()), Value::Null];
+        let stmt = Parser::with_params("insert into t values ($1, $2, $3, $1);", params.clone())
+            .parse()?;
+        assert_eq!(
+            stmt,
+            Parser::new("insert into t values (1, 'it''s $2', null, 1);").parse()?
+        );
+        let stmt = Parser::with_params("select * from t where b = $2;", params).parse()?;
+        assert_eq!(stmt.to_string(), "SELECT * FROM t WHERE b = 'it''s $2'");
+
+        // 参数个数不够、序号不合法
+        assert!(Parser::new("select * from t where a = $1;").parse().is_err());
+        assert!(Parser::with_params("select $0 from t;", vec![Value::Null]).parse().is_err());
+        assert!(Parser::new("select $ from t;").parse().is_err());
+        Ok(())
+    }
+
+    // 输出的 SQL 重新解析之后得到相同的语法树，再次输出的文本也相同
+    fn assert_round_trip(stmt: &ast::Statement) -> Result<()> {
+        let sql = stmt.to_string();
+        let parsed = Parser::new(&format!("{};", sql)).parse()?;
+        assert_eq!(&parsed, stmt, "{}", sql);
+        assert_eq!(parsed.to_string(), sql);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_round_trip() -> Result<()> {
+        for sql in [
+            "create table t (a int primary key, b float not null default 1.5, c varchar null default 'it''s' index, d bool default (1 + 2), e interval default interval '1 day');",
+            "create table t (a int primary key) with (layout = 'columnar');",
+            "create table t (a int primary key, b text index using hash, c int index using btree, d text index using fulltext);",
+            "select * from t where match(d, 'rust database');",
+            "create table t (a int primary key, b bool index using bitmap);",
+            "create table t (a int primary key, tags text[]);",
+            "create table t (a int primary key, b int unique, c text not null unique index);",
+            "create table t (a int, b text, c int, primary key (a, b));",
+            "create table t (id int primary key auto_increment, b text);",
+            "create table t (a int primary key, b int check (b > 0 and b < 10), c text, check (c like 'x%' or b = 1));",
+            "select distinct a, b + 1 as c from t order by a limit 2;",
+            "select a, b, count(c) from t group by a, b;",
+            "select b, sum(c) from t group by b having sum(c) > 5 and count(a) > 1;",
+            "select a * b + 1, (a + 1) * 2, a - (b - c), a / (b * c), 7 / 2 * 3 from t where a * 2 > b / 3;",
+            "select tags[1] from t;",
+            "select * from t cross join unnest(t.tags) as tag;",
+            "drop table t;",
+            "truncate t;",
+            "alter table t alter column a set default (a - 20);",
+            "alter table t alter a drop default;",
+            "alter table t alter column a set data type string;",
+            "alter table t add column c int not null default 1 index;",
+            "alter table t drop column c;",
+            "alter table t rename column a to b;",
+            "reindex table t;",
+            "reindex index b on t;",
+            "create index idx on t (b);",
+            "create index on t (c) using bitmap;",
+            "drop index idx on t;",
+            "insert into t values (1, 'a', null), (2, default, true);",
+            "insert into t (a, b) values (1 + a, 'x');",
+            "insert into t default values;",
+            "select * from t;",
+            "select * from t tablesample (1.5 percent) repeatable (42) join t2 tablesample (10 percent) on t.a = t2.a;",
+            "select a, b + 1 as c, count(a), coalesce(b, 0.5), extract(year from d), now() from t where a > 1 - 3 group by a having count(a) > 1 order by a desc, b limit 10 offset 2;",
+            "select cast(a + 1 as text), cast('1' as int[]) from t where cast(b as float) > 1;",
+            "select * from t where a like 'a%';",
+            "select * from t where a is not distinct from null;",
+            "select * from t where a = 1 and b > 2 or not c = 3;",
+            "select * from t where (a = 1 or b like 'x%') and not (c or d is distinct from 2);",
+            "select * from t where (a, b) > (1, 'x');",
+            "select * from t where a between 1 and (b + 2) and b not in (1, 'x', null) or c not like 'a%';",
+            "select * from t where not a not between b - 1 and 10;",
+            "select * from t where a - (b - c) < a - b - c;",
+            "select * from t where d = '2024-01-01' + interval '1 day';",
+            "create table t (a int primary key, d date index, ts timestamp default timestamp '2024-01-01 10:00:00.5');",
+            "select * from t where d between date '2024-01-01' and date '2024-01-31' and ts > timestamp '2024-01-01';",
+            "select current_date, current_timestamp() from t;",
+            "select upper(a), substr(b, 1, 2), concat(a, 'x', null) from t where length(lower(b)) > 1;",
+            "create table t (a decimal(10, 2) primary key, b numeric default decimal '-1.50', c decimal(5));",
+            "select a * decimal '1.10', cast(b as numeric(38, 38)) from t where a > decimal '0.5';",
+            "create table t (a bytea primary key, b blob default x'00FF');",
+            "select * from t where a = X'deadBEEF' or b = cast('ab' as blob);",
+            "select t1.a, t2.b from t1 join t2 on t1.a = t2.a cross join t3 left join t4 on t1.a is distinct from t4.a;",
+            "select * from t1 right join t2 on t1.a = (t2.b + 1);",
+            "select t1.a, x.b from t1 join t2 as x on t1.a = x.b join t3 y on x.a = y.a where y.c > 1;",
+            "select x.a from t x tablesample (10 percent);",
+            "select * from (select a, b from t where a > 1) x group by (a + b) limit (1 + 2);",
+            "select a from t union select b from t2 union all select c from t3 order by a desc limit 2 offset 1;",
+            "select a from t intersect all select b from t2 except select c from t3;",
+            "select a as x, b from t order by x desc, 2, a * b + 1, abs(b) limit 1;",
+            "select * from t where a in (select b from t2 union select c from t3);",
+            "update t set a = a + 1, b = 'x' where c = true;",
+            "update t set b = t2.b from t2 join t3 on t2.a = t3.a where t.a = t2.a;",
+            "delete from t where a = 1;",
+            "delete from t using t2 cross join t3 where t.a = t2.a;",
+            "delete from t;",
+            "begin;",
+            "begin transaction isolation level read committed;",
+            "begin isolation level repeatable read;",
+            "begin transaction isolation level serializable;",
+            "begin read only;",
+            "begin transaction read only as of version 10;",
+            "commit;",
+            "rollback;",
+            "savepoint a;",
+            "rollback to savepoint a;",
+            "rollback to a;",
+            "release savepoint a;",
+            "release a;",
+            "explain select * from t;",
+            "explain (format dot) select * from t1 join t2 on t1.a = t2.a;",
+            "explain analyze update t set a = a + 1 where b > 1;",
+            "select * from t where a in (select b from t2 where c > 1) and not exists (select * from t3);",
+            "select a, (select max(b) from t2) as m from t where a not in (select b from t2) or a = (select min(b) from t2);",
+            "delete from t where exists (select * from t2 where b in (select c from t3));",
+            "set strict_types = on;",
+            "set memory_limit = 1024;",
+            "set name = 'abc';",
+            "vacuum;",
+            "declare c cursor for select a from t order by a;",
+            "fetch 5 from c;",
+            "fetch all c;",
+            "close c;",
+            "show profile;",
+            "copy t from 'data.csv';",
+            "copy t to '/tmp/it''s.csv' with (header = true, delimiter = ';');",
+            "backup to '/tmp/db.bak';",
+            "restore from 'db.bak';",
+        ] {
+            assert_round_trip(&Parser::new(sql).parse()?)?;
+        }
+
+        // 负数和浮点数常量
+        let stmt = Parser::new("select * from t where a = 1 - 2.5;").parse()?;
+        assert_eq!(stmt.to_string(), "SELECT * FROM t WHERE a = (0.0 - 1.5)");
+        assert_round_trip(&stmt)?;
+        let stmt = Parser::new("select * from t where a = 2 - 3 * 4 + 7 / 2;").parse()?;
+        assert_eq!(stmt.to_string(), "SELECT * FROM t WHERE a = (0 - 7)");
+        assert_round_trip(&stmt)?;
+
+        // 日期时间常量和时间间隔的运算在解析时计算
+        let stmt = Parser::new("select * from t where d = date '2024-01-31' + interval '1 month';").parse()?;
+        assert_eq!(stmt.to_string(), "SELECT * FROM t WHERE d = DATE '2024-02-29'");
+        assert!(Parser::new("select date '2024-02-30';").parse().is_err());
+        assert!(Parser::new("select timestamp 1;").parse().is_err());
+
+        // explain analyze 只支持文本格式
+        assert!(Parser::new("explain (format dot) analyze select * from t;").parse().is_err());
+
+        // order by、limit 只能出现在最后一个 select 之后
+        assert!(Parser::new("select a from t order by a union select b from t2;").parse().is_err());
+        assert!(Parser::new("select a from t union select b from t2 limit 1 except select c from t3;").parse().is_err());
+        Ok(())
+    }
+
+    // 随机生成表达式，检查 parse(print(ast)) == ast
+    #[test]
+    fn test_parser_round_trip_random() -> Result<()> {
+        let mut seed = 42u64;
+        let mut rand = move |n: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) % n
+        };
+
+        // 加减运算的左边总是带有列，否则两边都是常量时解析时会直接计算出结果
+        fn gen(rand: &mut impl FnMut(u64) -> u64, depth: u32) -> Expression {
+            let leaf = depth == 0 || rand(3) == 0;
+            match rand(if leaf { 6 } else { 9 }) {
+                0 => match rand(3) {
+                    0 => Expression::Field(None, "a".into()),
+                    1 => Expression::Field(None, "b".into()),
+                    _ => Expression::Field(Some("t".into()), "c".into()),
+                },
+                1 => Consts::Integer(rand(1000) as i64).into(),
+                2 => Consts::Float(rand(1000) as f64 / 8.0 - 50.0).into(),
+                3 => Consts::String(["", "x", "it's", "a b"][rand(4) as usize].into()).into(),
+                4 => [Consts::Null, Consts::Boolean(true), Consts::Boolean(false)]
+                    [rand(3) as usize]
+                    .clone()
+                    .into(),
+                5 => Expression::Function("max".into(), "a".into()),
+                6 | 7 => {
+                    let l = Box::new(Expression::Field(None, "a".into()));
+                    let r = Box::new(gen(rand, depth - 1));
+                    let l = match rand(2) {
+                        0 => l,
+                        _ => Box::new(Expression::Operation(ast::Operation::Add(
+                            l,
+                            Box::new(gen(rand, depth - 1)),
+                        ))),
+                    };
+                    Expression::Operation(match rand(4) {
+                        0 => ast::Operation::Add(l, r),
+                        1 => ast::Operation::Subtract(l, r),
+                        2 => ast::Operation::Multiply(l, r),
+                        _ => ast::Operation::Divide(l, r),
+                    })
+                }
+                _ => Expression::Call(
+                    "coalesce".into(),
+                    (0..=rand(3)).map(|_| gen(rand, depth - 1)).collect(),
+                ),
+            }
+        }
+
+        // 用 AND、OR、NOT 连接的 LIKE 条件
+        fn gen_cond(rand: &mut impl FnMut(u64) -> u64, depth: u32) -> Expression {
+            let op = match rand(if depth == 0 { 1 } else { 4 }) {
+                0 => ast::Operation::Like(Box::new(gen(rand, 3)), Box::new(gen(rand, 3))),
+                1 => ast::Operation::Not(Box::new(gen_cond(rand, depth - 1))),
+                2 => ast::Operation::And(
+                    Box::new(gen_cond(rand, depth - 1)),
+                    Box::new(gen_cond(rand, depth - 1)),
+                ),
+                _ => ast::Operation::Or(
+                    Box::new(gen_cond(rand, depth - 1)),
+                    Box::new(gen_cond(rand, depth - 1)),
+                ),
+            };
+            Expression::Operation(op)
+        }
+
+        for _ in 0..500 {
+            let stmt = ast::Statement::Select {
+                distinct: rand(2) == 0,
+                select: vec![(gen(&mut rand, 3), None), (gen(&mut rand, 3), Some("x".into()))],
+                from: ast::FromItem::Table {
+                    name: "t".into(),
+                    alias: None,
+                    sample: None,
+                },
+                where_clause: Some(gen_cond(&mut rand, 2)),
+                group_by: vec![gen(&mut rand, 2), gen(&mut rand, 2)],
+                having: None,
+                order_by: vec![],
+                limit: None,
+                offset: None,
+            };
+            assert_round_trip(&stmt)?;
+        }
         Ok(())
     }
 }
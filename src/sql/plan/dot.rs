@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+
+use crate::sql::parser::ast::{Expression, Operation, SetOperator};
+
+use super::Node;
+
+// 输出 Graphviz 格式的执行计划，explain (format dot) 使用
+// 每个节点的标签包含节点的描述和估算的行数，table_rows 是每张表的行数
+pub fn to_dot(node: &Node, table_rows: &HashMap<String, usize>) -> String {
+    let mut lines = vec!["digraph plan {".to_string(), "  node [shape=box];".to_string()];
+    let mut edges = Vec::new();
+    let mut nodes = vec![(node, None::<usize>)];
+    let mut id = 0;
+    while let Some((node, parent)) = nodes.pop() {
+        lines.push(format!(
+            "  n{} [label=\"{}\\nrows={}\"];",
+            id,
+            escape(&node.describe()),
+            estimate_rows(node, table_rows)
+        ));
+        if let Some(parent) = parent {
+            edges.push(format!("  n{} -> n{};", parent, id));
+        }
+        // 倒序入栈，保证左边的子节点先输出
+        nodes.extend(node.children().into_iter().rev().map(|c| (c, Some(id))));
+        id += 1;
+    }
+    lines.extend(edges);
+    lines.push("}".into());
+    lines.join("\n")
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// 估算节点输出的行数，扫描节点使用表的实际行数，过滤条件按照固定的选择率估算
+pub fn estimate_rows(node: &Node, table_rows: &HashMap<String, usize>) -> usize {
+    let rows = |name: &String| table_rows.get(name).copied().unwrap_or(0);
+    let child = |n: &Node| estimate_rows(n, table_rows);
+    match node {
+        // 下推的 limit 限制了扫描读取的行数
+        Node::Scan {
+            table_name,
+            filter,
+            limit,
+            ..
+        } => apply(rows(table_name), filter.as_ref()).min(limit.unwrap_or(usize::MAX)),
+        Node::IndexScan {
+            table_name, limit, ..
+        } => scale(rows(table_name), 0.1).min(limit.unwrap_or(usize::MAX)),
+        Node::VirtualScan { table_name, filter } => apply(rows(table_name), filter.as_ref()),
+        Node::PrimaryKeyScan { table_name, .. } => rows(table_name).min(1),
+        Node::IndexPrefixScan { table_name, .. }
+        | Node::PrimaryKeyPrefixScan { table_name, .. }
+        | Node::FullTextScan { table_name, .. }
+        | Node::BitmapScan { table_name, .. } => scale(rows(table_name), 0.1),
+        // 和 BETWEEN 的选择率一致
+        Node::RangeIndexScan { table_name, .. } => scale(rows(table_name), 0.25),
+        Node::Filter { source, predicate } => apply(child(source), Some(predicate)),
+        Node::Limit { source, limit } => child(source).min(*limit),
+        Node::Offset { source, offset } => child(source).saturating_sub(*offset),
+        // 和 group by 的估算一致
+        Node::Distinct { source } => scale(child(source), 0.1),
+        Node::Aggregate { source, group_by, .. } if group_by.is_empty() => child(source).min(1),
+        Node::Aggregate { source, .. } => scale(child(source), 0.1),
+        // 等值 join 按照较大的一边估算，outer join 的结果也不会少于左边的行数
+        Node::NestedLoopJoin {
+            left,
+            right,
+            predicate,
+            ..
+        }
+        | Node::HashJoin {
+            left,
+            right,
+            predicate,
+            ..
+        } => {
+            let (l, r) = (child(left), child(right));
+            match predicate {
+                None => l * r,
+                Some(_) => l.max(r),
+            }
+        }
+        Node::Sample {
+            source, percent, ..
+        } => scale(child(source), percent / 100.0),
+        // union 按照两边之和估算，intersect、except 的结果不会多于左边
+        Node::SetOperation {
+            op: SetOperator::Union,
+            left,
+            right,
+            ..
+        } => child(left) + child(right),
+        Node::SetOperation { left, .. } => child(left),
+        Node::Update { source, .. }
+        | Node::Delete { source, .. }
+        | Node::Order { source, .. }
+        | Node::Projection { source, .. }
+        | Node::Derived { source, .. } => child(source),
+        Node::Insert { values, .. } => values.len(),
+        // 数组常量按照元素个数估算，其他情况假设每一行展开一个元素
+        Node::Unnest { source, expr, .. } => {
+            let items = match expr {
+                Expression::Array(items) => items.len(),
+                _ => 1,
+            };
+            source.as_ref().map_or(1, |s| child(s)) * items
+        }
+        Node::CreateTable { .. }
+        | Node::DropTable { .. }
+        | Node::TruncateTable { .. }
+        | Node::AlterTable { .. }
+        | Node::Reindex { .. }
+        | Node::CreateIndex { .. }
+        | Node::DropIndex { .. }
+        | Node::CopyFrom { .. }
+        | Node::CopyTo { .. } => 0,
+    }
+}
+
+fn apply(rows: usize, filter: Option<&Expression>) -> usize {
+    match filter {
+        Some(expr) => scale(rows, selectivity(expr)),
+        None => rows,
+    }
+}
+
+fn scale(rows: usize, selectivity: f64) -> usize {
+    (rows as f64 * selectivity).ceil() as usize
+}
+
+// 过滤条件的选择率
+fn selectivity(expr: &Expression) -> f64 {
+    match expr {
+        Expression::Operation(Operation::Equal(..) | Operation::IsNotDistinctFrom(..)) => 0.1,
+        Expression::Operation(Operation::GreaterThan(..) | Operation::LessThan(..)) => 1.0 / 3.0,
+        Expression::Operation(Operation::Like(..)) => 0.25,
+        Expression::Operation(Operation::IsDistinctFrom(..)) => 0.9,
+        // 假设条件之间相互独立
+        Expression::Operation(Operation::And(l, r)) => selectivity(l) * selectivity(r),
+        Expression::Operation(Operation::Or(l, r)) => {
+            let (l, r) = (selectivity(l), selectivity(r));
+            l + r - l * r
+        }
+        Expression::Operation(Operation::Not(expr)) => 1.0 - selectivity(expr),
+        Expression::Operation(Operation::Between(..)) => 0.25,
+        Expression::Operation(Operation::In(_, list)) => (0.1 * list.len() as f64).min(1.0),
+        _ => 1.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::sql::parser::ast::{Consts, Expression, Operation};
+
+    use super::{super::Node, to_dot};
+
+    #[test]
+    fn test_to_dot() {
+        let scan = |name: &str, filter| Node::Scan {
+            table_name: name.into(),
+            filter,
+            columns: None,
+            limit: None,
+        };
+        let filter = Expression::Operation(Operation::Equal(
+            Box::new(Expression::Field(None, "a".into())),
+            Box::new(Consts::String("x\"y".into()).into()),
+        ));
+        let node = Node::Limit {
+            source: Box::new(Node::HashJoin {
+                left: Box::new(scan("t1", Some(filter))),
+                right: Box::new(scan("t2", None)),
+                predicate: Some(Expression::Operation(Operation::Equal(
+                    Box::new(Expression::Field(Some("t1".into()), "a".into())),
+                    Box::new(Expression::Field(Some("t2".into()), "a".into())),
+                ))),
+                outer: false,
+            }),
+            limit: 5,
+        };
+        let rows = HashMap::from([("t1".to_string(), 100), ("t2".to_string(), 3)]);
+        assert_eq!(
+            to_dot(&node, &rows),
+            [
+                "digraph plan {",
+                "  node [shape=box];",
+                "  n0 [label=\"Limit 5\\nrows=5\"];",
+                "  n1 [label=\"Hash Join(t1.a = t2.a)\\nrows=10\"];",
+                "  n2 [label=\"Seq Scan On t1 (a = 'x\\\"y')\\nrows=10\"];",
+                "  n3 [label=\"Seq Scan On t2\\nrows=3\"];",
+                "  n0 -> n1;",
+                "  n1 -> n2;",
+                "  n1 -> n3;",
+                "}",
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -1,21 +1,22 @@
-use std::{collections::BTreeMap, fmt::Display};
+use std::{collections::BTreeMap, fmt::Display, ops::Bound};
 
 use planner::Planner;
 
-use crate::error::Result;
+use crate::error::{Error, Result};
 
 use super::{
-    engine::Transaction,
-    executor::{Executor, ResultSet},
+    engine::{RowIter, Transaction},
+    executor::{ColumnDesc, Executor, NodeStats, PlanStats, ResultSet},
     parser::ast::{self, Expression, OrderDirection},
-    schema::Table,
+    schema::{IndexType, Table},
     types::Value,
 };
 
+pub mod dot;
 mod planner;
 
 // 执行节点
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Node {
     // 创建表
     CreateTable {
@@ -27,6 +28,37 @@ pub enum Node {
         name: String,
     },
 
+    // 清空表
+    TruncateTable {
+        name: String,
+    },
+
+    // 修改表结构
+    AlterTable {
+        table_name: String,
+        action: ast::AlterTableAction,
+    },
+
+    // 重建索引
+    Reindex {
+        table_name: String,
+        column: Option<String>,
+    },
+
+    // 在已有的表上创建索引
+    CreateIndex {
+        name: Option<String>,
+        table_name: String,
+        column: String,
+        index_type: IndexType,
+    },
+
+    // 删除索引
+    DropIndex {
+        name: String,
+        table_name: String,
+    },
+
     // 插入数据
     Insert {
         table_name: String,
@@ -34,10 +66,32 @@ pub enum Node {
         values: Vec<Vec<Expression>>,
     },
 
-    // 扫描节点
+    // 从 csv 文件导入数据
+    CopyFrom {
+        table_name: String,
+        path: String,
+        options: ast::CopyOptions,
+    },
+
+    // 把表的数据导出到 csv 文件
+    CopyTo {
+        table_name: String,
+        path: String,
+        options: ast::CopyOptions,
+    },
+
+    // 扫描节点，columns 不为空时只读取这些列，limit 不为空时最多读取这么多行
     Scan {
         table_name: String,
         filter: Option<Expression>,
+        columns: Option<Vec<String>>,
+        limit: Option<usize>,
+    },
+
+    // 扫描 information_schema 中的虚拟表
+    VirtualScan {
+        table_name: String,
+        filter: Option<Expression>,
     },
 
     // 更新节点
@@ -56,7 +110,7 @@ pub enum Node {
     // 排序节点
     Order {
         source: Box<Node>,
-        order_by: Vec<(String, OrderDirection)>,
+        order_by: Vec<(Expression, OrderDirection)>,
     },
 
     // Limit 节点
@@ -71,6 +125,19 @@ pub enum Node {
         offset: usize,
     },
 
+    // Distinct 节点，去掉投影之后重复的行
+    Distinct {
+        source: Box<Node>,
+    },
+
+    // 集合操作节点，合并两边的结果，两边的列数必须相同，all 为 false 时去掉重复的行
+    SetOperation {
+        op: ast::SetOperator,
+        all: bool,
+        left: Box<Node>,
+        right: Box<Node>,
+    },
+
     // 投影节点
     Projection {
         source: Box<Node>,
@@ -97,7 +164,7 @@ pub enum Node {
     Aggregate {
         source: Box<Node>,
         exprs: Vec<(Expression, Option<String>)>,
-        group_by: Option<Expression>,
+        group_by: Vec<Expression>,
     },
 
     // 过滤节点
@@ -106,11 +173,23 @@ pub enum Node {
         predicate: Expression,
     },
 
-    // 索引查询节点
+    // 索引查询节点，limit 不为空时按照主键顺序最多读取这么多行
+    // columns 不为空时是覆盖索引，只输出索引列和主键列，直接从索引中得到，不需要回表读取整行
     IndexScan {
         table_name: String,
         field: String,
         value: Value,
+        limit: Option<usize>,
+        columns: Option<Vec<String>>,
+    },
+
+    // 索引范围查询节点，索引列的值在 start 和 end 之间，columns 和 IndexScan 一样
+    RangeIndexScan {
+        table_name: String,
+        field: String,
+        start: Bound<Value>,
+        end: Bound<Value>,
+        columns: Option<Vec<String>>,
     },
 
     // 主键查询节点
@@ -118,20 +197,261 @@ pub enum Node {
         table_name: String,
         value: Value,
     },
+
+    // 索引前缀查询节点，索引列的值以 prefix 开头
+    IndexPrefixScan {
+        table_name: String,
+        field: String,
+        prefix: String,
+    },
+
+    // 主键前缀查询节点，主键以 prefix 开头
+    PrimaryKeyPrefixScan {
+        table_name: String,
+        prefix: String,
+    },
+
+    // 全文索引查询节点，文本中包含 query 中的所有词
+    FullTextScan {
+        table_name: String,
+        field: String,
+        query: String,
+    },
+
+    // 位图索引查询节点，按照 condition 合并各个位图之后再读取行
+    BitmapScan {
+        table_name: String,
+        condition: BitmapCondition,
+    },
+
+    // 表采样节点，source 的每一行以 percent% 的概率输出
+    Sample {
+        source: Box<Node>,
+        percent: f64,
+        seed: Option<u64>,
+    },
+
+    // 子查询节点，子查询的结果作为一张名为 alias 的表
+    Derived {
+        source: Box<Node>,
+        alias: String,
+    },
+
+    // 展开数组，每个元素输出一行，列名是 alias
+    // 有 source 时对 source 的每一行求值，输出 source 的列加上展开的列，相当于 lateral join
+    Unnest {
+        source: Option<Box<Node>>,
+        expr: Expression,
+        alias: String,
+        predicate: Option<Expression>,
+        outer: bool,
+    },
+}
+
+// 位图索引扫描的条件，叶子节点是位图索引列的等值条件
+#[derive(Debug, PartialEq, Clone)]
+pub enum BitmapCondition {
+    Equal(String, Value),
+    And(Box<BitmapCondition>, Box<BitmapCondition>),
+    Or(Box<BitmapCondition>, Box<BitmapCondition>),
+}
+
+impl Display for BitmapCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BitmapCondition::Equal(field, value) => {
+                write!(f, "{} = {}", field, Expression::from(value.clone()))
+            }
+            BitmapCondition::And(l, r) => write!(f, "({} AND {})", l, r),
+            BitmapCondition::Or(l, r) => write!(f, "({} OR {})", l, r),
+        }
+    }
 }
 
 impl Display for Node {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.format(f, "", true)
+        self.format(f, "", true, None, &mut 0)
+    }
+}
+
+// explain analyze 的输出，每个节点后面带上实际输出的行数和耗时
+pub struct AnalyzedPlan {
+    pub node: Node,
+    pub stats: Vec<NodeStats>,
+}
+
+impl Display for AnalyzedPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.node.format(f, "", true, Some(&self.stats), &mut 0)?;
+        let total = self.stats.first().map(|s| s.elapsed).unwrap_or_default();
+        write!(f, "\nExecution Time: {:.3} ms", total.as_secs_f64() * 1000.0)
     }
 }
 
 impl Node {
+    // 节点名称，用于 tracing
+    pub fn name(&self) -> &'static str {
+        match self {
+            Node::CreateTable { .. } => "CreateTable",
+            Node::DropTable { .. } => "DropTable",
+            Node::TruncateTable { .. } => "TruncateTable",
+            Node::AlterTable { .. } => "AlterTable",
+            Node::Reindex { .. } => "Reindex",
+            Node::CreateIndex { .. } => "CreateIndex",
+            Node::DropIndex { .. } => "DropIndex",
+            Node::Insert { .. } => "Insert",
+            Node::CopyFrom { .. } => "CopyFrom",
+            Node::CopyTo { .. } => "CopyTo",
+            Node::Scan { .. } => "Scan",
+            Node::VirtualScan { .. } => "VirtualScan",
+            Node::Update { .. } => "Update",
+            Node::Delete { .. } => "Delete",
+            Node::Order { .. } => "Order",
+            Node::Limit { .. } => "Limit",
+            Node::Offset { .. } => "Offset",
+            Node::Distinct { .. } => "Distinct",
+            Node::SetOperation { .. } => "SetOperation",
+            Node::Projection { .. } => "Projection",
+            Node::NestedLoopJoin { .. } => "NestedLoopJoin",
+            Node::HashJoin { .. } => "HashJoin",
+            Node::Aggregate { .. } => "Aggregate",
+            Node::Filter { .. } => "Filter",
+            Node::IndexScan { .. } => "IndexScan",
+            Node::RangeIndexScan { .. } => "RangeIndexScan",
+            Node::PrimaryKeyScan { .. } => "PrimaryKeyScan",
+            Node::IndexPrefixScan { .. } => "IndexPrefixScan",
+            Node::PrimaryKeyPrefixScan { .. } => "PrimaryKeyPrefixScan",
+            Node::FullTextScan { .. } => "FullTextScan",
+            Node::BitmapScan { .. } => "BitmapScan",
+            Node::Sample { .. } => "Sample",
+            Node::Derived { .. } => "Derived",
+            Node::Unnest { .. } => "Unnest",
+        }
+    }
+
+    // 扫描单表的节点返回表名，子查询节点返回别名
+    pub fn scan_table_name(&self) -> Option<String> {
+        match self {
+            Node::Scan { table_name, .. }
+            | Node::VirtualScan { table_name, .. }
+            | Node::IndexScan { table_name, .. }
+            | Node::RangeIndexScan { table_name, .. }
+            | Node::PrimaryKeyScan { table_name, .. }
+            | Node::IndexPrefixScan { table_name, .. }
+            | Node::PrimaryKeyPrefixScan { table_name, .. }
+            | Node::FullTextScan { table_name, .. }
+            | Node::BitmapScan { table_name, .. } => Some(table_name.clone()),
+            Node::Derived { alias, .. } => Some(alias.clone()),
+            // 下推到扫描上的条件，过滤之后依然是这张表的行
+            Node::Sample { source, .. } | Node::Filter { source, .. } => source.scan_table_name(),
+            _ => None,
+        }
+    }
+
+    // 直接子节点
+    pub fn children(&self) -> Vec<&Node> {
+        match self {
+            Node::Update { source, .. }
+            | Node::Delete { source, .. }
+            | Node::Order { source, .. }
+            | Node::Limit { source, .. }
+            | Node::Offset { source, .. }
+            | Node::Distinct { source }
+            | Node::Projection { source, .. }
+            | Node::Aggregate { source, .. }
+            | Node::Filter { source, .. }
+            | Node::Sample { source, .. }
+            | Node::Derived { source, .. } => vec![source],
+            Node::NestedLoopJoin { left, right, .. }
+            | Node::HashJoin { left, right, .. }
+            | Node::SetOperation { left, right, .. } => vec![left, right],
+            Node::Unnest { source, .. } => source.iter().map(|s| s.as_ref()).collect(),
+            _ => vec![],
+        }
+    }
+
+    // 节点本身用到的表达式，不包括子节点中的表达式
+    pub fn expressions(&self) -> Vec<&Expression> {
+        match self {
+            Node::Insert { values, .. } => values.iter().flatten().collect(),
+            Node::Update { columns, .. } => columns.values().collect(),
+            Node::Projection { exprs, .. } => exprs.iter().map(|(e, _)| e).collect(),
+            Node::Order { order_by, .. } => order_by.iter().map(|(e, _)| e).collect(),
+            Node::Aggregate {
+                exprs, group_by, ..
+            } => exprs.iter().map(|(e, _)| e).chain(group_by).collect(),
+            Node::Filter { predicate, .. } => vec![predicate],
+            Node::Scan { filter, .. }
+            | Node::VirtualScan { filter, .. }
+            | Node::NestedLoopJoin {
+                predicate: filter, ..
+            }
+            | Node::HashJoin {
+                predicate: filter, ..
+            } => filter.iter().collect(),
+            Node::Unnest {
+                expr, predicate, ..
+            } => std::iter::once(expr).chain(predicate).collect(),
+            _ => vec![],
+        }
+    }
+
+    // stats 不为空时在每个节点后面输出执行统计，id 是节点前序遍历的编号
+    // 可以修改的直接子节点
+    fn children_mut(&mut self) -> Vec<&mut Node> {
+        match self {
+            Node::Update { source, .. }
+            | Node::Delete { source, .. }
+            | Node::Order { source, .. }
+            | Node::Limit { source, .. }
+            | Node::Offset { source, .. }
+            | Node::Distinct { source }
+            | Node::Projection { source, .. }
+            | Node::Aggregate { source, .. }
+            | Node::Filter { source, .. }
+            | Node::Sample { source, .. }
+            | Node::Derived { source, .. } => vec![source],
+            Node::NestedLoopJoin { left, right, .. }
+            | Node::HashJoin { left, right, .. }
+            | Node::SetOperation { left, right, .. } => vec![left, right],
+            Node::Unnest { source, .. } => source.iter_mut().map(|s| s.as_mut()).collect(),
+            _ => vec![],
+        }
+    }
+
+    // 可以修改的节点本身用到的表达式
+    fn expressions_mut(&mut self) -> Vec<&mut Expression> {
+        match self {
+            Node::Insert { values, .. } => values.iter_mut().flatten().collect(),
+            Node::Update { columns, .. } => columns.values_mut().collect(),
+            Node::Projection { exprs, .. } => exprs.iter_mut().map(|(e, _)| e).collect(),
+            Node::Order { order_by, .. } => order_by.iter_mut().map(|(e, _)| e).collect(),
+            Node::Aggregate {
+                exprs, group_by, ..
+            } => exprs.iter_mut().map(|(e, _)| e).chain(group_by).collect(),
+            Node::Filter { predicate, .. } => vec![predicate],
+            Node::Scan { filter, .. }
+            | Node::VirtualScan { filter, .. }
+            | Node::NestedLoopJoin {
+                predicate: filter, ..
+            }
+            | Node::HashJoin {
+                predicate: filter, ..
+            } => filter.iter_mut().collect(),
+            Node::Unnest {
+                expr, predicate, ..
+            } => std::iter::once(expr).chain(predicate).collect(),
+            _ => vec![],
+        }
+    }
+
     fn format(
         &self,
         f: &mut std::fmt::Formatter<'_>,
         prefix: &str,
         root: bool,
+        stats: Option<&[NodeStats]>,
+        id: &mut usize,
     ) -> std::fmt::Result {
         if !root {
             writeln!(f)?;
@@ -147,40 +467,81 @@ impl Node {
             format!("  {}", prefix)
         };
 
+        write!(f, "{}", self.describe())?;
+        if let Some(node) = stats.and_then(|stats| stats.get(*id)) {
+            write!(
+                f,
+                " (actual rows={} time={:.3} ms)",
+                node.rows,
+                node.elapsed.as_secs_f64() * 1000.0
+            )?;
+        }
+        *id += 1;
+        for child in self.children() {
+            child.format(f, &prefix, false, stats, id)?;
+        }
+        Ok(())
+    }
+
+    // 节点本身的描述，不包括子节点
+    pub fn describe(&self) -> String {
         match self {
-            Node::CreateTable { schema } => {
-                write!(f, "Create Table {}", schema.name)
+            Node::CreateTable { schema } => format!("Create Table {}", schema.name),
+            Node::DropTable { name } => format!("Drop Table {}", name),
+            Node::TruncateTable { name } => format!("Truncate Table {}", name),
+            Node::AlterTable { table_name, action } => {
+                format!("Alter Table {} ({})", table_name, action)
             }
-            Node::DropTable { name } => {
-                write!(f, "Drop Table {}", name)
+            Node::Reindex { table_name, column } => match column {
+                Some(column) => format!("Reindex {} ({})", table_name, column),
+                None => format!("Reindex {}", table_name),
+            },
+            Node::CreateIndex {
+                name,
+                table_name,
+                column,
+                ..
+            } => format!(
+                "Create Index {} On {} ({})",
+                name.as_ref().unwrap_or(column),
+                table_name,
+                column
+            ),
+            Node::DropIndex { name, table_name } => {
+                format!("Drop Index {} On {}", name, table_name)
             }
-            Node::Insert {
+            Node::Insert { table_name, .. } => format!("Insert Into {}", table_name),
+            Node::CopyFrom {
+                table_name, path, ..
+            } => format!("Copy {} From '{}'", table_name, path),
+            Node::CopyTo {
+                table_name, path, ..
+            } => format!("Copy {} To '{}'", table_name, path),
+            Node::Scan {
                 table_name,
-                columns: _,
-                values: _,
+                filter,
+                columns,
+                limit,
             } => {
-                write!(f, "Insert Into {}", table_name)
-            }
-            Node::Scan { table_name, filter } => {
-                write!(f, "Seq Scan On {}", table_name)?;
+                let mut desc = format!("Seq Scan On {}", table_name);
+                if let Some(columns) = columns {
+                    desc += &format!(" [{}]", columns.join(", "));
+                }
                 if let Some(filter) = filter {
-                    write!(f, " ({})", filter)?;
+                    desc += &format!(" ({})", filter);
                 }
-                Ok(())
-            }
-            Node::Update {
-                table_name,
-                source,
-                columns: _,
-            } => {
-                write!(f, "Update On {}", table_name)?;
-                (*source).format(f, &prefix, false)
-            }
-            Node::Delete { table_name, source } => {
-                write!(f, "Delete On {}", table_name)?;
-                (*source).format(f, &prefix, false)
+                if let Some(limit) = limit {
+                    desc += &format!(" Limit {}", limit);
+                }
+                desc
             }
-            Node::Order { source, order_by } => {
+            Node::VirtualScan { table_name, filter } => match filter {
+                Some(filter) => format!("Virtual Scan On {} ({})", table_name, filter),
+                None => format!("Virtual Scan On {}", table_name),
+            },
+            Node::Update { table_name, .. } => format!("Update On {}", table_name),
+            Node::Delete { table_name, .. } => format!("Delete On {}", table_name),
+            Node::Order { order_by, .. } => {
                 let desc = order_by
                     .iter()
                     .map(|c| {
@@ -196,103 +557,126 @@ impl Node {
                     })
                     .collect::<Vec<_>>()
                     .join(",");
-                write!(f, "Order By ({})", desc)?;
-                (*source).format(f, &prefix, false)
+                format!("Order By ({})", desc)
             }
-            Node::Limit { source, limit } => {
-                write!(f, "Limit {}", limit)?;
-                (*source).format(f, &prefix, false)
-            }
-            Node::Offset { source, offset } => {
-                write!(f, "Offset {}", offset)?;
-                (*source).format(f, &prefix, false)
-            }
-            Node::Projection { source, exprs } => {
-                let desc = exprs
-                    .iter()
-                    .map(|(e, alias)| {
-                        format!(
-                            "{}{}",
-                            e,
-                            if alias.is_some() {
-                                format!(" as {}", alias.clone().unwrap())
-                            } else {
-                                "".into()
-                            }
-                        )
-                    })
-                    .collect::<Vec<_>>()
-                    .join(", ");
-                write!(f, "Projection ({})", desc)?;
-                (*source).format(f, &prefix, false)
-            }
-            Node::NestedLoopJoin {
-                left,
-                right,
-                predicate,
-                outer: _,
-            } => {
-                write!(f, "Nested Loop Join")?;
-                if let Some(expr) = predicate {
-                    write!(f, "({})", expr)?;
-                }
-                (*left).format(f, &prefix, false)?;
-                (*right).format(f, &prefix, false)
-            }
-            Node::HashJoin {
-                left,
-                right,
-                predicate,
-                outer: _,
+            Node::Limit { limit, .. } => format!("Limit {}", limit),
+            Node::Offset { offset, .. } => format!("Offset {}", offset),
+            Node::Distinct { .. } => "Distinct".into(),
+            Node::SetOperation { op, all, .. } => match all {
+                true => format!("{} All", op),
+                false => op.to_string(),
+            },
+            Node::Projection { exprs, .. } => format!("Projection ({})", describe_exprs(exprs)),
+            Node::NestedLoopJoin { predicate, .. } => match predicate {
+                Some(expr) => format!("Nested Loop Join({})", expr),
+                None => "Nested Loop Join".into(),
+            },
+            Node::HashJoin { predicate, .. } => match predicate {
+                Some(expr) => format!("Hash Join({})", expr),
+                None => "Hash Join".into(),
+            },
+            Node::Aggregate { exprs, .. } => format!("Aggregate ({})", describe_exprs(exprs)),
+            Node::Filter { predicate, .. } => format!("Filter ({})", predicate),
+            Node::IndexScan {
+                table_name,
+                field,
+                limit,
+                columns,
+                ..
             } => {
-                write!(f, "Hash Join")?;
-                if let Some(expr) = predicate {
-                    write!(f, "({})", expr)?;
+                let scan = match columns {
+                    Some(_) => "Index Only Scan",
+                    None => "Index Scan",
+                };
+                match limit {
+                    Some(limit) => format!("{} On {}.{} Limit {}", scan, table_name, field, limit),
+                    None => format!("{} On {}.{}", scan, table_name, field),
                 }
-                (*left).format(f, &prefix, false)?;
-                (*right).format(f, &prefix, false)
             }
-            Node::Aggregate {
-                source,
-                exprs,
-                group_by: _,
+            Node::RangeIndexScan {
+                table_name,
+                field,
+                start,
+                end,
+                columns,
             } => {
-                let desc = exprs
-                    .iter()
-                    .map(|(e, alias)| {
-                        format!(
-                            "{}{}",
-                            e,
-                            if alias.is_some() {
-                                format!(" as {}", alias.clone().unwrap())
-                            } else {
-                                "".into()
-                            }
-                        )
-                    })
-                    .collect::<Vec<_>>()
-                    .join(", ");
-                write!(f, "Aggregate ({})", desc)?;
-                (*source).format(f, &prefix, false)
+                let start = match start {
+                    Bound::Included(v) => format!("[{}", v),
+                    Bound::Excluded(v) => format!("({}", v),
+                    Bound::Unbounded => "(-inf".into(),
+                };
+                let end = match end {
+                    Bound::Included(v) => format!("{}]", v),
+                    Bound::Excluded(v) => format!("{})", v),
+                    Bound::Unbounded => "+inf)".into(),
+                };
+                let scan = match columns {
+                    Some(_) => "Index Only Range Scan",
+                    None => "Index Range Scan",
+                };
+                format!("{} On {}.{}{}, {}", scan, table_name, field, start, end)
             }
-            Node::Filter { source, predicate } => {
-                write!(f, "Filter ({})", predicate)?;
-                (*source).format(f, &prefix, false)
+            Node::PrimaryKeyScan { table_name, value } => {
+                format!("Primary Key Scan On {}({})", table_name, value)
             }
-            Node::IndexScan {
+            Node::IndexPrefixScan {
                 table_name,
                 field,
-                value: _,
-            } => {
-                write!(f, "Index Scan On {}.{}", table_name, field)
+                prefix,
+            } => format!("Index Prefix Scan On {}.{}('{}%')", table_name, field, prefix),
+            Node::PrimaryKeyPrefixScan { table_name, prefix } => {
+                format!("Primary Key Prefix Scan On {}('{}%')", table_name, prefix)
             }
-            Node::PrimaryKeyScan { table_name, value } => {
-                write!(f, "Primary Key Scan On {}({})", table_name, value)
+            Node::FullTextScan {
+                table_name,
+                field,
+                query,
+            } => format!(
+                "Full Text Scan On {}.{}({})",
+                table_name,
+                field,
+                ast::Consts::String(query.clone())
+            ),
+            Node::BitmapScan {
+                table_name,
+                condition,
+            } => format!("Bitmap Scan On {}({})", table_name, condition),
+            Node::Sample { percent, seed, .. } => match seed {
+                Some(seed) => format!("Sample ({}%, seed {})", percent, seed),
+                None => format!("Sample ({}%)", percent),
+            },
+            // 带有别名的表
+            Node::Derived { source, alias } if !matches!(**source, Node::Derived { .. }) => {
+                match source.scan_table_name() {
+                    Some(name) => format!("Alias {} As {}", name, alias),
+                    None => format!("Subquery Scan On {}", alias),
+                }
             }
+            Node::Derived { alias, .. } => format!("Subquery Scan On {}", alias),
+            Node::Unnest {
+                expr,
+                alias,
+                predicate,
+                ..
+            } => match predicate {
+                Some(predicate) => format!("Unnest({}) As {} ({})", expr, alias, predicate),
+                None => format!("Unnest({}) As {}", expr, alias),
+            },
         }
     }
 }
 
+fn describe_exprs(exprs: &[(Expression, Option<String>)]) -> String {
+    exprs
+        .iter()
+        .map(|(e, alias)| match alias {
+            Some(alias) => format!("{} as {}", e, alias),
+            None => e.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 #[derive(Debug, PartialEq)]
 // 执行计划定义，底层是不同类型执行节点
 pub struct Plan(pub Node);
@@ -302,9 +686,92 @@ impl Plan {
         Planner::new(txn).build(stmt)
     }
 
-    pub fn execute<T: Transaction + 'static>(self, txn: &mut T) -> Result<ResultSet> {
+    pub fn execute<T: Transaction + 'static>(mut self, txn: &mut T) -> Result<ResultSet> {
+        self.resolve_subqueries(txn)?;
+        txn.memory().reset();
         <dyn Executor<T>>::build(self.0).execute(txn)
     }
+
+    // 实际执行，返回带有每个节点执行统计的执行计划
+    pub fn analyze<T: Transaction + 'static>(mut self, txn: &mut T) -> Result<AnalyzedPlan> {
+        let node = self.0.clone();
+        self.resolve_subqueries(txn)?;
+        txn.memory().reset();
+        let stats = PlanStats::default();
+        <dyn Executor<T>>::build_analyzed(self.0, &stats).execute(txn)?;
+        let stats = stats.lock()?.clone();
+        Ok(AnalyzedPlan { node, stats })
+    }
+
+    // 以迭代器的方式返回查询结果，读取每一行时才从下层节点读取
+    pub fn scan<T: Transaction + 'static>(
+        mut self,
+        txn: &mut T,
+    ) -> Result<(Vec<ColumnDesc>, RowIter)> {
+        self.resolve_subqueries(txn)?;
+        txn.memory().reset();
+        <dyn Executor<T>>::build(self.0).scan(txn)
+    }
+
+    // 子查询不能引用外层查询的列，在执行之前先执行一次，结果替换成常量：
+    // 标量子查询替换成它返回的值，EXISTS 替换成 true 或者 false，IN (SELECT ...) 替换成 IN 值列表
+    fn resolve_subqueries<T: Transaction + 'static>(&mut self, txn: &mut T) -> Result<()> {
+        let mut nodes = vec![&mut self.0];
+        while let Some(node) = nodes.pop() {
+            for expr in node.expressions_mut() {
+                if expr.has_subquery() {
+                    expr.walk_mut(&mut |e| resolve_subquery(e, txn))?;
+                }
+            }
+            nodes.extend(node.children_mut());
+        }
+        Ok(())
+    }
+}
+
+fn resolve_subquery<T: Transaction + 'static>(expr: &mut Expression, txn: &mut T) -> Result<()> {
+    *expr = match expr {
+        Expression::Exists(query) => {
+            // 读取到第一行就可以确定结果
+            let (_, mut rows) = Plan::build(*query.clone(), txn)?.scan(txn)?;
+            let exists = rows.next().transpose()?.is_some();
+            ast::Consts::Boolean(exists).into()
+        }
+        Expression::Subquery(query) => {
+            let mut values = subquery_values(query, txn)?;
+            if values.len() > 1 {
+                return Err(Error::InvalidInput(format!(
+                    "subquery ({}) returned more than one row",
+                    query
+                )));
+            }
+            values.pop().unwrap_or(Value::Null).into()
+        }
+        Expression::Operation(ast::Operation::InSubquery(left, query)) => {
+            let list = subquery_values(query, txn)?;
+            Expression::Operation(ast::Operation::In(
+                left.clone(),
+                list.into_iter().map(Expression::from).collect(),
+            ))
+        }
+        _ => return Ok(()),
+    };
+    Ok(())
+}
+
+// 执行只返回一列的子查询
+fn subquery_values<T: Transaction + 'static>(
+    query: &ast::Statement,
+    txn: &mut T,
+) -> Result<Vec<Value>> {
+    let (columns, rows) = Plan::build(query.clone(), txn)?.scan(txn)?;
+    if columns.len() != 1 {
+        return Err(Error::InvalidInput(format!(
+            "subquery ({}) must return only one column",
+            query
+        )));
+    }
+    rows.map(|row| row.map(|mut row| row.remove(0))).collect()
 }
 
 #[cfg(test)]
@@ -420,6 +887,8 @@ mod tests {
             Plan(Node::Scan {
                 table_name: "tbl1".to_string(),
                 filter: None,
+                columns: None,
+                limit: None,
             })
         );
 
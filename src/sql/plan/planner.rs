@@ -1,14 +1,18 @@
+use std::ops::Bound;
+
 use crate::{
     error::{Error, Result},
     sql::{
-        engine::Transaction,
+        engine::{sys, Transaction},
+        executor::information_schema,
+        functions,
         parser::ast::{self, Expression},
-        schema::{self, Table},
-        types::Value,
+        schema::{self, IndexType, Table},
+        types::{coerce::coerce_lookup, DataType, Value},
     },
 };
 
-use super::{Node, Plan};
+use super::{BitmapCondition, Node, Plan};
 
 pub struct Planner<'a, T: Transaction> {
     txn: &'a mut T,
@@ -25,32 +29,81 @@ impl<'a, T: Transaction> Planner<'a, T> {
 
     fn build_statment(&self, stmt: ast::Statement) -> Result<Node> {
         Ok(match stmt {
-            ast::Statement::CreateTable { name, columns } => Node::CreateTable {
-                schema: Table {
-                    name,
-                    columns: columns
-                        .into_iter()
-                        .map(|c| {
-                            let nullable = c.nullable.unwrap_or(!c.primary_key);
-                            let default = match c.default {
-                                Some(expr) => Some(Value::from_expression(expr)),
-                                None if nullable => Some(Value::Null),
-                                None => None,
-                            };
-
-                            schema::Column {
-                                name: c.name,
-                                datatype: c.datatype,
-                                nullable,
-                                default,
-                                primary_key: c.primary_key,
-                                index: c.index && !c.primary_key,
-                            }
-                        })
-                        .collect(),
-                },
-            },
+            ast::Statement::CreateTable {
+                name,
+                mut columns,
+                primary_key,
+                checks,
+                layout,
+            } => {
+                // 只能有一个列级别的主键，和表级别的主键约束不能同时出现
+                let column_pks = columns.iter().filter(|c| c.primary_key).count();
+                if column_pks > 1 || (column_pks > 0 && !primary_key.is_empty()) {
+                    return Err(Error::InvalidInput(format!(
+                        "Multiple primary keys for table {}",
+                        name
+                    )));
+                }
+                // 表级别的主键约束，把对应的列标记为主键
+                for (i, pk) in primary_key.iter().enumerate() {
+                    if primary_key[..i].contains(pk) {
+                        return Err(Error::InvalidInput(format!(
+                            "duplicate column {} in primary key of table {}",
+                            pk, name
+                        )));
+                    }
+                    match columns.iter_mut().find(|c| c.name == *pk) {
+                        Some(c) => c.primary_key = true,
+                        None => {
+                            return Err(Error::NotFound(format!(
+                                "primary key column {} not found in table {}",
+                                pk, name
+                            )))
+                        }
+                    }
+                }
+                let mut columns = columns
+                    .into_iter()
+                    .map(schema::build_column)
+                    .collect::<Result<Vec<_>>>()?;
+                // 没有声明主键时，使用隐藏的 _rowid 列作为主键
+                if !columns.iter().any(|c| c.primary_key) {
+                    columns.push(schema::Column::rowid());
+                }
+                let checks = checks
+                    .iter()
+                    .map(schema::check_constraint)
+                    .collect::<Result<Vec<_>>>()?;
+                Node::CreateTable {
+                    schema: Table {
+                        name,
+                        columns,
+                        layout,
+                        checks,
+                    },
+                }
+            }
             ast::Statement::DropTable { name } => Node::DropTable { name },
+            ast::Statement::TruncateTable { name } => Node::TruncateTable { name },
+            ast::Statement::AlterTable { name, action } => Node::AlterTable {
+                table_name: name,
+                action,
+            },
+            ast::Statement::Reindex { table_name, column } => {
+                Node::Reindex { table_name, column }
+            }
+            ast::Statement::CreateIndex {
+                name,
+                table_name,
+                column,
+                index_type,
+            } => Node::CreateIndex {
+                name,
+                table_name,
+                column,
+                index_type,
+            },
+            ast::Statement::DropIndex { name, table_name } => Node::DropIndex { name, table_name },
             ast::Statement::Insert {
                 table_name,
                 columns,
@@ -61,6 +114,7 @@ impl<'a, T: Transaction> Planner<'a, T> {
                 values,
             },
             ast::Statement::Select {
+                distinct,
                 select,
                 from,
                 where_clause,
@@ -70,8 +124,55 @@ impl<'a, T: Transaction> Planner<'a, T> {
                 limit,
                 offset,
             } => {
+                let mut exprs = select.iter().map(|(e, _)| e).collect::<Vec<_>>();
+                exprs.extend(where_clause.iter().chain(&group_by).chain(&having));
+                exprs.extend(order_by.iter().map(|(e, _)| e));
+                check_qualifiers(&from, Vec::new(), exprs)?;
+                let exprs = select.iter().map(|(e, _)| e).chain(&where_clause).chain(&group_by);
+                self.check_columns(&from, exprs.collect())?;
+
+                // 子查询在执行之前替换成常量，没有别名时使用子查询本身作为列名
+                let select = select
+                    .into_iter()
+                    .map(|(expr, alias)| match alias {
+                        None if expr.has_subquery() => {
+                            let alias = expr.to_string();
+                            (expr, Some(alias))
+                        }
+                        alias => (expr, alias),
+                    })
+                    .collect::<Vec<_>>();
+
+                // select * 不返回隐藏的 _rowid 列，展开成其他所有列
+                let select = match self.expand_star(&from, &select)? {
+                    Some(columns) => columns
+                        .into_iter()
+                        .map(|c| (Expression::Field(None, c), None))
+                        .collect(),
+                    None => select,
+                };
+
+                // order by 中 select 的位置和别名替换成对应的表达式
+                let mut order_by = resolve_order_by(order_by, &select)?;
+
                 // from
+                let single_table = matches!(from, ast::FromItem::Table { .. });
                 let mut node = self.build_from_item(from, &where_clause)?;
+                if single_table && !select.is_empty() {
+                    let mut exprs = select.iter().map(|(e, _)| e).collect::<Vec<_>>();
+                    exprs.extend(where_clause.iter().chain(&group_by).chain(&having));
+                    exprs.extend(order_by.iter().map(|(e, _)| e));
+                    let mut names = Vec::new();
+                    for expr in exprs {
+                        expr.walk(&mut |e| match e {
+                            Expression::Field(_, name) | Expression::Function(_, name) => {
+                                names.push(name.clone())
+                            }
+                            _ => {}
+                        });
+                    }
+                    self.prune_scan_columns(&mut node, names)?;
+                }
 
                 let mut has_agg = false;
                 // aggregate、group by
@@ -83,7 +184,7 @@ impl<'a, T: Transaction> Planner<'a, T> {
                             break;
                         }
                     }
-                    if group_by.is_some() {
+                    if !group_by.is_empty() {
                         has_agg = true;
                     }
                     if has_agg {
@@ -95,12 +196,35 @@ impl<'a, T: Transaction> Planner<'a, T> {
                     }
                 }
 
+                // having、order by 中的聚集函数改写成引用 Aggregate 输出的列
+                let mut having = having;
+                let mut outputs = Vec::new();
+                if let Node::Aggregate { exprs, .. } = &mut node {
+                    outputs = exprs.iter().map(|(e, alias)| output_name(e, alias)).collect();
+                    let len = exprs.len();
+                    for expr in having.iter_mut() {
+                        rewrite_aggregates(expr, exprs)?;
+                    }
+                    for (expr, _) in order_by.iter_mut() {
+                        // 和 select 中的表达式相同时直接引用输出的列，例如 select b as x ... order by x
+                        match exprs[..len].iter().find(|(e, _)| e == expr) {
+                            Some((e, alias)) => {
+                                *expr = Expression::Field(None, output_name(e, alias))
+                            }
+                            None => rewrite_aggregates(expr, exprs)?,
+                        }
+                    }
+                    if exprs.len() == len {
+                        outputs.clear();
+                    }
+                }
+
                 // having
                 if let Some(expr) = having {
                     node = Node::Filter {
                         source: Box::new(node),
                         predicate: expr,
-                    }
+                    };
                 }
 
                 // order by
@@ -111,30 +235,36 @@ impl<'a, T: Transaction> Planner<'a, T> {
                     }
                 }
 
-                // offset
-                if let Some(expr) = offset {
-                    node = Node::Offset {
+                // 去掉 select 中没有、只在 having、order by 中用到的聚集函数的列
+                if !outputs.is_empty() {
+                    node = Node::Projection {
                         source: Box::new(node),
-                        offset: match Value::from_expression(expr) {
-                            Value::Integer(i) => i as usize,
-                            _ => return Err(Error::Internal("invalid offset".into())),
-                        },
-                    }
+                        exprs: outputs
+                            .into_iter()
+                            .map(|name| (Expression::Field(None, name), None))
+                            .collect(),
+                    };
                 }
 
-                // limit
-                if let Some(expr) = limit {
-                    node = Node::Limit {
-                        source: Box::new(node),
-                        limit: match Value::from_expression(expr) {
-                            Value::Integer(i) => i as usize,
-                            _ => return Err(Error::Internal("invalid limit".into())),
-                        },
+                // distinct，在投影之后去重，offset、limit 作用在去重之后的结果上
+                let mut projected = has_agg || select.is_empty();
+                if distinct {
+                    if !projected {
+                        node = Node::Projection {
+                            source: Box::new(node),
+                            exprs: select.clone(),
+                        };
+                        projected = true;
                     }
+                    node = Node::Distinct {
+                        source: Box::new(node),
+                    };
                 }
 
+                node = build_offset_limit(node, offset, limit)?;
+
                 // projection
-                if !select.is_empty() && !has_agg {
+                if !projected {
                     node = Node::Projection {
                         source: Box::new(node),
                         exprs: select,
@@ -143,34 +273,338 @@ impl<'a, T: Transaction> Planner<'a, T> {
 
                 node
             }
+            ast::Statement::SetOperation {
+                op,
+                all,
+                left,
+                right,
+                order_by,
+                limit,
+                offset,
+            } => {
+                // 结果的列名取左边的列名，order by 只能引用这些列
+                let mut node = Node::SetOperation {
+                    op,
+                    all,
+                    left: Box::new(self.build_statment(*left)?),
+                    right: Box::new(self.build_statment(*right)?),
+                };
+                if !order_by.is_empty() {
+                    node = Node::Order {
+                        source: Box::new(node),
+                        order_by,
+                    }
+                }
+                build_offset_limit(node, offset, limit)?
+            }
             ast::Statement::Update {
                 table_name,
                 columns,
+                from,
                 where_clause,
-            } => Node::Update {
-                table_name: table_name.clone(),
-                source: Box::new(self.build_scan(table_name, where_clause)?),
-                columns,
-            },
+            } => {
+                let target = ast::FromItem::Table {
+                    name: table_name.clone(),
+                    alias: None,
+                    sample: None,
+                };
+                let exprs = columns.values().chain(&where_clause).collect();
+                check_qualifiers(&target, from.iter().collect(), exprs)?;
+                let source = match from {
+                    Some(from) => self.build_mutation_join(&table_name, from, where_clause)?,
+                    None => self.build_scan(table_name.clone(), where_clause)?,
+                };
+                Node::Update {
+                    table_name,
+                    source: Box::new(source),
+                    columns,
+                }
+            }
             ast::Statement::Delete {
                 table_name,
+                using,
                 where_clause,
-            } => Node::Delete {
-                table_name: table_name.clone(),
-                source: Box::new(self.build_scan(table_name, where_clause)?),
+            } => {
+                let target = ast::FromItem::Table {
+                    name: table_name.clone(),
+                    alias: None,
+                    sample: None,
+                };
+                check_qualifiers(&target, using.iter().collect(), where_clause.iter().collect())?;
+                let source = match using {
+                    Some(using) => self.build_mutation_join(&table_name, using, where_clause)?,
+                    None => self.build_scan(table_name.clone(), where_clause)?,
+                };
+                Node::Delete {
+                    table_name,
+                    source: Box::new(source),
+                }
+            }
+            ast::Statement::Copy {
+                table_name,
+                path,
+                direction: ast::CopyDirection::From,
+                options,
+            } => Node::CopyFrom {
+                table_name,
+                path,
+                options,
+            },
+            ast::Statement::Copy {
+                table_name,
+                path,
+                direction: ast::CopyDirection::To,
+                options,
+            } => Node::CopyTo {
+                table_name,
+                path,
+                options,
             },
-            ast::Statement::Begin | ast::Statement::Commit | ast::Statement::Rollback => {
+            ast::Statement::Begin { .. }
+            | ast::Statement::Commit
+            | ast::Statement::Rollback
+            | ast::Statement::Savepoint { .. }
+            | ast::Statement::RollbackToSavepoint { .. }
+            | ast::Statement::ReleaseSavepoint { .. } => {
                 return Err(Error::Internal("unexpected transaction command".into()));
             }
-            ast::Statement::Explain { stmt: _ } => {
+            ast::Statement::Explain { .. } => {
                 return Err(Error::Internal("unexpected explain command".into()));
             }
+            ast::Statement::Set { .. } => {
+                return Err(Error::Internal("unexpected set command".into()));
+            }
+            ast::Statement::Vacuum => {
+                return Err(Error::Internal("unexpected vacuum command".into()));
+            }
+            ast::Statement::Backup { .. } | ast::Statement::Restore { .. } => {
+                return Err(Error::Internal("unexpected backup command".into()));
+            }
+            ast::Statement::ShowProfile => {
+                return Err(Error::Internal("unexpected show command".into()));
+            }
+            ast::Statement::DeclareCursor { .. }
+            | ast::Statement::Fetch { .. }
+            | ast::Statement::CloseCursor { .. } => {
+                return Err(Error::Internal("unexpected cursor command".into()));
+            }
+        })
+    }
+
+    // select、where、group by 中的列必须是 from 中的列，from 的列都能提前知道时在这里检查
+    // 子查询单独生成执行计划，不带表名引用外层查询的列时即使子查询没有数据也会报错
+    fn check_columns(&self, from: &ast::FromItem, exprs: Vec<&Expression>) -> Result<()> {
+        let qualify = !matches!(from, ast::FromItem::Table { .. });
+        let columns = match self.star_columns(from, qualify)? {
+            Some(columns) => columns.into_iter().map(|(name, _)| name).collect::<Vec<_>>(),
+            None => return Ok(()),
+        };
+        let mut result = Ok(());
+        for expr in exprs {
+            expr.walk(&mut |e| {
+                if let Expression::Field(table, name) = e {
+                    if result.is_ok() {
+                        result = ast::field_position(&columns, table.as_deref(), name).map(|_| ());
+                    }
+                }
+            });
+        }
+        result
+    }
+
+    // 单表查询只用到了部分列时，扫描时只解码这些列
+    // select * 并且 from 中的表包含隐藏列时，返回应该输出的列
+    fn expand_star(
+        &self,
+        from: &ast::FromItem,
+        select: &[(Expression, Option<String>)],
+    ) -> Result<Option<Vec<String>>> {
+        if !select.is_empty() {
+            return Ok(None);
+        }
+        let columns = match self.star_columns(from, false)? {
+            Some(columns) if columns.iter().any(|(_, hidden)| *hidden) => columns,
+            _ => return Ok(None),
+        };
+        Ok(Some(
+            columns
+                .into_iter()
+                .filter(|(_, hidden)| !hidden)
+                .map(|(name, _)| name)
+                .collect(),
+        ))
+    }
+
+    // from 输出的所有列以及是否是隐藏列，join 中的列名带有表名前缀，子查询的列无法提前知道，返回 None
+    fn star_columns(&self, from: &ast::FromItem, qualify: bool) -> Result<Option<Vec<(String, bool)>>> {
+        Ok(match from {
+            // 表不存在时由扫描节点报错
+            ast::FromItem::Table { name, alias, .. } => self.txn.get_table(name.clone())?.map(|table| {
+                let prefix = alias.as_ref().unwrap_or(name);
+                table
+                    .columns
+                    .iter()
+                    .map(|c| match qualify {
+                        true => (format!("{}.{}", prefix, c.name), c.is_hidden()),
+                        false => (c.name.clone(), c.is_hidden()),
+                    })
+                    .collect()
+            }),
+            ast::FromItem::Derived { .. } => None,
+            ast::FromItem::Unnest { alias, .. } => Some(vec![(alias.clone(), false)]),
+            ast::FromItem::Join {
+                left,
+                right,
+                join_type,
+                ..
+            } => {
+                // right join 交换了左右两边
+                let (left, right) = match join_type {
+                    ast::JoinType::Right => (right, left),
+                    _ => (left, right),
+                };
+                match (self.star_columns(left, true)?, self.star_columns(right, true)?) {
+                    (Some(mut l), Some(r)) => {
+                        l.extend(r);
+                        Some(l)
+                    }
+                    _ => None,
+                }
+            }
         })
     }
 
+    fn prune_scan_columns(&self, node: &mut Node, names: Vec<String>) -> Result<()> {
+        match node {
+            // 索引查找之后再用其余条件过滤，过滤条件中的列也在 names 中
+            Node::Filter { source, .. } => return self.prune_scan_columns(source, names),
+            Node::IndexScan {
+                table_name,
+                field,
+                columns,
+                ..
+            }
+            | Node::RangeIndexScan {
+                table_name,
+                field,
+                columns,
+                ..
+            } => {
+                *columns = self.covered_columns(table_name, field, &names)?;
+                return Ok(());
+            }
+            _ => {}
+        }
+        if let Node::Scan {
+            table_name,
+            columns,
+            ..
+        } = node
+        {
+            let names = names
+                .iter()
+                .map(|n| n.rsplit_once('.').map_or(n.as_str(), |(_, c)| c))
+                .collect::<Vec<_>>();
+            let table = self.txn.must_get_table(table_name.clone())?;
+            let mut used = table
+                .columns
+                .iter()
+                .filter(|c| names.contains(&c.name.as_str()))
+                .map(|c| c.name.clone())
+                .collect::<Vec<_>>();
+            if used.len() == table.columns.len() {
+                return Ok(());
+            }
+            // 没有用到任何列时，依然需要知道有多少行
+            if used.is_empty() {
+                used.push(table.columns[0].name.clone());
+            }
+            *columns = Some(used);
+        }
+        Ok(())
+    }
+
+    // 用到的列只有索引列和主键列时，索引中保存了列值和对应的主键，可以直接从索引得到结果
+    // 返回用到的列，没有用到任何列时只输出索引列
+    fn covered_columns(&self, table_name: &str, field: &str, names: &[String]) -> Result<Option<Vec<String>>> {
+        let table = self.txn.must_get_table(table_name.into())?;
+        let mut used = Vec::new();
+        for name in names.iter().map(|n| n.rsplit_once('.').map_or(n.as_str(), |(_, c)| c)) {
+            let col = match table.columns.iter().find(|c| c.name == name) {
+                Some(col) => col,
+                None => continue,
+            };
+            if col.name != field && !col.primary_key {
+                return Ok(None);
+            }
+            if !used.contains(&col.name) {
+                used.push(col.name.clone());
+            }
+        }
+        if used.is_empty() {
+            used.push(field.to_string());
+        }
+        Ok(Some(used))
+    }
+
     fn build_from_item(&self, item: ast::FromItem, filter: &Option<Expression>) -> Result<Node> {
         Ok(match item {
-            ast::FromItem::Table { name } => self.build_scan(name, filter.clone())?,
+            ast::FromItem::Table {
+                name,
+                alias,
+                sample,
+            } => {
+                // 扫描节点中的列名没有表名前缀，去掉条件中的表名或者别名之后才能匹配主键和索引
+                let filter = filter.clone().map(strip_qualifier).transpose()?;
+                let node = self.build_scan(name, filter)?;
+                let node = match sample {
+                    // 先过滤再采样，和先采样再过滤的结果分布相同
+                    Some(sample) => Node::Sample {
+                        source: Box::new(node),
+                        percent: sample.percent,
+                        seed: sample.seed,
+                    },
+                    None => node,
+                };
+                // 有别名时和子查询一样，join 中的列名使用别名作为前缀
+                match alias {
+                    Some(alias) => Node::Derived {
+                        source: Box::new(node),
+                        alias,
+                    },
+                    None => node,
+                }
+            }
+            ast::FromItem::Derived { query, alias } => {
+                let node = Node::Derived {
+                    source: Box::new(self.build_statment(*query)?),
+                    alias,
+                };
+                // where 条件作用在子查询的结果上
+                match filter {
+                    Some(predicate) => Node::Filter {
+                        source: Box::new(node),
+                        predicate: predicate.clone(),
+                    },
+                    None => node,
+                }
+            }
+            ast::FromItem::Unnest { expr, alias } => {
+                let node = Node::Unnest {
+                    source: None,
+                    expr,
+                    alias,
+                    predicate: None,
+                    outer: false,
+                };
+                match filter {
+                    Some(predicate) => Node::Filter {
+                        source: Box::new(node),
+                        predicate: predicate.clone(),
+                    },
+                    None => node,
+                }
+            }
             ast::FromItem::Join {
                 left,
                 right,
@@ -183,81 +617,785 @@ impl<'a, T: Transaction> Planner<'a, T> {
                     _ => (left, right),
                 };
 
-                let outer = match join_type {
-                    ast::JoinType::Cross | ast::JoinType::Inner => false,
-                    _ => true,
-                };
+                let outer = !matches!(join_type, ast::JoinType::Cross | ast::JoinType::Inner);
 
-                if join_type == ast::JoinType::Cross {
+                // 右边是 unnest 时对左边的每一行展开，unnest 可以引用左边的列
+                // where 条件可能引用展开的列，不能下推到左边，作用在展开的结果上
+                if let ast::FromItem::Unnest { expr, alias } = *right {
+                    let node = Node::Unnest {
+                        source: Some(Box::new(self.build_from_item(*left, &None)?)),
+                        expr,
+                        alias,
+                        predicate,
+                        outer,
+                    };
+                    return Ok(match filter {
+                        Some(predicate) => Node::Filter {
+                            source: Box::new(node),
+                            predicate: predicate.clone(),
+                        },
+                        None => node,
+                    });
+                }
+
+                // 只引用一边的列的条件下推到这一边，过滤之后再 join
+                // 外连接中右边没有匹配的行需要补 NULL 输出：on 中只引用右边的条件可以下推到右边，
+                // where 中只引用右边的条件需要在补 NULL 之后过滤，不能下推；左边的情况相反
+                let (mut lfilter, mut rfilter, mut rest) = (Vec::new(), Vec::new(), Vec::new());
+                if let Some(expr) = predicate {
+                    let [l, r, both] = self.split_join_filter(&left, &right, expr)?;
+                    rfilter.extend(r);
+                    match outer {
+                        true => rest.extend(l),
+                        false => lfilter.extend(l),
+                    }
+                    rest.extend(both);
+                }
+                let predicate = (!rest.is_empty()).then(|| join_conjuncts(rest));
+                let mut rest = Vec::new();
+                if let Some(expr) = filter.clone() {
+                    let [l, r, both] = self.split_join_filter(&left, &right, expr)?;
+                    lfilter.extend(l);
+                    match outer {
+                        true => rest.extend(r),
+                        false => rfilter.extend(r),
+                    }
+                    rest.extend(both);
+                }
+                let filter = (!rest.is_empty()).then(|| join_conjuncts(rest));
+
+                // IS DISTINCT FROM 不是等值条件，不能使用 HashJoin
+                let hashable = matches!(
+                    predicate,
+                    Some(Expression::Operation(
+                        ast::Operation::Equal(..) | ast::Operation::IsNotDistinctFrom(..)
+                    ))
+                );
+                let lfilter = (!lfilter.is_empty()).then(|| join_conjuncts(lfilter));
+                let rfilter = (!rfilter.is_empty()).then(|| join_conjuncts(rfilter));
+                let left = Box::new(self.build_from_item(*left, &lfilter)?);
+                let right = Box::new(self.build_from_item(*right, &rfilter)?);
+                let node = if join_type == ast::JoinType::Cross || !hashable {
                     Node::NestedLoopJoin {
-                        left: Box::new(self.build_from_item(*left, filter)?),
-                        right: Box::new(self.build_from_item(*right, filter)?),
+                        left,
+                        right,
                         predicate,
                         outer,
                     }
                 } else {
                     Node::HashJoin {
-                        left: Box::new(self.build_from_item(*left, filter)?),
-                        right: Box::new(self.build_from_item(*right, filter)?),
+                        left,
+                        right,
                         predicate,
                         outer,
                     }
+                };
+                match filter {
+                    Some(predicate) => Node::Filter {
+                        source: Box::new(node),
+                        predicate,
+                    },
+                    None => node,
                 }
             }
         })
     }
 
+    // 把 AND 连接的条件分成只引用左边的列、只引用右边的列以及其他的条件三部分
+    // 条件中的列必须能确定属于哪一边，子查询的列名无法提前知道，这时不拆分；
+    // 带有子查询或者 volatile 函数的条件，以及不引用任何列的条件都留在 join 上
+    fn split_join_filter(
+        &self,
+        left: &ast::FromItem,
+        right: &ast::FromItem,
+        expr: Expression,
+    ) -> Result<[Vec<Expression>; 3]> {
+        let mut conjuncts = Vec::new();
+        split_conjuncts(expr, &mut conjuncts);
+        let names = |item| -> Result<Option<Vec<String>>> {
+            Ok(self
+                .star_columns(item, true)?
+                .map(|columns| columns.into_iter().map(|(name, _)| name).collect()))
+        };
+        let (lcols, rcols) = match (names(left)?, names(right)?) {
+            (Some(l), Some(r)) => (l, r),
+            _ => return Ok([Vec::new(), Vec::new(), conjuncts]),
+        };
+
+        let mut result = [Vec::new(), Vec::new(), Vec::new()];
+        for conjunct in conjuncts {
+            let mut sides = Vec::new();
+            if !conjunct.has_subquery() && !functions::has_volatile(&conjunct) {
+                conjunct.walk(&mut |e| {
+                    if let Expression::Field(table, name) = e {
+                        let l = ast::field_position(&lcols, table.as_deref(), name).is_ok();
+                        let r = ast::field_position(&rcols, table.as_deref(), name).is_ok();
+                        sides.push(match (l, r) {
+                            (true, false) => Some(0),
+                            (false, true) => Some(1),
+                            _ => None,
+                        });
+                    }
+                });
+            }
+            let side = match sides.split_first() {
+                Some((first, rest)) if rest.iter().all(|s| s == first) => first.unwrap_or(2),
+                _ => 2,
+            };
+            result[side].push(conjunct);
+        }
+        Ok(result)
+    }
+
+    // update ... from、delete ... using 的数据来源：要修改的表和 from 中的表 join，输出的列带有表名前缀
+    // 只引用一边的列的条件下推到这一边，其余条件中两边列的等值比较作为 HashJoin 的条件，再在 join 的结果上过滤
+    fn build_mutation_join(
+        &self,
+        table_name: &str,
+        from: ast::FromItem,
+        where_clause: Option<Expression>,
+    ) -> Result<Node> {
+        let target = ast::FromItem::Table {
+            name: table_name.into(),
+            alias: None,
+            sample: None,
+        };
+        let [lfilter, rfilter, mut conjuncts] = match where_clause {
+            Some(expr) => self.split_join_filter(&target, &from, expr)?,
+            None => Default::default(),
+        };
+        let lfilter = (!lfilter.is_empty()).then(|| join_conjuncts(lfilter));
+        let rfilter = (!rfilter.is_empty()).then(|| join_conjuncts(rfilter));
+        let left = Box::new(self.build_from_item(target, &lfilter)?);
+        let right = Box::new(self.build_from_item(from, &rfilter)?);
+        // 取第一个两边列的等值比较作为 join 条件
+        let equal = conjuncts.iter().position(|c| match c {
+            Expression::Operation(ast::Operation::Equal(l, r)) => {
+                matches!((&**l, &**r), (Expression::Field(..), Expression::Field(..)))
+            }
+            _ => false,
+        });
+        let node = match equal {
+            Some(i) => Node::HashJoin {
+                left,
+                right,
+                predicate: Some(conjuncts.remove(i)),
+                outer: false,
+            },
+            None => Node::NestedLoopJoin {
+                left,
+                right,
+                predicate: None,
+                outer: false,
+            },
+        };
+        Ok(match conjuncts.is_empty() {
+            true => node,
+            false => Node::Filter {
+                source: Box::new(node),
+                predicate: join_conjuncts(conjuncts),
+            },
+        })
+    }
+
     fn build_scan(&self, table_name: String, filter: Option<Expression>) -> Result<Node> {
-        Ok(match Self::parse_scan_filter(filter.clone()) {
-            Some((field, value)) => {
-                let table = self.txn.must_get_table(table_name.clone())?;
+        if information_schema::is_information_schema(&table_name) {
+            self.txn.must_get_table(table_name.clone())?;
+            return Ok(Node::VirtualScan { table_name, filter });
+        }
+        if let Some(node) = self.build_index_scan(&table_name, &filter)? {
+            return Ok(node);
+        }
+        let mut conjuncts = Vec::new();
+        if let Some(expr) = &filter {
+            split_conjuncts(expr.clone(), &mut conjuncts);
+        }
+        // 复合主键的每一列都有等值条件时，组成完整的主键查找
+        if let Some((node, used)) = self.build_composite_key_scan(&table_name, &conjuncts)? {
+            return Ok(filter_rest(node, conjuncts, &used));
+        }
+        // AND 连接的多个条件，选择其中一个可以走主键或者索引的条件，优先使用主键，
+        // 其余的条件在扫描的结果上过滤
+        if conjuncts.len() > 1 {
+            let mut best = None;
+            for (i, conjunct) in conjuncts.iter().enumerate() {
+                if let Some(node) = self.build_index_scan(&table_name, &Some(conjunct.clone()))? {
+                    let primary = matches!(node, Node::PrimaryKeyScan { .. });
+                    if best.is_none() || primary {
+                        best = Some((i, node));
+                    }
+                    if primary {
+                        break;
+                    }
+                }
+            }
+            if let Some((i, node)) = best {
+                conjuncts.remove(i);
+                return Ok(Node::Filter {
+                    source: Box::new(node),
+                    predicate: join_conjuncts(conjuncts),
+                });
+            }
+        }
+        // 没有等值条件可用时，尝试索引列上的范围条件
+        if let Some((node, used)) = self.build_range_scan(&table_name, &conjuncts)? {
+            return Ok(filter_rest(node, conjuncts, &used));
+        }
+        Ok(Node::Scan {
+            table_name,
+            filter,
+            columns: None,
+            limit: None,
+        })
+    }
 
-                // 判断是否是主键
-                if table
-                    .columns
-                    .iter()
-                    .position(|c| c.name == field && c.primary_key)
-                    .is_some()
-                {
-                    return Ok(Node::PrimaryKeyScan { table_name, value });
+    // 复合主键的每一列都有和常量的等值条件时，返回主键查找节点以及用到的条件
+    fn build_composite_key_scan(
+        &self,
+        table_name: &str,
+        conjuncts: &[Expression],
+    ) -> Result<Option<(Node, Vec<usize>)>> {
+        if conjuncts.len() < 2 || sys::is_sys_table(table_name) {
+            return Ok(None);
+        }
+        let table = self.txn.must_get_table(table_name.into())?;
+        if !table.is_composite_key() {
+            return Ok(None);
+        }
+        let mut values = Vec::new();
+        let mut used = Vec::new();
+        for pos in table.primary_key_positions() {
+            let name = &table.columns[pos].name;
+            let found = conjuncts.iter().enumerate().find_map(|(i, c)| {
+                match Self::parse_scan_filter(Some(c.clone())) {
+                    Some((field, value)) if field == *name => Some((i, value)),
+                    _ => None,
+                }
+            });
+            match found {
+                Some((i, value)) => {
+                    used.push(i);
+                    values.push(value);
                 }
+                None => return Ok(None),
+            }
+        }
+        let node = Node::PrimaryKeyScan {
+            table_name: table_name.into(),
+            value: Value::Array(values.into()),
+        };
+        Ok(Some((node, used)))
+    }
 
-                match table
-                    .columns
+    // 整个 where 条件可以通过主键、索引查找时返回对应的扫描节点
+    fn build_index_scan(&self, table_name: &str, filter: &Option<Expression>) -> Result<Option<Node>> {
+        if let Some(node) = self.build_prefix_scan(table_name, filter)? {
+            return Ok(Some(node));
+        }
+        if let Some(node) = self.build_fulltext_scan(table_name, filter)? {
+            return Ok(Some(node));
+        }
+        if let Some(node) = self.build_bitmap_scan(table_name, filter)? {
+            return Ok(Some(node));
+        }
+        let (field, value) = match Self::parse_scan_filter(filter.clone()) {
+            Some(f) => f,
+            None => return Ok(None),
+        };
+        let table = self.txn.must_get_table(table_name.into())?;
+        let table_name = table_name.to_string();
+
+        // 判断是否是主键，复合主键的单个列不能直接查找
+        if !table.is_composite_key()
+            && table
+                .columns
+                .iter()
+                .any(|c| c.name == field && c.primary_key)
+        {
+            return Ok(Some(Node::PrimaryKeyScan { table_name, value }));
+        }
+
+        // 全文索引不能用于等值查找
+        Ok(table
+            .columns
+            .iter()
+            .any(|c| c.name == field && c.index && c.index_type != IndexType::FullText)
+            .then_some(Node::IndexScan {
+                table_name,
+                field,
+                value,
+                limit: None,
+                columns: None,
+            }))
+    }
+
+    // where 条件是 col LIKE 'ab%' 并且 col 是字符串类型的主键或者索引列时，只扫描前缀为 ab 的数据
+    // 模式不是 前缀% 的形式时，扫描的结果还需要再按照 LIKE 条件过滤
+    // match(col, 'words') 并且 col 上有全文索引时，使用全文索引查找
+    fn build_fulltext_scan(
+        &self,
+        table_name: &str,
+        filter: &Option<Expression>,
+    ) -> Result<Option<Node>> {
+        let (field, query) = match filter {
+            Some(Expression::Call(name, args)) if name == "match" => match args.as_slice() {
+                [Expression::Field(_, f), Expression::Consts(ast::Consts::String(q))] => (f, q),
+                _ => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        if sys::is_sys_table(table_name) {
+            return Ok(None);
+        }
+        let table = self.txn.must_get_table(table_name.into())?;
+        match table.columns.iter().find(|c| &c.name == field) {
+            Some(col) if col.index && col.index_type == IndexType::FullText => {
+                Ok(Some(Node::FullTextScan {
+                    table_name: table_name.into(),
+                    field: field.clone(),
+                    query: query.clone(),
+                }))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    // where 条件只包含位图索引列上的等值条件时，合并位图之后再读取行
+    fn build_bitmap_scan(
+        &self,
+        table_name: &str,
+        filter: &Option<Expression>,
+    ) -> Result<Option<Node>> {
+        let filter = match filter {
+            Some(filter) if !sys::is_sys_table(table_name) => filter,
+            _ => return Ok(None),
+        };
+        let table = self.txn.must_get_table(table_name.into())?;
+        Ok(
+            Self::bitmap_condition(&table, filter).map(|condition| Node::BitmapScan {
+                table_name: table_name.into(),
+                condition,
+            }),
+        )
+    }
+
+    // AND、OR 连接的条件全部是位图索引列上的等值条件时，分别对位图求交集、并集
+    // col IN (v1, v2) 等价于 col = v1 OR col = v2
+    fn bitmap_condition(table: &Table, expr: &Expression) -> Option<BitmapCondition> {
+        match expr {
+            Expression::Operation(ast::Operation::In(field, list)) => {
+                return list
                     .iter()
-                    .position(|c| c.name == field && c.index)
-                {
-                    Some(_) => Node::IndexScan {
-                        table_name,
-                        field,
-                        value,
-                    },
-                    None => Node::Scan { table_name, filter },
+                    .map(|item| {
+                        let equal = ast::Operation::Equal(field.clone(), Box::new(item.clone()));
+                        Self::bitmap_condition(table, &Expression::Operation(equal))
+                    })
+                    .reduce(|l, r| Some(BitmapCondition::Or(Box::new(l?), Box::new(r?))))?;
+            }
+            Expression::Operation(ast::Operation::And(l, r)) => {
+                return Some(BitmapCondition::And(
+                    Box::new(Self::bitmap_condition(table, l)?),
+                    Box::new(Self::bitmap_condition(table, r)?),
+                ))
+            }
+            Expression::Operation(ast::Operation::Or(l, r)) => {
+                return Some(BitmapCondition::Or(
+                    Box::new(Self::bitmap_condition(table, l)?),
+                    Box::new(Self::bitmap_condition(table, r)?),
+                ))
+            }
+            _ => {}
+        }
+        let (field, value) = Self::parse_scan_filter(Some(expr.clone()))?;
+        table
+            .columns
+            .iter()
+            .any(|c| c.name == field && c.index && c.index_type == IndexType::Bitmap)
+            .then_some(BitmapCondition::Equal(field, value))
+    }
+
+    fn build_prefix_scan(
+        &self,
+        table_name: &str,
+        filter: &Option<Expression>,
+    ) -> Result<Option<Node>> {
+        let (field, pattern) = match filter {
+            Some(Expression::Operation(ast::Operation::Like(l, r))) => match (&**l, &**r) {
+                (Expression::Field(_, f), Expression::Consts(ast::Consts::String(p))) => (f, p),
+                _ => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        let prefix = ast::like_prefix(pattern);
+        if prefix.is_empty() || sys::is_sys_table(table_name) {
+            return Ok(None);
+        }
+        let table = self.txn.must_get_table(table_name.into())?;
+        let col = match table.columns.iter().find(|c| &c.name == field) {
+            Some(col) if col.datatype == DataType::String => col,
+            _ => return Ok(None),
+        };
+        let source = if col.primary_key && !table.is_composite_key() {
+            Node::PrimaryKeyPrefixScan {
+                table_name: table_name.into(),
+                prefix: prefix.into(),
+            }
+        } else if col.index && col.index_type == IndexType::Ordered {
+            // 哈希索引不保留列值的顺序，无法按前缀查找
+            Node::IndexPrefixScan {
+                table_name: table_name.into(),
+                field: field.clone(),
+                prefix: prefix.into(),
+            }
+        } else {
+            return Ok(None);
+        };
+        if pattern.len() == prefix.len() + 1 && pattern.ends_with('%') {
+            return Ok(Some(source));
+        }
+        Ok(Some(Node::Filter {
+            source: Box::new(source),
+            predicate: filter.clone().unwrap(),
+        }))
+    }
+
+    // 有序索引列和常量的 >、<、BETWEEN 条件可以按照索引范围扫描，同一列上的多个条件合并为一个范围
+    // 返回扫描节点和用到的条件的位置，常量按照列的类型转换，无法转换时不走索引
+    fn build_range_scan(
+        &self,
+        table_name: &str,
+        conjuncts: &[Expression],
+    ) -> Result<Option<(Node, Vec<usize>)>> {
+        if conjuncts.is_empty() || sys::is_sys_table(table_name) {
+            return Ok(None);
+        }
+        let table = self.txn.must_get_table(table_name.into())?;
+        let mut range: Option<(String, Bound<Value>, Bound<Value>)> = None;
+        let mut used = Vec::new();
+        for (i, conjunct) in conjuncts.iter().enumerate() {
+            let (field, start, end) = match Self::parse_range_filter(&table, conjunct) {
+                Some(r) => r,
+                None => continue,
+            };
+            match &mut range {
+                None => range = Some((field, start, end)),
+                Some((f, s, e)) if *f == field => {
+                    *s = tighter_bound(s.clone(), start, true);
+                    *e = tighter_bound(e.clone(), end, false);
                 }
+                Some(_) => continue,
             }
-            None => Node::Scan { table_name, filter },
-        })
+            used.push(i);
+        }
+        Ok(range.map(|(field, start, end)| {
+            let node = Node::RangeIndexScan {
+                table_name: table_name.into(),
+                field,
+                start,
+                end,
+                columns: None,
+            };
+            (node, used)
+        }))
+    }
+
+    fn parse_range_filter(
+        table: &Table,
+        expr: &Expression,
+    ) -> Option<(String, Bound<Value>, Bound<Value>)> {
+        use Bound::*;
+        let value = |e: &Expression| match e {
+            Expression::Consts(c) => Some(Value::from_expression(Expression::Consts(c.clone()))),
+            _ => None,
+        };
+        let (field, start, end) = match expr {
+            Expression::Operation(ast::Operation::GreaterThan(l, r)) => match (&**l, &**r) {
+                (Expression::Field(_, f), c) => (f, Excluded(value(c)?), Unbounded),
+                (c, Expression::Field(_, f)) => (f, Unbounded, Excluded(value(c)?)),
+                _ => return None,
+            },
+            Expression::Operation(ast::Operation::LessThan(l, r)) => match (&**l, &**r) {
+                (Expression::Field(_, f), c) => (f, Unbounded, Excluded(value(c)?)),
+                (c, Expression::Field(_, f)) => (f, Excluded(value(c)?), Unbounded),
+                _ => return None,
+            },
+            Expression::Operation(ast::Operation::Between(e, low, high)) => match &**e {
+                Expression::Field(_, f) => (f, Included(value(low)?), Included(value(high)?)),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        let col = table.columns.iter().find(|c| &c.name == field)?;
+        if !col.index || col.primary_key || col.index_type != IndexType::Ordered {
+            return None;
+        }
+        // 和 NULL 比较的结果永远不为真，交给普通的过滤处理
+        let coerce = |bound: Bound<Value>| match bound {
+            Included(v) => coerce_lookup(&v, &col.datatype).filter(|v| *v != Value::Null).map(Included),
+            Excluded(v) => coerce_lookup(&v, &col.datatype).filter(|v| *v != Value::Null).map(Excluded),
+            Unbounded => Some(Unbounded),
+        };
+        Some((field.clone(), coerce(start)?, coerce(end)?))
     }
 
+    // where 条件是列和常量的等值比较时，可以走主键或者索引
+    // = NULL 的结果永远不为真，只有 IS NOT DISTINCT FROM NULL 可以通过索引查找 NULL 值
     fn parse_scan_filter(filter: Option<Expression>) -> Option<(String, Value)> {
-        match filter {
-            Some(expr) => match expr {
-                Expression::Field(f) => Some((f, Value::Null)),
-                Expression::Consts(c) => {
-                    Some(("".into(), Value::from_expression(Expression::Consts(c))))
+        let (l, r, null_safe) = match filter? {
+            Expression::Operation(ast::Operation::Equal(l, r)) => (l, r, false),
+            Expression::Operation(ast::Operation::IsNotDistinctFrom(l, r)) => (l, r, true),
+            _ => return None,
+        };
+        let (field, value) = match (*l, *r) {
+            (Expression::Field(_, f), Expression::Consts(c))
+            | (Expression::Consts(c), Expression::Field(_, f)) => {
+                (f, Value::from_expression(Expression::Consts(c)))
+            }
+            _ => return None,
+        };
+        if value == Value::Null && !null_safe {
+            return None;
+        }
+        Some((field, value))
+    }
+
+}
+
+// 在 node 上加上 offset、limit 节点，limit 作用在跳过 offset 行之后的结果上
+fn build_offset_limit(
+    mut node: Node,
+    offset: Option<Expression>,
+    limit: Option<Expression>,
+) -> Result<Node> {
+    let mut skip = 0;
+    if let Some(expr) = offset {
+        skip = match Value::from_expression(expr) {
+            Value::Integer(i) => i as usize,
+            _ => return Err(Error::InvalidInput("invalid offset".into())),
+        };
+        node = Node::Offset {
+            source: Box::new(node),
+            offset: skip,
+        }
+    }
+    if let Some(expr) = limit {
+        let limit = match Value::from_expression(expr) {
+            Value::Integer(i) => i as usize,
+            _ => return Err(Error::InvalidInput("invalid limit".into())),
+        };
+        push_down_limit(&mut node, limit.saturating_add(skip));
+        node = Node::Limit {
+            source: Box::new(node),
+            limit,
+        }
+    }
+    Ok(node)
+}
+
+// limit 下推到扫描节点，扫描读取到 offset + limit 行之后停止，上层的 Offset、Limit 节点保持不变
+// 扫描带有过滤条件时读取的行数和输出的行数不同，不下推
+fn push_down_limit(node: &mut Node, rows: usize) {
+    match node {
+        Node::Offset { source, .. } => push_down_limit(source, rows),
+        Node::Scan {
+            filter: None,
+            limit,
+            ..
+        }
+        | Node::IndexScan { limit, .. } => *limit = Some(limit.map_or(rows, |l| l.min(rows))),
+        _ => {}
+    }
+}
+
+// 合并同一列上的两个范围边界，取更严格的一个，值相同时开区间更严格
+fn tighter_bound(a: Bound<Value>, b: Bound<Value>, lower: bool) -> Bound<Value> {
+    let value = |bound: &Bound<Value>| match bound {
+        Bound::Included(v) | Bound::Excluded(v) => Some(v.clone()),
+        Bound::Unbounded => None,
+    };
+    let (va, vb) = match (value(&a), value(&b)) {
+        (None, _) => return b,
+        (_, None) => return a,
+        (Some(va), Some(vb)) => (va, vb),
+    };
+    match va.partial_cmp(&vb) {
+        Some(std::cmp::Ordering::Less) => match lower {
+            true => b,
+            false => a,
+        },
+        Some(std::cmp::Ordering::Greater) => match lower {
+            true => a,
+            false => b,
+        },
+        _ => match a {
+            Bound::Excluded(_) => a,
+            _ => b,
+        },
+    }
+}
+
+// 去掉扫描节点已经用到的条件，其余的条件在扫描的结果上过滤
+fn filter_rest(node: Node, conjuncts: Vec<Expression>, used: &[usize]) -> Node {
+    let rest = conjuncts
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| !used.contains(i))
+        .map(|(_, c)| c)
+        .collect::<Vec<_>>();
+    if rest.is_empty() {
+        return node;
+    }
+    Node::Filter {
+        source: Box::new(node),
+        predicate: join_conjuncts(rest),
+    }
+}
+
+// Aggregate 输出的列名
+fn output_name(expr: &Expression, alias: &Option<String>) -> String {
+    match (expr, alias) {
+        (_, Some(alias)) => alias.clone(),
+        (Expression::Function(func_name, _), None) => func_name.clone(),
+        (expr, None) => expr.to_string(),
+    }
+}
+
+// 把 having、order by 中的聚集函数替换成 Aggregate 输出的列，例如 select b, sum(c) as s ... having sum(c) > 5 改写成 having s > 5
+// select 中没有的聚集函数追加到 Aggregate 的输出中，列名是聚集函数本身，例如 count(a)
+fn rewrite_aggregates(
+    expr: &mut Expression,
+    exprs: &mut Vec<(Expression, Option<String>)>,
+) -> Result<()> {
+    expr.walk_mut(&mut |e| {
+        if let Expression::Function(func_name, col_name) = e {
+            let found = exprs.iter().find(|(expr, _)| {
+                matches!(expr, Expression::Function(f, c) if f.eq_ignore_ascii_case(func_name) && c == col_name)
+            });
+            let name = match found {
+                Some((expr, alias)) => output_name(expr, alias),
+                None => {
+                    let name = e.to_string();
+                    exprs.push((e.clone(), Some(name.clone())));
+                    name
                 }
-                Expression::Operation(operation) => match operation {
-                    ast::Operation::Equal(l, r) => {
-                        let lv = Self::parse_scan_filter(Some(*l));
-                        let rv = Self::parse_scan_filter(Some(*r));
+            };
+            *e = Expression::Field(None, name);
+        }
+        Ok(())
+    })
+}
 
-                        Some((lv.unwrap().0, rv.unwrap().1))
+// order by 中的整数常量是 select 中的位置，和 select 中的别名相同的列名替换成别名对应的表达式
+// select * 时 select 为空，位置在执行时按照结果的列计算
+fn resolve_order_by(
+    order_by: Vec<(Expression, ast::OrderDirection)>,
+    select: &[(Expression, Option<String>)],
+) -> Result<Vec<(Expression, ast::OrderDirection)>> {
+    order_by
+        .into_iter()
+        .map(|(expr, direction)| {
+            let expr = match expr {
+                Expression::Consts(ast::Consts::Integer(n)) if !select.is_empty() => {
+                    match usize::try_from(n).ok().and_then(|n| select.get(n.wrapping_sub(1))) {
+                        Some((e, _)) => e.clone(),
+                        None => {
+                            return Err(Error::InvalidInput(format!(
+                                "ORDER BY position {} is not in select list",
+                                n
+                            )))
+                        }
                     }
-                    _ => None,
+                }
+                Expression::Field(None, name) => {
+                    match select.iter().find(|(_, alias)| alias.as_ref() == Some(&name)) {
+                        Some((e, _)) => e.clone(),
+                        None => Expression::Field(None, name),
+                    }
+                }
+                expr => expr,
+            };
+            Ok((expr, direction))
+        })
+        .collect()
+}
+
+// 检查列名的表名前缀，必须是 from 中的表名，有别名时必须是别名，否则返回 NotFound 错误
+// 子查询单独生成执行计划，引用外层查询的列时前缀不在子查询的 from 中，同样在这里报错
+fn check_qualifiers<'a>(
+    from: &'a ast::FromItem,
+    others: Vec<&'a ast::FromItem>,
+    mut exprs: Vec<&'a Expression>,
+) -> Result<()> {
+    let mut tables = Vec::new();
+    let mut items = vec![from];
+    items.extend(others);
+    while let Some(item) = items.pop() {
+        match item {
+            ast::FromItem::Table { name, alias, .. } => tables.push(alias.as_ref().unwrap_or(name)),
+            ast::FromItem::Derived { alias, .. } => tables.push(alias),
+            // unnest 的别名是展开的列名
+            ast::FromItem::Unnest { expr, .. } => exprs.push(expr),
+            ast::FromItem::Join {
+                left,
+                right,
+                predicate,
+                ..
+            } => {
+                items.push(left);
+                items.push(right);
+                exprs.extend(predicate);
+            }
+        }
+    }
+
+    let mut result = Ok(());
+    for expr in exprs {
+        expr.walk(&mut |e| {
+            let table = match e {
+                Expression::Field(Some(table), _) => table.as_str(),
+                Expression::Function(_, col) => match col.rsplit_once('.') {
+                    Some((table, _)) => table,
+                    None => return,
                 },
-                _ => None,
-            },
-            None => None,
+                _ => return,
+            };
+            if result.is_ok() && !tables.iter().any(|t| *t == table) {
+                result = Err(Error::NotFound(format!(
+                    "missing FROM-clause entry for table {}",
+                    table
+                )));
+            }
+        });
+    }
+    result
+}
+
+// 去掉条件中列名的表名前缀
+fn strip_qualifier(mut expr: Expression) -> Result<Expression> {
+    expr.walk_mut(&mut |e| {
+        if let Expression::Field(table, _) = e {
+            *table = None;
         }
+        Ok(())
+    })?;
+    Ok(expr)
+}
+
+// 把 AND 连接的条件拆成多个条件
+fn split_conjuncts(expr: Expression, conjuncts: &mut Vec<Expression>) {
+    match expr {
+        Expression::Operation(ast::Operation::And(l, r)) => {
+            split_conjuncts(*l, conjuncts);
+            split_conjuncts(*r, conjuncts);
+        }
+        expr => conjuncts.push(expr),
     }
 }
+
+// 用 AND 连接多个条件，conjuncts 不能为空
+fn join_conjuncts(conjuncts: Vec<Expression>) -> Expression {
+    conjuncts
+        .into_iter()
+        .reduce(|l, r| Expression::Operation(ast::Operation::And(Box::new(l), Box::new(r))))
+        .unwrap()
+}
@@ -4,12 +4,171 @@ use serde::{Deserialize, Serialize};
 
 use crate::error::{Error, Result};
 
-use super::types::{DataType, Row, Value};
+use super::{
+    functions,
+    parser::{
+        ast::{self, column_position, evaluate_expr, Expression},
+        Parser,
+    },
+    types::{coerce::coerce_value, DataType, Row, Value},
+};
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+// 没有声明主键的表自动生成的隐藏列，作为行的主键，select * 不会返回这一列
+pub const ROWID: &str = "_rowid";
+
+// 计算列的默认值，建表或者修改默认值时计算一次，同样遵循隐式类型转换规则，例如 float default 1
+// volatile 函数每次调用结果不同，不能作为默认值
+pub fn default_value(column: &str, datatype: &DataType, expr: &Expression) -> Result<Value> {
+    if functions::has_volatile(expr) {
+        return Err(Error::InvalidInput(format!(
+            "default value for column {} cannot use volatile function {}",
+            column, expr
+        )));
+    }
+    let value = evaluate_expr(expr, &vec![], &vec![], &vec![], &vec![])?;
+    coerce_value(value, datatype, false).map_err(|_| {
+        Error::TypeMismatch(format!("Default value for column {} mismatch", column))
+    })
+}
+
+// CHECK 约束的表达式只能引用当前行的列，不能包含子查询、聚集函数和 volatile 函数
+// 表结构中保存表达式的 SQL 文本，写入数据时重新解析
+pub fn check_constraint(expr: &Expression) -> Result<String> {
+    let mut invalid = expr.has_subquery() || functions::has_volatile(expr);
+    expr.walk(&mut |e| invalid |= matches!(e, Expression::Function(..) | Expression::Default));
+    if invalid {
+        return Err(Error::InvalidInput(format!(
+            "check constraint {} can only reference columns of the row",
+            expr
+        )));
+    }
+    Ok(expr.to_string())
+}
+
+// 根据建表、加列语句中的列定义生成列信息，没有声明是否可以为空时主键不能为空，其他列可以为空
+pub fn build_column(c: ast::Column) -> Result<Column> {
+    if c.name == ROWID {
+        return Err(Error::InvalidInput(format!(
+            "column name {} is reserved",
+            c.name
+        )));
+    }
+    let nullable = c.nullable.unwrap_or(!c.primary_key);
+    let default = match c.default {
+        Some(_) if c.auto_increment => {
+            return Err(Error::InvalidInput(format!(
+                "auto_increment column {} cannot have a default value",
+                c.name
+            )))
+        }
+        Some(expr) => Some(default_value(&c.name, &c.datatype, &expr)?),
+        // 自增列没有给出值时插入 NULL，写入时分配下一个序号
+        None if nullable || c.auto_increment => Some(Value::Null),
+        None => None,
+    };
+    let check = c.check.as_ref().map(check_constraint).transpose()?;
+    Ok(Column {
+        name: c.name,
+        datatype: c.datatype,
+        nullable,
+        default,
+        primary_key: c.primary_key,
+        index: c.index && !c.primary_key,
+        index_type: c.index_type,
+        index_name: None,
+        unique: c.unique && !c.primary_key,
+        auto_increment: c.auto_increment,
+        check,
+    })
+}
+
+// 由主键列的值组成主键，单列主键就是列的值，复合主键是按照列顺序排列的数组
+// keycode 依次编码数组中的每个值，编码之后的顺序和按列逐个比较的顺序一致
+pub fn primary_key_value(mut values: Vec<Value>) -> Value {
+    match values.len() {
+        1 => values.remove(0),
+        _ => Value::Array(values.into()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Table {
     pub name: String,
     pub columns: Vec<Column>,
+    pub layout: Layout,
+    // 表级别的 CHECK 约束
+    pub checks: Vec<String>,
+}
+
+// 表数据的存储方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Layout {
+    // 按行存储，一行数据保存在一个 key 中
+    #[default]
+    Row,
+    // 按列存储，每一列的值保存在单独的 key 范围中，只读取部分列的聚集查询更快
+    Columnar,
+}
+
+// 数据格式版本 0 的表结构，还没有存储方式、约束等之后加入的字段，只在升级旧版本的数据时解码
+#[derive(Deserialize)]
+struct LegacyTable {
+    name: String,
+    columns: Vec<LegacyColumn>,
+}
+
+#[derive(Deserialize)]
+struct LegacyColumn {
+    name: String,
+    datatype: DataType,
+    nullable: bool,
+    default: Option<Value>,
+    primary_key: bool,
+    index: bool,
+}
+
+impl Table {
+    // 解码版本 0 的表结构，之后加入的字段使用默认值，索引都是有序索引
+    pub fn decode_legacy(buf: &[u8]) -> Result<Self> {
+        let table: LegacyTable = bincode::deserialize(buf)?;
+        let columns = table
+            .columns
+            .into_iter()
+            .map(|c| Column {
+                name: c.name,
+                datatype: c.datatype,
+                nullable: c.nullable,
+                default: c.default,
+                primary_key: c.primary_key,
+                index: c.index,
+                index_type: IndexType::Ordered,
+                index_name: None,
+                unique: false,
+                auto_increment: false,
+                check: None,
+            })
+            .collect();
+        Ok(Self {
+            name: table.name,
+            columns,
+            layout: Layout::Row,
+            checks: Vec::new(),
+        })
+    }
+}
+
+// 二级索引的存储结构
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum IndexType {
+    // 按照列值有序存储，支持等值查找和前缀查找
+    #[default]
+    Ordered,
+    // 按照列值的哈希分桶存储，key 的长度固定，只支持等值查找
+    Hash,
+    // 全文索引，保存文本中的每个词对应的主键集合，用于 match(col, 'words') 查找
+    FullText,
+    // 位图索引，保存每个值对应的主键位图，适合取值较少的列，多个条件可以先合并位图再读取行
+    Bitmap,
 }
 
 impl Table {
@@ -17,87 +176,283 @@ impl Table {
     pub fn validate(&self) -> Result<()> {
         // 校验是否有列信息
         if self.columns.is_empty() {
-            return Err(Error::Internal(format!(
+            return Err(Error::InvalidInput(format!(
                 "table {} has no columns",
                 self.name
             )));
         }
 
-        // 校验是否有主键
-        match self.columns.iter().filter(|c| c.primary_key).count() {
-            1 => {}
-            0 => {
-                return Err(Error::Internal(format!(
-                    "No primary key for table {}",
-                    self.name
-                )))
-            }
-            _ => {
-                return Err(Error::Internal(format!(
-                    "Multiple primary keys for table {}",
-                    self.name
-                )))
-            }
+        // 校验是否有主键，多个主键列组成复合主键
+        if !self.columns.iter().any(|c| c.primary_key) {
+            return Err(Error::InvalidInput(format!(
+                "No primary key for table {}",
+                self.name
+            )));
         }
 
         // 校验列信息
         for col in &self.columns {
             // 主键不能为空
             if col.primary_key && col.nullable {
-                return Err(Error::Internal(format!(
+                return Err(Error::InvalidInput(format!(
                     "Primary key {} cannot be nullable in table{}",
                     col.name, self.name
                 )));
             }
+            // 自增列的序号保存在表级别，只能是单个整数主键列，没有给出值时默认是 NULL
+            if col.auto_increment
+                && (!col.primary_key
+                    || self.is_composite_key()
+                    || col.datatype != DataType::Integer
+                    || col.default != Some(Value::Null))
+            {
+                return Err(Error::InvalidInput(format!(
+                    "auto_increment column {} must be the integer primary key without default in table {}",
+                    col.name, self.name
+                )));
+            }
+            // 数组不能作为 key 的一部分
+            if matches!(col.datatype, DataType::Array(_))
+                && (col.primary_key || col.index || col.unique)
+            {
+                return Err(Error::InvalidInput(format!(
+                    "array column {} cannot be primary key, unique or indexed in table {}",
+                    col.name, self.name
+                )));
+            }
+            // 全文索引只能建在字符串列上
+            if col.index && col.index_type == IndexType::FullText && col.datatype != DataType::String {
+                return Err(Error::InvalidInput(format!(
+                    "fulltext index requires a string column, but {} is {:?} in table {}",
+                    col.name, col.datatype, self.name
+                )));
+            }
+            // 位图中的位置就是主键的值，要求主键是单个整数列
+            if col.index
+                && col.index_type == IndexType::Bitmap
+                && (self.is_composite_key()
+                    || self.columns.iter().any(|c| c.primary_key && c.datatype != DataType::Integer))
+            {
+                return Err(Error::InvalidInput(format!(
+                    "bitmap index on column {} requires an integer primary key in table {}",
+                    col.name, self.name
+                )));
+            }
             // 校验默认值是否和列类型匹配
             if let Some(default_val) = &col.default {
-                match default_val.datatype() {
-                    Some(dt) => {
-                        if dt != col.datatype {
-                            return Err(Error::Internal(format!(
-                                "Default value for column {} mismatch in table{}",
-                                col.name, self.name
-                            )));
-                        }
+                if let Some(dt) = default_val.datatype() {
+                    // 定点小数的值只记录小数位数，精度在写入默认值时已经校验过
+                    let matched = match (&dt, &col.datatype) {
+                        (DataType::Decimal(_, s), DataType::Decimal(_, scale)) => s == scale,
+                        (dt, datatype) => dt == datatype,
+                    };
+                    if !matched {
+                        return Err(Error::TypeMismatch(format!(
+                            "Default value for column {} mismatch in table{}",
+                            col.name, self.name
+                        )));
                     }
-                    None => {}
                 }
             }
         }
 
+        // CHECK 约束引用的列必须存在
+        let names = self.column_names();
+        for check in self.check_constraints() {
+            let mut result = Ok(());
+            Parser::new(check).parse_expr()?.walk(&mut |e| {
+                if let Expression::Field(_, name) = e {
+                    if result.is_ok() {
+                        result = column_position(&names, name).map(|_| ());
+                    }
+                }
+            });
+            result.map_err(|e| {
+                Error::InvalidInput(format!(
+                    "check constraint {} in table {}: {}",
+                    check, self.name, e
+                ))
+            })?;
+        }
+
         Ok(())
     }
 
-    pub fn get_primary_key(&self, row: &Row) -> Result<Value> {
-        let pos = self
-            .columns
+    fn column_names(&self) -> Vec<String> {
+        self.columns.iter().map(|c| c.name.clone()).collect()
+    }
+
+    // 列级别和表级别的所有 CHECK 约束
+    fn check_constraints(&self) -> impl Iterator<Item = &String> {
+        self.columns
+            .iter()
+            .filter_map(|c| c.check.as_ref())
+            .chain(&self.checks)
+    }
+
+    // 校验一行数据是否满足所有的 CHECK 约束，结果是 NULL 时视为满足
+    pub fn check_row(&self, row: &Row) -> Result<()> {
+        let mut names = None;
+        for check in self.check_constraints() {
+            let names = names.get_or_insert_with(|| self.column_names());
+            let expr = Parser::new(check).parse_expr()?;
+            match evaluate_expr(&expr, names, row, names, row)? {
+                Value::Boolean(true) | Value::Null => {}
+                Value::Boolean(false) => {
+                    return Err(Error::Constraint(format!(
+                        "row violates check constraint {} of table {}",
+                        check, self.name
+                    )))
+                }
+                v => {
+                    return Err(Error::TypeMismatch(format!(
+                        "check constraint {} returned non-boolean value {}",
+                        check, v
+                    )))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 重命名列时改写 CHECK 约束中引用的列名
+    pub fn rename_check_column(&mut self, from: &str, to: &str) -> Result<()> {
+        let rename = |check: &mut String| -> Result<()> {
+            let mut expr = Parser::new(check).parse_expr()?;
+            expr.walk_mut(&mut |e| {
+                if let Expression::Field(table, name) = e {
+                    if name == from {
+                        *table = None;
+                        *name = to.to_string();
+                    }
+                }
+                Ok(())
+            })?;
+            *check = expr.to_string();
+            Ok(())
+        };
+        for col in &mut self.columns {
+            if let Some(check) = &mut col.check {
+                rename(check)?;
+            }
+        }
+        for check in &mut self.checks {
+            rename(check)?;
+        }
+        Ok(())
+    }
+
+    // 主键列的位置，按照列的顺序排列
+    pub fn primary_key_positions(&self) -> Vec<usize> {
+        self.columns
             .iter()
-            .position(|c| c.primary_key)
-            .expect("No primary key found");
-        Ok(row[pos].clone())
+            .enumerate()
+            .filter(|(_, c)| c.primary_key)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    // 是否是多个列组成的复合主键
+    pub fn is_composite_key(&self) -> bool {
+        self.columns.iter().filter(|c| c.primary_key).count() > 1
+    }
+
+    pub fn get_primary_key(&self, row: &Row) -> Result<Value> {
+        let values = self
+            .primary_key_positions()
+            .into_iter()
+            .map(|i| row[i].clone())
+            .collect();
+        Ok(primary_key_value(values))
+    }
+
+    // 按照列定义校验一行数据，并做隐式类型转换
+    pub fn coerce_row(&self, row: Row, strict: bool) -> Result<Row> {
+        if row.len() != self.columns.len() {
+            return Err(Error::InvalidInput(format!(
+                "row has {} values but table {} has {} columns",
+                row.len(),
+                self.name,
+                self.columns.len()
+            )));
+        }
+
+        let mut results = Vec::with_capacity(row.len());
+        for (col, value) in self.columns.iter().zip(row) {
+            if value == Value::Null && !col.nullable {
+                return Err(Error::Constraint(format!(
+                    "column {} cannot be null",
+                    col.name
+                )));
+            }
+            let value = coerce_value(value, &col.datatype, strict).map_err(|_| {
+                Error::TypeMismatch(format!("column {} type mismatch", col.name))
+            })?;
+            results.push(value);
+        }
+        Ok(results)
     }
 
     pub fn get_col_index(&self, col_name: &str) -> Result<usize> {
         self.columns
             .iter()
             .position(|c| c.name == col_name)
-            .ok_or(Error::Internal(format!("column {} not found", col_name)))
+            .ok_or(Error::NotFound(format!("column {} not found", col_name)))
+    }
+
+    // 根据索引名查找索引列，没有名字的索引使用列名
+    pub fn get_index_col(&self, index_name: &str) -> Result<usize> {
+        self.columns
+            .iter()
+            .position(|c| c.index && c.index_name.as_deref().unwrap_or(&c.name) == index_name)
+            .ok_or(Error::NotFound(format!(
+                "index {} not found in table {}",
+                index_name, self.name
+            )))
     }
 }
 
 impl Display for Table {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let col_desc = self
+        let composite = self.is_composite_key();
+        let mut col_desc = self
             .columns
             .iter()
-            .map(|c| format!("{}", c))
-            .collect::<Vec<_>>()
-            .join(",\n");
-        write!(f, "CREATE TABLE {} (\n{}\n)", self.name, col_desc)
+            .filter(|c| !c.is_hidden())
+            .map(|c| match composite {
+                // 复合主键在最后单独输出
+                true => format!(
+                    "{}",
+                    Column {
+                        primary_key: false,
+                        ..c.clone()
+                    }
+                ),
+                false => format!("{}", c),
+            })
+            .collect::<Vec<_>>();
+        if composite {
+            let names = self
+                .columns
+                .iter()
+                .filter(|c| c.primary_key)
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>();
+            col_desc.push(format!("    PRIMARY KEY ({})", names.join(", ")));
+        }
+        for check in &self.checks {
+            col_desc.push(format!("    CHECK ({})", check));
+        }
+        let col_desc = col_desc.join(",\n");
+        write!(f, "CREATE TABLE {} (\n{}\n)", self.name, col_desc)?;
+        if self.layout == Layout::Columnar {
+            write!(f, " WITH (layout = 'columnar')")?;
+        }
+        Ok(())
     }
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Column {
     pub name: String,
     pub datatype: DataType,
@@ -105,19 +460,77 @@ pub struct Column {
     pub default: Option<Value>,
     pub primary_key: bool,
     pub index: bool,
+    pub index_type: IndexType,
+    // CREATE INDEX 指定的索引名，建表时声明的索引没有名字，使用列名
+    pub index_name: Option<String>,
+    // 唯一约束，非 NULL 的值不能重复，主键本身就是唯一的
+    pub unique: bool,
+    // 自增列，插入时没有给出值则分配表的下一个序号，只能是单个整数主键列
+    pub auto_increment: bool,
+    // 列级别的 CHECK 约束
+    pub check: Option<String>,
+}
+
+impl Column {
+    // 隐藏的 _rowid 列，插入时没有给出值则自动分配
+    pub fn rowid() -> Self {
+        Self {
+            name: ROWID.into(),
+            datatype: DataType::Integer,
+            nullable: false,
+            default: Some(Value::Null),
+            primary_key: true,
+            index: false,
+            index_type: IndexType::Ordered,
+            index_name: None,
+            unique: false,
+            auto_increment: true,
+            check: None,
+        }
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.name == ROWID
+    }
+}
+
+// 表结构中列类型的描述，数组类型在元素类型后面加上 []
+fn type_desc(datatype: &DataType) -> String {
+    match datatype {
+        DataType::Array(dt) => format!("{}[]", type_desc(dt)),
+        dt => format!("{:?}", dt),
+    }
 }
 
 impl Display for Column {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut col_desc = format!("    {} {:?}", self.name, self.datatype);
+        let mut col_desc = format!("    {} {}", self.name, type_desc(&self.datatype));
         if self.primary_key {
             col_desc += " PRIMARY KEY";
         }
+        if self.auto_increment {
+            col_desc += " AUTO_INCREMENT";
+        }
         if !self.nullable && !self.primary_key {
             col_desc += " NOT NULL";
         }
         if let Some(v) = &self.default {
-            col_desc += &format!(" DEFAULT {}", v.to_string());
+            col_desc += &format!(" DEFAULT {}", v);
+        }
+        if self.unique {
+            col_desc += " UNIQUE";
+        }
+        if self.index {
+            col_desc += " INDEX";
+            match self.index_type {
+                IndexType::Ordered => {}
+                IndexType::Hash => col_desc += " USING HASH",
+                IndexType::FullText => col_desc += " USING FULLTEXT",
+                IndexType::Bitmap => col_desc += " USING BITMAP",
+            }
+        }
+        if let Some(check) = &self.check {
+            col_desc += &format!(" CHECK ({})", check);
         }
         write!(f, "{}", col_desc)
     }
@@ -0,0 +1,54 @@
+use crate::error::{Error, Result};
+
+// 二进制数据的文本格式和 Postgres 的 bytea 一致，\x 后面是十六进制编码，例如 \xdeadbeef
+// 常量写作 x'DEADBEEF'，大小写都可以
+
+// 解析十六进制字符串，长度必须是偶数
+pub fn parse_hex(s: &str) -> Result<Vec<u8>> {
+    let invalid = || Error::InvalidInput(format!("invalid hexadecimal value {}", s));
+    let s = s.trim().as_bytes();
+    if !s.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+    s.chunks(2)
+        .map(|pair| {
+            let hi = (pair[0] as char).to_digit(16).ok_or_else(invalid)?;
+            let lo = (pair[1] as char).to_digit(16).ok_or_else(invalid)?;
+            Ok((hi * 16 + lo) as u8)
+        })
+        .collect()
+}
+
+// 字符串转换为二进制数据，\x 开头时按照十六进制解析，否则取字符串的 UTF-8 编码
+pub fn parse_blob(s: &str) -> Result<Vec<u8>> {
+    match s.strip_prefix("\\x") {
+        Some(hex) => parse_hex(hex),
+        None => Ok(s.as_bytes().to_vec()),
+    }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn format_blob(bytes: &[u8]) -> String {
+    format!("\\x{}", to_hex(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_blob, parse_blob, parse_hex};
+    use crate::error::Result;
+
+    #[test]
+    fn test_hex() -> Result<()> {
+        assert_eq!(parse_hex("DEADbeef")?, vec![0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(parse_hex("")?, Vec::<u8>::new());
+        assert!(parse_hex("abc").is_err());
+        assert!(parse_hex("zz").is_err());
+        assert_eq!(format_blob(&[0, 1, 255]), "\\x0001ff");
+        assert_eq!(parse_blob("\\x0001ff")?, vec![0, 1, 255]);
+        assert_eq!(parse_blob("ab")?, b"ab".to_vec());
+        Ok(())
+    }
+}
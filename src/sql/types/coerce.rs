@@ -0,0 +1,418 @@
+use crate::error::{Error, Result};
+
+use super::{blob, temporal, DataType, Decimal, Interval, Value};
+
+// 隐式类型转换规则，insert / update 写入列值、以及按键查找时统一使用
+//
+//   源类型 \ 目标类型 | BOOLEAN | INTEGER | FLOAT | STRING
+//   -----------------+---------+---------+-------+--------
+//   BOOLEAN          |    ✓    |    ✗    |   ✗   |   ✗
+//   INTEGER          |    ✗    |    ✓    |   ✓*  |   ✗
+//   FLOAT            |    ✗    |    ✓**  |   ✓   |   ✗
+//   STRING           |    ✗    |    ✗    |   ✗   |   ✓
+//
+//   INTERVAL 列可以写入 INTERVAL 值，非 strict 模式下也可以写入 '1 day' 这样的字符串
+//   DATE、TIMESTAMP 列同样可以写入 '2024-01-01'、'2024-01-01 10:00:00' 这样的字符串，
+//   TIMESTAMP 列可以写入 DATE 值，表示当天的 00:00:00
+//   DECIMAL(p, s) 列可以写入整数和定点小数，四舍五入到 s 位小数，超过 p 位有效数字时报错，
+//   非 strict 模式下也可以写入浮点数、'12.50' 这样的字符串
+//   BLOB 列非 strict 模式下可以写入字符串，'\xdeadbeef' 按照十六进制解析，其他字符串取 UTF-8 编码
+//
+//   *  整数提升为浮点数
+//   ** 只有没有小数部分的浮点数才能转换为整数
+//   NULL 可以赋值给任意类型，是否允许为空由列约束决定
+//   strict 模式下 * 和 ** 两种转换都会被拒绝
+pub fn coerce_value(value: Value, datatype: &DataType, strict: bool) -> Result<Value> {
+    Ok(match (value, datatype) {
+        (Value::Null, _) => Value::Null,
+        (v @ Value::Boolean(_), DataType::Boolean) => v,
+        (v @ Value::Integer(_), DataType::Integer) => v,
+        (v @ Value::Float(_), DataType::Float) => v,
+        (v @ Value::String(_), DataType::String) => v,
+        (v @ Value::Interval(_), DataType::Interval) => v,
+        (Value::String(s), DataType::Interval) if !strict => Value::Interval(Interval::parse(&s)?),
+        (v @ Value::Date(_), DataType::Date) => v,
+        (v @ Value::Timestamp(_), DataType::Timestamp) => v,
+        (Value::String(s), DataType::Date) if !strict => Value::Date(temporal::parse_date(&s)?),
+        (Value::String(s), DataType::Timestamp) if !strict => {
+            Value::Timestamp(temporal::parse_timestamp(&s)?)
+        }
+        (Value::Date(d), DataType::Timestamp) if !strict => {
+            Value::Timestamp(temporal::date_to_timestamp(d)?)
+        }
+        (Value::Decimal(d), DataType::Decimal(p, s)) => Value::from(d.fit(*p, *s)?),
+        (Value::Integer(i), DataType::Decimal(p, s)) => {
+            Value::from(Decimal::from_i64(i).fit(*p, *s)?)
+        }
+        (Value::Float(f), DataType::Decimal(p, s)) if !strict => {
+            Value::from(Decimal::from_f64(f)?.fit(*p, *s)?)
+        }
+        (Value::String(s), DataType::Decimal(p, scale)) if !strict => {
+            Value::from(Decimal::parse(&s)?.fit(*p, *scale)?)
+        }
+        (v @ Value::Blob(_), DataType::Blob) => v,
+        (Value::String(s), DataType::Blob) if !strict => Value::Blob(blob::parse_blob(&s)?.into()),
+        (Value::Decimal(d), DataType::Float) if !strict => Value::Float(d.to_f64()),
+        (Value::Decimal(d), DataType::Integer) if !strict => match d.to_i64() {
+            Some(i) => Value::Integer(i),
+            None => {
+                return Err(Error::TypeMismatch(format!(
+                    "cannot convert {} to {:?} without losing precision",
+                    d, datatype
+                )))
+            }
+        },
+        // 数组的每个元素按照元素类型转换
+        (Value::Array(items), DataType::Array(dt)) => Value::Array(
+            items
+                .into_vec()
+                .into_iter()
+                .map(|v| coerce_value(v, dt, strict))
+                .collect::<Result<_>>()?,
+        ),
+        (Value::Integer(i), DataType::Float) if !strict => Value::Float(i as f64),
+        (Value::Float(f), DataType::Integer) if !strict => match float_to_integer(f) {
+            Some(i) => Value::Integer(i),
+            None => {
+                return Err(Error::TypeMismatch(format!(
+                    "cannot convert {} to {:?} without losing precision",
+                    f, datatype
+                )))
+            }
+        },
+        (v, dt) => {
+            return Err(Error::TypeMismatch(format!(
+                "cannot convert {} to {:?}{}",
+                v,
+                dt,
+                if strict { " in strict mode" } else { "" }
+            )))
+        }
+    })
+}
+
+// 将查找值转换成列的类型，用于主键、索引的等值查找
+// 和比较运算的语义保持一致：2.0 = 2 成立，2.5 永远不会等于一个整数
+// 返回 None 表示该值不可能和这一列的任何值相等
+// NULL 只会来自 IS NOT DISTINCT FROM NULL，查找这一列为 NULL 的行
+pub fn coerce_lookup(value: &Value, datatype: &DataType) -> Option<Value> {
+    match (value, datatype) {
+        (Value::Null, _) => Some(Value::Null),
+        (Value::Integer(i), DataType::Float) => Some(Value::Float(*i as f64)),
+        (Value::Float(f), DataType::Integer) => float_to_integer(*f).map(Value::Integer),
+        // 字符串按照日期时间解析，时间戳只有在 00:00:00 时才可能等于某个日期
+        (Value::String(s), DataType::Date) => temporal::parse_date(s).ok().map(Value::Date),
+        (Value::String(s), DataType::Timestamp) => {
+            temporal::parse_timestamp(s).ok().map(Value::Timestamp)
+        }
+        (Value::Date(d), DataType::Timestamp) => {
+            temporal::date_to_timestamp(*d).ok().map(Value::Timestamp)
+        }
+        (Value::Timestamp(ts), DataType::Date) => {
+            let d = temporal::timestamp_to_date(*ts);
+            (temporal::date_to_timestamp(d).ok()? == *ts).then_some(Value::Date(d))
+        }
+        // 和比较运算一样，字符串按照数字、布尔值解析
+        (Value::String(s), DataType::Integer | DataType::Float) => {
+            coerce_lookup(&parse_number(s).ok()?, datatype)
+        }
+        (Value::String(s), DataType::Boolean) => parse_boolean(s).ok(),
+        // 定点小数只有在列的小数位数下能精确表示时才可能相等
+        (Value::Decimal(d), DataType::Decimal(_, s)) => lookup_decimal(**d, *s),
+        (Value::Integer(i), DataType::Decimal(_, s)) => lookup_decimal(Decimal::from_i64(*i), *s),
+        (Value::Float(f), DataType::Decimal(_, s)) => {
+            lookup_decimal(Decimal::from_f64(*f).ok()?, *s)
+        }
+        (Value::String(v), DataType::Decimal(_, s)) => lookup_decimal(Decimal::parse(v).ok()?, *s),
+        (Value::Decimal(d), DataType::Integer) => d.to_i64().map(Value::Integer),
+        (Value::String(s), DataType::Blob) => blob::parse_blob(s).ok().map(|b| Value::Blob(b.into())),
+        (Value::Decimal(d), DataType::Float) => Some(Value::Float(d.to_f64())),
+        (v, dt) => match v.datatype() {
+            Some(vdt) if vdt == *dt => Some(v.clone()),
+            _ => None,
+        },
+    }
+}
+
+fn lookup_decimal(d: Decimal, scale: u8) -> Option<Value> {
+    d.rescale(scale)
+        .ok()
+        .filter(|v| *v == d)
+        .map(Value::from)
+}
+
+// 比较运算两边的隐式类型转换，等值、大小比较之前统一调用
+// 数字和字符串比较时字符串按照数字解析，布尔值和字符串比较时字符串按照布尔值解析，解析失败时报错
+// 整数和浮点数、日期时间和字符串之间的比较由比较运算自己处理，这里保持不变
+pub fn coerce_comparison(lv: Value, rv: Value) -> Result<(Value, Value)> {
+    Ok(match (lv, rv) {
+        (l @ (Value::Integer(_) | Value::Float(_)), Value::String(s)) => (l, parse_number(&s)?),
+        (Value::String(s), r @ (Value::Integer(_) | Value::Float(_))) => (parse_number(&s)?, r),
+        (l @ Value::Boolean(_), Value::String(s)) => (l, parse_boolean(&s)?),
+        (Value::String(s), r @ Value::Boolean(_)) => (parse_boolean(&s)?, r),
+        // 定点小数和字符串比较时字符串按照定点小数解析，不经过浮点数
+        (l @ Value::Decimal(_), Value::String(s)) => (l, Decimal::parse(&s)?.into()),
+        (Value::String(s), r @ Value::Decimal(_)) => (Decimal::parse(&s)?.into(), r),
+        (l @ Value::Blob(_), Value::String(s)) => (l, Value::Blob(blob::parse_blob(&s)?.into())),
+        (Value::String(s), r @ Value::Blob(_)) => (Value::Blob(blob::parse_blob(&s)?.into()), r),
+        pair => pair,
+    })
+}
+
+// 字符串解析为数字，能解析成整数时返回整数，否则返回浮点数
+fn parse_number(s: &str) -> Result<Value> {
+    let s = s.trim();
+    if let Ok(i) = s.parse::<i64>() {
+        return Ok(Value::Integer(i));
+    }
+    match s.parse::<f64>() {
+        Ok(f) => Ok(Value::Float(f)),
+        Err(_) => Err(Error::TypeMismatch(format!("invalid numeric value: {}", s))),
+    }
+}
+
+fn parse_boolean(s: &str) -> Result<Value> {
+    match s.trim().to_lowercase().as_str() {
+        "true" | "t" | "1" => Ok(Value::Boolean(true)),
+        "false" | "f" | "0" => Ok(Value::Boolean(false)),
+        _ => Err(Error::TypeMismatch(format!("invalid boolean value: {}", s))),
+    }
+}
+
+// 显式类型转换，比隐式转换宽松，例如 alter column type 时重写已有数据
+// 浮点数转整数时四舍五入，字符串按字面值解析，其他类型都可以转换为字符串
+pub fn cast_value(value: Value, datatype: &DataType) -> Result<Value> {
+    Ok(match (value, datatype) {
+        (Value::Null, _) => Value::Null,
+        (Value::Boolean(b), DataType::Integer) => Value::Integer(b as i64),
+        (Value::Boolean(b), DataType::Float) => Value::Float(b as i64 as f64),
+        (Value::Integer(i), DataType::Boolean) => Value::Boolean(i != 0),
+        (Value::Integer(i), DataType::Float) => Value::Float(i as f64),
+        (Value::Float(f), DataType::Boolean) => Value::Boolean(f != 0.0),
+        (Value::Float(f), DataType::Integer) => match float_to_integer(f.round()) {
+            Some(i) => Value::Integer(i),
+            None => return Err(Error::TypeMismatch(format!("integer out of range: {}", f))),
+        },
+        (Value::String(s), DataType::Boolean) => parse_boolean(&s)?,
+        (Value::String(s), DataType::Integer) => match s.trim().parse() {
+            Ok(i) => Value::Integer(i),
+            Err(_) => return Err(Error::TypeMismatch(format!("invalid integer value: {}", s))),
+        },
+        (Value::String(s), DataType::Float) => match s.trim().parse() {
+            Ok(f) => Value::Float(f),
+            Err(_) => return Err(Error::TypeMismatch(format!("invalid float value: {}", s))),
+        },
+        (Value::String(s), DataType::Interval) => Value::Interval(Interval::parse(&s)?),
+        // 转换为定点小数时四舍五入到目标的小数位数，定点小数转换为整数时同样四舍五入
+        (Value::Integer(i), DataType::Decimal(p, s)) => {
+            Value::from(Decimal::from_i64(i).fit(*p, *s)?)
+        }
+        (Value::Float(f), DataType::Decimal(p, s)) => {
+            Value::from(Decimal::from_f64(f)?.fit(*p, *s)?)
+        }
+        (Value::String(v), DataType::Decimal(p, s)) => {
+            Value::from(Decimal::parse(&v)?.fit(*p, *s)?)
+        }
+        (Value::Decimal(d), DataType::Decimal(p, s)) => Value::from(d.fit(*p, *s)?),
+        (Value::Boolean(b), DataType::Decimal(p, s)) => {
+            Value::from(Decimal::from_i64(b as i64).fit(*p, *s)?)
+        }
+        (Value::Decimal(d), DataType::Float) => Value::Float(d.to_f64()),
+        (Value::Decimal(d), DataType::Integer) => {
+            match d.rescale(0).ok().and_then(|d| d.to_i64()) {
+                Some(i) => Value::Integer(i),
+                None => return Err(Error::TypeMismatch(format!("integer out of range: {}", d))),
+            }
+        }
+        (Value::Decimal(d), DataType::Boolean) => Value::Boolean(d.value != 0),
+        (Value::String(s), DataType::Blob) => Value::Blob(blob::parse_blob(&s)?.into()),
+        (v @ Value::Blob(_), DataType::Blob) => v,
+        // 时间戳转换为日期时去掉时间部分
+        (Value::String(s), DataType::Date) => Value::Date(match temporal::parse_date(&s) {
+            Ok(d) => d,
+            Err(_) => temporal::timestamp_to_date(temporal::parse_timestamp(&s)?),
+        }),
+        (Value::String(s), DataType::Timestamp) => Value::Timestamp(temporal::parse_timestamp(&s)?),
+        (Value::Timestamp(ts), DataType::Date) => Value::Date(temporal::timestamp_to_date(ts)),
+        (Value::Date(d), DataType::Timestamp) => Value::Timestamp(temporal::date_to_timestamp(d)?),
+        (Value::Array(items), DataType::Array(dt)) => Value::Array(
+            items
+                .into_vec()
+                .into_iter()
+                .map(|v| cast_value(v, dt))
+                .collect::<Result<_>>()?,
+        ),
+        (v @ Value::String(_), DataType::String) => v,
+        (v, DataType::String) => Value::String(v.to_string()),
+        (v @ Value::Date(_), DataType::Date) | (v @ Value::Timestamp(_), DataType::Timestamp) => v,
+        (v, DataType::Date | DataType::Timestamp | DataType::Decimal(..) | DataType::Blob) => {
+            return Err(Error::TypeMismatch(format!("cannot cast {} to {}", v, datatype)))
+        }
+        (v, _) => v,
+    })
+}
+
+fn float_to_integer(f: f64) -> Option<i64> {
+    if f.fract() == 0.0 && f >= i64::MIN as f64 && f < i64::MAX as f64 {
+        Some(f as i64)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cast_value, coerce_comparison, coerce_lookup, coerce_value};
+    use crate::sql::types::{DataType, Decimal, Value};
+
+    #[test]
+    fn test_coerce_value() {
+        assert_eq!(
+            coerce_value(Value::Integer(1), &DataType::Float, false),
+            Ok(Value::Float(1.0))
+        );
+        assert_eq!(
+            coerce_value(Value::Float(2.0), &DataType::Integer, false),
+            Ok(Value::Integer(2))
+        );
+        assert!(coerce_value(Value::Float(2.5), &DataType::Integer, false).is_err());
+        assert!(coerce_value(Value::Integer(1), &DataType::Float, true).is_err());
+        assert!(coerce_value(Value::Float(2.0), &DataType::Integer, true).is_err());
+        assert!(coerce_value(Value::Boolean(true), &DataType::Integer, false).is_err());
+        assert!(coerce_value(Value::String("1".into()), &DataType::Integer, false).is_err());
+        assert_eq!(
+            coerce_value(Value::Null, &DataType::String, true),
+            Ok(Value::Null)
+        );
+    }
+
+    #[test]
+    fn test_cast_value() {
+        assert_eq!(
+            cast_value(Value::Float(2.6), &DataType::Integer),
+            Ok(Value::Integer(3))
+        );
+        assert_eq!(
+            cast_value(Value::String(" 12 ".into()), &DataType::Integer),
+            Ok(Value::Integer(12))
+        );
+        assert_eq!(
+            cast_value(Value::Integer(12), &DataType::String),
+            Ok(Value::String("12".into()))
+        );
+        assert_eq!(
+            cast_value(Value::String("t".into()), &DataType::Boolean),
+            Ok(Value::Boolean(true))
+        );
+        assert!(cast_value(Value::String("abc".into()), &DataType::Float).is_err());
+        assert_eq!(
+            cast_value(Value::String("1970-01-02 10:00:00".into()), &DataType::Date),
+            Ok(Value::Date(1))
+        );
+        assert_eq!(
+            cast_value(Value::Date(1), &DataType::Timestamp),
+            Ok(Value::Timestamp(86_400_000_000))
+        );
+        assert_eq!(
+            cast_value(Value::Date(1), &DataType::String),
+            Ok(Value::String("1970-01-02".into()))
+        );
+        assert!(cast_value(Value::Integer(1), &DataType::Date).is_err());
+    }
+
+    #[test]
+    fn test_coerce_lookup() {
+        assert_eq!(
+            coerce_lookup(&Value::Float(3.0), &DataType::Integer),
+            Some(Value::Integer(3))
+        );
+        assert_eq!(coerce_lookup(&Value::Float(3.5), &DataType::Integer), None);
+        assert_eq!(
+            coerce_lookup(&Value::Integer(3), &DataType::Float),
+            Some(Value::Float(3.0))
+        );
+        assert_eq!(coerce_lookup(&Value::String("a".into()), &DataType::Integer), None);
+        assert_eq!(
+            coerce_lookup(&Value::String("3".into()), &DataType::Integer),
+            Some(Value::Integer(3))
+        );
+        assert_eq!(coerce_lookup(&Value::String("3.5".into()), &DataType::Integer), None);
+        assert_eq!(
+            coerce_lookup(&Value::String("1970-01-02".into()), &DataType::Date),
+            Some(Value::Date(1))
+        );
+        assert_eq!(
+            coerce_lookup(&Value::Timestamp(86_400_000_000), &DataType::Date),
+            Some(Value::Date(1))
+        );
+        assert_eq!(coerce_lookup(&Value::Timestamp(1), &DataType::Date), None);
+    }
+
+    #[test]
+    fn test_coerce_decimal() {
+        let dt = DataType::Decimal(5, 2);
+        let d = |s: &str| Value::from(Decimal::parse(s).unwrap());
+        let coerce = |v: Value, strict: bool| coerce_value(v, &dt, strict).map(|v| v.to_string());
+        assert_eq!(coerce(Value::Integer(7), true), Ok("7.00".into()));
+        assert_eq!(coerce(d("1.005"), true), Ok("1.01".into()));
+        assert_eq!(coerce(Value::Float(0.1), false), Ok("0.10".into()));
+        assert_eq!(
+            coerce(Value::String("-2.5".into()), false),
+            Ok("-2.50".into())
+        );
+        assert!(coerce(Value::Float(0.1), true).is_err());
+        assert!(coerce(Value::Integer(1000), false).is_err());
+        assert_eq!(
+            coerce_value(d("3.00"), &DataType::Integer, false),
+            Ok(Value::Integer(3))
+        );
+        assert!(coerce_value(d("3.5"), &DataType::Integer, false).is_err());
+
+        assert_eq!(
+            cast_value(d("2.5"), &DataType::Integer),
+            Ok(Value::Integer(3))
+        );
+        assert_eq!(
+            cast_value(d("2.5"), &DataType::String),
+            Ok(Value::String("2.5".into()))
+        );
+        assert!(cast_value(Value::Date(1), &DataType::Decimal(5, 2)).is_err());
+
+        // 只有能按照列的小数位数精确表示时才能查找
+        assert_eq!(coerce_lookup(&Value::Integer(2), &dt), Some(d("2")));
+        assert_eq!(
+            coerce_lookup(&Value::String("1.5".into()), &dt),
+            Some(d("1.5"))
+        );
+        assert_eq!(coerce_lookup(&d("1.505"), &dt), None);
+        assert_eq!(
+            coerce_lookup(&d("2.0"), &DataType::Integer),
+            Some(Value::Integer(2))
+        );
+        assert_eq!(
+            coerce_comparison(d("1.5"), Value::String("1.50".into())),
+            Ok((d("1.5"), d("1.5")))
+        );
+    }
+
+    #[test]
+    fn test_coerce_comparison() {
+        assert_eq!(
+            coerce_comparison(Value::Integer(1), Value::String(" 2 ".into())),
+            Ok((Value::Integer(1), Value::Integer(2)))
+        );
+        assert_eq!(
+            coerce_comparison(Value::String("1.5".into()), Value::Integer(1)),
+            Ok((Value::Float(1.5), Value::Integer(1)))
+        );
+        assert_eq!(
+            coerce_comparison(Value::Boolean(true), Value::String("f".into())),
+            Ok((Value::Boolean(true), Value::Boolean(false)))
+        );
+        assert!(coerce_comparison(Value::Float(1.0), Value::String("abc".into())).is_err());
+        assert_eq!(
+            coerce_comparison(Value::String("a".into()), Value::Date(1)),
+            Ok((Value::String("a".into()), Value::Date(1)))
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use serde::{
+    de::{self, value::MapDeserializer, value::SeqDeserializer, IntoDeserializer, Visitor},
+    forward_to_deserialize_any,
+};
+
+use crate::error::{Error, Result};
+
+use super::{Row, Value};
+
+// 将查询结果的一行数据转换成用户定义的类型
+// 结构体、HashMap 按照列名匹配字段，join 结果中带表名前缀的列名 t1.a 按照 a 匹配
+// 元组、Vec 按照列的位置匹配；只有一列时也可以直接转换成基本类型，例如 i64、String
+pub fn from_row<'de, T: de::Deserialize<'de>>(columns: &'de [String], row: Row) -> Result<T> {
+    T::deserialize(RowDeserializer { columns, row })
+}
+
+struct RowDeserializer<'de> {
+    columns: &'de [String],
+    row: Row,
+}
+
+impl<'de> RowDeserializer<'de> {
+    // 只有一列时，取出这一列的值
+    fn single(self) -> Result<Value> {
+        match <[Value; 1]>::try_from(self.row) {
+            Ok([value]) => Ok(value),
+            Err(row) => Err(Error::TypeMismatch(format!(
+                "expected 1 column, got {}",
+                row.len()
+            ))),
+        }
+    }
+}
+
+// 基本类型转发到单列的值上
+macro_rules! forward_to_single {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+                self.single()?.into_deserializer().$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for RowDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let names = self
+            .columns
+            .iter()
+            .map(|c| c.rsplit_once('.').map_or(c.as_str(), |(_, name)| name));
+        visitor.visit_map(MapDeserializer::new(names.zip(self.row)))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(SeqDeserializer::new(self.row.into_iter()))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_single! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_f32
+        deserialize_f64 deserialize_char deserialize_str deserialize_string
+        deserialize_option deserialize_unit
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 bytes byte_buf unit_struct enum identifier ignored_any
+    }
+}
+
+// 单个值的反序列化，NULL 对应 Option::None
+pub struct ValueDeserializer {
+    value: Value,
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Value {
+    type Deserializer = ValueDeserializer;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        ValueDeserializer { value: self }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value {
+            Value::Null => visitor.visit_none(),
+            Value::Boolean(b) => visitor.visit_bool(b),
+            Value::Integer(i) => visitor.visit_i64(i),
+            Value::Float(f) => visitor.visit_f64(f),
+            Value::String(s) => visitor.visit_string(s),
+            Value::Interval(i) => visitor.visit_string(i.to_string()),
+            v @ (Value::Date(_) | Value::Timestamp(_) | Value::Decimal(_)) => {
+                visitor.visit_string(v.to_string())
+            }
+            Value::Blob(b) => visitor.visit_byte_buf(b.into_vec()),
+            Value::Array(items) => visitor.visit_seq(SeqDeserializer::new(items.into_vec().into_iter())),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct enum
+        identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde::Deserialize;
+
+    use crate::{
+        error::{Error, Result},
+        sql::types::Value,
+    };
+
+    use super::from_row;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct User {
+        id: i64,
+        name: String,
+        score: Option<f64>,
+    }
+
+    #[test]
+    fn test_from_row() -> Result<()> {
+        let columns = vec!["name".to_string(), "t.id".to_string(), "score".to_string()];
+        let row = vec![Value::String("a".into()), Value::Integer(1), Value::Null];
+
+        // 按列名匹配字段
+        let user: User = from_row(&columns, row.clone())?;
+        assert_eq!(
+            user,
+            User {
+                id: 1,
+                name: "a".into(),
+                score: None
+            }
+        );
+
+        // 按位置匹配
+        let tuple: (String, i32, Option<f64>) = from_row(&columns, row.clone())?;
+        assert_eq!(tuple, ("a".into(), 1, None));
+
+        let map: HashMap<String, Option<String>> =
+            from_row(&columns[..1], vec![Value::String("b".into())])?;
+        assert_eq!(map.get("name"), Some(&Some("b".to_string())));
+
+        // 单列转换成基本类型
+        let count: i64 = from_row(&columns[1..2], vec![Value::Integer(3)])?;
+        assert_eq!(count, 3);
+
+        // 类型不匹配
+        assert!(matches!(
+            from_row::<(i64, i64, i64)>(&columns, row.clone()),
+            Err(Error::TypeMismatch(_))
+        ));
+        assert!(from_row::<i64>(&columns, row).is_err());
+        Ok(())
+    }
+}
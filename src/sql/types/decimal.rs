@@ -0,0 +1,306 @@
+use std::{cmp::Ordering, fmt::Display, hash::Hash};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+// 最多 38 位有效数字，i128 可以完整表示
+pub(crate) const MAX_PRECISION: u8 = 38;
+// 除法结果最少保留的小数位数
+const MIN_DIV_SCALE: u8 = 6;
+
+// 定点小数，值为 value / 10^scale，例如 12.50 保存为 value = 1250、scale = 2
+// 写入 DECIMAL(p, s) 列时统一转换为列的 scale，同一列中的值编码之后的顺序和大小顺序一致
+// 比较、哈希时和 scale 无关，1.5 和 1.50 相等
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Decimal {
+    pub value: i128,
+    pub scale: u8,
+}
+
+impl Decimal {
+    pub fn new(value: i128, scale: u8) -> Self {
+        Self { value, scale }
+    }
+
+    pub fn from_i64(i: i64) -> Self {
+        Self::new(i as i128, 0)
+    }
+
+    // 浮点数按照最短的十进制表示转换，例如 0.1 转换为 0.1，而不是 0.1000000000000000055...
+    pub fn from_f64(f: f64) -> Result<Self> {
+        if !f.is_finite() {
+            return Err(Error::InvalidInput(format!(
+                "cannot convert {} to decimal",
+                f
+            )));
+        }
+        Self::parse(&f.to_string())
+    }
+
+    // 解析 [-]123.45 格式的字符串，超过 38 位的小数部分四舍五入
+    pub fn parse(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidInput(format!("invalid decimal value: {}", s));
+        let text = s.trim();
+        let (negative, text) = match text.strip_prefix('-') {
+            Some(text) => (true, text),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        let (int_part, frac_part) = text.split_once('.').unwrap_or((text, ""));
+        if int_part.is_empty() && frac_part.is_empty() {
+            return Err(invalid());
+        }
+        let mut value: i128 = 0;
+        let mut scale = 0;
+        let mut round_up = false;
+        for (i, c) in int_part.chars().chain(frac_part.chars()).enumerate() {
+            let digit = c.to_digit(10).ok_or_else(invalid)? as i128;
+            if i >= int_part.len() {
+                if scale == MAX_PRECISION {
+                    // 只需要看被舍去的第一位
+                    round_up |= i == int_part.len() + MAX_PRECISION as usize && digit >= 5;
+                    continue;
+                }
+                scale += 1;
+            }
+            value = value
+                .checked_mul(10)
+                .and_then(|v| v.checked_add(digit))
+                .ok_or_else(|| out_of_range(s))?;
+        }
+        if round_up {
+            value = value.checked_add(1).ok_or_else(|| out_of_range(s))?;
+        }
+        Ok(Self::new(if negative { -value } else { value }, scale))
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.value as f64 / 10f64.powi(self.scale as i32)
+    }
+
+    // 小数部分为 0 时转换为整数
+    pub fn to_i64(self) -> Option<i64> {
+        let p = pow10(self.scale);
+        if self.value % p != 0 {
+            return None;
+        }
+        i64::try_from(self.value / p).ok()
+    }
+
+    // 有效数字的位数，0 为 1 位
+    pub fn digits(self) -> u8 {
+        let mut v = self.value.unsigned_abs();
+        let mut n = 1;
+        while v >= 10 {
+            v /= 10;
+            n += 1;
+        }
+        n
+    }
+
+    // 转换为指定的小数位数，位数减少时四舍五入
+    pub fn rescale(self, scale: u8) -> Result<Self> {
+        if scale > MAX_PRECISION {
+            return Err(out_of_range(self));
+        }
+        let value = match scale.cmp(&self.scale) {
+            Ordering::Equal => self.value,
+            Ordering::Greater => self
+                .value
+                .checked_mul(pow10(scale - self.scale))
+                .ok_or_else(|| out_of_range(self))?,
+            Ordering::Less => div_round(self.value, pow10(self.scale - scale)),
+        };
+        Ok(Self::new(value, scale))
+    }
+
+    // 转换为 DECIMAL(precision, scale) 列的值，整数部分超过 precision - scale 位时报错
+    pub fn fit(self, precision: u8, scale: u8) -> Result<Self> {
+        let d = self.rescale(scale)?;
+        if d.value != 0 && d.digits() > precision {
+            return Err(Error::InvalidInput(format!(
+                "numeric field overflow: {} does not fit DECIMAL({}, {})",
+                self, precision, scale
+            )));
+        }
+        Ok(d)
+    }
+
+    pub fn checked_add(self, other: Self) -> Result<Self> {
+        let scale = self.scale.max(other.scale);
+        let (l, r) = (self.rescale(scale)?, other.rescale(scale)?);
+        let value = l
+            .value
+            .checked_add(r.value)
+            .ok_or_else(|| out_of_range(self))?;
+        Ok(Self::new(value, scale))
+    }
+
+    pub fn checked_neg(self) -> Result<Self> {
+        let value = self.value.checked_neg().ok_or_else(|| out_of_range(self))?;
+        Ok(Self::new(value, self.scale))
+    }
+
+    // 乘法的结果是精确的，小数位数是两边之和，超过 38 位时四舍五入
+    pub fn checked_mul(self, other: Self) -> Result<Self> {
+        let value = self
+            .value
+            .checked_mul(other.value)
+            .ok_or_else(|| out_of_range(self))?;
+        let scale = self.scale + other.scale;
+        match scale > MAX_PRECISION {
+            true => Ok(Self::new(
+                div_round(value, pow10(scale - MAX_PRECISION)),
+                MAX_PRECISION,
+            )),
+            false => Ok(Self::new(value, scale)),
+        }
+    }
+
+    // 除法的结果保留两边中较大的小数位数，至少保留 6 位，四舍五入
+    pub fn checked_div(self, other: Self) -> Result<Self> {
+        if other.value == 0 {
+            return Err(Error::InvalidInput("division by zero".into()));
+        }
+        let scale = self.scale.max(other.scale).max(MIN_DIV_SCALE);
+        // (l / 10^ls) / (r / 10^rs) * 10^scale = l * 10^(scale + rs - ls) / r
+        let exp = scale + other.scale - self.scale;
+        let value = (exp <= MAX_PRECISION)
+            .then(|| self.value.checked_mul(pow10(exp)))
+            .flatten()
+            .ok_or_else(|| out_of_range(self))?;
+        Ok(Self::new(div_round(value, other.value), scale))
+    }
+
+    // 整数部分向下取整，小数部分统一扩展到 38 位，用于比较不同 scale 的值
+    fn split(self) -> (i128, i128) {
+        let p = pow10(self.scale);
+        (
+            self.value.div_euclid(p),
+            self.value.rem_euclid(p) * pow10(MAX_PRECISION - self.scale),
+        )
+    }
+
+    // 去掉小数部分末尾的 0
+    fn normalize(self) -> Self {
+        let mut d = self;
+        while d.scale > 0 && d.value % 10 == 0 {
+            d.value /= 10;
+            d.scale -= 1;
+        }
+        d
+    }
+}
+
+fn pow10(exp: u8) -> i128 {
+    10i128.pow(exp as u32)
+}
+
+// 除法，四舍五入到整数，0.5 远离 0
+fn div_round(n: i128, d: i128) -> i128 {
+    let (q, r) = (n / d, n % d);
+    if r.unsigned_abs() >= d.unsigned_abs() - r.unsigned_abs() {
+        if (n < 0) == (d < 0) {
+            q + 1
+        } else {
+            q - 1
+        }
+    } else {
+        q
+    }
+}
+
+fn out_of_range(v: impl Display) -> Error {
+    Error::InvalidInput(format!("decimal value out of range: {}", v))
+}
+
+impl Display for Decimal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.value < 0 { "-" } else { "" };
+        let abs = self.value.unsigned_abs();
+        if self.scale == 0 {
+            return write!(f, "{}{}", sign, abs);
+        }
+        let p = 10u128.pow(self.scale as u32);
+        write!(
+            f,
+            "{}{}.{:0width$}",
+            sign,
+            abs / p,
+            abs % p,
+            width = self.scale as usize
+        )
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.split().cmp(&other.split())
+    }
+}
+
+impl Hash for Decimal {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        let d = self.normalize();
+        d.value.hash(state);
+        d.scale.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Decimal;
+    use crate::error::Result;
+
+    fn d(s: &str) -> Decimal {
+        Decimal::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_decimal() -> Result<()> {
+        assert_eq!(d("12.50"), Decimal::new(1250, 2));
+        assert_eq!(d("-0.05").to_string(), "-0.05");
+        assert_eq!(d(".5").to_string(), "0.5");
+        assert_eq!(d("12.50").to_string(), "12.50");
+        assert!(Decimal::parse("1.2.3").is_err());
+        assert!(Decimal::parse("abc").is_err());
+        assert!(Decimal::parse("-").is_err());
+        assert!(Decimal::parse(&"9".repeat(40)).is_err());
+
+        // 比较、相等和 scale 无关
+        assert_eq!(d("1.5"), d("1.50"));
+        assert!(d("-1.5") < d("-1.25"));
+        assert!(d("0.3") > d("0.29999"));
+
+        // 0.1 + 0.2 精确等于 0.3
+        assert_eq!(d("0.1").checked_add(d("0.2"))?, d("0.3"));
+        assert_eq!(d("1.10").checked_mul(d("3"))?.to_string(), "3.30");
+        assert_eq!(d("10").checked_div(d("3"))?.to_string(), "3.333333");
+        assert_eq!(d("-2").checked_div(d("3"))?.to_string(), "-0.666667");
+        assert!(d("1").checked_div(d("0")).is_err());
+
+        // 四舍五入到列的精度
+        assert_eq!(d("2.345").fit(5, 2)?.to_string(), "2.35");
+        assert_eq!(d("-2.345").fit(5, 2)?.to_string(), "-2.35");
+        assert_eq!(d("7").fit(5, 2)?.to_string(), "7.00");
+        assert!(d("1234.5").fit(5, 2).is_err());
+        assert_eq!(Decimal::from_f64(0.1)?.fit(10, 2)?.to_string(), "0.10");
+        assert_eq!(d("3.00").to_i64(), Some(3));
+        assert_eq!(d("3.01").to_i64(), None);
+        Ok(())
+    }
+}
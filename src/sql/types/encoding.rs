@@ -0,0 +1,145 @@
+use crate::error::{Error, Result};
+
+use super::{Row, Value};
+
+// 行数据的编码格式
+// | 版本号 (u8) | 列数 n (u32) | 每一列结束位置的偏移 n * u32 | 每一列的值 (bincode) |
+// 通过偏移可以直接定位到某一列，只查询部分列时不需要反序列化整行
+const LEN_SIZE: usize = 4;
+
+// 行数据编码格式的版本号，编码格式变化时加一，读取时遇到不认识的版本号直接报错
+pub const ROW_FORMAT_VERSION: u8 = 1;
+
+pub fn encode_row(row: &Row) -> Result<Vec<u8>> {
+    let mut values = Vec::new();
+    let mut offsets = Vec::with_capacity(row.len());
+    for value in row {
+        bincode::serialize_into(&mut values, value)?;
+        offsets.push(values.len() as u32);
+    }
+
+    let mut buf = Vec::with_capacity(1 + LEN_SIZE * (row.len() + 1) + values.len());
+    buf.push(ROW_FORMAT_VERSION);
+    buf.extend((row.len() as u32).to_le_bytes());
+    for offset in offsets {
+        buf.extend(offset.to_le_bytes());
+    }
+    buf.extend(values);
+    Ok(buf)
+}
+
+// 解码整行数据
+pub fn decode_row(buf: &[u8]) -> Result<Row> {
+    let reader = RowReader::new(buf)?;
+    (0..reader.len).map(|i| reader.value(i)).collect()
+}
+
+// 只解码 positions 指定的列，按照 positions 的顺序返回
+pub fn decode_columns(buf: &[u8], positions: &[usize]) -> Result<Row> {
+    let reader = RowReader::new(buf)?;
+    positions.iter().map(|i| reader.value(*i)).collect()
+}
+
+struct RowReader<'a> {
+    len: usize,
+    offsets: &'a [u8],
+    values: &'a [u8],
+}
+
+impl<'a> RowReader<'a> {
+    fn new(buf: &'a [u8]) -> Result<Self> {
+        let buf = match buf.split_first() {
+            Some((&ROW_FORMAT_VERSION, buf)) => buf,
+            Some((version, _)) => {
+                return Err(Error::Internal(format!(
+                    "unsupported row format version {}",
+                    version
+                )))
+            }
+            None => return Err(invalid()),
+        };
+        let len = read_u32(buf, 0)? as usize;
+        // 列数来自数据本身，损坏时可能非常大，计算头部长度时不能溢出
+        let header = len
+            .checked_add(1)
+            .and_then(|n| n.checked_mul(LEN_SIZE))
+            .ok_or_else(invalid)?;
+        if buf.len() < header {
+            return Err(invalid());
+        }
+        Ok(Self {
+            len,
+            offsets: &buf[LEN_SIZE..header],
+            values: &buf[header..],
+        })
+    }
+
+    // 第 i 列的值，位于前一列的结束位置和这一列的结束位置之间
+    fn value(&self, i: usize) -> Result<Value> {
+        if i >= self.len {
+            return Err(invalid());
+        }
+        let start = match i {
+            0 => 0,
+            i => read_u32(self.offsets, (i - 1) * LEN_SIZE)? as usize,
+        };
+        let end = read_u32(self.offsets, i * LEN_SIZE)? as usize;
+        let bytes = self.values.get(start..end).ok_or_else(invalid)?;
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+fn read_u32(buf: &[u8], pos: usize) -> Result<u32> {
+    let end = pos.checked_add(LEN_SIZE).ok_or_else(invalid)?;
+    let bytes = buf.get(pos..end).ok_or_else(invalid)?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn invalid() -> Error {
+    Error::Internal("invalid row encoding".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::Result,
+        sql::types::{Interval, Value},
+    };
+
+    use super::{decode_columns, decode_row, encode_row, ROW_FORMAT_VERSION};
+
+    #[test]
+    fn test_row_encoding() -> Result<()> {
+        let row = vec![
+            Value::Integer(-1),
+            Value::Null,
+            Value::String("abc".into()),
+            Value::Float(1.5),
+            Value::Boolean(true),
+            Value::Interval(Interval::parse("1 day")?),
+        ];
+        let buf = encode_row(&row)?;
+        assert_eq!(decode_row(&buf)?, row);
+        assert_eq!(
+            decode_columns(&buf, &[4, 2, 2])?,
+            vec![Value::Boolean(true), Value::String("abc".into()), Value::String("abc".into())]
+        );
+        assert_eq!(decode_columns(&buf, &[])?, vec![]);
+        assert_eq!(decode_row(&encode_row(&vec![])?)?, vec![]);
+
+        // 越界、数据损坏时返回错误
+        assert!(decode_columns(&buf, &[6]).is_err());
+        assert!(decode_row(&buf[..buf.len() - 1]).is_err());
+        assert!(decode_row(&buf[..10]).is_err());
+        assert!(decode_row(&[]).is_err());
+        let mut huge = vec![ROW_FORMAT_VERSION];
+        huge.extend(u32::MAX.to_le_bytes());
+        assert!(decode_row(&huge).is_err());
+
+        // 不认识的版本号
+        let mut buf = buf;
+        buf[0] = ROW_FORMAT_VERSION + 1;
+        assert!(decode_row(&buf).is_err());
+        Ok(())
+    }
+}
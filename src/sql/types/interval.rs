@@ -0,0 +1,159 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+// 时间间隔，月和秒分开保存，因为每个月的天数不同
+// 例如 '1 year 2 months 3 days 04:05:06' 保存为 14 个月加上 3 天 4 小时 5 分 6 秒
+// 先比较月数再比较秒数，和相等判断以及索引中 key 的编码顺序一致
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Interval {
+    pub months: i64,
+    pub seconds: i64,
+}
+
+impl Interval {
+    // 解析时间间隔，由若干个 "数量 单位" 组成，也可以带有 [-]HH:MM:SS 格式的时间
+    // 例如 '1 day'、'2 hours 30 minutes'、'-1 year'、'3 days 04:00:00'
+    pub fn parse(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidInput(format!("invalid interval {}", s));
+        let mut interval = Interval {
+            months: 0,
+            seconds: 0,
+        };
+        let mut parts = s.split_whitespace().peekable();
+        if parts.peek().is_none() {
+            return Err(invalid());
+        }
+        while let Some(part) = parts.next() {
+            if part.contains(':') {
+                let (sign, hms) = match part.strip_prefix('-') {
+                    Some(hms) => (-1, hms),
+                    None => (1, part),
+                };
+                // HH:MM 或者 HH:MM:SS
+                let fields = hms.split(':').collect::<Vec<_>>();
+                if fields.len() > 3 {
+                    return Err(invalid());
+                }
+                let mut seconds: i64 = 0;
+                for i in 0..3 {
+                    let v = fields.get(i).map_or(Ok(0), |v| v.parse::<i64>());
+                    seconds = seconds
+                        .checked_mul(60)
+                        .and_then(|s| s.checked_add(v.ok()?))
+                        .ok_or_else(invalid)?;
+                }
+                interval.seconds = interval
+                    .seconds
+                    .checked_add(sign * seconds)
+                    .ok_or_else(invalid)?;
+                continue;
+            }
+
+            let n = part.parse::<i64>().map_err(|_| invalid())?;
+            let unit = parts.next().ok_or_else(invalid)?.to_lowercase();
+            // 单位可以是复数形式，例如 days
+            let (months, seconds) = match unit.trim_end_matches('s') {
+                "year" => (12, 0),
+                "month" => (1, 0),
+                "week" => (0, 7 * SECONDS_PER_DAY),
+                "day" => (0, SECONDS_PER_DAY),
+                "hour" => (0, 60 * 60),
+                "minute" => (0, 60),
+                "second" => (0, 1),
+                _ => return Err(invalid()),
+            };
+            interval.months = n
+                .checked_mul(months)
+                .and_then(|m| m.checked_add(interval.months))
+                .ok_or_else(invalid)?;
+            interval.seconds = n
+                .checked_mul(seconds)
+                .and_then(|m| m.checked_add(interval.seconds))
+                .ok_or_else(invalid)?;
+        }
+        Ok(interval)
+    }
+
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        Some(Interval {
+            months: self.months.checked_add(other.months)?,
+            seconds: self.seconds.checked_add(other.seconds)?,
+        })
+    }
+
+    pub fn checked_neg(self) -> Option<Self> {
+        Some(Interval {
+            months: self.months.checked_neg()?,
+            seconds: self.seconds.checked_neg()?,
+        })
+    }
+}
+
+impl Display for Interval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let plural = |n: i64| if n.abs() == 1 { "" } else { "s" };
+        let mut parts = Vec::new();
+        let (years, months) = (self.months / 12, self.months % 12);
+        if years != 0 {
+            parts.push(format!("{} year{}", years, plural(years)));
+        }
+        if months != 0 {
+            parts.push(format!("{} month{}", months, plural(months)));
+        }
+        let (days, seconds) = (self.seconds / SECONDS_PER_DAY, self.seconds % SECONDS_PER_DAY);
+        if days != 0 {
+            parts.push(format!("{} day{}", days, plural(days)));
+        }
+        if seconds != 0 || parts.is_empty() {
+            let s = seconds.abs();
+            parts.push(format!(
+                "{}{:02}:{:02}:{:02}",
+                if seconds < 0 { "-" } else { "" },
+                s / 3600,
+                s % 3600 / 60,
+                s % 60
+            ));
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Result;
+
+    use super::Interval;
+
+    #[test]
+    fn test_interval() -> Result<()> {
+        let i = Interval::parse("1 year 2 months 3 days 04:05:06")?;
+        assert_eq!(i.months, 14);
+        assert_eq!(i.seconds, 3 * 86400 + 4 * 3600 + 5 * 60 + 6);
+        assert_eq!(i.to_string(), "1 year 2 months 3 days 04:05:06");
+        assert_eq!(Interval::parse(&i.to_string())?, i);
+
+        assert_eq!(Interval::parse("2 HOURS 30 minute")?.to_string(), "02:30:00");
+        assert_eq!(Interval::parse("-1 day")?.to_string(), "-1 day");
+        assert_eq!(Interval::parse("1 week -01:00")?.to_string(), "6 days 23:00:00");
+        assert_eq!(Interval::parse("0 seconds")?.to_string(), "00:00:00");
+
+        // 月数不同时不再比较秒数，相等的时间间隔比较结果也是相等
+        assert!(Interval::parse("1 month")? > Interval::parse("31 days")?);
+        assert!(Interval::parse("-1 month")? < Interval::parse("1 day")?);
+        assert!(Interval::parse("1 month 1 day")? > Interval::parse("1 month")?);
+        assert_ne!(Interval::parse("1 month")?, Interval::parse("30 days")?);
+        let (a, b) = (Interval::parse("1 day")?, Interval::parse("24 hours")?);
+        assert_eq!(a, b);
+        assert_eq!(a.cmp(&b), std::cmp::Ordering::Equal);
+
+        for s in ["", "1", "day", "1 fortnight", "1:2:3:4", "9223372036854775807 years"] {
+            assert!(Interval::parse(s).is_err(), "{}", s);
+        }
+        Ok(())
+    }
+}
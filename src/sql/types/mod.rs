@@ -4,12 +4,50 @@ use serde::{Deserialize, Serialize};
 
 use super::parser::ast::{Consts, Expression};
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub mod blob;
+pub mod coerce;
+pub mod de;
+pub mod decimal;
+pub mod encoding;
+pub mod interval;
+pub mod temporal;
+
+pub use decimal::Decimal;
+pub use interval::Interval;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum DataType {
     Boolean,
     Integer,
     Float,
     String,
+    Interval,
+    // 数组，元素的类型，例如 int[]
+    Array(Box<DataType>),
+    // 新的类型加在最后，已经保存的表结构中类型的编号不变
+    Date,
+    Timestamp,
+    // 定点小数，总位数和小数位数，例如 DECIMAL(10, 2)
+    Decimal(u8, u8),
+    // 二进制数据
+    Blob,
+}
+
+impl Display for DataType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DataType::Boolean => "BOOLEAN",
+            DataType::Integer => "INTEGER",
+            DataType::Float => "FLOAT",
+            DataType::String => "STRING",
+            DataType::Interval => "INTERVAL",
+            DataType::Array(dt) => return write!(f, "{}[]", dt),
+            DataType::Date => "DATE",
+            DataType::Timestamp => "TIMESTAMP",
+            DataType::Decimal(p, s) => return write!(f, "DECIMAL({}, {})", p, s),
+            DataType::Blob => "BLOB",
+        })
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -19,6 +57,17 @@ pub enum Value {
     Integer(i64),
     Float(f64),
     String(String),
+    Interval(Interval),
+    // 数组，使用 Box<[Value]> 而不是 Vec 保持 Value 的大小不变
+    Array(Box<[Value]>),
+    // 1970-01-01 之后的天数
+    Date(i64),
+    // 1970-01-01 00:00:00 之后的微秒数
+    Timestamp(i64),
+    // 定点小数，同样使用 Box 保持 Value 的大小不变
+    Decimal(Box<Decimal>),
+    // 二进制数据，使用 Box<[u8]> 保持 Value 的大小不变
+    Blob(#[serde(with = "serde_bytes")] Box<[u8]>),
 }
 
 impl Value {
@@ -29,6 +78,14 @@ impl Value {
             Expression::Consts(Consts::Integer(i)) => Self::Integer(i),
             Expression::Consts(Consts::Float(f)) => Self::Float(f),
             Expression::Consts(Consts::String(s)) => Self::String(s),
+            Expression::Consts(Consts::Interval(i)) => Self::Interval(i),
+            Expression::Consts(Consts::Date(d)) => Self::Date(d),
+            Expression::Consts(Consts::Timestamp(ts)) => Self::Timestamp(ts),
+            Expression::Consts(Consts::Decimal(d)) => d.into(),
+            Expression::Consts(Consts::Blob(b)) => Self::Blob(b.into()),
+            Expression::Array(items) => {
+                Self::Array(items.into_iter().map(Self::from_expression).collect())
+            }
             _ => unreachable!(),
         }
     }
@@ -40,19 +97,44 @@ impl Value {
             Self::Integer(_) => Some(DataType::Integer),
             Self::Float(_) => Some(DataType::Float),
             Self::String(_) => Some(DataType::String),
+            Self::Interval(_) => Some(DataType::Interval),
+            Self::Date(_) => Some(DataType::Date),
+            Self::Timestamp(_) => Some(DataType::Timestamp),
+            // 值本身只记录小数位数，总位数取最大值
+            Self::Decimal(d) => Some(DataType::Decimal(decimal::MAX_PRECISION, d.scale)),
+            Self::Blob(_) => Some(DataType::Blob),
+            // 数组的元素类型取第一个不为 NULL 的元素
+            Self::Array(items) => Some(DataType::Array(Box::new(
+                items.iter().find_map(|v| v.datatype())?,
+            ))),
         }
     }
 }
 
+impl From<Decimal> for Value {
+    fn from(d: Decimal) -> Self {
+        Value::Decimal(Box::new(d))
+    }
+}
+
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Value::Null => write!(f, "{}", "NULL"),
-            Value::Boolean(b) if *b => write!(f, "{}", "TRUE"),
-            Value::Boolean(_) => write!(f, "{}", "FALSE"),
+            Value::Null => write!(f, "NULL"),
+            Value::Boolean(b) if *b => write!(f, "TRUE"),
+            Value::Boolean(_) => write!(f, "FALSE"),
             Value::Integer(v) => write!(f, "{}", v),
             Value::Float(v) => write!(f, "{}", v),
             Value::String(v) => write!(f, "{}", v),
+            Value::Interval(v) => write!(f, "{}", v),
+            Value::Date(v) => write!(f, "{}", temporal::format_date(*v)),
+            Value::Timestamp(v) => write!(f, "{}", temporal::format_timestamp(*v)),
+            Value::Decimal(v) => write!(f, "{}", v),
+            Value::Blob(v) => write!(f, "{}", blob::format_blob(v)),
+            Value::Array(items) => {
+                let items = items.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+                write!(f, "[{}]", items.join(", "))
+            }
         }
     }
 }
@@ -69,6 +151,23 @@ impl PartialOrd for Value {
             (Value::Float(a), Value::Integer(b)) => a.partial_cmp(&(*b as f64)),
             (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
             (Value::String(a), Value::String(b)) => a.partial_cmp(b),
+            (Value::Interval(a), Value::Interval(b)) => a.partial_cmp(b),
+            (Value::Array(a), Value::Array(b)) => a.partial_cmp(b),
+            (Value::Date(a), Value::Date(b)) => a.partial_cmp(b),
+            (Value::Timestamp(a), Value::Timestamp(b)) => a.partial_cmp(b),
+            (Value::Decimal(a), Value::Decimal(b)) => a.partial_cmp(b),
+            (Value::Decimal(a), Value::Integer(b)) => (**a).partial_cmp(&Decimal::from_i64(*b)),
+            (Value::Integer(a), Value::Decimal(b)) => Decimal::from_i64(*a).partial_cmp(b),
+            (Value::Decimal(a), Value::Float(b)) => a.to_f64().partial_cmp(b),
+            (Value::Float(a), Value::Decimal(b)) => a.partial_cmp(&b.to_f64()),
+            (Value::Blob(a), Value::Blob(b)) => a.partial_cmp(b),
+            // 日期按照当天 00:00:00 和时间戳比较
+            (Value::Date(a), Value::Timestamp(b)) => {
+                temporal::date_to_timestamp(*a).ok()?.partial_cmp(b)
+            }
+            (Value::Timestamp(a), Value::Date(b)) => {
+                a.partial_cmp(&temporal::date_to_timestamp(*b).ok()?)
+            }
             (_, _) => None,
         }
     }
@@ -94,6 +193,30 @@ impl Hash for Value {
                 state.write_u8(2);
                 v.hash(state);
             }
+            Value::Interval(v) => {
+                state.write_u8(5);
+                v.hash(state);
+            }
+            Value::Array(v) => {
+                state.write_u8(6);
+                v.hash(state);
+            }
+            Value::Date(v) => {
+                state.write_u8(7);
+                v.hash(state);
+            }
+            Value::Timestamp(v) => {
+                state.write_u8(8);
+                v.hash(state);
+            }
+            Value::Decimal(v) => {
+                state.write_u8(9);
+                v.hash(state);
+            }
+            Value::Blob(v) => {
+                state.write_u8(10);
+                v.hash(state);
+            }
         }
     }
 }
@@ -0,0 +1,130 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+use crate::error::{Error, Result};
+
+// DATE 保存为 1970-01-01 之后的天数，TIMESTAMP 保存为 1970-01-01 00:00:00 之后的微秒数，都按 UTC 时间处理
+// 两者都是 i64，keycode 编码之后的字节顺序和时间顺序一致，可以直接用作主键、索引以及范围查询
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const MICROS_PER_DAY: i64 = 24 * 60 * 60 * 1_000_000;
+
+fn epoch() -> NaiveDate {
+    NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()
+}
+
+// 解析 YYYY-MM-DD 格式的日期
+pub fn parse_date(s: &str) -> Result<i64> {
+    match NaiveDate::parse_from_str(s.trim(), DATE_FORMAT) {
+        Ok(d) => Ok(date_from_naive(d)),
+        Err(_) => Err(Error::InvalidInput(format!("invalid date value {}", s))),
+    }
+}
+
+// 解析 YYYY-MM-DD HH:MM:SS[.ffffff] 格式的时间戳，日期和时间之间也可以用 T 分隔
+// 只有日期部分时表示当天的 00:00:00
+pub fn parse_timestamp(s: &str) -> Result<i64> {
+    let s = s.trim();
+    for fmt in ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"] {
+        if let Ok(ts) = NaiveDateTime::parse_from_str(s, fmt) {
+            return Ok(timestamp_from_naive(ts));
+        }
+    }
+    match parse_date(s) {
+        Ok(days) => date_to_timestamp(days),
+        Err(_) => Err(Error::InvalidInput(format!(
+            "invalid timestamp value {}",
+            s
+        ))),
+    }
+}
+
+pub fn date_from_naive(d: NaiveDate) -> i64 {
+    (d - epoch()).num_days()
+}
+
+pub fn timestamp_from_naive(ts: NaiveDateTime) -> i64 {
+    ts.and_utc().timestamp_micros()
+}
+
+pub fn date_to_naive(days: i64) -> Result<NaiveDate> {
+    chrono::Duration::try_days(days)
+        .and_then(|d| epoch().checked_add_signed(d))
+        .ok_or_else(|| Error::InvalidInput(format!("date out of range {}", days)))
+}
+
+pub fn timestamp_to_naive(micros: i64) -> Result<NaiveDateTime> {
+    DateTime::from_timestamp_micros(micros)
+        .map(|ts| ts.naive_utc())
+        .ok_or_else(|| Error::InvalidInput(format!("timestamp out of range {}", micros)))
+}
+
+// 日期转换为当天 00:00:00 的时间戳
+pub fn date_to_timestamp(days: i64) -> Result<i64> {
+    days.checked_mul(MICROS_PER_DAY)
+        .ok_or_else(|| Error::InvalidInput(format!("date out of range {}", days)))
+}
+
+// 时间戳截断为日期，1970 年之前的时间向下取整
+pub fn timestamp_to_date(micros: i64) -> i64 {
+    micros.div_euclid(MICROS_PER_DAY)
+}
+
+pub fn format_date(days: i64) -> String {
+    match date_to_naive(days) {
+        Ok(d) => d.format(DATE_FORMAT).to_string(),
+        Err(_) => days.to_string(),
+    }
+}
+
+// 没有小数部分的秒时不输出小数
+pub fn format_timestamp(micros: i64) -> String {
+    let fmt = match micros % 1_000_000 {
+        0 => "%Y-%m-%d %H:%M:%S",
+        _ => "%Y-%m-%d %H:%M:%S%.6f",
+    };
+    match timestamp_to_naive(micros) {
+        Ok(ts) => ts.format(fmt).to_string(),
+        Err(_) => micros.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_date, format_timestamp, parse_date, parse_timestamp, timestamp_to_date};
+    use crate::error::Result;
+
+    #[test]
+    fn test_temporal() -> Result<()> {
+        assert_eq!(parse_date("1970-01-02")?, 1);
+        assert_eq!(parse_date("1969-12-31")?, -1);
+        assert_eq!(format_date(parse_date("2024-02-29")?), "2024-02-29");
+        assert!(parse_date("2024-02-30").is_err());
+        assert!(parse_date("2024-01-01 10:00:00").is_err());
+
+        assert_eq!(parse_timestamp("1970-01-01 00:00:01")?, 1_000_000);
+        assert_eq!(parse_timestamp("1970-01-02")?, 86_400_000_000);
+        assert_eq!(
+            parse_timestamp("2024-01-31T10:20:30.5")?,
+            parse_timestamp("2024-01-31 10:20:30.500")?
+        );
+        assert_eq!(
+            format_timestamp(parse_timestamp("2024-01-31 10:20:30")?),
+            "2024-01-31 10:20:30"
+        );
+        assert_eq!(
+            format_timestamp(parse_timestamp("1969-12-31 23:59:59.25")?),
+            "1969-12-31 23:59:59.250000"
+        );
+        assert!(parse_timestamp("2024-01-31 25:00:00").is_err());
+
+        assert_eq!(
+            timestamp_to_date(parse_timestamp("1969-12-31 12:00:00")?),
+            -1
+        );
+        assert_eq!(
+            timestamp_to_date(parse_timestamp("2024-01-31 12:00:00")?),
+            parse_date("2024-01-31")?
+        );
+        Ok(())
+    }
+}
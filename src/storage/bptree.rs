@@ -0,0 +1,685 @@
+use std::ops::{Bound, RangeBounds};
+
+use crate::error::Result;
+
+use super::engine::{EngineIterator, Status};
+
+// 基于页的 B+ 树存储引擎，数据保存在内存中，为以后把页写到磁盘上做准备
+// 所有的页保存在 pages 中，页之间通过页号引用，释放的页号放入 free 中复用
+// 页的大小按照编码之后的字节数计算，超过 page_size 时分裂，
+// 不到 page_size 的四分之一时和相邻的页合并，合并之后超过 page_size 时重新分裂，相当于在两个页之间重新分配
+// 叶子页之间通过 prev、next 连成双向链表，范围扫描时沿着链表正向或者反向遍历
+
+type PageId = usize;
+
+const DEFAULT_PAGE_SIZE: usize = 4096;
+
+// 页头的大小，每个 key、value 的长度，以及每个子页号占用的大小
+const PAGE_HEADER_SIZE: usize = 16;
+const LEN_SIZE: usize = 4;
+const CHILD_SIZE: usize = 8;
+
+enum Page {
+    // 按照 key 排序的记录
+    Leaf {
+        entries: Vec<(Vec<u8>, Vec<u8>)>,
+        prev: Option<PageId>,
+        next: Option<PageId>,
+    },
+    // children 比 keys 多一个，children[i + 1] 中所有的 key 都大于等于 keys[i]
+    Internal {
+        keys: Vec<Vec<u8>>,
+        children: Vec<PageId>,
+    },
+}
+
+impl Page {
+    fn empty_leaf() -> Self {
+        Page::Leaf {
+            entries: Vec::new(),
+            prev: None,
+            next: None,
+        }
+    }
+
+    fn size(&self) -> usize {
+        PAGE_HEADER_SIZE
+            + match self {
+                Page::Leaf { entries, .. } => entries.iter().map(|(k, v)| entry_size(k, v)).sum(),
+                Page::Internal { keys, children } => {
+                    keys.iter().map(|k| LEN_SIZE + k.len()).sum::<usize>()
+                        + children.len() * CHILD_SIZE
+                }
+            }
+    }
+
+    fn entries(&self) -> &[(Vec<u8>, Vec<u8>)] {
+        match self {
+            Page::Leaf { entries, .. } => entries,
+            Page::Internal { .. } => unreachable!("page is not a leaf"),
+        }
+    }
+}
+
+fn entry_size(key: &[u8], value: &[u8]) -> usize {
+    LEN_SIZE * 2 + key.len() + value.len()
+}
+
+// 按照字节数找到中间的位置，左右两边至少各保留 min 个元素
+fn split_point(sizes: impl Iterator<Item = usize>, len: usize, min: usize) -> usize {
+    let sizes = sizes.collect::<Vec<_>>();
+    let half = sizes.iter().sum::<usize>() / 2;
+    let mut acc = 0;
+    let at = sizes
+        .iter()
+        .position(|size| {
+            acc += size;
+            acc >= half
+        })
+        .unwrap_or(0);
+    at.clamp(min, len - min)
+}
+
+pub struct BPTreeEngine {
+    pages: Vec<Page>,
+    free: Vec<PageId>,
+    root: PageId,
+    page_size: usize,
+    keys: u64,
+    size: u64,
+}
+
+impl Default for BPTreeEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BPTreeEngine {
+    pub fn new() -> Self {
+        Self::with_page_size(DEFAULT_PAGE_SIZE)
+    }
+
+    pub fn with_page_size(page_size: usize) -> Self {
+        Self {
+            pages: vec![Page::empty_leaf()],
+            free: Vec::new(),
+            root: 0,
+            page_size,
+            keys: 0,
+            size: 0,
+        }
+    }
+
+    // 正在使用的页数
+    pub fn page_count(&self) -> usize {
+        self.pages.len() - self.free.len()
+    }
+
+    // 树的高度，只有一个叶子页时为 1
+    pub fn depth(&self) -> usize {
+        let mut depth = 1;
+        let mut id = self.root;
+        while let Page::Internal { children, .. } = &self.pages[id] {
+            id = children[0];
+            depth += 1;
+        }
+        depth
+    }
+
+    fn alloc(&mut self, page: Page) -> PageId {
+        match self.free.pop() {
+            Some(id) => {
+                self.pages[id] = page;
+                id
+            }
+            None => {
+                self.pages.push(page);
+                self.pages.len() - 1
+            }
+        }
+    }
+
+    fn release(&mut self, id: PageId) -> Page {
+        self.free.push(id);
+        std::mem::replace(&mut self.pages[id], Page::empty_leaf())
+    }
+
+    fn set_prev(&mut self, id: Option<PageId>, page: PageId) {
+        if let Some(Page::Leaf { prev, .. }) = id.map(|id| &mut self.pages[id]) {
+            *prev = Some(page);
+        }
+    }
+
+    fn internal_mut(&mut self, id: PageId) -> (&mut Vec<Vec<u8>>, &mut Vec<PageId>) {
+        match &mut self.pages[id] {
+            Page::Internal { keys, children } => (keys, children),
+            Page::Leaf { .. } => unreachable!("page is not an internal page"),
+        }
+    }
+
+    // 从根节点开始查找 key 所在的叶子页
+    fn find_leaf(&self, key: &[u8]) -> PageId {
+        let mut id = self.root;
+        while let Page::Internal { keys, children } = &self.pages[id] {
+            id = children[keys.partition_point(|k| k.as_slice() <= key)];
+        }
+        id
+    }
+
+    // 最左边或者最右边的叶子页
+    fn edge_leaf(&self, last: bool) -> PageId {
+        let mut id = self.root;
+        while let Page::Internal { children, .. } = &self.pages[id] {
+            id = if last {
+                children[children.len() - 1]
+            } else {
+                children[0]
+            };
+        }
+        id
+    }
+
+    // 写入 key，页分裂时返回新的右边的页以及它的最小 key
+    fn insert(&mut self, id: PageId, key: Vec<u8>, value: Vec<u8>) -> Option<(Vec<u8>, PageId)> {
+        match &mut self.pages[id] {
+            Page::Leaf { entries, .. } => match entries.binary_search_by(|(k, _)| k.cmp(&key)) {
+                Ok(i) => {
+                    self.size = self.size + value.len() as u64 - entries[i].1.len() as u64;
+                    entries[i].1 = value;
+                }
+                Err(i) => {
+                    self.keys += 1;
+                    self.size += (key.len() + value.len()) as u64;
+                    entries.insert(i, (key, value));
+                }
+            },
+            Page::Internal { keys, children } => {
+                let i = keys.partition_point(|k| k <= &key);
+                let child = children[i];
+                if let Some((sep, right)) = self.insert(child, key, value) {
+                    let (keys, children) = self.internal_mut(id);
+                    keys.insert(i, sep);
+                    children.insert(i + 1, right);
+                }
+            }
+        }
+        self.split(id)
+    }
+
+    // 页超过 page_size 时从中间分裂，左边保留在原来的页中
+    // 只有一条记录的叶子页无法分裂，可以超过 page_size
+    fn split(&mut self, id: PageId) -> Option<(Vec<u8>, PageId)> {
+        let page = &mut self.pages[id];
+        if page.size() <= self.page_size {
+            return None;
+        }
+        match page {
+            Page::Leaf { entries, next, .. } => {
+                if entries.len() < 2 {
+                    return None;
+                }
+                let sizes = entries.iter().map(|(k, v)| entry_size(k, v));
+                let at = split_point(sizes, entries.len(), 1);
+                let right_entries = entries.split_off(at);
+                let sep = right_entries[0].0.clone();
+                let old_next = next.take();
+                let right = self.alloc(Page::Leaf {
+                    entries: right_entries,
+                    prev: Some(id),
+                    next: old_next,
+                });
+                self.set_prev(old_next, right);
+                if let Page::Leaf { next, .. } = &mut self.pages[id] {
+                    *next = Some(right);
+                }
+                Some((sep, right))
+            }
+            // 中间的 key 移到父节点中，左右两边至少各保留一个 key
+            Page::Internal { keys, children } => {
+                if keys.len() < 3 {
+                    return None;
+                }
+                let sizes = keys.iter().map(|k| LEN_SIZE + k.len() + CHILD_SIZE);
+                let at = split_point(sizes, keys.len() - 1, 1);
+                let right_keys = keys.split_off(at + 1);
+                let sep = keys.pop().unwrap();
+                let right_children = children.split_off(at + 1);
+                let right = self.alloc(Page::Internal {
+                    keys: right_keys,
+                    children: right_children,
+                });
+                Some((sep, right))
+            }
+        }
+    }
+
+    // 删除 key，返回 key 是否存在
+    fn remove(&mut self, id: PageId, key: &[u8]) -> bool {
+        let (i, child) = match &mut self.pages[id] {
+            Page::Leaf { entries, .. } => {
+                return match entries.binary_search_by(|(k, _)| k.as_slice().cmp(key)) {
+                    Ok(i) => {
+                        let (k, v) = entries.remove(i);
+                        self.keys -= 1;
+                        self.size -= (k.len() + v.len()) as u64;
+                        true
+                    }
+                    Err(_) => false,
+                };
+            }
+            Page::Internal { keys, children } => {
+                let i = keys.partition_point(|k| k.as_slice() <= key);
+                (i, children[i])
+            }
+        };
+        if !self.remove(child, key) {
+            return false;
+        }
+        if self.underflow(child) {
+            self.rebalance(id, i);
+        }
+        true
+    }
+
+    fn underflow(&self, id: PageId) -> bool {
+        let page = &self.pages[id];
+        let empty = match page {
+            Page::Leaf { entries, .. } => entries.is_empty(),
+            Page::Internal { keys, .. } => keys.is_empty(),
+        };
+        empty || page.size() < self.page_size / 4
+    }
+
+    // 第 i 个子页太小，和相邻的页合并，合并之后太大时重新分裂
+    fn rebalance(&mut self, parent: PageId, i: usize) {
+        let (keys, children) = self.internal_mut(parent);
+        if keys.is_empty() {
+            return;
+        }
+        let i = i.min(keys.len() - 1);
+        let sep = keys.remove(i);
+        let right = children.remove(i + 1);
+        let left = children[i];
+
+        let right_page = self.release(right);
+        let mut right_next = None;
+        match (&mut self.pages[left], right_page) {
+            (
+                Page::Leaf { entries, next, .. },
+                Page::Leaf {
+                    entries: right_entries,
+                    next: old_next,
+                    ..
+                },
+            ) => {
+                entries.extend(right_entries);
+                *next = old_next;
+                right_next = old_next;
+            }
+            (
+                Page::Internal { keys, children },
+                Page::Internal {
+                    keys: right_keys,
+                    children: right_children,
+                },
+            ) => {
+                keys.push(sep);
+                keys.extend(right_keys);
+                children.extend(right_children);
+            }
+            _ => unreachable!("sibling pages at different levels"),
+        }
+        self.set_prev(right_next, left);
+
+        if let Some((sep, right)) = self.split(left) {
+            let (keys, children) = self.internal_mut(parent);
+            keys.insert(i, sep);
+            children.insert(i + 1, right);
+        }
+    }
+}
+
+impl super::engine::Engine for BPTreeEngine {
+    type EngineIterator<'a> = BPTreeEngineIterator<'a>;
+
+    fn set(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
+        let root = self.root;
+        // 根节点分裂时树的高度加一
+        if let Some((sep, right)) = self.insert(root, key, value) {
+            self.root = self.alloc(Page::Internal {
+                keys: vec![sep],
+                children: vec![root, right],
+            });
+        }
+        Ok(())
+    }
+
+    fn get(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        let entries = self.pages[self.find_leaf(&key)].entries();
+        Ok(entries
+            .binary_search_by(|(k, _)| k.cmp(&key))
+            .ok()
+            .map(|i| entries[i].1.clone()))
+    }
+
+    fn delete(&mut self, key: Vec<u8>) -> Result<()> {
+        let root = self.root;
+        self.remove(root, &key);
+        // 根节点只剩一个子页时树的高度减一
+        if let Page::Internal { keys, children } = &self.pages[root] {
+            if keys.is_empty() {
+                self.root = children[0];
+                self.release(root);
+            }
+        }
+        Ok(())
+    }
+
+    fn scan(&self, range: impl RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_> {
+        let pages = &self.pages;
+        let front = match range.start_bound() {
+            Bound::Included(k) => {
+                let id = self.find_leaf(k);
+                first_from(
+                    pages,
+                    id,
+                    pages[id].entries().partition_point(|(e, _)| e < k),
+                )
+            }
+            Bound::Excluded(k) => {
+                let id = self.find_leaf(k);
+                first_from(
+                    pages,
+                    id,
+                    pages[id].entries().partition_point(|(e, _)| e <= k),
+                )
+            }
+            Bound::Unbounded => first_from(pages, self.edge_leaf(false), 0),
+        };
+        let back = match range.end_bound() {
+            Bound::Included(k) => {
+                let id = self.find_leaf(k);
+                last_before(
+                    pages,
+                    id,
+                    pages[id].entries().partition_point(|(e, _)| e <= k),
+                )
+            }
+            Bound::Excluded(k) => {
+                let id = self.find_leaf(k);
+                last_before(
+                    pages,
+                    id,
+                    pages[id].entries().partition_point(|(e, _)| e < k),
+                )
+            }
+            Bound::Unbounded => {
+                let id = self.edge_leaf(true);
+                last_before(pages, id, pages[id].entries().len())
+            }
+        };
+        let mut iter = BPTreeEngineIterator { pages, front, back };
+        // 范围内没有记录，或者起始位置在结束位置之后时范围为空
+        let empty = match (iter.front, iter.back) {
+            (Some(front), Some(back)) => iter.entry(front).0 > iter.entry(back).0,
+            _ => true,
+        };
+        if empty {
+            iter.front = None;
+            iter.back = None;
+        }
+        iter
+    }
+
+    fn status(&mut self) -> Result<Status> {
+        Ok(Status {
+            name: "bptree".into(),
+            keys: self.keys,
+            size: self.size,
+            disk_size: 0,
+            compactions: 0,
+        })
+    }
+}
+
+// 从 id 页的第 i 条记录开始，第一条记录的位置
+fn first_from(pages: &[Page], mut id: PageId, mut i: usize) -> Option<(PageId, usize)> {
+    loop {
+        let Page::Leaf { entries, next, .. } = &pages[id] else {
+            unreachable!("page is not a leaf")
+        };
+        if i < entries.len() {
+            return Some((id, i));
+        }
+        id = (*next)?;
+        i = 0;
+    }
+}
+
+// id 页的前 i 条记录中，最后一条记录的位置
+fn last_before(pages: &[Page], mut id: PageId, mut i: usize) -> Option<(PageId, usize)> {
+    loop {
+        if i > 0 {
+            return Some((id, i - 1));
+        }
+        let Page::Leaf { prev, .. } = &pages[id] else {
+            unreachable!("page is not a leaf")
+        };
+        id = (*prev)?;
+        i = pages[id].entries().len();
+    }
+}
+
+// B+ 树存储引擎迭代器，front 和 back 是正向和反向下一条要返回的记录，两者相遇之后结束
+pub struct BPTreeEngineIterator<'a> {
+    pages: &'a [Page],
+    front: Option<(PageId, usize)>,
+    back: Option<(PageId, usize)>,
+}
+
+impl<'a> BPTreeEngineIterator<'a> {
+    fn entry(&self, (id, i): (PageId, usize)) -> &'a (Vec<u8>, Vec<u8>) {
+        &self.pages[id].entries()[i]
+    }
+
+    // 返回 pos 处的记录，然后移动到下一个位置
+    fn advance(
+        &mut self,
+        pos: (PageId, usize),
+        step: Option<(PageId, usize)>,
+        forward: bool,
+    ) -> <Self as Iterator>::Item {
+        if self.front == self.back {
+            self.front = None;
+            self.back = None;
+        } else if forward {
+            self.front = step;
+        } else {
+            self.back = step;
+        }
+        let (k, v) = self.entry(pos);
+        Ok((k.clone(), v.clone()))
+    }
+}
+
+impl<'a> EngineIterator for BPTreeEngineIterator<'a> {}
+
+impl<'a> Iterator for BPTreeEngineIterator<'a> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (id, i) = self.front?;
+        let step = first_from(self.pages, id, i + 1);
+        Some(self.advance((id, i), step, true))
+    }
+}
+
+impl<'a> DoubleEndedIterator for BPTreeEngineIterator<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (id, i) = self.back?;
+        let step = last_before(self.pages, id, i);
+        Some(self.advance((id, i), step, false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, ops::Bound};
+
+    use super::{BPTreeEngine, Page, PageId};
+    use crate::{error::Result, storage::engine::Engine};
+
+    // 检查树的结构：所有的叶子页在同一层，key 有序并且在父节点的范围内，叶子页的链表和顺序一致
+    fn check(eng: &BPTreeEngine) {
+        let mut leaves = Vec::new();
+        let mut depths = Vec::new();
+        // 页号、深度以及页中 key 的范围
+        type Item = (PageId, usize, Option<Vec<u8>>, Option<Vec<u8>>);
+        let mut stack: Vec<Item> = vec![(eng.root, 1, None, None)];
+        while let Some((id, depth, low, high)) = stack.pop() {
+            let in_range = |k: &Vec<u8>| {
+                low.as_ref().is_none_or(|l| k >= l) && high.as_ref().is_none_or(|h| k < h)
+            };
+            match &eng.pages[id] {
+                Page::Leaf { entries, .. } => {
+                    assert!(entries.windows(2).all(|w| w[0].0 < w[1].0));
+                    assert!(entries.iter().all(|(k, _)| in_range(k)));
+                    leaves.push(id);
+                    depths.push(depth);
+                }
+                Page::Internal { keys, children } => {
+                    assert_eq!(keys.len() + 1, children.len());
+                    assert!(keys.windows(2).all(|w| w[0] < w[1]));
+                    assert!(keys.iter().all(in_range));
+                    for (i, child) in children.iter().enumerate().rev() {
+                        let l = if i == 0 {
+                            low.clone()
+                        } else {
+                            Some(keys[i - 1].clone())
+                        };
+                        let h = keys.get(i).cloned().or(high.clone());
+                        stack.push((*child, depth + 1, l, h));
+                    }
+                }
+            }
+        }
+        assert!(depths.iter().all(|d| *d == eng.depth()));
+        for (i, id) in leaves.iter().enumerate() {
+            let Page::Leaf { prev, next, .. } = &eng.pages[*id] else {
+                unreachable!()
+            };
+            assert_eq!(*prev, i.checked_sub(1).map(|i| leaves[i]));
+            assert_eq!(*next, leaves.get(i + 1).copied());
+        }
+        assert!(leaves.len() + eng.free.len() <= eng.pages.len());
+    }
+
+    #[test]
+    fn test_bptree_split_merge() -> Result<()> {
+        let mut eng = BPTreeEngine::with_page_size(128);
+        let mut expect = BTreeMap::new();
+        // 简单的线性同余随机数，保证每次运行的结果一致
+        let mut seed: u64 = 7;
+        let mut rand = |n: u64| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) % n
+        };
+
+        for _ in 0..3000 {
+            let key = format!("key{:04}", rand(1000)).into_bytes();
+            if rand(3) == 0 {
+                eng.delete(key.clone())?;
+                expect.remove(&key);
+            } else {
+                let value = vec![rand(256) as u8; rand(40) as usize];
+                eng.set(key.clone(), value.clone())?;
+                expect.insert(key, value);
+            }
+        }
+        check(&eng);
+        assert!(eng.depth() > 2);
+        let status = eng.status()?;
+        assert_eq!(status.keys, expect.len() as u64);
+        assert_eq!(
+            status.size,
+            expect
+                .iter()
+                .map(|(k, v)| (k.len() + v.len()) as u64)
+                .sum::<u64>()
+        );
+
+        // 点查以及各种边界的正向、反向范围扫描
+        for i in (0..1000).step_by(7) {
+            let key = format!("key{:04}", i).into_bytes();
+            assert_eq!(eng.get(key.clone())?, expect.get(&key).cloned());
+        }
+        let bounds = [
+            Bound::Unbounded,
+            Bound::Included(b"key0100".to_vec()),
+            Bound::Excluded(b"key0100".to_vec()),
+            Bound::Included(b"key05".to_vec()),
+            Bound::Excluded(b"key0999".to_vec()),
+        ];
+        for start in bounds.iter() {
+            for end in bounds.iter() {
+                let range = (start.clone(), end.clone());
+                // 起始位置在结束位置之后时为空，BTreeMap 在这种情况下会 panic
+                match (start, end) {
+                    (
+                        Bound::Included(s) | Bound::Excluded(s),
+                        Bound::Included(e) | Bound::Excluded(e),
+                    ) if s >= e => {
+                        if s > e {
+                            assert_eq!(eng.scan(range).count(), 0);
+                        }
+                        continue;
+                    }
+                    _ => {}
+                }
+                let expected = expect
+                    .range(range.clone())
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect::<Vec<_>>();
+                let forward = eng.scan(range.clone()).collect::<Result<Vec<_>>>()?;
+                assert_eq!(forward, expected);
+                let mut backward = eng.scan(range.clone()).rev().collect::<Result<Vec<_>>>()?;
+                backward.reverse();
+                assert_eq!(backward, expected);
+                // 两端交替读取，在中间相遇
+                let mut iter = eng.scan(range);
+                let mut count = 0;
+                while let Some(item) = if count % 2 == 0 {
+                    iter.next()
+                } else {
+                    iter.next_back()
+                } {
+                    item?;
+                    count += 1;
+                }
+                assert_eq!(count, expected.len());
+            }
+        }
+
+        // 删除所有的 key 之后页被合并，只剩下一个空的根节点
+        for key in expect.keys() {
+            eng.delete(key.clone())?;
+        }
+        check(&eng);
+        assert_eq!((eng.depth(), eng.page_count()), (1, 1));
+        assert_eq!(eng.scan(..).count(), 0);
+
+        // 超过页大小的 value 单独占用一个叶子页
+        eng.set(b"a".to_vec(), vec![1; 1000])?;
+        eng.set(b"b".to_vec(), vec![2; 1000])?;
+        eng.set(b"c".to_vec(), vec![3; 10])?;
+        check(&eng);
+        assert_eq!(eng.get(b"b".to_vec())?, Some(vec![2; 1000]));
+        eng.delete(b"b".to_vec())?;
+        check(&eng);
+        assert_eq!(eng.scan(..).rev().count(), 2);
+        Ok(())
+    }
+}
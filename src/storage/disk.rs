@@ -2,29 +2,195 @@ use std::{
     collections::{btree_map, BTreeMap},
     fs::{File, OpenOptions},
     io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    thread::JoinHandle,
+    time::{Duration, Instant},
     vec,
 };
 
 use fs4::FileExt;
 
-use crate::error::Result;
+use crate::error::{Error, Result};
+
+use super::engine::{prefix_range, Engine, Status, WriteBatch};
 
 pub type KeyDir = BTreeMap<Vec<u8>, (u64, u32)>;
-const LOG_HEADER_SIZE: u32 = 8;
+// 压缩的结果：重写之后的日志，以及每个 key 压缩开始时的位置和在新日志中的位置
+type CompactResult = (Log, Vec<(Vec<u8>, (u64, u32), (u64, u32))>);
+pub(super) const LOG_HEADER_SIZE: u32 = 13;
+
+// 日志文件头，打开时校验，不认识的文件直接报错，不会被当作损坏的日志截断
+// +----------+------------+
+// | magic(8) | version(4) |
+// +----------+------------+
+const LOG_MAGIC: [u8; 8] = *b"SQLDBLOG";
+// 版本 1 是没有文件头、记录没有校验和的旧格式，打开时转换成当前的格式
+const LOG_FORMAT_VERSION: u32 = 2;
+pub(super) const LOG_FILE_HEADER_SIZE: u64 = 12;
+
+// value 的压缩算法，每条记录的 flags 中保存了写入时使用的算法，读取时按照记录自己的 flags 解压
+// 修改配置之后旧的记录仍然可以读取，压缩之后没有变小的 value 按原样保存
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    #[cfg(feature = "lz4")]
+    Lz4,
+    #[cfg(feature = "snappy")]
+    Snappy,
+}
+
+const FLAG_NONE: u8 = 0;
+const FLAG_LZ4: u8 = 1;
+const FLAG_SNAPPY: u8 = 2;
+
+impl Compression {
+    fn flag(self) -> u8 {
+        match self {
+            Self::None => FLAG_NONE,
+            #[cfg(feature = "lz4")]
+            Self::Lz4 => FLAG_LZ4,
+            #[cfg(feature = "snappy")]
+            Self::Snappy => FLAG_SNAPPY,
+        }
+    }
+
+    // 编译时没有启用对应 feature 的算法写入的记录无法读取
+    fn from_flag(flag: u8) -> Result<Self> {
+        match flag {
+            FLAG_NONE => Ok(Self::None),
+            #[cfg(feature = "lz4")]
+            FLAG_LZ4 => Ok(Self::Lz4),
+            #[cfg(not(feature = "lz4"))]
+            FLAG_LZ4 => Err(Error::InvalidState(
+                "log entry is compressed with lz4, build with feature lz4 to read it".into(),
+            )),
+            #[cfg(feature = "snappy")]
+            FLAG_SNAPPY => Ok(Self::Snappy),
+            #[cfg(not(feature = "snappy"))]
+            FLAG_SNAPPY => Err(Error::InvalidState(
+                "log entry is compressed with snappy, build with feature snappy to read it".into(),
+            )),
+            flag => Err(Error::InvalidState(format!("unknown log entry flags {}", flag))),
+        }
+    }
+
+    // 返回实际写入的 flags 和数据
+    fn compress(self, value: &[u8]) -> Result<(u8, std::borrow::Cow<'_, [u8]>)> {
+        let compressed: Option<Vec<u8>> = match self {
+            Self::None => None,
+            #[cfg(feature = "lz4")]
+            Self::Lz4 => Some(lz4_flex::compress_prepend_size(value)),
+            #[cfg(feature = "snappy")]
+            Self::Snappy => Some(
+                snap::raw::Encoder::new()
+                    .compress_vec(value)
+                    .map_err(|e| Error::Internal(e.to_string()))?,
+            ),
+        };
+        match compressed {
+            Some(data) if data.len() < value.len() => Ok((self.flag(), data.into())),
+            _ => Ok((FLAG_NONE, value.into())),
+        }
+    }
+
+    fn decompress(self, data: Vec<u8>) -> Result<Vec<u8>> {
+        match self {
+            Self::None => Ok(data),
+            #[cfg(feature = "lz4")]
+            Self::Lz4 => lz4_flex::decompress_size_prepended(&data)
+                .map_err(|e| Error::Internal(e.to_string())),
+            #[cfg(feature = "snappy")]
+            Self::Snappy => snap::raw::Decoder::new()
+                .decompress_vec(&data)
+                .map_err(|e| Error::Internal(e.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DiskOptions {
+    // 无效数据占日志文件的比例超过这个值时自动压缩，0 表示不自动压缩
+    pub compact_ratio: f64,
+    // 无效数据不到这个大小时不自动压缩，避免小文件频繁压缩
+    pub compact_min_garbage: u64,
+    // 写入 value 时使用的压缩算法，适合包含大量长字符串的表
+    pub compression: Compression,
+    // 什么时候把写入的数据同步到磁盘
+    pub durability: Durability,
+}
+
+impl Default for DiskOptions {
+    fn default() -> Self {
+        Self {
+            compact_ratio: 0.5,
+            compact_min_garbage: 4 << 20,
+            compression: Compression::None,
+            durability: Durability::Commit,
+        }
+    }
+}
+
+// 数据同步到磁盘的时机，越晚同步写入越快，崩溃时可能丢失的数据也越多
+// 没有同步的数据只在操作系统崩溃、断电时丢失，进程崩溃不影响已经写入文件的数据
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Durability {
+    // 每次写入之后都同步
+    Always,
+    // 事务提交时同步，提交成功之后数据不会丢失
+    Commit,
+    // 距离上次同步超过这个间隔时，在下一次写入或者提交时同步
+    // 没有后台定时同步：之后一直没有写入时，最后提交的事务要到 flush、close 或者 drop 时才同步，
+    // 崩溃时可能丢失的不止这段时间内提交的事务，需要时由调用方定期调用 flush
+    Periodic(Duration),
+}
 
 // 磁盘存储引擎定义
 pub struct DiskEngine {
     keydir: KeyDir,
     log: Log,
+    options: DiskOptions,
+    // 日志中已经失效的字节数：被覆盖、删除的记录以及删除记录本身
+    garbage: u64,
+    // 启动以来执行过的压缩次数
+    compactions: u64,
+    // 正在后台执行的自动压缩
+    compaction: Option<JoinHandle<Result<CompactResult>>>,
+    // 打开时从 log 中恢复的结果
+    recovery: RecoveryReport,
+    // 上次同步到磁盘的时间，以及启动以来同步的次数
+    last_sync: Instant,
+    syncs: u64,
 }
 
 impl DiskEngine {
     pub fn new(file_path: PathBuf) -> Result<Self> {
+        Self::with_options(file_path, DiskOptions::default())
+    }
+
+    pub fn with_options(file_path: PathBuf, options: DiskOptions) -> Result<Self> {
+        upgrade_legacy_log(&file_path, options.compression)?;
         let mut log = Log::new(file_path)?;
-        // 从 log 中去恢复的 keydir
-        let keydir = log.build_keydir()?;
-        Ok(Self { keydir, log })
+        log.compression = options.compression;
+        let mut recovery = RecoveryReport::default();
+        // 上次正常关闭时写入了 hint 文件，直接加载 keydir，否则从 log 中恢复 keydir
+        let keydir = match log.load_hint()? {
+            Some(keydir) => keydir,
+            None => log.build_keydir(&mut recovery)?,
+        };
+        let live: u64 = keydir.iter().map(|(k, (_, val_size))| entry_size(k, *val_size)).sum();
+        let garbage = log.file.metadata()?.len().saturating_sub(live + LOG_FILE_HEADER_SIZE);
+        Ok(Self {
+            keydir,
+            log,
+            options,
+            garbage,
+            compactions: 0,
+            compaction: None,
+            recovery,
+            last_sync: Instant::now(),
+            syncs: 0,
+        })
     }
 
     pub fn new_compact(file_path: PathBuf) -> Result<Self> {
@@ -33,54 +199,233 @@ impl DiskEngine {
         Ok(eng)
     }
 
-    fn compact(&mut self) -> Result<()> {
-        // 新打开一个临时日志文件
-        let mut new_path = self.log.file_path.clone();
-        new_path.set_extension("compact");
+    // 日志中 offset 处写入了一条 size 字节的记录之后更新内存索引
+    // 100----------------|-----150
+    //                   130
+    // val size = 20，压缩时是压缩之后的大小
+    fn apply_set(&mut self, key: Vec<u8>, offset: u64, size: u32) {
+        let val_size = size - LOG_HEADER_SIZE - key.len() as u32;
+        if let Some((_, old_size)) = self.keydir.get(&key) {
+            self.garbage += entry_size(&key, *old_size);
+        }
+        self.keydir.insert(key, (offset + size as u64 - val_size as u64, val_size));
+    }
 
-        let mut new_log = Log::new(new_path)?;
-        let mut new_keydir = KeyDir::new();
+    // 内存中删除，删除记录本身和被删除的记录都是无效数据
+    fn apply_delete(&mut self, key: Vec<u8>, size: u32) {
+        self.garbage += size as u64;
+        if let Some((_, old_size)) = self.keydir.remove(&key) {
+            self.garbage += entry_size(&key, old_size);
+        }
+    }
 
-        // 重写数据到临时文件中;
-        // 全部最新数据都在内存中; 包括多个版本数据;
-        for (key, (offset, val_size)) in self.keydir.iter() {
-            // 读取 value
-            let value = self.log.read_value(*offset, *val_size)?;
-            let (new_offset, new_size) = new_log.write_entry(key, Some(&value))?;
+    // 写入之后按照 durability 配置同步，commit 表示是在事务提交时
+    fn sync_written(&mut self, commit: bool) -> Result<()> {
+        let sync = match self.options.durability {
+            Durability::Always => !commit,
+            Durability::Commit => commit,
+            Durability::Periodic(interval) => self.last_sync.elapsed() >= interval,
+        };
+        match sync {
+            true => self.flush(),
+            false => Ok(()),
+        }
+    }
 
-            new_keydir.insert(key.clone(), (new_offset + new_size as u64 - *val_size as u64, *val_size), );
+    pub fn recovery(&self) -> &RecoveryReport {
+        &self.recovery
+    }
+
+    fn compact_path(&self) -> PathBuf {
+        self.log.file_path.with_extension("compact")
+    }
+
+    // 在后台把有效数据重写到新文件中，日志只追加，已经写入的记录不会变化，压缩期间可以继续读写
+    fn start_compaction(&mut self) -> Result<()> {
+        let log = self.log.try_clone()?;
+        let path = self.compact_path();
+        let compression = self.options.compression;
+        let entries = self.keydir.iter().map(|(k, l)| (k.clone(), *l)).collect();
+        let handle = std::thread::spawn(move || rewrite_log(&log, path, compression, entries));
+        self.compaction = Some(handle);
+        Ok(())
+    }
+
+    // 后台压缩完成时使用压缩的结果，wait 为 true 时等待压缩完成，返回是否使用了压缩的结果
+    fn finish_compaction(&mut self, wait: bool) -> Result<bool> {
+        match &self.compaction {
+            Some(handle) if wait || handle.is_finished() => {}
+            _ => return Ok(false),
+        }
+        let result = self
+            .compaction
+            .take()
+            .unwrap()
+            .join()
+            .unwrap_or_else(|_| Err(Error::Internal("log compaction panicked".into())));
+        match result {
+            Ok(result) => self.install_compaction(result)?,
+            Err(err) => {
+                remove_file(&self.compact_path())?;
+                return Err(err);
+            }
         }
+        Ok(true)
+    }
+
+    // 压缩开始之后被覆盖、新写入的 key 从当前的日志复制到新日志中，被删除的 key 写入删除记录
+    // 新日志同步之后替换当前的日志，再同步目录，保证崩溃之后看到的是完整的新日志或者旧日志
+    fn install_compaction(&mut self, (mut new_log, result): CompactResult) -> Result<()> {
+        let mut keydir = KeyDir::new();
+        let mut ops = Vec::new();
+        for (key, old, new) in result {
+            match self.keydir.get(&key) {
+                Some(location) if *location == old => {
+                    keydir.insert(key, new);
+                }
+                Some(_) => {}
+                None => ops.push((key, None)),
+            }
+        }
+        for (key, (offset, val_size)) in &self.keydir {
+            if !keydir.contains_key(key) {
+                let value = self.log.read_value(key, *offset, *val_size)?;
+                ops.push((key.clone(), Some(value)));
+            }
+        }
+        let locations =
+            new_log.write_entries(ops.iter().map(|(k, v)| (k.as_slice(), v.as_deref())))?;
+        for ((key, value), (offset, size)) in ops.into_iter().zip(locations) {
+            if value.is_some() {
+                let val_size = size - LOG_HEADER_SIZE - key.len() as u32;
+                keydir.insert(key, (offset + size as u64 - val_size as u64, val_size));
+            }
+        }
+        new_log.file.sync_all()?;
 
         // 将临时文件更改为正式文件;
         std::fs::rename(&new_log.file_path, &self.log.file_path)?;
+        sync_dir(&self.log.file_path)?;
 
         new_log.file_path = self.log.file_path.clone();
-        self.keydir = new_keydir;
+        let live: u64 = keydir.iter().map(|(k, (_, val_size))| entry_size(k, *val_size)).sum();
+        self.garbage = new_log.file.metadata()?.len().saturating_sub(live + LOG_FILE_HEADER_SIZE);
+        self.keydir = keydir;
         self.log = new_log;
+        self.compactions += 1;
+        Ok(())
+    }
+}
+
+// 把 entries 中的数据按照 compression 重写到临时文件 path 中，写完之后同步到磁盘
+// 之前崩溃时可能遗留了临时文件，先删除，避免在旧的内容之后追加
+fn rewrite_log(
+    log: &Log,
+    path: PathBuf,
+    compression: Compression,
+    entries: Vec<(Vec<u8>, (u64, u32))>,
+) -> Result<CompactResult> {
+    remove_file(&path)?;
+    let mut new_log = Log::new(path)?;
+    new_log.compression = compression;
+    let mut result = Vec::with_capacity(entries.len());
+    for (key, (offset, val_size)) in entries {
+        let value = log.read_value(&key, offset, val_size)?;
+        let (new_offset, new_size) = new_log.write_entry(&key, Some(&value))?;
+
+        // 使用新的压缩配置重新写入，value 的大小可能变化
+        let new_val_size = new_size - LOG_HEADER_SIZE - key.len() as u32;
+        let location = (new_offset + new_size as u64 - new_val_size as u64, new_val_size);
+        result.push((key, (offset, val_size), location));
+    }
+    new_log.file.sync_all()?;
+    Ok((new_log, result))
+}
+
+// 文件不存在时忽略
+pub(super) fn remove_file(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+// 同步文件所在的目录，重命名之后目录项才会持久化
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
 
+// windows 上不能打开目录同步，重命名由文件系统保证持久化
+#[cfg(windows)]
+fn sync_dir(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+// 打开存储引擎时重放日志的结果，从 hint 文件加载时没有重放任何记录
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecoveryReport {
+    // 重放的有效记录数
+    pub entries: u64,
+    // 被截断的日志文件
+    pub truncated: Vec<Truncation>,
+}
+
+// 日志文件从 offset 开始的 discarded 字节被丢弃
+// corrupted 为 true 时是记录的校验和不一致，否则是文件末尾不完整的记录
+#[derive(Debug, Clone, PartialEq)]
+pub struct Truncation {
+    pub path: PathBuf,
+    pub offset: u64,
+    pub discarded: u64,
+    pub corrupted: bool,
+}
+
+impl std::fmt::Display for RecoveryReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "replayed {} log entries", self.entries)?;
+        for t in &self.truncated {
+            write!(
+                f,
+                "\ntruncated {} at offset {}, discarded {} bytes ({})",
+                t.path.display(),
+                t.offset,
+                t.discarded,
+                if t.corrupted { "checksum mismatch" } else { "incomplete entry" }
+            )?;
+        }
         Ok(())
     }
 }
 
-impl super::engine::Engine for DiskEngine {
+// 没有显式关闭时，在 drop 时关闭
+impl Drop for DiskEngine {
+    fn drop(&mut self) {
+        if let Err(err) = self.close() {
+            tracing::warn!(error = %err, "failed to close disk engine");
+        }
+    }
+}
+
+impl Engine for DiskEngine {
     type EngineIterator<'a> = DiskEngineIterator<'a>;
 
     fn set(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
         // 先写日志
         let (offset, size) = self.log.write_entry(&key, Some(&value))?;
-        // 更新内存索引
-        // 100----------------|-----150
-        //                   130
-        // val size = 20
-        let val_size = value.len() as u32;
-        self.keydir.insert(key, (offset + size as u64 - val_size as u64, val_size));
-        Ok(())
+        self.apply_set(key, offset, size);
+        self.sync_written(false)
     }
 
-    fn get(&mut self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
+    fn get(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
         match self.keydir.get(&key) {
             Some((offset, val_size)) => {
-                let val = self.log.read_value(*offset, *val_size)?;
+                let val = self.log.read_value(&key, *offset, *val_size)?;
                 Ok(Some(val))
             }
             None => Ok(None),
@@ -88,29 +433,135 @@ impl super::engine::Engine for DiskEngine {
     }
 
     fn delete(&mut self, key: Vec<u8>) -> Result<()> {
-        self.log.write_entry(&key, None)?; // 磁盘中记录 删除记录;
-        self.keydir.remove(&key); // 内存中删除;
-        Ok(())
+        let (_, size) = self.log.write_entry(&key, None)?; // 磁盘中记录 删除记录;
+        self.apply_delete(key, size);
+        self.sync_written(false)
     }
 
-    fn scan(&mut self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_> {
+    // 整组记录编码之后一次追加到日志文件中，再依次更新内存索引
+    fn write_batch(&mut self, batch: WriteBatch) -> Result<()> {
+        let ops = batch.into_iter().collect::<Vec<_>>();
+        let locations = self.log.write_entries(ops.iter().map(|(k, v)| (k.as_slice(), v.as_deref())))?;
+        for ((key, value), (offset, size)) in ops.into_iter().zip(locations) {
+            match value {
+                Some(_) => self.apply_set(key, offset, size),
+                None => self.apply_delete(key, size),
+            }
+        }
+        self.sync_written(false)
+    }
+
+    fn scan(&self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_> {
         DiskEngineIterator {
             inner: self.keydir.range(range),
-            log: &mut self.log,
+            log: &self.log,
         }
     }
+
+    // key 都在内存索引中，不需要读取日志文件
+    fn scan_keys(&self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Result<Vec<Vec<u8>>> {
+        Ok(self.keydir.range(range).map(|(key, _)| key.clone()).collect())
+    }
+
+    // 只需要从内存索引中找到 key，不需要读取 value
+    fn delete_prefix(&mut self, prefix: Vec<u8>) -> Result<usize> {
+        let keys = self
+            .keydir
+            .range(prefix_range(prefix))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        let count = keys.len();
+        let mut batch = WriteBatch::new();
+        for key in keys {
+            batch.delete(key);
+        }
+        self.write_batch(batch)?;
+        Ok(count)
+    }
+
+    // 先等待后台的压缩完成，再重写压缩期间写入的数据
+    fn compact(&mut self) -> Result<()> {
+        self.finish_compaction(true)?;
+        let entries = self.keydir.iter().map(|(k, l)| (k.clone(), *l)).collect();
+        // 全部最新数据都在内存中; 包括多个版本数据;
+        let result = rewrite_log(&self.log, self.compact_path(), self.options.compression, entries)?;
+        self.install_compaction(result)
+    }
+
+    // 达到阈值时在后台压缩，不阻塞事务提交；之后调用时使用已经完成的压缩结果
+    // 返回这次调用是否完成了一次压缩
+    fn auto_compact(&mut self) -> Result<bool> {
+        if self.compaction.is_some() {
+            return self.finish_compaction(false);
+        }
+        let ratio = self.options.compact_ratio;
+        if ratio <= 0.0 || self.garbage < self.options.compact_min_garbage {
+            return Ok(false);
+        }
+        let disk_size = self.log.file.metadata()?.len();
+        if (self.garbage as f64) < disk_size as f64 * ratio {
+            return Ok(false);
+        }
+        tracing::debug!(garbage = self.garbage, disk_size, "auto compacting disk log");
+        self.start_compaction()?;
+        Ok(false)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.log.check_open()?;
+        self.log.file.sync_all()?;
+        self.last_sync = Instant::now();
+        self.syncs += 1;
+        Ok(())
+    }
+
+    fn sync_commit(&mut self) -> Result<()> {
+        self.sync_written(true)
+    }
+
+    // 同步数据，写入 hint 文件，释放文件锁，下次打开时可以直接加载 keydir
+    fn close(&mut self) -> Result<()> {
+        if self.log.closed {
+            return Ok(());
+        }
+        // 关闭之前等待后台压缩完成，失败时保留当前的日志
+        if let Err(err) = self.finish_compaction(true) {
+            tracing::warn!(error = %err, "failed to compact disk log");
+        }
+        self.flush()?;
+        self.log.write_hint(&self.keydir)?;
+        self.log.file.unlock()?;
+        self.log.closed = true;
+        Ok(())
+    }
+
+    fn status(&mut self) -> Result<Status> {
+        Ok(Status {
+            name: "disk".into(),
+            keys: self.keydir.len() as u64,
+            // 文件头也算作有效数据，disk_size - size 就是无效数据的大小
+            size: LOG_FILE_HEADER_SIZE
+                + self
+                    .keydir
+                    .iter()
+                    .map(|(k, (_, val_size))| entry_size(k, *val_size))
+                    .sum::<u64>(),
+            disk_size: self.log.file.metadata()?.len(),
+            compactions: self.compactions,
+        })
+    }
 }
 
 pub struct DiskEngineIterator<'a> {
     inner: btree_map::Range<'a, Vec<u8>, (u64, u32)>,
-    log: &'a mut Log,
+    log: &'a Log,
 }
 
 impl<'a> DiskEngineIterator<'a> {
     // 重写map函数;
     fn map(&mut self, item: (&Vec<u8>, &(u64, u32))) -> <Self as Iterator>::Item {
         let (k, (offset, val_size)) = item;
-        let value = self.log.read_value(*offset, *val_size)?;
+        let value = self.log.read_value(k, *offset, *val_size)?;
         Ok((k.clone(), value))
     }
 }
@@ -131,23 +582,29 @@ impl<'a> DoubleEndedIterator for DiskEngineIterator<'a> {
     }
 }
 
-struct Log {
-    file_path: PathBuf,
-    file: std::fs::File,
+// 追加写的日志文件，分段存储引擎的每个段同样使用这个格式
+pub(super) struct Log {
+    pub(super) file_path: PathBuf,
+    pub(super) file: std::fs::File,
+    // 关闭之后不能再读写
+    pub(super) closed: bool,
+    // 写入 value 时使用的压缩算法，分段存储引擎不压缩
+    pub(super) compression: Compression,
 }
 
 impl Log {
-    fn new(file_path: PathBuf) -> Result<Self> {
+    pub(super) fn new(file_path: PathBuf) -> Result<Self> {
         // 如果目录不存在的话则创建
         if let Some(dir) = file_path.parent() {
             if !dir.exists() {
-                std::fs::create_dir_all(&dir)?;
+                std::fs::create_dir_all(dir)?;
             }
         }
 
         // 打开文件
         let file = OpenOptions::new()
             .create(true)
+            .truncate(false)
             .read(true)
             .write(true)
             .open(&file_path)?;
@@ -155,98 +612,421 @@ impl Log {
         // 加文件锁，保证同时只能有一个服务去使用这个文件
         file.try_lock_exclusive()?;
 
-        Ok(Self { file, file_path })
+        let mut log = Self {
+            file,
+            file_path,
+            closed: false,
+            compression: Compression::None,
+        };
+        log.check_file_header()?;
+        Ok(log)
+    }
+
+    // 新文件写入文件头，已有的文件校验文件头
+    // 创建文件时崩溃可能留下不完整的文件头，这样的文件中没有任何记录，重新写入文件头
+    fn check_file_header(&mut self) -> Result<()> {
+        let header = log_file_header();
+        let size = self.file.metadata()?.len();
+        let mut buf = vec![0; size.min(LOG_FILE_HEADER_SIZE) as usize];
+        read_at(&self.file, &mut buf, 0)?;
+        if size < LOG_FILE_HEADER_SIZE && header.starts_with(&buf) {
+            self.file.seek(SeekFrom::Start(0))?;
+            self.file.write_all(&header)?;
+            self.file.sync_all()?;
+            return Ok(());
+        }
+        if !buf.starts_with(&LOG_MAGIC) {
+            return Err(unrecognized_log(&self.file_path));
+        }
+        let version = u32::from_be_bytes(buf[LOG_MAGIC.len()..].try_into().unwrap());
+        if version != LOG_FORMAT_VERSION {
+            return Err(Error::InvalidState(format!(
+                "unsupported log format version {} in {}",
+                version,
+                self.file_path.display()
+            )));
+        }
+        Ok(())
+    }
+
+    // 共享同一个文件的副本，后台压缩时读取已经写入的记录
+    fn try_clone(&self) -> Result<Self> {
+        Ok(Self {
+            file: self.file.try_clone()?,
+            file_path: self.file_path.clone(),
+            closed: self.closed,
+            compression: self.compression,
+        })
+    }
+
+    pub(super) fn check_open(&self) -> Result<()> {
+        match self.closed {
+            true => Err(Error::InvalidState("disk engine is closed".into())),
+            false => Ok(()),
+        }
+    }
+
+    // hint 文件保存了关闭时的 keydir 以及 log 文件的大小
+    fn hint_path(&self) -> PathBuf {
+        let mut path = self.file_path.clone();
+        path.set_extension("hint");
+        path
+    }
+
+    fn write_hint(&self, keydir: &KeyDir) -> Result<()> {
+        let size = self.file.metadata()?.len();
+        // 先写入临时文件再重命名，避免留下不完整的 hint 文件
+        let mut tmp_path = self.hint_path();
+        tmp_path.set_extension("hint.tmp");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        bincode::serialize_into(&mut writer, &(size, keydir))?;
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        std::fs::rename(tmp_path, self.hint_path())?;
+        Ok(())
+    }
+
+    // 加载 hint 文件，之后的写入会让 hint 失效，所以加载之后删除
+    // log 文件的大小和 hint 中记录的不一致时，说明关闭之后 log 又被修改过，不能使用
+    fn load_hint(&mut self) -> Result<Option<KeyDir>> {
+        let path = self.hint_path();
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let hint: Option<(u64, KeyDir)> = bincode::deserialize_from(BufReader::new(file)).ok();
+        std::fs::remove_file(&path)?;
+        match hint {
+            Some((size, keydir)) if size == self.file.metadata()?.len() => Ok(Some(keydir)),
+            _ => Ok(None),
+        }
     }
 
     // 遍历数据文件，构建内存索引
-    fn build_keydir(&mut self) -> Result<KeyDir> {
+    fn build_keydir(&mut self, recovery: &mut RecoveryReport) -> Result<KeyDir> {
         let mut keydir = KeyDir::new();
+        self.replay(recovery, |key, location| match location {
+            Some(location) => {
+                keydir.insert(key, location);
+            }
+            None => {
+                keydir.remove(&key);
+            }
+        })?;
+        Ok(keydir)
+    }
+
+    // 按照写入顺序遍历每条记录，f 的参数是 key 以及 value 的位置和长度，删除记录的位置为空
+    // 写入过程中崩溃时，文件末尾可能留下不完整的记录；磁盘损坏时记录的校验和不一致
+    // 两种情况都截断到最后一条有效记录的结尾，之后的数据无法确认是否有效，全部丢弃，并记录在 recovery 中
+    pub(super) fn replay(
+        &mut self,
+        recovery: &mut RecoveryReport,
+        mut f: impl FnMut(Vec<u8>, Option<(u64, u32)>),
+    ) -> Result<()> {
         let file_size = self.file.metadata()?.len();
         let mut buf_reader = BufReader::new(&self.file);
 
-        let mut offset = 0;
-        loop {
-            if offset >= file_size {
-                break;
-            }
-
-            let (key, val_size) = Self::read_entry(&mut buf_reader, offset)?;
+        // 文件头在打开时已经校验过
+        let mut offset = LOG_FILE_HEADER_SIZE;
+        while offset < file_size {
+            let (key, val_size) = match Self::read_entry(&mut buf_reader, offset, file_size)? {
+                LogEntry::Valid(key, val_size) => (key, val_size),
+                entry => {
+                    let corrupted = entry == LogEntry::Corrupted;
+                    tracing::warn!(
+                        path = %self.file_path.display(),
+                        offset,
+                        file_size,
+                        corrupted,
+                        "truncating log at invalid entry"
+                    );
+                    drop(buf_reader);
+                    self.file.set_len(offset)?;
+                    self.file.sync_all()?;
+                    recovery.truncated.push(Truncation {
+                        path: self.file_path.clone(),
+                        offset,
+                        discarded: file_size - offset,
+                        corrupted,
+                    });
+                    break;
+                }
+            };
+            recovery.entries += 1;
             let key_size = key.len() as u32;
             if val_size == -1 {
-                keydir.remove(&key);
+                f(key, None);
                 offset += key_size as u64 + LOG_HEADER_SIZE as u64;
             } else {
-                keydir.insert(
+                f(
                     key,
-                    (
+                    Some((
                         offset + LOG_HEADER_SIZE as u64 + key_size as u64,
                         val_size as u32,
-                    ),
+                    )),
                 );
                 offset += key_size as u64 + val_size as u64 + LOG_HEADER_SIZE as u64;
             }
         }
 
-        Ok(keydir)
+        Ok(())
     }
 
-    // +-------------+-------------+----------------+----------------+
-    // | key len(4)    val len(4)     key(varint)       val(varint)  |
-    // +-------------+-------------+----------------+----------------+
-    fn write_entry(&mut self, key: &Vec<u8>, value: Option<&Vec<u8>>) -> Result<(u64, u32)> {
+    // +-----------+------------+------------+----------+---------+---------+
+    // | crc32(4)  | key len(4) | val len(4) | flags(1) |   key   |  value  |
+    // +-----------+------------+------------+----------+---------+---------+
+    // crc32 是 key len 之后所有字节的校验和，删除记录的 val len 为 -1，没有 value
+    // flags 是 value 的压缩算法，val len 是压缩之后的长度
+    pub(super) fn write_entry(&mut self, key: &[u8], value: Option<&[u8]>) -> Result<(u64, u32)> {
+        Ok(self.write_entries(std::iter::once((key, value)))?[0])
+    }
+
+    // 多条记录编码到同一个缓冲区中，只需要一次 seek 和一次 write，返回每条记录的位置和长度
+    pub(super) fn write_entries<'a>(
+        &mut self,
+        entries: impl IntoIterator<Item = (&'a [u8], Option<&'a [u8]>)>,
+    ) -> Result<Vec<(u64, u32)>> {
+        self.check_open()?;
         // 首先将文件偏移移动到文件末尾;
-        let offset = self.file.seek(SeekFrom::End(0))?;
+        let mut offset = self.file.seek(SeekFrom::End(0))?;
+        let mut buf = Vec::new();
+        let mut locations = Vec::new();
+        for (key, value) in entries {
+            let size = self.encode_entry(&mut buf, key, value)?;
+            locations.push((offset, size));
+            offset += size as u64;
+        }
+        self.file.write_all(&buf)?;
+        Ok(locations)
+    }
+
+    // 一条记录追加到 buf 的末尾，返回记录的长度
+    fn encode_entry(&self, buf: &mut Vec<u8>, key: &[u8], value: Option<&[u8]>) -> Result<u32> {
+        let (flags, value) = match value {
+            Some(v) => {
+                let (flags, v) = self.compression.compress(v)?;
+                (flags, Some(v))
+            }
+            None => (FLAG_NONE, None),
+        };
+        let value = value.as_deref();
         let key_size = key.len() as u32;
         let val_size = value.map_or(0, |v| v.len() as u32);
         let total_size = key_size + val_size + LOG_HEADER_SIZE;
 
-        // 写入 key size、value size、key、value
-        let mut writer = BufWriter::with_capacity(total_size as usize, &self.file);
-        writer.write_all(&key_size.to_be_bytes())?;
-        writer.write_all(&value.map_or(-1, |v| v.len() as i32).to_be_bytes())?;
-        writer.write_all(&key)?;
+        let key_len = key_size.to_be_bytes();
+        let val_len = value.map_or(-1, |v| v.len() as i32).to_be_bytes();
+        let crc = checksum(&key_len, &val_len, flags, key, value.unwrap_or_default());
+
+        // 写入 crc、key size、value size、flags、key、value
+        buf.reserve(total_size as usize);
+        buf.extend_from_slice(&crc.to_be_bytes());
+        buf.extend_from_slice(&key_len);
+        buf.extend_from_slice(&val_len);
+        buf.push(flags);
+        buf.extend_from_slice(key);
         if let Some(v) = value {
-            writer.write_all(v)?;
+            buf.extend_from_slice(v);
         }
-        writer.flush()?;
-
-        Ok((offset, total_size))
+        Ok(total_size)
     }
 
-    fn read_value(&mut self, offset: u64, val_size: u32) -> Result<Vec<u8>> {
-        self.file.seek(SeekFrom::Start(offset))?;
-        let mut buf = vec![0; val_size as usize];
-        self.file.read_exact(&mut buf)?;
-        Ok(buf)
+    // offset 和 val_size 是 value 在文件中的位置，flags 在 key 之前，和 value 一起读取
+    pub(super) fn read_value(&self, key: &[u8], offset: u64, val_size: u32) -> Result<Vec<u8>> {
+        self.check_open()?;
+        let start = offset - key.len() as u64 - 1;
+        let mut buf = vec![0; 1 + key.len() + val_size as usize];
+        read_at(&self.file, &mut buf, start)?;
+        let compression = Compression::from_flag(buf[0])?;
+        compression.decompress(buf.split_off(1 + key.len()))
     }
 
-    fn read_entry(buf_reader: &mut BufReader<&File>, offset: u64) -> Result<(Vec<u8>, i32)> {
+    // 读取一条记录的 key 和 value 长度，并校验整条记录
+    fn read_entry(
+        buf_reader: &mut BufReader<&File>,
+        offset: u64,
+        file_size: u64,
+    ) -> Result<LogEntry> {
+        if offset + LOG_HEADER_SIZE as u64 > file_size {
+            return Ok(LogEntry::Incomplete);
+        }
         buf_reader.seek(SeekFrom::Start(offset))?;
-        let mut len_buf = [0; 4];
-
-        // 读取 key size
-        buf_reader.read_exact(&mut len_buf)?;
-        let key_size = u32::from_be_bytes(len_buf);
+        let mut crc = [0; 4];
+        let mut key_len = [0; 4];
+        let mut val_len = [0; 4];
+        let mut flags = [0; 1];
+        buf_reader.read_exact(&mut crc)?;
+        buf_reader.read_exact(&mut key_len)?;
+        buf_reader.read_exact(&mut val_len)?;
+        buf_reader.read_exact(&mut flags)?;
+        let key_size = u32::from_be_bytes(key_len);
+        let val_size = i32::from_be_bytes(val_len);
 
-        // 读取 value size
-        buf_reader.read_exact(&mut len_buf)?;
-        let val_size = i32::from_be_bytes(len_buf);
+        if val_size < -1 {
+            return Ok(LogEntry::Corrupted);
+        }
+        let end = offset + LOG_HEADER_SIZE as u64 + key_size as u64 + val_size.max(0) as u64;
+        if end > file_size {
+            return Ok(LogEntry::Incomplete);
+        }
 
-        // 读取 key
+        // 读取 key 和 value，计算校验和
         let mut key = vec![0; key_size as usize];
         buf_reader.read_exact(&mut key)?;
+        let mut value = vec![0; val_size.max(0) as usize];
+        buf_reader.read_exact(&mut value)?;
+        if checksum(&key_len, &val_len, flags[0], &key, &value) != u32::from_be_bytes(crc) {
+            return Ok(LogEntry::Corrupted);
+        }
+        // 校验和一致时记录本身是完整的，不认识的压缩算法不能当作损坏截断
+        Compression::from_flag(flags[0])?;
+
+        Ok(LogEntry::Valid(key, val_size))
+    }
+}
+
+// 按照位置读取，不移动文件的读写位置，多个线程可以同时读取同一个文件
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> Result<()> {
+    use std::os::unix::fs::FileExt;
+    file.read_exact_at(buf, offset)?;
+    Ok(())
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset)? {
+            0 => return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()),
+            n => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn log_file_header() -> Vec<u8> {
+    let mut header = LOG_MAGIC.to_vec();
+    header.extend(LOG_FORMAT_VERSION.to_be_bytes());
+    header
+}
+
+fn unrecognized_log(path: &Path) -> Error {
+    Error::InvalidState(format!("unrecognized log file format {}", path.display()))
+}
+
+// 第一个版本的日志没有文件头，记录中也没有校验和以及 flags
+// +------------+------------+---------+---------+
+// | key len(4) | val len(4) |   key   |  value  |
+// +------------+------------+---------+---------+
+// 打开时把这种日志重写成当前的格式。旧格式没有校验和，记录必须正好占满整个文件，
+// 否则不能确认是旧格式的日志，直接报错，不修改文件
+fn upgrade_legacy_log(file_path: &Path, compression: Compression) -> Result<()> {
+    let file = match File::open(file_path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    let size = file.metadata()?.len();
+    let mut buf = vec![0; size.min(LOG_FILE_HEADER_SIZE) as usize];
+    read_at(&file, &mut buf, 0)?;
+    // 当前格式的日志，或者是创建时只写入了一部分文件头
+    if log_file_header().starts_with(&buf) || buf.starts_with(&LOG_MAGIC) {
+        return Ok(());
+    }
+    file.try_lock_exclusive()?;
+
+    let mut keydir = KeyDir::new();
+    let mut reader = BufReader::new(&file);
+    let mut offset = 0;
+    while offset < size {
+        if offset + 8 > size {
+            return Err(unrecognized_log(file_path));
+        }
+        let mut lens = [0; 8];
+        reader.read_exact(&mut lens)?;
+        let key_size = u32::from_be_bytes(lens[..4].try_into().unwrap()) as u64;
+        let val_size = i32::from_be_bytes(lens[4..].try_into().unwrap());
+        let end = offset + 8 + key_size + val_size.max(0) as u64;
+        if val_size < -1 || end > size {
+            return Err(unrecognized_log(file_path));
+        }
+        let mut key = vec![0; key_size as usize];
+        reader.read_exact(&mut key)?;
+        match val_size {
+            -1 => keydir.remove(&key),
+            n => {
+                reader.seek_relative(n as i64)?;
+                keydir.insert(key, (offset + 8 + key_size, n as u32))
+            }
+        };
+        offset = end;
+    }
 
-        Ok((key, val_size))
+    // 先写入临时文件，完成之后替换旧的日志
+    let mut tmp_path = file_path.to_path_buf();
+    tmp_path.set_extension("upgrade");
+    if tmp_path.exists() {
+        std::fs::remove_file(&tmp_path)?;
     }
+    let mut log = Log::new(tmp_path)?;
+    log.compression = compression;
+    for (key, (offset, val_size)) in keydir.iter() {
+        let mut value = vec![0; *val_size as usize];
+        read_at(&file, &mut value, *offset)?;
+        log.write_entry(key, Some(&value))?;
+    }
+    log.file.sync_all()?;
+    drop(file);
+    std::fs::rename(&log.file_path, file_path)?;
+    tracing::info!(path = %file_path.display(), keys = keydir.len(), "upgraded legacy log");
+    Ok(())
+}
+
+// 一条写入记录在日志中占用的字节数
+fn entry_size(key: &[u8], val_size: u32) -> u64 {
+    LOG_HEADER_SIZE as u64 + key.len() as u64 + val_size as u64
+}
+
+// 重放时读取一条记录的结果
+#[derive(Debug, PartialEq)]
+enum LogEntry {
+    // key 以及 value 的长度，删除记录的长度为 -1
+    Valid(Vec<u8>, i32),
+    // 记录超出了文件末尾
+    Incomplete,
+    // 记录的校验和不一致
+    Corrupted,
+}
+
+fn checksum(key_len: &[u8], val_len: &[u8], flags: u8, key: &[u8], value: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(key_len);
+    hasher.update(val_len);
+    hasher.update(&[flags]);
+    hasher.update(key);
+    hasher.update(value);
+    hasher.finalize()
 }
 
 #[cfg(test)]
 mod test {
     use crate::{
         error::Result,
-        storage::{disk::DiskEngine, engine::Engine},
+        storage::{
+            disk::{
+                Compression, DiskEngine, DiskOptions, Durability, Truncation,
+                LOG_FILE_HEADER_SIZE, LOG_MAGIC,
+            },
+            engine::{Engine, WriteBatch},
+            mvcc::Mvcc,
+        },
     };
-    use std::path::PathBuf;
+    use std::{fs::OpenOptions, io::Write, path::PathBuf};
 
     #[test]
     fn test_disk_engine_compact() -> Result<()> {
@@ -277,7 +1057,7 @@ mod test {
         );
         drop(eng);
 
-        let mut eng2 = DiskEngine::new_compact(PathBuf::from("/tmp/sqldb/sqldb-log"))?;
+        let eng2 = DiskEngine::new_compact(PathBuf::from("/tmp/sqldb/sqldb-log"))?;
         let iter2 = eng2.scan(..);
         let v2 = iter2.collect::<Result<Vec<_>>>()?;
         assert_eq!(
@@ -294,4 +1074,452 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_disk_engine_torn_tail() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let mut eng = DiskEngine::new(p.clone())?;
+        eng.set(b"key1".to_vec(), b"value1".to_vec())?;
+        eng.set(b"key2".to_vec(), b"value2".to_vec())?;
+        eng.delete(b"key1".to_vec())?;
+        drop(eng);
+        let size = std::fs::metadata(&p)?.len();
+
+        // 模拟写入过程中崩溃：不完整的记录头、完整的记录头但数据不完整
+        let mut entry = Vec::new();
+        entry.extend(0u32.to_be_bytes());
+        entry.extend(4u32.to_be_bytes());
+        entry.extend(6i32.to_be_bytes());
+        entry.extend(b"key3valu");
+        for len in [3, entry.len()] {
+            let mut file = OpenOptions::new().append(true).open(&p)?;
+            file.write_all(&entry[..len])?;
+            drop(file);
+
+            let eng = DiskEngine::new(p.clone())?;
+            assert_eq!(std::fs::metadata(&p)?.len(), size);
+            let truncated = &eng.recovery().truncated;
+            assert_eq!((truncated[0].offset, truncated[0].discarded), (size, len as u64));
+            assert!(!truncated[0].corrupted);
+            assert_eq!(
+                eng.scan(..).collect::<Result<Vec<_>>>()?,
+                vec![(b"key2".to_vec(), b"value2".to_vec())]
+            );
+        }
+
+        // 截断之后可以继续写入
+        let mut eng = DiskEngine::new(p.clone())?;
+        eng.set(b"key3".to_vec(), b"value3".to_vec())?;
+        drop(eng);
+        let eng = DiskEngine::new(p.clone())?;
+        assert_eq!(eng.get(b"key3".to_vec())?, Some(b"value3".to_vec()));
+        drop(eng);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_close() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let hint = p.with_extension("hint");
+        let mut eng = DiskEngine::new(p.clone())?;
+        eng.set(b"key1".to_vec(), b"value1".to_vec())?;
+        eng.set(b"key2".to_vec(), b"value2".to_vec())?;
+        eng.delete(b"key1".to_vec())?;
+        eng.flush()?;
+        eng.close()?;
+        assert!(hint.exists());
+        // 关闭之后不能再读写，再次关闭没有影响
+        assert!(eng.get(b"key2".to_vec()).is_err());
+        assert!(eng.set(b"key3".to_vec(), vec![]).is_err());
+        eng.close()?;
+
+        // 关闭时释放了文件锁，可以再次打开，并且从 hint 文件中加载 keydir
+        let mut eng2 = DiskEngine::new(p.clone())?;
+        assert!(!hint.exists());
+        assert_eq!(
+            eng2.scan(..).collect::<Result<Vec<_>>>()?,
+            vec![(b"key2".to_vec(), b"value2".to_vec())]
+        );
+        drop(eng);
+
+        // drop 时同样会关闭并写入 hint 文件
+        eng2.set(b"key3".to_vec(), b"value3".to_vec())?;
+        drop(eng2);
+        assert!(hint.exists());
+        let stale = std::fs::read(&hint)?;
+
+        // log 在 hint 写入之后又被修改过时，忽略 hint 重新构建 keydir
+        let mut eng3 = DiskEngine::new(p.clone())?;
+        eng3.delete(b"key2".to_vec())?;
+        eng3.close()?;
+        std::fs::write(&hint, stale)?;
+        let eng4 = DiskEngine::new(p.clone())?;
+        assert_eq!(
+            eng4.scan(..).collect::<Result<Vec<_>>>()?,
+            vec![(b"key3".to_vec(), b"value3".to_vec())]
+        );
+        drop((eng3, eng4));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_legacy_log() -> Result<()> {
+        // 没有文件头和校验和的旧格式日志
+        let legacy = |entries: &[(&[u8], Option<&[u8]>)]| {
+            let mut data = Vec::new();
+            for (key, value) in entries {
+                data.extend((key.len() as u32).to_be_bytes());
+                data.extend(value.map_or(-1, |v| v.len() as i32).to_be_bytes());
+                data.extend(*key);
+                data.extend(value.unwrap_or_default());
+            }
+            data
+        };
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        std::fs::write(
+            &p,
+            legacy(&[
+                (b"key1", Some(b"value1")),
+                (b"key2", Some(b"value2")),
+                (b"key1", None),
+                (b"key3", Some(b"")),
+                (b"key2", Some(b"value4")),
+            ]),
+        )?;
+
+        // 打开时转换成当前的格式
+        let eng = DiskEngine::new(p.clone())?;
+        let expect = vec![
+            (b"key2".to_vec(), b"value4".to_vec()),
+            (b"key3".to_vec(), b"".to_vec()),
+        ];
+        assert_eq!(eng.scan(..).collect::<Result<Vec<_>>>()?, expect);
+        drop(eng);
+        assert!(std::fs::read(&p)?.starts_with(&LOG_MAGIC));
+        std::fs::remove_file(p.with_extension("hint"))?;
+        let eng = DiskEngine::new(p.clone())?;
+        assert_eq!(eng.scan(..).collect::<Result<Vec<_>>>()?, expect);
+        assert!(eng.recovery().truncated.is_empty());
+        drop(eng);
+
+        // 不完整的旧格式日志以及其他不认识的文件直接报错，不修改文件
+        let mut torn = legacy(&[(b"key1", Some(b"value1"))]);
+        torn.truncate(torn.len() - 1);
+        let mut unsupported = std::fs::read(&p)?;
+        unsupported[LOG_MAGIC.len() + 3] = 99;
+        for data in [torn, b"not a log file".to_vec(), unsupported] {
+            std::fs::write(&p, &data)?;
+            assert!(DiskEngine::new(p.clone()).is_err());
+            assert_eq!(std::fs::read(&p)?, data);
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_checksum() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let mut eng = DiskEngine::new(p.clone())?;
+        eng.set(b"key1".to_vec(), b"value1".to_vec())?;
+        eng.set(b"key2".to_vec(), b"value2".to_vec())?;
+        eng.set(b"key3".to_vec(), b"value3".to_vec())?;
+        drop(eng);
+        std::fs::remove_file(p.with_extension("hint"))?;
+
+        // 第二条记录的 value 损坏，从这条记录开始截断，之后完整的记录也被丢弃
+        let mut data = std::fs::read(&p)?;
+        let size = (data.len() as u64 - LOG_FILE_HEADER_SIZE) / 3;
+        data[(LOG_FILE_HEADER_SIZE + size * 2) as usize - 1] ^= 0xff;
+        std::fs::write(&p, data)?;
+        let mut eng = DiskEngine::new(p.clone())?;
+        assert_eq!(eng.recovery().entries, 1);
+        assert_eq!(
+            eng.recovery().truncated,
+            vec![Truncation {
+                path: p.clone(),
+                offset: LOG_FILE_HEADER_SIZE + size,
+                discarded: size * 2,
+                corrupted: true,
+            }]
+        );
+        assert!(eng.recovery().to_string().contains("checksum mismatch"));
+        assert_eq!(std::fs::metadata(&p)?.len(), LOG_FILE_HEADER_SIZE + size);
+        assert_eq!(
+            eng.scan(..).collect::<Result<Vec<_>>>()?,
+            vec![(b"key1".to_vec(), b"value1".to_vec())]
+        );
+
+        // 截断之后可以继续写入，再次打开时没有需要截断的数据
+        eng.set(b"key2".to_vec(), b"value4".to_vec())?;
+        drop(eng);
+        std::fs::remove_file(p.with_extension("hint"))?;
+        let eng = DiskEngine::new(p.clone())?;
+        assert_eq!(eng.recovery().entries, 2);
+        assert!(eng.recovery().truncated.is_empty());
+        assert_eq!(eng.get(b"key2".to_vec())?, Some(b"value4".to_vec()));
+        drop(eng);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_auto_compact() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let options = DiskOptions {
+            compact_ratio: 0.5,
+            compact_min_garbage: 1024,
+            ..Default::default()
+        };
+        let mut eng = DiskEngine::with_options(p.clone(), options.clone())?;
+        // 无效数据不到 compact_min_garbage 时不压缩
+        for i in 0..10 {
+            eng.set(b"key".to_vec(), format!("value{}", i).into_bytes())?;
+        }
+        assert!(!eng.auto_compact()?);
+        // 覆盖写入超过一半的数据之后压缩，只保留最新的数据
+        for i in 0..100 {
+            eng.set(format!("key{}", i % 10).into_bytes(), vec![i as u8; 20])?;
+        }
+        eng.delete(b"key".to_vec())?;
+        let status = eng.status()?;
+        assert_eq!(eng.garbage, status.disk_size - status.size);
+        // 压缩在后台进行，完成之后才使用压缩的结果
+        assert!(!eng.auto_compact()?);
+        assert!(eng.compaction.is_some());
+        assert!(eng.finish_compaction(true)?);
+        let status = eng.status()?;
+        assert_eq!((status.compactions, status.size), (1, status.disk_size));
+        assert!(!eng.auto_compact()?);
+        assert!(eng.compaction.is_none());
+        assert_eq!(eng.get(b"key9".to_vec())?, Some(vec![99; 20]));
+        drop(eng);
+
+        // 重新打开时根据日志计算无效数据的大小，事务提交之后自动压缩
+        let eng = DiskEngine::with_options(p.clone(), options.clone())?;
+        assert_eq!(eng.garbage, 0);
+        let mvcc = Mvcc::new(eng);
+        for i in 0..100 {
+            let txn = mvcc.begin()?;
+            txn.set(b"key".to_vec(), vec![i as u8; 20])?;
+            txn.commit()?;
+        }
+        let txn = mvcc.begin()?;
+        assert_eq!(txn.get(b"key".to_vec())?, Some(vec![99; 20]));
+        txn.commit()?;
+        // 关闭时等待后台的压缩完成，旧版本的数据依然有效，压缩之后无效数据不会超过阈值太多
+        drop((txn, mvcc));
+        let mut eng = DiskEngine::with_options(p.clone(), options)?;
+        let status = eng.status()?;
+        assert!(status.disk_size - status.size < status.disk_size / 2 + 1024);
+        drop(eng);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_compact_concurrent_writes() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let mut eng = DiskEngine::new(p.clone())?;
+        for i in 0..100 {
+            eng.set(format!("key{:02}", i).into_bytes(), vec![i as u8; 20])?;
+        }
+        // 后台压缩期间覆盖、删除、新写入的 key 在使用压缩结果时保留
+        eng.start_compaction()?;
+        eng.set(b"key00".to_vec(), b"new".to_vec())?;
+        eng.delete(b"key01".to_vec())?;
+        eng.delete(b"key02".to_vec())?;
+        eng.set(b"key02".to_vec(), b"again".to_vec())?;
+        eng.set(b"key100".to_vec(), b"added".to_vec())?;
+        assert!(eng.finish_compaction(true)?);
+
+        let check = |eng: &mut DiskEngine| -> Result<()> {
+            assert_eq!(eng.get(b"key00".to_vec())?, Some(b"new".to_vec()));
+            assert_eq!(eng.get(b"key01".to_vec())?, None);
+            assert_eq!(eng.get(b"key02".to_vec())?, Some(b"again".to_vec()));
+            assert_eq!(eng.get(b"key03".to_vec())?, Some(vec![3; 20]));
+            assert_eq!(eng.get(b"key100".to_vec())?, Some(b"added".to_vec()));
+            assert_eq!(eng.scan(..).count(), 100);
+            let status = eng.status()?;
+            assert_eq!(eng.garbage, status.disk_size - status.size);
+            Ok(())
+        };
+        check(&mut eng)?;
+        assert_eq!(eng.status()?.compactions, 1);
+        drop(eng);
+
+        // 不使用 hint 文件，重放压缩之后的日志
+        std::fs::remove_file(p.with_extension("hint"))?;
+        let mut eng = DiskEngine::new(p.clone())?;
+        check(&mut eng)?;
+        drop(eng);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_compact_stale_file() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        // 上次压缩时崩溃遗留的临时文件
+        let mut stale = DiskEngine::new(p.with_extension("compact"))?;
+        stale.set(b"stale".to_vec(), b"value".to_vec())?;
+        drop(stale);
+        std::fs::remove_file(p.with_extension("hint"))?;
+
+        let mut eng = DiskEngine::new(p.clone())?;
+        eng.set(b"key".to_vec(), b"value".to_vec())?;
+        eng.compact()?;
+        assert_eq!(eng.get(b"stale".to_vec())?, None);
+        let status = eng.status()?;
+        assert_eq!(status.size, status.disk_size);
+        drop(eng);
+
+        std::fs::remove_file(p.with_extension("hint"))?;
+        let eng = DiskEngine::new(p.clone())?;
+        assert_eq!(eng.get(b"stale".to_vec())?, None);
+        assert_eq!(eng.scan(..).count(), 1);
+        drop(eng);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_compression() -> Result<()> {
+        #[allow(unused_mut)]
+        let mut codecs = vec![Compression::None];
+        #[cfg(feature = "lz4")]
+        codecs.push(Compression::Lz4);
+        #[cfg(feature = "snappy")]
+        codecs.push(Compression::Snappy);
+
+        let value = "abcdefgh".repeat(512).into_bytes();
+        for compression in codecs {
+            let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+            let options = DiskOptions {
+                compression,
+                ..Default::default()
+            };
+            let mut eng = DiskEngine::with_options(p.clone(), options.clone())?;
+            for i in 0..10 {
+                eng.set(format!("key{}", i).into_bytes(), value.clone())?;
+            }
+            // 压缩不了的短 value 按原样保存
+            eng.set(b"short".to_vec(), b"x".to_vec())?;
+            eng.delete(b"key0".to_vec())?;
+            let size = eng.status()?.size;
+            match compression {
+                Compression::None => assert!(size > 9 * value.len() as u64),
+                #[allow(unreachable_patterns)]
+                _ => assert!(size < value.len() as u64),
+            }
+            assert_eq!(eng.get(b"key1".to_vec())?, Some(value.clone()));
+            assert_eq!(eng.get(b"short".to_vec())?, Some(b"x".to_vec()));
+            assert_eq!(eng.scan(..).count(), 10);
+
+            // 没有 hint 文件时重放日志，关闭压缩之后旧的记录仍然可以读取，压缩时按照新的配置重写
+            drop(eng);
+            std::fs::remove_file(p.with_extension("hint"))?;
+            let mut eng = DiskEngine::with_options(p.clone(), DiskOptions::default())?;
+            assert_eq!(eng.recovery().entries, 12);
+            assert_eq!(eng.status()?.size, size);
+            assert_eq!(eng.get(b"key9".to_vec())?, Some(value.clone()));
+            eng.compact()?;
+            assert!(eng.status()?.size > 9 * value.len() as u64);
+            assert_eq!(eng.get(b"key9".to_vec())?, Some(value.clone()));
+            assert_eq!(eng.get(b"key0".to_vec())?, None);
+            drop(eng);
+
+            std::fs::remove_dir_all(p.parent().unwrap())?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_durability() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let open = |durability| {
+            let options = DiskOptions {
+                durability,
+                ..Default::default()
+            };
+            DiskEngine::with_options(p.clone(), options)
+        };
+
+        // 每次写入之后都同步，批量写入只同步一次，提交时不需要再同步
+        let mut eng = open(Durability::Always)?;
+        eng.set(b"a".to_vec(), b"1".to_vec())?;
+        eng.delete(b"b".to_vec())?;
+        let mut batch = WriteBatch::new();
+        batch.set(b"c".to_vec(), b"3".to_vec());
+        batch.set(b"d".to_vec(), b"4".to_vec());
+        eng.write_batch(batch)?;
+        eng.sync_commit()?;
+        assert_eq!(eng.syncs, 3);
+        drop(eng);
+
+        // 只在提交时同步
+        let mut eng = open(Durability::Commit)?;
+        eng.set(b"e".to_vec(), b"5".to_vec())?;
+        eng.set(b"f".to_vec(), b"6".to_vec())?;
+        assert_eq!(eng.syncs, 0);
+        eng.sync_commit()?;
+        assert_eq!(eng.syncs, 1);
+        drop(eng);
+
+        // 超过间隔之后，下一次写入或者提交时同步
+        let mut eng = open(Durability::Periodic(std::time::Duration::from_millis(200)))?;
+        eng.set(b"g".to_vec(), b"7".to_vec())?;
+        eng.sync_commit()?;
+        assert_eq!(eng.syncs, 0);
+        std::thread::sleep(std::time::Duration::from_millis(250));
+        eng.set(b"h".to_vec(), b"8".to_vec())?;
+        assert_eq!(eng.syncs, 1);
+        eng.sync_commit()?;
+        assert_eq!(eng.syncs, 1);
+        drop(eng);
+
+        let eng = DiskEngine::new(p.clone())?;
+        assert_eq!(eng.scan(..).count(), 7);
+        drop(eng);
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_concurrent_reads() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let mut eng = DiskEngine::new(p.clone())?;
+        for i in 0..100u32 {
+            eng.set(i.to_be_bytes().to_vec(), format!("value{}", i).into_bytes())?;
+        }
+
+        // 读取不需要可变引用，多个线程可以同时读取
+        let eng = &eng;
+        std::thread::scope(|s| {
+            let handles = (0..4)
+                .map(|_| {
+                    s.spawn(move || -> Result<()> {
+                        for i in 0..100u32 {
+                            let value = eng.get(i.to_be_bytes().to_vec())?;
+                            assert_eq!(value, Some(format!("value{}", i).into_bytes()));
+                        }
+                        assert_eq!(eng.scan(..).count(), 100);
+                        Ok(())
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles.into_iter().try_for_each(|h| h.join().unwrap())
+        })?;
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
 }
@@ -3,7 +3,9 @@ use std::ops::{Bound, RangeBounds};
 use crate::error::Result;
 
 // 抽象存储引擎接口定义，接入不同的存储引擎，目前支持内存和简单的磁盘 KV 存储
-pub trait Engine {
+// 读取只需要 &self，上层用读写锁共享存储引擎时多个读取可以同时进行，写入互斥
+// 扫描结果的迭代器持有存储引擎的共享引用，可以在线程之间传递
+pub trait Engine: Send + Sync + 'static {
     type EngineIterator<'a>: EngineIterator
     where
         Self: 'a;
@@ -12,45 +14,158 @@ pub trait Engine {
     fn set(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<()>;
 
     // 获取 key 对应的数据
-    fn get(&mut self, key: Vec<u8>) -> Result<Option<Vec<u8>>>;
+    fn get(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>>;
 
     // 删除 key 对应的数据，如果 key 不存在的话则忽略
     fn delete(&mut self, key: Vec<u8>) -> Result<()>;
 
+    // 按顺序执行一组写入，默认逐个写入，存储引擎可以把整组写入合并成一次 I/O
+    fn write_batch(&mut self, batch: WriteBatch) -> Result<()> {
+        for (key, value) in batch {
+            match value {
+                Some(value) => self.set(key, value)?,
+                None => self.delete(key)?,
+            }
+        }
+        Ok(())
+    }
+
     // 扫描
-    fn scan(&mut self, range: impl RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_>;
+    fn scan(&self, range: impl RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_>;
 
     // 前缀扫描
-    fn scan_prefix(&mut self, prefix: Vec<u8>) -> Self::EngineIterator<'_> {
-        // start: aaaa
-        // end:   aaab
-        let start = Bound::Included(prefix.clone());
-        let mut bound_prefix = prefix.clone();
-        // 前缀包含了 255，例如 10,2,3,255
-        // 右边界就是 10,2,4
-        // 如果全都是 255
-        // 右边界 Unbounded
-        let end = match bound_prefix.iter().rposition(|b| *b != 255) {
-            Some(pos) => {
-                bound_prefix[pos] += 1;
-                bound_prefix.truncate(pos + 1);
-                Bound::Excluded(bound_prefix)
-            }
-            None => Bound::Unbounded,
-        };
+    fn scan_prefix(&self, prefix: Vec<u8>) -> Self::EngineIterator<'_> {
+        self.scan(prefix_range(prefix))
+    }
+
+    // 只扫描范围内的 key，默认通过 scan 实现，key 和 value 分开保存的存储引擎可以不读取 value
+    fn scan_keys(&self, range: impl RangeBounds<Vec<u8>>) -> Result<Vec<Vec<u8>>> {
+        self.scan(range).map(|r| r.map(|(key, _)| key)).collect()
+    }
 
-        self.scan((start, end))
+    // 删除前缀匹配的所有 key，返回删除的 key 数量
+    // 默认先扫描出所有的 key 再逐个删除，存储引擎可以实现更高效的范围删除
+    fn delete_prefix(&mut self, prefix: Vec<u8>) -> Result<usize> {
+        let keys = self.scan_keys(prefix_range(prefix))?;
+        for key in keys.iter() {
+            self.delete(key.clone())?;
+        }
+        Ok(keys.len())
     }
+
+    // 压缩存储空间，清理掉已经失效的数据，默认不做任何事情
+    fn compact(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    // 按照存储引擎自己的策略判断是否需要压缩，完成了一次压缩时返回 true，默认不做任何事情
+    // 事务提交之后调用，持有引擎的写锁，耗时的压缩应该放到后台执行
+    fn auto_compact(&mut self) -> Result<bool> {
+        Ok(false)
+    }
+
+    // 把数据同步到磁盘上，默认不做任何事情
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    // 事务提交之后调用，默认把数据同步到磁盘，存储引擎可以按照自己的配置推迟同步
+    fn sync_commit(&mut self) -> Result<()> {
+        self.flush()
+    }
+
+    // 关闭存储引擎，之后不能再读写数据，默认只同步数据
+    fn close(&mut self) -> Result<()> {
+        self.flush()
+    }
+
+    // 获取存储引擎的状态信息
+    fn status(&mut self) -> Result<Status>;
+}
+
+// 一组写入操作，value 为 None 表示删除，通过 Engine::write_batch 一起写入
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct WriteBatch {
+    ops: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        self.ops.push((key, Some(value)));
+    }
+
+    pub fn delete(&mut self, key: Vec<u8>) {
+        self.ops.push((key, None));
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+impl IntoIterator for WriteBatch {
+    type Item = (Vec<u8>, Option<Vec<u8>>);
+    type IntoIter = std::vec::IntoIter<Self::Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ops.into_iter()
+    }
+}
+
+// 存储引擎的状态信息
+#[derive(Debug, Clone, PartialEq)]
+pub struct Status {
+    // 存储引擎名称
+    pub name: String,
+    // key 的数量
+    pub keys: u64,
+    // 有效数据占用的大小
+    pub size: u64,
+    // 占用的磁盘空间大小
+    pub disk_size: u64,
+    // 已经执行过的压缩次数
+    pub compactions: u64,
+}
+
+// 前缀对应的 key 范围
+pub fn prefix_range(prefix: Vec<u8>) -> (Bound<Vec<u8>>, Bound<Vec<u8>>) {
+    // start: aaaa
+    // end:   aaab
+    let start = Bound::Included(prefix.clone());
+    let mut bound_prefix = prefix;
+    // 前缀包含了 255，例如 10,2,3,255
+    // 右边界就是 10,2,4
+    // 如果全都是 255
+    // 右边界 Unbounded
+    let end = match bound_prefix.iter().rposition(|b| *b != 255) {
+        Some(pos) => {
+            bound_prefix[pos] += 1;
+            bound_prefix.truncate(pos + 1);
+            Bound::Excluded(bound_prefix)
+        }
+        None => Bound::Unbounded,
+    };
+    (start, end)
 }
 
 pub trait EngineIterator: DoubleEndedIterator<Item = Result<(Vec<u8>, Vec<u8>)>> {}
 
 #[cfg(test)]
 mod tests {
-    use super::Engine;
+    use super::{Engine, WriteBatch};
     use crate::{
         error::Result,
-        storage::{disk::DiskEngine, memory::MemoryEngine},
+        storage::{
+            bptree::BPTreeEngine, disk::DiskEngine, memory::MemoryEngine, segment::SegmentEngine,
+        },
     };
     use std::{ops::Bound, path::PathBuf};
 
@@ -131,15 +246,61 @@ mod tests {
         assert_eq!(key1, b"camhue".to_vec());
         let (key2, _) = iter.next().transpose()?.unwrap();
         assert_eq!(key2, b"canehe".to_vec());
+        drop(iter);
+
+        // 前缀删除
+        assert_eq!(eng.delete_prefix(b"ca".to_vec())?, 2);
+        assert_eq!(eng.get(b"camhue".to_vec())?, None);
+        assert_eq!(eng.get(b"ccnaes".to_vec())?, Some(b"value1".to_vec()));
+        assert_eq!(eng.scan_prefix(b"ca".to_vec()).count(), 0);
+        assert_eq!(eng.delete_prefix(b"ca".to_vec())?, 0);
 
         Ok(())
     }
 
+    // 测试批量写入，按照加入的顺序执行
+    fn test_write_batch(mut eng: impl Engine) -> Result<()> {
+        eng.set(b"a".to_vec(), b"value1".to_vec())?;
+        let mut batch = WriteBatch::new();
+        batch.set(b"b".to_vec(), b"value2".to_vec());
+        batch.delete(b"a".to_vec());
+        batch.set(b"c".to_vec(), b"value3".to_vec());
+        batch.set(b"c".to_vec(), b"value4".to_vec());
+        batch.delete(b"d".to_vec());
+        assert_eq!(batch.len(), 5);
+        eng.write_batch(batch)?;
+        eng.write_batch(WriteBatch::new())?;
+
+        assert_eq!(eng.get(b"a".to_vec())?, None);
+        let items = eng.scan(..).collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            items,
+            vec![
+                (b"b".to_vec(), b"value2".to_vec()),
+                (b"c".to_vec(), b"value4".to_vec()),
+            ]
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_memory() -> Result<()> {
         test_point_opt(MemoryEngine::new())?;
         test_scan(MemoryEngine::new())?;
         test_scan_prefix(MemoryEngine::new())?;
+        test_write_batch(MemoryEngine::new())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_bptree() -> Result<()> {
+        test_point_opt(BPTreeEngine::new())?;
+        test_scan(BPTreeEngine::new())?;
+        test_scan_prefix(BPTreeEngine::new())?;
+        test_write_batch(BPTreeEngine::new())?;
+        // 很小的页，每个页只能放下一两条记录
+        test_scan(BPTreeEngine::with_page_size(32))?;
+        test_scan_prefix(BPTreeEngine::with_page_size(32))?;
         Ok(())
     }
 
@@ -153,6 +314,26 @@ mod tests {
 
         test_scan_prefix(DiskEngine::new(PathBuf::from("/tmp/sqldb3/db.log"))?)?;
         std::fs::remove_dir_all(PathBuf::from("/tmp/sqldb3"))?;
+
+        // 批量写入的记录重新打开之后依然可以读取
+        let dir = tempfile::tempdir()?.into_path();
+        test_write_batch(DiskEngine::new(dir.join("db.log"))?)?;
+        let eng = DiskEngine::new(dir.join("db.log"))?;
+        assert_eq!(eng.get(b"c".to_vec())?, Some(b"value4".to_vec()));
+        assert_eq!(eng.get(b"a".to_vec())?, None);
+        drop(eng);
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_segment() -> Result<()> {
+        let dir = tempfile::tempdir()?.into_path();
+        test_point_opt(SegmentEngine::new(dir.join("point"))?)?;
+        test_scan(SegmentEngine::new(dir.join("scan"))?)?;
+        test_scan_prefix(SegmentEngine::new(dir.join("prefix"))?)?;
+        test_write_batch(SegmentEngine::new(dir.join("batch"))?)?;
+        std::fs::remove_dir_all(dir)?;
         Ok(())
     }
 }
@@ -0,0 +1,350 @@
+use std::{
+    ops::RangeBounds,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use crate::error::{Error, Result};
+
+use super::{
+    disk::DiskEngine,
+    engine::{Engine, EngineIterator, Status},
+};
+
+// 故障注入存储引擎，包装其他的存储引擎，按照配置注入故障：
+// 1. 写入失败、读取失败，返回 I/O 错误
+// 2. 崩溃点：成功写入 n 次之后崩溃，之后所有的操作都会失败
+// 3. 部分写入：崩溃时的那次写入只写入一半的 value
+// 故障配置通过 faults() 获取，存储引擎交给上层使用之后依然可以修改
+pub struct FaultEngine<E: Engine> {
+    inner: E,
+    faults: Arc<Faults>,
+}
+
+// 故障配置
+#[derive(Default)]
+pub struct Faults {
+    state: Mutex<FaultState>,
+}
+
+#[derive(Default)]
+struct FaultState {
+    // 成功写入的次数，包括 set 和 delete
+    writes: u64,
+    fail_writes: bool,
+    fail_reads: bool,
+    // 成功写入 n 次之后崩溃
+    crash_after: Option<u64>,
+    // 崩溃时是否写入一部分数据
+    partial_write: bool,
+    crashed: bool,
+}
+
+impl Faults {
+    // 之后的写入都返回错误
+    pub fn fail_writes(&self, fail: bool) {
+        self.state.lock().unwrap().fail_writes = fail;
+    }
+
+    // 之后的读取都返回错误
+    pub fn fail_reads(&self, fail: bool) {
+        self.state.lock().unwrap().fail_reads = fail;
+    }
+
+    // 再成功写入 n 次之后崩溃
+    pub fn crash_after(&self, writes: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.crash_after = Some(state.writes + writes);
+    }
+
+    // 崩溃时的那次写入只写入一半的 value
+    pub fn partial_write(&self, partial: bool) {
+        self.state.lock().unwrap().partial_write = partial;
+    }
+
+    pub fn crashed(&self) -> bool {
+        self.state.lock().unwrap().crashed
+    }
+
+    pub fn writes(&self) -> u64 {
+        self.state.lock().unwrap().writes
+    }
+
+    fn check_read(&self) -> Result<()> {
+        let state = self.state.lock()?;
+        if state.crashed {
+            return Err(crashed_error());
+        }
+        if state.fail_reads {
+            return Err(Error::Internal("injected read error".into()));
+        }
+        Ok(())
+    }
+
+    // 检查这次写入是否可以执行，返回 Some 表示在这次写入时崩溃，Some(true) 表示只写入一部分
+    fn check_write(&self) -> Result<Option<bool>> {
+        let mut state = self.state.lock()?;
+        if state.crashed {
+            return Err(crashed_error());
+        }
+        if state.fail_writes {
+            return Err(Error::Internal("injected write error".into()));
+        }
+        if state.crash_after == Some(state.writes) {
+            state.crashed = true;
+            return Ok(Some(state.partial_write));
+        }
+        state.writes += 1;
+        Ok(None)
+    }
+}
+
+fn crashed_error() -> Error {
+    Error::Internal("injected crash".into())
+}
+
+impl<E: Engine> FaultEngine<E> {
+    pub fn new(inner: E) -> Self {
+        Self {
+            inner,
+            faults: Arc::new(Faults::default()),
+        }
+    }
+
+    pub fn faults(&self) -> Arc<Faults> {
+        self.faults.clone()
+    }
+
+    pub fn into_inner(self) -> E {
+        self.inner
+    }
+}
+
+impl<E: Engine> Engine for FaultEngine<E> {
+    type EngineIterator<'a>
+        = FaultEngineIterator<E::EngineIterator<'a>>
+    where
+        E: 'a;
+
+    fn set(&mut self, key: Vec<u8>, mut value: Vec<u8>) -> Result<()> {
+        match self.faults.check_write()? {
+            None => self.inner.set(key, value),
+            Some(partial) => {
+                if partial {
+                    value.truncate(value.len() / 2);
+                    self.inner.set(key, value)?;
+                }
+                Err(crashed_error())
+            }
+        }
+    }
+
+    fn get(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        self.faults.check_read()?;
+        self.inner.get(key)
+    }
+
+    fn delete(&mut self, key: Vec<u8>) -> Result<()> {
+        match self.faults.check_write()? {
+            None => self.inner.delete(key),
+            Some(_) => Err(crashed_error()),
+        }
+    }
+
+    fn scan(&self, range: impl RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_> {
+        match self.faults.check_read() {
+            Ok(()) => FaultEngineIterator::Inner(self.inner.scan(range)),
+            Err(err) => FaultEngineIterator::Failed(Some(err)),
+        }
+    }
+
+    fn scan_keys(&self, range: impl RangeBounds<Vec<u8>>) -> Result<Vec<Vec<u8>>> {
+        self.faults.check_read()?;
+        self.inner.scan_keys(range)
+    }
+
+    fn delete_prefix(&mut self, prefix: Vec<u8>) -> Result<usize> {
+        match self.faults.check_write()? {
+            None => self.inner.delete_prefix(prefix),
+            Some(_) => Err(crashed_error()),
+        }
+    }
+
+    fn compact(&mut self) -> Result<()> {
+        self.faults.check_read()?;
+        self.inner.compact()
+    }
+
+    fn auto_compact(&mut self) -> Result<bool> {
+        self.faults.check_read()?;
+        self.inner.auto_compact()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn sync_commit(&mut self) -> Result<()> {
+        self.inner.sync_commit()
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.inner.close()
+    }
+
+    fn status(&mut self) -> Result<Status> {
+        self.faults.check_read()?;
+        let mut status = self.inner.status()?;
+        status.name = format!("fault({})", status.name);
+        Ok(status)
+    }
+}
+
+// 注入读取失败时，迭代器返回一个错误
+pub enum FaultEngineIterator<I: EngineIterator> {
+    Inner(I),
+    Failed(Option<Error>),
+}
+
+impl<I: EngineIterator> EngineIterator for FaultEngineIterator<I> {}
+
+impl<I: EngineIterator> Iterator for FaultEngineIterator<I> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            FaultEngineIterator::Inner(iter) => iter.next(),
+            FaultEngineIterator::Failed(err) => err.take().map(Err),
+        }
+    }
+}
+
+impl<I: EngineIterator> DoubleEndedIterator for FaultEngineIterator<I> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match self {
+            FaultEngineIterator::Inner(iter) => iter.next_back(),
+            FaultEngineIterator::Failed(err) => err.take().map(Err),
+        }
+    }
+}
+
+// 崩溃恢复测试：依次在第 0、1、2... 次写入时模拟崩溃
+// 每一轮都在新的日志文件上执行 workload，崩溃之后重新打开 DiskEngine，交给 check 校验数据
+// workload 执行完成没有崩溃时结束，返回测试过的崩溃点数量
+pub fn crash_test<W, C>(file_path: &Path, workload: W, check: C) -> Result<u64>
+where
+    W: Fn(FaultEngine<DiskEngine>) -> Result<()>,
+    C: Fn(DiskEngine) -> Result<()>,
+{
+    let mut crash_point = 0;
+    loop {
+        if file_path.exists() {
+            std::fs::remove_file(file_path)?;
+        }
+        let engine = FaultEngine::new(DiskEngine::new(file_path.to_path_buf())?);
+        let faults = engine.faults();
+        faults.crash_after(crash_point);
+
+        match workload(engine) {
+            Ok(()) if !faults.crashed() => return Ok(crash_point),
+            Err(err) if !faults.crashed() => return Err(err),
+            _ => {}
+        }
+        check(DiskEngine::new(file_path.to_path_buf())?)
+            .map_err(|err| Error::Internal(format!("crash point {}: {}", crash_point, err)))?;
+        crash_point += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::{Error, Result},
+        sql::{
+            engine::{kv::KVEngine, Engine as _},
+            executor::ResultSet,
+            types::Value,
+        },
+        storage::{engine::Engine, memory::MemoryEngine},
+    };
+
+    use super::{crash_test, FaultEngine};
+
+    #[test]
+    fn test_fault_engine() -> Result<()> {
+        let mut eng = FaultEngine::new(MemoryEngine::new());
+        let faults = eng.faults();
+        eng.set(b"a".to_vec(), b"1".to_vec())?;
+
+        faults.fail_writes(true);
+        assert!(eng.set(b"b".to_vec(), b"2".to_vec()).is_err());
+        assert!(eng.delete(b"a".to_vec()).is_err());
+        assert_eq!(eng.get(b"a".to_vec())?, Some(b"1".to_vec()));
+        faults.fail_writes(false);
+
+        faults.fail_reads(true);
+        assert!(eng.get(b"a".to_vec()).is_err());
+        assert!(eng.scan(..).next().unwrap().is_err());
+        faults.fail_reads(false);
+
+        // 再写入一次之后崩溃，崩溃时的写入只写入一半
+        faults.crash_after(1);
+        faults.partial_write(true);
+        eng.set(b"b".to_vec(), b"2".to_vec())?;
+        assert!(eng.set(b"c".to_vec(), b"3333".to_vec()).is_err());
+        assert!(faults.crashed());
+        assert!(eng.get(b"a".to_vec()).is_err());
+        assert_eq!(faults.writes(), 2);
+
+        let inner = eng.into_inner();
+        assert_eq!(inner.get(b"c".to_vec())?, Some(b"33".to_vec()));
+        Ok(())
+    }
+
+    // 在事务的每一次写入时崩溃，恢复之后事务要么全部可见，要么全部不可见
+    #[test]
+    fn test_crash_recovery() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let count_rows = |eng| -> Result<Option<usize>> {
+            let mut s = KVEngine::new(eng).session()?;
+            let rows = match s.execute("select * from t;") {
+                Ok(ResultSet::Scan { rows, .. }) => rows,
+                Err(Error::NotFound(_)) => return Ok(None),
+                result => return Err(Error::Internal(format!("unexpected {:?}", result))),
+            };
+            // 崩溃遗留的事务不能阻塞之后对相同数据的写入
+            s.execute("delete from t;")?;
+            s.execute("insert into t values (1, 1), (2, 2), (3, 3);")?;
+            Ok(Some(rows.len()))
+        };
+
+        let crash_points = crash_test(
+            &p,
+            |eng| {
+                let mut s = KVEngine::new(eng).session()?;
+                s.execute("create table t (a int primary key, b int);")?;
+                s.execute("begin;")?;
+                s.execute("insert into t values (1, 1), (2, 2), (3, 3);")?;
+                s.execute("update t set b = 10 where a = 2;")?;
+                s.execute("commit;")?;
+                Ok(())
+            },
+            |eng| match count_rows(eng)? {
+                None | Some(0) | Some(3) => Ok(()),
+                Some(n) => Err(Error::Internal(format!("partial transaction with {} rows", n))),
+            },
+        )?;
+        assert!(crash_points > 10);
+
+        // 没有崩溃时，数据全部可见
+        let mut s = KVEngine::new(crate::storage::disk::DiskEngine::new(p.clone())?).session()?;
+        match s.execute("select b from t where a = 2;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Integer(10)]]),
+            _ => unreachable!(),
+        }
+        drop(s);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+}
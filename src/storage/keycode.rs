@@ -5,6 +5,9 @@ use serde::{
 
 use crate::error::{Error, Result};
 
+// 64 位整数的符号位
+const SIGN_BIT: u64 = 1 << 63;
+
 pub fn serialize_key<T: serde::Serialize>(key: &T) -> Result<Vec<u8>> {
     let mut ser = Serializer { output: Vec::new() };
     key.serialize(&mut ser)?;
@@ -12,7 +15,13 @@ pub fn serialize_key<T: serde::Serialize>(key: &T) -> Result<Vec<u8>> {
 }
 
 pub fn deserialize_key<'a, T: serde::Deserialize<'a>>(input: &'a [u8]) -> Result<T> {
-    let mut der = Deserializer { input };
+    let mut der = Deserializer { input, legacy: false };
+    T::deserialize(&mut der)
+}
+
+// 按照保序编码之前的格式解码，整数和浮点数直接按大端编码，只在升级旧版本的数据时使用
+pub fn deserialize_legacy_key<'a, T: serde::Deserialize<'a>>(input: &'a [u8]) -> Result<T> {
+    let mut der = Deserializer { input, legacy: true };
     T::deserialize(&mut der)
 }
 
@@ -20,7 +29,7 @@ pub struct Serializer {
     output: Vec<u8>,
 }
 
-impl<'a> ser::Serializer for &'a mut Serializer {
+impl ser::Serializer for &mut Serializer {
     type Ok = ();
 
     type Error = Error;
@@ -35,7 +44,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
 
     type SerializeMap = serde::ser::Impossible<Self::Ok, Self::Error>;
 
-    type SerializeStruct = serde::ser::Impossible<Self::Ok, Self::Error>;
+    type SerializeStruct = Self;
 
     type SerializeStructVariant = serde::ser::Impossible<Self::Ok, Self::Error>;
 
@@ -56,13 +65,24 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         todo!()
     }
 
+    // 有符号整数翻转符号位之后按照大端编码，负数排在正数之前
+    // -1 -> 7f ff ff ff ff ff ff ff
+    //  0 -> 80 00 00 00 00 00 00 00
+    //  1 -> 80 00 00 00 00 00 00 01
     fn serialize_i64(self, v: i64) -> Result<()> {
-        self.output.extend(v.to_be_bytes());
+        self.output.extend(((v as u64) ^ SIGN_BIT).to_be_bytes());
         Ok(())
     }
 
-    fn serialize_u8(self, _v: u8) -> Result<()> {
-        todo!()
+    // 和 i64 一样翻转符号位，用于定点小数
+    fn serialize_i128(self, v: i128) -> Result<()> {
+        self.output.extend(((v as u128) ^ (1 << 127)).to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.output.push(v);
+        Ok(())
     }
 
     fn serialize_u16(self, _v: u16) -> Result<()> {
@@ -82,8 +102,11 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         todo!()
     }
 
+    // 浮点数为正数时翻转符号位，为负数时翻转所有的位，编码后的字节顺序和数值的大小顺序一致
     fn serialize_f64(self, v: f64) -> Result<()> {
-        self.output.extend(v.to_be_bytes());
+        let bits = v.to_bits();
+        let bits = if bits & SIGN_BIT == 0 { bits ^ SIGN_BIT } else { !bits };
+        self.output.extend(bits.to_be_bytes());
         Ok(())
     }
 
@@ -101,7 +124,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     // 97 98 0 0 99 -> 97 98 0 255 0 255 99 0 0
     fn serialize_bytes(self, v: &[u8]) -> Result<()> {
         let mut res = Vec::new();
-        for e in v.into_iter() {
+        for e in v.iter() {
             match e {
                 0 => res.extend([0, 255]),
                 b => res.push(*b),
@@ -198,8 +221,9 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         todo!()
     }
 
+    // 结构体按照字段的顺序依次编码，类似 Interval { months, seconds }
     fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
-        todo!()
+        Ok(self)
     }
 
     fn serialize_struct_variant(
@@ -213,7 +237,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeSeq for &'a mut Serializer {
+impl ser::SerializeSeq for &mut Serializer {
     type Ok = ();
 
     type Error = Error;
@@ -230,7 +254,7 @@ impl<'a> ser::SerializeSeq for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeTuple for &'a mut Serializer {
+impl ser::SerializeTuple for &mut Serializer {
     type Ok = ();
 
     type Error = Error;
@@ -247,7 +271,7 @@ impl<'a> ser::SerializeTuple for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
+impl ser::SerializeTupleVariant for &mut Serializer {
     type Ok = ();
 
     type Error = Error;
@@ -264,8 +288,27 @@ impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
     }
 }
 
+impl ser::SerializeStruct for &mut Serializer {
+    type Ok = ();
+
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, _key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
 pub struct Deserializer<'de> {
     input: &'de [u8],
+    // 是否是保序编码之前的格式
+    legacy: bool,
 }
 
 impl<'de> Deserializer<'de> {
@@ -296,7 +339,7 @@ impl<'de> Deserializer<'de> {
     }
 }
 
-impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     type Error = Error;
 
     fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value>
@@ -342,15 +385,26 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: de::Visitor<'de>,
     {
         let bytes = self.take_bytes(8);
-        let v = i64::from_be_bytes(bytes.try_into()?);
-        visitor.visit_i64(v)
+        let v = u64::from_be_bytes(bytes.try_into()?);
+        let v = if self.legacy { v } else { v ^ SIGN_BIT };
+        visitor.visit_i64(v as i64)
     }
 
-    fn deserialize_u8<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        let bytes = self.take_bytes(16);
+        let v = u128::from_be_bytes(bytes.try_into()?) ^ (1 << 127);
+        visitor.visit_i128(v as i128)
+    }
+
+    fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        let v = self.take_bytes(1)[0];
+        visitor.visit_u8(v)
     }
 
     fn deserialize_u16<V>(self, _visitor: V) -> Result<V::Value>
@@ -390,8 +444,15 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: de::Visitor<'de>,
     {
         let bytes = self.take_bytes(8);
-        let v = f64::from_be_bytes(bytes.try_into()?);
-        visitor.visit_f64(v)
+        let bits = u64::from_be_bytes(bytes.try_into()?);
+        let bits = if self.legacy {
+            bits
+        } else if bits & SIGN_BIT != 0 {
+            bits ^ SIGN_BIT
+        } else {
+            !bits
+        };
+        visitor.visit_f64(f64::from_bits(bits))
     }
 
     fn deserialize_char<V>(self, _visitor: V) -> Result<V::Value>
@@ -409,11 +470,11 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         visitor.visit_str(&String::from_utf8(bytes)?)
     }
 
-    fn deserialize_string<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        self.deserialize_str(visitor)
     }
 
     fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
@@ -495,12 +556,12 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         self,
         _name: &'static str,
         _fields: &'static [&'static str],
-        _visitor: V,
+        visitor: V,
     ) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_seq(self)
     }
 
     fn deserialize_enum<V>(
@@ -530,7 +591,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     }
 }
 
-impl<'de, 'a> de::SeqAccess<'de> for Deserializer<'de> {
+impl<'de> de::SeqAccess<'de> for Deserializer<'de> {
     type Error = Error;
 
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
@@ -541,7 +602,7 @@ impl<'de, 'a> de::SeqAccess<'de> for Deserializer<'de> {
     }
 }
 
-impl<'de, 'a> de::EnumAccess<'de> for &mut Deserializer<'de> {
+impl<'de> de::EnumAccess<'de> for &mut Deserializer<'de> {
     type Error = Error;
 
     type Variant = Self;
@@ -556,7 +617,7 @@ impl<'de, 'a> de::EnumAccess<'de> for &mut Deserializer<'de> {
     }
 }
 
-impl<'de, 'a> de::VariantAccess<'de> for &mut Deserializer<'de> {
+impl<'de> de::VariantAccess<'de> for &mut Deserializer<'de> {
     type Error = Error;
 
     fn unit_variant(self) -> Result<()> {
@@ -587,9 +648,12 @@ impl<'de, 'a> de::VariantAccess<'de> for &mut Deserializer<'de> {
 
 #[cfg(test)]
 mod tests {
-    use crate::storage::{
-        keycode::{deserialize_key, serialize_key},
-        mvcc::{MvccKey, MvccKeyPrefix},
+    use crate::{
+        sql::types::{temporal, Decimal, Interval, Value},
+        storage::{
+            keycode::{deserialize_key, deserialize_legacy_key, serialize_key},
+            mvcc::{MvccKey, MvccKeyPrefix},
+        },
     };
 
     #[test]
@@ -646,6 +710,83 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_struct() {
+        let v = Value::Interval(Interval {
+            months: 1,
+            seconds: 2,
+        });
+        let res = serialize_key(&v).unwrap();
+        assert_eq!(res, vec![5, 128, 0, 0, 0, 0, 0, 0, 1, 128, 0, 0, 0, 0, 0, 0, 2]);
+        assert_eq!(deserialize_key::<Value>(&res).unwrap(), v);
+    }
+
+    #[test]
+    fn test_order_preserving() {
+        let check = |values: Vec<Value>| {
+            let keys = values
+                .iter()
+                .map(|v| serialize_key(v).unwrap())
+                .collect::<Vec<_>>();
+            for (v, k) in values.iter().zip(keys.iter()) {
+                assert_eq!(&deserialize_key::<Value>(k).unwrap(), v);
+            }
+            // 编码后的字节顺序和值的大小顺序一致
+            for w in keys.windows(2) {
+                assert!(w[0] < w[1], "{:?} {:?}", w[0], w[1]);
+            }
+        };
+
+        check(
+            [i64::MIN, -1000, -1, 0, 1, 255, 256, i64::MAX]
+                .into_iter()
+                .map(Value::Integer)
+                .collect(),
+        );
+        check(
+            [f64::NEG_INFINITY, f64::MIN, -1.5, -1e-300, 0.0, 1e-300, 1.5, f64::MAX, f64::INFINITY]
+                .into_iter()
+                .map(Value::Float)
+                .collect(),
+        );
+        check(vec![
+            Value::Interval(Interval::parse("-1 month").unwrap()),
+            Value::Interval(Interval::parse("-1 day").unwrap()),
+            Value::Interval(Interval::parse("1 day").unwrap()),
+            Value::Interval(Interval::parse("1 month").unwrap()),
+        ]);
+        check(
+            ["1969-12-31", "1970-01-01", "2024-02-29", "2024-03-01"]
+                .into_iter()
+                .map(|d| Value::Date(temporal::parse_date(d).unwrap()))
+                .collect(),
+        );
+        check(
+            ["1969-12-31 23:59:59.5", "1970-01-01 00:00:00", "2024-01-01 00:00:00.000001", "2024-01-01 10:00:00"]
+                .into_iter()
+                .map(|ts| Value::Timestamp(temporal::parse_timestamp(ts).unwrap()))
+                .collect(),
+        );
+        // 同一列中的定点小数 scale 相同
+        check(
+            ["-1000.00", "-1.50", "-0.01", "0.00", "0.01", "1.50", "1000.00"]
+                .into_iter()
+                .map(|d| Value::from(Decimal::parse(d).unwrap()))
+                .collect(),
+        );
+    }
+
+    #[test]
+    fn test_legacy_key() {
+        // 保序编码之前的格式，整数和浮点数直接按大端编码
+        let key = vec![2, 255, 255, 255, 255, 255, 255, 255, 254];
+        assert_eq!(deserialize_legacy_key::<Value>(&key).unwrap(), Value::Integer(-2));
+        let key = vec![3, 191, 248, 0, 0, 0, 0, 0, 0];
+        assert_eq!(deserialize_legacy_key::<Value>(&key).unwrap(), Value::Float(-1.5));
+        let key = vec![4, 97, 0, 0];
+        assert_eq!(deserialize_legacy_key::<Value>(&key).unwrap(), Value::String("a".into()));
+    }
+
     // #[test]
     // fn test_u8_convert() {
     //     let v = [1 as u8, 2, 3];
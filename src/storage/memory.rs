@@ -2,11 +2,19 @@ use std::collections::{btree_map, BTreeMap};
 
 use crate::error::Result;
 
+use super::engine::Status;
+
 // 内存存储引擎定义
 pub struct MemoryEngine {
     data: BTreeMap<Vec<u8>, Vec<u8>>,
 }
 
+impl Default for MemoryEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MemoryEngine {
     pub fn new() -> Self {
         Self {
@@ -23,7 +31,7 @@ impl super::engine::Engine for MemoryEngine {
         Ok(())
     }
 
-    fn get(&mut self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
+    fn get(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
         let value = self.data.get(&key).cloned();
         Ok(value)
     }
@@ -33,11 +41,21 @@ impl super::engine::Engine for MemoryEngine {
         Ok(())
     }
 
-    fn scan(&mut self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_> {
+    fn scan(&self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_> {
         MemoryEngineIterator {
             inner: self.data.range(range),
         }
     }
+
+    fn status(&mut self) -> Result<Status> {
+        Ok(Status {
+            name: "memory".into(),
+            keys: self.data.len() as u64,
+            size: self.data.iter().map(|(k, v)| (k.len() + v.len()) as u64).sum(),
+            disk_size: 0,
+            compactions: 0,
+        })
+    }
 }
 
 // 内存存储引擎迭代器
@@ -1,5 +1,11 @@
+pub mod bptree;
+#[cfg(feature = "native")]
 pub mod disk;
 pub mod engine;
+#[cfg(feature = "native")]
+pub mod fault;
 pub mod keycode;
 pub mod memory;
 pub mod mvcc;
+#[cfg(feature = "native")]
+pub mod segment;
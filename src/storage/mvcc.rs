@@ -1,7 +1,11 @@
 use std::{
-    collections::{BTreeMap, HashSet},
-    sync::{Arc, Mutex, MutexGuard},
-    u64,
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::Bound,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
 };
 
 use serde::{Deserialize, Serialize};
@@ -9,20 +13,36 @@ use serde::{Deserialize, Serialize};
 use crate::error::{Error, Result};
 
 use super::{
-    engine::Engine,
+    engine::{self, Engine, WriteBatch},
     keycode::{deserialize_key, serialize_key},
 };
+#[cfg(feature = "native")]
+use super::disk::DiskEngine;
+#[cfg(feature = "native")]
+use std::path::PathBuf;
 
 pub type Version = u64;
 
+// 编码后的 key 的范围
+type KeyRange = (Bound<Vec<u8>>, Bound<Vec<u8>>);
+
+// 扫描迭代器每次持有存储引擎的锁时最多读取的 key 数量
+const SCAN_BATCH_SIZE: usize = 256;
+
 pub struct Mvcc<E: Engine> {
-    engine: Arc<Mutex<E>>,
+    engine: Arc<RwLock<E>>,
+    // 是否已经回滚了崩溃遗留的事务
+    recovered: Arc<Mutex<bool>>,
+    // 还没有结束的只读事务数量，只读事务不写入活跃事务列表，vacuum 时通过这个计数判断
+    read_only_txns: Arc<AtomicUsize>,
 }
 
 impl<E: Engine> Clone for Mvcc<E> {
     fn clone(&self) -> Self {
         Self {
             engine: self.engine.clone(),
+            recovered: self.recovered.clone(),
+            read_only_txns: self.read_only_txns.clone(),
         }
     }
 }
@@ -30,44 +50,301 @@ impl<E: Engine> Clone for Mvcc<E> {
 impl<E: Engine> Mvcc<E> {
     pub fn new(eng: E) -> Self {
         Self {
-            engine: Arc::new(Mutex::new(eng)),
+            engine: Arc::new(RwLock::new(eng)),
+            recovered: Arc::new(Mutex::new(false)),
+            read_only_txns: Arc::new(AtomicUsize::new(0)),
         }
     }
 
     pub fn begin(&self) -> Result<MvccTransaction<E>> {
-        MvccTransaction::begin(self.engine.clone())
+        self.begin_with(IsolationLevel::default())
+    }
+
+    // 使用指定的隔离级别开启事务
+    pub fn begin_with(&self, isolation: IsolationLevel) -> Result<MvccTransaction<E>> {
+        self.recover()?;
+        MvccTransaction::begin(self.engine.clone(), isolation)
+    }
+
+    // 开启只读事务，as_of 为空时读取当前最新的快照，否则读取版本号不超过 as_of 的已提交数据
+    pub fn begin_read_only(&self, as_of: Option<Version>) -> Result<MvccTransaction<E>> {
+        self.recover()?;
+        MvccTransaction::begin_read_only(self.engine.clone(), as_of, self.read_only_txns.clone())
+    }
+
+    // 把数据同步到磁盘上
+    pub fn flush(&self) -> Result<()> {
+        self.engine.write()?.flush()
+    }
+
+    // 关闭底层的存储引擎
+    pub fn close(&self) -> Result<()> {
+        self.engine.write()?.close()
+    }
+
+    // 回滚上次异常退出时遗留的未完成事务，在第一次开启事务之前执行
+    // 此时还没有开启过任何事务，存储中的活跃事务都是崩溃遗留的
+    fn recover(&self) -> Result<()> {
+        let mut recovered = self.recovered.lock()?;
+        if *recovered {
+            return Ok(());
+        }
+
+        let mut engine = self.engine.write()?;
+        let active_versions = MvccTransaction::scan_active(&*engine)?;
+        for version in active_versions.iter() {
+            MvccTransaction::rollback_version(&mut *engine, *version)?;
+        }
+
+        // 提交过程中崩溃时，事务已经提交，但是 TxnWrite 记录可能没有删除完
+        // 去掉编码后版本号的 8 个字节，得到所有 TxnWrite 记录的前缀
+        let mut prefix = MvccKeyPrefix::TxnWrite(0).encode()?;
+        prefix.truncate(prefix.len() - 8);
+        let keys = engine
+            .scan_prefix(prefix)
+            .map(|r| r.map(|(key, _)| key))
+            .collect::<Result<Vec<_>>>()?;
+        for key in keys {
+            engine.delete(key)?;
+        }
+
+        tracing::debug!(txns = active_versions.len(), "recover");
+        *recovered = true;
+        Ok(())
+    }
+
+    // 清理旧版本数据，并压缩底层存储，返回清理掉的版本数
+    // 活跃事务以及只读事务可能还会读取旧版本，所以存在这些事务时只压缩底层存储
+    pub fn vacuum(&self) -> Result<usize> {
+        self.recover()?;
+        let mut engine = self.engine.write()?;
+        let mut delete_keys = Vec::new();
+
+        if MvccTransaction::scan_active(&*engine)?.is_empty()
+            && self.read_only_txns.load(Ordering::SeqCst) == 0
+        {
+            // 没有活跃事务时，所有的版本都已经提交，每个 key 只需保留最新的版本
+            // 最新的版本是删除标记的话，也可以一并清理掉
+            let mut last: Option<(Vec<u8>, Vec<u8>, bool)> = None;
+            // 去掉空 key 编码后的 [0, 0] 后缀，得到所有版本数据的前缀
+            let mut prefix = MvccKeyPrefix::Version(vec![]).encode()?;
+            prefix.truncate(prefix.len() - 2);
+            let mut iter = engine.scan_prefix(prefix);
+            while let Some((key, value)) = iter.next().transpose()? {
+                let raw_key = match MvccKey::decode(key.clone())? {
+                    MvccKey::Version(raw_key, _) => raw_key,
+                    _ => {
+                        return Err(Error::Internal(format!(
+                            "unexpected key: {:?}",
+                            String::from_utf8(key)
+                        )))
+                    }
+                };
+                let deleted = bincode::deserialize::<Option<Vec<u8>>>(&value)?.is_none();
+                if let Some((last_raw_key, last_key, last_deleted)) = last.take() {
+                    if last_raw_key == raw_key || last_deleted {
+                        delete_keys.push(last_key);
+                    }
+                }
+                last = Some((raw_key, key, deleted));
+            }
+            drop(iter);
+            if let Some((_, last_key, true)) = last {
+                delete_keys.push(last_key);
+            }
+
+            for key in delete_keys.iter() {
+                engine.delete(key.clone())?;
+            }
+            // 当前已经分配的版本号之前的历史版本都被清理了，之后不能再读取这些版本的快照
+            let next_version: Version = match engine.get(MvccKey::NextVersion.encode()?)? {
+                Some(value) => bincode::deserialize(&value)?,
+                None => 1,
+            };
+            engine.set(
+                MvccKey::VacuumVersion.encode()?,
+                bincode::serialize(&(next_version - 1))?,
+            )?;
+        }
+
+        engine.compact()?;
+        Ok(delete_keys.len())
+    }
+
+    // 把当前最新快照中的数据备份到一个新的磁盘文件中，返回备份的 key 数量
+    // 在只读事务中读取数据，不影响其他事务的读写；备份文件中只保留每个 key 的最新版本，
+    // 版本号都是 1，可以直接作为数据库文件打开
+    #[cfg(feature = "native")]
+    pub fn backup(&self, path: PathBuf) -> Result<usize> {
+        if path.exists() {
+            return Err(Error::InvalidInput(format!(
+                "backup file {} already exists",
+                path.display()
+            )));
+        }
+        let txn = self.begin_read_only(None)?;
+        let results = txn.scan_prefix(Vec::new())?;
+        txn.commit()?;
+
+        let mut engine = DiskEngine::new(path)?;
+        let mut batch = WriteBatch::new();
+        batch.set(MvccKey::NextVersion.encode()?, bincode::serialize(&(2 as Version))?);
+        for result in results.iter() {
+            batch.set(
+                MvccKey::Version(result.key.clone(), 1).encode()?,
+                bincode::serialize(&Some(result.value.clone()))?,
+            );
+        }
+        engine.write_batch(batch)?;
+        engine.close()?;
+        tracing::info!(keys = results.len(), "backup");
+        Ok(results.len())
+    }
+}
+
+// 读取备份文件中的所有数据
+#[cfg(feature = "native")]
+pub fn load_backup(path: PathBuf) -> Result<Vec<ScanResult>> {
+    if !path.exists() {
+        return Err(Error::NotFound(format!(
+            "backup file {} does not exist",
+            path.display()
+        )));
+    }
+    let mvcc = Mvcc::new(DiskEngine::new(path)?);
+    let txn = mvcc.begin_read_only(None)?;
+    let results = txn.scan_prefix(Vec::new())?;
+    txn.commit()?;
+    mvcc.close()?;
+    Ok(results)
+}
+
+// 事务的隔离级别
+// READ COMMITTED：每条语句开始时重新获取快照，可以读到其他事务在这之前提交的数据
+// REPEATABLE READ：整个事务使用开启时的快照，默认的隔离级别
+// SERIALIZABLE：在 REPEATABLE READ 的基础上记录读取过的 key 和范围，
+// 提交时如果其中有对当前事务不可见的事务写入的数据，说明读取的数据已经过期，提交失败，可以重试
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IsolationLevel {
+    ReadCommitted,
+    #[default]
+    RepeatableRead,
+    Serializable,
+}
+
+impl std::fmt::Display for IsolationLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            IsolationLevel::ReadCommitted => "READ COMMITTED",
+            IsolationLevel::RepeatableRead => "REPEATABLE READ",
+            IsolationLevel::Serializable => "SERIALIZABLE",
+        })
     }
 }
 
 pub struct MvccTransaction<E: Engine> {
-    engine: Arc<Mutex<E>>,
-    state: TransactionState,
+    engine: Arc<RwLock<E>>,
+    // 当前事务的版本号
+    version: Version,
+    isolation: IsolationLevel,
+    // 读取数据时使用的快照，READ COMMITTED 每条语句开始时更新
+    state: RefCell<TransactionState>,
+    // SERIALIZABLE 事务读取过的 key 范围，编码后的 key
+    reads: RefCell<Vec<KeyRange>>,
+    // 只读事务持有的计数，事务结束时释放
+    read_only: Option<ReadOnlyGuard>,
+    // 事务中读写的 key 数量，和扫描迭代器共享
+    io: Arc<IoCounters>,
+    // 保存点，按照创建的顺序排列
+    savepoints: RefCell<Vec<Savepoint>>,
+}
+
+// 只读事务存在期间计数加一，drop 时减一
+struct ReadOnlyGuard(Arc<AtomicUsize>);
+
+impl ReadOnlyGuard {
+    fn new(count: Arc<AtomicUsize>) -> Self {
+        count.fetch_add(1, Ordering::SeqCst);
+        Self(count)
+    }
+}
+
+impl Drop for ReadOnlyGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// 保存点，记录创建之后第一次写入的 key 在写入之前本事务写入的数据，回滚到保存点时恢复
+// 更早的写入由更早的保存点记录，回滚时从后往前依次恢复
+struct Savepoint {
+    name: String,
+    // 编码前的 key，以及本事务版本的原始数据，None 表示本事务之前没有写入过这个 key
+    undo: HashMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+// 事务读取、写入的 key 数量
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct IoStats {
+    pub keys_read: u64,
+    pub keys_written: u64,
+}
+
+#[derive(Default)]
+struct IoCounters {
+    keys_read: AtomicU64,
+    keys_written: AtomicU64,
+}
+
+impl IoCounters {
+    fn record(&self, read: usize, written: usize) {
+        self.keys_read.fetch_add(read as u64, Ordering::Relaxed);
+        self.keys_written.fetch_add(written as u64, Ordering::Relaxed);
+    }
 }
 
 // 事务状态
+#[derive(Clone)]
 pub struct TransactionState {
     // 当前事务的版本号;持久化;
     pub version: Version,
-    // 当前活跃事务版本列表;持久化;
+    // 快照中可见的最大版本号，开启事务时等于当前事务的版本号
+    pub snapshot: Version,
+    // 获取快照时的活跃事务版本列表，不包括当前事务;持久化;
     pub active_versions: HashSet<Version>,
 }
 
 impl TransactionState {
     fn is_visible(&self, version: Version) -> bool {
         if self.active_versions.contains(&version) {
-            return false;
+            false
+        } else if version == self.version {
+            true
         } else {
-            return version <= self.version;
+            version <= self.snapshot
         }
     }
 }
 
+// MVCC 的状态信息
+#[derive(Debug, Clone, PartialEq)]
+pub struct Status {
+    // 底层存储引擎的状态
+    pub storage: engine::Status,
+    // 所有 key 的版本总数
+    pub versions: u64,
+    // 活跃事务的版本号，以及写入的 key 数量
+    pub active_txns: Vec<(Version, u64)>,
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub enum MvccKey {
     NextVersion,
     TenActive(Version),
     TxnWrite(Version, #[serde(with = "serde_bytes")] Vec<u8>),
     Version(#[serde(with = "serde_bytes")] Vec<u8>, Version),
+    // 上次 vacuum 时已经分配的最大版本号，更早的历史版本已经被清理
+    VacuumVersion,
 }
 
 // NextVersion 0
@@ -100,9 +377,9 @@ impl MvccKeyPrefix {
 
 impl<E: Engine> MvccTransaction<E> {
     // 开启事务
-    pub fn begin(eng: Arc<Mutex<E>>) -> Result<Self> {
+    pub fn begin(eng: Arc<RwLock<E>>, isolation: IsolationLevel) -> Result<Self> {
         // 获取存储引擎
-        let mut engine = eng.lock()?;
+        let mut engine = eng.write()?;
         // 获取最新的版本号
         let next_version = match engine.get(MvccKey::NextVersion.encode()?)? {
             Some(value) => bincode::deserialize(&value)?,
@@ -115,59 +392,288 @@ impl<E: Engine> MvccTransaction<E> {
         )?;
 
         // 获取当前活跃的事务列表
-        let active_versions = Self::scan_active(&mut engine)?;
+        let active_versions = Self::scan_active(&engine)?;
 
         // 当前事务加入到活跃事务列表中
         engine.set(MvccKey::TenActive(next_version).encode()?, vec![])?;
 
         Ok(Self {
             engine: eng.clone(),
-            state: TransactionState {
+            version: next_version,
+            isolation,
+            state: RefCell::new(TransactionState {
                 version: next_version,
+                snapshot: next_version,
                 active_versions,
-            },
+            }),
+            reads: RefCell::new(Vec::new()),
+            read_only: None,
+            io: Arc::new(IoCounters::default()),
+            savepoints: RefCell::new(Vec::new()),
+        })
+    }
+
+    // 开启只读事务，不分配新的版本号，也不加入活跃事务列表
+    // 事务的版本号是读取的快照版本，当前还没有提交的事务写入的数据不可见
+    pub fn begin_read_only(
+        eng: Arc<RwLock<E>>,
+        as_of: Option<Version>,
+        read_only_txns: Arc<AtomicUsize>,
+    ) -> Result<Self> {
+        let engine = eng.read()?;
+        let next_version: Version = match engine.get(MvccKey::NextVersion.encode()?)? {
+            Some(value) => bincode::deserialize(&value)?,
+            None => 1,
+        };
+        let version = match as_of {
+            Some(version) if version >= next_version => {
+                return Err(Error::InvalidInput(format!(
+                    "version {} does not exist, the latest version is {}",
+                    version,
+                    next_version - 1
+                )))
+            }
+            Some(version) => {
+                let vacuumed: Version = match engine.get(MvccKey::VacuumVersion.encode()?)? {
+                    Some(value) => bincode::deserialize(&value)?,
+                    None => 0,
+                };
+                if version < vacuumed {
+                    return Err(Error::InvalidInput(format!(
+                        "version {} has been removed by vacuum, the earliest version is {}",
+                        version, vacuumed
+                    )));
+                }
+                version
+            }
+            None => next_version - 1,
+        };
+        let active_versions = Self::scan_active(&engine)?;
+
+        Ok(Self {
+            engine: eng.clone(),
+            version,
+            isolation: IsolationLevel::RepeatableRead,
+            state: RefCell::new(TransactionState {
+                version,
+                snapshot: version,
+                active_versions,
+            }),
+            reads: RefCell::new(Vec::new()),
+            read_only: Some(ReadOnlyGuard::new(read_only_txns)),
+            io: Arc::new(IoCounters::default()),
+            savepoints: RefCell::new(Vec::new()),
         })
     }
 
     // 获取版本号
     pub fn version(&self) -> u64 {
-        self.state.version
+        self.version
+    }
+
+    pub fn isolation(&self) -> IsolationLevel {
+        self.isolation
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.is_some()
+    }
+
+    // 每条语句开始执行之前调用，READ COMMITTED 事务重新获取快照，其他隔离级别不需要处理
+    pub fn begin_statement(&self) -> Result<()> {
+        if self.isolation != IsolationLevel::ReadCommitted {
+            return Ok(());
+        }
+        let engine = self.engine.read()?;
+        let next_version: Version = match engine.get(MvccKey::NextVersion.encode()?)? {
+            Some(value) => bincode::deserialize(&value)?,
+            None => 1,
+        };
+        let mut active_versions = Self::scan_active(&engine)?;
+        active_versions.remove(&self.version);
+        let mut state = self.state.borrow_mut();
+        state.snapshot = next_version - 1;
+        state.active_versions = active_versions;
+        Ok(())
+    }
+
+    // 事务开始以来读取、写入的 key 数量
+    pub fn io_stats(&self) -> IoStats {
+        IoStats {
+            keys_read: self.io.keys_read.load(Ordering::Relaxed),
+            keys_written: self.io.keys_written.load(Ordering::Relaxed),
+        }
+    }
+
+    fn record_io(&self, read: usize, written: usize) {
+        self.io.record(read, written);
+    }
+
+    // 获取 MVCC 以及底层存储引擎的状态信息
+    pub fn status(&self) -> Result<Status> {
+        let mut engine = self.engine.write()?;
+
+        let mut active_versions = Self::scan_active(&engine)?
+            .into_iter()
+            .collect::<Vec<_>>();
+        active_versions.sort();
+        let mut active_txns = Vec::new();
+        for version in active_versions {
+            let writes = engine
+                .scan_prefix(MvccKeyPrefix::TxnWrite(version).encode()?)
+                .count();
+            active_txns.push((version, writes as u64));
+        }
+
+        let mut prefix = MvccKeyPrefix::Version(vec![]).encode()?;
+        prefix.truncate(prefix.len() - 2);
+        let versions = engine.scan_prefix(prefix).count() as u64;
+
+        Ok(Status {
+            storage: engine.status()?,
+            versions,
+            active_txns,
+        })
     }
 
     // 提交事务
     pub fn commit(&self) -> Result<()> {
+        tracing::trace!(version = self.version, "commit");
+        // 只读事务没有写入任何数据，不需要处理
+        if self.is_read_only() {
+            return Ok(());
+        }
         // 获取存储引擎
-        let mut engine = self.engine.lock()?;
+        let mut engine = self.engine.write()?;
 
-        let mut delete_keys = Vec::new();
-        // 找到这个当前事务的 TxnWrite 信息;
-        let mut iter = engine.scan_prefix(MvccKeyPrefix::TxnWrite(self.state.version).encode()?);
+        // 检查写入和读取过的数据是否已经被其他事务修改，修改过的话回滚当前事务
+        if let Err(err) = self.check_writes(&engine).and_then(|_| self.check_reads(&engine)) {
+            Self::rollback_version(&mut engine, self.version)?;
+            return Err(err);
+        }
+
+        // 先从活跃事务列表中删除，删除之后事务就已经提交了;
+        // 之后如果崩溃，遗留的 TxnWrite 信息在恢复时清理
+        let mut batch = WriteBatch::new();
+        batch.delete(MvccKey::TenActive(self.version).encode()?);
+
+        // 删除这个当前事务的 TxnWrite 信息;
+        let mut iter = engine.scan_prefix(MvccKeyPrefix::TxnWrite(self.version).encode()?);
         while let Some((key, _)) = iter.next().transpose()? {
-            delete_keys.push(key);
+            batch.delete(key);
         }
         drop(iter);
 
-        // 删除这些
-        for key in delete_keys.into_iter() {
-            engine.delete(key)?;
+        // 提交记录一次写入，事务中所有的写入通过一次 fsync 落盘（group commit）
+        // 存储引擎可以按照配置推迟同步
+        engine.write_batch(batch)?;
+        engine.sync_commit()?;
+
+        // 事务已经提交，压缩失败不影响提交的结果；磁盘引擎在后台压缩，这里只启动或者收尾
+        if let Err(err) = engine.auto_compact() {
+            tracing::warn!(error = %err, "auto compaction failed");
         }
+        Ok(())
+    }
 
-        // 从活跃事务列表中删除;
-        engine.delete(MvccKey::TenActive(self.state.version).encode()?)
+    // 写写冲突检测：写入过的 key 有对当前事务不可见、或者版本号更大的已提交版本时，
+    // 提交会覆盖其他事务的修改，返回 Serialization 错误，所有隔离级别都检查
+    // 写入时已经检测过一次并返回 WriteConflict，正常情况下提交时不会再发现冲突
+    fn check_writes(&self, engine: &E) -> Result<()> {
+        let active_versions = Self::scan_active(engine)?;
+        let state = self.state.borrow();
+        let mut iter = engine.scan_prefix(MvccKeyPrefix::TxnWrite(self.version).encode()?);
+        while let Some((key, _)) = iter.next().transpose()? {
+            let raw_key = match MvccKey::decode(key.clone())? {
+                MvccKey::TxnWrite(_, raw_key) => raw_key,
+                _ => {
+                    return Err(Error::Internal(format!(
+                        "unexpected key: {:?}",
+                        String::from_utf8(key)
+                    )))
+                }
+            };
+            let from = MvccKey::Version(raw_key.clone(), 0).encode()?;
+            let to = MvccKey::Version(raw_key, u64::MAX).encode()?;
+            let mut versions = engine.scan(from..=to);
+            while let Some((key, _)) = versions.next().transpose()? {
+                match MvccKey::decode(key.clone())? {
+                    MvccKey::Version(_, version) => {
+                        let committed =
+                            version != self.version && !active_versions.contains(&version);
+                        if committed && (!state.is_visible(version) || version > self.version) {
+                            return Err(Error::Serialization);
+                        }
+                    }
+                    _ => {
+                        return Err(Error::Internal(format!(
+                            "unexpected key: {:?}",
+                            String::from_utf8(key)
+                        )))
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 读写冲突检测：读取过的范围中有对当前事务不可见、并且已经提交的版本时，
+    // 说明快照之后其他事务修改了读取的数据，当前事务不能排在它之后提交，返回 Serialization 错误
+    // 还没有提交的事务会排在当前事务之后，由它自己提交时检查
+    fn check_reads(&self, engine: &E) -> Result<()> {
+        if self.reads.borrow().is_empty() {
+            return Ok(());
+        }
+        let active_versions = Self::scan_active(engine)?;
+        let state = self.state.borrow();
+        for range in self.reads.borrow().iter() {
+            let mut iter = engine.scan(range.clone());
+            while let Some((key, _)) = iter.next().transpose()? {
+                match MvccKey::decode(key.clone())? {
+                    MvccKey::Version(_, version) => {
+                        if !state.is_visible(version) && !active_versions.contains(&version) {
+                            return Err(Error::Serialization);
+                        }
+                    }
+                    _ => {
+                        return Err(Error::Internal(format!(
+                            "unexpected key: {:?}",
+                            String::from_utf8(key)
+                        )))
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // SERIALIZABLE 事务记录读取的范围
+    fn record_read(&self, range: KeyRange) {
+        if self.isolation == IsolationLevel::Serializable {
+            self.reads.borrow_mut().push(range);
+        }
     }
 
     // 回滚事务
     pub fn rollback(&self) -> Result<()> {
+        tracing::trace!(version = self.version, "rollback");
+        if self.is_read_only() {
+            return Ok(());
+        }
         // 获取存储引擎
-        let mut engine = self.engine.lock()?;
+        let mut engine = self.engine.write()?;
+        Self::rollback_version(&mut engine, self.version)
+    }
 
-        let mut delete_keys = Vec::new();
+    // 删除事务写入的数据，并从活跃事务列表中删除
+    fn rollback_version(engine: &mut E, version: Version) -> Result<()> {
+        let mut batch = WriteBatch::new();
         // 找到这个当前事务的 TxnWrite 信息
-        let mut iter = engine.scan_prefix(MvccKeyPrefix::TxnWrite(self.state.version).encode()?);
+        let mut iter = engine.scan_prefix(MvccKeyPrefix::TxnWrite(version).encode()?);
         while let Some((key, _)) = iter.next().transpose()? {
             match MvccKey::decode(key.clone())? {
                 MvccKey::TxnWrite(_, raw_key) => {
-                    delete_keys.push(MvccKey::Version(raw_key, self.state.version).encode()?);
+                    batch.delete(MvccKey::Version(raw_key, version).encode()?);
                 }
                 _ => {
                     return Err(Error::Internal(format!(
@@ -176,16 +682,66 @@ impl<E: Engine> MvccTransaction<E> {
                     )))
                 }
             }
-            delete_keys.push(key);
+            batch.delete(key);
         }
         drop(iter);
 
-        for key in delete_keys.into_iter() {
-            engine.delete(key)?;
+        // 从活跃事务列表中删除
+        batch.delete(MvccKey::TenActive(version).encode()?);
+        engine.write_batch(batch)
+    }
+
+    // 创建保存点，同名的保存点已经存在时，之后按照名字查找的是新创建的这个
+    pub fn savepoint(&self, name: String) -> Result<()> {
+        self.savepoints.borrow_mut().push(Savepoint {
+            name,
+            undo: HashMap::new(),
+        });
+        Ok(())
+    }
+
+    // 撤销保存点之后的写入，保存点本身保留，之后的保存点被删除
+    pub fn rollback_to_savepoint(&self, name: &str) -> Result<()> {
+        let mut savepoints = self.savepoints.borrow_mut();
+        let pos = Self::find_savepoint(&savepoints, name)?;
+        let mut engine = self.engine.write()?;
+        for savepoint in savepoints[pos..].iter_mut().rev() {
+            for (key, value) in savepoint.undo.drain() {
+                let version_key = MvccKey::Version(key.clone(), self.version).encode()?;
+                match value {
+                    Some(value) => engine.set(version_key, value)?,
+                    None => {
+                        engine.delete(version_key)?;
+                        engine.delete(MvccKey::TxnWrite(self.version, key).encode()?)?;
+                    }
+                }
+            }
         }
+        savepoints.truncate(pos + 1);
+        Ok(())
+    }
 
-        // 从活跃事务列表中删除
-        engine.delete(MvccKey::TenActive(self.state.version).encode()?)
+    // 删除保存点以及之后的保存点，写入依然保留，由前一个保存点负责撤销
+    pub fn release_savepoint(&self, name: &str) -> Result<()> {
+        let mut savepoints = self.savepoints.borrow_mut();
+        let pos = Self::find_savepoint(&savepoints, name)?;
+        let released = savepoints.split_off(pos);
+        if let Some(prev) = savepoints.last_mut() {
+            // 同一个 key 保留更早的数据
+            for savepoint in released {
+                for (key, value) in savepoint.undo {
+                    prev.undo.entry(key).or_insert(value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn find_savepoint(savepoints: &[Savepoint], name: &str) -> Result<usize> {
+        savepoints
+            .iter()
+            .rposition(|s| s.name == name)
+            .ok_or_else(|| Error::NotFound(format!("savepoint {} does not exist", name)))
     }
 
     pub fn set(&self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
@@ -197,20 +753,32 @@ impl<E: Engine> MvccTransaction<E> {
     }
 
     pub fn get(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        let span = tracing::trace_span!("storage.get", key_bytes = key.len(), value_bytes = tracing::field::Empty);
+        let _enter = span.enter();
+        self.record_io(1, 0);
         // 获取存储引擎
-        let mut engine = self.engine.lock()?;
+        let engine = self.engine.read()?;
 
+        self.record_read((
+            Bound::Included(MvccKey::Version(key.clone(), 0).encode()?),
+            Bound::Included(MvccKey::Version(key.clone(), u64::MAX).encode()?),
+        ));
         // version: 9
         // 扫描的 version 的范围应该是 0-8
+        let state = self.state.borrow();
         let from = MvccKey::Version(key.clone(), 0).encode()?;
-        let to = MvccKey::Version(key.clone(), self.state.version).encode()?;
+        let to = MvccKey::Version(key.clone(), state.snapshot).encode()?;
         let mut iter = engine.scan(from..=to).rev();
         // 从最新的版本开始读取，找到一个最新的可见的版本
         while let Some((key, value)) = iter.next().transpose()? {
             match MvccKey::decode(key.clone())? {
                 MvccKey::Version(_, version) => {
-                    if self.state.is_visible(version) {
-                        return Ok(bincode::deserialize(&value)?);
+                    if state.is_visible(version) {
+                        let value: Option<Vec<u8>> = bincode::deserialize(&value)?;
+                        if let Some(value) = &value {
+                            span.record("value_bytes", value.len());
+                        }
+                        return Ok(value);
                     }
                 }
                 _ => {
@@ -225,25 +793,71 @@ impl<E: Engine> MvccTransaction<E> {
     }
 
     pub fn scan_prefix(&self, prefix: Vec<u8>) -> Result<Vec<ScanResult>> {
-        let mut eng = self.engine.lock()?;
-        let mut enc_prefix = MvccKeyPrefix::Version(prefix).encode()?;
-        // 原始值           编码后
-        // 97 98 99     -> 97 98 99 0 0
-        // 前缀原始值        前缀编码后
-        // 97 98        -> 97 98 0 0         -> 97 98
-        // 去掉最后的 [0, 0] 后缀
-        enc_prefix.truncate(enc_prefix.len() - 2);
-
-        let mut iter = eng.scan_prefix(enc_prefix);
-        let mut results = BTreeMap::new();
-        while let Some((key, value)) = iter.next().transpose()? {
+        self.scan_prefix_limit(prefix, usize::MAX)
+    }
+
+    // 前缀扫描，最多返回 limit 个 key，读取到足够的 key 之后不再继续扫描存储引擎
+    pub fn scan_prefix_limit(&self, prefix: Vec<u8>, limit: usize) -> Result<Vec<ScanResult>> {
+        let span = tracing::trace_span!(
+            "storage.scan_prefix",
+            keys = tracing::field::Empty,
+            bytes = tracing::field::Empty
+        );
+        let _enter = span.enter();
+        let engine = self.engine.read()?;
+        let results = self.scan_visible(&engine, version_prefix_range(prefix)?, limit)?;
+        self.record_io(results.len(), 0);
+
+        span.record("keys", results.len());
+        span.record("bytes", results.iter().map(|(k, v)| k.len() + v.len()).sum::<usize>());
+        Ok(results
+            .into_iter()
+            .map(|(key, value)| ScanResult { key, value })
+            .collect())
+    }
+
+    // 范围扫描，返回 key 在 start 和 end 之间的当前事务可见的最新数据，按照 key 排序
+    pub fn scan_range(&self, start: Bound<Vec<u8>>, end: Bound<Vec<u8>>) -> Result<Vec<ScanResult>> {
+        let span = tracing::trace_span!("storage.scan_range", keys = tracing::field::Empty);
+        let _enter = span.enter();
+        let range = (version_bound(start, true)?, version_bound(end, false)?);
+        // 存储引擎中的 BTreeMap 在起点大于终点时会 panic，空范围直接返回
+        match &range {
+            (
+                Bound::Included(s) | Bound::Excluded(s),
+                Bound::Included(e) | Bound::Excluded(e),
+            ) if s > e || (s == e && !matches!(range, (Bound::Included(_), Bound::Included(_)))) => {
+                return Ok(Vec::new())
+            }
+            _ => {}
+        }
+        let engine = self.engine.read()?;
+        let results = self.scan_visible(&engine, range, usize::MAX)?;
+        self.record_io(results.len(), 0);
+
+        span.record("keys", results.len());
+        Ok(results
+            .into_iter()
+            .map(|(key, value)| ScanResult { key, value })
+            .collect())
+    }
+
+    // 删除前缀匹配的所有 key，返回删除的 key 数量
+    // 在一次加锁中扫描出当前事务可见的 key，并写入删除标记，不需要逐个 key 加锁
+    // 只扫描 key 找到每个 key 可见的最新版本，历史版本的 value 不读取，
+    // 最新版本只用来判断 key 是否已经被删除
+    pub fn delete_prefix(&self, prefix: Vec<u8>) -> Result<usize> {
+        let span = tracing::trace_span!("storage.delete_prefix", keys = tracing::field::Empty);
+        let _enter = span.enter();
+        let mut engine = self.engine.write()?;
+        let range = version_prefix_range(prefix)?;
+        self.record_read(range.clone());
+        let mut latest = BTreeMap::new();
+        for key in engine.scan_keys(range)? {
             match MvccKey::decode(key.clone())? {
                 MvccKey::Version(raw_key, version) => {
-                    if self.state.is_visible(version) {
-                        match bincode::deserialize(&value)? {
-                            Some(raw_value) => results.insert(raw_key, raw_value),
-                            None => results.remove(&raw_key),
-                        };
+                    if self.state.borrow().is_visible(version) {
+                        latest.insert(raw_key, key);
                     }
                 }
                 _ => {
@@ -254,30 +868,88 @@ impl<E: Engine> MvccTransaction<E> {
                 }
             }
         }
+        let mut keys = Vec::with_capacity(latest.len());
+        for (raw_key, key) in latest {
+            let value: Option<Vec<u8>> = match engine.get(key)? {
+                Some(value) => bincode::deserialize(&value)?,
+                None => None,
+            };
+            if value.is_some() {
+                keys.push(raw_key);
+            }
+        }
+        for key in keys.iter() {
+            self.write_locked(&mut engine, key.clone(), None)?;
+        }
+        span.record("keys", keys.len());
+        Ok(keys.len())
+    }
 
-        Ok(results
-            .into_iter()
-            .map(|(key, value)| ScanResult { key, value })
-            .collect())
+    // 扫描编码后的 key 范围中当前事务可见的最新数据，最多返回 limit 个 key
+    // 同一个 key 的各个版本相邻，已经有 limit 个 key 时遇到新的 key 就可以停止
+    fn scan_visible(
+        &self,
+        engine: &E,
+        range: KeyRange,
+        limit: usize,
+    ) -> Result<BTreeMap<Vec<u8>, Vec<u8>>> {
+        self.record_read(range.clone());
+        Ok(scan_visible(engine, &self.state.borrow(), range, limit)?.0)
+    }
+
+    // 分批扫描前缀匹配的 key，最多返回 limit 个，返回的迭代器不借用事务，遍历时才读取存储引擎
+    // 迭代器使用创建时的快照，之后当前事务写入的数据在还没有扫描到的范围中时同样可见
+    pub fn scan_prefix_iter(&self, prefix: Vec<u8>, limit: usize) -> Result<ScanIterator<E>> {
+        let range = version_prefix_range(prefix)?;
+        self.record_read(range.clone());
+        Ok(ScanIterator {
+            engine: self.engine.clone(),
+            state: self.state.borrow().clone(),
+            io: self.io.clone(),
+            range: Some(range),
+            limit,
+            batch: BTreeMap::new().into_iter(),
+        })
     }
 
     // 更新/删除数据
     fn write_inner(&self, key: Vec<u8>, value: Option<Vec<u8>>) -> Result<()> {
+        let _enter = tracing::trace_span!(
+            "storage.write",
+            key_bytes = key.len(),
+            value_bytes = value.as_ref().map_or(0, |v| v.len())
+        )
+        .entered();
         // 获取存储引擎
-        let mut engine = self.engine.lock()?;
+        let mut engine = self.engine.write()?;
+        self.write_locked(&mut engine, key, value)
+    }
 
+    // 已经持有存储引擎的锁时写入数据
+    fn write_locked(
+        &self,
+        engine: &mut E,
+        key: Vec<u8>,
+        value: Option<Vec<u8>>,
+    ) -> Result<()> {
+        if self.is_read_only() {
+            return Err(Error::ReadOnly(
+                "cannot write in a read-only transaction".into(),
+            ));
+        }
         // 检测冲突
         //  3 4 5
         //  6
         //  key1-3 key2-4 key3-5
+        let state = self.state.borrow();
         let from = MvccKey::Version(
             key.clone(),
-            self.state
+            state
                 .active_versions
                 .iter()
                 .min()
                 .copied()
-                .unwrap_or(self.state.version + 1),
+                .unwrap_or(self.version + 1),
         ).encode()?;
 
         let to = MvccKey::Version(key.clone(), u64::MAX).encode()?;
@@ -291,7 +963,9 @@ impl<E: Engine> MvccTransaction<E> {
             match MvccKey::decode(k.clone())? {
                 MvccKey::Version(_, version) => {
                     // 检测这个 version 是否是可见的
-                    if !self.state.is_visible(version) {
+                    // READ COMMITTED 可以看到比当前事务版本号大的已提交数据，但是不能在这之上写入，
+                    // 否则当前事务写入的版本号更小，提交之后会被这个版本覆盖
+                    if !state.is_visible(version) || version > self.version {
                         return Err(Error::WriteConflict);
                     }
                 }
@@ -304,21 +978,28 @@ impl<E: Engine> MvccTransaction<E> {
             }
         }
 
-        // 记录这个 version 写入了哪些 key，用于回滚事务;
-        engine.set(
-            MvccKey::TxnWrite(self.state.version, key.clone()).encode()?,
-            vec![],
-        )?;
+        // 有保存点时，记录这个 key 在写入之前本事务写入的数据
+        if let Some(savepoint) = self.savepoints.borrow_mut().last_mut() {
+            if !savepoint.undo.contains_key(&key) {
+                let value = engine.get(MvccKey::Version(key.clone(), self.version).encode()?)?;
+                savepoint.undo.insert(key.clone(), value);
+            }
+        }
 
-        // 写入实际的 key value 数据
-        engine.set(
-            MvccKey::Version(key.clone(), self.state.version).encode()?, bincode::serialize(&value)?,
-        )?;
+        // 记录这个 version 写入了哪些 key，用于回滚事务，和实际的 key value 数据一起写入
+        let mut batch = WriteBatch::new();
+        batch.set(MvccKey::TxnWrite(self.version, key.clone()).encode()?, vec![]);
+        batch.set(
+            MvccKey::Version(key.clone(), self.version).encode()?,
+            bincode::serialize(&value)?,
+        );
+        engine.write_batch(batch)?;
+        self.record_io(0, 1);
         Ok(())
     }
 
     // 扫描获取当前活跃事务列表
-    fn scan_active(engine: &mut MutexGuard<E>) -> Result<HashSet<Version>> {
+    fn scan_active(engine: &E) -> Result<HashSet<Version>> {
         let mut active_versions = HashSet::new();
         let mut iter = engine.scan_prefix(MvccKeyPrefix::TenActive.encode()?);
         while let Some((key, _)) = iter.next().transpose()? {
@@ -338,20 +1019,142 @@ impl<E: Engine> MvccTransaction<E> {
     }
 }
 
+// 可见的最新数据，以及扫描停止的位置
+type VisibleBatch = (BTreeMap<Vec<u8>, Vec<u8>>, Option<Vec<u8>>);
+
+// 扫描 range 中 state 可见的最新数据，读取到 limit 个 key 之后停止
+// 同时返回停止的位置，也就是下一个 key 的第一个版本，扫描完整个范围时为空
+fn scan_visible<E: Engine>(
+    engine: &E,
+    state: &TransactionState,
+    range: KeyRange,
+    limit: usize,
+) -> Result<VisibleBatch> {
+    let mut iter = engine.scan(range);
+    let mut results: BTreeMap<Vec<u8>, Vec<u8>> = BTreeMap::new();
+    while let Some((key, value)) = iter.next().transpose()? {
+        match MvccKey::decode(key.clone())? {
+            MvccKey::Version(raw_key, version) => {
+                if results.len() >= limit && !results.contains_key(&raw_key) {
+                    return Ok((results, Some(key)));
+                }
+                if state.is_visible(version) {
+                    match bincode::deserialize(&value)? {
+                        Some(raw_value) => results.insert(raw_key, raw_value),
+                        None => results.remove(&raw_key),
+                    };
+                }
+            }
+            _ => {
+                return Err(Error::Internal(format!(
+                    "Unexepected key {:?}",
+                    String::from_utf8(key)
+                )))
+            }
+        }
+    }
+    Ok((results, None))
+}
+
+// 分批扫描的迭代器，每一批在存储引擎的读锁中最多读取 SCAN_BATCH_SIZE 个 key，
+// 下一批从上一批停止的位置继续，两批之间不持有锁，其他事务可以写入
+pub struct ScanIterator<E: Engine> {
+    engine: Arc<RwLock<E>>,
+    state: TransactionState,
+    io: Arc<IoCounters>,
+    // 还没有扫描的范围，扫描结束时为空
+    range: Option<KeyRange>,
+    // 最多还可以返回的 key 数量
+    limit: usize,
+    batch: std::collections::btree_map::IntoIter<Vec<u8>, Vec<u8>>,
+}
+
+impl<E: Engine> ScanIterator<E> {
+    fn next_batch(&mut self, range: KeyRange) -> Result<()> {
+        let span = tracing::trace_span!(
+            "storage.scan_batch",
+            keys = tracing::field::Empty,
+            bytes = tracing::field::Empty
+        );
+        let _enter = span.enter();
+        let engine = self.engine.read()?;
+        let size = self.limit.min(SCAN_BATCH_SIZE);
+        let (results, next) = scan_visible(&*engine, &self.state, range.clone(), size)?;
+        self.io.record(results.len(), 0);
+        span.record("keys", results.len());
+        span.record("bytes", results.iter().map(|(k, v)| k.len() + v.len()).sum::<usize>());
+        self.limit -= results.len();
+        self.range = next.map(|start| (Bound::Included(start), range.1));
+        self.batch = results.into_iter();
+        Ok(())
+    }
+}
+
+impl<E: Engine> Iterator for ScanIterator<E> {
+    type Item = Result<ScanResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((key, value)) = self.batch.next() {
+                return Some(Ok(ScanResult { key, value }));
+            }
+            if self.limit == 0 {
+                return None;
+            }
+            let range = self.range.take()?;
+            if let Err(err) = self.next_batch(range) {
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct ScanResult {
     pub key: Vec<u8>,
     pub value: Vec<u8>,
 }
 
+// 原始 key 前缀对应的编码后的 key 范围，匹配前缀的 key 的所有版本
+fn version_prefix_range(prefix: Vec<u8>) -> Result<KeyRange> {
+    let mut enc_prefix = MvccKeyPrefix::Version(prefix).encode()?;
+    // 原始值           编码后
+    // 97 98 99     -> 97 98 99 0 0
+    // 前缀原始值        前缀编码后
+    // 97 98        -> 97 98 0 0         -> 97 98
+    // 去掉最后的 [0, 0] 后缀
+    enc_prefix.truncate(enc_prefix.len() - 2);
+    Ok(engine::prefix_range(enc_prefix))
+}
+
+// 原始 key 的边界转换为编码后的 key 的边界，边界上的 key 的所有版本一起包含或者排除
+// 原始 key 编码为 key 0 0 version，比它大的 key 的编码在相同位置是 0 255 或者非 0 字节，
+// 所以 key 0 1 位于 key 的所有版本和比它大的 key 之间
+fn version_bound(bound: Bound<Vec<u8>>, lower: bool) -> Result<Bound<Vec<u8>>> {
+    let after = |key: Vec<u8>| -> Result<Vec<u8>> {
+        let mut enc = MvccKeyPrefix::Version(key).encode()?;
+        *enc.last_mut().unwrap() = 1;
+        Ok(enc)
+    };
+    Ok(match (bound, lower) {
+        (Bound::Included(key), true) => Bound::Included(MvccKeyPrefix::Version(key).encode()?),
+        (Bound::Excluded(key), true) => Bound::Included(after(key)?),
+        (Bound::Included(key), false) => Bound::Excluded(after(key)?),
+        (Bound::Excluded(key), false) => Bound::Excluded(MvccKeyPrefix::Version(key).encode()?),
+        (Bound::Unbounded, true) => version_prefix_range(Vec::new())?.0,
+        // Version 是最后一种 key，之后没有其他的 key
+        (Bound::Unbounded, false) => Bound::Unbounded,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
-        error::Result,
+        error::{Error, Result},
         storage::{disk::DiskEngine, engine::Engine, memory::MemoryEngine},
     };
 
-    use super::Mvcc;
+    use super::{IsolationLevel, Mvcc, MvccKey, SCAN_BATCH_SIZE};
 
     // 1. Get
     fn get(eng: impl Engine) -> Result<()> {
@@ -571,6 +1374,57 @@ mod tests {
         Ok(())
     }
 
+    // 范围扫描，边界上的 key 有多个版本，以及 key 中包含 0 字节的情况
+    fn scan_range(eng: impl Engine) -> Result<()> {
+        use std::ops::Bound;
+        let mvcc = Mvcc::new(eng);
+        let tx = mvcc.begin()?;
+        for key in [&b"a"[..], b"ab", b"ab\0", b"ab\0c", b"abc", b"b"] {
+            tx.set(key.to_vec(), b"v1".to_vec())?;
+        }
+        tx.commit()?;
+        let tx = mvcc.begin()?;
+        tx.set(b"ab".to_vec(), b"v2".to_vec())?;
+        tx.delete(b"abc".to_vec())?;
+        tx.commit()?;
+
+        let tx = mvcc.begin()?;
+        let keys = |start, end| -> Result<Vec<Vec<u8>>> {
+            Ok(tx.scan_range(start, end)?.into_iter().map(|r| r.key).collect())
+        };
+        let ab = b"ab".to_vec();
+        let b = b"b".to_vec();
+        assert_eq!(
+            keys(Bound::Included(ab.clone()), Bound::Excluded(b.clone()))?,
+            vec![b"ab".to_vec(), b"ab\0".to_vec(), b"ab\0c".to_vec()]
+        );
+        assert_eq!(
+            keys(Bound::Excluded(ab.clone()), Bound::Included(b.clone()))?,
+            vec![b"ab\0".to_vec(), b"ab\0c".to_vec(), b"b".to_vec()]
+        );
+        assert_eq!(
+            keys(Bound::Unbounded, Bound::Included(ab.clone()))?,
+            vec![b"a".to_vec(), b"ab".to_vec()]
+        );
+        assert_eq!(
+            keys(Bound::Excluded(b"ab\0".to_vec()), Bound::Unbounded)?,
+            vec![b"ab\0c".to_vec(), b"b".to_vec()]
+        );
+        assert_eq!(tx.scan_range(Bound::Included(ab.clone()), Bound::Included(ab.clone()))?[0].value, b"v2");
+        assert!(keys(Bound::Included(b.clone()), Bound::Excluded(ab.clone()))?.is_empty());
+        assert!(keys(Bound::Excluded(b.clone()), Bound::Excluded(b))?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_range() -> Result<()> {
+        scan_range(MemoryEngine::new())?;
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        scan_range(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
     // 5. set
     fn set(eng: impl Engine) -> Result<()> {
         let mvcc = Mvcc::new(eng);
@@ -731,6 +1585,80 @@ mod tests {
         Ok(())
     }
 
+    // 提交时的写写冲突检测
+    fn commit_conflict(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let tx1 = mvcc.begin()?;
+        tx1.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx1.set(b"key2".to_vec(), b"val2".to_vec())?;
+        // 绕过写入时的检测，直接写入一个其他事务已经提交的更新的版本
+        let version = MvccKey::Version(b"key1".to_vec(), tx1.version() + 1).encode()?;
+        let value = bincode::serialize(&Some(b"val1-1".to_vec()))?;
+        mvcc.engine.write()?.set(version, value)?;
+        assert_eq!(tx1.commit(), Err(Error::Serialization));
+
+        // 提交失败的事务已经回滚
+        let tx2 = mvcc.begin()?;
+        assert_eq!(tx2.get(b"key1".to_vec())?, Some(b"val1-1".to_vec()));
+        assert_eq!(tx2.get(b"key2".to_vec())?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit_conflict() -> Result<()> {
+        commit_conflict(MemoryEngine::new())?;
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        commit_conflict(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 前缀删除
+    fn delete_prefix(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let tx = mvcc.begin()?;
+        tx.set(b"aa".to_vec(), b"val1".to_vec())?;
+        tx.set(b"ab".to_vec(), b"val2".to_vec())?;
+        tx.set(b"ac".to_vec(), b"val3".to_vec())?;
+        tx.set(b"b".to_vec(), b"val4".to_vec())?;
+        tx.delete(b"ac".to_vec())?;
+        tx.commit()?;
+
+        // 已经删除的 key 不计数，回滚之后数据恢复
+        let tx1 = mvcc.begin()?;
+        assert_eq!(tx1.delete_prefix(b"a".to_vec())?, 2);
+        assert_eq!(tx1.scan_prefix(b"a".to_vec())?, vec![]);
+        tx1.rollback()?;
+
+        let tx2 = mvcc.begin()?;
+        let tx3 = mvcc.begin()?;
+        assert_eq!(tx2.delete_prefix(b"a".to_vec())?, 2);
+        // 其他事务修改了其中的 key 时冲突
+        assert_eq!(tx3.delete_prefix(b"a".to_vec()), Err(super::Error::WriteConflict));
+        assert_eq!(tx3.get(b"aa".to_vec())?, Some(b"val1".to_vec()));
+        tx2.commit()?;
+        tx3.rollback()?;
+
+        let tx4 = mvcc.begin()?;
+        assert_eq!(
+            tx4.scan_prefix(vec![])?,
+            vec![super::ScanResult {
+                key: b"b".to_vec(),
+                value: b"val4".to_vec()
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_prefix() -> Result<()> {
+        delete_prefix(MemoryEngine::new())?;
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        delete_prefix(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
     // 9. dirty read
     fn dirty_read(eng: impl Engine) -> Result<()> {
         let mvcc = Mvcc::new(eng);
@@ -883,4 +1811,373 @@ mod tests {
         std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
+
+    // 13. vacuum
+    fn vacuum(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let tx = mvcc.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.set(b"key2".to_vec(), b"val2".to_vec())?;
+        tx.set(b"key3".to_vec(), b"val3".to_vec())?;
+        tx.commit()?;
+
+        let tx1 = mvcc.begin()?;
+        tx1.set(b"key1".to_vec(), b"val4".to_vec())?;
+        tx1.delete(b"key2".to_vec())?;
+        tx1.commit()?;
+
+        // 存在活跃事务时不清理旧版本
+        let tx2 = mvcc.begin()?;
+        assert_eq!(mvcc.vacuum()?, 0);
+        assert_eq!(tx2.get(b"key1".to_vec())?, Some(b"val4".to_vec()));
+        tx2.commit()?;
+
+        // key1 的旧版本，key2 的两个版本被清理
+        assert_eq!(mvcc.vacuum()?, 3);
+        assert_eq!(mvcc.vacuum()?, 0);
+
+        let tx3 = mvcc.begin()?;
+        let iter = tx3.scan_prefix(b"key".to_vec())?;
+        assert_eq!(
+            iter,
+            vec![
+                super::ScanResult {
+                    key: b"key1".to_vec(),
+                    value: b"val4".to_vec(),
+                },
+                super::ScanResult {
+                    key: b"key3".to_vec(),
+                    value: b"val3".to_vec(),
+                },
+            ]
+        );
+        tx3.commit()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_vacuum() -> Result<()> {
+        vacuum(MemoryEngine::new())?;
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        vacuum(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_io_stats() -> Result<()> {
+        let mvcc = Mvcc::new(MemoryEngine::new());
+        let tx = mvcc.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.set(b"key2".to_vec(), b"val2".to_vec())?;
+        tx.get(b"key1".to_vec())?;
+        tx.get(b"key3".to_vec())?;
+        tx.scan_prefix(b"key".to_vec())?;
+        assert_eq!(
+            tx.io_stats(),
+            super::IoStats {
+                keys_read: 4,
+                keys_written: 2,
+            }
+        );
+        tx.delete_prefix(b"key".to_vec())?;
+        assert_eq!(tx.io_stats().keys_written, 4);
+        tx.commit()?;
+        Ok(())
+    }
+
+    // 保存点
+    fn savepoint(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let tx = mvcc.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.commit()?;
+
+        let tx = mvcc.begin()?;
+        tx.set(b"key2".to_vec(), b"val2".to_vec())?;
+        tx.savepoint("a".into())?;
+        tx.set(b"key1".to_vec(), b"val1-1".to_vec())?;
+        tx.set(b"key2".to_vec(), b"val2-1".to_vec())?;
+        tx.savepoint("b".into())?;
+        tx.delete(b"key1".to_vec())?;
+        tx.set(b"key3".to_vec(), b"val3".to_vec())?;
+        assert_eq!(tx.get(b"key1".to_vec())?, None);
+
+        // 回滚到 b 只撤销 b 之后的写入，b 依然可以使用
+        tx.rollback_to_savepoint("b")?;
+        assert_eq!(tx.get(b"key1".to_vec())?, Some(b"val1-1".to_vec()));
+        assert_eq!(tx.get(b"key3".to_vec())?, None);
+        tx.set(b"key3".to_vec(), b"val3-1".to_vec())?;
+        tx.rollback_to_savepoint("b")?;
+        assert_eq!(tx.get(b"key3".to_vec())?, None);
+
+        // 释放 b 之后，b 之后的写入由 a 负责撤销
+        tx.set(b"key4".to_vec(), b"val4".to_vec())?;
+        tx.release_savepoint("b")?;
+        assert!(tx.rollback_to_savepoint("b").is_err());
+        tx.rollback_to_savepoint("a")?;
+        assert_eq!(tx.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        assert_eq!(tx.get(b"key2".to_vec())?, Some(b"val2".to_vec()));
+        assert_eq!(tx.get(b"key4".to_vec())?, None);
+        // 撤销的 key 不再参与事务回滚
+        assert_eq!(tx.status()?.active_txns, vec![(tx.version(), 1)]);
+        tx.commit()?;
+
+        let tx = mvcc.begin()?;
+        assert_eq!(
+            tx.scan_prefix(b"key".to_vec())?
+                .into_iter()
+                .map(|r| (r.key, r.value))
+                .collect::<Vec<_>>(),
+            vec![
+                (b"key1".to_vec(), b"val1".to_vec()),
+                (b"key2".to_vec(), b"val2".to_vec()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_savepoint() -> Result<()> {
+        savepoint(MemoryEngine::new())?;
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        savepoint(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 14. isolation level
+    fn isolation_level(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let tx = mvcc.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.set(b"key2".to_vec(), b"val2".to_vec())?;
+        tx.commit()?;
+
+        // READ COMMITTED 每条语句可以读到之前提交的数据，但是读不到未提交的数据
+        let tx1 = mvcc.begin_with(IsolationLevel::ReadCommitted)?;
+        let tx2 = mvcc.begin_with(IsolationLevel::RepeatableRead)?;
+        let tx3 = mvcc.begin()?;
+        tx3.set(b"key1".to_vec(), b"val1-1".to_vec())?;
+        tx1.begin_statement()?;
+        assert_eq!(tx1.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        tx3.commit()?;
+        assert_eq!(tx1.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        tx1.begin_statement()?;
+        assert_eq!(tx1.get(b"key1".to_vec())?, Some(b"val1-1".to_vec()));
+        // 开启之后提交的版本号更大，不能在这之上修改
+        assert_eq!(
+            tx1.set(b"key1".to_vec(), b"val1-2".to_vec()),
+            Err(super::Error::WriteConflict)
+        );
+        tx1.set(b"key2".to_vec(), b"val2-1".to_vec())?;
+        assert_eq!(tx1.get(b"key2".to_vec())?, Some(b"val2-1".to_vec()));
+        tx1.commit()?;
+
+        // REPEATABLE READ 始终使用开启时的快照
+        tx2.begin_statement()?;
+        assert_eq!(tx2.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        tx2.rollback()?;
+
+        // SERIALIZABLE 防止写偏斜：两个事务各自读取对方写入的 key
+        let tx4 = mvcc.begin_with(IsolationLevel::Serializable)?;
+        let tx5 = mvcc.begin_with(IsolationLevel::Serializable)?;
+        assert_eq!(tx4.get(b"key1".to_vec())?, Some(b"val1-1".to_vec()));
+        assert_eq!(tx5.scan_prefix(b"key2".to_vec())?.len(), 1);
+        tx4.set(b"key2".to_vec(), b"val2-2".to_vec())?;
+        tx5.set(b"key1".to_vec(), b"val1-2".to_vec())?;
+        tx4.commit()?;
+        assert_eq!(tx5.commit(), Err(super::Error::Serialization));
+
+        // 提交失败的事务已经回滚
+        let tx6 = mvcc.begin()?;
+        assert_eq!(tx6.get(b"key1".to_vec())?, Some(b"val1-1".to_vec()));
+        assert_eq!(tx6.get(b"key2".to_vec())?, Some(b"val2-2".to_vec()));
+        // 同样的情况 REPEATABLE READ 可以提交
+        let tx7 = mvcc.begin()?;
+        assert_eq!(tx6.get(b"key1".to_vec())?, Some(b"val1-1".to_vec()));
+        assert_eq!(tx7.get(b"key2".to_vec())?, Some(b"val2-2".to_vec()));
+        tx6.set(b"key2".to_vec(), b"val2-3".to_vec())?;
+        tx7.set(b"key1".to_vec(), b"val1-3".to_vec())?;
+        tx6.commit()?;
+        tx7.commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_isolation_level() -> Result<()> {
+        isolation_level(MemoryEngine::new())?;
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        isolation_level(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 15. read only
+    fn read_only(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let tx = mvcc.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.commit()?;
+        let v1 = tx.version();
+
+        let tx1 = mvcc.begin()?;
+        tx1.set(b"key1".to_vec(), b"val1-1".to_vec())?;
+        tx1.set(b"key2".to_vec(), b"val2".to_vec())?;
+
+        // 只读事务不分配版本号，看不到还没有提交的数据
+        let ro = mvcc.begin_read_only(None)?;
+        assert_eq!(ro.version(), tx1.version());
+        assert_eq!(ro.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        assert_eq!(
+            ro.set(b"key1".to_vec(), b"val1-2".to_vec()),
+            Err(super::Error::ReadOnly("cannot write in a read-only transaction".into()))
+        );
+        tx1.commit()?;
+        assert_eq!(ro.get(b"key2".to_vec())?, None);
+        ro.commit()?;
+        drop(ro);
+        let tx2 = mvcc.begin()?;
+        assert_eq!(tx2.version(), tx1.version() + 1);
+        tx2.rollback()?;
+
+        // 读取历史版本
+        let ro = mvcc.begin_read_only(Some(v1))?;
+        assert_eq!(ro.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        assert_eq!(ro.scan_prefix(b"key".to_vec())?.len(), 1);
+        let ro1 = mvcc.begin_read_only(Some(tx1.version()))?;
+        assert_eq!(ro1.get(b"key1".to_vec())?, Some(b"val1-1".to_vec()));
+        assert_eq!(ro1.scan_prefix(b"key".to_vec())?.len(), 2);
+        assert_eq!(
+            mvcc.begin_read_only(Some(100)).err(),
+            Some(super::Error::InvalidInput(format!(
+                "version 100 does not exist, the latest version is {}",
+                tx2.version()
+            )))
+        );
+
+        // 存在只读事务时 vacuum 不清理旧版本
+        assert_eq!(mvcc.vacuum()?, 0);
+        drop(ro);
+        drop(ro1);
+        assert_eq!(mvcc.vacuum()?, 1);
+        // 清理之后只能读取清理时最新的版本
+        assert_eq!(
+            mvcc.begin_read_only(Some(v1)).err(),
+            Some(super::Error::InvalidInput(format!(
+                "version {} has been removed by vacuum, the earliest version is {}",
+                v1,
+                tx2.version()
+            )))
+        );
+        let ro = mvcc.begin_read_only(None)?;
+        assert_eq!(ro.get(b"key1".to_vec())?, Some(b"val1-1".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_only() -> Result<()> {
+        read_only(MemoryEngine::new())?;
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        read_only(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 16. scan prefix limit
+    fn scan_prefix_limit(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let tx = mvcc.begin()?;
+        for key in [b"a1", b"a2", b"a3", b"a4", b"b1"] {
+            tx.set(key.to_vec(), b"val".to_vec())?;
+        }
+        tx.commit()?;
+        // a1 有多个版本，a2 被删除
+        let tx = mvcc.begin()?;
+        tx.set(b"a1".to_vec(), b"val1".to_vec())?;
+        tx.delete(b"a2".to_vec())?;
+        tx.commit()?;
+
+        let tx = mvcc.begin()?;
+        let keys = |limit| -> Result<Vec<Vec<u8>>> {
+            Ok(tx
+                .scan_prefix_limit(b"a".to_vec(), limit)?
+                .into_iter()
+                .map(|r| r.key)
+                .collect())
+        };
+        assert_eq!(keys(2)?, vec![b"a1".to_vec(), b"a3".to_vec()]);
+        assert_eq!(keys(0)?, Vec::<Vec<u8>>::new());
+        assert_eq!(keys(10)?.len(), 3);
+        let results = tx.scan_prefix_limit(b"a".to_vec(), 1)?;
+        assert_eq!(results[0].value, b"val1".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_prefix_limit() -> Result<()> {
+        scan_prefix_limit(MemoryEngine::new())?;
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        scan_prefix_limit(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    fn scan_prefix_iter(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let n = SCAN_BATCH_SIZE * 2 + 10;
+        let key = |i: usize| format!("a{:04}", i).into_bytes();
+        let tx = mvcc.begin()?;
+        for i in 0..n {
+            tx.set(key(i), b"val".to_vec())?;
+        }
+        tx.commit()?;
+        // 前两批中间的 key 有多个版本或者被删除
+        let tx = mvcc.begin()?;
+        for i in (0..n).step_by(3) {
+            tx.delete(key(i))?;
+        }
+        tx.set(key(SCAN_BATCH_SIZE), b"val1".to_vec())?;
+        tx.commit()?;
+
+        let tx = mvcc.begin()?;
+        let expect = (0..n).filter(|i| i % 3 != 0).map(key).collect::<Vec<_>>();
+        let mut iter = tx.scan_prefix_iter(b"a".to_vec(), usize::MAX)?;
+        let first = iter.next().unwrap()?;
+        assert_eq!(first.key, expect[0]);
+
+        // 两批之间其他事务提交的数据不可见
+        let other = mvcc.begin()?;
+        other.set(key(n), b"val".to_vec())?;
+        other.delete(expect[expect.len() - 1].clone())?;
+        other.commit()?;
+
+        let mut results = vec![first];
+        for result in iter {
+            results.push(result?);
+        }
+        assert_eq!(results.iter().map(|r| r.key.clone()).collect::<Vec<_>>(), expect);
+        let i = expect.iter().position(|k| *k == key(SCAN_BATCH_SIZE)).unwrap();
+        assert_eq!(results[i].value, b"val1".to_vec());
+        assert_eq!(tx.io_stats().keys_read, expect.len() as u64);
+
+        // 最多返回 limit 个 key
+        let keys = tx
+            .scan_prefix_iter(b"a".to_vec(), SCAN_BATCH_SIZE + 1)?
+            .map(|r| r.map(|r| r.key))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(keys, expect[..SCAN_BATCH_SIZE + 1].to_vec());
+        assert_eq!(tx.scan_prefix_iter(b"a".to_vec(), 0)?.count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_prefix_iter() -> Result<()> {
+        scan_prefix_iter(MemoryEngine::new())?;
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        scan_prefix_iter(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
 }
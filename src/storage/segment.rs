@@ -0,0 +1,665 @@
+use std::{
+    collections::{btree_map, hash_map, BTreeMap, HashMap},
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    thread::JoinHandle,
+};
+
+use fs4::FileExt;
+
+use crate::error::{Error, Result};
+
+use super::{
+    disk::{remove_file, Log, RecoveryReport, LOG_FILE_HEADER_SIZE, LOG_HEADER_SIZE},
+    engine::{prefix_range, Engine, EngineIterator, Status},
+};
+
+// 分段存储引擎，数据写入目录下的多个日志文件（段），每个段的记录格式和 DiskEngine 的日志相同
+//
+//   00000001.log  00000001.hint  00000002.log  00000002.hint  00000003.log
+//   |------------ 封存的段，不再修改 -----------|             活跃段
+//
+// 新的记录追加到段号最大的活跃段，活跃段超过 segment_size 之后封存，写入 hint 文件并创建新的段
+// hint 文件按照写入顺序保存段中每条记录的 key 和 value 的位置，打开时依次加载，不需要读取 value
+// 封存的段达到 merge_segments 个时在后台线程中合并：把仍然有效的数据重写到一个新的段，
+// 使用参与合并的最大段号，之后删除参与合并的段，写入和读取不需要等待合并完成
+
+// 段号、value 在段中的位置和长度
+type Location = (u32, u64, u32);
+
+// 一个段中按照写入顺序排列的记录，删除记录的位置为空
+type HintEntries = Vec<(Vec<u8>, Option<(u64, u32)>)>;
+
+// 合并之后每个 key 原来的位置以及在新段中的位置
+type MergeResult = Vec<(Vec<u8>, Location, (u64, u32))>;
+
+#[derive(Debug, Clone)]
+pub struct SegmentOptions {
+    // 活跃段超过这个大小之后封存，写入新的段
+    pub segment_size: u64,
+    // 封存的段达到这个数量时在后台合并
+    pub merge_segments: usize,
+}
+
+impl Default for SegmentOptions {
+    fn default() -> Self {
+        Self {
+            segment_size: 8 << 20,
+            merge_segments: 4,
+        }
+    }
+}
+
+pub struct SegmentEngine {
+    dir: PathBuf,
+    options: SegmentOptions,
+    keydir: BTreeMap<Vec<u8>, Location>,
+    // 所有的段，段号最大的是活跃段
+    segments: BTreeMap<u32, Log>,
+    active_id: u32,
+    active_size: u64,
+    // 活跃段中的记录，封存时写入 hint 文件
+    active_entries: HintEntries,
+    // 正在后台执行的合并
+    merge: Option<MergeTask>,
+    // 目录锁，保证同时只有一个引擎使用这个目录
+    lock: File,
+    compactions: u64,
+    // 打开时从没有 hint 文件的段中恢复的结果
+    recovery: RecoveryReport,
+    closed: bool,
+}
+
+struct MergeTask {
+    // 参与合并的最大段号，合并的结果使用这个段号
+    id: u32,
+    handle: JoinHandle<Result<MergeResult>>,
+}
+
+impl SegmentEngine {
+    pub fn new(dir: PathBuf) -> Result<Self> {
+        Self::with_options(dir, SegmentOptions::default())
+    }
+
+    pub fn with_options(dir: PathBuf, options: SegmentOptions) -> Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(dir.join("LOCK"))?;
+        lock.try_lock_exclusive()?;
+        recover_merge(&dir)?;
+
+        let mut ids = list_files(&dir)?
+            .into_iter()
+            .filter(|(_, ext)| ext == "log")
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        ids.sort();
+        let active_id = ids.last().copied().unwrap_or(1);
+        if ids.is_empty() {
+            ids.push(active_id);
+        }
+
+        let mut keydir = BTreeMap::new();
+        let mut segments = BTreeMap::new();
+        let mut active_entries = Vec::new();
+        let mut recovery = RecoveryReport::default();
+        for id in ids {
+            let mut log = Log::new(segment_path(&dir, id, "log"))?;
+            let entries = match load_hint(&dir, id, log.file.metadata()?.len())? {
+                Some(entries) => entries,
+                None => {
+                    let mut entries = Vec::new();
+                    log.replay(&mut recovery, |key, location| entries.push((key, location)))?;
+                    // 封存的段不再修改，补写 hint 文件，下次打开时直接加载
+                    if id != active_id {
+                        write_hint(&dir, id, log.file.metadata()?.len(), &entries)?;
+                    }
+                    entries
+                }
+            };
+            for (key, location) in entries.iter() {
+                match location {
+                    Some((offset, val_size)) => {
+                        keydir.insert(key.clone(), (id, *offset, *val_size))
+                    }
+                    None => keydir.remove(key),
+                };
+            }
+            if id == active_id {
+                // 活跃段继续写入之后 hint 失效
+                remove_file(&segment_path(&dir, id, "hint"))?;
+                active_entries = entries;
+            }
+            segments.insert(id, log);
+        }
+        let active_size = segments[&active_id].file.metadata()?.len();
+
+        Ok(Self {
+            dir,
+            options,
+            keydir,
+            segments,
+            active_id,
+            active_size,
+            active_entries,
+            merge: None,
+            lock,
+            compactions: 0,
+            recovery,
+            closed: false,
+        })
+    }
+
+    pub fn recovery(&self) -> &RecoveryReport {
+        &self.recovery
+    }
+
+    // 段的数量，包括活跃段
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    fn active(&mut self) -> &mut Log {
+        self.segments.get_mut(&self.active_id).unwrap()
+    }
+
+    fn write(&mut self, key: Vec<u8>, value: Option<Vec<u8>>) -> Result<()> {
+        let (offset, size) = self.active().write_entry(&key, value.as_deref())?;
+        self.active_size = offset + size as u64;
+        match value {
+            Some(value) => {
+                let val_size = value.len() as u32;
+                let val_offset = self.active_size - val_size as u64;
+                self.active_entries
+                    .push((key.clone(), Some((val_offset, val_size))));
+                self.keydir
+                    .insert(key, (self.active_id, val_offset, val_size));
+            }
+            None => {
+                self.keydir.remove(&key);
+                self.active_entries.push((key, None));
+            }
+        }
+
+        // 后台合并的失败不影响这次写入，封存的段保持不变
+        if let Err(err) = self.finish_merge(false) {
+            tracing::warn!(error = %err, "segment merge failed");
+        }
+        if self.active_size >= self.options.segment_size {
+            self.rotate()?;
+            if self.merge.is_none() && self.segments.len() > self.options.merge_segments.max(2) {
+                self.start_merge();
+            }
+        }
+        Ok(())
+    }
+
+    // 封存活跃段并写入 hint 文件，之后的写入使用新的段
+    fn rotate(&mut self) -> Result<()> {
+        self.active().file.sync_all()?;
+        write_hint(
+            &self.dir,
+            self.active_id,
+            self.active_size,
+            &self.active_entries,
+        )?;
+        self.active_entries.clear();
+        self.active_id += 1;
+        let log = Log::new(segment_path(&self.dir, self.active_id, "log"))?;
+        self.segments.insert(self.active_id, log);
+        self.active_size = 0;
+        Ok(())
+    }
+
+    // 段号不超过 id 的段中所有有效数据的位置
+    fn merge_entries(&self, id: u32) -> Vec<(Vec<u8>, Location)> {
+        self.keydir
+            .iter()
+            .filter(|(_, location)| location.0 <= id)
+            .map(|(key, location)| (key.clone(), *location))
+            .collect()
+    }
+
+    // 在后台合并所有封存的段，封存的段不再修改，合并时不需要持有引擎
+    fn start_merge(&mut self) {
+        let id = self.active_id - 1;
+        let entries = self.merge_entries(id);
+        let dir = self.dir.clone();
+        let handle = std::thread::spawn(move || merge_segments(&dir, id, entries));
+        self.merge = Some(MergeTask { id, handle });
+    }
+
+    // 后台合并完成时使用合并的结果，wait 为 true 时等待合并完成
+    fn finish_merge(&mut self, wait: bool) -> Result<()> {
+        match &self.merge {
+            Some(task) if wait || task.handle.is_finished() => {}
+            _ => return Ok(()),
+        }
+        let task = self.merge.take().unwrap();
+        let result = task
+            .handle
+            .join()
+            .unwrap_or_else(|_| Err(Error::Internal("segment merge panicked".into())));
+        match result {
+            Ok(result) => self.install_merge(task.id, result),
+            Err(err) => {
+                remove_file(&segment_path(&self.dir, task.id, "merge.tmp"))?;
+                Err(err)
+            }
+        }
+    }
+
+    fn install_merge(&mut self, id: u32, result: MergeResult) -> Result<()> {
+        // 合并期间被覆盖或者删除的 key 保留当前的位置，它们在更新的段中
+        let mut entries = Vec::with_capacity(result.len());
+        for (key, old, (offset, val_size)) in result {
+            if self.keydir.get(&key) == Some(&old) {
+                self.keydir.insert(key.clone(), (id, offset, val_size));
+            }
+            entries.push((key, Some((offset, val_size))));
+        }
+        // 先删除参与合并的段，再把合并的结果重命名为新的段，中途崩溃时打开引擎会完成剩下的步骤
+        let merged = self
+            .segments
+            .range(..=id)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for old in merged {
+            self.segments.remove(&old);
+            remove_segment(&self.dir, old)?;
+        }
+        std::fs::rename(
+            segment_path(&self.dir, id, "merge"),
+            segment_path(&self.dir, id, "log"),
+        )?;
+        let log = Log::new(segment_path(&self.dir, id, "log"))?;
+        write_hint(&self.dir, id, log.file.metadata()?.len(), &entries)?;
+        self.segments.insert(id, log);
+        self.compactions += 1;
+        Ok(())
+    }
+}
+
+// 没有显式关闭时，在 drop 时关闭
+impl Drop for SegmentEngine {
+    fn drop(&mut self) {
+        if let Err(err) = self.close() {
+            tracing::warn!(error = %err, "failed to close segment engine");
+        }
+    }
+}
+
+impl Engine for SegmentEngine {
+    type EngineIterator<'a> = SegmentEngineIterator<'a>;
+
+    fn set(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
+        self.write(key, Some(value))
+    }
+
+    fn get(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        match self.keydir.get(&key) {
+            Some((id, offset, val_size)) => {
+                let log = self
+                    .segments
+                    .get(id)
+                    .ok_or_else(|| missing_segment(*id))?;
+                Ok(Some(log.read_value(&key, *offset, *val_size)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn delete(&mut self, key: Vec<u8>) -> Result<()> {
+        self.write(key, None)
+    }
+
+    fn scan(&self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_> {
+        SegmentEngineIterator {
+            inner: self.keydir.range(range),
+            segments: &self.segments,
+        }
+    }
+
+    // key 都在内存索引中，不需要读取段文件
+    fn scan_keys(&self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Result<Vec<Vec<u8>>> {
+        Ok(self.keydir.range(range).map(|(key, _)| key.clone()).collect())
+    }
+
+    // 只需要从内存索引中找到 key，不需要读取 value
+    fn delete_prefix(&mut self, prefix: Vec<u8>) -> Result<usize> {
+        let keys = self
+            .keydir
+            .range(prefix_range(prefix))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        for key in keys.iter() {
+            self.delete(key.clone())?;
+        }
+        Ok(keys.len())
+    }
+
+    // 封存活跃段，同步合并所有的段
+    fn compact(&mut self) -> Result<()> {
+        self.active().check_open()?;
+        self.finish_merge(true)?;
+        if self.active_size > 0 {
+            self.rotate()?;
+        }
+        if self.segments.len() > 1 {
+            let id = self.active_id - 1;
+            let result = merge_segments(&self.dir, id, self.merge_entries(id))?;
+            self.install_merge(id, result)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let log = self.active();
+        log.check_open()?;
+        log.file.sync_all()?;
+        Ok(())
+    }
+
+    // 等待后台合并完成，写入活跃段的 hint 文件，释放文件锁
+    fn close(&mut self) -> Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        self.finish_merge(true)?;
+        self.flush()?;
+        write_hint(
+            &self.dir,
+            self.active_id,
+            self.active_size,
+            &self.active_entries,
+        )?;
+        for log in self.segments.values_mut() {
+            log.file.unlock()?;
+            log.closed = true;
+        }
+        self.lock.unlock()?;
+        self.closed = true;
+        Ok(())
+    }
+
+    fn status(&mut self) -> Result<Status> {
+        let mut disk_size = 0;
+        for log in self.segments.values() {
+            disk_size += log.file.metadata()?.len();
+        }
+        Ok(Status {
+            name: "segment".into(),
+            keys: self.keydir.len() as u64,
+            // 每个段文件的文件头也算作有效数据
+            size: self.segments.len() as u64 * LOG_FILE_HEADER_SIZE
+                + self
+                    .keydir
+                    .iter()
+                    .map(|(k, (_, _, val_size))| {
+                        (LOG_HEADER_SIZE + k.len() as u32 + *val_size) as u64
+                    })
+                    .sum::<u64>(),
+            disk_size,
+            compactions: self.compactions,
+        })
+    }
+}
+
+pub struct SegmentEngineIterator<'a> {
+    inner: btree_map::Range<'a, Vec<u8>, Location>,
+    segments: &'a BTreeMap<u32, Log>,
+}
+
+impl<'a> SegmentEngineIterator<'a> {
+    fn map(&mut self, item: (&Vec<u8>, &Location)) -> <Self as Iterator>::Item {
+        let (key, (id, offset, val_size)) = item;
+        let log = self
+            .segments
+            .get(id)
+            .ok_or_else(|| missing_segment(*id))?;
+        Ok((key.clone(), log.read_value(key, *offset, *val_size)?))
+    }
+}
+
+impl<'a> EngineIterator for SegmentEngineIterator<'a> {}
+
+impl<'a> Iterator for SegmentEngineIterator<'a> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|item| self.map(item))
+    }
+}
+
+impl<'a> DoubleEndedIterator for SegmentEngineIterator<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|item| self.map(item))
+    }
+}
+
+fn missing_segment(id: u32) -> Error {
+    Error::Internal(format!("segment {} not found", id))
+}
+
+fn segment_path(dir: &Path, id: u32, ext: &str) -> PathBuf {
+    dir.join(format!("{:08}.{}", id, ext))
+}
+
+// 目录中的段文件，文件名是 段号.扩展名
+fn list_files(dir: &Path) -> Result<Vec<(u32, String)>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let Some((id, ext)) = name.to_str().and_then(|name| name.split_once('.')) else {
+            continue;
+        };
+        if let Ok(id) = id.parse() {
+            files.push((id, ext.to_string()));
+        }
+    }
+    Ok(files)
+}
+
+fn remove_segment(dir: &Path, id: u32) -> Result<()> {
+    remove_file(&segment_path(dir, id, "log"))?;
+    remove_file(&segment_path(dir, id, "hint"))
+}
+
+// 合并的结果写完之后才会重命名为 .merge 文件，存在 .merge 文件说明合并已经完成，
+// 删除参与合并的段之后重命名为正式的段；没有写完的临时文件直接删除
+fn recover_merge(dir: &Path) -> Result<()> {
+    let files = list_files(dir)?;
+    for (id, ext) in files.iter() {
+        if ext.ends_with(".tmp") {
+            remove_file(&segment_path(dir, *id, ext))?;
+        }
+    }
+    let merged = files
+        .iter()
+        .filter(|(_, ext)| ext == "merge")
+        .map(|(id, _)| *id)
+        .max();
+    if let Some(id) = merged {
+        for (old, ext) in files.iter() {
+            if *old <= id && (ext == "log" || ext == "hint") {
+                remove_file(&segment_path(dir, *old, ext))?;
+            }
+        }
+        std::fs::rename(segment_path(dir, id, "merge"), segment_path(dir, id, "log"))?;
+    }
+    Ok(())
+}
+
+// 把 entries 中的数据依次写入新的段，完成之后重命名为 id.merge
+fn merge_segments(dir: &Path, id: u32, entries: Vec<(Vec<u8>, Location)>) -> Result<MergeResult> {
+    let tmp_path = segment_path(dir, id, "merge.tmp");
+    remove_file(&tmp_path)?;
+    let mut log = Log::new(tmp_path.clone())?;
+    let mut files: HashMap<u32, File> = HashMap::new();
+    let mut result = Vec::with_capacity(entries.len());
+    for (key, location) in entries {
+        let (segment, offset, val_size) = location;
+        let file = match files.entry(segment) {
+            hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            hash_map::Entry::Vacant(entry) => {
+                entry.insert(File::open(segment_path(dir, segment, "log"))?)
+            }
+        };
+        // 段中的 value 没有压缩，直接读取原始数据
+        let mut value = vec![0; val_size as usize];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut value)?;
+        let (new_offset, size) = log.write_entry(&key, Some(&value))?;
+        result.push((
+            key,
+            location,
+            (new_offset + size as u64 - val_size as u64, val_size),
+        ));
+    }
+    log.file.sync_all()?;
+    drop(log);
+    std::fs::rename(tmp_path, segment_path(dir, id, "merge"))?;
+    Ok(result)
+}
+
+// hint 文件保存了段的大小以及段中的记录，段的大小不一致时说明 hint 写入之后段又被修改过
+fn write_hint(dir: &Path, id: u32, size: u64, entries: &HintEntries) -> Result<()> {
+    // 先写入临时文件再重命名，避免留下不完整的 hint 文件
+    let tmp_path = segment_path(dir, id, "hint.tmp");
+    let mut writer = BufWriter::new(File::create(&tmp_path)?);
+    bincode::serialize_into(&mut writer, &(size, entries))?;
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+    std::fs::rename(tmp_path, segment_path(dir, id, "hint"))?;
+    Ok(())
+}
+
+fn load_hint(dir: &Path, id: u32, size: u64) -> Result<Option<HintEntries>> {
+    let file = match File::open(segment_path(dir, id, "hint")) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let hint: Option<(u64, HintEntries)> = bincode::deserialize_from(BufReader::new(file)).ok();
+    match hint {
+        Some((hint_size, entries)) if hint_size == size => Ok(Some(entries)),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::{error::Result, storage::engine::Engine};
+
+    use super::{list_files, merge_segments, SegmentEngine, SegmentOptions};
+
+    fn options() -> SegmentOptions {
+        SegmentOptions {
+            segment_size: 256,
+            merge_segments: 3,
+        }
+    }
+
+    fn scan(eng: &mut SegmentEngine) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        eng.scan(..).collect()
+    }
+
+    #[test]
+    fn test_segment_engine_merge() -> Result<()> {
+        let dir = tempfile::tempdir()?.into_path();
+        let mut eng = SegmentEngine::with_options(dir.clone(), options())?;
+        // 反复覆盖和删除，活跃段写满之后封存，封存的段达到 3 个时在后台合并
+        let mut expect = BTreeMap::new();
+        for i in 0..2000u32 {
+            let key = format!("key{}", i % 50).into_bytes();
+            if i % 7 == 0 {
+                eng.delete(key.clone())?;
+                expect.remove(&key);
+            } else {
+                let value = format!("value{}", i).into_bytes();
+                eng.set(key.clone(), value.clone())?;
+                expect.insert(key, value);
+            }
+        }
+        let expect = expect.into_iter().collect::<Vec<_>>();
+        assert_eq!(scan(&mut eng)?, expect);
+        eng.finish_merge(true)?;
+        assert!(eng.status()?.compactions > 0);
+        assert_eq!(scan(&mut eng)?, expect);
+        assert!(eng.segment_count() < 20);
+
+        // 重新打开时从 hint 文件加载
+        eng.close()?;
+        assert!(eng.get(b"key1".to_vec()).is_err());
+        drop(eng);
+        let mut eng = SegmentEngine::with_options(dir.clone(), options())?;
+        assert_eq!(scan(&mut eng)?, expect);
+        assert!(SegmentEngine::with_options(dir.clone(), options()).is_err());
+
+        // 同步合并所有的段之后只剩下合并的段和新的活跃段
+        eng.compact()?;
+        assert_eq!(eng.segment_count(), 2);
+        let status = eng.status()?;
+        assert_eq!(status.keys, expect.len() as u64);
+        assert_eq!(status.size, status.disk_size);
+        assert_eq!(scan(&mut eng)?, expect);
+        drop(eng);
+
+        // 没有 hint 文件时遍历段重建索引
+        for (id, ext) in list_files(&dir)? {
+            if ext == "hint" {
+                std::fs::remove_file(dir.join(format!("{:08}.{}", id, ext)))?;
+            }
+        }
+        let mut eng = SegmentEngine::with_options(dir.clone(), options())?;
+        assert_eq!(scan(&mut eng)?, expect);
+        drop(eng);
+
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_segment_engine_recover_merge() -> Result<()> {
+        let dir = tempfile::tempdir()?.into_path();
+        let options = SegmentOptions {
+            segment_size: 64,
+            merge_segments: usize::MAX,
+        };
+        let mut eng = SegmentEngine::with_options(dir.clone(), options.clone())?;
+        for i in 0..20u32 {
+            eng.set(
+                format!("key{}", i % 5).into_bytes(),
+                format!("value{}", i).into_bytes(),
+            )?;
+        }
+        eng.delete(b"key0".to_vec())?;
+        eng.compact()?;
+        eng.set(b"key1".to_vec(), b"new".to_vec())?;
+        eng.delete(b"key2".to_vec())?;
+        eng.rotate()?;
+        let expect = scan(&mut eng)?;
+
+        // 合并的结果已经写完，但是还没有删除参与合并的段时崩溃
+        let id = eng.active_id - 1;
+        merge_segments(&dir, id, eng.merge_entries(id))?;
+        drop(eng);
+        let mut eng = SegmentEngine::with_options(dir.clone(), options)?;
+        assert_eq!(eng.segment_count(), 2);
+        assert_eq!(scan(&mut eng)?, expect);
+        assert_eq!(eng.get(b"key1".to_vec())?, Some(b"new".to_vec()));
+        assert_eq!(eng.get(b"key2".to_vec())?, None);
+        drop(eng);
+
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    error::{Error, Result},
+    sql::{
+        engine::{kv::KVEngine, Engine, Session},
+        executor::ResultSet,
+        json::{error_json, json_value, result_json},
+        types::Value,
+    },
+    storage::memory::MemoryEngine,
+};
+
+// 浏览器中使用的数据库，数据只保存在内存中，页面关闭之后丢失
+// 执行结果和错误都以 JSON 字符串返回，格式和 HTTP 接口相同
+//
+//   const db = new Database();
+//   db.execute("create table t (a int primary key);");
+//   JSON.parse(db.execute("select * from t;")).rows
+#[wasm_bindgen]
+pub struct Database {
+    session: Session<KVEngine<MemoryEngine>>,
+}
+
+#[wasm_bindgen]
+impl Database {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> std::result::Result<Database, JsError> {
+        let session = KVEngine::new(MemoryEngine::new())
+            .session()
+            .map_err(|e| JsError::new(&e.to_string()))?;
+        Ok(Self { session })
+    }
+
+    // 执行一条 SQL 语句
+    pub fn execute(&mut self, sql: &str) -> String {
+        to_response(self.session.execute(sql))
+    }
+
+    // params 是 JSON 数组，依次绑定到 $1、$2 ...
+    #[wasm_bindgen(js_name = executeParams)]
+    pub fn execute_params(&mut self, sql: &str, params: &str) -> String {
+        let result =
+            parse_params(params).and_then(|params| self.session.execute_params(sql, params));
+        to_response(result)
+    }
+}
+
+fn parse_params(params: &str) -> Result<Vec<Value>> {
+    match serde_json::from_str(params).map_err(|e| Error::InvalidInput(e.to_string()))? {
+        serde_json::Value::Array(params) => params.iter().map(json_value).collect(),
+        _ => Err(Error::InvalidInput("params must be an array".into())),
+    }
+}
+
+fn to_response(result: Result<ResultSet>) -> String {
+    match result {
+        Ok(result) => result_json(result),
+        Err(err) => error_json(&err),
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::Database;
+
+    #[test]
+    fn test_database() {
+        let mut db = Database::new().unwrap();
+        let result = |s: String| serde_json::from_str::<serde_json::Value>(&s).unwrap();
+        let r = result(db.execute("create table t (a int primary key, b text);"));
+        assert_eq!(r["type"], "create_table");
+        let r = result(db.execute_params("insert into t values ($1, $2);", r#"[1, "x"]"#));
+        assert_eq!(r["count"], 1);
+        let r = result(db.execute("select * from t;"));
+        assert_eq!(r["rows"], json!([[1, "x"]]));
+
+        let r = result(db.execute("select * from t2;"));
+        assert_eq!(r["code"], "42704");
+        let r = result(db.execute_params("select $1;", "{}"));
+        assert!(r["error"].is_string());
+    }
+}